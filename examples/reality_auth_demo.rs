@@ -2,7 +2,9 @@
 ///
 /// This program demonstrates how Reality authentication is injected
 /// into ServerHello.random field.
-use vless_reality_xhttp::transport::reality::server_rustls::RealityServerRustls;
+use base64::{engine::general_purpose, Engine as _};
+use xray_lite::transport::reality::server_rustls::RealityServerRustls;
+use xray_lite::transport::reality::RealityAuth;
 
 fn main() {
     println!("=== Reality Authentication Demo ===\n");
@@ -12,10 +14,13 @@ fn main() {
     println!("1. Creating Reality server...");
     println!("   Private key: {:02x?}...", &private_key[0..8]);
 
-    let server = RealityServerRustls::new(
+    let auth = RealityAuth::new(&general_purpose::STANDARD.encode(&private_key))
+        .expect("Failed to create Reality auth");
+    let _server = RealityServerRustls::new(
         private_key,
         Some("www.microsoft.com:443".to_string()),
         vec![],
+        vec![],
     )
     .expect("Failed to create Reality server");
     println!("   ✓ Server created successfully\n");
@@ -37,9 +42,7 @@ fn main() {
 
     // 4. Inject Reality authentication
     println!("4. Injecting Reality authentication...");
-    server
-        .test_inject_auth(&mut server_random, &client_random)
-        .expect("Failed to inject Reality auth");
+    server_random = auth.inject_auth_into_random(&server_random, &client_random);
     println!("   ✓ Authentication injected\n");
 
     // 5. Show modified ServerHello.random
@@ -69,9 +72,7 @@ fn main() {
     for (i, byte) in server_random2.iter_mut().enumerate() {
         *byte = i as u8;
     }
-    server
-        .test_inject_auth(&mut server_random2, &client_random)
-        .expect("Failed to inject Reality auth");
+    server_random2 = auth.inject_auth_into_random(&server_random2, &client_random);
 
     if server_random == server_random2 {
         println!("   ✓ HMAC is deterministic (same input → same output)");
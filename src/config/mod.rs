@@ -1,9 +1,21 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
+use std::net::IpAddr;
 use std::path::Path;
 
+use crate::network::routing::IpCidr;
+
+mod port;
+mod share;
+mod summary;
 mod validator;
+pub use port::PortList;
+pub use share::{derive_public_key, reality_clients, RealityClientInfo};
+pub use summary::{InboundSummary, StartupSummary};
 pub use validator::Validator;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,16 +24,188 @@ pub struct Config {
     pub outbounds: Vec<Outbound>,
     #[serde(default)]
     pub routing: RoutingConfig,
+    #[serde(default)]
+    pub log: LogConfig,
+    #[serde(default)]
+    pub relay: RelayConfig,
+    #[serde(default)]
+    pub overload: OverloadConfig,
+    #[serde(default)]
+    pub runtime: RuntimeConfig,
+}
+
+/// 日志配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogConfig {
+    /// 吞吐采样间隔 (秒)，0 表示关闭
+    #[serde(rename = "throughputSampleInterval", default)]
+    pub throughput_sample_interval: u64,
+    /// 连接持续超过该时长 (秒) 后才输出吞吐采样
+    #[serde(rename = "throughputSampleMinDuration", default)]
+    pub throughput_sample_min_duration: u64,
+    /// 高频错误日志 (解码失败、握手失败等) 每个窗口内每类最多输出的条数，0 表示不限流
+    #[serde(rename = "errorLogBurst", default = "default_error_log_burst")]
+    pub error_log_burst: u32,
+    /// 错误日志限流窗口 (秒)
+    #[serde(rename = "errorLogWindow", default = "default_error_log_window")]
+    pub error_log_window: u64,
+    /// 按入站输出并发指标 (活跃连接、进行中的握手) 的间隔 (秒)，0 表示关闭
+    #[serde(rename = "metricsInterval", default)]
+    pub metrics_interval: u64,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            throughput_sample_interval: 0,
+            throughput_sample_min_duration: 0,
+            error_log_burst: default_error_log_burst(),
+            error_log_window: default_error_log_window(),
+            metrics_interval: 0,
+        }
+    }
+}
+
+fn default_error_log_burst() -> u32 {
+    crate::utils::log_limit::DEFAULT_BURST
+}
+
+fn default_error_log_window() -> u64 {
+    crate::utils::log_limit::DEFAULT_WINDOW.as_secs()
+}
+
+/// 数据转发配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayConfig {
+    /// 每个方向的转发缓冲区大小 (字节)，小缓冲区降低延迟，大缓冲区提高吞吐
+    #[serde(rename = "bufferSize", default = "default_relay_buffer_size")]
+    pub buffer_size: usize,
+    /// 写出数据后何时 flush
+    #[serde(default)]
+    pub flush: FlushStrategy,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        Self {
+            buffer_size: default_relay_buffer_size(),
+            flush: FlushStrategy::default(),
+        }
+    }
+}
+
+fn default_relay_buffer_size() -> usize {
+    8 * 1024
+}
+
+/// 过载保护配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OverloadConfig {
+    /// 每个入站的最大并发连接数，超出后新连接立即被拒绝
+    #[serde(rename = "maxConnections", default = "default_max_connections")]
+    pub max_connections: usize,
+    /// 拒绝明文 HTTP 请求时 `Retry-After` 建议的等待时间 (秒)
+    #[serde(rename = "retryAfter", default = "default_retry_after")]
+    pub retry_after: u64,
+}
+
+impl Default for OverloadConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: default_max_connections(),
+            retry_after: default_retry_after(),
+        }
+    }
+}
+
+/// 异步运行时配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuntimeConfig {
+    /// 工作线程数，未设置时等于 CPU 核数
+    #[serde(rename = "workerThreads", default, skip_serializing_if = "Option::is_none")]
+    pub worker_threads: Option<usize>,
+    /// 运行时线程绑定的 CPU 编号 (仅 Linux)，为空表示不绑定
+    #[serde(rename = "cpuAffinity", default, skip_serializing_if = "Vec::is_empty")]
+    pub cpu_affinity: Vec<usize>,
+    /// 绑定方式
+    #[serde(rename = "affinityMode", default)]
+    pub affinity_mode: AffinityMode,
+    /// 收到退出信号后等待进行中的连接结束的最长时间 (秒)，超时后终止剩余连接
+    #[serde(rename = "shutdownTimeout", default = "default_shutdown_timeout")]
+    pub shutdown_timeout: u64,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self {
+            worker_threads: None,
+            cpu_affinity: Vec::new(),
+            affinity_mode: AffinityMode::default(),
+            shutdown_timeout: default_shutdown_timeout(),
+        }
+    }
+}
+
+fn default_shutdown_timeout() -> u64 {
+    30
+}
+
+/// CPU 绑定方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AffinityMode {
+    /// 所有线程共享 `cpuAffinity` 中的全部 CPU (适合按 NUMA 节点绑定)
+    #[default]
+    Shared,
+    /// 每个线程依次绑定到其中一个 CPU (适合减少缓存失效)
+    PerThread,
+}
+
+fn default_max_connections() -> usize {
+    4096
+}
+
+fn default_retry_after() -> u64 {
+    5
+}
+
+/// 转发 flush 策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlushStrategy {
+    /// 读端暂无数据时才 flush，尽量合并写入 (吞吐优先)
+    #[default]
+    Auto,
+    /// 每次写出后立即 flush (延迟优先)
+    Always,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Inbound {
+    /// 入站标识，用于日志与指标；未设置时使用监听地址
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub tag: String,
     pub protocol: Protocol,
     pub listen: String,
     pub port: u16,
     pub settings: InboundSettings,
     #[serde(rename = "streamSettings")]
     pub stream_settings: StreamSettings,
+    /// 该入站同时进行中的握手 (已接受、尚未完成认证的连接) 上限，超出后新连接立即被拒绝；
+    /// 未设置时只受全局 `maxConnections` 限制
+    #[serde(rename = "maxHandshakes", default, skip_serializing_if = "Option::is_none")]
+    pub max_handshakes: Option<usize>,
+}
+
+impl Inbound {
+    /// 日志与指标中使用的名称
+    pub fn name(&self) -> String {
+        if self.tag.is_empty() {
+            format!("{}:{}", self.listen, self.port)
+        } else {
+            self.tag.clone()
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,15 +215,151 @@ pub enum Protocol {
     Vmess,
     Trojan,
     Shadowsocks,
+    Socks,
+    /// 端口转发 (任意门)
+    #[serde(alias = "dokodemo-door")]
+    Dokodemo,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InboundSettings {
+    #[serde(default)]
     pub clients: Vec<Client>,
     #[serde(default = "default_decryption")]
     pub decryption: String,
     #[serde(default)]
     pub sniffing: SniffingConfig,
+    /// UDP 单个数据报的最大长度 (字节，最大 65535)，超长的数据报会被单独丢弃
+    #[serde(rename = "maxUdpDatagramSize", default = "default_max_udp_datagram_size")]
+    pub max_udp_datagram_size: usize,
+    /// 收到明文 HTTP 探测请求 (而非 VLESS 请求) 时返回的响应
+    #[serde(rename = "probeResponse", default)]
+    pub probe_response: ProbeResponse,
+    /// VLESS 认证失败 (含 HTTP 探测) 时的回落目标，设置后取代 `probeResponse`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallbacks: Vec<Fallback>,
+    /// Shadowsocks 入站的加密方式
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    /// Shadowsocks 入站的密码
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// SOCKS 入站的账号，非空时要求用户名密码认证
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub accounts: Vec<SocksAccount>,
+    /// SOCKS 入站是否允许 UDP ASSOCIATE
+    #[serde(default)]
+    pub udp: bool,
+    /// SOCKS 入站 UDP 中继绑定并告知客户端的地址，未设置时监听所有地址并回复 0.0.0.0
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip: Option<std::net::IpAddr>,
+    /// dokodemo 入站的转发目标地址
+    #[serde(rename = "targetAddress", default, skip_serializing_if = "Option::is_none")]
+    pub target_address: Option<String>,
+    /// dokodemo 入站的转发目标端口
+    #[serde(rename = "targetPort", default, skip_serializing_if = "Option::is_none")]
+    pub target_port: Option<u16>,
+    /// dokodemo 入站接收的网络类型
+    #[serde(default)]
+    pub network: InboundNetwork,
+}
+
+/// 入站接收的网络类型
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InboundNetwork {
+    #[default]
+    #[serde(rename = "tcp")]
+    Tcp,
+    #[serde(rename = "udp")]
+    Udp,
+    #[serde(rename = "tcp,udp")]
+    TcpUdp,
+}
+
+impl InboundNetwork {
+    pub fn has_tcp(self) -> bool {
+        matches!(self, InboundNetwork::Tcp | InboundNetwork::TcpUdp)
+    }
+
+    pub fn has_udp(self) -> bool {
+        matches!(self, InboundNetwork::Udp | InboundNetwork::TcpUdp)
+    }
+}
+
+/// SOCKS 入站账号
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SocksAccount {
+    pub user: String,
+    pub pass: String,
+}
+
+/// 探测响应配置
+///
+/// 不同端口可以伪装成不同的服务，例如 80 端口返回 301 跳转到 https。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProbeResponse {
+    /// HTTP 状态码
+    #[serde(default = "default_probe_status")]
+    pub status: u16,
+    /// 额外的响应头，例如 `Location`、`Server`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// 响应体
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub body: String,
+}
+
+impl Default for ProbeResponse {
+    fn default() -> Self {
+        Self {
+            status: default_probe_status(),
+            headers: BTreeMap::new(),
+            body: String::new(),
+        }
+    }
+}
+
+/// VLESS 回落配置，与 Xray 的 `fallbacks` 一致
+///
+/// 认证失败的连接按 ALPN 与首包 HTTP 路径选择回落，把已读到的数据连同后续流量
+/// 一起转发给 `dest`，使端口表现得像普通网站。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fallback {
+    /// 匹配的 ALPN，空为任意
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub alpn: String,
+    /// 匹配的首包 HTTP 路径 (不含查询参数)，空为任意；非空时必须以 `/` 开头
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub path: String,
+    /// 回落目标: `addr:port`，或只写端口 (表示 `127.0.0.1:端口`)
+    #[serde(deserialize_with = "deserialize_fallback_dest")]
+    pub dest: String,
+    /// 向回落目标发送 PROXY protocol 头的版本，0 表示不发送
+    #[serde(default)]
+    pub xver: u8,
+}
+
+/// 回落目标可以写成端口号 (数字或字符串) 或 `addr:port`
+fn deserialize_fallback_dest<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Dest {
+        Port(u16),
+        Addr(String),
+    }
+    Ok(match Dest::deserialize(deserializer)? {
+        Dest::Port(port) => format!("127.0.0.1:{}", port),
+        Dest::Addr(addr) if addr.parse::<u16>().is_ok() => format!("127.0.0.1:{}", addr),
+        Dest::Addr(addr) => addr,
+    })
+}
+
+fn default_probe_status() -> u16 {
+    204
+}
+
+fn default_max_udp_datagram_size() -> usize {
+    8192
 }
 
 fn default_true() -> bool {
@@ -55,6 +375,52 @@ pub struct SniffingConfig {
     /// 嗅探目标类型
     #[serde(rename = "destOverride", default = "default_dest_override")]
     pub dest_override: Vec<String>,
+    /// 嗅探到的域名只用于路由与日志，不改写连接目标 (适用于目标在不校验 SNI 的 CDN 之后)
+    #[serde(rename = "routeOnly", default)]
+    pub route_only: bool,
+    /// 不使用嗅探结果的域名: `full:` 前缀或无前缀为完整匹配，`domain:` 前缀匹配该域名及其子域名
+    #[serde(rename = "domainsExcluded", default, skip_serializing_if = "Vec::is_empty")]
+    pub domains_excluded: Vec<String>,
+    /// 嗅探到 TLS 但没有 SNI 时的处理方式 (例如 ECH 流量)
+    #[serde(rename = "noSniAction", default)]
+    pub no_sni_action: NoSniAction,
+    /// `noSniAction` 为 `route` 时使用的出站
+    #[serde(rename = "noSniOutbound", skip_serializing_if = "Option::is_none")]
+    pub no_sni_outbound: Option<String>,
+    /// 检测到 ECH (Encrypted Client Hello) 时的处理方式
+    #[serde(rename = "echAction", default)]
+    pub ech_action: EchAction,
+    /// VLESS 请求未携带数据时，等待首包的最长时间 (毫秒)
+    #[serde(rename = "firstPacketWait", default = "default_sniff_wait")]
+    pub first_packet_wait: u64,
+    /// ClientHello 被拆分到多个记录时，等待后续记录的最长时间 (毫秒)
+    #[serde(rename = "fragmentWait", default = "default_sniff_wait")]
+    pub fragment_wait: u64,
+}
+
+fn default_sniff_wait() -> u64 {
+    500
+}
+
+impl SniffingConfig {
+    /// 嗅探到的协议 (`tls`、`http`、`quic`) 是否在 `destOverride` 中，即是否用嗅探结果覆盖目标
+    pub fn overrides(&self, protocol: &str) -> bool {
+        self.dest_override.iter().any(|p| p.eq_ignore_ascii_case(protocol))
+    }
+
+    /// 嗅探到的域名是否在 `domainsExcluded` 中 (不区分大小写，忽略末尾的点)
+    pub fn is_excluded(&self, domain: &str) -> bool {
+        let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+        self.domains_excluded.iter().any(|entry| {
+            let entry = entry.trim_end_matches('.').to_ascii_lowercase();
+            match entry.strip_prefix("domain:") {
+                Some(suffix) => {
+                    domain == suffix || domain.strip_suffix(suffix).is_some_and(|rest| rest.ends_with('.'))
+                }
+                None => domain == entry.strip_prefix("full:").unwrap_or(&entry),
+            }
+        })
+    }
 }
 
 impl Default for SniffingConfig {
@@ -62,10 +428,45 @@ impl Default for SniffingConfig {
         Self {
             enabled: false, // 默认关闭
             dest_override: vec!["tls".to_string(), "http".to_string()],
+            route_only: false,
+            domains_excluded: Vec::new(),
+            no_sni_action: NoSniAction::default(),
+            no_sni_outbound: None,
+            ech_action: EchAction::default(),
+            first_packet_wait: default_sniff_wait(),
+            fragment_wait: default_sniff_wait(),
         }
     }
 }
 
+/// ECH 流量处理方式
+///
+/// ECH 会把真实 SNI 加密在内层 ClientHello 中，嗅探只能看到外层的公开 SNI。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EchAction {
+    /// 按外层 SNI 覆盖目标并路由
+    #[default]
+    Outer,
+    /// 直接断开
+    Block,
+    /// 不使用外层 SNI，保持原目标
+    Passthrough,
+}
+
+/// 无 SNI 的 TLS 流量处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoSniAction {
+    /// 保持原目标继续转发
+    #[default]
+    Proceed,
+    /// 直接断开
+    Block,
+    /// 交给 `noSniOutbound` 指定的出站
+    Route,
+}
+
 fn default_dest_override() -> Vec<String> {
     vec!["tls".to_string(), "http".to_string()]
 }
@@ -91,6 +492,8 @@ pub struct StreamSettings {
     pub reality_settings: Option<RealitySettings>,
     #[serde(rename = "xhttpSettings", skip_serializing_if = "Option::is_none")]
     pub xhttp_settings: Option<XhttpSettings>,
+    #[serde(rename = "grpcSettings", skip_serializing_if = "Option::is_none")]
+    pub grpc_settings: Option<GrpcSettings>,
     #[serde(default)]
     pub sockopt: SockOpt,
 }
@@ -101,12 +504,38 @@ pub struct SockOpt {
     /// TCP Fast Open - 减少握手延迟
     #[serde(rename = "tcpFastOpen", default = "default_true")]
     pub tcp_fast_open: bool,
-    /// TCP No Delay (禁用 Nagle 算法) - 减少小包延迟
+    /// TCP No Delay (禁用 Nagle 算法) - 减少小包延迟，客户端与远端两侧未单独配置时的默认值
     #[serde(rename = "tcpNoDelay", default = "default_true")]
     pub tcp_no_delay: bool,
+    /// 客户端一侧 (入站接受的连接) 的 TCP_NODELAY，不设置时沿用 `tcpNoDelay`
+    #[serde(rename = "clientTcpNoDelay", default, skip_serializing_if = "Option::is_none")]
+    pub client_tcp_no_delay: Option<bool>,
+    /// 远端一侧 (出站连接) 的 TCP_NODELAY，不设置时沿用 `tcpNoDelay`
+    ///
+    /// 例如客户端一侧开启以降低交互延迟，远端一侧关闭让 Nagle 合并大流量的小包。
+    #[serde(rename = "remoteTcpNoDelay", default, skip_serializing_if = "Option::is_none")]
+    pub remote_tcp_no_delay: Option<bool>,
     /// 接受 Proxy Protocol (用于获取真实客户端 IP)
     #[serde(rename = "acceptProxyProtocol", default)]
     pub accept_proxy_protocol: bool,
+    /// 允许发送 Proxy Protocol 头部的对端 (IP 或 CIDR)，例如前置的负载均衡器
+    ///
+    /// 其他对端发来的数据按普通流量处理，防止伪造来源地址。开启 acceptProxyProtocol 时必须设置，
+    /// 信任所有对端需显式写 `0.0.0.0/0` 与 `::/0`。
+    #[serde(rename = "proxyProtocolTrustedAddresses", default, skip_serializing_if = "Vec::is_empty")]
+    pub proxy_protocol_trusted_addresses: Vec<String>,
+    /// SO_LINGER 秒数 (入站接受的连接与出站连接)，不设置时使用系统默认
+    ///
+    /// 只支持 0: 关闭时直接发送 RST，不进入 TIME_WAIT，适合连接量大的节点快速释放资源。
+    /// 非零值会让 `close()` 阻塞运行时的工作线程直到数据发完或超时，校验时拒绝。
+    #[serde(rename = "tcpLinger", default, skip_serializing_if = "Option::is_none")]
+    pub tcp_linger: Option<u64>,
+    /// 出站 socket 的 IP TOS 字节 (DSCP << 2 | ECN)，用于 QoS 标记，不设置时使用系统默认
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tos: Option<u32>,
+    /// 出站 socket 的 IP TTL (IPv6 为 hop limit)，不设置时使用系统默认
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
 }
 
 impl Default for SockOpt {
@@ -114,8 +543,56 @@ impl Default for SockOpt {
         Self {
             tcp_fast_open: true,          // 默认开启
             tcp_no_delay: true,           // 默认开启
+            client_tcp_no_delay: None,    // 沿用 tcp_no_delay
+            remote_tcp_no_delay: None,    // 沿用 tcp_no_delay
             accept_proxy_protocol: false, // 默认关闭
+            proxy_protocol_trusted_addresses: Vec::new(),
+            tcp_linger: None,             // 系统默认
+            tos: None,                    // 系统默认
+            ttl: None,                    // 系统默认
+        }
+    }
+}
+
+impl SockOpt {
+    /// 客户端一侧实际使用的 TCP_NODELAY
+    pub fn client_no_delay(&self) -> bool {
+        self.client_tcp_no_delay.unwrap_or(self.tcp_no_delay)
+    }
+
+    /// 远端一侧实际使用的 TCP_NODELAY
+    pub fn remote_no_delay(&self) -> bool {
+        self.remote_tcp_no_delay.unwrap_or(self.tcp_no_delay)
+    }
+
+    /// 解析 proxyProtocolTrustedAddresses，未开启 acceptProxyProtocol 时返回 None
+    ///
+    /// 入站启动时调用一次，之后每个连接只做网段匹配。
+    pub fn trusted_proxies(&self) -> Result<Option<TrustedProxies>> {
+        if !self.accept_proxy_protocol {
+            return Ok(None);
         }
+        let cidrs = self
+            .proxy_protocol_trusted_addresses
+            .iter()
+            .map(|entry| IpCidr::parse(entry))
+            .collect::<Result<_>>()?;
+        Ok(Some(TrustedProxies { cidrs }))
+    }
+}
+
+/// 允许发送 Proxy Protocol 头部的对端网段 (已解析)
+#[derive(Debug, Clone)]
+pub struct TrustedProxies {
+    cidrs: Vec<IpCidr>,
+}
+
+impl TrustedProxies {
+    /// 是否解析来自 `peer` 的 Proxy Protocol 头部，名单为空时不信任任何对端
+    pub fn contains(&self, peer: IpAddr) -> bool {
+        // 双栈监听时 IPv4 对端表现为 IPv4 映射地址
+        let peer = peer.to_canonical();
+        self.cidrs.iter().any(|cidr| cidr.contains(&peer))
     }
 }
 
@@ -141,6 +618,7 @@ pub struct RealitySettings {
     pub dest: String,
     #[serde(rename = "serverNames")]
     pub server_names: Vec<String>,
+    /// base64 私钥，或 `file://` 开头的密钥文件路径 (如 Docker/K8s secret)，见 [`RealitySettings::load_private_key`]
     #[serde(rename = "privateKey")]
     pub private_key: String,
     #[serde(rename = "publicKey", skip_serializing_if = "Option::is_none")]
@@ -149,12 +627,121 @@ pub struct RealitySettings {
     pub short_ids: Vec<String>,
     #[serde(default = "default_fingerprint")]
     pub fingerprint: String,
+    /// 客户端的 spiderX 初始路径 (服务端不使用，仅用于导出客户端参数)
+    #[serde(rename = "spiderX", default = "default_spider_x")]
+    pub spider_x: String,
+    /// 回落到 dest 时发送的 PROXY protocol 版本 (0 不发送，1 或 2)，使 dest 能看到真实客户端地址
+    #[serde(default)]
+    pub xver: u8,
+    /// 客户端时间戳与服务器时间允许的最大偏差 (毫秒)，0 表示不检查
+    #[serde(rename = "maxTimeDiff", default = "default_max_time_diff")]
+    pub max_time_diff: u64,
+    /// 重新获取 dest 证书的间隔 (秒)，临时证书复制其主题、SAN 与有效期；0 表示不模仿
+    #[serde(rename = "certRefreshInterval", default = "default_cert_refresh_interval")]
+    pub cert_refresh_interval: u64,
+    /// 按 SNI 选择回落目标，如 `{"www.a.com": "a-origin:443", "*": "default:443"}`
+    ///
+    /// 键支持 `*.example.com` 通配符，没有匹配的条目时使用 `*` 条目，再没有时使用 `dest`。
+    #[serde(rename = "destMap", default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dest_map: BTreeMap<String, String>,
+    /// 允许的最低客户端版本 (如 `1.8.0`)，低于该版本的客户端按非 Reality 客户端回落
+    #[serde(rename = "minClientVer", default, skip_serializing_if = "Option::is_none")]
+    pub min_client_ver: Option<String>,
+    /// 允许的最高客户端版本
+    #[serde(rename = "maxClientVer", default, skip_serializing_if = "Option::is_none")]
+    pub max_client_ver: Option<String>,
+    /// 完成 TLS 握手的实现，用于排查互通问题
+    #[serde(default)]
+    pub backend: RealityBackend,
+    /// 同时回落到 dest 的连接上限，超出时直接关闭客户端连接；0 表示不限制
+    #[serde(rename = "maxFallbacks", default = "default_max_fallbacks")]
+    pub max_fallbacks: usize,
+    /// 每个来源 IP 每秒允许的回落次数，0 表示不限制
+    #[serde(rename = "fallbackRate", default)]
+    pub fallback_rate: u32,
+    /// 每个来源 IP 允许突发的回落次数，小于 `fallbackRate` 时按 `fallbackRate` 计算
+    #[serde(rename = "fallbackBurst", default)]
+    pub fallback_burst: u32,
+    /// 预先建立并保持的到 dest 的连接数，隐藏回落时的连接延迟；0 表示不预连接
+    #[serde(rename = "fallbackPoolSize", default)]
+    pub fallback_pool_size: usize,
+    /// 预连接的空闲时间上限 (秒)，超过后丢弃，避免使用已被 dest 超时关闭的连接
+    #[serde(rename = "fallbackPoolIdleTimeout", default = "default_fallback_pool_idle_timeout")]
+    pub fallback_pool_idle_timeout: u64,
+    /// 等待客户端发送完整 ClientHello 的时间 (秒)，超时后关闭连接或把已收到的数据转发给 dest；0 表示不限制
+    #[serde(rename = "handshakeTimeout", default = "default_handshake_timeout")]
+    pub handshake_timeout: u64,
+    /// PEM 格式的 Ed25519 证书链，代替生成的临时证书；需要同时设置 `keyFile`
+    #[serde(rename = "certificateFile", default, skip_serializing_if = "Option::is_none")]
+    pub certificate_file: Option<String>,
+    /// `certificateFile` 对应的 PKCS#8 私钥 (PEM)
+    #[serde(rename = "keyFile", default, skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<String>,
+}
+
+/// Reality 的 TLS 握手实现
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RealityBackend {
+    /// rustls-reality (默认)，支持 vision 直接复制
+    #[default]
+    Rustls,
+    /// 手写的 TLS 1.3 握手，密钥交换仅支持 X25519，不支持 vision 直接复制
+    Native,
+}
+
+impl RealitySettings {
+    /// 读取实际的私钥 (base64)
+    ///
+    /// `privateKey` 以 `file://` 开头时从该路径读取，避免私钥随主配置文件的备份泄露。
+    /// 文件内容可以是 base64 文本 (忽略首尾空白)，也可以是 32 字节的原始密钥。
+    pub fn load_private_key(&self) -> Result<String> {
+        let Some(path) = self.private_key.strip_prefix(PRIVATE_KEY_FILE_SCHEME) else {
+            return Ok(self.private_key.clone());
+        };
+        let content = fs::read(path).map_err(|e| anyhow!("读取 Reality 私钥文件 {} 失败: {}", path, e))?;
+        // base64 编码的 32 字节密钥至少 43 个字符，正好 32 字节的内容只能是原始密钥
+        if content.len() == 32 {
+            return Ok(URL_SAFE_NO_PAD.encode(&content));
+        }
+        match std::str::from_utf8(&content).map(str::trim) {
+            Ok(text) if !text.is_empty() => Ok(text.to_string()),
+            _ => Err(anyhow!("Reality 私钥文件 {} 内容无效", path)),
+        }
+    }
 }
 
+/// `privateKey` 引用密钥文件时使用的前缀
+const PRIVATE_KEY_FILE_SCHEME: &str = "file://";
+
 fn default_fingerprint() -> String {
     "chrome".to_string()
 }
 
+fn default_spider_x() -> String {
+    "/".to_string()
+}
+
+fn default_max_time_diff() -> u64 {
+    crate::transport::reality::DEFAULT_MAX_TIME_DIFF
+}
+
+fn default_cert_refresh_interval() -> u64 {
+    crate::transport::reality::DEFAULT_CERT_REFRESH_INTERVAL
+}
+
+fn default_max_fallbacks() -> usize {
+    crate::transport::reality::DEFAULT_MAX_FALLBACKS
+}
+
+fn default_fallback_pool_idle_timeout() -> u64 {
+    crate::transport::reality::DEFAULT_FALLBACK_POOL_IDLE_TIMEOUT
+}
+
+fn default_handshake_timeout() -> u64 {
+    crate::transport::reality::DEFAULT_HANDSHAKE_TIMEOUT
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct XhttpSettings {
     #[serde(default = "default_xhttp_mode")]
@@ -163,6 +750,89 @@ pub struct XhttpSettings {
     pub path: String,
     #[serde(default = "default_host")]
     pub host: String,
+    /// 客户端在路径的查询串中携带的密钥 (`"path": "/xh?密钥"`)，不符的请求得到 404；为空时不检查
+    #[serde(rename = "pathSecret", default)]
+    pub path_secret: String,
+    /// 单个上行 POST 的字节数上限，超过时重置该请求
+    #[serde(rename = "scMaxEachPostBytes", default = "default_sc_max_each_post_bytes")]
+    pub sc_max_each_post_bytes: usize,
+    /// packet-up 重排缓冲最多容纳的 POST 数: seq 超出下一个期望值之后这么多个的上行请求被拒绝
+    #[serde(rename = "scMaxBufferedPosts", default = "default_sc_max_buffered_posts")]
+    pub sc_max_buffered_posts: usize,
+    /// 响应头 `x-padding` 的长度范围，如 `"100-1000"`；`0` 表示不发送该头
+    #[serde(rename = "xPaddingBytes", default)]
+    pub x_padding_bytes: crate::transport::xhttp::PaddingRange,
+    /// 会话空闲超过该秒数 (没有上下行数据) 后被清理，防止只打开 GET 就断开的客户端占用会话
+    #[serde(rename = "sessionIdleTimeout", default = "default_session_idle_timeout")]
+    pub session_idle_timeout: u64,
+    /// 与客户端的 noGRPCHeader 对应: 独立流一律按原始字节流收发，不做 gRPC 分帧
+    #[serde(rename = "noGRPCHeader", default)]
+    pub no_grpc_header: bool,
+    /// HTTP/2 窗口、并发流数与帧长度，默认值与之前的固定参数一致
+    #[serde(rename = "h2Settings", default)]
+    pub h2_settings: crate::transport::xhttp::H2Settings,
+    /// HTTP/2 连接的 PING 间隔 (秒)，用于发现被中间 NAT 丢弃的空闲连接；0 表示关闭
+    #[serde(rename = "keepalivePeriod", default)]
+    pub keepalive_period: u64,
+    /// 等待 PING 应答的秒数，超时后关闭连接
+    #[serde(rename = "keepaliveTimeout", default = "default_keepalive_timeout")]
+    pub keepalive_timeout: u64,
+    /// 伪装响应头: 内置模板名 (`"nginx"`、`"cloudflare"`) 或按顺序排列的头部表
+    #[serde(rename = "responseHeaders", default)]
+    pub response_headers: crate::transport::xhttp::ResponseHeaders,
+    /// 相同取值的 XHTTP 入站共用会话表，用于上下行分别经过不同域名或 CDN 的 downloadSettings
+    #[serde(rename = "sessionScope", default)]
+    pub session_scope: Option<String>,
+    /// 允许的浏览器来源 (browser dialer)，如 `"https://example.com"`；`"*"` 允许任意来源
+    #[serde(rename = "corsOrigins", default)]
+    pub cors_origins: Vec<String>,
+    /// 每个来源 IP 同时打开的会话 (下行 GET) 数上限，防止单个客户端占满会话；0 表示不限制
+    #[serde(rename = "maxSessionsPerIp", default)]
+    pub max_sessions_per_ip: usize,
+    /// 每个来源 IP 进行中的 XHTTP 请求 (HTTP/2 流) 数上限；0 表示不限制
+    #[serde(rename = "maxStreamsPerIp", default)]
+    pub max_streams_per_ip: usize,
+    /// 额外的 HTTP/3 (QUIC) 监听，需要以 `http3` feature 编译
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http3: Option<Http3Settings>,
+}
+
+/// XHTTP 入站的 HTTP/3 监听，与 TCP 监听共用路径与会话
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Http3Settings {
+    /// UDP 端口，不设置时与入站端口相同
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// PEM 证书链，不设置时沿用 Reality 的 certificateFile，都没有时生成自签名证书
+    #[serde(rename = "certificateFile", default, skip_serializing_if = "Option::is_none")]
+    pub certificate_file: Option<String>,
+    /// PEM 私钥，与 certificateFile 同时设置
+    #[serde(rename = "keyFile", default, skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<String>,
+}
+
+/// gRPC 传输设置 (network: grpc)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GrpcSettings {
+    /// 服务名，客户端调用 `/{serviceName}/Tun`
+    #[serde(rename = "serviceName", default)]
+    pub service_name: String,
+}
+
+fn default_keepalive_timeout() -> u64 {
+    crate::transport::xhttp::DEFAULT_KEEPALIVE_TIMEOUT
+}
+
+fn default_session_idle_timeout() -> u64 {
+    crate::transport::xhttp::DEFAULT_SESSION_IDLE_TIMEOUT
+}
+
+fn default_sc_max_each_post_bytes() -> usize {
+    crate::transport::xhttp::DEFAULT_SC_MAX_EACH_POST_BYTES
+}
+
+fn default_sc_max_buffered_posts() -> usize {
+    crate::transport::xhttp::DEFAULT_SC_MAX_BUFFERED_POSTS
 }
 
 fn default_xhttp_mode() -> XhttpMode {
@@ -193,12 +863,98 @@ pub struct Outbound {
     pub tag: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub settings: Option<serde_json::Value>,
+    /// 出站传输配置 (目前用于 vless 出站)
+    #[serde(rename = "streamSettings", default, skip_serializing_if = "Option::is_none")]
+    pub stream_settings: Option<OutboundStreamSettings>,
+}
+
+/// 出站传输配置，结构与入站 streamSettings 对应
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboundStreamSettings {
+    #[serde(default = "default_outbound_network")]
+    pub network: Network,
+    #[serde(default = "default_outbound_security")]
+    pub security: Security,
+    #[serde(rename = "realitySettings", default, skip_serializing_if = "Option::is_none")]
+    pub reality_settings: Option<RealityClientSettings>,
+}
+
+fn default_outbound_network() -> Network {
+    Network::Tcp
+}
+
+fn default_outbound_security() -> Security {
+    Security::None
+}
+
+/// 出站 Reality 客户端配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RealityClientSettings {
+    /// 握手使用的 SNI
+    #[serde(rename = "serverName")]
+    pub server_name: String,
+    /// 服务端 X25519 公钥 (Base64 编码)
+    #[serde(rename = "publicKey")]
+    pub public_key: String,
+    /// shortId (十六进制)
+    #[serde(rename = "shortId", default)]
+    pub short_id: String,
+    #[serde(default = "default_fingerprint")]
+    pub fingerprint: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct RoutingConfig {
     #[serde(default)]
     pub rules: Vec<RoutingRule>,
+    /// 负载均衡器
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub balancers: Vec<BalancerConfig>,
+}
+
+/// 负载均衡器配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalancerConfig {
+    pub tag: String,
+    /// 出站标签前缀列表，匹配任一前缀的出站都会加入候选
+    pub selector: Vec<String>,
+    #[serde(default)]
+    pub strategy: BalancerStrategy,
+    /// 连续连接失败多少次后视为不健康
+    #[serde(rename = "maxFailures", default = "default_max_failures")]
+    pub max_failures: u32,
+    /// 不健康出站的冷却时间 (秒)
+    #[serde(default = "default_cooldown")]
+    pub cooldown: u64,
+}
+
+fn default_max_failures() -> u32 {
+    3
+}
+
+fn default_cooldown() -> u64 {
+    30
+}
+
+/// 负载均衡策略
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BalancerStrategy {
+    #[serde(rename = "type", default)]
+    pub strategy_type: BalancerStrategyType,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BalancerStrategyType {
+    /// 轮询
+    #[default]
+    #[serde(rename = "roundRobin")]
+    RoundRobin,
+    /// 随机
+    #[serde(rename = "random")]
+    Random,
+    /// 活跃连接数最少
+    #[serde(rename = "leastConn")]
+    LeastConn,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -209,8 +965,38 @@ pub struct RoutingRule {
     pub domain: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ip: Option<Vec<String>>,
-    #[serde(rename = "outboundTag")]
+    /// 目标端口，例如 "443"、"1000-2000"、"53,443,8443"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<PortList>,
+    /// 来源地址 (客户端真实 IP / CIDR)，启用 Proxy Protocol 时为解析出的地址
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<Vec<String>>,
+    /// 用户邮箱 (对应入站 clients 中的 email)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<Vec<String>>,
+    #[serde(rename = "outboundTag", default, skip_serializing_if = "String::is_empty")]
     pub outbound_tag: String,
+    /// 交给负载均衡器选择出站 (与 outboundTag 二选一)
+    #[serde(rename = "balancerTag", skip_serializing_if = "Option::is_none")]
+    pub balancer_tag: Option<String>,
+    /// 流量镜像 (仅用于实验室分析，默认关闭)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tee: Option<TeeConfig>,
+}
+
+/// 流量镜像配置
+///
+/// 命中规则的 TCP 连接会把明文负载复制一份写入下列目标。
+/// 目标可以是文件路径，或 `tcp://host:port` 形式的分析端点。
+/// 镜像会完整暴露用户流量，只应在取得授权的测试环境中开启。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TeeConfig {
+    /// 客户端 -> 目标 方向的镜像目标
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uplink: Option<String>,
+    /// 目标 -> 客户端 方向的镜像目标
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub downlink: Option<String>,
 }
 
 impl Config {
@@ -274,4 +1060,84 @@ mod tests {
         assert_eq!(config.inbounds.len(), 1);
         assert_eq!(config.outbounds.len(), 1);
     }
+
+    #[test]
+    fn test_sockopt_no_delay_split() {
+        let sockopt: SockOpt = serde_json::from_str(r#"{ "tcpNoDelay": false, "clientTcpNoDelay": true }"#).unwrap();
+        assert!(sockopt.client_no_delay());
+        assert!(!sockopt.remote_no_delay());
+
+        // 未单独配置时两侧沿用 tcpNoDelay
+        let sockopt: SockOpt = serde_json::from_str("{}").unwrap();
+        assert!(sockopt.client_no_delay() && sockopt.remote_no_delay());
+    }
+
+    #[test]
+    fn test_sockopt_trusted_proxies() {
+        let sockopt: SockOpt = serde_json::from_str(
+            r#"{ "acceptProxyProtocol": true, "proxyProtocolTrustedAddresses": ["10.0.0.0/8", "fd00::1"] }"#,
+        )
+        .unwrap();
+        let trusted = sockopt.trusted_proxies().unwrap().unwrap();
+        assert!(trusted.contains("10.1.2.3".parse().unwrap()));
+        assert!(trusted.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(trusted.contains("fd00::1".parse().unwrap()));
+        assert!(!trusted.contains("192.168.1.1".parse().unwrap()));
+
+        // 空名单不信任任何对端，未开启时一律不解析
+        let sockopt: SockOpt = serde_json::from_str(r#"{ "acceptProxyProtocol": true }"#).unwrap();
+        assert!(!sockopt.trusted_proxies().unwrap().unwrap().contains("192.168.1.1".parse().unwrap()));
+        assert!(SockOpt::default().trusted_proxies().unwrap().is_none());
+
+        let sockopt: SockOpt = serde_json::from_str(
+            r#"{ "acceptProxyProtocol": true, "proxyProtocolTrustedAddresses": ["10.0.0.0/33"] }"#,
+        )
+        .unwrap();
+        assert!(sockopt.trusted_proxies().is_err());
+    }
+
+    #[test]
+    fn test_sniffing_domains_excluded() {
+        let sniffing: SniffingConfig = serde_json::from_str(
+            r#"{ "enabled": true, "domainsExcluded": ["domain:push.apple.com", "full:Example.com."] }"#,
+        )
+        .unwrap();
+        assert!(sniffing.is_excluded("courier.push.apple.com"));
+        assert!(sniffing.is_excluded("PUSH.apple.com."));
+        assert!(sniffing.is_excluded("example.com"));
+        // 兄弟域名与子域名不受影响
+        assert!(!sniffing.is_excluded("www.apple.com"));
+        assert!(!sniffing.is_excluded("notpush.apple.com"));
+        assert!(!sniffing.is_excluded("www.example.com"));
+    }
+
+    #[test]
+    fn test_routing_port_rule() {
+        let json = r#"
+        {
+            "inbounds": [],
+            "outbounds": [
+                {"protocol": "freedom", "tag": "direct"},
+                {"protocol": "blackhole", "tag": "block"}
+            ],
+            "routing": {
+                "rules": [
+                    {"type": "field", "port": "25,465-587", "outboundTag": "block"},
+                    {"type": "field", "port": 53, "domain": ["domain:dns.google"], "outboundTag": "direct"}
+                ]
+            }
+        }
+        "#;
+
+        let config: Config = serde_json::from_str(json).unwrap();
+        let rules = &config.routing.rules;
+        assert!(rules[0].port.as_ref().unwrap().contains(500));
+        assert!(rules[1].port.as_ref().unwrap().contains(53));
+
+        let reversed = json.replace("465-587", "587-465");
+        assert!(serde_json::from_str::<Config>(&reversed).is_err());
+
+        let too_large = json.replace("465-587", "465-70000");
+        assert!(serde_json::from_str::<Config>(&too_large).is_err());
+    }
 }
@@ -0,0 +1,178 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// 端口列表 (路由规则使用)
+///
+/// 支持 `"443"`, `"1000-2000"`, `"53,443,8443"` 以及混合写法，
+/// 在加载配置时一次性解析为闭区间列表。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "PortListRaw", into = "String")]
+pub struct PortList {
+    ranges: Vec<(u16, u16)>,
+}
+
+/// JSON 中端口既可以写成数字也可以写成字符串
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PortListRaw {
+    Number(u64),
+    Text(String),
+}
+
+impl TryFrom<PortListRaw> for PortList {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: PortListRaw) -> Result<Self> {
+        match raw {
+            PortListRaw::Number(n) => n.to_string().parse(),
+            PortListRaw::Text(s) => s.parse(),
+        }
+    }
+}
+
+impl From<PortList> for String {
+    fn from(list: PortList) -> Self {
+        list.to_string()
+    }
+}
+
+impl PortList {
+    /// 判断端口是否在列表中
+    pub fn contains(&self, port: u16) -> bool {
+        self.ranges
+            .iter()
+            .any(|&(start, end)| port >= start && port <= end)
+    }
+
+    /// 已解析的端口区间
+    pub fn ranges(&self) -> &[(u16, u16)] {
+        &self.ranges
+    }
+}
+
+fn parse_port(s: &str) -> Result<u16> {
+    let value: u32 = s
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("无效的端口: {:?}", s))?;
+    if value > u16::MAX as u32 {
+        return Err(anyhow!("端口超出范围 (0-65535): {}", value));
+    }
+    Ok(value as u16)
+}
+
+impl FromStr for PortList {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut ranges = Vec::new();
+
+        for part in s.split(',') {
+            let part = part.trim();
+            if part.is_empty() {
+                return Err(anyhow!("端口列表中存在空项: {:?}", s));
+            }
+
+            let range = match part.split_once('-') {
+                Some((start, end)) => {
+                    let start = parse_port(start)?;
+                    let end = parse_port(end)?;
+                    if start > end {
+                        return Err(anyhow!("端口范围起点大于终点: {}", part));
+                    }
+                    (start, end)
+                }
+                None => {
+                    let port = parse_port(part)?;
+                    (port, port)
+                }
+            };
+            ranges.push(range);
+        }
+
+        // 排序并合并重叠/相邻区间，保持列表紧凑
+        ranges.sort_unstable();
+        let mut merged: Vec<(u16, u16)> = Vec::with_capacity(ranges.len());
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some(last) if start as u32 <= last.1 as u32 + 1 => {
+                    last.1 = last.1.max(end);
+                }
+                _ => merged.push((start, end)),
+            }
+        }
+
+        Ok(Self { ranges: merged })
+    }
+}
+
+impl fmt::Display for PortList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (start, end)) in self.ranges.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            if start == end {
+                write!(f, "{}", start)?;
+            } else {
+                write!(f, "{}-{}", start, end)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_forms() {
+        let single: PortList = "443".parse().unwrap();
+        assert!(single.contains(443));
+        assert!(!single.contains(444));
+
+        let range: PortList = "1000-2000".parse().unwrap();
+        assert!(range.contains(1000));
+        assert!(range.contains(2000));
+        assert!(!range.contains(999));
+        assert!(!range.contains(2001));
+
+        let list: PortList = "53, 443,8443".parse().unwrap();
+        assert!(list.contains(53));
+        assert!(list.contains(8443));
+        assert!(!list.contains(80));
+    }
+
+    #[test]
+    fn test_merge_ranges() {
+        let list: PortList = "10-20,15-30,31,100".parse().unwrap();
+        assert_eq!(list.ranges(), &[(10, 31), (100, 100)]);
+        assert_eq!(list.to_string(), "10-31,100");
+    }
+
+    #[test]
+    fn test_invalid_ports() {
+        assert!("2000-1000".parse::<PortList>().is_err());
+        assert!("65536".parse::<PortList>().is_err());
+        assert!("1-70000".parse::<PortList>().is_err());
+        assert!("abc".parse::<PortList>().is_err());
+        assert!("53,,443".parse::<PortList>().is_err());
+    }
+
+    #[test]
+    fn test_serde_number_and_string() {
+        let from_num: PortList = serde_json::from_str("25").unwrap();
+        assert!(from_num.contains(25));
+
+        let from_str: PortList = serde_json::from_str("\"53,1000-2000\"").unwrap();
+        assert!(from_str.contains(1500));
+
+        assert!(serde_json::from_str::<PortList>("\"443-80\"").is_err());
+        assert!(serde_json::from_str::<PortList>("99999").is_err());
+
+        assert_eq!(serde_json::to_string(&from_str).unwrap(), "\"53,1000-2000\"");
+    }
+}
@@ -0,0 +1,358 @@
+//! Reality 客户端参数导出
+//!
+//! 为每个 Reality 入站的每个用户生成客户端需要的参数 (公钥由私钥推导)，
+//! 以表格和 `vless://` 分享链接两种形式输出，减少手动配置客户端时的猜测。
+
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine as _;
+use std::fmt::{self, Write as _};
+use std::net::IpAddr;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use super::{Config, Inbound, Network, Security};
+
+/// 监听地址为通配地址且未指定 `--address` 时使用的占位主机名
+pub const PLACEHOLDER_HOST: &str = "SERVER_ADDRESS";
+
+/// 单个用户的 Reality 客户端参数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RealityClientInfo {
+    pub remark: String,
+    pub uuid: String,
+    pub flow: String,
+    pub host: String,
+    pub port: u16,
+    pub network: &'static str,
+    pub sni: String,
+    pub public_key: String,
+    pub short_id: String,
+    pub fingerprint: String,
+    pub spider_x: String,
+    /// XHTTP 传输的路径与模式
+    pub xhttp: Option<(String, String)>,
+    /// gRPC 传输的服务名
+    pub service_name: Option<String>,
+}
+
+impl RealityClientInfo {
+    /// 生成 `vless://` 分享链接 (Xray 标准格式)
+    pub fn to_url(&self) -> String {
+        let mut params = vec![
+            ("encryption", "none".to_string()),
+            ("security", "reality".to_string()),
+            ("sni", self.sni.clone()),
+            ("fp", self.fingerprint.clone()),
+            ("pbk", self.public_key.clone()),
+            ("sid", self.short_id.clone()),
+            ("spx", self.spider_x.clone()),
+            ("type", self.network.to_string()),
+        ];
+        if !self.flow.is_empty() {
+            params.insert(1, ("flow", self.flow.clone()));
+        }
+        if let Some((path, mode)) = &self.xhttp {
+            params.push(("path", path.clone()));
+            params.push(("mode", mode.clone()));
+        }
+        if let Some(service_name) = &self.service_name {
+            params.push(("serviceName", service_name.clone()));
+        }
+        let query: Vec<String> = params
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, percent_encode(value)))
+            .collect();
+
+        let host = match self.host.parse::<IpAddr>() {
+            Ok(IpAddr::V6(ip)) => format!("[{}]", ip),
+            _ => self.host.clone(),
+        };
+        format!(
+            "vless://{}@{}:{}?{}#{}",
+            self.uuid,
+            host,
+            self.port,
+            query.join("&"),
+            percent_encode(&self.remark)
+        )
+    }
+}
+
+impl fmt::Display for RealityClientInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rows = vec![
+            ("remark", self.remark.as_str()),
+            ("address", self.host.as_str()),
+        ];
+        let port = self.port.to_string();
+        rows.push(("port", &port));
+        rows.push(("id", &self.uuid));
+        if !self.flow.is_empty() {
+            rows.push(("flow", &self.flow));
+        }
+        rows.push(("network", self.network));
+        if let Some((path, mode)) = &self.xhttp {
+            rows.push(("path", path));
+            rows.push(("mode", mode));
+        }
+        if let Some(service_name) = &self.service_name {
+            rows.push(("serviceName", service_name));
+        }
+        rows.extend([
+            ("serverName", self.sni.as_str()),
+            ("publicKey", self.public_key.as_str()),
+            ("shortId", self.short_id.as_str()),
+            ("fingerprint", self.fingerprint.as_str()),
+            ("spiderX", self.spider_x.as_str()),
+        ]);
+
+        for (key, value) in rows {
+            writeln!(f, "  {:<12} {}", key, value)?;
+        }
+        write!(f, "  {}", self.to_url())
+    }
+}
+
+/// 收集配置中所有 Reality 入站的客户端参数
+///
+/// `address` 为客户端连接使用的服务器地址；未指定时使用监听地址，
+/// 监听地址为通配地址时使用占位符 [`PLACEHOLDER_HOST`]。
+pub fn reality_clients(config: &Config, address: Option<&str>) -> Result<Vec<RealityClientInfo>> {
+    let mut clients = Vec::new();
+    for inbound in &config.inbounds {
+        if !matches!(inbound.stream_settings.security, Security::Reality) {
+            continue;
+        }
+        clients.extend(inbound_clients(inbound, address)?);
+    }
+    Ok(clients)
+}
+
+fn inbound_clients(inbound: &Inbound, address: Option<&str>) -> Result<Vec<RealityClientInfo>> {
+    let stream = &inbound.stream_settings;
+    let reality = stream
+        .reality_settings
+        .as_ref()
+        .ok_or_else(|| anyhow!("入站 {}:{} 缺少 realitySettings", inbound.listen, inbound.port))?;
+    let public_key = derive_public_key(&reality.load_private_key()?)?;
+
+    let host = match address {
+        Some(address) => address.to_string(),
+        None => match inbound.listen.parse::<IpAddr>() {
+            Ok(ip) if ip.is_unspecified() => PLACEHOLDER_HOST.to_string(),
+            _ => inbound.listen.clone(),
+        },
+    };
+    let (network, xhttp) = match &stream.xhttp_settings {
+        Some(xhttp) => {
+            let mode = serde_json::to_value(&xhttp.mode)?
+                .as_str()
+                .unwrap_or("auto")
+                .to_string();
+            // 客户端在路径的查询串中携带 pathSecret
+            let path = match xhttp.path_secret.as_str() {
+                "" => xhttp.path.clone(),
+                secret => format!("{}?{}", xhttp.path, secret),
+            };
+            ("xhttp", Some((path, mode)))
+        }
+        None => {
+            let network = match stream.network {
+                Network::Tcp => "tcp",
+                Network::Http => "http",
+                Network::Ws => "ws",
+                Network::Grpc => "grpc",
+            };
+            (network, None)
+        }
+    };
+
+    Ok(inbound
+        .settings
+        .clients
+        .iter()
+        .map(|client| RealityClientInfo {
+            remark: if client.email.is_empty() {
+                format!("xray-lite-{}", inbound.port)
+            } else {
+                client.email.clone()
+            },
+            uuid: client.id.clone(),
+            flow: client.flow.clone(),
+            host: host.clone(),
+            port: inbound.port,
+            network,
+            sni: reality.server_names.first().cloned().unwrap_or_default(),
+            public_key: public_key.clone(),
+            short_id: reality.short_ids.first().cloned().unwrap_or_default(),
+            fingerprint: reality.fingerprint.clone(),
+            spider_x: reality.spider_x.clone(),
+            xhttp: xhttp.clone(),
+            service_name: stream.grpc_settings.as_ref().map(|grpc| grpc.service_name.clone()),
+        })
+        .collect())
+}
+
+/// 由私钥 (base64，与 Xray 一致) 推导 X25519 公钥，输出为 URL-safe 无填充格式
+pub fn derive_public_key(private_key: &str) -> Result<String> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(private_key)
+        .or_else(|_| STANDARD.decode(private_key))
+        .map_err(|e| anyhow!("privateKey 不是有效的 base64: {}", e))?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|b: Vec<u8>| anyhow!("privateKey 长度必须为 32 字节，实际 {} 字节", b.len()))?;
+    let public = PublicKey::from(&StaticSecret::from(bytes));
+    Ok(URL_SAFE_NO_PAD.encode(public.as_bytes()))
+}
+
+/// 百分号编码 (保留 RFC 3986 的非保留字符)
+fn percent_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            out.push(byte as char);
+        } else {
+            let _ = write!(out, "%{:02X}", byte);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RFC 7748 6.1 中 Alice 的密钥对
+    const PRIVATE_KEY: &str = "dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo";
+    const PUBLIC_KEY: &str = "hSDwCYkwp1R0i33ctD73Wg2_Og0mOBr066SpjqqbTmo";
+
+    fn config() -> Config {
+        serde_json::from_value(serde_json::json!({
+            "inbounds": [
+                {
+                    "protocol": "vless",
+                    "listen": "0.0.0.0",
+                    "port": 443,
+                    "settings": { "clients": [
+                        { "id": "b831381d-6324-4d53-ad4f-8cda48b30811", "flow": "xtls-rprx-vision", "email": "alice@example.com" },
+                        { "id": "0f0e4a8e-6f5c-4c8f-9a51-1d8a1b2c3d4e" }
+                    ] },
+                    "streamSettings": {
+                        "network": "tcp",
+                        "security": "reality",
+                        "realitySettings": {
+                            "dest": "www.microsoft.com:443",
+                            "serverNames": ["www.microsoft.com", "microsoft.com"],
+                            "privateKey": PRIVATE_KEY,
+                            "shortIds": ["0123456789abcdef", ""],
+                            "fingerprint": "firefox",
+                            "spiderX": "/a b"
+                        }
+                    }
+                },
+                {
+                    "protocol": "vless",
+                    "listen": "127.0.0.1",
+                    "port": 8080,
+                    "settings": { "clients": [{ "id": "b831381d-6324-4d53-ad4f-8cda48b30811" }] },
+                    "streamSettings": { "network": "tcp", "security": "none" }
+                }
+            ],
+            "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_derive_public_key() {
+        assert_eq!(derive_public_key(PRIVATE_KEY).unwrap(), PUBLIC_KEY);
+        assert!(derive_public_key("c2hvcnQ").is_err());
+    }
+
+    #[test]
+    fn test_reality_clients_match_config() {
+        let clients = reality_clients(&config(), None).unwrap();
+        assert_eq!(clients.len(), 2);
+
+        let alice = &clients[0];
+        assert_eq!(alice.host, PLACEHOLDER_HOST);
+        assert_eq!(alice.sni, "www.microsoft.com");
+        assert_eq!(alice.short_id, "0123456789abcdef");
+        assert_eq!(alice.fingerprint, "firefox");
+        assert_eq!(alice.spider_x, "/a b");
+        assert_eq!(alice.public_key, PUBLIC_KEY);
+        assert_eq!(
+            alice.to_url(),
+            format!(
+                "vless://b831381d-6324-4d53-ad4f-8cda48b30811@SERVER_ADDRESS:443\
+                 ?encryption=none&flow=xtls-rprx-vision&security=reality&sni=www.microsoft.com&fp=firefox\
+                 &pbk={}&sid=0123456789abcdef&spx=%2Fa%20b&type=tcp#alice%40example.com",
+                PUBLIC_KEY
+            )
+        );
+
+        let table = alice.to_string();
+        assert!(table.contains(&format!("  publicKey    {}\n", PUBLIC_KEY)), "{}", table);
+        assert!(table.ends_with(&alice.to_url()), "{}", table);
+
+        // 没有 email 的用户使用默认备注，也不输出 flow
+        assert_eq!(clients[1].remark, "xray-lite-443");
+        assert!(!clients[1].to_url().contains("flow="));
+    }
+
+    #[test]
+    fn test_xhttp_path_carries_secret() {
+        let mut config = config();
+        config.inbounds[0].stream_settings.xhttp_settings =
+            Some(serde_json::from_value(serde_json::json!({ "path": "/xh", "pathSecret": "k=9f3a" })).unwrap());
+        let clients = reality_clients(&config, None).unwrap();
+        assert_eq!(clients[0].xhttp, Some(("/xh?k=9f3a".to_string(), "auto".to_string())));
+        assert!(clients[0].to_url().contains("&path=%2Fxh%3Fk%3D9f3a&mode=auto"), "{}", clients[0].to_url());
+    }
+
+    #[test]
+    fn test_explicit_address() {
+        let clients = reality_clients(&config(), Some("2001:db8::1")).unwrap();
+        assert!(clients[0].to_url().contains("@[2001:db8::1]:443?"));
+    }
+
+    #[test]
+    fn test_private_key_from_file() {
+        let dir = std::env::temp_dir();
+        let text_path = dir.join(format!("xray-lite-reality-{}.key", uuid::Uuid::new_v4()));
+        let raw_path = dir.join(format!("xray-lite-reality-{}.bin", uuid::Uuid::new_v4()));
+        std::fs::write(&text_path, format!("{}\n", PRIVATE_KEY)).unwrap();
+        std::fs::write(&raw_path, URL_SAFE_NO_PAD.decode(PRIVATE_KEY).unwrap()).unwrap();
+
+        // base64 文本与原始 32 字节两种密钥文件都推导出相同的公钥
+        for path in [&text_path, &raw_path] {
+            let mut config = config();
+            let reality = config.inbounds[0].stream_settings.reality_settings.as_mut().unwrap();
+            reality.private_key = format!("file://{}", path.display());
+            let clients = reality_clients(&config, None).unwrap();
+            assert_eq!(clients[0].public_key, PUBLIC_KEY);
+        }
+        std::fs::remove_file(&text_path).unwrap();
+        std::fs::remove_file(&raw_path).unwrap();
+
+        let mut config = config();
+        let reality = config.inbounds[0].stream_settings.reality_settings.as_mut().unwrap();
+        reality.private_key = format!("file://{}", text_path.display());
+        assert!(reality.load_private_key().is_err());
+    }
+
+    #[test]
+    fn test_saved_config_keeps_client_parameters() {
+        let path = std::env::temp_dir().join(format!("xray-lite-share-{}.json", uuid::Uuid::new_v4()));
+        config().save(&path).unwrap();
+        let loaded = Config::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // spiderX 与 fingerprint 经保存、加载后仍出现在客户端参数中
+        let clients = reality_clients(&loaded, None).unwrap();
+        assert_eq!(clients, reality_clients(&config(), None).unwrap());
+        assert_eq!((clients[0].spider_x.as_str(), clients[0].fingerprint.as_str()), ("/a b", "firefox"));
+        assert!(clients[0].to_url().contains("&spx=%2Fa%20b&"));
+    }
+}
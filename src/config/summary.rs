@@ -0,0 +1,190 @@
+//! 启动摘要
+//!
+//! 列出每个入站补全默认值之后实际生效的配置 (协议、传输、安全层、监听地址、用户数)，
+//! 启动时打印一次，方便确认配置是否如预期。
+
+use std::fmt;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+
+use super::{Config, Inbound, Network, Protocol, Security};
+
+/// 单个入站的摘要
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InboundSummary {
+    pub protocol: &'static str,
+    pub transport: &'static str,
+    pub security: &'static str,
+    /// 解析后的监听地址，无法解析时保留原始 `listen:port`
+    pub listen: String,
+    pub clients: usize,
+}
+
+impl InboundSummary {
+    /// 根据入站配置生成摘要
+    pub fn from_inbound(inbound: &Inbound) -> Self {
+        let protocol = match inbound.protocol {
+            Protocol::Vless => "vless",
+            Protocol::Vmess => "vmess",
+            Protocol::Trojan => "trojan",
+            Protocol::Shadowsocks => "shadowsocks",
+            Protocol::Socks => "socks",
+            Protocol::Dokodemo => "dokodemo",
+        };
+        let stream = &inbound.stream_settings;
+        let transport = if stream.xhttp_settings.is_some() {
+            "xhttp"
+        } else {
+            match stream.network {
+                Network::Tcp => "tcp",
+                Network::Http => "http",
+                Network::Ws => "ws",
+                Network::Grpc => "grpc",
+            }
+        };
+        let security = match stream.security {
+            Security::None => "none",
+            Security::Tls => "tls",
+            Security::Reality => "reality",
+        };
+        Self {
+            protocol,
+            transport,
+            security,
+            listen: resolve_listen(&inbound.listen, inbound.port),
+            clients: inbound.settings.clients.len(),
+        }
+    }
+}
+
+/// 解析监听地址: IP 直接使用，主机名取第一个解析结果
+fn resolve_listen(listen: &str, port: u16) -> String {
+    if let Ok(ip) = listen.parse::<IpAddr>() {
+        return SocketAddr::new(ip, port).to_string();
+    }
+    match (listen, port).to_socket_addrs().map(|mut addrs| addrs.next()) {
+        Ok(Some(addr)) => addr.to_string(),
+        _ => format!("{}:{}", listen, port),
+    }
+}
+
+/// 全部入站的启动摘要，`Display` 输出为对齐的表格
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StartupSummary {
+    pub inbounds: Vec<InboundSummary>,
+}
+
+impl StartupSummary {
+    /// 根据配置生成摘要
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            inbounds: config.inbounds.iter().map(InboundSummary::from_inbound).collect(),
+        }
+    }
+}
+
+impl fmt::Display for StartupSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const HEADER: [&str; 5] = ["PROTOCOL", "TRANSPORT", "SECURITY", "LISTEN", "CLIENTS"];
+
+        let rows: Vec<[String; 5]> = self
+            .inbounds
+            .iter()
+            .map(|inbound| {
+                [
+                    inbound.protocol.to_string(),
+                    inbound.transport.to_string(),
+                    inbound.security.to_string(),
+                    inbound.listen.clone(),
+                    inbound.clients.to_string(),
+                ]
+            })
+            .collect();
+
+        let mut widths = HEADER.map(str::len);
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+
+        let header = HEADER.map(String::from);
+        for (idx, row) in std::iter::once(&header).chain(&rows).enumerate() {
+            if idx > 0 {
+                writeln!(f)?;
+            }
+            let line: Vec<String> = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            write!(f, "{}", line.join("  ").trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_table() {
+        let config: Config = serde_json::from_str(
+            r#"{
+                "inbounds": [
+                    {
+                        "protocol": "vless",
+                        "listen": "0.0.0.0",
+                        "port": 443,
+                        "settings": { "clients": [{ "id": "b831381d-6324-4d53-ad4f-8cda48b30811" }, { "id": "0f0e4a8e-6f5c-4c8f-9a51-1d8a1b2c3d4e" }] },
+                        "streamSettings": {
+                            "network": "http",
+                            "security": "reality",
+                            "realitySettings": {
+                                "dest": "www.apple.com:443",
+                                "serverNames": ["www.apple.com"],
+                                "privateKey": "test_key",
+                                "shortIds": [""]
+                            },
+                            "xhttpSettings": { "path": "/" }
+                        }
+                    },
+                    {
+                        "protocol": "vless",
+                        "listen": "::1",
+                        "port": 8080,
+                        "settings": { "clients": [{ "id": "b831381d-6324-4d53-ad4f-8cda48b30811" }] },
+                        "streamSettings": { "network": "tcp", "security": "none" }
+                    }
+                ],
+                "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+            }"#,
+        )
+        .unwrap();
+
+        let summary = StartupSummary::from_config(&config);
+        assert_eq!(
+            summary.inbounds[0],
+            InboundSummary {
+                protocol: "vless",
+                transport: "xhttp",
+                security: "reality",
+                listen: "0.0.0.0:443".to_string(),
+                clients: 2,
+            }
+        );
+        assert_eq!(
+            summary.to_string(),
+            "PROTOCOL  TRANSPORT  SECURITY  LISTEN       CLIENTS\n\
+             vless     xhttp      reality   0.0.0.0:443  2\n\
+             vless     tcp        none      [::1]:8080   1"
+        );
+    }
+
+    #[test]
+    fn test_resolve_listen() {
+        assert_eq!(resolve_listen("127.0.0.1", 80), "127.0.0.1:80");
+        assert_eq!(resolve_listen("localhost", 80).rsplit(':').next(), Some("80"));
+        assert_eq!(resolve_listen("no such host", 80), "no such host:80");
+    }
+}
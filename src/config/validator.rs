@@ -1,4 +1,5 @@
 use anyhow::{anyhow, Result};
+use tracing::warn;
 use uuid::Uuid;
 
 use super::Config;
@@ -22,6 +23,94 @@ impl Validator {
             return Err(anyhow!("至少需要一个出站配置"));
         }
 
+        // 验证转发缓冲区大小
+        let buffer_size = config.relay.buffer_size;
+        if !(1024..=1024 * 1024).contains(&buffer_size) {
+            return Err(anyhow!("relay.bufferSize 必须在 1024-1048576 之间: {}", buffer_size));
+        }
+
+        if config.log.error_log_burst > 0 && config.log.error_log_window == 0 {
+            return Err(anyhow!("log.errorLogWindow 必须大于 0 (或将 errorLogBurst 设为 0 关闭限流)"));
+        }
+
+        if config.runtime.worker_threads == Some(0) {
+            return Err(anyhow!("runtime.workerThreads 必须大于 0"));
+        }
+        if let Some(cpu) = config.runtime.cpu_affinity.iter().find(|cpu| **cpu >= crate::utils::affinity::MAX_CPUS) {
+            return Err(anyhow!(
+                "runtime.cpuAffinity 中的 CPU 编号必须小于 {}: {}",
+                crate::utils::affinity::MAX_CPUS,
+                cpu
+            ));
+        }
+
+        if config.overload.max_connections == 0 {
+            return Err(anyhow!("overload.maxConnections 必须大于 0"));
+        }
+
+        // 验证嗅探的无 SNI 出站
+        for (idx, inbound) in config.inbounds.iter().enumerate() {
+            let sniffing = &inbound.settings.sniffing;
+            if sniffing.no_sni_action == super::NoSniAction::Route {
+                match &sniffing.no_sni_outbound {
+                    Some(tag) if config.outbounds.iter().any(|o| &o.tag == tag) => {}
+                    Some(tag) => {
+                        return Err(anyhow!("入站 {} 的 noSniOutbound 不存在: {}", idx, tag));
+                    }
+                    None => {
+                        return Err(anyhow!("入站 {} 的 noSniAction 为 route 时必须设置 noSniOutbound", idx));
+                    }
+                }
+            }
+        }
+
+        // 验证负载均衡器
+        for balancer in &config.routing.balancers {
+            if balancer.tag.is_empty() {
+                return Err(anyhow!("负载均衡器的 tag 不能为空"));
+            }
+            let matched = config
+                .outbounds
+                .iter()
+                .any(|o| balancer.selector.iter().any(|prefix| o.tag.starts_with(prefix.as_str())));
+            if !matched {
+                return Err(anyhow!(
+                    "负载均衡器 {} 的 selector 没有匹配任何出站",
+                    balancer.tag
+                ));
+            }
+        }
+
+        // 验证路由规则
+        for (idx, rule) in config.routing.rules.iter().enumerate() {
+            if let Some(balancer_tag) = &rule.balancer_tag {
+                if !rule.outbound_tag.is_empty() {
+                    return Err(anyhow!(
+                        "路由规则 {} 不能同时设置 outboundTag 和 balancerTag",
+                        idx
+                    ));
+                }
+                if !config.routing.balancers.iter().any(|b| &b.tag == balancer_tag) {
+                    return Err(anyhow!(
+                        "路由规则 {} 的 balancerTag 不存在: {}",
+                        idx,
+                        balancer_tag
+                    ));
+                }
+            } else if !config.outbounds.iter().any(|o| o.tag == rule.outbound_tag) {
+                return Err(anyhow!(
+                    "路由规则 {} 的 outboundTag 不存在: {}",
+                    idx,
+                    rule.outbound_tag
+                ));
+            }
+            if let Some(tee) = &rule.tee {
+                if tee.uplink.is_none() && tee.downlink.is_none() {
+                    return Err(anyhow!("路由规则 {} 的 tee 至少需要配置 uplink 或 downlink", idx));
+                }
+            }
+        }
+
         Ok(())
     }
 
@@ -31,6 +120,10 @@ impl Validator {
             return Err(anyhow!("入站 {} 的端口不能为 0", idx));
         }
 
+        if inbound.max_handshakes == Some(0) {
+            return Err(anyhow!("入站 {} 的 maxHandshakes 必须大于 0", idx));
+        }
+
         // 验证客户端 UUID
         for (client_idx, client) in inbound.settings.clients.iter().enumerate() {
             if Uuid::parse_str(&client.id).is_err() {
@@ -41,6 +134,123 @@ impl Validator {
                     client.id
                 ));
             }
+            if !client.flow.is_empty() && client.flow != crate::protocol::vless::FLOW_VISION {
+                return Err(anyhow!(
+                    "入站 {} 的客户端 {} flow 不受支持: {} (仅支持 {})",
+                    idx,
+                    client_idx,
+                    client.flow,
+                    crate::protocol::vless::FLOW_VISION
+                ));
+            }
+        }
+
+        // 验证 Shadowsocks 入站的加密方式与密码
+        if matches!(inbound.protocol, super::Protocol::Shadowsocks) {
+            let settings = &inbound.settings;
+            let (Some(method), Some(password)) = (&settings.method, &settings.password) else {
+                return Err(anyhow!("入站 {} 为 shadowsocks 时必须设置 method 与 password", idx));
+            };
+            crate::protocol::ShadowsocksCodec::new(method, password)
+                .map_err(|e| anyhow!("入站 {} 的 Shadowsocks 配置无效: {}", idx, e))?;
+        }
+
+        // 验证 dokodemo 入站的转发目标
+        if matches!(inbound.protocol, super::Protocol::Dokodemo) {
+            let settings = &inbound.settings;
+            if settings.target_address.as_deref().is_none_or(str::is_empty) {
+                return Err(anyhow!("入站 {} 为 dokodemo 时必须设置 targetAddress", idx));
+            }
+            if settings.target_port.is_none_or(|port| port == 0) {
+                return Err(anyhow!("入站 {} 为 dokodemo 时必须设置非 0 的 targetPort", idx));
+            }
+        }
+
+        // 验证 SOCKS 账号 (RFC 1929 用户名与密码各最长 255 字节)
+        for (account_idx, account) in inbound.settings.accounts.iter().enumerate() {
+            if account.user.is_empty() || account.user.len() > 255 || account.pass.len() > 255 {
+                return Err(anyhow!(
+                    "入站 {} 的账号 {} 无效: 用户名不能为空，用户名与密码不能超过 255 字节",
+                    idx,
+                    account_idx
+                ));
+            }
+        }
+
+        // 验证 UDP 数据报上限 (VLESS UDP 帧长度字段为 u16)
+        let max_udp = inbound.settings.max_udp_datagram_size;
+        if max_udp == 0 || max_udp > 65535 {
+            return Err(anyhow!(
+                "入站 {} 的 maxUdpDatagramSize 必须在 1-65535 之间: {}",
+                idx,
+                max_udp
+            ));
+        }
+
+        // 验证探测响应 (响应头由配置直接拼接，不能包含换行)
+        let probe = &inbound.settings.probe_response;
+        if !(100..=599).contains(&probe.status) {
+            return Err(anyhow!(
+                "入站 {} 的 probeResponse.status 必须在 100-599 之间: {}",
+                idx,
+                probe.status
+            ));
+        }
+        for (name, value) in &probe.headers {
+            let invalid_name = name.is_empty() || name.contains([':', '\r', '\n']);
+            if invalid_name || value.contains(['\r', '\n']) {
+                return Err(anyhow!("入站 {} 的 probeResponse 响应头无效: {}", idx, name));
+            }
+        }
+
+        for entry in &inbound.settings.sniffing.domains_excluded {
+            let domain = entry.strip_prefix("domain:").or_else(|| entry.strip_prefix("full:")).unwrap_or(entry);
+            if domain.trim_end_matches('.').is_empty() {
+                return Err(anyhow!("入站 {} 的 domainsExcluded 包含空域名: {:?}", idx, entry));
+            }
+        }
+
+        for (fallback_idx, fallback) in inbound.settings.fallbacks.iter().enumerate() {
+            if fallback.dest.is_empty() {
+                return Err(anyhow!("入站 {} 的回落 {} 的 dest 不能为空", idx, fallback_idx));
+            }
+            if !fallback.path.is_empty() && !fallback.path.starts_with('/') {
+                return Err(anyhow!("入站 {} 的回落 {} 的 path 必须以 / 开头: {}", idx, fallback_idx, fallback.path));
+            }
+            if fallback.xver > 2 {
+                return Err(anyhow!("入站 {} 的回落 {} 的 xver 只能为 0、1 或 2: {}", idx, fallback_idx, fallback.xver));
+            }
+        }
+
+        let sockopt = &inbound.stream_settings.sockopt;
+        if sockopt.accept_proxy_protocol && sockopt.proxy_protocol_trusted_addresses.is_empty() {
+            return Err(anyhow!(
+                "入站 {} 开启了 acceptProxyProtocol，但 proxyProtocolTrustedAddresses 为空 (信任所有对端请写 0.0.0.0/0 与 ::/0)",
+                idx
+            ));
+        }
+        for entry in &sockopt.proxy_protocol_trusted_addresses {
+            crate::network::routing::IpCidr::parse(entry)
+                .map_err(|e| anyhow!("入站 {} 的 proxyProtocolTrustedAddresses 无效: {}", idx, e))?;
+        }
+
+        if let Some(linger) = inbound.stream_settings.sockopt.tcp_linger {
+            // 非零的 SO_LINGER 让 close() 阻塞，会卡住 tokio 的工作线程
+            if linger != 0 {
+                return Err(anyhow!("入站 {} 的 tcpLinger 只支持 0 (关闭时发送 RST): {}", idx, linger));
+            }
+        }
+
+        if let Some(tos) = inbound.stream_settings.sockopt.tos {
+            if tos > 255 {
+                return Err(anyhow!("入站 {} 的 tos 必须在 0-255 之间: {}", idx, tos));
+            }
+        }
+
+        if let Some(ttl) = inbound.stream_settings.sockopt.ttl {
+            if !(1..=255).contains(&ttl) {
+                return Err(anyhow!("入站 {} 的 ttl 必须在 1-255 之间: {}", idx, ttl));
+            }
         }
 
         // 验证 Reality 设置
@@ -53,6 +263,21 @@ impl Validator {
             Self::validate_xhttp_settings(xhttp, idx)?;
         }
 
+        // 验证 gRPC 设置
+        if matches!(inbound.stream_settings.network, super::Network::Grpc) {
+            let service_name = inbound.stream_settings.grpc_settings.as_ref().map(|grpc| grpc.service_name.as_str());
+            match service_name {
+                None | Some("") => return Err(anyhow!("入站 {} 的 gRPC serviceName 不能为空", idx)),
+                Some(name) if name.contains('/') => {
+                    return Err(anyhow!("入站 {} 的 gRPC serviceName 不能包含 /: {:?}", idx, name))
+                }
+                Some(_) => {}
+            }
+            if inbound.stream_settings.xhttp_settings.is_some() {
+                return Err(anyhow!("入站 {} 的 network 为 grpc 时不能同时设置 xhttpSettings", idx));
+            }
+        }
+
         Ok(())
     }
 
@@ -64,6 +289,19 @@ impl Validator {
         if reality.dest.is_empty() {
             return Err(anyhow!("入站 {} 的 Reality dest 不能为空", inbound_idx));
         }
+        for (server_name, dest) in &reality.dest_map {
+            if server_name.is_empty() || dest.is_empty() {
+                return Err(anyhow!("入站 {} 的 Reality destMap 条目不能为空: {:?} -> {:?}", inbound_idx, server_name, dest));
+            }
+        }
+        // unix socket 可能由稍后启动的 nginx 等创建，不存在时只警告
+        for dest in std::iter::once(&reality.dest).chain(reality.dest_map.values()) {
+            if let Some(path) = dest.strip_prefix(crate::network::fallback::UNIX_SCHEME) {
+                if !std::path::Path::new(path).exists() {
+                    warn!("入站 {} 的 Reality 回落目标 {} 不存在，回落连接会失败直到它被创建", inbound_idx, dest);
+                }
+            }
+        }
 
         // 验证服务器名称
         if reality.server_names.is_empty() {
@@ -80,6 +318,34 @@ impl Validator {
                 inbound_idx
             ));
         }
+        if reality.xver > 2 {
+            return Err(anyhow!("入站 {} 的 Reality xver 只能为 0、1 或 2: {}", inbound_idx, reality.xver));
+        }
+
+        let client_ver = |version: &Option<String>| {
+            version
+                .as_deref()
+                .map(crate::transport::reality::parse_client_version)
+                .transpose()
+                .map_err(|e| anyhow!("入站 {} 的 Reality {}", inbound_idx, e))
+        };
+        if let (Some(min), Some(max)) = (client_ver(&reality.min_client_ver)?, client_ver(&reality.max_client_ver)?) {
+            if min > max {
+                return Err(anyhow!("入站 {} 的 Reality minClientVer 不能大于 maxClientVer", inbound_idx));
+            }
+        }
+
+        if !reality.spider_x.starts_with('/') {
+            return Err(anyhow!("入站 {} 的 Reality spiderX 必须以 / 开头: {:?}", inbound_idx, reality.spider_x));
+        }
+        if reality.certificate_file.is_some() != reality.key_file.is_some() {
+            return Err(anyhow!("入站 {} 的 Reality certificateFile 与 keyFile 需要同时设置", inbound_idx));
+        }
+
+        // 私钥文件在启动时读取，不可读时尽早报错
+        reality
+            .load_private_key()
+            .map_err(|e| anyhow!("入站 {} 的 Reality privateKey 无效: {}", inbound_idx, e))?;
 
         Ok(())
     }
@@ -91,6 +357,35 @@ impl Validator {
         if xhttp.path.is_empty() {
             return Err(anyhow!("入站 {} 的 XHTTP path 不能为空", inbound_idx));
         }
+        // 密钥是查询串中的一项，不能跨越多项或含有 URL 中无法原样传递的字符
+        if xhttp.path_secret.contains(|c: char| matches!(c, '&' | '?' | '#') || c.is_whitespace() || c.is_control()) {
+            return Err(anyhow!("入站 {} 的 XHTTP pathSecret 不能包含 &、?、# 或空白字符", inbound_idx));
+        }
+
+        if xhttp.sc_max_each_post_bytes == 0 {
+            return Err(anyhow!("入站 {} 的 XHTTP scMaxEachPostBytes 必须大于 0", inbound_idx));
+        }
+        if xhttp.sc_max_buffered_posts == 0 {
+            return Err(anyhow!("入站 {} 的 XHTTP scMaxBufferedPosts 必须大于 0", inbound_idx));
+        }
+        xhttp
+            .h2_settings
+            .validate()
+            .map_err(|e| anyhow!("入站 {} 的 XHTTP h2Settings 无效: {}", inbound_idx, e))?;
+        if xhttp.keepalive_period > 0 && xhttp.keepalive_timeout == 0 {
+            return Err(anyhow!("入站 {} 的 XHTTP keepaliveTimeout 必须大于 0", inbound_idx));
+        }
+        if let Some(http3) = &xhttp.http3 {
+            if !cfg!(feature = "http3") {
+                return Err(anyhow!("入站 {} 的 XHTTP http3 需要以 http3 feature 编译", inbound_idx));
+            }
+            if http3.port == Some(0) {
+                return Err(anyhow!("入站 {} 的 XHTTP http3 port 不能为 0", inbound_idx));
+            }
+            if http3.certificate_file.is_some() != http3.key_file.is_some() {
+                return Err(anyhow!("入站 {} 的 XHTTP http3 certificateFile 与 keyFile 需要同时设置", inbound_idx));
+            }
+        }
 
         Ok(())
     }
@@ -105,6 +400,7 @@ mod tests {
     fn test_valid_config() {
         let config = Config {
             inbounds: vec![Inbound {
+                tag: String::new(),
                 protocol: Protocol::Vless,
                 listen: "0.0.0.0".to_string(),
                 port: 443,
@@ -115,6 +411,18 @@ mod tests {
                         email: "".to_string(),
                     }],
                     decryption: "none".to_string(),
+                    sniffing: SniffingConfig::default(),
+                    max_udp_datagram_size: 8192,
+                    probe_response: ProbeResponse::default(),
+                    fallbacks: Vec::new(),
+                    method: None,
+                    password: None,
+                    accounts: vec![],
+                    udp: false,
+                    ip: None,
+                    target_address: None,
+                    target_port: None,
+                    network: InboundNetwork::Tcp,
                 },
                 stream_settings: StreamSettings {
                     network: Network::Tcp,
@@ -126,16 +434,40 @@ mod tests {
                         public_key: None,
                         short_ids: vec!["0123456789abcdef".to_string()],
                         fingerprint: "chrome".to_string(),
+                        spider_x: "/".to_string(),
+                        xver: 0,
+                        max_time_diff: 120_000,
+                        cert_refresh_interval: 0,
+                        dest_map: Default::default(),
+                        min_client_ver: None,
+                        max_client_ver: None,
+                        backend: Default::default(),
+                        max_fallbacks: 0,
+                        fallback_rate: 0,
+                        fallback_burst: 0,
+                        fallback_pool_size: 0,
+                        fallback_pool_idle_timeout: 30,
+                        handshake_timeout: 10,
+                        certificate_file: None,
+                        key_file: None,
                     }),
                     xhttp_settings: None,
+                    grpc_settings: None,
+                    sockopt: SockOpt::default(),
                 },
+                max_handshakes: None,
             }],
             outbounds: vec![Outbound {
                 protocol: "freedom".to_string(),
                 tag: "direct".to_string(),
                 settings: None,
+                stream_settings: None,
             }],
             routing: RoutingConfig::default(),
+            log: LogConfig::default(),
+            relay: RelayConfig::default(),
+            overload: OverloadConfig::default(),
+            runtime: RuntimeConfig::default(),
         };
 
         assert!(Validator::validate(&config).is_ok());
@@ -145,6 +477,7 @@ mod tests {
     fn test_invalid_uuid() {
         let config = Config {
             inbounds: vec![Inbound {
+                tag: String::new(),
                 protocol: Protocol::Vless,
                 listen: "0.0.0.0".to_string(),
                 port: 443,
@@ -155,22 +488,348 @@ mod tests {
                         email: "".to_string(),
                     }],
                     decryption: "none".to_string(),
+                    sniffing: SniffingConfig::default(),
+                    max_udp_datagram_size: 8192,
+                    probe_response: ProbeResponse::default(),
+                    fallbacks: Vec::new(),
+                    method: None,
+                    password: None,
+                    accounts: vec![],
+                    udp: false,
+                    ip: None,
+                    target_address: None,
+                    target_port: None,
+                    network: InboundNetwork::Tcp,
                 },
                 stream_settings: StreamSettings {
                     network: Network::Tcp,
                     security: Security::None,
                     reality_settings: None,
                     xhttp_settings: None,
+                    grpc_settings: None,
+                    sockopt: SockOpt::default(),
                 },
+                max_handshakes: None,
             }],
             outbounds: vec![Outbound {
                 protocol: "freedom".to_string(),
                 tag: "direct".to_string(),
                 settings: None,
+                stream_settings: None,
             }],
             routing: RoutingConfig::default(),
+            log: LogConfig::default(),
+            relay: RelayConfig::default(),
+            overload: OverloadConfig::default(),
+            runtime: RuntimeConfig::default(),
         };
 
         assert!(Validator::validate(&config).is_err());
     }
+
+    #[test]
+    fn test_dokodemo_requires_target() {
+        let config = |settings: serde_json::Value| -> Config {
+            serde_json::from_value(serde_json::json!({
+                "inbounds": [{
+                    "protocol": "dokodemo-door",
+                    "listen": "127.0.0.1",
+                    "port": 5353,
+                    "settings": settings,
+                    "streamSettings": { "network": "tcp", "security": "none" }
+                }],
+                "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+            }))
+            .unwrap()
+        };
+
+        let valid = config(serde_json::json!({ "targetAddress": "1.1.1.1", "targetPort": 53, "network": "udp" }));
+        assert!(Validator::validate(&valid).is_ok());
+        assert_eq!(valid.inbounds[0].settings.network, InboundNetwork::Udp);
+
+        assert!(Validator::validate(&config(serde_json::json!({ "targetPort": 53 }))).is_err());
+        assert!(Validator::validate(&config(serde_json::json!({ "targetAddress": "1.1.1.1" }))).is_err());
+        assert!(Validator::validate(&config(serde_json::json!({ "targetAddress": "1.1.1.1", "targetPort": 0 }))).is_err());
+    }
+
+    #[test]
+    fn test_client_flow() {
+        let config = |flow: &str| -> Config {
+            serde_json::from_value(serde_json::json!({
+                "inbounds": [{
+                    "protocol": "vless",
+                    "listen": "127.0.0.1",
+                    "port": 443,
+                    "settings": { "clients": [{ "id": "b831381d-6324-4d53-ad4f-8cda48b30811", "flow": flow }] },
+                    "streamSettings": { "network": "tcp", "security": "none" }
+                }],
+                "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+            }))
+            .unwrap()
+        };
+
+        assert!(Validator::validate(&config("")).is_ok());
+        assert!(Validator::validate(&config("xtls-rprx-vision")).is_ok());
+        assert!(Validator::validate(&config("xtls-rprx-direct")).is_err());
+    }
+
+    #[test]
+    fn test_fallbacks() {
+        let config = |fallbacks: serde_json::Value| -> Config {
+            serde_json::from_value(serde_json::json!({
+                "inbounds": [{
+                    "protocol": "vless",
+                    "listen": "127.0.0.1",
+                    "port": 443,
+                    "settings": {
+                        "clients": [{ "id": "b831381d-6324-4d53-ad4f-8cda48b30811" }],
+                        "fallbacks": fallbacks
+                    },
+                    "streamSettings": { "network": "tcp", "security": "none" }
+                }],
+                "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+            }))
+            .unwrap()
+        };
+
+        let valid = config(serde_json::json!([{ "dest": 8080 }, { "path": "/api", "dest": "8081", "xver": 1 }]));
+        assert!(Validator::validate(&valid).is_ok());
+        assert_eq!(valid.inbounds[0].settings.fallbacks[0].dest, "127.0.0.1:8080");
+        assert_eq!(valid.inbounds[0].settings.fallbacks[1].dest, "127.0.0.1:8081");
+
+        assert!(Validator::validate(&config(serde_json::json!([{ "dest": "" }]))).is_err());
+        assert!(Validator::validate(&config(serde_json::json!([{ "path": "api", "dest": 80 }]))).is_err());
+        assert!(Validator::validate(&config(serde_json::json!([{ "dest": 80, "xver": 3 }]))).is_err());
+    }
+
+    #[test]
+    fn test_proxy_protocol_trusted_addresses() {
+        let config = |sockopt: serde_json::Value| -> Config {
+            serde_json::from_value(serde_json::json!({
+                "inbounds": [{
+                    "protocol": "vless",
+                    "listen": "127.0.0.1",
+                    "port": 443,
+                    "settings": { "clients": [{ "id": "b831381d-6324-4d53-ad4f-8cda48b30811" }] },
+                    "streamSettings": { "network": "tcp", "security": "none", "sockopt": sockopt }
+                }],
+                "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+            }))
+            .unwrap()
+        };
+
+        let trusted = serde_json::json!({ "acceptProxyProtocol": true, "proxyProtocolTrustedAddresses": ["0.0.0.0/0", "::/0"] });
+        assert!(Validator::validate(&config(trusted)).is_ok());
+        // 开启 Proxy Protocol 时必须列出可信对端
+        assert!(Validator::validate(&config(serde_json::json!({ "acceptProxyProtocol": true }))).is_err());
+        let invalid = serde_json::json!({ "acceptProxyProtocol": true, "proxyProtocolTrustedAddresses": ["10.0.0.0/33"] });
+        assert!(Validator::validate(&config(invalid)).is_err());
+    }
+
+    #[test]
+    fn test_tcp_linger_only_zero() {
+        let config = |linger: u64| -> Config {
+            serde_json::from_value(serde_json::json!({
+                "inbounds": [{
+                    "protocol": "vless",
+                    "listen": "127.0.0.1",
+                    "port": 443,
+                    "settings": { "clients": [{ "id": "b831381d-6324-4d53-ad4f-8cda48b30811" }] },
+                    "streamSettings": { "network": "tcp", "security": "none", "sockopt": { "tcpLinger": linger } }
+                }],
+                "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+            }))
+            .unwrap()
+        };
+
+        assert!(Validator::validate(&config(0)).is_ok());
+        assert!(Validator::validate(&config(5)).is_err());
+    }
+
+    #[test]
+    fn test_reality_client_ver() {
+        let config = |min: Option<&str>, max: Option<&str>| -> Config {
+            serde_json::from_value(serde_json::json!({
+                "inbounds": [{
+                    "protocol": "vless",
+                    "listen": "127.0.0.1",
+                    "port": 443,
+                    "settings": { "clients": [{ "id": "b831381d-6324-4d53-ad4f-8cda48b30811" }] },
+                    "streamSettings": {
+                        "network": "tcp",
+                        "security": "reality",
+                        "realitySettings": {
+                            "dest": "www.apple.com:443",
+                            "serverNames": ["www.apple.com"],
+                            "privateKey": "QUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUE=",
+                            "shortIds": [""],
+                            "minClientVer": min,
+                            "maxClientVer": max
+                        }
+                    }
+                }],
+                "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+            }))
+            .unwrap()
+        };
+
+        assert!(Validator::validate(&config(None, None)).is_ok());
+        assert!(Validator::validate(&config(Some("1.8.0"), Some("25.1.30"))).is_ok());
+        assert!(Validator::validate(&config(Some("1.8"), None)).is_err());
+        assert!(Validator::validate(&config(None, Some("1.8.256"))).is_err());
+        assert!(Validator::validate(&config(Some("1.9.0"), Some("1.8.0"))).is_err());
+    }
+
+    #[test]
+    fn test_reality_spider_x() {
+        let config = |spider_x: &str| -> Config {
+            serde_json::from_value(serde_json::json!({
+                "inbounds": [{
+                    "protocol": "vless",
+                    "listen": "127.0.0.1",
+                    "port": 443,
+                    "settings": { "clients": [{ "id": "b831381d-6324-4d53-ad4f-8cda48b30811" }] },
+                    "streamSettings": {
+                        "network": "tcp",
+                        "security": "reality",
+                        "realitySettings": {
+                            "dest": "www.apple.com:443",
+                            "serverNames": ["www.apple.com"],
+                            "privateKey": "QUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUE=",
+                            "shortIds": [""],
+                            "spiderX": spider_x
+                        }
+                    }
+                }],
+                "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+            }))
+            .unwrap()
+        };
+
+        assert!(Validator::validate(&config("/")).is_ok());
+        assert!(Validator::validate(&config("/search?q=1")).is_ok());
+        assert!(Validator::validate(&config("search")).is_err());
+        assert!(Validator::validate(&config("")).is_err());
+    }
+
+    #[test]
+    fn test_xhttp_upload_limits() {
+        let config = |xhttp: serde_json::Value| -> Config {
+            serde_json::from_value(serde_json::json!({
+                "inbounds": [{
+                    "protocol": "vless",
+                    "listen": "127.0.0.1",
+                    "port": 443,
+                    "settings": { "clients": [{ "id": "b831381d-6324-4d53-ad4f-8cda48b30811" }] },
+                    "streamSettings": { "network": "http", "security": "none", "xhttpSettings": xhttp }
+                }],
+                "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+            }))
+            .unwrap()
+        };
+
+        let defaults = config(serde_json::json!({ "path": "/xh" }));
+        let xhttp = defaults.inbounds[0].stream_settings.xhttp_settings.as_ref().unwrap();
+        assert_eq!(xhttp.sc_max_each_post_bytes, 1_000_000);
+        assert_eq!(xhttp.sc_max_buffered_posts, 30);
+        assert!(Validator::validate(&defaults).is_ok());
+
+        assert!(Validator::validate(&config(serde_json::json!({ "path": "/xh", "scMaxEachPostBytes": 0 }))).is_err());
+        assert!(Validator::validate(&config(serde_json::json!({ "path": "/xh", "scMaxBufferedPosts": 0 }))).is_err());
+
+        // 保活默认关闭，开启时超时不能为 0
+        assert_eq!((xhttp.keepalive_period, xhttp.keepalive_timeout), (0, 15));
+        assert!(Validator::validate(&config(serde_json::json!({ "path": "/xh", "keepalivePeriod": 30 }))).is_ok());
+        let no_timeout = serde_json::json!({ "path": "/xh", "keepalivePeriod": 30, "keepaliveTimeout": 0 });
+        assert!(Validator::validate(&config(no_timeout)).is_err());
+
+        assert!(Validator::validate(&config(serde_json::json!({ "path": "/xh", "pathSecret": "k=9f3a" }))).is_ok());
+        assert!(Validator::validate(&config(serde_json::json!({ "path": "/xh", "pathSecret": "a&b" }))).is_err());
+        assert!(Validator::validate(&config(serde_json::json!({ "path": "/xh", "pathSecret": "a b" }))).is_err());
+
+        // 按来源 IP 的上限默认不限制
+        assert_eq!((xhttp.max_sessions_per_ip, xhttp.max_streams_per_ip), (0, 0));
+        let per_ip = config(serde_json::json!({ "path": "/xh", "maxSessionsPerIp": 8, "maxStreamsPerIp": 64 }));
+        let limits = per_ip.inbounds[0].stream_settings.xhttp_settings.as_ref().unwrap();
+        assert_eq!((limits.max_sessions_per_ip, limits.max_streams_per_ip), (8, 64));
+
+        // http3 只在以 http3 feature 编译时可用，证书与私钥需要成对配置
+        let http3 = config(serde_json::json!({ "path": "/xh", "http3": {} }));
+        assert_eq!(Validator::validate(&http3).is_ok(), cfg!(feature = "http3"));
+        let cert_only = serde_json::json!({ "path": "/xh", "http3": { "certificateFile": "cert.pem" } });
+        assert!(Validator::validate(&config(cert_only)).is_err());
+    }
+
+    #[test]
+    fn test_xhttp_h2_settings() {
+        let config = |h2: serde_json::Value| -> Config {
+            serde_json::from_value(serde_json::json!({
+                "inbounds": [{
+                    "protocol": "vless",
+                    "listen": "127.0.0.1",
+                    "port": 443,
+                    "settings": { "clients": [{ "id": "b831381d-6324-4d53-ad4f-8cda48b30811" }] },
+                    "streamSettings": {
+                        "network": "http",
+                        "security": "none",
+                        "xhttpSettings": { "path": "/xh", "h2Settings": h2 }
+                    }
+                }],
+                "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+            }))
+            .unwrap()
+        };
+
+        // 只写部分字段时其余保持默认
+        let partial = config(serde_json::json!({ "initialStreamWindow": 8388608 }));
+        let h2 = partial.inbounds[0].stream_settings.xhttp_settings.as_ref().unwrap().h2_settings;
+        assert_eq!(h2.initial_stream_window, 8_388_608);
+        assert_eq!(h2.initial_conn_window, 65_535);
+        assert_eq!(h2.max_frame_size, 16_384);
+        assert!(Validator::validate(&partial).is_ok());
+
+        assert!(Validator::validate(&config(serde_json::json!({ "initialStreamWindow": 0 }))).is_err());
+        assert!(Validator::validate(&config(serde_json::json!({ "initialStreamWindow": 2147483648u64 }))).is_err());
+        assert!(Validator::validate(&config(serde_json::json!({ "initialConnWindow": 1024 }))).is_err());
+        assert!(Validator::validate(&config(serde_json::json!({ "maxConcurrentStreams": 0 }))).is_err());
+        assert!(Validator::validate(&config(serde_json::json!({ "maxFrameSize": 1024 }))).is_err());
+        assert!(Validator::validate(&config(serde_json::json!({ "maxFrameSize": 16777216 }))).is_err());
+    }
+
+    #[test]
+    fn test_grpc_settings() {
+        let config = |stream: serde_json::Value| -> Config {
+            serde_json::from_value(serde_json::json!({
+                "inbounds": [{
+                    "protocol": "vless",
+                    "listen": "127.0.0.1",
+                    "port": 443,
+                    "settings": { "clients": [{ "id": "b831381d-6324-4d53-ad4f-8cda48b30811" }] },
+                    "streamSettings": stream
+                }],
+                "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+            }))
+            .unwrap()
+        };
+
+        let grpc = config(serde_json::json!({
+            "network": "grpc",
+            "security": "none",
+            "grpcSettings": { "serviceName": "GunService" }
+        }));
+        assert_eq!(grpc.inbounds[0].stream_settings.grpc_settings.as_ref().unwrap().service_name, "GunService");
+        assert!(Validator::validate(&grpc).is_ok());
+
+        // serviceName 必填，且只能是单段路径
+        assert!(Validator::validate(&config(serde_json::json!({ "network": "grpc", "security": "none" }))).is_err());
+        let nested = serde_json::json!({ "network": "grpc", "security": "none", "grpcSettings": { "serviceName": "a/b" } });
+        assert!(Validator::validate(&config(nested)).is_err());
+        let both = serde_json::json!({
+            "network": "grpc",
+            "security": "none",
+            "grpcSettings": { "serviceName": "GunService" },
+            "xhttpSettings": { "path": "/xh" }
+        });
+        assert!(Validator::validate(&config(both)).is_err());
+    }
 }
@@ -1,17 +1,75 @@
 use anyhow::Result;
-use tracing::{info, error, debug};
+use tracing::{info, error, debug, warn};
 use crate::server::AsyncStream;
-use crate::protocol::vless::{VlessCodec, Command, VlessResponse};
-use crate::network::ConnectionManager;
+use crate::protocol::vless::{check_flow, packet_addr, Address, VlessCodec, Command, VisionStream, VlessResponse, FLOW_VISION};
+use crate::protocol::vmess::{Command as VmessCommand, VmessCodec, VmessStream};
+use crate::protocol::shadowsocks::ShadowsocksCodec;
+use crate::protocol::mux::{SessionStatus, TargetNetwork};
+use crate::protocol::socks::{self, SocksCodec, SocksCommand};
+use crate::network::attempt::{self, AttemptOutcome};
+use crate::network::fallback::{relay_fallback, select_fallback};
+use crate::network::{ConnectionLabel, ConnectionManager, RouteContext, Router, TeeSinks};
+use crate::config::{EchAction, InboundSettings, NoSniAction, ProbeResponse, SniffingConfig, SockOpt};
+use crate::protocol::{quic, sniffer};
+use crate::outbound::Dialer;
+
+/// TLS 嗅探结果
+#[derive(Debug, PartialEq)]
+enum SniffOutcome<'a> {
+    /// 非 TLS 流量，保持原目标
+    Keep,
+    /// 嗅探到 SNI
+    Sni(String),
+    /// TLS 流量没有 SNI，按配置阻断
+    Block,
+    /// TLS 流量没有 SNI，交给指定出站
+    Route(&'a str),
+    /// 嗅探到明文 HTTP 请求的 Host
+    Host(String),
+}
+
+/// 根据首包嗅探结果与 ECH / 无 SNI 策略决定如何处理连接
+fn sniff_initial_data<'a>(sniffing: &'a SniffingConfig, data: &[u8]) -> SniffOutcome<'a> {
+    let Some(hello) = sniffer::sniff_client_hello(data) else {
+        return match sniffer::sniff_http_host(data) {
+            Some(host) if sniffing.overrides("http") => SniffOutcome::Host(host),
+            _ => SniffOutcome::Keep,
+        };
+    };
+
+    if hello.has_ech {
+        match sniffing.ech_action {
+            EchAction::Block => return SniffOutcome::Block,
+            EchAction::Passthrough => return SniffOutcome::Keep,
+            EchAction::Outer => {}
+        }
+    }
+
+    if let Some(sni) = hello.sni {
+        return if sniffing.overrides("tls") { SniffOutcome::Sni(sni) } else { SniffOutcome::Keep };
+    }
+    if !sniffer::is_client_hello(data) {
+        return SniffOutcome::Keep;
+    }
+    match (sniffing.no_sni_action, sniffing.no_sni_outbound.as_deref()) {
+        (NoSniAction::Block, _) => SniffOutcome::Block,
+        (NoSniAction::Route, Some(tag)) => SniffOutcome::Route(tag),
+        _ => SniffOutcome::Keep,
+    }
+}
 
 /// 处理 VLESS 会话核心逻辑
 pub async fn serve_vless(
     mut stream: Box<dyn AsyncStream>,
     codec: VlessCodec,
     connection_manager: ConnectionManager,
-    sniffing_enabled: bool,
-    tcp_no_delay: bool,
+    router: std::sync::Arc<Router>,
+    client_addr: std::net::SocketAddr,
+    inbound_settings: std::sync::Arc<InboundSettings>,
+    sockopt: SockOpt,
 ) -> Result<()> {
+    let sniffing = &inbound_settings.sniffing;
+
     // 读取 VLESS 请求（带超时，支持多次读取）
     let mut buf = bytes::BytesMut::with_capacity(4096);
     use tokio::io::AsyncReadExt;
@@ -35,31 +93,85 @@ pub async fn serve_vless(
         }
     }
 
-    let request = match codec.decode_request(&mut buf) {
-        Ok(req) => req,
+    // 检查是否是 HTTP 探测请求 (解码失败时 buf 已被部分消费，需在解码前判断)
+    let is_http_probe = looks_like_http(&buf);
+    let probe_len = buf.len();
+    let probe_peek = if is_http_probe {
+        String::from_utf8_lossy(&buf[..probe_len.min(64)]).replace("\r", "\\r").replace("\n", "\\n")
+    } else {
+        String::new()
+    };
+
+    // 配置了回落时保留原始数据，认证失败后原样转发给回落目标
+    let raw = (!inbound_settings.fallbacks.is_empty()).then(|| buf.clone());
+
+    let (request, client) = match codec.decode_request(&mut buf).await {
+        Ok(decoded) => decoded,
         Err(e) => {
-            // 检查是否是 HTTP 探测请求
-            let buf_slice = &buf[..];
-            let is_http_probe = buf_slice.windows(4).any(|w| 
-                w == b"GET " || w == b"POST"
-            ) || buf_slice.windows(4).any(|w| w == b"HEAD");
-            
+            // 入站不协商 ALPN，只使用 ALPN 为空的回落
+            let fallback = raw
+                .as_deref()
+                .and_then(|raw| select_fallback(&inbound_settings.fallbacks, None, raw).map(|f| (f, raw)));
+            if let Some((fallback, raw)) = fallback {
+                crate::log_limited!(info, "vless_fallback", "↪️ VLESS 认证失败，回落到 {} ({})", fallback.dest, e);
+                attempt::record(AttemptOutcome::Fallback, None);
+                return relay_fallback(stream, fallback, raw, client_addr, attempt::local_addr()).await;
+            }
             if is_http_probe {
-                let peek_len = buf.len().min(64);
-                let peek = String::from_utf8_lossy(&buf[..peek_len]).replace("\r", "\\r").replace("\n", "\\n");
-                info!("🔍 检测到 HTTP 探测请求 ({} bytes): \"{}\"", buf.len(), peek);
+                crate::log_limited!(info, "http_probe", "🔍 检测到 HTTP 探测请求 ({} bytes): \"{}\"", probe_len, probe_peek);
+                attempt::record(AttemptOutcome::Probe, None);
                 use tokio::io::AsyncWriteExt;
-                let _ = stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").await;
+                let _ = stream.write_all(&probe_response_bytes(&inbound_settings.probe_response)).await;
                 return Ok(());
             }
             
+            attempt::record(AttemptOutcome::Rejected, None);
             let bytes_read = buf.len();
-            let hex_dump = hex::encode(&buf[..bytes_read.min(128)]);
-            error!("❌ VLESS 解码失败: {}. Bytes: {} Hex: {}", e, bytes_read, hex_dump);
+            crate::log_limited!(
+                error,
+                "vless_decode",
+                "❌ VLESS 解码失败: {}. Bytes: {} Hex: {}",
+                e,
+                bytes_read,
+                hex::encode(&buf[..bytes_read.min(128)])
+            );
             return Err(e);
         }
     };
-    info!("📨 VLESS 请求: {:?} -> {}", request.command, request.address.to_string());
+    let ids = ConnectionIds::new(&request.uuid, request.addons.continuity.as_deref());
+    info!(
+        "📨 VLESS 请求: {:?} -> {} (流控: {}, 会话: {}, 连接: {})",
+        request.command,
+        request.address.to_string(),
+        request.flow().unwrap_or("-"),
+        ids.session,
+        ids.conn
+    );
+
+    // 路由上下文: 来源地址 + 已认证用户
+    let user = client.email.as_deref();
+    let vision = match check_flow(request.flow(), client.flow.as_deref(), request.command) {
+        Ok(vision) => vision,
+        Err(e) => {
+            attempt::record(AttemptOutcome::Rejected, user);
+            warn!("拒绝 VLESS 请求 (用户: {}): {}", user.unwrap_or("-"), e);
+            return Ok(());
+        }
+    };
+    // vision 的 Direct 命令需要传输层能绕过外层 TLS (Reality rustls 后端上的原始 TCP)，
+    // 其他传输层下客户端切换后双方会失去同步，与 xray-core 一样直接拒绝
+    let direct = if vision { crate::network::direct::current() } else { None };
+    if vision && direct.is_none() {
+        attempt::record(AttemptOutcome::Rejected, user);
+        warn!("拒绝 VLESS 请求 (用户: {}): 当前传输层不支持 {} 流控", user.unwrap_or("-"), FLOW_VISION);
+        return Ok(());
+    }
+    attempt::record(AttemptOutcome::Authenticated, user);
+    let route_ctx = |address| {
+        RouteContext::new(address)
+            .with_source(client_addr)
+            .with_user(user)
+    };
 
     // 发送 VLESS 响应
     let response = VlessResponse::new();
@@ -69,186 +181,2142 @@ pub async fn serve_vless(
     stream.write_all(&response_bytes).await?;
     stream.flush().await?; // 确保响应已发送
 
+    // vision 流控: 与请求一并到达的数据同样是填充帧，交给 VisionStream 解析
+    let (stream, initial_data): (Box<dyn AsyncStream>, Vec<u8>) = if vision {
+        debug!("vision 流控已启用 (连接: {})", ids.conn);
+        (Box::new(VisionStream::new(stream, request.uuid, &buf, direct)), Vec::new())
+    } else {
+        (stream, buf.to_vec())
+    };
+
     // 根据命令类型处理
     match request.command {
         Command::Tcp => {
-            let mut target_address = request.address.to_string();
-            let mut initial_data = Vec::new();
-
-            // --- 🌟 SNIFFING START ---
-            if !buf.is_empty() {
-                initial_data.extend_from_slice(&buf);
-                buf.clear(); 
-            }
-
-            if sniffing_enabled {
-                // 如果没有初始数据，尝试再次通过超时读取
-                if initial_data.is_empty() {
-                    let mut temp_buf = vec![0u8; 4096];
-                    if let Ok(Ok(n)) = timeout(Duration::from_millis(500), stream.read(&mut temp_buf)).await {
-                         if n > 0 {
-                             initial_data.extend_from_slice(&temp_buf[..n]);
-                             debug!("Sniffing: 读取了额外的 {} 字节", n);
-                         }
-                    }
-                }
+            let session = TcpSession {
+                connection_manager: &connection_manager,
+                router: &router,
+                client_addr,
+                user,
+                sniffing,
+                sockopt: &sockopt,
+            };
+            // 与请求一并到达的数据作为首包
+            session.relay(stream, request.address.clone(), initial_data).await?;
+        }
+        Command::Udp => {
+            use tokio::time::sleep_until;
 
-                if !initial_data.is_empty() {
-                    if let Some(sni) = crate::protocol::sniffer::sniff_tls_sni(&initial_data) {
-                        info!("👃 Sniffed SNI: {} (Override: {})", sni, target_address);
-                        // 判断是否需要覆盖目标地址
-                        // 这里不再做 dest_override 过滤，简单起见总是覆盖
-                        // 实际应根据配置判断
-                         target_address = format!("{}:443", sni);
-                    }
+            // packet-addr 会话的每个数据报自带目标，否则都发往请求头中的目标
+            let packet_addr = packet_addr::is_packet_addr(&request.address);
+            info!("📡 UDP 请求: {}{}", request.address.to_string(), if packet_addr { " (按包指定目标)" } else { "" });
+            let max_datagram = inbound_settings.max_udp_datagram_size;
+            let (stream_read, mut stream_write) = tokio::io::split(stream);
+            // 与请求一并到达的数据是最初的若干帧 (客户端可能一次发出多个数据报)
+            let mut frames = UdpFrameReader::new((&buf[..]).chain(stream_read), max_datagram);
+
+            // 按第一个数据报的目标路由并建立出站会话
+            let mut first = loop {
+                let datagram = match timeout(UDP_IDLE_TIMEOUT, frames.next()).await {
+                    Ok(Ok(Some(datagram))) => datagram,
+                    _ => return Ok(()),
+                };
+                match split_udp_destination(packet_addr, &request.address, datagram) {
+                    Ok((destination, payload)) => break (destination, payload.to_vec()),
+                    Err(e) => warn!("丢弃无效的 UDP 数据报: {}", e),
+                }
+            };
+            // destOverride 包含 quic 时嗅探 QUIC Initial 中的 SNI，整个会话改为发往嗅探出的域名；
+            // routeOnly 或 packet-addr 会话 (目标逐包指定) 时嗅探结果只用于路由
+            let mut session_target = request.address.clone();
+            let mut sniffed_domain = None;
+            let sniffed = if sniffing.enabled && sniffing.overrides("quic") { quic::sniff_quic_sni(&first.1) } else { None };
+            let sniffed = sniffed.filter(|sni| !sniffing.is_excluded(sni));
+            if let Some(sni) = sniffed {
+                info!("👃 Sniffed QUIC SNI: {} (Override: {})", sni, first.0.to_string());
+                if sniffing.route_only || packet_addr {
+                    sniffed_domain = Some(sni);
+                } else {
+                    session_target = Address::Domain(sni, first.0.port());
+                    first.0 = session_target.clone();
                 }
             }
-            // --- SNIFFING END ---
-
-            info!("🔗 连接目标: {}", target_address);
+            let initial_target = first.0.clone();
+            let outbound_tag = router.route(&route_ctx(&initial_target).with_sniffed_domain(sniffed_domain.as_deref()));
+            let outbound = dialer(&router, outbound_tag)?;
+            if outbound.is_blackhole() {
+                info!("🚫 UDP 路由到 blackhole ({}): {}", outbound_tag, initial_target.to_string());
+                return Ok(());
+            }
             
-            // 连接远程服务器
-            let mut remote_stream = match tokio::net::TcpStream::connect(&target_address).await {
+            let udp_session = match outbound.connect_udp(&initial_target, &sockopt).await {
                 Ok(s) => s,
                 Err(e) => {
-                    error!("无法连接到目标 {}: {}", target_address, e);
-                    return Err(e.into());
+                    error!("无法建立 UDP 会话 (出站: {}): {}", outbound_tag, e);
+                    return Err(e);
+                }
+            };
+            let destinations = UdpDestinations::new(UDP_IDLE_TIMEOUT);
+            destinations.touch(&initial_target, true);
+            let send = |destination: Address, payload: Vec<u8>| {
+                let udp_session = &udp_session;
+                let initial_target = &initial_target;
+                let destinations = &destinations;
+                async move {
+                    destinations.touch(&destination, true);
+                    let sent = if &destination == initial_target {
+                        udp_session.send(&payload).await
+                    } else {
+                        udp_session.send_to(&payload, &destination).await
+                    };
+                    if let Err(e) = sent {
+                        error!("UDP 发送失败 ({}): {}", destination.to_string(), e);
+                    }
                 }
             };
             
-            // TCP No Delay
-            if tcp_no_delay {
-                if let Err(e) = remote_stream.set_nodelay(true) {
-                    error!("Remote: 设置 TCP_NODELAY 失败: {}", e);
+            // 客户端 -> UDP
+            let send_task = async {
+                send(first.0, first.1).await;
+                while let Ok(Some(datagram)) = frames.next().await {
+                    match split_udp_destination(packet_addr, &session_target, datagram) {
+                        Ok((destination, payload)) => send(destination, payload.to_vec()).await,
+                        Err(e) => warn!("丢弃无效的 UDP 数据报: {}", e),
+                    }
                 }
+            };
+            
+            // UDP -> 客户端，回包附带来源地址 (packet-addr)
+            let recv_task = async {
+                // 按 UDP 理论上限分配，避免截断后再按配置判断是否丢弃
+                let mut recv_buf = vec![0u8; 65536];
+                let mut frame = Vec::new();
+                while let Ok((n, source)) = udp_session.recv_from(&mut recv_buf).await {
+                    destinations.touch(&source, false);
+                    if n > max_datagram {
+                        warn!("丢弃超长 UDP 回包: {} 字节 (上限 {})", n, max_datagram);
+                        continue;
+                    }
+                    frame.clear();
+                    frame.extend_from_slice(&[0, 0]);
+                    if !packet_addr {
+                        frame.extend_from_slice(&recv_buf[..n]);
+                    } else if let Err(e) = packet_addr::encode(&source, &recv_buf[..n], &mut frame) {
+                        debug!("丢弃 UDP 回包: {}", e);
+                        continue;
+                    }
+                    let Ok(len) = u16::try_from(frame.len() - 2) else {
+                        warn!("丢弃超长 UDP 回包: {} 字节", frame.len() - 2);
+                        continue;
+                    };
+                    frame[..2].copy_from_slice(&len.to_be_bytes());
+                    if stream_write.write_all(&frame).await.is_err() { break; }
+                    if stream_write.flush().await.is_err() { break; }
+                }
+            };
+
+            // 各目标单独计算空闲时间，全部过期后结束会话
+            let expiry_task = async {
+                while let Some(deadline) = destinations.evict_idle() {
+                    sleep_until(deadline).await;
+                }
+            };
+            
+            tokio::select! {
+                _ = send_task => {}
+                _ = recv_task => {}
+                _ = expiry_task => {}
             }
+            info!("📡 UDP 会话结束");
+        }
+        Command::Mux => {
+            info!("🔀 Mux 会话开始 (来源: {})", client_addr);
+            let context = MuxContext {
+                connection_manager,
+                router: router.clone(),
+                client_addr,
+                user: client.email.clone(),
+                inbound_settings: inbound_settings.clone(),
+                sockopt,
+            };
+            // 与请求一并到达的数据是最初的 Mux 帧
+            serve_mux(stream, &initial_data, std::sync::Arc::new(context)).await;
+            info!("🔀 Mux 会话结束");
+        }
+    }
+
+    Ok(())
+}
+
+/// 入站协议对应的请求解码器
+#[derive(Clone)]
+pub enum InboundCodec {
+    Vless(VlessCodec),
+    Vmess(VmessCodec),
+    Shadowsocks(ShadowsocksCodec),
+    Socks(SocksCodec),
+    /// dokodemo 入站不解析协议，直接转发到固定目标
+    Dokodemo(Address),
+}
+
+impl InboundCodec {
+    /// 按入站协议处理会话
+    pub async fn serve(
+        self,
+        stream: Box<dyn AsyncStream>,
+        connection_manager: ConnectionManager,
+        router: std::sync::Arc<Router>,
+        client_addr: std::net::SocketAddr,
+        inbound_settings: std::sync::Arc<InboundSettings>,
+        sockopt: SockOpt,
+    ) -> Result<()> {
+        match self {
+            InboundCodec::Vless(codec) => {
+                serve_vless(stream, codec, connection_manager, router, client_addr, inbound_settings, sockopt).await
+            }
+            InboundCodec::Vmess(codec) => {
+                serve_vmess(stream, codec, connection_manager, router, client_addr, inbound_settings, sockopt).await
+            }
+            InboundCodec::Shadowsocks(codec) => {
+                serve_shadowsocks(stream, codec, connection_manager, router, client_addr, inbound_settings, sockopt)
+                    .await
+            }
+            InboundCodec::Socks(codec) => {
+                serve_socks(stream, codec, connection_manager, router, client_addr, inbound_settings, sockopt).await
+            }
+            InboundCodec::Dokodemo(target) => {
+                info!("📨 dokodemo 转发: {} -> {}", client_addr, target.to_string());
+                attempt::record(AttemptOutcome::Forwarded, None);
+                let session = TcpSession {
+                    connection_manager: &connection_manager,
+                    router: &router,
+                    client_addr,
+                    user: None,
+                    sniffing: &inbound_settings.sniffing,
+                    sockopt: &sockopt,
+                };
+                session.relay(stream, target, Vec::new()).await
+            }
+        }
+    }
+}
+
+/// 处理 VMess 会话 (目前只转发 TCP)
+pub async fn serve_vmess(
+    mut stream: Box<dyn AsyncStream>,
+    codec: VmessCodec,
+    connection_manager: ConnectionManager,
+    router: std::sync::Arc<Router>,
+    client_addr: std::net::SocketAddr,
+    inbound_settings: std::sync::Arc<InboundSettings>,
+    sockopt: SockOpt,
+) -> Result<()> {
+    use tokio::time::{timeout, Duration};
+
+    let (uuid, request) = match timeout(Duration::from_secs(30), codec.read_request(&mut stream)).await {
+        Ok(Ok(request)) => request,
+        Ok(Err(e)) => {
+            crate::log_limited!(error, "vmess_decode", "❌ VMess 请求解码失败 (来源: {}): {}", client_addr, e);
+            attempt::record(AttemptOutcome::Rejected, None);
+            drain_rejected(&mut stream).await;
+            return Ok(());
+        }
+        Err(_) => {
+            error!("读取 VMess 请求超时");
+            return Err(anyhow::anyhow!("Read timeout"));
+        }
+    };
+    attempt::record(AttemptOutcome::Authenticated, codec.email(&uuid));
+    let ids = ConnectionIds::new(&uuid, None);
+    info!(
+        "📨 VMess 请求: {:?} -> {} (加密: {:?}, 连接: {})",
+        request.command,
+        request.address.as_ref().map(Address::to_string).unwrap_or_default(),
+        request.security,
+        ids.conn
+    );
+
+    let target = match (request.command, &request.address) {
+        (VmessCommand::Tcp, Some(address)) => address.clone(),
+        (command, _) => {
+            warn!("VMess {:?} 暂不支持", command);
+            return Ok(());
+        }
+    };
+
+    let session = TcpSession {
+        connection_manager: &connection_manager,
+        router: &router,
+        client_addr,
+        user: codec.email(&uuid),
+        sniffing: &inbound_settings.sniffing,
+        sockopt: &sockopt,
+    };
+    session.relay(VmessStream::new(stream, &request), target, Vec::new()).await
+}
+
+/// 入站协议解出 TCP 目标后的公共处理: 嗅探、路由、拨号与双向转发
+struct TcpSession<'a> {
+    connection_manager: &'a ConnectionManager,
+    router: &'a Router,
+    client_addr: std::net::SocketAddr,
+    /// 已认证用户的邮箱
+    user: Option<&'a str>,
+    sniffing: &'a SniffingConfig,
+    sockopt: &'a SockOpt,
+}
+
+impl TcpSession<'_> {
+    fn route_ctx<'b>(&'b self, address: &'b Address) -> RouteContext<'b> {
+        RouteContext::new(address)
+            .with_source(self.client_addr)
+            .with_user(self.user)
+    }
+
+    /// 转发到 `target`，`initial_data` 为已从客户端读到的首包
+    async fn relay<S>(&self, mut stream: S, mut target: Address, mut initial_data: Vec<u8>) -> Result<()>
+    where
+        S: AsyncStream + 'static,
+    {
+        use tokio::io::AsyncWriteExt;
+
+        let sniffing = self.sniffing;
+        let router = self.router;
+        let mut no_sni_outbound = None;
+        let mut sniffed_domain = None;
+
+        // --- 🌟 SNIFFING START ---
+        if sniffing.enabled {
+            read_sniff_data(&mut stream, &mut initial_data, sniffing).await;
 
-            // 发送初始数据
             if !initial_data.is_empty() {
-                remote_stream.write_all(&initial_data).await?;
+                match sniff_initial_data(sniffing, &initial_data) {
+                    SniffOutcome::Sni(sni) => {
+                        info!("👃 Sniffed SNI: {} (Override: {})", sni, target.to_string());
+                        sniffed_domain = Some(sni);
+                    }
+                    SniffOutcome::Host(host) => {
+                        info!("👃 Sniffed HTTP Host: {} (Override: {})", host, target.to_string());
+                        sniffed_domain = Some(host);
+                    }
+                    SniffOutcome::Block => {
+                        info!("🚫 TLS 流量 (无 SNI 或 ECH) 按配置断开: {}", target.to_string());
+                        return Ok(());
+                    }
+                    SniffOutcome::Route(tag) => {
+                        info!("👃 TLS 流量无 SNI，转交出站 {}: {}", tag, target.to_string());
+                        no_sni_outbound = Some(tag);
+                    }
+                    SniffOutcome::Keep => {}
+                }
             }
+        }
+        // --- SNIFFING END ---
 
-            // 开始双向转发
-            connection_manager
-                .handle_connection(stream, remote_stream)
-                .await?;
+        // 排除的域名既不改写目标也不参与路由
+        if let Some(domain) = sniffed_domain.as_deref().filter(|domain| sniffing.is_excluded(domain)) {
+            info!("👃 嗅探到的域名 {} 在排除列表中，保持原目标: {}", domain, target.to_string());
+            sniffed_domain = None;
         }
-        Command::Udp => {
-            info!("📡 UDP 请求: {}", request.address.to_string());
-            
-            // 创建 UDP socket (Full Cone NAT)
-            let udp_socket = match tokio::net::UdpSocket::bind("0.0.0.0:0").await {
-                Ok(s) => s,
+        // routeOnly 时嗅探到的域名只参与路由，否则改写目标 (保留原端口)
+        if !sniffing.route_only {
+            if let Some(domain) = sniffed_domain.take() {
+                target = Address::Domain(domain, target.port());
+            }
+        }
+
+        let decision = router.decide(&self.route_ctx(&target).with_sniffed_domain(sniffed_domain.as_deref()));
+        let outbound_tag = no_sni_outbound.unwrap_or(decision.outbound_tag);
+        let outbound = dialer(router, outbound_tag)?;
+        if outbound.is_blackhole() {
+            info!("🚫 路由到 blackhole ({}): {}", outbound_tag, target.to_string());
+            return Ok(());
+        }
+
+        let target_address = target.to_string();
+        let conn_id = crate::network::connection::next_connection_id();
+        info!(
+            "🔗 连接目标: {} (连接 #{}, 出站: {}, 来源: {}, 嗅探: {})",
+            target_address,
+            conn_id,
+            outbound_tag,
+            self.client_addr,
+            sniffed_domain.as_deref().unwrap_or("-")
+        );
+
+        // 连接远程服务器
+        let mut remote_stream = match outbound.connect_tcp(&target, self.sockopt).await {
+            Ok(s) => {
+                router.report_connect(outbound_tag, true);
+                s
+            }
+            Err(e) => {
+                router.report_connect(outbound_tag, false);
+                error!("{}", e);
+                return Err(e);
+            }
+        };
+
+        // 按路由规则打开流量镜像 (在后台打开，失败不影响正常转发)
+        let mut tee = decision.tee.map(|config| TeeSinks::open(config, conn_id));
+        let label = ConnectionLabel { id: conn_id, client: self.client_addr, target: target_address };
+
+        // 发送初始数据
+        if !initial_data.is_empty() {
+            if let Some(tee) = tee.as_mut() {
+                tee.mirror_uplink(&initial_data);
+            }
+            remote_stream.write_all(&initial_data).await?;
+        }
+
+        // 开始双向转发，等待转发结束以便调用方在此期间保持连接许可
+        let relay = self
+            .connection_manager
+            .handle_connection(stream, remote_stream, outbound_tag, label, tee)
+            .await?;
+        let _ = relay.await;
+        Ok(())
+    }
+}
+
+/// 处理 Shadowsocks 会话 (目前只转发 TCP)
+///
+/// 首个数据块无法解密或解析时不回复任何数据，读完客户端发来的数据后再关闭，
+/// 避免主动探测通过响应内容或关闭时机识别服务。
+pub async fn serve_shadowsocks(
+    mut stream: Box<dyn AsyncStream>,
+    codec: ShadowsocksCodec,
+    connection_manager: ConnectionManager,
+    router: std::sync::Arc<Router>,
+    client_addr: std::net::SocketAddr,
+    inbound_settings: std::sync::Arc<InboundSettings>,
+    sockopt: SockOpt,
+) -> Result<()> {
+    use tokio::time::{timeout, Duration};
+
+    let request = match timeout(Duration::from_secs(30), codec.read_request(&mut stream)).await {
+        Ok(Ok(request)) => request,
+        Ok(Err(e)) => {
+            crate::log_limited!(warn, "shadowsocks_decode", "Shadowsocks 请求无效 (来源: {}): {}", client_addr, e);
+            attempt::record(AttemptOutcome::Rejected, None);
+            drain_rejected(&mut stream).await;
+            return Ok(());
+        }
+        Err(_) => {
+            error!("读取 Shadowsocks 请求超时");
+            return Err(anyhow::anyhow!("Read timeout"));
+        }
+    };
+    attempt::record(AttemptOutcome::Authenticated, None);
+    info!("📨 Shadowsocks 请求: {} (来源: {})", request.target.to_string(), client_addr);
+
+    let session = TcpSession {
+        connection_manager: &connection_manager,
+        router: &router,
+        client_addr,
+        user: None,
+        sniffing: &inbound_settings.sniffing,
+        sockopt: &sockopt,
+    };
+    let target = request.target.clone();
+    session.relay(request.into_stream(stream), target, Vec::new()).await
+}
+
+/// 认证失败后继续读取并丢弃数据，直到客户端关闭或超时
+///
+/// 不在读到固定字节数后立即断开，避免主动探测按断开时机识别出协议。
+async fn drain_rejected(stream: &mut Box<dyn AsyncStream>) {
+    use tokio::io::AsyncReadExt;
+
+    let mut sink = [0u8; 4096];
+    let _ = tokio::time::timeout(std::time::Duration::from_secs(30), async {
+        while matches!(stream.read(&mut sink).await, Ok(n) if n > 0) {}
+    })
+    .await;
+}
+
+/// SOCKS5 UDP 关联最多同时转发的目标数
+const MAX_SOCKS_UDP_TARGETS: usize = 256;
+
+/// 处理 SOCKS5 会话
+///
+/// CONNECT 与其他入站一样经过嗅探、路由后转发；UDP ASSOCIATE 在新绑定的 UDP 端口上
+/// 中继数据报，控制连接关闭时结束。
+pub async fn serve_socks(
+    mut stream: Box<dyn AsyncStream>,
+    codec: SocksCodec,
+    connection_manager: ConnectionManager,
+    router: std::sync::Arc<Router>,
+    client_addr: std::net::SocketAddr,
+    inbound_settings: std::sync::Arc<InboundSettings>,
+    sockopt: SockOpt,
+) -> Result<()> {
+    use tokio::time::{timeout, Duration};
+
+    let request = match timeout(Duration::from_secs(30), codec.read_request(&mut stream)).await {
+        Ok(Ok(request)) => request,
+        Ok(Err(e)) => {
+            crate::log_limited!(warn, "socks_handshake", "SOCKS5 握手失败 (来源: {}): {}", client_addr, e);
+            attempt::record(AttemptOutcome::Rejected, None);
+            return Ok(());
+        }
+        Err(_) => {
+            error!("读取 SOCKS5 请求超时");
+            return Err(anyhow::anyhow!("Read timeout"));
+        }
+    };
+    attempt::record(AttemptOutcome::Authenticated, request.user.as_deref());
+    info!(
+        "📨 SOCKS5 请求: {:?} -> {} (来源: {})",
+        request.command,
+        request.target.to_string(),
+        client_addr
+    );
+
+    let session = TcpSession {
+        connection_manager: &connection_manager,
+        router: &router,
+        client_addr,
+        user: request.user.as_deref(),
+        sniffing: &inbound_settings.sniffing,
+        sockopt: &sockopt,
+    };
+    match request.command {
+        SocksCommand::Connect => {
+            socks::write_reply(&mut stream, socks::reply::SUCCEEDED, &socks::unspecified()).await?;
+            session.relay(stream, request.target, Vec::new()).await
+        }
+        SocksCommand::UdpAssociate => {
+            let bind_ip = inbound_settings.ip.unwrap_or(if client_addr.is_ipv4() {
+                std::net::Ipv4Addr::UNSPECIFIED.into()
+            } else {
+                std::net::Ipv6Addr::UNSPECIFIED.into()
+            });
+            let socket = match tokio::net::UdpSocket::bind((bind_ip, 0)).await {
+                Ok(socket) => socket,
                 Err(e) => {
-                    error!("无法创建 UDP socket: {}", e);
-                    return Err(e.into());
+                    socks::write_reply(&mut stream, socks::reply::GENERAL_FAILURE, &socks::unspecified()).await?;
+                    return Err(anyhow::anyhow!("无法绑定 SOCKS5 UDP 中继端口: {}", e));
                 }
             };
-            
-            // 解析目标地址
-            let target_addr = request.address.to_string();
-            let initial_target: std::net::SocketAddr = match tokio::net::lookup_host(&target_addr).await {
-                Ok(mut addrs) => {
-                    if let Some(addr) = addrs.next() {
-                        info!("🔗 UDP 初始目标: {}", addr);
-                        addr
-                    } else {
-                        error!("无法解析 UDP 目标地址: {}", target_addr);
-                        return Err(anyhow::anyhow!("DNS resolution failed"));
-                    }
-                }
+            let bound = Address::from(socket.local_addr()?);
+            socks::write_reply(&mut stream, socks::reply::SUCCEEDED, &bound).await?;
+            info!("📡 SOCKS5 UDP 中继: {} (来源: {})", bound.to_string(), client_addr);
+
+            session
+                .relay_socks_udp(stream, socket, inbound_settings.max_udp_datagram_size)
+                .await;
+            info!("📡 SOCKS5 UDP 关联结束");
+            Ok(())
+        }
+    }
+}
+
+impl TcpSession<'_> {
+    /// SOCKS5 UDP 中继: 按数据报中的目标分别路由并建立出站 UDP 会话，回包加上来源地址发回客户端
+    ///
+    /// 只接受来自客户端 IP 的数据报，第一个数据报确定客户端的 UDP 端口。
+    async fn relay_socks_udp(&self, mut control: Box<dyn AsyncStream>, socket: tokio::net::UdpSocket, max_datagram: usize) {
+        use std::collections::HashMap;
+        use std::sync::Arc;
+        use tokio::io::AsyncReadExt;
+        use tokio::time::{sleep_until, Duration, Instant};
+
+        // 与 VLESS UDP 一致，5 分钟没有上行数据报即结束
+        let session_timeout = Duration::from_secs(300);
+        let socket = Arc::new(socket);
+        let mut client_udp: Option<std::net::SocketAddr> = None;
+        let mut targets: HashMap<String, Arc<crate::outbound::UdpSession>> = HashMap::new();
+        // 各目标的回包任务，随关联结束一并中止
+        let mut replies = tokio::task::JoinSet::new();
+        let mut control_buf = [0u8; 64];
+        let mut buf = vec![0u8; 65536];
+        let mut last_activity = Instant::now();
+
+        loop {
+            let received = tokio::select! {
+                // 控制连接关闭 (或出现多余数据) 即结束关联
+                _ = control.read(&mut control_buf) => break,
+                _ = sleep_until(last_activity + session_timeout) => break,
+                received = socket.recv_from(&mut buf) => received,
+            };
+            let Ok((n, from)) = received else { continue };
+            if from.ip() != self.client_addr.ip() {
+                debug!("丢弃非客户端地址发来的 SOCKS5 UDP 数据报: {}", from);
+                continue;
+            }
+            let client = *client_udp.get_or_insert(from);
+            if client != from {
+                debug!("丢弃非关联端口发来的 SOCKS5 UDP 数据报: {}", from);
+                continue;
+            }
+            let (target, payload) = match socks::decode_udp_packet(&buf[..n]) {
+                Ok(packet) => packet,
                 Err(e) => {
-                    error!("DNS 解析失败: {}", e);
-                    return Err(e.into());
+                    debug!("丢弃无效的 SOCKS5 UDP 数据报: {}", e);
+                    continue;
                 }
             };
-            
-            // UDP 会话超时 (5分钟)
-            let session_timeout = Duration::from_secs(300);
-            
-            let udp_socket = std::sync::Arc::new(udp_socket);
-            let udp_socket_recv = udp_socket.clone();
-            
-            // 发送初始 UDP 数据
-            if !buf.is_empty() {
-               if buf.len() >= 2 {
-                    let len = ((buf[0] as usize) << 8) | (buf[1] as usize);
-                    if buf.len() >= 2 + len {
-                        let payload = &buf[2..2+len];
-                        if let Err(e) = udp_socket.send_to(payload, initial_target).await {
-                            error!("UDP 发送失败: {}", e);
-                        } else {
-                            debug!("UDP 发送了 {} 字节 (初始数据)", len);
-                        }
-                    }
-                }
+            if payload.len() > max_datagram {
+                warn!("丢弃超长 UDP 数据报: {} 字节 (上限 {})", payload.len(), max_datagram);
+                continue;
             }
-            
-            let (mut stream_read, mut stream_write) = tokio::io::split(stream);
-            let initial_target_clone = initial_target;
-            
-            // 客户端 -> UDP
-            let send_task = async {
-                let mut read_buf = vec![0u8; 8192];
-                let mut last_activity = tokio::time::Instant::now();
-                
-                loop {
-                    let read_timeout = session_timeout.saturating_sub(last_activity.elapsed());
-                    let mut len_buf = [0u8; 2];
-                    match timeout(read_timeout, stream_read.read_exact(&mut len_buf)).await {
-                        Ok(Ok(_)) => {
-                            last_activity = tokio::time::Instant::now();
-                            let len = ((len_buf[0] as usize) << 8) | (len_buf[1] as usize);
-                            if len == 0 || len > read_buf.len() {
+            last_activity = Instant::now();
+
+            let key = target.to_string();
+            let udp_session = match targets.get(&key) {
+                Some(udp_session) => udp_session.clone(),
+                None => {
+                    if targets.len() >= MAX_SOCKS_UDP_TARGETS {
+                        warn!("SOCKS5 UDP 关联的目标数已达上限 {}，丢弃发往 {} 的数据报", MAX_SOCKS_UDP_TARGETS, key);
+                        continue;
+                    }
+                    let outbound_tag = self.router.route(&self.route_ctx(&target));
+                    let Ok(outbound) = dialer(self.router, outbound_tag) else { continue };
+                    if outbound.is_blackhole() {
+                        info!("🚫 UDP 路由到 blackhole ({}): {}", outbound_tag, key);
+                        continue;
+                    }
+                    let udp_session = match outbound.connect_udp(&target, self.sockopt).await {
+                        Ok(udp_session) => Arc::new(udp_session),
+                        Err(e) => {
+                            error!("无法建立 UDP 会话 (出站: {}): {}", outbound_tag, e);
+                            continue;
+                        }
+                    };
+                    info!("📡 UDP 目标: {} (出站: {})", key, outbound_tag);
+
+                    let reply_session = udp_session.clone();
+                    let reply_socket = socket.clone();
+                    replies.spawn(async move {
+                        let mut recv_buf = vec![0u8; 65536];
+                        while let Ok(n) = reply_session.recv(&mut recv_buf).await {
+                            if n > max_datagram {
+                                warn!("丢弃超长 UDP 回包: {} 字节 (上限 {})", n, max_datagram);
+                                continue;
+                            }
+                            let packet = socks::encode_udp_packet(&target, &recv_buf[..n]);
+                            if reply_socket.send_to(&packet, client).await.is_err() {
                                 break;
                             }
-                            match stream_read.read_exact(&mut read_buf[..len]).await {
-                                Ok(_) => {
-                                    if let Err(_) = udp_socket.send_to(&read_buf[..len], initial_target_clone).await {
+                        }
+                    });
+                    targets.insert(key, udp_session.clone());
+                    udp_session
+                }
+            };
+            if let Err(e) = udp_session.send(payload).await {
+                error!("UDP 发送失败: {}", e);
+            }
+        }
+    }
+}
+
+/// Mux 写队列最多缓存的下行帧数
+const MUX_WRITE_QUEUE: usize = 64;
+/// Mux 子连接上行管道的缓冲区大小
+const MUX_PIPE_BUFFER: usize = 64 * 1024;
+/// 每个 Mux 子连接上行队列最多缓存的数据帧数
+///
+/// 队列满时 TCP 子连接被重置，UDP 子连接丢弃该数据报。
+const MUX_SESSION_QUEUE: usize = 32;
+/// 下行数据帧的单帧数据上限
+const MUX_MAX_CHUNK: usize = 16 * 1024;
+/// 单个 Mux 连接最多同时存在的子连接数
+const MAX_MUX_SESSIONS: usize = 128;
+
+/// Mux 子连接共用的会话参数，子连接在独立任务中转发，因此持有所有权
+struct MuxContext {
+    connection_manager: ConnectionManager,
+    router: std::sync::Arc<Router>,
+    client_addr: std::net::SocketAddr,
+    user: Option<String>,
+    inbound_settings: std::sync::Arc<InboundSettings>,
+    sockopt: SockOpt,
+}
+
+impl MuxContext {
+    fn session(&self) -> TcpSession<'_> {
+        TcpSession {
+            connection_manager: &self.connection_manager,
+            router: &self.router,
+            client_addr: self.client_addr,
+            user: self.user.as_deref(),
+            sniffing: &self.inbound_settings.sniffing,
+            sockopt: &self.sockopt,
+        }
+    }
+}
+
+/// Mux 子连接的上行端
+enum MuxUplink {
+    /// TCP 子连接: 子连接任务从队列中取出数据写入交给 [`TcpSession::relay`] 的内存管道
+    Tcp(tokio::sync::mpsc::Sender<bytes::Bytes>),
+    /// UDP 子连接: 每个数据帧是一个数据报，XUDP 帧附带该数据报的目标
+    Udp(tokio::sync::mpsc::Sender<(Option<Address>, bytes::Bytes)>),
+}
+
+struct MuxSession {
+    /// 客户端发送 End 后为 `None`，下行方向继续直到目标关闭
+    uplink: Option<MuxUplink>,
+    cancel: tokio_util::sync::CancellationToken,
+}
+
+/// 处理 VLESS Mux (Mux.cool) 连接
+///
+/// 每个 TCP 子连接经内存管道交给 [`TcpSession::relay`]，与普通请求一样嗅探、路由和拨号；
+/// UDP 子连接按数据帧收发数据报，XUDP 的帧可以逐包指定目标 (见 [`relay_mux_udp`])。
+/// 子连接各自在独立任务中转发，下行数据经同一写队列按帧交错发回客户端。
+/// 上行数据放入各子连接自己的队列，帧读取循环从不等待某个子连接的目标，
+/// 目标不读取导致队列写满时只重置该子连接。客户端连接关闭时所有子连接随之结束。
+async fn serve_mux(stream: Box<dyn AsyncStream>, initial_data: &[u8], context: std::sync::Arc<MuxContext>) {
+    use crate::protocol::mux::{Frame, MuxCodec};
+    use futures::StreamExt;
+    use std::collections::HashMap;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_util::codec::FramedRead;
+
+    let (read_half, mut write_half) = tokio::io::split(stream);
+    let mut frames = FramedRead::new(initial_data.chain(read_half), MuxCodec);
+    let (frame_tx, mut frame_rx) = tokio::sync::mpsc::channel::<Frame>(MUX_WRITE_QUEUE);
+
+    // 写任务: 合并队列中已有的帧后一次写出
+    let mut writer = tokio::spawn(async move {
+        let mut buf = bytes::BytesMut::new();
+        while let Some(frame) = frame_rx.recv().await {
+            frame.encode(&mut buf)?;
+            while buf.len() < MUX_PIPE_BUFFER {
+                match frame_rx.try_recv() {
+                    Ok(frame) => frame.encode(&mut buf)?,
+                    Err(_) => break,
+                }
+            }
+            write_half.write_all(&buf).await?;
+            write_half.flush().await?;
+            buf.clear();
+        }
+        Ok::<_, anyhow::Error>(())
+    });
+
+    let mut sessions: HashMap<u16, MuxSession> = HashMap::new();
+    let mut tasks = tokio::task::JoinSet::new();
+
+    loop {
+        let frame = tokio::select! {
+            frame = frames.next() => match frame {
+                Some(Ok(frame)) => frame,
+                Some(Err(e)) => {
+                    crate::log_limited!(warn, "mux_decode", "Mux 帧解析失败 (来源: {}): {}", context.client_addr, e);
+                    break;
+                }
+                None => break,
+            },
+            Some(finished) = tasks.join_next(), if !tasks.is_empty() => {
+                if let Ok(id) = finished {
+                    sessions.remove(&id);
+                }
+                continue;
+            }
+            _ = &mut writer => break,
+        };
+
+        let id = frame.meta.session_id;
+        match frame.meta.status {
+            SessionStatus::New => {
+                let Some((network, target)) = frame.meta.target else { continue };
+                if sessions.contains_key(&id) || sessions.len() >= MAX_MUX_SESSIONS {
+                    warn!("拒绝 Mux 子连接 {} -> {} (重复的会话 ID 或已达上限 {})", id, target.to_string(), MAX_MUX_SESSIONS);
+                    let _ = frame_tx.send(Frame::end(id, true)).await;
+                    continue;
+                }
+                debug!("🔀 Mux 子连接 {}: {:?} -> {}", id, network, target.to_string());
+                let cancel = tokio_util::sync::CancellationToken::new();
+                let data = frame.data.unwrap_or_default();
+                let uplink = match network {
+                    TargetNetwork::Tcp => {
+                        let (local, remote) = tokio::io::duplex(MUX_PIPE_BUFFER);
+                        let (mut downlink, mut pipe) = tokio::io::split(local);
+                        let (uplink, mut chunks) = tokio::sync::mpsc::channel::<bytes::Bytes>(MUX_SESSION_QUEUE);
+                        let context = context.clone();
+                        let frame_tx = frame_tx.clone();
+                        let cancel = cancel.clone();
+                        tasks.spawn(async move {
+                            // 客户端结束子连接 (队列关闭) 后半关闭上行，目标收到 EOF 后下行方向自然结束
+                            let feed = async {
+                                while let Some(data) = chunks.recv().await {
+                                    if pipe.write_all(&data).await.is_err() {
+                                        break;
+                                    }
+                                }
+                                let _ = pipe.shutdown().await;
+                                std::future::pending::<()>().await
+                            };
+                            let pump = async {
+                                let mut buf = vec![0u8; MUX_MAX_CHUNK];
+                                while let Ok(n) = downlink.read(&mut buf).await {
+                                    if n == 0 || frame_tx.send(Frame::keep(id, bytes::Bytes::copy_from_slice(&buf[..n]))).await.is_err() {
                                         break;
                                     }
                                 }
-                                Err(_) => break,
+                            };
+                            let session = context.session();
+                            let relay = session.relay(remote, target, data.to_vec());
+                            let failed = tokio::select! {
+                                (result, ()) = async { tokio::join!(relay, pump) } => result.is_err(),
+                                _ = feed => true,
+                                _ = cancel.cancelled() => true,
+                            };
+                            let _ = frame_tx.send(Frame::end(id, failed)).await;
+                            id
+                        });
+                        MuxUplink::Tcp(uplink)
+                    }
+                    TargetNetwork::Udp => {
+                        let (uplink, datagrams) = tokio::sync::mpsc::channel(MUX_SESSION_QUEUE);
+                        if !data.is_empty() {
+                            let _ = uplink.try_send((None, data));
+                        }
+                        let flow = MuxUdpFlow {
+                            id,
+                            target,
+                            xudp: frame.meta.global_id.is_some(),
+                        };
+                        tasks.spawn(relay_mux_udp(context.clone(), flow, datagrams, frame_tx.clone(), cancel.clone()));
+                        MuxUplink::Udp(uplink)
+                    }
+                };
+                sessions.insert(id, MuxSession { uplink: Some(uplink), cancel });
+            }
+            SessionStatus::Keep => {
+                let Some(data) = frame.data else { continue };
+                let Some(session) = sessions.get_mut(&id) else {
+                    // 未知的子连接，通知客户端结束
+                    let _ = frame_tx.send(Frame::end(id, false)).await;
+                    continue;
+                };
+                use tokio::sync::mpsc::error::TrySendError;
+                let delivered = match &session.uplink {
+                    Some(MuxUplink::Tcp(chunks)) => match chunks.try_send(data) {
+                        Ok(()) => true,
+                        Err(TrySendError::Full(_)) => {
+                            // 目标不读取，重置该子连接而不阻塞其他子连接
+                            warn!("Mux 子连接 {} 上行队列已满，重置该子连接", id);
+                            session.cancel.cancel();
+                            false
+                        }
+                        Err(TrySendError::Closed(_)) => false,
+                    },
+                    Some(MuxUplink::Udp(datagrams)) => {
+                        let destination = frame.meta.target.map(|(_, address)| address);
+                        match datagrams.try_send((destination, data)) {
+                            Ok(()) => true,
+                            Err(TrySendError::Full(_)) => {
+                                debug!("Mux 子连接 {} 上行队列已满，丢弃数据报", id);
+                                true
                             }
+                            Err(TrySendError::Closed(_)) => false,
                         }
-                        Ok(Err(_)) | Err(_) => break,
                     }
+                    None => true,
+                };
+                if !delivered {
+                    session.uplink = None;
                 }
-            };
-            
-            // UDP -> 客户端
-            let recv_task = async {
-                let mut recv_buf = vec![0u8; 8192];
-                let mut last_activity = tokio::time::Instant::now();
-                loop {
-                    let recv_timeout = session_timeout.saturating_sub(last_activity.elapsed());
-                    match timeout(recv_timeout, udp_socket_recv.recv_from(&mut recv_buf)).await {
-                        Ok(Ok((n, _))) => {
-                            if n == 0 { break; }
-                            last_activity = tokio::time::Instant::now();
-                            let len_bytes = [(n >> 8) as u8, (n & 0xff) as u8];
-                            let mut frame = Vec::with_capacity(2 + n);
-                            frame.extend_from_slice(&len_bytes);
-                            frame.extend_from_slice(&recv_buf[..n]);
-                            if stream_write.write_all(&frame).await.is_err() { break; }
-                            if stream_write.flush().await.is_err() { break; }
+            }
+            SessionStatus::End => {
+                let Some(session) = sessions.get_mut(&id) else { continue };
+                if frame.meta.has_error() {
+                    session.cancel.cancel();
+                }
+                // 关闭上行队列，子连接任务写完队列中的数据后半关闭上行
+                session.uplink = None;
+            }
+            SessionStatus::KeepAlive => {}
+        }
+    }
+
+    // 客户端已断开，结束所有子连接
+    tasks.abort_all();
+    writer.abort();
+}
+
+/// Mux 中的一个 UDP 子连接
+struct MuxUdpFlow {
+    id: u16,
+    /// New 帧中的目标，决定路由与出站
+    target: Address,
+    /// 是否为 XUDP: New 帧带全局 ID 或 Keep 帧带目标时，回包附带来源地址
+    xudp: bool,
+}
+
+/// 转发 Mux 中的一个 UDP 子连接
+///
+/// 每个子连接使用一个出站 UDP 会话 (full-cone): 按包发往帧中指定的目标 (未指定时为
+/// New 帧的目标)，任何来源的回包都转发给客户端。XUDP 的回包附带来源地址以便客户端区分。
+async fn relay_mux_udp(
+    context: std::sync::Arc<MuxContext>,
+    flow: MuxUdpFlow,
+    mut datagrams: tokio::sync::mpsc::Receiver<(Option<Address>, bytes::Bytes)>,
+    frame_tx: tokio::sync::mpsc::Sender<crate::protocol::mux::Frame>,
+    cancel: tokio_util::sync::CancellationToken,
+) -> u16 {
+    use crate::protocol::mux::Frame;
+    use tokio::time::{sleep_until, Duration, Instant};
+
+    let MuxUdpFlow { id, target, mut xudp } = flow;
+
+    let session = context.session();
+    let max_datagram = context.inbound_settings.max_udp_datagram_size;
+    let outbound_tag = session.router.route(&session.route_ctx(&target));
+    let udp_session = match dialer(session.router, outbound_tag) {
+        Ok(outbound) if outbound.is_blackhole() => {
+            info!("🚫 UDP 路由到 blackhole ({}): {}", outbound_tag, target.to_string());
+            None
+        }
+        Ok(outbound) => match outbound.connect_udp(&target, session.sockopt).await {
+            Ok(udp_session) => Some(udp_session),
+            Err(e) => {
+                error!("无法建立 UDP 会话 (出站: {}): {}", outbound_tag, e);
+                None
+            }
+        },
+        Err(_) => None,
+    };
+    let Some(udp_session) = udp_session else {
+        let _ = frame_tx.send(Frame::end(id, true)).await;
+        return id;
+    };
+
+    // 与 VLESS UDP 一致，双向空闲 5 分钟后结束
+    let session_timeout = Duration::from_secs(300);
+    let mut last_activity = Instant::now();
+    let mut recv_buf = vec![0u8; 65536];
+    loop {
+        tokio::select! {
+            datagram = datagrams.recv() => {
+                let Some((destination, datagram)) = datagram else { break };
+                last_activity = Instant::now();
+                xudp |= destination.is_some();
+                if datagram.len() > max_datagram {
+                    warn!("丢弃超长 UDP 数据报: {} 字节 (上限 {})", datagram.len(), max_datagram);
+                    continue;
+                }
+                let sent = match destination {
+                    Some(destination) if destination != target => udp_session.send_to(&datagram, &destination).await,
+                    _ => udp_session.send(&datagram).await,
+                };
+                if let Err(e) = sent {
+                    error!("UDP 发送失败: {}", e);
+                }
+            }
+            received = udp_session.recv_from(&mut recv_buf) => {
+                let Ok((n, source)) = received else { break };
+                last_activity = Instant::now();
+                if n > max_datagram {
+                    warn!("丢弃超长 UDP 回包: {} 字节 (上限 {})", n, max_datagram);
+                    continue;
+                }
+                let data = bytes::Bytes::copy_from_slice(&recv_buf[..n]);
+                let frame = if xudp { Frame::packet(id, source, data) } else { Frame::keep(id, data) };
+                if frame_tx.send(frame).await.is_err() {
+                    break;
+                }
+            }
+            _ = sleep_until(last_activity + session_timeout) => break,
+            _ = cancel.cancelled() => break,
+        }
+    }
+    let _ = frame_tx.send(Frame::end(id, false)).await;
+    id
+}
+
+/// dokodemo UDP 转发中单个客户端的出站会话
+struct DokodemoUdpClient {
+    session: crate::outbound::UdpSession,
+    last_uplink: std::sync::Mutex<tokio::time::Instant>,
+}
+
+/// dokodemo 入站的 UDP 转发
+///
+/// 每个客户端地址按来源单独路由并建立一个出站 UDP 会话，双向空闲 5 分钟后回收。
+pub async fn serve_dokodemo_udp(
+    socket: tokio::net::UdpSocket,
+    target: Address,
+    router: std::sync::Arc<Router>,
+    max_datagram: usize,
+    sockopt: SockOpt,
+) -> Result<()> {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tokio::time::{timeout, Duration, Instant};
+
+    let idle_timeout = Duration::from_secs(300);
+    let socket = Arc::new(socket);
+    let clients: Arc<Mutex<HashMap<std::net::SocketAddr, Arc<DokodemoUdpClient>>>> = Arc::default();
+    // 各客户端的回包任务，随入站结束一并中止
+    let mut replies = tokio::task::JoinSet::new();
+    let mut buf = vec![0u8; 65536];
+
+    loop {
+        let (n, from) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => {
+                debug!("dokodemo UDP 接收失败: {}", e);
+                continue;
+            }
+        };
+        while replies.try_join_next().is_some() {}
+        if n > max_datagram {
+            warn!("丢弃超长 UDP 数据报: {} 字节 (上限 {})", n, max_datagram);
+            continue;
+        }
+
+        let existing = clients.lock().unwrap().get(&from).cloned();
+        let client = match existing {
+            Some(client) => client,
+            None => {
+                let outbound_tag = router.route(&RouteContext::new(&target).with_source(from));
+                let Ok(outbound) = dialer(&router, outbound_tag) else { continue };
+                if outbound.is_blackhole() {
+                    info!("🚫 UDP 路由到 blackhole ({}): {}", outbound_tag, target.to_string());
+                    continue;
+                }
+                let session = match outbound.connect_udp(&target, &sockopt).await {
+                    Ok(session) => session,
+                    Err(e) => {
+                        error!("无法建立 UDP 会话 (出站: {}): {}", outbound_tag, e);
+                        continue;
+                    }
+                };
+                info!("📡 dokodemo UDP: {} -> {} (出站: {})", from, target.to_string(), outbound_tag);
+                let client = Arc::new(DokodemoUdpClient {
+                    session,
+                    last_uplink: Mutex::new(Instant::now()),
+                });
+                clients.lock().unwrap().insert(from, client.clone());
+
+                let reply_client = client.clone();
+                let reply_socket = socket.clone();
+                let reply_clients = clients.clone();
+                replies.spawn(async move {
+                    let mut recv_buf = vec![0u8; 65536];
+                    loop {
+                        match timeout(idle_timeout, reply_client.session.recv(&mut recv_buf)).await {
+                            Ok(Ok(n)) => {
+                                if n > max_datagram {
+                                    warn!("丢弃超长 UDP 回包: {} 字节 (上限 {})", n, max_datagram);
+                                    continue;
+                                }
+                                if reply_socket.send_to(&recv_buf[..n], from).await.is_err() {
+                                    break;
+                                }
+                            }
+                            Ok(Err(_)) => break,
+                            // 下行空闲，但上行仍然活跃时继续等待
+                            Err(_) => {
+                                if reply_client.last_uplink.lock().unwrap().elapsed() >= idle_timeout {
+                                    break;
+                                }
+                            }
                         }
-                        Ok(Err(_)) | Err(_) => break,
                     }
+                    reply_clients.lock().unwrap().remove(&from);
+                    debug!("dokodemo UDP 会话结束: {}", from);
+                });
+                client
+            }
+        };
+
+        *client.last_uplink.lock().unwrap() = Instant::now();
+        if let Err(e) = client.session.send(&buf[..n]).await {
+            error!("UDP 发送失败: {}", e);
+        }
+    }
+}
+
+/// 日志用的连接标识
+///
+/// 每个连接都有独立的 `conn`；客户端在附加数据中携带会话延续令牌时，
+/// `session` 由用户 UUID 与令牌派生，重连后保持不变，否则与 `conn` 相同。
+struct ConnectionIds {
+    session: String,
+    conn: String,
+}
+
+impl ConnectionIds {
+    fn new(uuid: &uuid::Uuid, continuity: Option<&str>) -> Self {
+        use sha2::{Digest, Sha256};
+
+        let conn = format!("{:016x}", rand::random::<u64>());
+        let session = match continuity {
+            // 不直接记录令牌本身，避免日志泄露客户端标识
+            Some(token) => {
+                let mut hasher = Sha256::new();
+                hasher.update(uuid.as_bytes());
+                hasher.update(token.as_bytes());
+                hex::encode(&hasher.finalize()[..8])
+            }
+            None => conn.clone(),
+        };
+        Self { session, conn }
+    }
+}
+
+/// 按入站配置生成探测响应
+fn probe_response_bytes(probe: &ProbeResponse) -> Vec<u8> {
+    let reason = match probe.status {
+        200 => "OK",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        _ => "",
+    };
+    let mut response = format!("HTTP/1.1 {} {}\r\n", probe.status, reason);
+    for (name, value) in &probe.headers {
+        response.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    // 204/304 不允许携带响应体
+    let has_body = !matches!(probe.status, 204 | 304);
+    if has_body {
+        response.push_str(&format!("Content-Length: {}\r\n", probe.body.len()));
+    }
+    response.push_str("\r\n");
+    if has_body {
+        response.push_str(&probe.body);
+    }
+    response.into_bytes()
+}
+
+/// 首包是否像明文 HTTP 请求 (VLESS 请求首字节为版本号 0)
+fn looks_like_http(buf: &[u8]) -> bool {
+    buf.first() != Some(&0)
+        && buf
+            .windows(4)
+            .any(|w| w == b"GET " || w == b"POST" || w == b"HEAD")
+}
+
+/// 过载时拒绝连接
+///
+/// 明文入站先短暂读取首包: HTTP 请求 (含 XHTTP) 回复 503 + Retry-After 让客户端退避，
+/// 其他流量 (原始 VLESS、TLS/Reality) 直接关闭。每次回复占用一个任务，调用方负责限制同时回复的数量。
+pub async fn reject_overloaded<S>(mut stream: S, sniff_http: bool, retry_after: u64)
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::time::{timeout, Duration};
+    const SNIFF_TIMEOUT: Duration = Duration::from_secs(1);
+
+    if !sniff_http {
+        return;
+    }
+    let mut buf = [0u8; 1024];
+    let n = match timeout(SNIFF_TIMEOUT, stream.read(&mut buf)).await {
+        Ok(Ok(n)) => n,
+        _ => return,
+    };
+    if !looks_like_http(&buf[..n]) {
+        return;
+    }
+
+    let response = ProbeResponse {
+        status: 503,
+        headers: [
+            ("Retry-After".to_string(), retry_after.to_string()),
+            ("Connection".to_string(), "close".to_string()),
+        ]
+        .into_iter()
+        .collect(),
+        body: String::new(),
+    };
+    if stream.write_all(&probe_response_bytes(&response)).await.is_err() {
+        return;
+    }
+    let _ = stream.shutdown().await;
+    // 读完请求剩余部分再关闭，避免未读数据触发 RST 冲掉响应
+    let _ = timeout(SNIFF_TIMEOUT, async {
+        while matches!(stream.read(&mut buf).await, Ok(n) if n > 0) {}
+    })
+    .await;
+}
+
+/// VLESS UDP 会话中单个目标的空闲超时
+const UDP_IDLE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// 按 VLESS UDP 帧 (`[len:u16][payload]`) 读取数据报，超长的数据报单独丢弃
+struct UdpFrameReader<R> {
+    reader: R,
+    buf: Vec<u8>,
+    max_datagram: usize,
+}
+
+impl<R: tokio::io::AsyncRead + Unpin> UdpFrameReader<R> {
+    fn new(reader: R, max_datagram: usize) -> Self {
+        Self {
+            reader,
+            buf: Vec::new(),
+            max_datagram,
+        }
+    }
+
+    /// 读取下一个数据报，连接关闭或收到长度为 0 的帧时返回 `None`
+    async fn next(&mut self) -> std::io::Result<Option<&[u8]>> {
+        use tokio::io::AsyncReadExt;
+
+        loop {
+            let len = match self.reader.read_u16().await {
+                Ok(len) => len as usize,
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e),
+            };
+            if len == 0 {
+                return Ok(None);
+            }
+            if len > self.max_datagram {
+                // 只丢弃这一个数据报，跳过其负载后继续会话
+                warn!("丢弃超长 UDP 数据报: {} 字节 (上限 {})", len, self.max_datagram);
+                let mut skip = (&mut self.reader).take(len as u64);
+                if tokio::io::copy(&mut skip, &mut tokio::io::sink()).await? < len as u64 {
+                    return Ok(None);
                 }
+                continue;
+            }
+            self.buf.resize(len, 0);
+            self.reader.read_exact(&mut self.buf).await?;
+            return Ok(Some(&self.buf));
+        }
+    }
+}
+
+/// 拆出数据报的目标: packet-addr 会话从负载开头解析，否则为请求头中的目标
+fn split_udp_destination<'a>(packet_addr: bool, target: &Address, datagram: &'a [u8]) -> Result<(Address, &'a [u8])> {
+    if packet_addr {
+        packet_addr::decode(datagram)
+    } else {
+        Ok((target.clone(), datagram))
+    }
+}
+
+/// UDP 会话中各目标的最近活动时间，空闲的目标单独过期
+struct UdpDestinations {
+    idle_timeout: std::time::Duration,
+    last_activity: std::sync::Mutex<std::collections::HashMap<Address, tokio::time::Instant>>,
+}
+
+impl UdpDestinations {
+    fn new(idle_timeout: std::time::Duration) -> Self {
+        Self {
+            idle_timeout,
+            last_activity: Default::default(),
+        }
+    }
+
+    /// 刷新目标的活动时间；`insert` 为 false 时只刷新已有的目标 (回包来源)
+    fn touch(&self, address: &Address, insert: bool) {
+        let mut last_activity = self.last_activity.lock().unwrap();
+        if let Some(at) = last_activity.get_mut(address) {
+            *at = tokio::time::Instant::now();
+        } else if insert {
+            last_activity.insert(address.clone(), tokio::time::Instant::now());
+        }
+    }
+
+    /// 移除空闲的目标，返回最早的下一次过期时间；没有目标时返回 `None`
+    fn evict_idle(&self) -> Option<tokio::time::Instant> {
+        let now = tokio::time::Instant::now();
+        let mut last_activity = self.last_activity.lock().unwrap();
+        last_activity.retain(|address, at| {
+            let alive = *at + self.idle_timeout > now;
+            if !alive {
+                debug!("UDP 目标空闲过期: {}", address.to_string());
+            }
+            alive
+        });
+        last_activity.values().map(|at| *at + self.idle_timeout).min()
+    }
+}
+
+/// 读取嗅探所需的首包数据
+///
+/// 首包已是完整 ClientHello 时直接返回；否则按配置的等待时间补读首包或 ClientHello 的后续记录。
+async fn read_sniff_data<S>(stream: &mut S, data: &mut Vec<u8>, sniffing: &SniffingConfig)
+where
+    S: tokio::io::AsyncRead + Unpin + ?Sized,
+{
+    use tokio::io::AsyncReadExt;
+    use tokio::time::{timeout, timeout_at, Duration, Instant};
+
+    if sniffer::is_client_hello(data) {
+        return;
+    }
+
+    // 如果没有初始数据，尝试再次通过超时读取
+    let mut temp_buf = vec![0u8; 4096];
+    if data.is_empty() {
+        if let Ok(Ok(n)) = timeout(Duration::from_millis(sniffing.first_packet_wait), stream.read(&mut temp_buf)).await {
+            if n > 0 {
+                data.extend_from_slice(&temp_buf[..n]);
+                debug!("Sniffing: 读取了额外的 {} 字节", n);
+            }
+        }
+    }
+
+    // ClientHello 被拆分到多个记录、HTTP 请求头被拆分到多次读取时，继续读取直到完整
+    let deadline = Instant::now() + Duration::from_millis(sniffing.fragment_wait);
+    while sniffer::is_partial_client_hello(data) || sniffer::is_partial_http_request(data) {
+        match timeout_at(deadline, stream.read(&mut temp_buf)).await {
+            Ok(Ok(n)) if n > 0 => {
+                data.extend_from_slice(&temp_buf[..n]);
+                debug!("Sniffing: 首包未完整，继续读取 {} 字节", n);
+            }
+            _ => break,
+        }
+    }
+}
+
+/// 查找路由选中的出站连接器
+fn dialer(router: &Router, tag: &str) -> Result<std::sync::Arc<dyn Dialer>> {
+    router
+        .dialer(tag)
+        .ok_or_else(|| anyhow::anyhow!("出站不存在: {}", tag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::InboundNetwork;
+    use crate::protocol::sniffer::tests::{client_hello_ext, client_hello_with, record};
+
+    fn sniffing(action: NoSniAction, outbound: Option<&str>) -> SniffingConfig {
+        SniffingConfig {
+            enabled: true,
+            no_sni_action: action,
+            no_sni_outbound: outbound.map(str::to_string),
+            ..SniffingConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_no_sni_policy() {
+        let no_sni = record(&client_hello_with(None, 64));
+        let with_sni = record(&client_hello_with(Some("example.com"), 64));
+        let plain = b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n".to_vec();
+
+        let proceed = sniffing(NoSniAction::Proceed, None);
+        assert_eq!(sniff_initial_data(&proceed, &no_sni), SniffOutcome::Keep);
+
+        let block = sniffing(NoSniAction::Block, None);
+        assert_eq!(sniff_initial_data(&block, &no_sni), SniffOutcome::Block);
+
+        let route = sniffing(NoSniAction::Route, Some("ech-out"));
+        assert_eq!(sniff_initial_data(&route, &no_sni), SniffOutcome::Route("ech-out"));
+
+        // 策略只作用于无 SNI 的 TLS 流量
+        assert_eq!(
+            sniff_initial_data(&block, &with_sni),
+            SniffOutcome::Sni("example.com".to_string())
+        );
+        assert_eq!(sniff_initial_data(&block, &plain), SniffOutcome::Host("example.com".to_string()));
+    }
+
+    #[test]
+    fn test_http_host_respects_dest_override() {
+        let plain = b"GET / HTTP/1.1\r\nHost: example.com:8080\r\n\r\n";
+        let mut config = sniffing(NoSniAction::Proceed, None);
+        assert_eq!(sniff_initial_data(&config, plain), SniffOutcome::Host("example.com".to_string()));
+
+        config.dest_override = vec!["tls".to_string()];
+        assert_eq!(sniff_initial_data(&config, plain), SniffOutcome::Keep);
+    }
+
+    #[test]
+    fn test_tls_sni_respects_dest_override() {
+        let hello = record(&client_hello_with(Some("example.com"), 64));
+        let plain = b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n";
+
+        // 只覆盖 TLS: HTTP 流量保持原目标
+        let mut config = sniffing(NoSniAction::Proceed, None);
+        config.dest_override = vec!["tls".to_string()];
+        assert_eq!(sniff_initial_data(&config, &hello), SniffOutcome::Sni("example.com".to_string()));
+        assert_eq!(sniff_initial_data(&config, plain), SniffOutcome::Keep);
+
+        // 只覆盖 HTTP: TLS 流量保持原目标，无 SNI 的策略不受影响
+        config.dest_override = vec!["http".to_string()];
+        assert_eq!(sniff_initial_data(&config, &hello), SniffOutcome::Keep);
+        assert_eq!(sniff_initial_data(&config, plain), SniffOutcome::Host("example.com".to_string()));
+        config.no_sni_action = NoSniAction::Block;
+        assert_eq!(sniff_initial_data(&config, &record(&client_hello_with(None, 64))), SniffOutcome::Block);
+    }
+
+    #[tokio::test]
+    async fn test_read_sniff_data_waits_for_http_headers() {
+        use tokio::io::AsyncWriteExt;
+
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let mut data = b"GET / HTTP/1.1\r\nUser-Agent: curl/8.0\r\nHo".to_vec();
+        let reader = tokio::spawn(async move {
+            read_sniff_data(&mut server, &mut data, &sniffing(NoSniAction::Proceed, None)).await;
+            data
+        });
+        client.write_all(b"st: example.com\r\n\r\n").await.unwrap();
+
+        let data = reader.await.unwrap();
+        assert!(data.ends_with(b"\r\n\r\n"));
+        assert_eq!(sniffer::sniff_http_host(&data), Some("example.com".to_string()));
+    }
+
+    #[test]
+    fn test_ech_policy() {
+        let ech = record(&client_hello_ext(Some("public.example.com"), 64, true));
+
+        let mut config = sniffing(NoSniAction::Proceed, None);
+        assert_eq!(
+            sniff_initial_data(&config, &ech),
+            SniffOutcome::Sni("public.example.com".to_string())
+        );
+
+        config.ech_action = EchAction::Block;
+        assert_eq!(sniff_initial_data(&config, &ech), SniffOutcome::Block);
+
+        config.ech_action = EchAction::Passthrough;
+        assert_eq!(sniff_initial_data(&config, &ech), SniffOutcome::Keep);
+    }
+
+    /// 只有一个直连出站 (标签 direct) 的路由器
+    fn direct_router() -> Router {
+        use crate::config::{Outbound, RoutingConfig};
+
+        let outbounds = vec![Outbound {
+            protocol: "freedom".to_string(),
+            tag: "direct".to_string(),
+            settings: None,
+            stream_settings: None,
+        }];
+        Router::new(&RoutingConfig::default(), &outbounds).unwrap()
+    }
+
+    /// 在内存管道上启动一个 VLESS 会话，返回客户端一端与会话任务
+    fn spawn_session_with(
+        uuid: uuid::Uuid,
+        router: Router,
+        sniffing: SniffingConfig,
+        max_udp_datagram_size: usize,
+    ) -> (tokio::io::DuplexStream, tokio::task::JoinHandle<Result<()>>) {
+        let codec = VlessCodec::new(vec![uuid]);
+        let settings = std::sync::Arc::new(InboundSettings {
+            clients: vec![],
+            decryption: "none".to_string(),
+            sniffing,
+            max_udp_datagram_size,
+            probe_response: ProbeResponse::default(),
+            fallbacks: Vec::new(),
+            method: None,
+            password: None,
+            accounts: vec![],
+            udp: false,
+            ip: None,
+            target_address: None,
+            target_port: None,
+            network: InboundNetwork::Tcp,
+        });
+
+        let (client, server) = tokio::io::duplex(1 << 16);
+        let session = tokio::spawn(serve_vless(
+            Box::new(server),
+            codec,
+            ConnectionManager::new(),
+            std::sync::Arc::new(router),
+            "127.0.0.1:40000".parse().unwrap(),
+            settings,
+            SockOpt::default(),
+        ));
+        (client, session)
+    }
+
+    /// 在内存管道上启动一个 VLESS 会话 (直连出站)
+    fn spawn_session(
+        uuid: uuid::Uuid,
+        max_udp_datagram_size: usize,
+    ) -> (tokio::io::DuplexStream, tokio::task::JoinHandle<Result<()>>) {
+        spawn_session_with(uuid, direct_router(), SniffingConfig::default(), max_udp_datagram_size)
+    }
+
+    #[tokio::test]
+    async fn test_dialer_receives_sniffed_target() {
+        use crate::outbound::MockDialer;
+        use crate::protocol::vless::{Addons, VlessRequest};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let remote = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mock = std::sync::Arc::new(MockDialer::new(remote.local_addr().unwrap()));
+        let router = direct_router().with_dialer("direct", mock.clone());
+
+        let uuid = uuid::Uuid::new_v4();
+        let (mut client, _session) = spawn_session_with(uuid, router, sniffing(NoSniAction::Proceed, None), 8192);
+        let request = VlessRequest {
+            version: 0,
+            uuid,
+            command: Command::Tcp,
+            address: Address::Ipv4(std::net::Ipv4Addr::new(192, 0, 2, 1), 8443),
+            addon_length: 0,
+            addons: Addons::default(),
+        };
+        let hello = record(&client_hello_with(Some("example.com"), 64));
+        let mut data = request.encode().unwrap().to_vec();
+        data.extend_from_slice(&hello);
+        client.write_all(&data).await.unwrap();
+
+        // 首包原样转发到出站，出站收到的目标是嗅探出的域名，端口保持不变
+        let (mut upstream, _) = remote.accept().await.unwrap();
+        let mut received = vec![0u8; hello.len()];
+        upstream.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, hello);
+        assert_eq!(mock.dialed(), vec![Address::Domain("example.com".to_string(), 8443)]);
+    }
+
+    #[tokio::test]
+    async fn test_route_only_keeps_connect_target() {
+        use crate::config::{Outbound, RoutingConfig};
+        use crate::outbound::MockDialer;
+        use crate::protocol::vless::{Addons, VlessRequest};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let remote = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mock = std::sync::Arc::new(MockDialer::new(remote.local_addr().unwrap()));
+        let outbounds = ["direct", "cdn"].map(|tag| Outbound {
+            protocol: "freedom".to_string(),
+            tag: tag.to_string(),
+            settings: None,
+            stream_settings: None,
+        });
+        let routing: RoutingConfig = serde_json::from_value(serde_json::json!({
+            "rules": [{ "type": "field", "domain": ["domain:example.com"], "outboundTag": "cdn" }]
+        }))
+        .unwrap();
+        let router = Router::new(&routing, &outbounds).unwrap().with_dialer("cdn", mock.clone());
+
+        let uuid = uuid::Uuid::new_v4();
+        let mut config = sniffing(NoSniAction::Proceed, None);
+        config.route_only = true;
+        let (mut client, _session) = spawn_session_with(uuid, router, config, 8192);
+        let target = Address::Ipv4(std::net::Ipv4Addr::new(192, 0, 2, 1), 443);
+        let request = VlessRequest {
+            version: 0,
+            uuid,
+            command: Command::Tcp,
+            address: target.clone(),
+            addon_length: 0,
+            addons: Addons::default(),
+        };
+        let hello = record(&client_hello_with(Some("www.example.com"), 64));
+        let mut data = request.encode().unwrap().to_vec();
+        data.extend_from_slice(&hello);
+        client.write_all(&data).await.unwrap();
+
+        // 按嗅探出的域名路由到 cdn 出站，但连接的仍是客户端请求的地址
+        let (mut upstream, _) = remote.accept().await.unwrap();
+        let mut received = vec![0u8; hello.len()];
+        upstream.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, hello);
+        assert_eq!(mock.dialed(), vec![target]);
+    }
+
+    #[tokio::test]
+    async fn test_excluded_domains_keep_target() {
+        use crate::outbound::MockDialer;
+        use crate::protocol::vless::{Addons, VlessRequest};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let remote = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mock = std::sync::Arc::new(MockDialer::new(remote.local_addr().unwrap()));
+        let mut config = sniffing(NoSniAction::Proceed, None);
+        config.domains_excluded = vec!["domain:push.apple.com".to_string()];
+        let target = Address::Ipv4(std::net::Ipv4Addr::new(17, 0, 0, 1), 5223);
+
+        for sni in ["courier.push.apple.com", "www.apple.com"] {
+            let uuid = uuid::Uuid::new_v4();
+            let (mut client, _session) = spawn_session_with(uuid, direct_router().with_dialer("direct", mock.clone()), config.clone(), 8192);
+            let request = VlessRequest {
+                version: 0,
+                uuid,
+                command: Command::Tcp,
+                address: target.clone(),
+                addon_length: 0,
+                addons: Addons::default(),
             };
-            
-            tokio::select! {
-                _ = send_task => {}
-                _ = recv_task => {}
+            let hello = record(&client_hello_with(Some(sni), 64));
+            let mut data = request.encode().unwrap().to_vec();
+            data.extend_from_slice(&hello);
+            client.write_all(&data).await.unwrap();
+            let (mut upstream, _) = remote.accept().await.unwrap();
+            let mut received = vec![0u8; hello.len()];
+            upstream.read_exact(&mut received).await.unwrap();
+        }
+
+        // 排除的域名保持原目标，兄弟域名仍被覆盖
+        assert_eq!(
+            mock.dialed(),
+            vec![target, Address::Domain("www.apple.com".to_string(), 5223)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_vmess_session_relays_to_dialer() {
+        use crate::outbound::MockDialer;
+        use crate::protocol::vmess::{self, option, ChunkDecoder, ChunkEncoder, Security, VmessRequest};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let remote = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mock = std::sync::Arc::new(MockDialer::new(remote.local_addr().unwrap()));
+        let router = direct_router().with_dialer("direct", mock.clone());
+
+        let uuid = uuid::Uuid::new_v4();
+        let settings = std::sync::Arc::new(InboundSettings {
+            clients: vec![],
+            decryption: "none".to_string(),
+            sniffing: SniffingConfig::default(),
+            max_udp_datagram_size: 8192,
+            probe_response: ProbeResponse::default(),
+            fallbacks: Vec::new(),
+            method: None,
+            password: None,
+            accounts: vec![],
+            udp: false,
+            ip: None,
+            target_address: None,
+            target_port: None,
+            network: InboundNetwork::Tcp,
+        });
+        let (mut client, server) = tokio::io::duplex(1 << 16);
+        let _session = tokio::spawn(serve_vmess(
+            Box::new(server),
+            VmessCodec::new(vec![uuid]),
+            ConnectionManager::new(),
+            std::sync::Arc::new(router),
+            "127.0.0.1:40000".parse().unwrap(),
+            settings,
+            SockOpt::default(),
+        ));
+
+        let request = VmessRequest {
+            body_iv: rand::random(),
+            body_key: rand::random(),
+            response_header: 0x5a,
+            option: option::CHUNK_STREAM | option::CHUNK_MASKING | option::GLOBAL_PADDING,
+            security: Security::Aes128Gcm,
+            command: vmess::Command::Tcp,
+            address: Some(Address::Domain("example.com".to_string(), 80)),
+        };
+        let cmd_key = vmess::cmd_key(&uuid);
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let auth_id = vmess::create_auth_id(&cmd_key, now, rand::random());
+        let mut data = vmess::seal_request(&cmd_key, &auth_id, &rand::random(), &request.encode(&[]).unwrap());
+        ChunkEncoder::new(&request, &request.body_key, &request.body_iv).encode(b"ping", &mut data);
+        client.write_all(&data).await.unwrap();
+
+        let (mut upstream, _) = remote.accept().await.unwrap();
+        let mut received = [0u8; 4];
+        upstream.read_exact(&mut received).await.unwrap();
+        assert_eq!(&received, b"ping");
+        assert_eq!(mock.dialed(), vec![Address::Domain("example.com".to_string(), 80)]);
+
+        // 响应头之后是响应方向的数据块
+        upstream.write_all(b"pong").await.unwrap();
+        let mut header = vec![0u8; vmess::encode_response_header(&request).len()];
+        client.read_exact(&mut header).await.unwrap();
+        assert_eq!(header, vmess::encode_response_header(&request));
+
+        let (key, iv) = vmess::response_keys(&request);
+        let mut decoder = ChunkDecoder::new(&request, &key, &iv);
+        let mut incoming = bytes::BytesMut::new();
+        let mut plaintext = bytes::BytesMut::new();
+        while plaintext.len() < 4 {
+            let mut buf = [0u8; 256];
+            let n = client.read(&mut buf).await.unwrap();
+            assert!(n > 0);
+            incoming.extend_from_slice(&buf[..n]);
+            decoder.decode(&mut incoming, &mut plaintext).unwrap();
+        }
+        assert_eq!(&plaintext[..], b"pong");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_vmess_request_is_silent() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let settings = std::sync::Arc::new(InboundSettings {
+            clients: vec![],
+            decryption: "none".to_string(),
+            sniffing: SniffingConfig::default(),
+            max_udp_datagram_size: 8192,
+            probe_response: ProbeResponse::default(),
+            fallbacks: Vec::new(),
+            method: None,
+            password: None,
+            accounts: vec![],
+            udp: false,
+            ip: None,
+            target_address: None,
+            target_port: None,
+            network: InboundNetwork::Tcp,
+        });
+        let (mut client, server) = tokio::io::duplex(1 << 16);
+        let session = tokio::spawn(serve_vmess(
+            Box::new(server),
+            VmessCodec::new(vec![uuid::Uuid::new_v4()]),
+            ConnectionManager::new(),
+            std::sync::Arc::new(direct_router()),
+            "127.0.0.1:40000".parse().unwrap(),
+            settings,
+            SockOpt::default(),
+        ));
+
+        // 认证 ID 无效时服务端继续读取，而不是在第 16 字节后断开
+        client.write_all(&[0x42; 16]).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!session.is_finished());
+        client.write_all(&[0x42; 64]).await.unwrap();
+
+        client.shutdown().await.unwrap();
+        session.await.unwrap().unwrap();
+        let mut reply = Vec::new();
+        client.read_to_end(&mut reply).await.unwrap();
+        assert!(reply.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_shadowsocks_request_is_silent() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let settings = std::sync::Arc::new(InboundSettings {
+            clients: vec![],
+            decryption: "none".to_string(),
+            sniffing: SniffingConfig::default(),
+            max_udp_datagram_size: 8192,
+            probe_response: ProbeResponse::default(),
+            fallbacks: Vec::new(),
+            method: None,
+            password: None,
+            accounts: vec![],
+            udp: false,
+            ip: None,
+            target_address: None,
+            target_port: None,
+            network: InboundNetwork::Tcp,
+        });
+        let (mut client, server) = tokio::io::duplex(1 << 16);
+        let session = tokio::spawn(serve_shadowsocks(
+            Box::new(server),
+            ShadowsocksCodec::new("aes-128-gcm", "password").unwrap(),
+            ConnectionManager::new(),
+            std::sync::Arc::new(direct_router()),
+            "127.0.0.1:40000".parse().unwrap(),
+            settings,
+            SockOpt::default(),
+        ));
+
+        // 认证失败后服务端继续读取而不是立即断开
+        client.write_all(&[0x42; 64]).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!session.is_finished());
+        client.write_all(&[0x42; 64]).await.unwrap();
+
+        // 客户端关闭后会话安静结束，没有任何回复
+        client.shutdown().await.unwrap();
+        session.await.unwrap().unwrap();
+        let mut reply = Vec::new();
+        client.read_to_end(&mut reply).await.unwrap();
+        assert!(reply.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_complete_client_hello_skips_sniff_wait() {
+        let hello = record(&client_hello_with(Some("example.com"), 64));
+        let mut config = sniffing(NoSniAction::Proceed, None);
+        config.first_packet_wait = 60_000;
+        config.fragment_wait = 60_000;
+
+        // 客户端一端保持打开但不再发送数据，任何等待都会超时
+        let (_client, mut server) = tokio::io::duplex(1024);
+        let mut data = hello.clone();
+        tokio::time::timeout(std::time::Duration::from_secs(1), read_sniff_data(&mut server, &mut data, &config))
+            .await
+            .expect("完整的 ClientHello 不应等待");
+        assert_eq!(data, hello);
+
+        // 首包只有半个 ClientHello 时按 fragmentWait 等待后续记录
+        config.fragment_wait = 50;
+        let mut partial = hello[..hello.len() / 2].to_vec();
+        let started = tokio::time::Instant::now();
+        read_sniff_data(&mut server, &mut partial, &config).await;
+        assert!(started.elapsed() >= std::time::Duration::from_millis(50));
+        assert_eq!(partial, &hello[..hello.len() / 2]);
+    }
+
+    #[tokio::test]
+    async fn test_udp_dialer_receives_request_target() {
+        use crate::outbound::MockDialer;
+        use crate::protocol::vless::{Addons, VlessRequest};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let echo = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mock = std::sync::Arc::new(MockDialer::new(echo.local_addr().unwrap()));
+        let router = direct_router().with_dialer("direct", mock.clone());
+
+        let uuid = uuid::Uuid::new_v4();
+        let (mut client, _session) = spawn_session_with(uuid, router, SniffingConfig::default(), 8192);
+        let target = Address::Domain("dns.example".to_string(), 53);
+        let request = VlessRequest {
+            version: 0,
+            uuid,
+            command: Command::Udp,
+            address: target.clone(),
+            addon_length: 0,
+            addons: Addons::default(),
+        };
+        let mut data = request.encode().unwrap().to_vec();
+        data.extend_from_slice(b"\x00\x04ping");
+        client.write_all(&data).await.unwrap();
+        let mut header = [0u8; 2];
+        client.read_exact(&mut header).await.unwrap();
+
+        let mut buf = [0u8; 16];
+        let (n, _) = echo.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"ping");
+        assert_eq!(mock.dialed(), vec![target]);
+    }
+
+    /// 启动 UDP 回显目标与一个 VLESS 会话，返回客户端一端、会话任务与编码后的 UDP 请求头
+    async fn start_udp_session(
+        max_udp_datagram_size: usize,
+    ) -> (tokio::io::DuplexStream, tokio::task::JoinHandle<Result<()>>, Vec<u8>) {
+        use crate::protocol::vless::{Addons, VlessRequest};
+
+        // UDP 回显目标
+        let echo = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let echo_addr = echo.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 65536];
+            while let Ok((n, from)) = echo.recv_from(&mut buf).await {
+                let _ = echo.send_to(&buf[..n], from).await;
             }
-            info!("📡 UDP 会话结束");
+        });
+
+        let uuid = uuid::Uuid::new_v4();
+        let (client, session) = spawn_session(uuid, max_udp_datagram_size);
+        let request = VlessRequest {
+            version: 0,
+            uuid,
+            command: Command::Udp,
+            address: Address::from(echo_addr),
+            addon_length: 0,
+            addons: Addons::default(),
+        };
+        (client, session, request.encode().unwrap().to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_oversized_udp_datagram_is_dropped() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (mut client, session, request) = start_udp_session(512).await;
+        client.write_all(&request).await.unwrap();
+        let mut header = [0u8; 2];
+        client.read_exact(&mut header).await.unwrap();
+
+        // 超长数据报被丢弃，随后的正常数据报照常转发
+        let oversized = vec![0xAA; 1000];
+        client.write_all(&(oversized.len() as u16).to_be_bytes()).await.unwrap();
+        client.write_all(&oversized).await.unwrap();
+        client.write_all(&5u16.to_be_bytes()).await.unwrap();
+        client.write_all(b"hello").await.unwrap();
+
+        let mut frame = [0u8; 7];
+        tokio::time::timeout(std::time::Duration::from_secs(5), client.read_exact(&mut frame))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&frame, b"\x00\x05hello");
+        assert!(!session.is_finished());
+    }
+
+    #[tokio::test]
+    async fn test_packet_addr_udp_destinations() {
+        use crate::protocol::vless::{Addons, VlessRequest};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // 两个 UDP 回显目标，回包前加上各自的标记
+        let mut targets = Vec::new();
+        for tag in [b'a', b'b'] {
+            let echo = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            targets.push(Address::from(echo.local_addr().unwrap()));
+            tokio::spawn(async move {
+                let mut buf = vec![0u8; 2048];
+                while let Ok((n, from)) = echo.recv_from(&mut buf).await {
+                    let reply = [&[tag][..], &buf[..n]].concat();
+                    let _ = echo.send_to(&reply, from).await;
+                }
+            });
         }
-        Command::Mux => {
-            use tracing::warn;
-            warn!("Mux 暂不支持");
+
+        let uuid = uuid::Uuid::new_v4();
+        let (mut client, _session) = spawn_session(uuid, 8192);
+        let request = VlessRequest {
+            version: 0,
+            uuid,
+            command: Command::Udp,
+            address: Address::Domain(packet_addr::PACKET_ADDR_DOMAIN.to_string(), 0),
+            addon_length: 0,
+            addons: Addons::default(),
+        };
+        let frame = |target: &Address, data: &[u8]| {
+            let mut payload = Vec::new();
+            packet_addr::encode(target, data, &mut payload).unwrap();
+            [&(payload.len() as u16).to_be_bytes()[..], &payload].concat()
+        };
+        // 第一个数据报随请求头发出，之后的数据报交替发往两个目标
+        let mut data = request.encode().unwrap().to_vec();
+        data.extend_from_slice(&frame(&targets[0], b"one"));
+        client.write_all(&data).await.unwrap();
+        let mut header = [0u8; 2];
+        client.read_exact(&mut header).await.unwrap();
+
+        for (index, (target, tag)) in [(0, b'a'), (1, b'b'), (0, b'a')].into_iter().enumerate() {
+            if index > 0 {
+                client.write_all(&frame(&targets[target], b"more")).await.unwrap();
+            }
+            let len = tokio::time::timeout(std::time::Duration::from_secs(5), client.read_u16()).await.unwrap().unwrap();
+            let mut payload = vec![0u8; len as usize];
+            client.read_exact(&mut payload).await.unwrap();
+            // 回包附带来源地址
+            let (source, data) = packet_addr::decode(&payload).unwrap();
+            assert_eq!(source, targets[target]);
+            assert_eq!(data[0], tag);
         }
     }
 
-    Ok(())
+    #[tokio::test]
+    async fn test_udp_frame_reader() {
+        let data: &[u8] = b"\x00\x03one\x00\x08oversize\x00\x03two\x00\x05thr";
+        let mut frames = UdpFrameReader::new(data, 5);
+        assert_eq!(frames.next().await.unwrap(), Some(&b"one"[..]));
+        // 超长的数据报被跳过
+        assert_eq!(frames.next().await.unwrap(), Some(&b"two"[..]));
+        // 不完整的最后一帧
+        assert!(frames.next().await.is_err());
+
+        let mut frames = UdpFrameReader::new(&b"\x00"[..], 5);
+        assert_eq!(frames.next().await.unwrap(), None);
+        let mut frames = UdpFrameReader::new(&b"\x00\x00\x00\x01x"[..], 5);
+        assert_eq!(frames.next().await.unwrap(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_udp_destinations_expire_independently() {
+        let destinations = UdpDestinations::new(std::time::Duration::from_secs(300));
+        let first = Address::Ipv4(std::net::Ipv4Addr::new(1, 1, 1, 1), 53);
+        let second = Address::Ipv4(std::net::Ipv4Addr::new(8, 8, 8, 8), 53);
+        destinations.touch(&first, true);
+        tokio::time::advance(std::time::Duration::from_secs(200)).await;
+        destinations.touch(&second, true);
+        // 未知来源的回包不会新增目标
+        destinations.touch(&Address::Ipv4(std::net::Ipv4Addr::new(9, 9, 9, 9), 53), false);
+
+        tokio::time::advance(std::time::Duration::from_secs(150)).await;
+        let next = destinations.evict_idle().unwrap();
+        assert_eq!(destinations.last_activity.lock().unwrap().len(), 1);
+        assert!(destinations.last_activity.lock().unwrap().contains_key(&second));
+
+        // 回包刷新已有目标
+        destinations.touch(&second, false);
+        assert!(destinations.evict_idle().unwrap() > next);
+        tokio::time::advance(std::time::Duration::from_secs(301)).await;
+        assert_eq!(destinations.evict_idle(), None);
+    }
+
+    #[tokio::test]
+    async fn test_coalesced_initial_udp_frames() {
+        use std::collections::HashSet;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (mut client, _session, mut request) = start_udp_session(8192).await;
+        // 请求头后紧跟两个完整帧以及第三帧的前半部分，一次写出
+        request.extend_from_slice(b"\x00\x05first\x00\x06second\x00\x05thi");
+        client.write_all(&request).await.unwrap();
+        let mut header = [0u8; 2];
+        client.read_exact(&mut header).await.unwrap();
+        client.write_all(b"rd").await.unwrap();
+
+        let mut echoed = HashSet::new();
+        for _ in 0..3 {
+            let mut len = [0u8; 2];
+            let mut payload = vec![0u8; 0];
+            tokio::time::timeout(std::time::Duration::from_secs(5), async {
+                client.read_exact(&mut len).await.unwrap();
+                payload.resize(u16::from_be_bytes(len) as usize, 0);
+                client.read_exact(&mut payload).await.unwrap();
+            })
+            .await
+            .unwrap();
+            echoed.insert(payload);
+        }
+        let expected: HashSet<Vec<u8>> =
+            [b"first".to_vec(), b"second".to_vec(), b"third".to_vec()].into_iter().collect();
+        assert_eq!(echoed, expected);
+    }
+
+    /// 把日志写入共享缓冲区，供断言使用
+    #[derive(Clone, Default)]
+    struct CapturedLogs(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_continuity_in_logs() {
+        use crate::protocol::vless::{Addons, VlessRequest};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let uuid = uuid::Uuid::new_v4();
+        let target = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target_addr = target.local_addr().unwrap();
+        // 目标接受后立即关闭，让每次转发都能结束
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = target.accept().await {
+                drop(stream);
+            }
+        });
+
+        // 同一令牌连接两次，再用另一个令牌和无令牌各连接一次
+        for token in [Some("phone"), Some("phone"), Some("laptop"), None] {
+            let (mut client, session) = spawn_session(uuid, 8192);
+            let request = VlessRequest {
+                version: 0,
+                uuid,
+                command: Command::Tcp,
+                address: Address::from(target_addr),
+                addon_length: 0,
+                addons: Addons {
+                    continuity: token.map(str::to_string),
+                    ..Default::default()
+                },
+            };
+            client.write_all(&request.encode().unwrap()).await.unwrap();
+            let mut header = [0u8; 2];
+            client.read_exact(&mut header).await.unwrap();
+            drop(client);
+            let _ = session.await;
+        }
+
+        let output = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let ids: Vec<(String, String)> = output
+            .lines()
+            .filter(|line| line.contains("VLESS 请求"))
+            .map(|line| {
+                let session = line.split("会话: ").nth(1).unwrap().split(',').next().unwrap();
+                let conn = line.split("连接: ").nth(1).unwrap().trim_end_matches(')');
+                (session.to_string(), conn.to_string())
+            })
+            .collect();
+        assert_eq!(ids.len(), 4, "{}", output);
+
+        // 相同令牌归到同一会话，但连接 id 各不相同
+        assert_eq!(ids[0].0, ids[1].0);
+        assert_ne!(ids[0].1, ids[1].1);
+        assert_ne!(ids[0].0, ids[2].0);
+        // 无令牌时会话 id 即连接 id
+        assert_eq!(ids[3].0, ids[3].1);
+        // 日志中不出现令牌原文
+        assert!(!output.contains("phone"));
+    }
+
+    #[tokio::test]
+    async fn test_probe_produces_unauthenticated_attempt_log() {
+        use crate::network::{ConnectionAttempt, CountingStream};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let settings = std::sync::Arc::new(InboundSettings {
+            clients: vec![],
+            decryption: "none".to_string(),
+            sniffing: SniffingConfig::default(),
+            max_udp_datagram_size: 8192,
+            probe_response: ProbeResponse::default(),
+            fallbacks: Vec::new(),
+            method: None,
+            password: None,
+            accounts: vec![],
+            udp: false,
+            ip: None,
+            target_address: None,
+            target_port: None,
+            network: InboundNetwork::Tcp,
+        });
+        let source: std::net::SocketAddr = "198.51.100.9:51234".parse().unwrap();
+        let attempt = ConnectionAttempt::new(source, None);
+        let (mut client, server) = tokio::io::duplex(1 << 16);
+        let probe = b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        client.write_all(probe).await.unwrap();
+
+        attempt
+            .clone()
+            .scope(serve_vless(
+                Box::new(CountingStream::new(server, attempt.clone())),
+                VlessCodec::new(vec![uuid::Uuid::new_v4()]),
+                ConnectionManager::new(),
+                std::sync::Arc::new(direct_router()),
+                source,
+                settings,
+                SockOpt::default(),
+            ))
+            .await
+            .unwrap();
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        assert!(response.starts_with(b"HTTP/1.1 "));
+        drop(attempt);
+
+        let output = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let entries: Vec<&str> = output.lines().filter(|line| line.contains("连接尝试")).collect();
+        assert_eq!(entries.len(), 1, "{}", output);
+        assert!(entries[0].contains("source=198.51.100.9:51234"));
+        assert!(entries[0].contains(&format!("bytes={}", probe.len())));
+        assert!(entries[0].contains("authenticated=false"));
+        assert!(entries[0].contains("outcome=probe"));
+    }
 }
@@ -1,6 +1,7 @@
 pub mod config;
 pub mod handler;
 pub mod network;
+pub mod outbound;
 pub mod protocol;
 pub mod server;
 pub mod transport;
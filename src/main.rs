@@ -5,13 +5,14 @@ use tracing_subscriber;
 
 mod config;
 mod network;
+mod outbound;
 mod protocol;
 mod server;
 mod transport;
 mod utils;
 mod handler;
 
-use crate::config::Config;
+use crate::config::{reality_clients, Config, StartupSummary};
 use crate::server::Server;
 
 #[derive(Parser, Debug)]
@@ -24,10 +25,17 @@ struct Args {
     /// 日志级别
     #[arg(short, long, default_value = "info")]
     log_level: String,
+
+    /// 打印各 Reality 入站的客户端参数与 vless:// 链接后退出
+    #[arg(long)]
+    show_links: bool,
+
+    /// 客户端连接使用的服务器地址 (配合 --show-links，默认使用监听地址)
+    #[arg(long, requires = "show_links")]
+    address: Option<String>,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
     let args = Args::parse();
 
     // 初始化日志
@@ -57,12 +65,59 @@ async fn main() -> Result<()> {
     let config = Config::load(&args.config)?;
     info!("✅ Configuration loaded successfully");
 
+    // 高频错误日志限流
+    utils::log_limit::configure(
+        config.log.error_log_burst,
+        std::time::Duration::from_secs(config.log.error_log_window),
+    );
+
+    // 运行时需要在加载配置后创建，以便按配置设置线程数与 CPU 绑定
+    let runtime = utils::affinity::build_runtime(&config.runtime)?;
+    runtime.block_on(run(args, config))
+}
+
+async fn run(args: Args, config: Config) -> Result<()> {
+    // 打印实际生效的入站配置
+    info!("📋 入站摘要:");
+    for line in StartupSummary::from_config(&config).to_string().lines() {
+        info!("    {}", line);
+    }
+
+    if args.show_links {
+        for client in reality_clients(&config, args.address.as_deref())? {
+            println!("{}\n", client);
+        }
+        return Ok(());
+    }
+
     // 创建并启动服务器
     let server = Server::new(config)?;
     info!("🌐 Server initialized");
 
-    // 运行服务器
-    server.run().await?;
+    // 运行服务器，收到 Ctrl-C / SIGTERM 后优雅关闭
+    server.run_until(shutdown_signal()).await?;
 
     Ok(())
 }
+
+/// 等待 Ctrl-C 或 SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(e) => tracing::warn!("无法监听 SIGTERM: {}", e),
+        }
+    }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        tracing::warn!("无法监听 Ctrl-C: {}", e);
+        std::future::pending::<()>().await;
+    }
+}
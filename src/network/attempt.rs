@@ -0,0 +1,293 @@
+//! 连接尝试日志
+//!
+//! 每个接受的 TCP 连接在认证之前就建立一条尝试记录: 来源地址、认证前收到的字节数、
+//! 是否通过认证以及结果 (认证成功、探测、回落、拒绝等)。结果确定时立即以 `access`
+//! 为 target 输出一条日志；连接在得出结论前就关闭的，在记录释放时输出 `closed`。
+//! 探测和失败的连接同样留下记录，可供 fail2ban 等入侵检测工具使用。
+//!
+//! 协议处理器通过 [`record`] 标记结果，记录通过任务局部变量传递，
+//! 不在 [`ConnectionAttempt::scope`] 作用域内 (例如直接调用处理器的单元测试) 时不产生效果。
+
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tracing::info;
+
+use super::metrics::HandshakeGuard;
+
+tokio::task_local! {
+    static CURRENT: Arc<ConnectionAttempt>;
+}
+
+/// 连接尝试的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttemptOutcome {
+    /// 通过入站协议认证
+    Authenticated,
+    /// 无需认证的入站 (dokodemo) 直接转发
+    Forwarded,
+    /// HTTP 探测，已返回伪装响应
+    Probe,
+    /// 验证未通过，回落到 Reality 的 dest 或 VLESS 的 fallbacks
+    Fallback,
+    /// 认证或协议解析失败
+    Rejected,
+    /// 连接数已满被拒绝
+    Overloaded,
+    /// 得出结论前连接已关闭
+    Closed,
+}
+
+impl AttemptOutcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AttemptOutcome::Authenticated => "authenticated",
+            AttemptOutcome::Forwarded => "forwarded",
+            AttemptOutcome::Probe => "probe",
+            AttemptOutcome::Fallback => "fallback",
+            AttemptOutcome::Rejected => "rejected",
+            AttemptOutcome::Overloaded => "overloaded",
+            AttemptOutcome::Closed => "closed",
+        }
+    }
+
+    /// 是否通过认证
+    pub fn is_authenticated(self) -> bool {
+        matches!(self, AttemptOutcome::Authenticated)
+    }
+}
+
+impl fmt::Display for AttemptOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 一个已接受连接的尝试记录，结果只记录一次
+#[derive(Debug)]
+pub struct ConnectionAttempt {
+    source: SocketAddr,
+    local: Option<SocketAddr>,
+    started: Instant,
+    /// 结果确定前从客户端收到的字节数
+    received: AtomicU64,
+    decided: AtomicBool,
+    /// 入站的握手计数，结果确定时释放
+    handshake: Mutex<Option<HandshakeGuard>>,
+}
+
+impl ConnectionAttempt {
+    /// `source` 为客户端地址 (已按 PROXY protocol 解析)，`local` 为入站的本地地址
+    pub fn new(source: SocketAddr, local: Option<SocketAddr>) -> Arc<Self> {
+        Arc::new(Self {
+            source,
+            local,
+            started: Instant::now(),
+            received: AtomicU64::new(0),
+            decided: AtomicBool::new(false),
+            handshake: Mutex::new(None),
+        })
+    }
+
+    /// 持有入站的握手计数直到结果确定
+    pub fn hold_handshake(&self, guard: HandshakeGuard) {
+        *self.handshake.lock().unwrap() = Some(guard);
+    }
+
+    /// 客户端地址
+    pub fn source(&self) -> SocketAddr {
+        self.source
+    }
+
+    /// 入站的本地地址
+    pub fn local(&self) -> Option<SocketAddr> {
+        self.local
+    }
+
+    /// 结果确定前收到的字节数
+    pub fn received(&self) -> u64 {
+        self.received.load(Ordering::Relaxed)
+    }
+
+    /// 结果是否已经确定
+    pub fn is_decided(&self) -> bool {
+        self.decided.load(Ordering::Relaxed)
+    }
+
+    /// 记录结果并输出日志，只有第一次调用生效
+    pub fn record(&self, outcome: AttemptOutcome, user: Option<&str>) {
+        if self.decided.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        self.handshake.lock().unwrap().take();
+        self.emit(outcome, user);
+    }
+
+    /// 在本记录的作用域内运行 `fut`，其中的 [`record`] 调用作用于本记录
+    pub async fn scope<F: Future>(self: Arc<Self>, fut: F) -> F::Output {
+        CURRENT.scope(self, fut).await
+    }
+
+    fn emit(&self, outcome: AttemptOutcome, user: Option<&str>) {
+        let local = self.local.map(|addr| addr.to_string());
+        info!(
+            target: "access",
+            source = %self.source,
+            local = %local.as_deref().unwrap_or("-"),
+            bytes = self.received(),
+            authenticated = outcome.is_authenticated(),
+            outcome = %outcome,
+            user = %user.unwrap_or("-"),
+            elapsed_ms = self.started.elapsed().as_millis() as u64,
+            "🔐 连接尝试"
+        );
+    }
+}
+
+impl Drop for ConnectionAttempt {
+    fn drop(&mut self) {
+        if !*self.decided.get_mut() {
+            self.emit(AttemptOutcome::Closed, None);
+        }
+    }
+}
+
+/// 为当前连接记录结果，不在尝试作用域内时忽略
+pub fn record(outcome: AttemptOutcome, user: Option<&str>) {
+    let _ = CURRENT.try_with(|attempt| attempt.record(outcome, user));
+}
+
+/// 当前连接的客户端地址，不在尝试作用域内时返回 `None`
+pub fn source_addr() -> Option<SocketAddr> {
+    CURRENT.try_with(|attempt| attempt.source()).ok()
+}
+
+/// 当前连接的入站本地地址，不在尝试作用域内时返回 `None`
+pub fn local_addr() -> Option<SocketAddr> {
+    CURRENT.try_with(|attempt| attempt.local()).ok().flatten()
+}
+
+/// 统计结果确定前从客户端读到的字节数
+pub struct CountingStream<S> {
+    inner: S,
+    attempt: Arc<ConnectionAttempt>,
+}
+
+impl<S> CountingStream<S> {
+    pub fn new(inner: S, attempt: Arc<ConnectionAttempt>) -> Self {
+        Self { inner, attempt }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for CountingStream<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if !self.attempt.is_decided() {
+            let n = (buf.filled().len() - before) as u64;
+            self.attempt.received.fetch_add(n, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for CountingStream<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_attempt_logged_once_with_pre_auth_bytes() {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let source: SocketAddr = "192.0.2.7:40000".parse().unwrap();
+        let attempt = ConnectionAttempt::new(source, None);
+        let (mut client, server) = tokio::io::duplex(64);
+        let mut stream = CountingStream::new(server, attempt.clone());
+
+        attempt
+            .clone()
+            .scope(async {
+                client.write_all(b"hello").await.unwrap();
+                let mut buf = [0u8; 5];
+                stream.read_exact(&mut buf).await.unwrap();
+                record(AttemptOutcome::Authenticated, Some("alice"));
+                // 结果确定后不再计数，也不会重复记录
+                client.write_all(b"more").await.unwrap();
+                stream.read_exact(&mut buf[..4]).await.unwrap();
+                record(AttemptOutcome::Rejected, None);
+            })
+            .await;
+        assert_eq!(attempt.received(), 5);
+        drop(stream);
+        drop(attempt);
+
+        // 作用域之外的调用被忽略；未确定结果的记录在释放时输出 closed
+        record(AttemptOutcome::Probe, None);
+        drop(ConnectionAttempt::new(source, None));
+
+        let output = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().filter(|line| line.contains("连接尝试")).collect();
+        assert_eq!(lines.len(), 2, "{}", output);
+        assert!(lines[0].contains("source=192.0.2.7:40000"));
+        assert!(lines[0].contains("bytes=5"));
+        assert!(lines[0].contains("authenticated=true"));
+        assert!(lines[0].contains("user=alice"));
+        assert!(lines[1].contains("authenticated=false"));
+        assert!(lines[1].contains("outcome=closed"));
+    }
+}
@@ -0,0 +1,260 @@
+//! 出站负载均衡
+//!
+//! 路由规则通过 `balancerTag` 指向负载均衡器，每个连接在候选出站中按策略选择一个。
+//! 连续连接失败达到 `maxFailures` 次的出站会在 `cooldown` 时间内被跳过；
+//! 所有候选都不健康时退回到全部候选，避免直接拒绝连接。
+
+use anyhow::{anyhow, Result};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::{BalancerConfig, BalancerStrategyType, Outbound};
+
+/// 单个出站的健康状态
+#[derive(Debug, Clone, Default)]
+struct Health {
+    consecutive_failures: u32,
+    unhealthy_until: Option<Instant>,
+}
+
+/// 负载均衡器
+#[derive(Debug)]
+pub struct Balancer {
+    tag: String,
+    candidates: Vec<String>,
+    strategy: BalancerStrategyType,
+    max_failures: u32,
+    cooldown: Duration,
+    next: AtomicUsize,
+    rng: Mutex<StdRng>,
+    health: Mutex<Vec<Health>>,
+}
+
+impl Balancer {
+    /// 根据配置创建，selector 按出站配置顺序展开为候选列表
+    pub fn new(config: &BalancerConfig, outbounds: &[Outbound]) -> Result<Self> {
+        let candidates: Vec<String> = outbounds
+            .iter()
+            .filter(|o| config.selector.iter().any(|prefix| o.tag.starts_with(prefix.as_str())))
+            .map(|o| o.tag.clone())
+            .collect();
+        if candidates.is_empty() {
+            return Err(anyhow!("负载均衡器 {} 的 selector 没有匹配任何出站", config.tag));
+        }
+
+        Ok(Self {
+            tag: config.tag.clone(),
+            health: Mutex::new(vec![Health::default(); candidates.len()]),
+            candidates,
+            strategy: config.strategy.strategy_type,
+            max_failures: config.max_failures.max(1),
+            cooldown: Duration::from_secs(config.cooldown),
+            next: AtomicUsize::new(0),
+            rng: Mutex::new(StdRng::from_entropy()),
+        })
+    }
+
+    /// 使用固定种子的随机数生成器 (便于测试复现)
+    pub fn with_seed(self, seed: u64) -> Self {
+        *self.rng.lock().unwrap() = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// 候选出站标签
+    pub fn candidates(&self) -> &[String] {
+        &self.candidates
+    }
+
+    /// 为新连接选择出站，`active` 返回某个出站当前的活跃连接数
+    pub fn select(&self, active: impl Fn(&str) -> usize) -> &str {
+        self.select_at(Instant::now(), active)
+    }
+
+    fn select_at(&self, now: Instant, active: impl Fn(&str) -> usize) -> &str {
+        let healthy: Vec<usize> = {
+            let health = self.health.lock().unwrap();
+            (0..self.candidates.len())
+                .filter(|&i| health[i].unhealthy_until.is_none_or(|until| now >= until))
+                .collect()
+        };
+        let pool: Vec<usize> = if healthy.is_empty() {
+            (0..self.candidates.len()).collect()
+        } else {
+            healthy
+        };
+
+        let idx = match self.strategy {
+            BalancerStrategyType::RoundRobin => {
+                let n = self.next.fetch_add(1, Ordering::Relaxed);
+                pool[n % pool.len()]
+            }
+            BalancerStrategyType::Random => {
+                let n = self.rng.lock().unwrap().gen_range(0..pool.len());
+                pool[n]
+            }
+            BalancerStrategyType::LeastConn => *pool
+                .iter()
+                .min_by_key(|&&i| active(&self.candidates[i]))
+                .expect("候选列表不为空"),
+        };
+
+        &self.candidates[idx]
+    }
+
+    /// 记录一次连接结果，返回该出站是否属于本负载均衡器
+    pub fn report(&self, outbound_tag: &str, success: bool) -> bool {
+        self.report_at(Instant::now(), outbound_tag, success)
+    }
+
+    fn report_at(&self, now: Instant, outbound_tag: &str, success: bool) -> bool {
+        let Some(idx) = self.candidates.iter().position(|t| t == outbound_tag) else {
+            return false;
+        };
+
+        let mut health = self.health.lock().unwrap();
+        let entry = &mut health[idx];
+        if success {
+            *entry = Health::default();
+        } else {
+            entry.consecutive_failures += 1;
+            if entry.consecutive_failures >= self.max_failures {
+                tracing::warn!(
+                    "⚠️ 负载均衡器 {}: 出站 {} 连续失败 {} 次，冷却 {}s",
+                    self.tag,
+                    outbound_tag,
+                    entry.consecutive_failures,
+                    self.cooldown.as_secs()
+                );
+                entry.unhealthy_until = Some(now + self.cooldown);
+                entry.consecutive_failures = 0;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BalancerStrategy;
+    use std::collections::HashMap;
+
+    fn outbounds() -> Vec<Outbound> {
+        ["relay-a", "relay-b", "relay-c", "direct"]
+            .iter()
+            .map(|tag| Outbound {
+                protocol: "freedom".to_string(),
+                tag: tag.to_string(),
+                settings: None,
+                stream_settings: None,
+            })
+            .collect()
+    }
+
+    fn balancer(strategy: BalancerStrategyType) -> Balancer {
+        let config = BalancerConfig {
+            tag: "relays".to_string(),
+            selector: vec!["relay-".to_string()],
+            strategy: BalancerStrategy { strategy_type: strategy },
+            max_failures: 2,
+            cooldown: 30,
+        };
+        Balancer::new(&config, &outbounds()).unwrap().with_seed(42)
+    }
+
+    #[test]
+    fn test_selector_prefix() {
+        let b = balancer(BalancerStrategyType::RoundRobin);
+        assert_eq!(b.candidates(), &["relay-a", "relay-b", "relay-c"]);
+
+        let config = BalancerConfig {
+            tag: "none".to_string(),
+            selector: vec!["missing-".to_string()],
+            strategy: BalancerStrategy::default(),
+            max_failures: 3,
+            cooldown: 30,
+        };
+        assert!(Balancer::new(&config, &outbounds()).is_err());
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let b = balancer(BalancerStrategyType::RoundRobin);
+        let picks: Vec<&str> = (0..6).map(|_| b.select(|_| 0)).collect();
+        assert_eq!(picks, ["relay-a", "relay-b", "relay-c", "relay-a", "relay-b", "relay-c"]);
+    }
+
+    #[test]
+    fn test_random_is_reproducible_with_seed() {
+        let first: Vec<String> = {
+            let b = balancer(BalancerStrategyType::Random);
+            (0..20).map(|_| b.select(|_| 0).to_string()).collect()
+        };
+        let second: Vec<String> = {
+            let b = balancer(BalancerStrategyType::Random);
+            (0..20).map(|_| b.select(|_| 0).to_string()).collect()
+        };
+        assert_eq!(first, second);
+
+        // 所有候选都会被选到
+        for tag in ["relay-a", "relay-b", "relay-c"] {
+            assert!(first.iter().any(|t| t == tag));
+        }
+    }
+
+    #[test]
+    fn test_least_connections() {
+        let b = balancer(BalancerStrategyType::LeastConn);
+        let load: HashMap<&str, usize> = [("relay-a", 5), ("relay-b", 1), ("relay-c", 3)].into();
+        assert_eq!(b.select(|tag| load[tag]), "relay-b");
+
+        let load: HashMap<&str, usize> = [("relay-a", 0), ("relay-b", 1), ("relay-c", 0)].into();
+        assert_eq!(b.select(|tag| load[tag]), "relay-a");
+    }
+
+    #[test]
+    fn test_unhealthy_outbound_is_skipped_during_cooldown() {
+        let b = balancer(BalancerStrategyType::LeastConn);
+        let now = Instant::now();
+
+        // 一次失败不足以标记为不健康
+        assert!(b.report_at(now, "relay-a", false));
+        assert_eq!(b.select_at(now, |_| 0), "relay-a");
+
+        assert!(b.report_at(now, "relay-a", false));
+        assert_eq!(b.select_at(now, |_| 0), "relay-b");
+        assert_eq!(b.select_at(now + Duration::from_secs(29), |_| 0), "relay-b");
+
+        // 冷却结束后恢复
+        assert_eq!(b.select_at(now + Duration::from_secs(30), |_| 0), "relay-a");
+
+        // 不属于该均衡器的出站不受影响
+        assert!(!b.report_at(now, "direct", false));
+    }
+
+    #[test]
+    fn test_success_resets_failures() {
+        let b = balancer(BalancerStrategyType::LeastConn);
+        let now = Instant::now();
+
+        b.report_at(now, "relay-a", false);
+        b.report_at(now, "relay-a", true);
+        b.report_at(now, "relay-a", false);
+        assert_eq!(b.select_at(now, |_| 0), "relay-a");
+    }
+
+    #[test]
+    fn test_all_unhealthy_falls_back_to_all() {
+        let b = balancer(BalancerStrategyType::RoundRobin);
+        let now = Instant::now();
+        for tag in ["relay-a", "relay-b", "relay-c"] {
+            b.report_at(now, tag, false);
+            b.report_at(now, tag, false);
+        }
+        let picks: Vec<&str> = (0..3).map(|_| b.select_at(now, |_| 0)).collect();
+        assert_eq!(picks, ["relay-a", "relay-b", "relay-c"]);
+    }
+}
@@ -1,16 +1,155 @@
 use anyhow::Result;
-use tokio::net::TcpStream;
-use tokio::io::{AsyncRead, AsyncWrite};
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::task::JoinHandle;
 use tracing::{debug, error, info};
 
+use super::metrics::Metrics;
+use super::relay::{self, RelayOptions};
+use super::tee::{TeeSinks, TeeStream};
+
+/// 下一个连接编号
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// 分配进程内唯一的连接编号，用于在日志与流量镜像中关联同一条连接
+pub fn next_connection_id() -> u64 {
+    NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// 连接的身份，写入吞吐采样日志，以便与连接的其他日志对应
+#[derive(Debug, Clone)]
+pub struct ConnectionLabel {
+    /// 连接编号 (见 [`next_connection_id`])
+    pub id: u64,
+    /// 客户端地址
+    pub client: SocketAddr,
+    /// 转发目标
+    pub target: String,
+}
+
+/// 吞吐采样配置
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThroughputSampling {
+    /// 采样间隔
+    pub interval: Duration,
+    /// 连接持续超过该时长后才开始输出采样
+    pub min_duration: Duration,
+}
+
+impl ThroughputSampling {
+    /// 从日志配置创建 (间隔为 0 表示关闭)
+    pub fn from_config(config: &crate::config::LogConfig) -> Option<Self> {
+        if config.throughput_sample_interval == 0 {
+            return None;
+        }
+        Some(Self {
+            interval: Duration::from_secs(config.throughput_sample_interval),
+            min_duration: Duration::from_secs(config.throughput_sample_min_duration),
+        })
+    }
+}
+
+/// 统计读写字节数的流包装
+struct CountingStream<S> {
+    inner: S,
+    read: Arc<AtomicU64>,
+    written: Arc<AtomicU64>,
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for CountingStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            let n = buf.filled().len() - before;
+            self.read.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for CountingStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            self.written.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// 周期性输出吞吐采样，永不返回 (由调用方取消)
+async fn sample_throughput(
+    sampling: ThroughputSampling,
+    label: Option<&ConnectionLabel>,
+    uploaded: Arc<AtomicU64>,
+    downloaded: Arc<AtomicU64>,
+) {
+    let start = tokio::time::Instant::now();
+    let mut ticker = tokio::time::interval_at(start + sampling.interval, sampling.interval);
+    let mut last_up = 0u64;
+    let mut last_down = 0u64;
+    let mut last_tick = start;
+
+    loop {
+        let now = ticker.tick().await;
+        let up = uploaded.load(Ordering::Relaxed);
+        let down = downloaded.load(Ordering::Relaxed);
+        let secs = now.duration_since(last_tick).as_secs_f64().max(f64::EPSILON);
+
+        if now.duration_since(start) >= sampling.min_duration {
+            info!(
+                conn_id = label.map(|l| l.id),
+                client = label.map(|l| tracing::field::display(l.client)),
+                target = label.map(|l| l.target.as_str()),
+                "📊 吞吐采样 [已持续 {}s]: 上行 {:.0} B/s, 下行 {:.0} B/s",
+                now.duration_since(start).as_secs(),
+                (up - last_up) as f64 / secs,
+                (down - last_down) as f64 / secs,
+            );
+        }
+
+        last_up = up;
+        last_down = down;
+        last_tick = now;
+    }
+}
+
 /// 代理连接
 pub struct ProxyConnection<C, R> {
     client_stream: C,
     remote_stream: R,
+    sampling: Option<ThroughputSampling>,
+    label: Option<ConnectionLabel>,
+    tee: Option<TeeSinks>,
+    relay: RelayOptions,
 }
 
-impl<C, R> ProxyConnection<C, R> 
-where 
+impl<C, R> ProxyConnection<C, R>
+where
     C: AsyncRead + AsyncWrite + Unpin + Send + 'static,
     R: AsyncRead + AsyncWrite + Unpin + Send + 'static
 {
@@ -19,16 +158,63 @@ where
         Self {
             client_stream,
             remote_stream,
+            sampling: None,
+            label: None,
+            tee: None,
+            relay: RelayOptions::default(),
         }
     }
 
+    /// 设置吞吐采样
+    pub fn with_throughput_sampling(mut self, sampling: Option<ThroughputSampling>) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// 设置连接的身份，吞吐采样日志中会带上它
+    pub fn with_label(mut self, label: ConnectionLabel) -> Self {
+        self.label = Some(label);
+        self
+    }
+
+    /// 设置流量镜像
+    pub fn with_tee(mut self, tee: Option<TeeSinks>) -> Self {
+        self.tee = tee;
+        self
+    }
+
+    /// 设置转发缓冲区与 flush 策略
+    pub fn with_relay_options(mut self, relay: RelayOptions) -> Self {
+        self.relay = relay;
+        self
+    }
+
     /// 双向数据转发
-    pub async fn relay(mut self) -> Result<()> {
+    pub async fn relay(self) -> Result<()> {
         debug!("开始双向数据转发");
 
-        // 使用 tokio 的 copy_bidirectional 进行高效的双向转发
-        match tokio::io::copy_bidirectional(&mut self.client_stream, &mut self.remote_stream).await
-        {
+        let uploaded = Arc::new(AtomicU64::new(0));
+        let downloaded = Arc::new(AtomicU64::new(0));
+        let mut client_stream = CountingStream {
+            inner: TeeStream::new(self.client_stream, self.tee),
+            read: uploaded.clone(),
+            written: downloaded.clone(),
+        };
+        let mut remote_stream = self.remote_stream;
+
+        // 按配置的缓冲区大小与 flush 策略双向转发
+        let copy = relay::copy_bidirectional(&mut client_stream, &mut remote_stream, self.relay);
+        let result = match self.sampling {
+            Some(sampling) => {
+                tokio::select! {
+                    result = copy => result,
+                    _ = sample_throughput(sampling, self.label.as_ref(), uploaded, downloaded) => unreachable!(),
+                }
+            }
+            None => copy.await,
+        };
+
+        match result {
             Ok((client_to_remote, remote_to_client)) => {
                 info!(
                     "连接关闭 - 上行: {} 字节, 下行: {} 字节",
@@ -45,10 +231,18 @@ where
 }
 
 /// 连接管理器
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct ConnectionManager {
     /// 活跃连接数
     active_connections: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    /// 按出站统计的活跃连接数 (供负载均衡使用)
+    outbound_connections: Arc<Mutex<HashMap<String, usize>>>,
+    /// 吞吐采样配置
+    sampling: Option<ThroughputSampling>,
+    /// 转发缓冲区与 flush 策略
+    relay: RelayOptions,
+    /// 按入站统计的并发指标
+    metrics: Arc<Metrics>,
 }
 
 impl ConnectionManager {
@@ -56,43 +250,100 @@ impl ConnectionManager {
     pub fn new() -> Self {
         Self {
             active_connections: std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0)),
+            outbound_connections: Arc::new(Mutex::new(HashMap::new())),
+            sampling: None,
+            relay: RelayOptions::default(),
+            metrics: Metrics::new(),
         }
     }
 
+    /// 为长连接启用周期性吞吐采样
+    pub fn with_throughput_sampling(mut self, sampling: Option<ThroughputSampling>) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// 设置转发缓冲区与 flush 策略
+    pub fn with_relay_options(mut self, relay: RelayOptions) -> Self {
+        self.relay = relay;
+        self
+    }
+
     /// 获取活跃连接数
     pub fn active_count(&self) -> usize {
         self.active_connections
             .load(std::sync::atomic::Ordering::Relaxed)
     }
 
-    /// 处理新连接
-    pub async fn handle_connection<T>(
+    /// 按入站统计的并发指标
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    /// 获取指定出站的活跃连接数
+    pub fn outbound_active_count(&self, outbound_tag: &str) -> usize {
+        self.outbound_connections
+            .lock()
+            .unwrap()
+            .get(outbound_tag)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// 处理新连接，转发在新任务中进行，返回该任务的句柄
+    pub async fn handle_connection<T, R>(
         &self,
         client_stream: T,
-        remote_stream: TcpStream,
-    ) -> Result<()> 
+        remote_stream: R,
+        outbound_tag: &str,
+        label: ConnectionLabel,
+        tee: Option<TeeSinks>,
+    ) -> Result<JoinHandle<()>>
     where
-        T: AsyncRead + AsyncWrite + Unpin + Send + 'static
+        T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        R: AsyncRead + AsyncWrite + Unpin + Send + 'static,
     {
         // 增加活跃连接计数
         self.active_connections
             .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
 
+        *self
+            .outbound_connections
+            .lock()
+            .unwrap()
+            .entry(outbound_tag.to_string())
+            .or_insert(0) += 1;
+
         let active_connections = self.active_connections.clone();
+        let outbound_connections = self.outbound_connections.clone();
+        let outbound_tag = outbound_tag.to_string();
+        let sampling = self.sampling;
+        let relay = self.relay;
 
         // 在新任务中处理连接
-        tokio::spawn(async move {
-            let connection = ProxyConnection::new(client_stream, remote_stream);
-            
+        let relay = tokio::spawn(async move {
+            let connection = ProxyConnection::new(client_stream, remote_stream)
+                .with_throughput_sampling(sampling)
+                .with_label(label)
+                .with_tee(tee)
+                .with_relay_options(relay);
+
             if let Err(e) = connection.relay().await {
                 error!("连接处理失败: {}", e);
             }
 
             // 减少活跃连接计数
             active_connections.fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+            let mut per_outbound = outbound_connections.lock().unwrap();
+            if let Some(count) = per_outbound.get_mut(&outbound_tag) {
+                *count -= 1;
+                if *count == 0 {
+                    per_outbound.remove(&outbound_tag);
+                }
+            }
         });
 
-        Ok(())
+        Ok(relay)
     }
 }
 
@@ -105,10 +356,154 @@ impl Default for ConnectionManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Mutex;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    fn label() -> ConnectionLabel {
+        ConnectionLabel {
+            id: 42,
+            client: "192.0.2.7:50000".parse().unwrap(),
+            target: "example.com:443".to_string(),
+        }
+    }
 
     #[test]
     fn test_connection_manager_creation() {
         let manager = ConnectionManager::new();
         assert_eq!(manager.active_count(), 0);
     }
+
+    #[tokio::test]
+    async fn test_outbound_active_counts() {
+        let manager = ConnectionManager::new();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let remote = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (upstream, _) = listener.accept().await.unwrap();
+        let (client, client_side) = tokio::io::duplex(1024);
+
+        manager
+            .handle_connection(client_side, remote, "relay-a", label(), None)
+            .await
+            .unwrap();
+        assert_eq!(manager.active_count(), 1);
+        assert_eq!(manager.outbound_active_count("relay-a"), 1);
+        assert_eq!(manager.outbound_active_count("relay-b"), 0);
+
+        drop(client);
+        drop(upstream);
+        for _ in 0..100 {
+            if manager.active_count() == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(manager.active_count(), 0);
+        assert_eq!(manager.outbound_active_count("relay-a"), 0);
+    }
+
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_long_lived_connection_emits_samples() {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let (mut client, client_side) = tokio::io::duplex(64 * 1024);
+        let (remote_side, mut remote) = tokio::io::duplex(64 * 1024);
+
+        let sampling = ThroughputSampling {
+            interval: Duration::from_millis(50),
+            min_duration: Duration::from_millis(100),
+        };
+        let relay = ProxyConnection::new(client_side, remote_side)
+            .with_throughput_sampling(Some(sampling))
+            .with_label(label())
+            .relay();
+
+        let traffic = async move {
+            let chunk = vec![0x5a; 16 * 1024];
+            let mut sink = vec![0u8; 64 * 1024];
+            let deadline = tokio::time::Instant::now() + Duration::from_millis(400);
+            while tokio::time::Instant::now() < deadline {
+                client.write_all(&chunk).await.unwrap();
+                let _ = remote.read(&mut sink).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+            client.shutdown().await.unwrap();
+            remote.shutdown().await.unwrap();
+        };
+
+        let (result, _) = tokio::join!(relay, traffic);
+        result.unwrap();
+
+        let output = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let samples = output.lines().filter(|l| l.contains("吞吐采样")).count();
+        assert!(samples >= 3, "expected periodic samples, got:\n{}", output);
+        assert!(!output.contains("上行 0 B/s"), "samples should reflect traffic:\n{}", output);
+        // 每条采样都带有连接的身份
+        assert!(
+            output
+                .lines()
+                .filter(|l| l.contains("吞吐采样"))
+                .all(|l| l.contains("conn_id=42") && l.contains("client=192.0.2.7:50000") && l.contains("target=\"example.com:443\"")),
+            "samples should identify the connection:\n{}",
+            output
+        );
+    }
+
+    #[tokio::test]
+    async fn test_short_connection_emits_no_samples() {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let (mut client, client_side) = tokio::io::duplex(1024);
+        let (remote_side, mut remote) = tokio::io::duplex(1024);
+
+        let sampling = ThroughputSampling {
+            interval: Duration::from_millis(20),
+            min_duration: Duration::from_secs(60),
+        };
+        let relay = ProxyConnection::new(client_side, remote_side)
+            .with_throughput_sampling(Some(sampling))
+            .relay();
+
+        let traffic = async move {
+            client.write_all(b"hello").await.unwrap();
+            let mut buf = [0u8; 5];
+            remote.read_exact(&mut buf).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            client.shutdown().await.unwrap();
+            remote.shutdown().await.unwrap();
+        };
+
+        let (result, _) = tokio::join!(relay, traffic);
+        result.unwrap();
+
+        let output = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(!output.contains("吞吐采样"));
+        assert!(output.contains("上行: 5 字节"));
+    }
 }
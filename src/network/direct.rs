@@ -0,0 +1,60 @@
+//! XTLS 直接复制开关
+//!
+//! xtls-rprx-vision 在识别出内层 TLS 1.3 流量后，双方会在约定的位置绕过外层 TLS，
+//! 直接在底层 TCP 上收发内层 TLS 记录。外层 TLS 由传输层 (Reality) 实现，
+//! vision 由协议层解析，两者通过 [`DirectCopy`] 协调: 协议层在读到 / 写出 Direct
+//! 命令后打开对应方向的开关，传输层随后绕过 TLS 直接读写底层连接。
+//!
+//! 开关通过任务局部变量传递，只有支持直接复制的传输层 (Reality rustls 后端上的
+//! 原始 TCP) 才会建立作用域；其他传输层 (native 后端、无加密、XHTTP、gRPC) 下
+//! [`current`] 返回 `None`，客户端一旦发出 Direct 就会与服务端失去同步，因此这些
+//! 传输层上的 vision 请求在握手时即被拒绝。
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+tokio::task_local! {
+    static CURRENT: Arc<DirectCopy>;
+}
+
+/// 一条连接两个方向的直接复制开关，打开后不再关闭
+#[derive(Debug, Default)]
+pub struct DirectCopy {
+    read: AtomicBool,
+    write: AtomicBool,
+}
+
+impl DirectCopy {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// 客户端 -> 服务器方向改为直接读取底层连接
+    pub fn enable_read(&self) {
+        self.read.store(true, Ordering::Release);
+    }
+
+    /// 服务器 -> 客户端方向改为直接写入底层连接
+    pub fn enable_write(&self) {
+        self.write.store(true, Ordering::Release);
+    }
+
+    pub fn read_enabled(&self) -> bool {
+        self.read.load(Ordering::Acquire)
+    }
+
+    pub fn write_enabled(&self) -> bool {
+        self.write.load(Ordering::Acquire)
+    }
+
+    /// 在本开关的作用域内运行 `fut`，其中的 [`current`] 返回本开关
+    pub async fn scope<F: Future>(self: Arc<Self>, fut: F) -> F::Output {
+        CURRENT.scope(self, fut).await
+    }
+}
+
+/// 当前连接的直接复制开关，传输层不支持时为 `None`
+pub fn current() -> Option<Arc<DirectCopy>> {
+    CURRENT.try_with(Arc::clone).ok()
+}
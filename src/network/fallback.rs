@@ -0,0 +1,398 @@
+//! VLESS 回落 (fallbacks)
+//!
+//! 认证失败的连接按 Xray 的规则选择回落: 先按 ALPN 分组 (没有对应分组时使用 ALPN
+//! 为空的分组)，再按首包 HTTP 请求行中的路径精确匹配，匹配不到时使用路径为空的回落。
+//! 选中后把已读取的数据连同后续流量一起转发给回落目标。
+//!
+//! 回落目标为 `host:port`，或 `unix:` 开头的 unix socket 路径 (如同机的 nginx)。
+//!
+//! [`FallbackRateLimiter`] 按来源 IP 限制回落速率，避免服务器被利用来反复连接回落目标；
+//! [`DestPool`] 预先建立到回落目标的连接，隐藏建立连接的往返时间。
+
+use anyhow::{Context, Result};
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+use tracing::debug;
+
+use crate::config::Fallback;
+use crate::protocol::ProxyHeader;
+
+/// unix socket 回落目标的前缀，如 `unix:/run/nginx.sock`
+pub const UNIX_SCHEME: &str = "unix:";
+
+/// 按 ALPN 与首包选择回落，没有可用的回落时返回 `None`
+pub fn select_fallback<'a>(fallbacks: &'a [Fallback], alpn: Option<&str>, first: &[u8]) -> Option<&'a Fallback> {
+    let alpn = alpn
+        .filter(|alpn| fallbacks.iter().any(|f| f.alpn == *alpn))
+        .unwrap_or("");
+    let group = || fallbacks.iter().filter(move |f| f.alpn == alpn);
+    if let Some(path) = http_path(first) {
+        if let Some(fallback) = group().find(|f| !f.path.is_empty() && f.path == path) {
+            return Some(fallback);
+        }
+    }
+    group().find(|f| f.path.is_empty())
+}
+
+/// 首包 HTTP 请求行中的路径 (不含查询参数)，不是 HTTP 请求时返回 `None`
+fn http_path(first: &[u8]) -> Option<&str> {
+    let line_end = first.iter().position(|&b| b == b'\r' || b == b'\n').unwrap_or(first.len());
+    let line = std::str::from_utf8(&first[..line_end]).ok()?;
+    let mut parts = line.splitn(3, ' ');
+    let _method = parts.next()?;
+    let target = parts.next()?;
+    if !target.starts_with('/') {
+        return None;
+    }
+    Some(target.split('?').next().unwrap_or(target))
+}
+
+/// 把连接转发给 VLESS 回落目标，见 [`relay_to`]
+pub async fn relay_fallback<S>(
+    stream: S,
+    fallback: &Fallback,
+    first: &[u8],
+    client_addr: SocketAddr,
+    local_addr: Option<SocketAddr>,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    relay_to(stream, &fallback.dest, fallback.xver, first, Some(client_addr), local_addr).await
+}
+
+/// 把连接转发给 `dest`: 按 `xver` 发送 PROXY protocol 头，再发送已读取的数据，之后双向转发
+///
+/// `dest` 以 [`UNIX_SCHEME`] 开头时连接 unix socket。客户端地址未知时不发送 PROXY protocol 头。
+pub async fn relay_to<S>(
+    stream: S,
+    dest: &str,
+    xver: u8,
+    first: &[u8],
+    client_addr: Option<SocketAddr>,
+    local_addr: Option<SocketAddr>,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    #[cfg(unix)]
+    if let Some(path) = dest.strip_prefix(UNIX_SCHEME) {
+        let target = UnixStream::connect(path)
+            .await
+            .with_context(|| format!("连接回落目标 {} 失败", dest))?;
+        return relay_over(stream, target, dest, xver, first, client_addr, local_addr).await;
+    }
+    let target = TcpStream::connect(dest)
+        .await
+        .with_context(|| format!("连接回落目标 {} 失败", dest))?;
+    relay_over(stream, target, dest, xver, first, client_addr, local_addr).await
+}
+
+/// 与 [`relay_to`] 相同，但使用已经建立的到 `dest` 的连接 `target`
+pub async fn relay_over<S, T>(
+    mut stream: S,
+    mut target: T,
+    dest: &str,
+    xver: u8,
+    first: &[u8],
+    client_addr: Option<SocketAddr>,
+    local_addr: Option<SocketAddr>,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    match client_addr {
+        Some(client_addr) if xver > 0 => {
+            let local_addr = local_addr.unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], 0)));
+            target.write_all(&ProxyHeader::new(client_addr, local_addr).encode(xver)?).await?;
+        }
+        None if xver > 0 => debug!("客户端地址未知，不向回落目标 {} 发送 PROXY protocol 头", dest),
+        _ => {}
+    }
+    target.write_all(first).await?;
+    let (up, down) = tokio::io::copy_bidirectional(&mut stream, &mut target).await?;
+    debug!("回落 {} 结束: 上行 {} 字节, 下行 {} 字节", dest, up, down);
+    Ok(())
+}
+
+/// 按来源 IP 的令牌桶: 每秒补充 `rate` 个令牌，最多积攒 `burst` 个
+#[derive(Debug)]
+pub struct FallbackRateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<RateBuckets>,
+}
+
+#[derive(Debug)]
+struct RateBuckets {
+    tokens: HashMap<IpAddr, (f64, Instant)>,
+    next_purge: Instant,
+}
+
+/// 清理已补满的令牌桶的间隔
+const RATE_PURGE_INTERVAL: Duration = Duration::from_secs(60);
+
+impl FallbackRateLimiter {
+    /// `burst` 小于 `rate` 时按 `rate` 计算
+    pub fn new(rate: u32, burst: u32) -> Self {
+        Self {
+            rate: rate as f64,
+            burst: burst.max(rate).max(1) as f64,
+            buckets: Mutex::new(RateBuckets {
+                tokens: HashMap::new(),
+                next_purge: Instant::now() + RATE_PURGE_INTERVAL,
+            }),
+        }
+    }
+
+    /// 消耗 `ip` 的一个令牌，令牌用尽时返回 false
+    pub fn allow(&self, ip: IpAddr) -> bool {
+        self.allow_at(ip, Instant::now())
+    }
+
+    fn allow_at(&self, ip: IpAddr, now: Instant) -> bool {
+        let mut buckets = self.buckets.lock().unwrap();
+        if now >= buckets.next_purge {
+            // 补满的令牌桶与新建的没有区别，可以丢弃
+            let (rate, burst) = (self.rate, self.burst);
+            buckets
+                .tokens
+                .retain(|_, (tokens, at)| *tokens + now.duration_since(*at).as_secs_f64() * rate < burst);
+            buckets.next_purge = now + RATE_PURGE_INTERVAL;
+        }
+        let (tokens, at) = buckets.tokens.entry(ip.to_canonical()).or_insert((self.burst, now));
+        *tokens = (*tokens + now.duration_since(*at).as_secs_f64() * self.rate).min(self.burst);
+        *at = now;
+        if *tokens < 1.0 {
+            return false;
+        }
+        *tokens -= 1.0;
+        true
+    }
+}
+
+/// 到回落目标的预连接池
+///
+/// 回落连接在 TLS 中途无法复用，租出的连接用完即丢弃，随后在后台补充新的连接；
+/// 空闲超过 `idle_ttl` 或已被对端关闭的连接不再使用。池中没有可用连接时直接连接。
+/// 信号量限制空闲与正在建立的预连接总数不超过 `size`。
+#[derive(Debug)]
+pub struct DestPool {
+    dest: String,
+    idle_ttl: Duration,
+    idle: Mutex<VecDeque<(TcpStream, Instant, tokio::sync::OwnedSemaphorePermit)>>,
+    slots: Arc<Semaphore>,
+    /// 最近一次直接连接的耗时 (微秒)，用于估算预连接节省的时间
+    connect_micros: AtomicU64,
+}
+
+impl DestPool {
+    pub fn new(dest: String, size: usize, idle_ttl: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            dest,
+            idle_ttl,
+            idle: Mutex::new(VecDeque::new()),
+            slots: Arc::new(Semaphore::new(size)),
+            connect_micros: AtomicU64::new(0),
+        })
+    }
+
+    pub fn dest(&self) -> &str {
+        &self.dest
+    }
+
+    /// 取出一条可用的预连接，没有时直接连接；之后在后台补满连接池
+    pub async fn connect(self: &Arc<Self>) -> Result<TcpStream> {
+        let pooled = self.take();
+        self.refill();
+        if let Some(stream) = pooled {
+            debug!(
+                "使用到 {} 的预连接，约节省 {} µs 连接时间",
+                self.dest,
+                self.connect_micros.load(Ordering::Relaxed)
+            );
+            return Ok(stream);
+        }
+        let start = Instant::now();
+        let stream = TcpStream::connect(&self.dest)
+            .await
+            .with_context(|| format!("连接回落目标 {} 失败", self.dest))?;
+        self.connect_micros.store(start.elapsed().as_micros() as u64, Ordering::Relaxed);
+        Ok(stream)
+    }
+
+    /// 最近建立的未过期且未被关闭的空闲连接
+    fn take(&self) -> Option<TcpStream> {
+        let mut idle = self.idle.lock().unwrap();
+        while let Some((stream, since, _permit)) = idle.pop_back() {
+            if since.elapsed() >= self.idle_ttl {
+                // 更早的连接同样已过期
+                idle.clear();
+                return None;
+            }
+            // 回落目标在收到数据前不应发送任何内容，可读说明连接已关闭或状态异常
+            let mut probe = [0u8; 1];
+            if matches!(stream.try_read(&mut probe), Err(e) if e.kind() == std::io::ErrorKind::WouldBlock) {
+                return Some(stream);
+            }
+        }
+        None
+    }
+
+    /// 按剩余名额在后台建立新的预连接
+    fn refill(self: &Arc<Self>) {
+        while let Ok(permit) = Arc::clone(&self.slots).try_acquire_owned() {
+            let pool = Arc::clone(self);
+            tokio::spawn(async move {
+                let start = Instant::now();
+                match TcpStream::connect(&pool.dest).await {
+                    Ok(stream) => {
+                        pool.connect_micros.store(start.elapsed().as_micros() as u64, Ordering::Relaxed);
+                        pool.idle.lock().unwrap().push_back((stream, Instant::now(), permit));
+                    }
+                    Err(e) => debug!("预连接回落目标 {} 失败: {}", pool.dest, e),
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fallback(alpn: &str, path: &str, dest: &str) -> Fallback {
+        Fallback {
+            alpn: alpn.to_string(),
+            path: path.to_string(),
+            dest: dest.to_string(),
+            xver: 0,
+        }
+    }
+
+    #[test]
+    fn test_select_fallback() {
+        let fallbacks = vec![
+            fallback("", "", "default"),
+            fallback("", "/api", "api"),
+            fallback("h2", "", "h2"),
+        ];
+        let dest = |alpn, first: &[u8]| select_fallback(&fallbacks, alpn, first).map(|f| f.dest.as_str());
+
+        assert_eq!(dest(None, b"GET / HTTP/1.1\r\n\r\n"), Some("default"));
+        assert_eq!(dest(None, b"GET /api?x=1 HTTP/1.1\r\n\r\n"), Some("api"));
+        assert_eq!(dest(None, b"GET /api/v2 HTTP/1.1\r\n\r\n"), Some("default"));
+        assert_eq!(dest(None, &[0x00, 0x01, 0x02]), Some("default"));
+        assert_eq!(dest(Some("h2"), b"GET /api HTTP/1.1\r\n\r\n"), Some("h2"));
+        // 没有对应 ALPN 分组时使用 ALPN 为空的分组
+        assert_eq!(dest(Some("http/1.1"), b"GET /api HTTP/1.1\r\n\r\n"), Some("api"));
+
+        assert!(select_fallback(&[fallback("h2", "", "h2")], None, b"GET /").is_none());
+        assert!(select_fallback(&[], None, b"GET /").is_none());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_relay_to_unix_socket() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let path = std::env::temp_dir().join(format!("xray-lite-fallback-{}.sock", uuid::Uuid::new_v4()));
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let (mut reader, mut writer) = stream.split();
+            tokio::io::copy(&mut reader, &mut writer).await.unwrap();
+        });
+
+        // 已读取的首包先于后续数据到达 unix socket
+        let (mut client, server) = tokio::io::duplex(1024);
+        let dest = format!("{}{}", UNIX_SCHEME, path.display());
+        let relay = tokio::spawn(async move { relay_to(server, &dest, 0, b"first ", None, None).await });
+        client.write_all(b"second").await.unwrap();
+        let mut echoed = [0u8; 12];
+        client.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"first second");
+        drop(client);
+        relay.await.unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(relay_to(tokio::io::duplex(64).0, &format!("{}{}", UNIX_SCHEME, path.display()), 0, b"", None, None).await.is_err());
+    }
+
+    /// 等待后台补充的预连接数达到 `count`
+    async fn wait_idle(pool: &DestPool, count: usize) {
+        for _ in 0..100 {
+            if pool.idle.lock().unwrap().len() == count {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("预连接数未达到 {}", count);
+    }
+
+    #[tokio::test]
+    async fn test_dest_pool() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let dest = listener.local_addr().unwrap().to_string();
+
+        // 第一次直接连接，并在后台补满两条预连接，之后的租用不再新建连接 (直到补充)
+        let pool = DestPool::new(dest.clone(), 2, Duration::from_secs(60));
+        let _direct = pool.connect().await.unwrap();
+        let mut accepted = Vec::new();
+        for _ in 0..3 {
+            accepted.push(listener.accept().await.unwrap().0);
+        }
+        wait_idle(&pool, 2).await;
+        assert!(pool.take().is_some());
+        assert!(pool.take().is_some());
+        assert!(pool.take().is_none());
+
+        // 空闲超时的连接不再使用
+        let expired = DestPool::new(dest.clone(), 1, Duration::ZERO);
+        expired.refill();
+        let _pooled = listener.accept().await.unwrap();
+        wait_idle(&expired, 1).await;
+        assert!(expired.take().is_none());
+
+        // 被对端关闭的连接不再使用
+        let closed = DestPool::new(dest, 1, Duration::from_secs(60));
+        closed.refill();
+        drop(listener.accept().await.unwrap());
+        wait_idle(&closed, 1).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(closed.take().is_none());
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = FallbackRateLimiter::new(1, 2);
+        let start = Instant::now();
+        let a: IpAddr = "192.0.2.1".parse().unwrap();
+        let b: IpAddr = "192.0.2.2".parse().unwrap();
+
+        assert!(limiter.allow_at(a, start));
+        assert!(limiter.allow_at(a, start));
+        assert!(!limiter.allow_at(a, start));
+        // 其他来源不受影响
+        assert!(limiter.allow_at(b, start));
+        // IPv4 映射地址与 IPv4 地址共用令牌桶
+        assert!(!limiter.allow_at("::ffff:192.0.2.1".parse().unwrap(), start));
+
+        // 每秒补充一个令牌
+        assert!(limiter.allow_at(a, start + Duration::from_millis(1100)));
+        assert!(!limiter.allow_at(a, start + Duration::from_millis(1200)));
+
+        // 补满的令牌桶在清理时被丢弃
+        limiter.allow_at(a, start + RATE_PURGE_INTERVAL * 2);
+        assert_eq!(limiter.buckets.lock().unwrap().tokens.len(), 1);
+    }
+}
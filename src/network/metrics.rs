@@ -0,0 +1,256 @@
+//! 按入站统计的并发指标
+//!
+//! 每个入站记录当前的活跃连接数与进行中的握手数 (已接受、尚未得出认证结论的连接)。
+//! 握手数可以按入站设置上限 (`maxHandshakes`)，被攻击的入站拒绝新连接时不影响其他入站。
+//! 握手在连接尝试记录结果 (认证成功、回落、拒绝等) 时结束，见 [`ConnectionAttempt`]。
+//! Reality 入站还统计转发给 dest 的回落连接，回落数同样可以设置上限。
+//!
+//! [`ConnectionAttempt`]: super::ConnectionAttempt
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+/// 单个入站的并发指标
+#[derive(Debug)]
+pub struct InboundMetrics {
+    name: String,
+    max_handshakes: Option<usize>,
+    active_connections: AtomicUsize,
+    handshakes_in_flight: AtomicUsize,
+    rejected_handshakes: AtomicU64,
+    fallbacks_attempted: AtomicU64,
+    fallbacks_active: AtomicUsize,
+    fallbacks_rejected: AtomicU64,
+}
+
+impl InboundMetrics {
+    /// `max_handshakes` 为进行中握手数的上限，`None` 表示不限制
+    pub fn new(name: impl Into<String>, max_handshakes: Option<usize>) -> Arc<Self> {
+        Arc::new(Self {
+            name: name.into(),
+            max_handshakes,
+            active_connections: AtomicUsize::new(0),
+            handshakes_in_flight: AtomicUsize::new(0),
+            rejected_handshakes: AtomicU64::new(0),
+            fallbacks_attempted: AtomicU64::new(0),
+            fallbacks_active: AtomicUsize::new(0),
+            fallbacks_rejected: AtomicU64::new(0),
+        })
+    }
+
+    /// 入站名称 (tag，未设置时为监听地址)
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn max_handshakes(&self) -> Option<usize> {
+        self.max_handshakes
+    }
+
+    pub fn active_connections(&self) -> usize {
+        self.active_connections.load(Ordering::Relaxed)
+    }
+
+    pub fn handshakes_in_flight(&self) -> usize {
+        self.handshakes_in_flight.load(Ordering::Relaxed)
+    }
+
+    /// 因握手数达到上限被拒绝的连接数
+    pub fn rejected_handshakes(&self) -> u64 {
+        self.rejected_handshakes.load(Ordering::Relaxed)
+    }
+
+    /// 记录一个活跃连接，返回的守卫释放时计数减一
+    pub fn track_connection(self: &Arc<Self>) -> ConnectionGuard {
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard { metrics: self.clone() }
+    }
+
+    /// 开始一次握手，达到上限时返回 `None` 并计入拒绝数
+    pub fn try_begin_handshake(self: &Arc<Self>) -> Option<HandshakeGuard> {
+        let limit = self.max_handshakes.unwrap_or(usize::MAX);
+        let admitted = self
+            .handshakes_in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| (n < limit).then_some(n + 1))
+            .is_ok();
+        if !admitted {
+            self.rejected_handshakes.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        Some(HandshakeGuard { metrics: self.clone() })
+    }
+
+    /// 尝试回落的连接数 (包括被拒绝的)
+    pub fn fallbacks_attempted(&self) -> u64 {
+        self.fallbacks_attempted.load(Ordering::Relaxed)
+    }
+
+    /// 正在转发给回落目标的连接数
+    pub fn fallbacks_active(&self) -> usize {
+        self.fallbacks_active.load(Ordering::Relaxed)
+    }
+
+    /// 因并发上限或速率限制未转发、直接关闭的回落连接数
+    pub fn fallbacks_rejected(&self) -> u64 {
+        self.fallbacks_rejected.load(Ordering::Relaxed)
+    }
+
+    /// 开始一次回落，进行中的回落数达到 `limit` (0 表示不限制) 时返回 `None` 并计入拒绝数
+    pub fn try_begin_fallback(self: &Arc<Self>, limit: usize) -> Option<FallbackGuard> {
+        self.fallbacks_attempted.fetch_add(1, Ordering::Relaxed);
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        let admitted = self
+            .fallbacks_active
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| (n < limit).then_some(n + 1))
+            .is_ok();
+        if !admitted {
+            self.fallbacks_rejected.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        Some(FallbackGuard { metrics: self.clone() })
+    }
+
+    /// 记录一次未经并发检查就被拒绝的回落 (如超出速率限制)
+    pub fn reject_fallback(&self) {
+        self.fallbacks_attempted.fetch_add(1, Ordering::Relaxed);
+        self.fallbacks_rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 输出一条指标日志
+    fn log(&self) {
+        info!(
+            target: "metrics",
+            inbound = %self.name,
+            active = self.active_connections(),
+            handshakes = self.handshakes_in_flight(),
+            rejected_handshakes = self.rejected_handshakes(),
+            fallbacks = self.fallbacks_attempted(),
+            active_fallbacks = self.fallbacks_active(),
+            rejected_fallbacks = self.fallbacks_rejected(),
+            "📊 入站指标"
+        );
+    }
+}
+
+/// 活跃连接计数的守卫
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    metrics: Arc<InboundMetrics>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.metrics.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// 进行中握手计数的守卫
+#[derive(Debug)]
+pub struct HandshakeGuard {
+    metrics: Arc<InboundMetrics>,
+}
+
+impl Drop for HandshakeGuard {
+    fn drop(&mut self) {
+        self.metrics.handshakes_in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// 进行中回落计数的守卫
+#[derive(Debug)]
+pub struct FallbackGuard {
+    metrics: Arc<InboundMetrics>,
+}
+
+impl Drop for FallbackGuard {
+    fn drop(&mut self) {
+        self.metrics.fallbacks_active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// 所有入站的指标
+#[derive(Debug, Default)]
+pub struct Metrics {
+    inbounds: Mutex<Vec<Arc<InboundMetrics>>>,
+}
+
+impl Metrics {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// 登记一个入站
+    pub fn register(&self, name: impl Into<String>, max_handshakes: Option<usize>) -> Arc<InboundMetrics> {
+        let metrics = InboundMetrics::new(name, max_handshakes);
+        self.inbounds.lock().unwrap().push(metrics.clone());
+        metrics
+    }
+
+    /// 按名称查找入站
+    pub fn inbound(&self, name: &str) -> Option<Arc<InboundMetrics>> {
+        self.inbounds.lock().unwrap().iter().find(|m| m.name == name).cloned()
+    }
+
+    /// 所有入站，按登记顺序
+    pub fn inbounds(&self) -> Vec<Arc<InboundMetrics>> {
+        self.inbounds.lock().unwrap().clone()
+    }
+
+    /// 为每个入站输出一条指标日志
+    pub fn log(&self) {
+        for inbound in self.inbounds() {
+            inbound.log();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handshake_cap() {
+        let metrics = Metrics::new();
+        let limited = metrics.register("limited", Some(2));
+        let other = metrics.register("other", None);
+
+        let first = limited.try_begin_handshake().unwrap();
+        let _second = limited.try_begin_handshake().unwrap();
+        assert!(limited.try_begin_handshake().is_none());
+        assert_eq!(limited.handshakes_in_flight(), 2);
+        assert_eq!(limited.rejected_handshakes(), 1);
+
+        // 其他入站不受影响
+        let _other = other.try_begin_handshake().unwrap();
+        assert_eq!(other.rejected_handshakes(), 0);
+
+        // 握手结束后腾出名额
+        drop(first);
+        assert_eq!(limited.handshakes_in_flight(), 1);
+        assert!(limited.try_begin_handshake().is_some());
+
+        let connection = limited.track_connection();
+        assert_eq!(metrics.inbound("limited").unwrap().active_connections(), 1);
+        drop(connection);
+        assert_eq!(limited.active_connections(), 0);
+    }
+
+    #[test]
+    fn test_fallback_cap() {
+        let metrics = InboundMetrics::new("reality", None);
+        let first = metrics.try_begin_fallback(2).unwrap();
+        let _second = metrics.try_begin_fallback(2).unwrap();
+        assert!(metrics.try_begin_fallback(2).is_none());
+        metrics.reject_fallback();
+        assert_eq!(metrics.fallbacks_attempted(), 4);
+        assert_eq!(metrics.fallbacks_active(), 2);
+        assert_eq!(metrics.fallbacks_rejected(), 2);
+
+        drop(first);
+        assert_eq!(metrics.fallbacks_active(), 1);
+        assert!(metrics.try_begin_fallback(2).is_some());
+        // 0 表示不限制
+        let _guards: Vec<_> = (0..10).map(|_| metrics.try_begin_fallback(0).unwrap()).collect();
+    }
+}
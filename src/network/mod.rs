@@ -1,3 +1,18 @@
+pub mod attempt;
+pub mod balancer;
 pub mod connection;
+pub mod direct;
+pub mod fallback;
+pub mod metrics;
+pub mod relay;
+pub mod routing;
+pub mod tee;
 
-pub use connection::ConnectionManager;
+pub use attempt::{AttemptOutcome, ConnectionAttempt, CountingStream};
+pub use balancer::Balancer;
+pub use connection::{ConnectionLabel, ConnectionManager, ThroughputSampling};
+pub use direct::DirectCopy;
+pub use metrics::{InboundMetrics, Metrics};
+pub use relay::RelayOptions;
+pub use routing::{RouteContext, RouteDecision, Router};
+pub use tee::TeeSinks;
@@ -0,0 +1,283 @@
+//! 双向数据转发
+//!
+//! 与 `tokio::io::copy_bidirectional` 相同的状态机，
+//! 但缓冲区大小与 flush 策略可配置 (小缓冲区偏向延迟，大缓冲区偏向吞吐)。
+
+use std::future::poll_fn;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::config::{FlushStrategy, RelayConfig};
+
+/// 转发参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelayOptions {
+    /// 每个方向的缓冲区大小 (字节)
+    pub buffer_size: usize,
+    /// flush 策略
+    pub flush: FlushStrategy,
+}
+
+impl RelayOptions {
+    /// 从转发配置创建
+    pub fn from_config(config: &RelayConfig) -> Self {
+        Self {
+            buffer_size: config.buffer_size,
+            flush: config.flush,
+        }
+    }
+}
+
+impl Default for RelayOptions {
+    fn default() -> Self {
+        Self::from_config(&RelayConfig::default())
+    }
+}
+
+/// 单方向的复制缓冲区
+struct CopyBuffer {
+    buf: Box<[u8]>,
+    pos: usize,
+    cap: usize,
+    amt: u64,
+    read_done: bool,
+    need_flush: bool,
+    flush: FlushStrategy,
+}
+
+impl CopyBuffer {
+    fn new(options: RelayOptions) -> Self {
+        Self {
+            buf: vec![0u8; options.buffer_size].into_boxed_slice(),
+            pos: 0,
+            cap: 0,
+            amt: 0,
+            read_done: false,
+            need_flush: false,
+            flush: options.flush,
+        }
+    }
+
+    fn poll_copy<R, W>(
+        &mut self,
+        cx: &mut Context<'_>,
+        mut reader: Pin<&mut R>,
+        mut writer: Pin<&mut W>,
+    ) -> Poll<io::Result<u64>>
+    where
+        R: AsyncRead + ?Sized,
+        W: AsyncWrite + ?Sized,
+    {
+        loop {
+            // 缓冲区已写空，继续读取
+            if self.pos == self.cap && !self.read_done {
+                let mut buf = ReadBuf::new(&mut self.buf);
+                match reader.as_mut().poll_read(cx, &mut buf) {
+                    Poll::Ready(Ok(())) => {
+                        let n = buf.filled().len();
+                        self.pos = 0;
+                        self.cap = n;
+                        if n == 0 {
+                            self.read_done = true;
+                        }
+                    }
+                    Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                    Poll::Pending => {
+                        // 读端暂时没有数据，先把已写出的数据 flush 出去
+                        if self.need_flush {
+                            ready!(writer.as_mut().poll_flush(cx))?;
+                            self.need_flush = false;
+                        }
+                        return Poll::Pending;
+                    }
+                }
+            }
+
+            while self.pos < self.cap {
+                let n = ready!(writer.as_mut().poll_write(cx, &self.buf[self.pos..self.cap]))?;
+                if n == 0 {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "写入端不再接受数据",
+                    )));
+                }
+                self.pos += n;
+                self.amt += n as u64;
+                self.need_flush = true;
+            }
+
+            if self.need_flush && self.flush == FlushStrategy::Always {
+                ready!(writer.as_mut().poll_flush(cx))?;
+                self.need_flush = false;
+            }
+
+            if self.read_done {
+                ready!(writer.as_mut().poll_flush(cx))?;
+                return Poll::Ready(Ok(self.amt));
+            }
+        }
+    }
+}
+
+enum TransferState {
+    Running(CopyBuffer),
+    ShuttingDown(u64),
+    Done(u64),
+}
+
+fn transfer_one_direction<A, B>(
+    cx: &mut Context<'_>,
+    state: &mut TransferState,
+    reader: &mut A,
+    writer: &mut B,
+) -> Poll<io::Result<u64>>
+where
+    A: AsyncRead + Unpin + ?Sized,
+    B: AsyncWrite + Unpin + ?Sized,
+{
+    loop {
+        match state {
+            TransferState::Running(buf) => {
+                let count = ready!(buf.poll_copy(cx, Pin::new(&mut *reader), Pin::new(&mut *writer)))?;
+                *state = TransferState::ShuttingDown(count);
+            }
+            TransferState::ShuttingDown(count) => {
+                ready!(Pin::new(&mut *writer).poll_shutdown(cx))?;
+                *state = TransferState::Done(*count);
+            }
+            TransferState::Done(count) => return Poll::Ready(Ok(*count)),
+        }
+    }
+}
+
+/// 双向转发直到两个方向都结束，返回 (a -> b, b -> a) 的字节数
+pub async fn copy_bidirectional<A, B>(a: &mut A, b: &mut B, options: RelayOptions) -> io::Result<(u64, u64)>
+where
+    A: AsyncRead + AsyncWrite + Unpin + ?Sized,
+    B: AsyncRead + AsyncWrite + Unpin + ?Sized,
+{
+    let mut a_to_b = TransferState::Running(CopyBuffer::new(options));
+    let mut b_to_a = TransferState::Running(CopyBuffer::new(options));
+
+    poll_fn(|cx| {
+        let a_to_b = transfer_one_direction(cx, &mut a_to_b, a, b)?;
+        let b_to_a = transfer_one_direction(cx, &mut b_to_a, b, a)?;
+        let a_to_b = ready!(a_to_b);
+        let b_to_a = ready!(b_to_a);
+        Poll::Ready(Ok((a_to_b, b_to_a)))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    /// 记录单次写入的最大长度与 flush 次数
+    struct Recorder {
+        inner: DuplexStream,
+        max_write: usize,
+        writes: usize,
+        flushes: usize,
+    }
+
+    impl Recorder {
+        fn new(inner: DuplexStream) -> Self {
+            Self {
+                inner,
+                max_write: 0,
+                writes: 0,
+                flushes: 0,
+            }
+        }
+    }
+
+    impl AsyncRead for Recorder {
+        fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncWrite for Recorder {
+        fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, buf))?;
+            self.max_write = self.max_write.max(n);
+            self.writes += 1;
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.flushes += 1;
+            Pin::new(&mut self.inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_shutdown(cx)
+        }
+    }
+
+    /// 两个方向各发送 `len` 字节，校验内容并返回远端一侧的记录
+    async fn relay_payload(options: RelayOptions, len: usize) -> Recorder {
+        let (client, mut client_side) = tokio::io::duplex(64 * 1024);
+        let (remote_side, remote) = tokio::io::duplex(64 * 1024);
+        let mut remote_side = Recorder::new(remote_side);
+
+        let upload: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+        let download: Vec<u8> = upload.iter().rev().copied().collect();
+
+        // 两端都同时读写，避免双方都在写而把管道塞满
+        let exchange = |stream: DuplexStream, payload: Vec<u8>| async move {
+            let (mut reader, mut writer) = tokio::io::split(stream);
+            let write = async {
+                writer.write_all(&payload).await.unwrap();
+                writer.shutdown().await.unwrap();
+            };
+            let read = async {
+                let mut received = Vec::new();
+                reader.read_to_end(&mut received).await.unwrap();
+                received
+            };
+            tokio::join!(write, read).1
+        };
+        let peers = async {
+            tokio::join!(exchange(client, upload.clone()), exchange(remote, download.clone()))
+        };
+
+        let (relayed, (client_received, remote_received)) =
+            tokio::join!(copy_bidirectional(&mut client_side, &mut remote_side, options), peers);
+        assert_eq!(relayed.unwrap(), (len as u64, len as u64));
+        assert_eq!(client_received, download);
+        assert_eq!(remote_received, upload);
+        remote_side
+    }
+
+    #[tokio::test]
+    async fn test_small_and_large_buffers() {
+        let small = RelayOptions {
+            buffer_size: 1024,
+            flush: FlushStrategy::Auto,
+        };
+        let recorder = relay_payload(small, 300_000).await;
+        assert!(recorder.max_write <= 1024);
+
+        let large = RelayOptions {
+            buffer_size: 256 * 1024,
+            flush: FlushStrategy::Auto,
+        };
+        let recorder = relay_payload(large, 300_000).await;
+        assert!(recorder.max_write > 1024);
+    }
+
+    #[tokio::test]
+    async fn test_flush_always() {
+        let options = RelayOptions {
+            buffer_size: 4096,
+            flush: FlushStrategy::Always,
+        };
+        let recorder = relay_payload(options, 100_000).await;
+        assert!(recorder.flushes >= recorder.writes, "{} flushes for {} writes", recorder.flushes, recorder.writes);
+    }
+}
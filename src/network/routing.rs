@@ -0,0 +1,657 @@
+//! 路由模块
+//!
+//! 按配置顺序匹配 `routing.rules`，第一条命中的规则决定出站；
+//! 都不命中时使用第一个出站 (与 Xray 行为一致)。
+//! 规则也可以通过 `balancerTag` 交给负载均衡器在多个出站间选择。
+//! 同一条规则内的多个条件 (domain / ip / port / source / user) 为逻辑与关系。
+
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+use super::balancer::Balancer;
+use super::connection::ConnectionManager;
+use crate::outbound::{Dialer, OutboundHandler};
+use crate::config::{Outbound, PortList, RoutingConfig, RoutingRule, TeeConfig};
+use crate::protocol::vless::Address;
+
+/// 路由决策所需的连接信息
+#[derive(Debug, Clone)]
+pub struct RouteContext<'a> {
+    /// 目标地址
+    pub address: &'a Address,
+    /// 客户端真实地址 (TCP 对端或 Proxy Protocol 解析结果)
+    pub source: Option<SocketAddr>,
+    /// 已认证用户的邮箱
+    pub user: Option<&'a str>,
+    /// 嗅探到的域名 (`routeOnly` 时不改写目标，只参与域名规则匹配)
+    pub sniffed_domain: Option<&'a str>,
+}
+
+impl<'a> RouteContext<'a> {
+    /// 仅包含目标地址的路由上下文
+    pub fn new(address: &'a Address) -> Self {
+        Self {
+            address,
+            source: None,
+            user: None,
+            sniffed_domain: None,
+        }
+    }
+
+    /// 设置客户端来源地址
+    pub fn with_source(mut self, source: SocketAddr) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// 设置已认证用户的邮箱
+    pub fn with_user(mut self, user: Option<&'a str>) -> Self {
+        self.user = user;
+        self
+    }
+    /// 设置嗅探到的域名，域名规则优先匹配它而不是目标地址
+    pub fn with_sniffed_domain(mut self, domain: Option<&'a str>) -> Self {
+        self.sniffed_domain = domain;
+        self
+    }
+}
+
+/// 域名匹配方式
+#[derive(Debug, Clone)]
+enum DomainMatcher {
+    /// `full:` 完整匹配
+    Full(String),
+    /// `domain:` 匹配该域名及其子域名
+    Suffix(String),
+    /// `keyword:` 或无前缀，子串匹配
+    Keyword(String),
+}
+
+impl DomainMatcher {
+    /// 解析域名规则，`regexp:` / `geosite:` / `ext:` 暂不支持，返回 None
+    fn parse(pattern: &str) -> Option<Self> {
+        let lower = pattern.to_ascii_lowercase();
+        if let Some(v) = lower.strip_prefix("full:") {
+            Some(DomainMatcher::Full(v.to_string()))
+        } else if let Some(v) = lower.strip_prefix("domain:") {
+            Some(DomainMatcher::Suffix(v.to_string()))
+        } else if let Some(v) = lower.strip_prefix("keyword:") {
+            Some(DomainMatcher::Keyword(v.to_string()))
+        } else if lower.starts_with("regexp:") || lower.starts_with("geosite:") || lower.starts_with("ext:") {
+            None
+        } else {
+            Some(DomainMatcher::Keyword(lower))
+        }
+    }
+
+    fn matches(&self, domain: &str) -> bool {
+        match self {
+            DomainMatcher::Full(v) => domain == v,
+            DomainMatcher::Suffix(v) => {
+                domain == v
+                    || (domain.len() > v.len()
+                        && domain.ends_with(v.as_str())
+                        && domain.as_bytes()[domain.len() - v.len() - 1] == b'.')
+            }
+            DomainMatcher::Keyword(v) => domain.contains(v.as_str()),
+        }
+    }
+}
+
+/// CIDR 网段
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IpCidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpCidr {
+    /// 解析 `1.2.3.4`、`10.0.0.0/8`、`fd00::/8` 等形式
+    pub fn parse(s: &str) -> Result<Self> {
+        let (ip_part, prefix_part) = match s.split_once('/') {
+            Some((ip, prefix)) => (ip, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = ip_part
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("无效的 IP 规则: {}", s))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix_part {
+            Some(p) => p
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= max)
+                .ok_or_else(|| anyhow!("无效的 CIDR 前缀: {}", s))?,
+            None => max,
+        };
+        Ok(Self { addr, prefix })
+    }
+
+    /// 判断 IP 是否落在网段内
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(*ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(*ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// 预编译的路由规则
+#[derive(Debug, Clone)]
+struct CompiledRule {
+    domains: Option<Vec<DomainMatcher>>,
+    ips: Option<Vec<IpCidr>>,
+    port: Option<PortList>,
+    sources: Option<Vec<IpCidr>>,
+    users: Option<Vec<String>>,
+    outbound_tag: String,
+    balancer_tag: Option<String>,
+    tee: Option<TeeConfig>,
+}
+
+impl CompiledRule {
+    fn compile(rule: &RoutingRule) -> Result<Self> {
+        // 不支持的域名规则跳过而不是报错，以免旧配置升级后无法启动；
+        // 全部被跳过的规则不会按域名命中任何连接
+        let domains = rule.domain.as_ref().map(|list| {
+            list.iter()
+                .filter_map(|d| {
+                    let matcher = DomainMatcher::parse(d);
+                    if matcher.is_none() {
+                        warn!("⚠️ 暂不支持的域名规则 {} (出站 {})，已忽略", d, rule.outbound_tag);
+                    }
+                    matcher
+                })
+                .collect::<Vec<_>>()
+        });
+        let ips = rule
+            .ip
+            .as_ref()
+            .map(|list| list.iter().map(|ip| IpCidr::parse(ip)).collect::<Result<Vec<_>>>())
+            .transpose()?;
+        let sources = rule
+            .source
+            .as_ref()
+            .map(|list| list.iter().map(|ip| IpCidr::parse(ip)).collect::<Result<Vec<_>>>())
+            .transpose()?;
+
+        Ok(Self {
+            domains,
+            ips,
+            port: rule.port.clone(),
+            sources,
+            users: rule.user.clone(),
+            outbound_tag: rule.outbound_tag.clone(),
+            balancer_tag: rule.balancer_tag.clone(),
+            tee: rule.tee.clone(),
+        })
+    }
+
+    fn matches(&self, ctx: &RouteContext<'_>) -> bool {
+        if let Some(domains) = &self.domains {
+            let domain = match (ctx.sniffed_domain, ctx.address) {
+                (Some(domain), _) => domain,
+                (None, Address::Domain(domain, _)) => domain.as_str(),
+                (None, _) => return false,
+            };
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            if !domains.iter().any(|m| m.matches(&domain)) {
+                return false;
+            }
+        }
+
+        if let Some(ips) = &self.ips {
+            let ip = match ctx.address {
+                Address::Ipv4(ip, _) => IpAddr::V4(*ip),
+                Address::Ipv6(ip, _) => IpAddr::V6(*ip),
+                Address::Domain(..) => return false,
+            };
+            if !ips.iter().any(|cidr| cidr.contains(&ip)) {
+                return false;
+            }
+        }
+
+        if let Some(port) = &self.port {
+            if !port.contains(ctx.address.port()) {
+                return false;
+            }
+        }
+
+        if let Some(sources) = &self.sources {
+            let Some(source) = ctx.source else {
+                return false;
+            };
+            let ip = source.ip().to_canonical();
+            if !sources.iter().any(|cidr| cidr.contains(&ip)) {
+                return false;
+            }
+        }
+
+        if let Some(users) = &self.users {
+            let Some(user) = ctx.user else {
+                return false;
+            };
+            if !users.iter().any(|u| u == user) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// 路由结果
+#[derive(Debug, Clone, Copy)]
+pub struct RouteDecision<'a> {
+    /// 选中的出站标签
+    pub outbound_tag: &'a str,
+    /// 命中规则配置的流量镜像
+    pub tee: Option<&'a TeeConfig>,
+}
+
+/// 路由器
+#[derive(Debug)]
+pub struct Router {
+    rules: Vec<CompiledRule>,
+    outbounds: HashMap<String, Outbound>,
+    dialers: HashMap<String, Arc<dyn Dialer>>,
+    balancers: HashMap<String, Balancer>,
+    default_tag: String,
+    /// 用于 leastConn 策略读取各出站的活跃连接数
+    connections: Option<ConnectionManager>,
+}
+
+impl Router {
+    /// 根据路由配置与出站列表创建路由器
+    pub fn new(routing: &RoutingConfig, outbounds: &[Outbound]) -> Result<Self> {
+        let default_tag = outbounds
+            .first()
+            .map(|o| o.tag.clone())
+            .ok_or_else(|| anyhow!("至少需要一个出站配置"))?;
+
+        let rules = routing
+            .rules
+            .iter()
+            .map(CompiledRule::compile)
+            .collect::<Result<Vec<_>>>()?;
+
+        let balancers = routing
+            .balancers
+            .iter()
+            .map(|b| Ok((b.tag.clone(), Balancer::new(b, outbounds)?)))
+            .collect::<Result<HashMap<_, _>>>()?;
+        for rule in &rules {
+            if let Some(tag) = &rule.balancer_tag {
+                if !balancers.contains_key(tag) {
+                    return Err(anyhow!("负载均衡器不存在: {}", tag));
+                }
+            }
+        }
+
+        let dialers = outbounds
+            .iter()
+            .map(|o| Ok((o.tag.clone(), Arc::new(OutboundHandler::from_config(o)?) as Arc<dyn Dialer>)))
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        let outbounds = outbounds
+            .iter()
+            .map(|o| (o.tag.clone(), o.clone()))
+            .collect();
+
+        Ok(Self {
+            rules,
+            outbounds,
+            dialers,
+            balancers,
+            default_tag,
+            connections: None,
+        })
+    }
+
+    /// 关联连接管理器，使 leastConn 策略能读取活跃连接数
+    pub fn with_connection_manager(mut self, connections: ConnectionManager) -> Self {
+        self.connections = Some(connections);
+        self
+    }
+
+    /// 为连接选择出站标签
+    pub fn route(&self, ctx: &RouteContext<'_>) -> &str {
+        self.decide(ctx).outbound_tag
+    }
+
+    /// 为连接选择出站及命中规则附带的选项
+    pub fn decide(&self, ctx: &RouteContext<'_>) -> RouteDecision<'_> {
+        match self.rules.iter().enumerate().find(|(_, rule)| rule.matches(ctx)) {
+            Some((idx, rule)) => {
+                debug!(
+                    "路由: {} 命中规则 {} -> {}",
+                    ctx.address.to_string(),
+                    idx,
+                    rule.balancer_tag.as_deref().unwrap_or(&rule.outbound_tag)
+                );
+                let outbound_tag = match &rule.balancer_tag {
+                    Some(tag) => {
+                        let selected = self.balancers[tag].select(|outbound| {
+                            self.connections
+                                .as_ref()
+                                .map_or(0, |c| c.outbound_active_count(outbound))
+                        });
+                        debug!("负载均衡器 {} 选择出站 {}", tag, selected);
+                        selected
+                    }
+                    None => rule.outbound_tag.as_str(),
+                };
+                RouteDecision {
+                    outbound_tag,
+                    tee: rule.tee.as_ref(),
+                }
+            }
+            None => {
+                debug!(
+                    "路由: {} 未命中任何规则 -> 默认出站 {}",
+                    ctx.address.to_string(),
+                    self.default_tag
+                );
+                RouteDecision {
+                    outbound_tag: &self.default_tag,
+                    tee: None,
+                }
+            }
+        }
+    }
+
+    /// 按标签查找出站配置
+    pub fn outbound(&self, tag: &str) -> Option<&Outbound> {
+        self.outbounds.get(tag)
+    }
+
+    /// 替换指定出站标签的连接器 (链式出站或测试注入)
+    pub fn with_dialer(mut self, tag: &str, dialer: Arc<dyn Dialer>) -> Self {
+        self.dialers.insert(tag.to_string(), dialer);
+        self
+    }
+
+    /// 按标签查找出站连接器
+    pub fn dialer(&self, tag: &str) -> Option<Arc<dyn Dialer>> {
+        self.dialers.get(tag).cloned()
+    }
+
+    /// 记录出站连接结果，更新相关负载均衡器的健康状态
+    pub fn report_connect(&self, outbound_tag: &str, success: bool) {
+        for balancer in self.balancers.values() {
+            balancer.report(outbound_tag, success);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn outbound(protocol: &str, tag: &str) -> Outbound {
+        Outbound {
+            protocol: protocol.to_string(),
+            tag: tag.to_string(),
+            settings: None,
+            stream_settings: None,
+        }
+    }
+
+    fn rule(domain: Option<&[&str]>, ip: Option<&[&str]>, port: Option<&str>, tag: &str) -> RoutingRule {
+        RoutingRule {
+            rule_type: "field".to_string(),
+            domain: domain.map(|d| d.iter().map(|s| s.to_string()).collect()),
+            ip: ip.map(|i| i.iter().map(|s| s.to_string()).collect()),
+            port: port.map(|p| p.parse().unwrap()),
+            source: None,
+            user: None,
+            outbound_tag: tag.to_string(),
+            balancer_tag: None,
+            tee: None,
+        }
+    }
+
+    fn router(rules: Vec<RoutingRule>) -> Router {
+        let outbounds = vec![
+            outbound("freedom", "direct"),
+            outbound("blackhole", "block"),
+            outbound("freedom", "dns-out"),
+            outbound("freedom", "special"),
+        ];
+        Router::new(
+            &RoutingConfig {
+                rules,
+                balancers: vec![],
+            },
+            &outbounds,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_default_outbound() {
+        let router = router(vec![]);
+        let addr = Address::Domain("example.com".to_string(), 443);
+        assert_eq!(router.route(&RouteContext::new(&addr)), "direct");
+    }
+
+    #[test]
+    fn test_port_rules() {
+        let router = router(vec![
+            rule(None, None, Some("25"), "block"),
+            rule(None, None, Some("53"), "dns-out"),
+        ]);
+
+        let smtp = Address::Ipv4(Ipv4Addr::new(1, 2, 3, 4), 25);
+        let dns = Address::Ipv4(Ipv4Addr::new(8, 8, 8, 8), 53);
+        let https = Address::Domain("example.com".to_string(), 443);
+
+        assert_eq!(router.route(&RouteContext::new(&smtp)), "block");
+        assert_eq!(router.route(&RouteContext::new(&dns)), "dns-out");
+        assert_eq!(router.route(&RouteContext::new(&https)), "direct");
+        assert_eq!(router.outbound("block").unwrap().protocol, "blackhole");
+    }
+
+    #[test]
+    fn test_port_and_domain_are_anded() {
+        let router = router(vec![rule(
+            Some(&["domain:example.com"]),
+            None,
+            Some("80,8000-9000"),
+            "special",
+        )]);
+
+        let both = Address::Domain("www.example.com".to_string(), 8080);
+        let domain_only = Address::Domain("www.example.com".to_string(), 443);
+        let port_only = Address::Domain("other.org".to_string(), 80);
+        let ip_target = Address::Ipv4(Ipv4Addr::new(1, 1, 1, 1), 80);
+
+        assert_eq!(router.route(&RouteContext::new(&both)), "special");
+        assert_eq!(router.route(&RouteContext::new(&domain_only)), "direct");
+        assert_eq!(router.route(&RouteContext::new(&port_only)), "direct");
+        assert_eq!(router.route(&RouteContext::new(&ip_target)), "direct");
+    }
+
+    #[test]
+    fn test_sniffed_domain_rules() {
+        let router = router(vec![
+            rule(Some(&["domain:example.com"]), None, None, "special"),
+            rule(None, Some(&["192.0.2.0/24"]), None, "block"),
+        ]);
+        let ip_target = Address::Ipv4(Ipv4Addr::new(203, 0, 113, 1), 443);
+        let ctx = RouteContext::new(&ip_target).with_sniffed_domain(Some("www.example.com"));
+        assert_eq!(router.route(&ctx), "special");
+        assert_eq!(router.route(&RouteContext::new(&ip_target)), "direct");
+
+        // IP 规则仍按目标地址匹配
+        let blocked = Address::Ipv4(Ipv4Addr::new(192, 0, 2, 1), 443);
+        let ctx = RouteContext::new(&blocked).with_sniffed_domain(Some("other.org"));
+        assert_eq!(router.route(&ctx), "block");
+    }
+
+    #[test]
+    fn test_ip_and_port_rules() {
+        let router = router(vec![rule(None, Some(&["10.0.0.0/8"]), Some("1000-2000"), "block")]);
+
+        let hit = Address::Ipv4(Ipv4Addr::new(10, 1, 2, 3), 1500);
+        let wrong_port = Address::Ipv4(Ipv4Addr::new(10, 1, 2, 3), 443);
+        let wrong_ip = Address::Ipv4(Ipv4Addr::new(11, 1, 2, 3), 1500);
+
+        assert_eq!(router.route(&RouteContext::new(&hit)), "block");
+        assert_eq!(router.route(&RouteContext::new(&wrong_port)), "direct");
+        assert_eq!(router.route(&RouteContext::new(&wrong_ip)), "direct");
+    }
+
+    #[test]
+    fn test_source_rules() {
+        let mut office_a = rule(None, None, None, "special");
+        office_a.source = Some(vec!["10.1.0.0/16".to_string()]);
+        let mut office_b = rule(None, None, Some("443"), "block");
+        office_b.source = Some(vec!["192.168.7.7".to_string(), "fd00::/8".to_string()]);
+        let router = router(vec![office_a, office_b]);
+
+        let target = Address::Domain("example.com".to_string(), 443);
+        let ctx = |src: &str| RouteContext::new(&target).with_source(src.parse().unwrap());
+
+        assert_eq!(router.route(&ctx("10.1.2.3:50000")), "special");
+        assert_eq!(router.route(&ctx("192.168.7.7:1234")), "block");
+        assert_eq!(router.route(&ctx("[fd00::1]:1234")), "block");
+        // IPv4 映射的 IPv6 地址按 IPv4 匹配
+        assert_eq!(router.route(&ctx("[::ffff:10.1.9.9]:1234")), "special");
+        assert_eq!(router.route(&ctx("172.16.0.1:1234")), "direct");
+        // 来源未知时不命中 source 规则
+        assert_eq!(router.route(&RouteContext::new(&target)), "direct");
+    }
+
+    #[test]
+    fn test_user_rules() {
+        let mut vip = rule(None, None, None, "special");
+        vip.user = Some(vec!["alice@example.com".to_string(), "bob@example.com".to_string()]);
+        let router = router(vec![vip, rule(None, None, Some("443"), "block")]);
+
+        let target = Address::Domain("example.com".to_string(), 443);
+        let ctx = |user| RouteContext::new(&target).with_user(user);
+
+        assert_eq!(router.route(&ctx(Some("alice@example.com"))), "special");
+        assert_eq!(router.route(&ctx(Some("bob@example.com"))), "special");
+        assert_eq!(router.route(&ctx(Some("carol@example.com"))), "block");
+        // 无邮箱的客户端只能命中不含 user 条件的规则
+        assert_eq!(router.route(&ctx(None)), "block");
+    }
+
+    #[test]
+    fn test_balancer_rule() {
+        use crate::config::{BalancerConfig, BalancerStrategy};
+
+        let outbounds = vec![
+            outbound("freedom", "direct"),
+            outbound("freedom", "relay-a"),
+            outbound("freedom", "relay-b"),
+        ];
+        let mut balanced = rule(None, None, Some("443"), "");
+        balanced.balancer_tag = Some("relays".to_string());
+        let routing = RoutingConfig {
+            rules: vec![balanced],
+            balancers: vec![BalancerConfig {
+                tag: "relays".to_string(),
+                selector: vec!["relay-".to_string()],
+                strategy: BalancerStrategy::default(),
+                max_failures: 1,
+                cooldown: 60,
+            }],
+        };
+        let router = Router::new(&routing, &outbounds).unwrap();
+
+        let https = Address::Domain("example.com".to_string(), 443);
+        let http = Address::Domain("example.com".to_string(), 80);
+        assert_eq!(router.route(&RouteContext::new(&https)), "relay-a");
+        assert_eq!(router.route(&RouteContext::new(&https)), "relay-b");
+        assert_eq!(router.route(&RouteContext::new(&http)), "direct");
+
+        // 连接失败的出站进入冷却
+        router.report_connect("relay-a", false);
+        assert_eq!(router.route(&RouteContext::new(&https)), "relay-b");
+        assert_eq!(router.route(&RouteContext::new(&https)), "relay-b");
+
+        let mut missing = routing.clone();
+        missing.rules[0].balancer_tag = Some("nope".to_string());
+        assert!(Router::new(&missing, &outbounds).is_err());
+    }
+
+    #[test]
+    fn test_tee_is_per_rule() {
+        let mut mirrored = rule(Some(&["domain:lab.test"]), None, None, "direct");
+        mirrored.tee = Some(TeeConfig {
+            uplink: Some("/tmp/uplink.bin".to_string()),
+            downlink: None,
+        });
+        let router = router(vec![mirrored]);
+
+        let lab = Address::Domain("www.lab.test".to_string(), 443);
+        let other = Address::Domain("example.com".to_string(), 443);
+
+        let decision = router.decide(&RouteContext::new(&lab));
+        assert_eq!(decision.outbound_tag, "direct");
+        assert!(decision.tee.is_some());
+        assert!(router.decide(&RouteContext::new(&other)).tee.is_none());
+    }
+
+    #[test]
+    fn test_domain_matchers() {
+        let suffix = DomainMatcher::parse("domain:example.com").unwrap();
+        assert!(suffix.matches("example.com"));
+        assert!(suffix.matches("a.example.com"));
+        assert!(!suffix.matches("badexample.com"));
+
+        let full = DomainMatcher::parse("full:example.com").unwrap();
+        assert!(full.matches("example.com"));
+        assert!(!full.matches("a.example.com"));
+
+        let keyword = DomainMatcher::parse("google").unwrap();
+        assert!(keyword.matches("www.google.com"));
+
+        assert!(DomainMatcher::parse("geosite:cn").is_none());
+    }
+
+    #[test]
+    fn test_unsupported_domain_rules_are_skipped() {
+        let router = router(vec![
+            rule(Some(&["geosite:cn", "regexp:^ads\\."]), None, None, "block"),
+            rule(Some(&["ext:custom.dat:tag", "domain:example.com"]), None, None, "special"),
+        ]);
+        // 只有不支持的规则时该规则不会命中，其余规则照常生效
+        let ads = Address::Domain("ads.example.org".to_string(), 443);
+        let example = Address::Domain("www.example.com".to_string(), 443);
+        assert_eq!(router.route(&RouteContext::new(&ads)), "direct");
+        assert_eq!(router.route(&RouteContext::new(&example)), "special");
+    }
+
+    #[test]
+    fn test_cidr() {
+        let v4 = IpCidr::parse("192.168.0.0/16").unwrap();
+        assert!(v4.contains(&"192.168.5.5".parse().unwrap()));
+        assert!(!v4.contains(&"192.169.0.1".parse().unwrap()));
+
+        let v6 = IpCidr::parse("fd00::/8").unwrap();
+        assert!(v6.contains(&"fd12::1".parse().unwrap()));
+        assert!(!v6.contains(&"192.168.5.5".parse().unwrap()));
+
+        let any = IpCidr::parse("0.0.0.0/0").unwrap();
+        assert!(any.contains(&"8.8.8.8".parse().unwrap()));
+
+        assert!(IpCidr::parse("10.0.0.0/33").is_err());
+    }
+}
@@ -0,0 +1,361 @@
+//! 流量镜像 (tee)
+//!
+//! 把转发中的明文负载复制一份写入文件或 TCP 端点，供实验室环境做安全分析。
+//! 镜像只在路由规则显式配置 `tee` 时启用；打开目标与写入都在独立任务中进行，
+//! 镜像目标连不上、变慢或出错时直接停止镜像，不会阻塞或中断主转发。
+//!
+//! 每条连接单独连接 TCP 端点，端点收到的是该方向的原始字节流。文件由所有连接共用，
+//! 每个数据块前写入帧头 `[连接编号 u64][方向 u8][长度 u32]` (大端，方向 0 为上行、1 为下行)，
+//! 帧头与数据在同一次写入中追加，并发连接的数据不会交错。
+//!
+//! ⚠️ 镜像内容包含用户的完整流量，只应在取得授权的测试环境中使用。
+
+use anyhow::{anyhow, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::config::TeeConfig;
+
+/// 每个方向最多缓存的数据块数，超出后放弃镜像
+const TEE_CHANNEL_CAPACITY: usize = 1024;
+
+/// 连接 TCP 镜像端点的时限，超时后放弃该方向的镜像
+const TEE_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// 镜像的方向，写入文件帧头
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    /// 客户端 -> 目标
+    Uplink = 0,
+    /// 目标 -> 客户端
+    Downlink = 1,
+}
+
+/// 已打开的镜像目标
+#[derive(Debug, Default)]
+pub struct TeeSinks {
+    uplink: Option<mpsc::Sender<Bytes>>,
+    downlink: Option<mpsc::Sender<Bytes>>,
+}
+
+impl TeeSinks {
+    /// 按配置为编号为 `conn_id` 的连接打开镜像目标
+    ///
+    /// 目标在后台打开，不等待连接建立；打开失败时记录警告并停止该方向的镜像。
+    pub fn open(config: &TeeConfig, conn_id: u64) -> Self {
+        let spawn = |target: &Option<String>, direction| {
+            target.as_deref().map(|target| spawn_sink(target.to_string(), conn_id, direction))
+        };
+        Self {
+            uplink: spawn(&config.uplink, Direction::Uplink),
+            downlink: spawn(&config.downlink, Direction::Downlink),
+        }
+    }
+
+    /// 镜像未经过转发流的上行数据 (例如 VLESS 请求头后附带的首包)
+    pub fn mirror_uplink(&mut self, data: &[u8]) {
+        mirror(&mut self.uplink, data);
+    }
+}
+
+/// 镜像目标的写入端
+enum Sink {
+    /// 该连接独占的 TCP 端点，写入原始字节
+    Tcp(tokio::net::TcpStream),
+    /// 所有连接共用的文件，写入带帧头的数据块
+    File(tokio::fs::File),
+}
+
+impl Sink {
+    async fn open(target: &str) -> Result<Self> {
+        if let Some(addr) = target.strip_prefix("tcp://") {
+            let stream = tokio::time::timeout(TEE_CONNECT_TIMEOUT, tokio::net::TcpStream::connect(addr))
+                .await
+                .map_err(|_| anyhow!("连接镜像目标 {} 超时", target))?
+                .map_err(|e| anyhow!("无法连接镜像目标 {}: {}", target, e))?;
+            return Ok(Sink::Tcp(stream));
+        }
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(target)
+            .await
+            .map_err(|e| anyhow!("无法打开镜像文件 {}: {}", target, e))?;
+        Ok(Sink::File(file))
+    }
+
+    async fn write(&mut self, conn_id: u64, direction: Direction, chunk: &[u8]) -> io::Result<()> {
+        match self {
+            Sink::Tcp(stream) => stream.write_all(chunk).await,
+            Sink::File(file) => file.write_all(&frame(conn_id, direction, chunk)).await,
+        }
+    }
+
+    async fn close(self) {
+        match self {
+            Sink::Tcp(mut stream) => {
+                let _ = stream.shutdown().await;
+            }
+            Sink::File(mut file) => {
+                let _ = file.flush().await;
+            }
+        }
+    }
+}
+
+/// 文件镜像的一帧: 帧头 `[连接编号 u64][方向 u8][长度 u32]` 加数据
+fn frame(conn_id: u64, direction: Direction, chunk: &[u8]) -> BytesMut {
+    let mut frame = BytesMut::with_capacity(13 + chunk.len());
+    frame.put_u64(conn_id);
+    frame.put_u8(direction as u8);
+    frame.put_u32(chunk.len() as u32);
+    frame.put_slice(chunk);
+    frame
+}
+
+/// 启动镜像任务: 在后台打开目标，再依次写入通道中的数据
+///
+/// 打开失败时任务结束，通道随之关闭，之后的 [`mirror`] 停止该方向的镜像。
+fn spawn_sink(target: String, conn_id: u64, direction: Direction) -> mpsc::Sender<Bytes> {
+    let (tx, mut rx) = mpsc::channel::<Bytes>(TEE_CHANNEL_CAPACITY);
+
+    tokio::spawn(async move {
+        let mut sink = match Sink::open(&target).await {
+            Ok(sink) => sink,
+            Err(e) => {
+                warn!("流量镜像不可用: {}", e);
+                return;
+            }
+        };
+        info!("🪞 流量镜像已启用: {} (连接 #{})", target, conn_id);
+        while let Some(chunk) = rx.recv().await {
+            if let Err(e) = sink.write(conn_id, direction, &chunk).await {
+                warn!("写入镜像目标 {} 失败: {}", target, e);
+                return;
+            }
+        }
+        sink.close().await;
+    });
+
+    tx
+}
+
+/// 复制一份数据到镜像通道，失败时关闭该方向的镜像
+fn mirror(sink: &mut Option<mpsc::Sender<Bytes>>, data: &[u8]) {
+    if data.is_empty() {
+        return;
+    }
+    if let Some(tx) = sink {
+        if tx.try_send(Bytes::copy_from_slice(data)).is_err() {
+            warn!("镜像目标跟不上或已关闭，停止该方向的镜像");
+            *sink = None;
+        }
+    }
+}
+
+/// 在客户端流上做镜像的包装
+///
+/// 从客户端读到的数据为上行，写给客户端的数据为下行。
+pub(crate) struct TeeStream<S> {
+    inner: S,
+    sinks: TeeSinks,
+}
+
+impl<S> TeeStream<S> {
+    pub(crate) fn new(inner: S, sinks: Option<TeeSinks>) -> Self {
+        Self {
+            inner,
+            sinks: sinks.unwrap_or_default(),
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for TeeStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            mirror(&mut self.sinks.uplink, &buf.filled()[before..]);
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for TeeStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            mirror(&mut self.sinks.downlink, &buf[..n]);
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::connection::ProxyConnection;
+    use std::time::Duration;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn test_tee_receives_identical_copy() {
+        let uplink_path = std::env::temp_dir().join(format!("xray-lite-tee-{}.bin", uuid::Uuid::new_v4()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = TeeConfig {
+            uplink: Some(uplink_path.to_string_lossy().into_owned()),
+            downlink: Some(format!("tcp://{}", listener.local_addr().unwrap())),
+        };
+
+        let sinks = TeeSinks::open(&config, 7);
+        let (mut downlink_sink, _) = listener.accept().await.unwrap();
+
+        let (client, client_side) = tokio::io::duplex(4096);
+        let (remote_side, remote) = tokio::io::duplex(4096);
+        let relay = ProxyConnection::new(client_side, remote_side)
+            .with_tee(Some(sinks))
+            .relay();
+
+        let upload: Vec<u8> = (0..50_000u32).map(|i| (i % 251) as u8).collect();
+        let download: Vec<u8> = (0..30_000u32).map(|i| (i % 127) as u8).collect();
+
+        let (up, down) = (upload.clone(), download.clone());
+        let traffic = async move {
+            let (mut client_rx, mut client_tx) = tokio::io::split(client);
+            let (mut remote_rx, mut remote_tx) = tokio::io::split(remote);
+            let mut client_got = Vec::new();
+            let mut remote_got = Vec::new();
+            tokio::join!(
+                async {
+                    client_tx.write_all(&up).await.unwrap();
+                    client_tx.shutdown().await.unwrap();
+                },
+                async {
+                    remote_tx.write_all(&down).await.unwrap();
+                    remote_tx.shutdown().await.unwrap();
+                },
+                async { client_rx.read_to_end(&mut client_got).await.unwrap() },
+                async { remote_rx.read_to_end(&mut remote_got).await.unwrap() },
+            );
+            (client_got, remote_got)
+        };
+
+        let (result, (client_got, remote_got)) = tokio::join!(relay, traffic);
+        result.unwrap();
+        // 主转发不受影响
+        assert_eq!(remote_got, upload);
+        assert_eq!(client_got, download);
+
+        let mut mirrored_down = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), downlink_sink.read_to_end(&mut mirrored_down))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(mirrored_down, download);
+
+        // 文件写入在后台任务中完成，等待其落盘
+        let frames = read_frames(&uplink_path, upload.len()).await;
+        let _ = std::fs::remove_file(&uplink_path);
+        assert!(frames.iter().all(|(conn_id, direction, _)| *conn_id == 7 && *direction == 0));
+        assert_eq!(payload(&frames), upload);
+    }
+
+    /// 等待镜像文件中的数据达到 `expected` 字节，返回其中的各帧 (连接编号, 方向, 数据)
+    async fn read_frames(path: &std::path::Path, expected: usize) -> Vec<(u64, u8, Vec<u8>)> {
+        let mut frames = Vec::new();
+        for _ in 0..100 {
+            let data = tokio::fs::read(path).await.unwrap_or_default();
+            frames.clear();
+            let mut rest = &data[..];
+            while rest.len() >= 13 {
+                let conn_id = u64::from_be_bytes(rest[..8].try_into().unwrap());
+                let len = u32::from_be_bytes(rest[9..13].try_into().unwrap()) as usize;
+                if rest.len() < 13 + len {
+                    break;
+                }
+                frames.push((conn_id, rest[8], rest[13..13 + len].to_vec()));
+                rest = &rest[13 + len..];
+            }
+            if frames.iter().map(|(_, _, payload)| payload.len()).sum::<usize>() >= expected {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        frames
+    }
+
+    /// 依次拼接各帧的数据
+    fn payload<'a>(frames: impl IntoIterator<Item = &'a (u64, u8, Vec<u8>)>) -> Vec<u8> {
+        frames.into_iter().flat_map(|(_, _, payload)| payload.iter().copied()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_shared_file_frames_per_connection() {
+        let path = std::env::temp_dir().join(format!("xray-lite-tee-{}.bin", uuid::Uuid::new_v4()));
+        let target = path.to_string_lossy().into_owned();
+        let config = TeeConfig { uplink: Some(target.clone()), downlink: Some(target) };
+
+        // 两条连接交替写入同一文件
+        let mut first = TeeSinks::open(&config, 1);
+        let mut second = TeeSinks::open(&config, 2);
+        for i in 0..50u8 {
+            first.mirror_uplink(&[b'a', i]);
+            second.mirror_uplink(&[b'b', i]);
+            mirror(&mut second.downlink, &[b'c', i]);
+        }
+        drop((first, second));
+
+        let frames = read_frames(&path, 300).await;
+        let _ = std::fs::remove_file(&path);
+        let select = |conn_id: u64, direction: u8| payload(frames.iter().filter(|f| f.0 == conn_id && f.1 == direction));
+        let expect = |tag: u8| -> Vec<u8> { (0..50u8).flat_map(|i| [tag, i]).collect() };
+        assert_eq!(select(1, 0), expect(b'a'));
+        assert_eq!(select(2, 0), expect(b'b'));
+        assert_eq!(select(2, 1), expect(b'c'));
+        assert!(select(1, 1).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_sink_is_dropped() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let config = TeeConfig {
+            uplink: Some(format!("tcp://{}", addr)),
+            downlink: None,
+        };
+        // 打开不等待连接，连接失败后该方向的镜像停止
+        let mut sinks = TeeSinks::open(&config, 1);
+        let closed = async {
+            while !sinks.uplink.as_ref().unwrap().is_closed() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), closed).await.unwrap();
+        sinks.mirror_uplink(b"data");
+        assert!(sinks.uplink.is_none());
+    }
+}
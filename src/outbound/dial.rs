@@ -0,0 +1,364 @@
+//! 出站 socket 创建
+//!
+//! 通过 socket2 先 `bind()` 到 `sendThrough` 指定的本地地址再发起连接，
+//! 用于多 IP 服务器固定出口地址；TCP 连接带总超时，并依次尝试目标的所有地址。
+
+use anyhow::{anyhow, Result};
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::{TcpSocket, TcpStream, UdpSocket};
+use tokio::time::Instant;
+use tracing::{error, warn};
+
+use crate::config::SockOpt;
+
+/// 解析目标地址，存在 `send_through` 时只保留与其地址族一致的结果
+pub async fn resolve_all(target: &str, send_through: Option<IpAddr>) -> Result<Vec<SocketAddr>> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host(target)
+        .await
+        .map_err(|e| anyhow!("DNS 解析失败 {}: {}", target, e))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(anyhow!("无法解析目标地址: {}", target));
+    }
+
+    match send_through {
+        None => Ok(addrs),
+        Some(local) => {
+            let matched: Vec<SocketAddr> = addrs
+                .iter()
+                .copied()
+                .filter(|addr| addr.is_ipv4() == local.is_ipv4())
+                .collect();
+            if matched.is_empty() {
+                return Err(anyhow!(
+                    "sendThrough {} 与目标 {} 的地址族不一致 (解析结果: {:?})",
+                    local,
+                    target,
+                    addrs
+                ));
+            }
+            Ok(matched)
+        }
+    }
+}
+
+/// 解析目标地址，返回第一个可用结果
+pub async fn resolve(target: &str, send_through: Option<IpAddr>) -> Result<SocketAddr> {
+    Ok(resolve_all(target, send_through).await?[0])
+}
+
+/// 创建绑定到本地地址的 socket
+fn bound_socket(target: SocketAddr, send_through: Option<IpAddr>, ty: Type, protocol: Protocol) -> Result<Socket> {
+    let local = match send_through {
+        Some(ip) if ip.is_ipv4() != target.is_ipv4() => {
+            return Err(anyhow!("sendThrough {} 与目标 {} 的地址族不一致", ip, target));
+        }
+        Some(ip) => Some(ip),
+        // UDP 需要显式绑定到与目标同族的任意地址
+        None if ty == Type::DGRAM => Some(if target.is_ipv4() {
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+        } else {
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+        }),
+        None => None,
+    };
+
+    let socket = Socket::new(Domain::for_address(target), ty, Some(protocol))?;
+    if let Some(ip) = local {
+        socket
+            .bind(&SocketAddr::new(ip, 0).into())
+            .map_err(|e| anyhow!("无法绑定本地地址 {}: {}", ip, e))?;
+    }
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+/// 按 sockopt 设置入站接受的连接 (客户端一侧)
+pub fn apply_sockopt(stream: &TcpStream, sockopt: &SockOpt) {
+    apply_tcp_options(stream, sockopt.client_no_delay(), sockopt);
+}
+
+/// 按 sockopt 设置出站 TCP 连接 (远端一侧)，并设置 TOS / TTL
+pub fn apply_outbound_sockopt(stream: &TcpStream, sockopt: &SockOpt) {
+    apply_tcp_options(stream, sockopt.remote_no_delay(), sockopt);
+    let ipv6 = stream.peer_addr().map(|addr| addr.is_ipv6()).unwrap_or(false);
+    apply_ip_marks(SockRef::from(stream), ipv6, sockopt);
+}
+
+/// 两侧共用的 TCP 选项: TCP_NODELAY 与 SO_LINGER
+fn apply_tcp_options(stream: &TcpStream, no_delay: bool, sockopt: &SockOpt) {
+    if let Err(e) = stream.set_nodelay(no_delay) {
+        error!("设置 TCP_NODELAY 失败: {}", e);
+    }
+    // 只设置 0 (关闭时发送 RST)，非零值会让 close() 阻塞工作线程，校验时已被拒绝
+    if sockopt.tcp_linger == Some(0) {
+        if let Err(e) = SockRef::from(stream).set_linger(Some(Duration::ZERO)) {
+            error!("设置 SO_LINGER 失败: {}", e);
+        }
+    }
+}
+
+/// 设置 IP 层的 TOS 与 TTL，平台不支持时只记录警告
+fn apply_ip_marks(socket: SockRef<'_>, ipv6: bool, sockopt: &SockOpt) {
+    if let Some(tos) = sockopt.tos {
+        let result = if ipv6 { set_tclass_v6(&socket, tos) } else { socket.set_tos(tos) };
+        if let Err(e) = result {
+            warn!("设置 TOS {} 失败: {}", tos, e);
+        }
+    }
+    if let Some(ttl) = sockopt.ttl {
+        let result = if ipv6 { socket.set_unicast_hops_v6(ttl) } else { socket.set_ttl(ttl) };
+        if let Err(e) = result {
+            warn!("设置 TTL {} 失败: {}", ttl, e);
+        }
+    }
+}
+
+#[cfg(any(
+    target_os = "android",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "fuchsia",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "netbsd",
+    target_os = "openbsd",
+))]
+fn set_tclass_v6(socket: &SockRef<'_>, tclass: u32) -> std::io::Result<()> {
+    socket.set_tclass_v6(tclass)
+}
+
+#[cfg(not(any(
+    target_os = "android",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "fuchsia",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "netbsd",
+    target_os = "openbsd",
+)))]
+fn set_tclass_v6(_socket: &SockRef<'_>, _tclass: u32) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "当前平台不支持 IPV6_TCLASS"))
+}
+
+/// 建立 TCP 连接 (可选绑定本地地址)
+pub async fn connect_tcp(target: SocketAddr, send_through: Option<IpAddr>) -> Result<TcpStream> {
+    let socket = bound_socket(target, send_through, Type::STREAM, Protocol::TCP)?;
+    let socket = TcpSocket::from_std_stream(socket.into());
+    Ok(socket.connect(target).await?)
+}
+
+/// 依次尝试目标解析出的每个地址，直到有一个在总时限内连接成功
+///
+/// DNS 解析与连接共用同一个时限，解析缓慢时留给连接的时间相应减少。
+/// 每次尝试分到剩余时间的均分份额，避免一个不可达地址耗尽全部时间。
+/// 全部失败时错误信息列出每个地址及其失败原因。
+pub async fn connect_any(target: &str, send_through: Option<IpAddr>, budget: Duration) -> Result<TcpStream> {
+    let deadline = Instant::now() + budget;
+    let addrs = tokio::time::timeout_at(deadline, resolve_all(target, send_through))
+        .await
+        .map_err(|_| anyhow!("解析目标 {} 超时 ({}ms)", target, budget.as_millis()))??;
+    connect_addrs(target, &addrs, send_through, deadline).await
+}
+
+async fn connect_addrs(
+    target: &str,
+    addrs: &[SocketAddr],
+    send_through: Option<IpAddr>,
+    deadline: Instant,
+) -> Result<TcpStream> {
+    let mut failures = Vec::with_capacity(addrs.len());
+
+    for (idx, addr) in addrs.iter().enumerate() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            failures.push(format!("{} (未尝试: 已超时)", addr));
+            continue;
+        }
+        let share = remaining / (addrs.len() - idx) as u32;
+        match tokio::time::timeout(share, connect_tcp(*addr, send_through)).await {
+            Ok(Ok(stream)) => return Ok(stream),
+            Ok(Err(e)) => failures.push(format!("{} ({})", addr, e)),
+            Err(_) => failures.push(format!("{} (连接超时 {}ms)", addr, share.as_millis())),
+        }
+    }
+
+    Err(anyhow!("无法连接到目标 {}: {}", target, failures.join(", ")))
+}
+
+/// 创建用于向 `target` 发送数据报的 UDP socket (可选绑定本地地址，按 sockopt 设置 TOS / TTL)
+pub fn bind_udp(target: SocketAddr, send_through: Option<IpAddr>, sockopt: &SockOpt) -> Result<UdpSocket> {
+    let socket = bound_socket(target, send_through, Type::DGRAM, Protocol::UDP)?;
+    apply_ip_marks(SockRef::from(&socket), target.is_ipv6(), sockopt);
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+/// 监听队列已满的端口: 新连接的 SYN 会被丢弃，connect 一直挂起 (测试不可达的上游)
+#[cfg(test)]
+pub(crate) fn saturated_listener() -> (Socket, Vec<std::net::TcpStream>, SocketAddr) {
+    let socket = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
+    socket.bind(&"127.0.0.1:0".parse::<SocketAddr>().unwrap().into()).unwrap();
+    socket.listen(0).unwrap();
+    let addr = socket.local_addr().unwrap().as_socket().unwrap();
+    let mut fillers = Vec::new();
+    while let Ok(stream) = std::net::TcpStream::connect_timeout(&addr, Duration::from_millis(100)) {
+        fillers.push(stream);
+    }
+    (socket, fillers, addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    const LOOPBACK_ALT: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2));
+
+    #[tokio::test]
+    async fn test_tcp_send_through() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap();
+
+        let _stream = connect_tcp(target, Some(LOOPBACK_ALT)).await.unwrap();
+        let (_, peer) = listener.accept().await.unwrap();
+        assert_eq!(peer.ip(), LOOPBACK_ALT);
+    }
+
+    #[tokio::test]
+    async fn test_udp_send_through() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let target = receiver.local_addr().unwrap();
+
+        let socket = bind_udp(target, Some(LOOPBACK_ALT), &SockOpt::default()).unwrap();
+        socket.send_to(b"ping", target).await.unwrap();
+        let mut buf = [0u8; 4];
+        let (_, from) = receiver.recv_from(&mut buf).await.unwrap();
+        assert_eq!(from.ip(), LOOPBACK_ALT);
+    }
+
+    #[tokio::test]
+    async fn test_apply_linger() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap();
+        let stream = TcpStream::connect(target).await.unwrap();
+        let (accepted, _) = listener.accept().await.unwrap();
+
+        // 未配置时保持系统默认
+        apply_sockopt(&stream, &SockOpt::default());
+        assert_eq!(SockRef::from(&stream).linger().unwrap(), None);
+
+        let abort = SockOpt {
+            tcp_linger: Some(0),
+            ..SockOpt::default()
+        };
+        apply_sockopt(&stream, &abort);
+        assert_eq!(SockRef::from(&stream).linger().unwrap(), Some(Duration::ZERO));
+        assert!(stream.nodelay().unwrap());
+
+        // 非零值会阻塞 close()，即使绕过校验也不会设置
+        let blocking = SockOpt {
+            tcp_linger: Some(5),
+            tcp_no_delay: false,
+            ..SockOpt::default()
+        };
+        apply_sockopt(&accepted, &blocking);
+        assert_eq!(SockRef::from(&accepted).linger().unwrap(), None);
+        assert!(!accepted.nodelay().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_client_and_remote_no_delay() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let remote = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (client, _) = listener.accept().await.unwrap();
+
+        // 客户端一侧开启，远端一侧关闭
+        let split = SockOpt {
+            tcp_no_delay: false,
+            client_tcp_no_delay: Some(true),
+            remote_tcp_no_delay: Some(false),
+            ..SockOpt::default()
+        };
+        apply_sockopt(&client, &split);
+        apply_outbound_sockopt(&remote, &split);
+        assert!(client.nodelay().unwrap());
+        assert!(!remote.nodelay().unwrap());
+
+        // 反过来配置，同一对连接上的值随之改变
+        let reversed = SockOpt {
+            tcp_no_delay: true,
+            client_tcp_no_delay: Some(false),
+            remote_tcp_no_delay: None,
+            ..SockOpt::default()
+        };
+        apply_sockopt(&client, &reversed);
+        apply_outbound_sockopt(&remote, &reversed);
+        assert!(!client.nodelay().unwrap());
+        assert!(remote.nodelay().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_apply_tos_and_ttl() {
+        let marks = SockOpt {
+            tos: Some(0xb8),
+            ttl: Some(42),
+            ..SockOpt::default()
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        apply_outbound_sockopt(&stream, &marks);
+        assert_eq!(stream.ttl().unwrap(), 42);
+        assert_eq!(SockRef::from(&stream).tos().unwrap(), 0xb8);
+
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = bind_udp(receiver.local_addr().unwrap(), None, &marks).unwrap();
+        assert_eq!(socket.ttl().unwrap(), 42);
+        assert_eq!(SockRef::from(&socket).tos().unwrap(), 0xb8);
+    }
+
+    #[tokio::test]
+    async fn test_send_through_family_mismatch() {
+        let v6 = Some(IpAddr::V6(Ipv6Addr::LOCALHOST));
+        let target: SocketAddr = "127.0.0.1:9".parse().unwrap();
+
+        let err = connect_tcp(target, v6).await.unwrap_err().to_string();
+        assert!(err.contains("地址族不一致"), "{}", err);
+        let err = bind_udp(target, v6, &SockOpt::default()).unwrap_err().to_string();
+        assert!(err.contains("地址族不一致"), "{}", err);
+        let err = resolve("127.0.0.1:9", v6).await.unwrap_err().to_string();
+        assert!(err.contains("地址族不一致"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_connect_falls_through_to_next_address() {
+        let (_listener, _fillers, dead) = saturated_listener();
+        let refused = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let live = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let live_addr = live.local_addr().unwrap();
+
+        let stream = connect_addrs("example.test:443", &[dead, refused, live_addr], None, Instant::now() + Duration::from_secs(3))
+            .await
+            .unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), live_addr);
+    }
+
+    #[tokio::test]
+    async fn test_connect_timeout_lists_every_attempt() {
+        let (_listener, _fillers, dead) = saturated_listener();
+        let refused = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+
+        let started = std::time::Instant::now();
+        let err = connect_addrs("example.test:443", &[dead, refused], None, Instant::now() + Duration::from_millis(400))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(err.contains("example.test:443"), "{}", err);
+        assert!(err.contains(&format!("{} (连接超时", dead)), "{}", err);
+        assert!(err.contains(&refused.to_string()), "{}", err);
+    }
+}
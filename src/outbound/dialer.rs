@@ -0,0 +1,111 @@
+//! 出站连接器抽象
+//!
+//! 会话处理只依赖 [`Dialer`]，由路由按出站标签选出具体实现；
+//! 各协议出站与测试用的 [`MockDialer`] 都通过它接入。
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use std::fmt;
+use std::time::Duration;
+use tracing::info;
+
+use super::{dial, FreedomSettings, UdpSession};
+use crate::config::SockOpt;
+use crate::protocol::vless::Address;
+use crate::server::AsyncStream;
+
+/// 建立到目标的 TCP 连接或 UDP 会话
+pub trait Dialer: fmt::Debug + Send + Sync {
+    /// 建立到目标的 TCP 连接
+    fn connect_tcp<'a>(&'a self, target: &'a Address, sockopt: &'a SockOpt) -> BoxFuture<'a, Result<Box<dyn AsyncStream>>>;
+
+    /// 建立到目标的 UDP 会话
+    fn connect_udp<'a>(&'a self, target: &'a Address, sockopt: &'a SockOpt) -> BoxFuture<'a, Result<UdpSession>>;
+
+    /// 是否直接丢弃连接 (blackhole)
+    fn is_blackhole(&self) -> bool {
+        false
+    }
+}
+
+/// 直连 (freedom)
+#[derive(Debug, Clone, Default)]
+pub struct DirectDialer {
+    settings: FreedomSettings,
+}
+
+impl DirectDialer {
+    pub fn new(settings: FreedomSettings) -> Self {
+        Self { settings }
+    }
+}
+
+impl Dialer for DirectDialer {
+    fn connect_tcp<'a>(&'a self, target: &'a Address, sockopt: &'a SockOpt) -> BoxFuture<'a, Result<Box<dyn AsyncStream>>> {
+        Box::pin(async move {
+            let budget = Duration::from_secs(self.settings.connect_timeout);
+            let stream = dial::connect_any(&target.to_string(), self.settings.send_through, budget).await?;
+            dial::apply_outbound_sockopt(&stream, sockopt);
+            Ok(Box::new(stream) as Box<dyn AsyncStream>)
+        })
+    }
+
+    fn connect_udp<'a>(&'a self, target: &'a Address, sockopt: &'a SockOpt) -> BoxFuture<'a, Result<UdpSession>> {
+        Box::pin(async move {
+            let resolved = dial::resolve(&target.to_string(), self.settings.send_through).await?;
+            info!("🔗 UDP 初始目标: {}", resolved);
+
+            // 创建 UDP socket (Full Cone NAT)
+            let socket = dial::bind_udp(resolved, self.settings.send_through, sockopt)?;
+            Ok(UdpSession::Direct {
+                socket,
+                target: resolved,
+            })
+        })
+    }
+}
+
+/// 测试用连接器: 记录请求的目标地址，实际连接统一转到固定的本地地址
+#[cfg(test)]
+#[derive(Debug)]
+pub struct MockDialer {
+    redirect: std::net::SocketAddr,
+    dialed: std::sync::Mutex<Vec<Address>>,
+}
+
+#[cfg(test)]
+impl MockDialer {
+    pub fn new(redirect: std::net::SocketAddr) -> Self {
+        Self {
+            redirect,
+            dialed: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    /// 目前为止请求过的目标地址 (按顺序)
+    pub fn dialed(&self) -> Vec<Address> {
+        self.dialed.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl Dialer for MockDialer {
+    fn connect_tcp<'a>(&'a self, target: &'a Address, _sockopt: &'a SockOpt) -> BoxFuture<'a, Result<Box<dyn AsyncStream>>> {
+        self.dialed.lock().unwrap().push(target.clone());
+        Box::pin(async move {
+            let stream = tokio::net::TcpStream::connect(self.redirect).await?;
+            Ok(Box::new(stream) as Box<dyn AsyncStream>)
+        })
+    }
+
+    fn connect_udp<'a>(&'a self, target: &'a Address, sockopt: &'a SockOpt) -> BoxFuture<'a, Result<UdpSession>> {
+        self.dialed.lock().unwrap().push(target.clone());
+        Box::pin(async move {
+            let socket = dial::bind_udp(self.redirect, None, sockopt)?;
+            Ok(UdpSession::Direct {
+                socket,
+                target: self.redirect,
+            })
+        })
+    }
+}
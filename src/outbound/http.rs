@@ -0,0 +1,202 @@
+//! HTTP 代理出站 (仅 CONNECT)
+//!
+//! 发送 `CONNECT host:port HTTP/1.1`，收到 200 后把原始 TCP 流交给转发逻辑。
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::debug;
+
+use super::dial;
+use crate::protocol::vless::Address;
+
+/// 响应头最大长度
+const MAX_RESPONSE_HEADER: usize = 8192;
+
+/// HTTP 代理出站配置 (`Outbound.settings`)
+#[derive(Debug, Clone, Deserialize)]
+pub struct HttpSettings {
+    /// 上游 HTTP 代理地址
+    pub address: String,
+    /// 上游 HTTP 代理端口
+    pub port: u16,
+    /// 用户名 (可选，Basic 认证)
+    #[serde(default, alias = "username")]
+    pub user: Option<String>,
+    /// 密码 (可选)
+    #[serde(default, alias = "password")]
+    pub pass: Option<String>,
+    /// 连接上游代理的超时 (秒)，代理地址解析出多个地址时为全部尝试的总时长
+    #[serde(rename = "connectTimeout", default = "super::default_connect_timeout")]
+    pub connect_timeout: u64,
+}
+
+/// HTTP 代理出站
+#[derive(Debug, Clone)]
+pub struct HttpOutbound {
+    settings: HttpSettings,
+}
+
+impl HttpOutbound {
+    pub fn new(settings: HttpSettings) -> Self {
+        Self { settings }
+    }
+
+    /// 上游代理地址
+    fn upstream(&self) -> String {
+        Address::from_host(&self.settings.address, self.settings.port).to_string()
+    }
+
+    /// 构造 CONNECT 请求
+    fn connect_request(&self, target: &Address) -> String {
+        let authority = target.to_string();
+        let mut request = format!(
+            "CONNECT {0} HTTP/1.1\r\nHost: {0}\r\nProxy-Connection: Keep-Alive\r\n",
+            authority
+        );
+        if let Some(user) = &self.settings.user {
+            let pass = self.settings.pass.as_deref().unwrap_or("");
+            let credentials = STANDARD.encode(format!("{}:{}", user, pass));
+            request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", credentials));
+        }
+        request.push_str("\r\n");
+        request
+    }
+
+    /// 通过 CONNECT 建立到目标的 TCP 隧道
+    pub async fn connect(&self, target: &Address) -> Result<TcpStream> {
+        let upstream = self.upstream();
+        let budget = Duration::from_secs(self.settings.connect_timeout);
+        let mut stream = dial::connect_any(&upstream, None, budget)
+            .await
+            .map_err(|e| anyhow!("无法连接 HTTP 代理 {}: {}", upstream, e))?;
+
+        stream.write_all(self.connect_request(target).as_bytes()).await?;
+
+        let header = read_response_header(&mut stream)
+            .await
+            .map_err(|e| anyhow!("HTTP 代理 {} 响应无效: {}", upstream, e))?;
+        let status_line = header.lines().next().unwrap_or_default();
+        let status = status_line.split_whitespace().nth(1);
+        if !status_line.starts_with("HTTP/1.") || status != Some("200") {
+            return Err(anyhow!(
+                "HTTP 代理 {} 拒绝 CONNECT {}: {}",
+                upstream,
+                target.to_string(),
+                status_line
+            ));
+        }
+
+        debug!("HTTP CONNECT {} via {} 成功", target.to_string(), upstream);
+        Ok(stream)
+    }
+}
+
+/// 逐字节读取响应头直到空行，避免多读隧道中的数据
+async fn read_response_header(stream: &mut TcpStream) -> Result<String> {
+    let mut header = Vec::with_capacity(256);
+    while !header.ends_with(b"\r\n\r\n") {
+        if header.len() >= MAX_RESPONSE_HEADER {
+            return Err(anyhow!("响应头超过 {} 字节", MAX_RESPONSE_HEADER));
+        }
+        let byte = stream.read_u8().await?;
+        header.push(byte);
+    }
+    Ok(String::from_utf8_lossy(&header).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    fn settings(addr: std::net::SocketAddr, user: Option<&str>) -> HttpSettings {
+        HttpSettings {
+            address: addr.ip().to_string(),
+            port: addr.port(),
+            user: user.map(str::to_string),
+            pass: user.map(|_| "p@ss".to_string()),
+            connect_timeout: 8,
+        }
+    }
+
+    /// 模拟 HTTP 代理：校验请求头后返回 `status_line`，随后回显隧道数据
+    async fn mock_proxy(status_line: &'static str) -> (std::net::SocketAddr, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            while !request.ends_with(b"\r\n\r\n") {
+                request.push(stream.read_u8().await.unwrap());
+            }
+            stream
+                .write_all(format!("{}\r\nVia: mock\r\n\r\n", status_line).as_bytes())
+                .await
+                .unwrap();
+            let (mut r, mut w) = stream.split();
+            let _ = tokio::io::copy(&mut r, &mut w).await;
+            String::from_utf8(request).unwrap()
+        });
+        (addr, handle)
+    }
+
+    #[tokio::test]
+    async fn test_connect_with_basic_auth() {
+        let (addr, proxy) = mock_proxy("HTTP/1.1 200 Connection established").await;
+        let outbound = HttpOutbound::new(settings(addr, Some("alice")));
+
+        let target = Address::Domain("example.com".to_string(), 443);
+        let mut stream = outbound.connect(&target).await.unwrap();
+        stream.write_all(b"tunnel").await.unwrap();
+        let mut buf = [0u8; 6];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"tunnel");
+        drop(stream);
+
+        let request = proxy.await.unwrap();
+        assert!(request.starts_with("CONNECT example.com:443 HTTP/1.1\r\n"));
+        assert!(request.contains("\r\nHost: example.com:443\r\n"));
+        // base64("alice:p@ss")
+        assert!(request.contains("\r\nProxy-Authorization: Basic YWxpY2U6cEBzcw==\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_connect_without_auth() {
+        let (addr, proxy) = mock_proxy("HTTP/1.0 200 OK").await;
+        let outbound = HttpOutbound::new(settings(addr, None));
+
+        let target = Address::Ipv6(std::net::Ipv6Addr::LOCALHOST, 8443);
+        drop(outbound.connect(&target).await.unwrap());
+
+        let request = proxy.await.unwrap();
+        assert!(request.starts_with("CONNECT [::1]:8443 HTTP/1.1\r\n"));
+        assert!(!request.contains("Proxy-Authorization"));
+    }
+
+    #[tokio::test]
+    async fn test_non_200_includes_status_line() {
+        let (addr, _proxy) = mock_proxy("HTTP/1.1 407 Proxy Authentication Required").await;
+        let outbound = HttpOutbound::new(settings(addr, None));
+
+        let target = Address::Domain("example.com".to_string(), 443);
+        let err = outbound.connect(&target).await.unwrap_err().to_string();
+        assert!(err.contains("HTTP/1.1 407 Proxy Authentication Required"), "{}", err);
+        assert!(err.contains(&addr.to_string()), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_proxy_times_out() {
+        let (_listener, _fillers, addr) = dial::saturated_listener();
+        let outbound = HttpOutbound::new(HttpSettings { connect_timeout: 1, ..settings(addr, None) });
+
+        let started = std::time::Instant::now();
+        let target = Address::Domain("example.com".to_string(), 443);
+        let err = outbound.connect(&target).await.unwrap_err().to_string();
+        assert!(started.elapsed() < Duration::from_secs(3));
+        assert!(err.contains("连接超时"), "{}", err);
+    }
+}
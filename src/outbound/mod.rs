@@ -0,0 +1,339 @@
+//! 出站实现
+//!
+//! 启动时根据 `outbounds` 配置解析出各协议的设置，
+//! 连接时由路由选中的出站负责建立到目标的 TCP 连接或 UDP 会话。
+
+pub mod dial;
+pub mod dialer;
+pub mod http;
+pub mod shadowsocks;
+pub mod socks;
+pub mod trojan;
+pub mod vless;
+
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use serde::Deserialize;
+use std::net::{IpAddr, SocketAddr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::net::UdpSocket;
+use tokio::sync::Mutex;
+
+use crate::config::{Outbound, SockOpt};
+use crate::protocol::vless::Address;
+use crate::server::AsyncStream;
+
+#[cfg(test)]
+pub use dialer::MockDialer;
+pub use dialer::{Dialer, DirectDialer};
+pub use http::{HttpOutbound, HttpSettings};
+pub use shadowsocks::{ShadowsocksOutbound, ShadowsocksSettings, ShadowsocksStream};
+pub use socks::{SocksOutbound, SocksSettings, SocksUdpSession};
+pub use trojan::{TrojanOutbound, TrojanSettings, TrojanStream};
+pub use vless::{VlessOutbound, VlessSettings, VlessStream};
+
+/// 已解析的出站
+#[derive(Debug, Clone)]
+pub enum OutboundHandler {
+    /// 直连 (freedom)
+    Direct(DirectDialer),
+    /// 丢弃连接
+    Blackhole,
+    /// 经由 SOCKS5 代理
+    Socks(SocksOutbound),
+    /// 经由 HTTP 代理 (CONNECT)
+    Http(HttpOutbound),
+    /// 链式转发到上游 VLESS 节点
+    Vless(VlessOutbound),
+    /// 经由 Shadowsocks 服务器 (仅 TCP)
+    Shadowsocks(ShadowsocksOutbound),
+    /// 经由 Trojan 服务器 (仅 TCP)
+    Trojan(TrojanOutbound),
+}
+
+impl OutboundHandler {
+    /// 根据出站配置创建
+    pub fn from_config(outbound: &Outbound) -> Result<Self> {
+        match outbound.protocol.as_str() {
+            "freedom" | "direct" => {
+                let settings: FreedomSettings = match outbound.settings {
+                    Some(_) => parse_settings(outbound)?,
+                    None => FreedomSettings::default(),
+                };
+                check_connect_timeout(outbound, settings.connect_timeout)?;
+                Ok(OutboundHandler::Direct(DirectDialer::new(settings)))
+            }
+            "blackhole" => Ok(OutboundHandler::Blackhole),
+            "socks" => {
+                let settings: SocksSettings = parse_settings(outbound)?;
+                check_connect_timeout(outbound, settings.connect_timeout)?;
+                Ok(OutboundHandler::Socks(SocksOutbound::new(settings)))
+            }
+            "http" => {
+                let settings: HttpSettings = parse_settings(outbound)?;
+                check_connect_timeout(outbound, settings.connect_timeout)?;
+                Ok(OutboundHandler::Http(HttpOutbound::new(settings)))
+            }
+            "vless" => {
+                let settings: VlessSettings = parse_settings(outbound)?;
+                check_connect_timeout(outbound, settings.connect_timeout)?;
+                let vless = VlessOutbound::new(settings, outbound.stream_settings.as_ref())
+                    .map_err(|e| anyhow!("出站 {} 配置无效: {}", outbound.tag, e))?;
+                Ok(OutboundHandler::Vless(vless))
+            }
+            "shadowsocks" => {
+                let settings: ShadowsocksSettings = parse_settings(outbound)?;
+                check_connect_timeout(outbound, settings.connect_timeout)?;
+                let shadowsocks = ShadowsocksOutbound::new(settings)
+                    .map_err(|e| anyhow!("出站 {} 配置无效: {}", outbound.tag, e))?;
+                Ok(OutboundHandler::Shadowsocks(shadowsocks))
+            }
+            "trojan" => {
+                let settings: TrojanSettings = parse_settings(outbound)?;
+                check_connect_timeout(outbound, settings.connect_timeout)?;
+                let trojan = TrojanOutbound::new(settings)
+                    .map_err(|e| anyhow!("出站 {} 配置无效: {}", outbound.tag, e))?;
+                Ok(OutboundHandler::Trojan(trojan))
+            }
+            // 未知或拼错的协议不能退化为直连，否则本应走代理的流量会直接发出
+            other => Err(anyhow!("出站 {} 的协议 {} 不受支持", outbound.tag, other)),
+        }
+    }
+}
+
+impl Dialer for OutboundHandler {
+    fn connect_tcp<'a>(&'a self, target: &'a Address, sockopt: &'a SockOpt) -> BoxFuture<'a, Result<Box<dyn AsyncStream>>> {
+        Box::pin(async move {
+            let stream = match self {
+                OutboundHandler::Direct(direct) => return direct.connect_tcp(target, sockopt).await,
+                OutboundHandler::Blackhole => return Err(anyhow!("blackhole 出站不建立连接")),
+                OutboundHandler::Socks(socks) => socks.connect(target).await?,
+                OutboundHandler::Http(http) => http.connect(target).await?,
+                OutboundHandler::Vless(vless) => {
+                    return Ok(Box::new(vless.connect(target, sockopt).await?));
+                }
+                OutboundHandler::Shadowsocks(shadowsocks) => {
+                    return Ok(Box::new(shadowsocks.connect(target, sockopt).await?));
+                }
+                OutboundHandler::Trojan(trojan) => {
+                    return Ok(Box::new(trojan.connect(target, sockopt).await?));
+                }
+            };
+            dial::apply_outbound_sockopt(&stream, sockopt);
+            Ok(Box::new(stream) as Box<dyn AsyncStream>)
+        })
+    }
+
+    fn connect_udp<'a>(&'a self, target: &'a Address, sockopt: &'a SockOpt) -> BoxFuture<'a, Result<UdpSession>> {
+        Box::pin(async move {
+            match self {
+                OutboundHandler::Direct(direct) => direct.connect_udp(target, sockopt).await,
+                OutboundHandler::Blackhole => Err(anyhow!("blackhole 出站不建立连接")),
+                OutboundHandler::Socks(socks) => Ok(UdpSession::Socks {
+                    session: socks.associate().await?,
+                    target: target.clone(),
+                }),
+                OutboundHandler::Http(_) => Err(anyhow!("HTTP 代理出站不支持 UDP")),
+                OutboundHandler::Shadowsocks(_) => Err(anyhow!("Shadowsocks 出站暂不支持 UDP")),
+                OutboundHandler::Trojan(_) => Err(anyhow!("Trojan 出站暂不支持 UDP")),
+                OutboundHandler::Vless(vless) => {
+                    let (reader, writer) = tokio::io::split(vless.connect_udp(target, sockopt).await?);
+                    Ok(UdpSession::Vless {
+                        reader: Mutex::new(reader),
+                        writer: Mutex::new(writer),
+                        target: target.clone(),
+                    })
+                }
+            }
+        })
+    }
+
+    fn is_blackhole(&self) -> bool {
+        matches!(self, OutboundHandler::Blackhole)
+    }
+}
+
+/// 直连出站配置 (`Outbound.settings`，可省略)
+#[derive(Debug, Clone, Deserialize)]
+pub struct FreedomSettings {
+    /// 出站连接绑定的本地地址 (多 IP 服务器固定出口)
+    #[serde(rename = "sendThrough", default)]
+    pub send_through: Option<IpAddr>,
+    /// TCP 连接超时 (秒)，目标解析出多个地址时为全部尝试的总时长
+    #[serde(rename = "connectTimeout", default = "default_connect_timeout")]
+    pub connect_timeout: u64,
+}
+
+impl Default for FreedomSettings {
+    fn default() -> Self {
+        Self {
+            send_through: None,
+            connect_timeout: default_connect_timeout(),
+        }
+    }
+}
+
+fn default_connect_timeout() -> u64 {
+    8
+}
+
+/// 检查出站的 connectTimeout (秒)，为 0 时任何连接都会立即超时
+fn check_connect_timeout(outbound: &Outbound, secs: u64) -> Result<()> {
+    if secs == 0 {
+        return Err(anyhow!("出站 {} 的 connectTimeout 必须大于 0", outbound.tag));
+    }
+    Ok(())
+}
+
+/// 解析出站的 settings 字段
+fn parse_settings<T: serde::de::DeserializeOwned>(outbound: &Outbound) -> Result<T> {
+    let settings = outbound
+        .settings
+        .clone()
+        .ok_or_else(|| anyhow!("出站 {} 缺少 settings", outbound.tag))?;
+    serde_json::from_value(settings)
+        .map_err(|e| anyhow!("出站 {} 的 settings 无效: {}", outbound.tag, e))
+}
+
+/// 出站 UDP 会话
+///
+/// [`send`](Self::send) / [`recv`](Self::recv) 面向建立会话时的初始目标；
+/// [`send_to`](Self::send_to) / [`recv_from`](Self::recv_from) 按包指定目标并返回来源 (full-cone)，
+/// 上游 VLESS 会话只能发往初始目标。
+pub enum UdpSession {
+    /// 本机直接收发
+    Direct { socket: UdpSocket, target: SocketAddr },
+    /// 通过 SOCKS5 UDP ASSOCIATE 中继
+    Socks { session: SocksUdpSession, target: Address },
+    /// 通过上游 VLESS 节点转发，数据报以 2 字节长度前缀分帧
+    Vless {
+        reader: Mutex<ReadHalf<VlessStream<Box<dyn AsyncStream>>>>,
+        writer: Mutex<WriteHalf<VlessStream<Box<dyn AsyncStream>>>>,
+        target: Address,
+    },
+}
+
+impl UdpSession {
+    /// 向目标发送一个数据报
+    pub async fn send(&self, payload: &[u8]) -> Result<()> {
+        match self {
+            UdpSession::Direct { socket, target } => {
+                socket.send_to(payload, target).await?;
+            }
+            UdpSession::Socks { session, target } => {
+                session.send_to(payload, target).await?;
+            }
+            UdpSession::Vless { writer, .. } => {
+                let len = u16::try_from(payload.len()).map_err(|_| anyhow!("UDP 数据报过长"))?;
+                let mut frame = Vec::with_capacity(2 + payload.len());
+                frame.extend_from_slice(&len.to_be_bytes());
+                frame.extend_from_slice(payload);
+                let mut writer = writer.lock().await;
+                writer.write_all(&frame).await?;
+                writer.flush().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 接收一个数据报，负载写入 `buf` 开头，返回负载长度
+    pub async fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        match self {
+            UdpSession::Direct { socket, .. } => {
+                let (n, _) = socket.recv_from(buf).await?;
+                Ok(n)
+            }
+            UdpSession::Socks { session, .. } => {
+                let (n, _) = session.recv_from(buf).await?;
+                Ok(n)
+            }
+            UdpSession::Vless { reader, .. } => {
+                let mut reader = reader.lock().await;
+                let len = reader.read_u16().await? as usize;
+                if len > buf.len() {
+                    return Err(anyhow!("上游 UDP 数据报超过缓冲区: {} 字节", len));
+                }
+                reader.read_exact(&mut buf[..len]).await?;
+                Ok(len)
+            }
+        }
+    }
+
+    /// 向指定目标发送一个数据报，同一会话可以发往不同目标
+    pub async fn send_to(&self, payload: &[u8], target: &Address) -> Result<()> {
+        match self {
+            UdpSession::Direct { socket, .. } => {
+                // 只解析与本地 socket 同族的地址
+                let local = socket.local_addr()?.ip();
+                let resolved = match target {
+                    Address::Ipv4(ip, port) => SocketAddr::new((*ip).into(), *port),
+                    Address::Ipv6(ip, port) => SocketAddr::new((*ip).into(), *port),
+                    Address::Domain(..) => dial::resolve(&target.to_string(), Some(local)).await?,
+                };
+                socket.send_to(payload, resolved).await?;
+                Ok(())
+            }
+            UdpSession::Socks { session, .. } => {
+                session.send_to(payload, target).await?;
+                Ok(())
+            }
+            UdpSession::Vless { target: fixed, .. } if fixed == target => self.send(payload).await,
+            UdpSession::Vless { target: fixed, .. } => Err(anyhow!(
+                "上游 VLESS UDP 会话只能发往 {}，丢弃发往 {} 的数据报",
+                fixed.to_string(),
+                target.to_string()
+            )),
+        }
+    }
+
+    /// 接收一个数据报，返回负载长度与来源地址
+    pub async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, Address)> {
+        match self {
+            UdpSession::Direct { socket, .. } => {
+                let (n, from) = socket.recv_from(buf).await?;
+                Ok((n, Address::from(from)))
+            }
+            UdpSession::Socks { session, .. } => Ok(session.recv_from(buf).await?),
+            UdpSession::Vless { target, .. } => Ok((self.recv(buf).await?, target.clone())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_freedom_send_through() {
+        let outbound = Outbound {
+            protocol: "freedom".to_string(),
+            tag: "pinned".to_string(),
+            settings: Some(serde_json::json!({ "sendThrough": "127.0.0.2" })),
+            stream_settings: None,
+        };
+        let handler = OutboundHandler::from_config(&outbound).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = Address::from(listener.local_addr().unwrap());
+        let _stream = handler.connect_tcp(&target, &SockOpt::default()).await.unwrap();
+        let (_, peer) = listener.accept().await.unwrap();
+        assert_eq!(peer.ip().to_string(), "127.0.0.2");
+
+        let v6_target = Address::Ipv6(std::net::Ipv6Addr::LOCALHOST, 443);
+        let err = handler.connect_tcp(&v6_target, &SockOpt::default()).await.err().unwrap().to_string();
+        assert!(err.contains("地址族不一致"), "{}", err);
+    }
+
+    #[test]
+    fn test_unknown_protocol_rejected() {
+        let outbound = Outbound {
+            protocol: "vmes".to_string(),
+            tag: "proxy".to_string(),
+            settings: None,
+            stream_settings: None,
+        };
+        let err = OutboundHandler::from_config(&outbound).unwrap_err().to_string();
+        assert!(err.contains("vmes"), "{}", err);
+    }
+}
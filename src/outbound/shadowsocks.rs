@@ -0,0 +1,734 @@
+//! Shadowsocks 出站 (AEAD 与 2022-blake3)
+//!
+//! 支持 aes-128-gcm / aes-256-gcm (SIP004) 以及 2022-blake3-aes-128-gcm /
+//! 2022-blake3-aes-256-gcm (SIP022)，目前只转发 TCP。
+//! 请求头在首次写入时与数据合并发出，上游数据在读取时按块解密。
+
+use aes_gcm::aead::Aead;
+use aes_gcm::{Aes128Gcm, Aes256Gcm, KeyInit, Nonce};
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use bytes::{Buf, BytesMut};
+use md5::{Digest, Md5};
+use rand::Rng;
+use ring::hkdf;
+use serde::Deserialize;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tracing::debug;
+
+use crate::config::SockOpt;
+use crate::protocol::vless::Address;
+
+/// AEAD 认证标签长度
+const TAG_LEN: usize = 16;
+/// SIP004 单块负载上限
+const MAX_PAYLOAD_AEAD: usize = 0x3FFF;
+/// SIP022 单块负载上限
+const MAX_PAYLOAD_2022: usize = 0xFFFF;
+/// SIP022 响应时间戳允许的最大偏差 (秒)
+const MAX_TIME_DIFF: u64 = 30;
+/// SIP022 没有首包数据时的最大填充长度
+const MAX_PADDING: usize = 900;
+const HEADER_TYPE_CLIENT: u8 = 0;
+const HEADER_TYPE_SERVER: u8 = 1;
+
+/// Shadowsocks 出站配置 (`Outbound.settings`)
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShadowsocksSettings {
+    /// 服务器地址
+    pub address: String,
+    /// 服务器端口
+    pub port: u16,
+    /// 加密方式
+    pub method: String,
+    /// 密码 (2022 系列为 base64 编码的预共享密钥)
+    pub password: String,
+    /// 连接服务器的超时 (秒)，服务器地址解析出多个地址时为全部尝试的总时长
+    #[serde(rename = "connectTimeout", default = "super::default_connect_timeout")]
+    pub connect_timeout: u64,
+}
+
+/// 加密方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Aes128Gcm,
+    Aes256Gcm,
+    Blake3Aes128Gcm,
+    Blake3Aes256Gcm,
+}
+
+impl Method {
+    /// 按配置中的名称解析
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "aes-128-gcm" => Ok(Method::Aes128Gcm),
+            "aes-256-gcm" => Ok(Method::Aes256Gcm),
+            "2022-blake3-aes-128-gcm" => Ok(Method::Blake3Aes128Gcm),
+            "2022-blake3-aes-256-gcm" => Ok(Method::Blake3Aes256Gcm),
+            other => Err(anyhow!("不支持的 Shadowsocks 加密方式: {}", other)),
+        }
+    }
+
+    /// 密钥长度 (盐的长度与之相同)
+    pub fn key_len(self) -> usize {
+        match self {
+            Method::Aes128Gcm | Method::Blake3Aes128Gcm => 16,
+            Method::Aes256Gcm | Method::Blake3Aes256Gcm => 32,
+        }
+    }
+
+    /// 是否为 2022 系列
+    pub fn is_2022(self) -> bool {
+        matches!(self, Method::Blake3Aes128Gcm | Method::Blake3Aes256Gcm)
+    }
+
+    pub(crate) fn max_payload(self) -> usize {
+        if self.is_2022() {
+            MAX_PAYLOAD_2022
+        } else {
+            MAX_PAYLOAD_AEAD
+        }
+    }
+
+    /// 由配置的密码得到主密钥
+    pub fn derive_key(self, password: &str) -> Result<Vec<u8>> {
+        if !self.is_2022() {
+            if password.is_empty() {
+                return Err(anyhow!("Shadowsocks 密码不能为空"));
+            }
+            return Ok(evp_bytes_to_key(password.as_bytes(), self.key_len()));
+        }
+
+        let key = STANDARD
+            .decode(password)
+            .map_err(|e| anyhow!("2022 系列密码必须是 base64 编码的密钥: {}", e))?;
+        if key.len() != self.key_len() {
+            return Err(anyhow!(
+                "2022 系列密钥长度必须是 {} 字节 (实际 {})",
+                self.key_len(),
+                key.len()
+            ));
+        }
+        Ok(key)
+    }
+}
+
+/// OpenSSL EVP_BytesToKey (MD5，单轮): D_i = MD5(D_{i-1} || password)
+fn evp_bytes_to_key(password: &[u8], len: usize) -> Vec<u8> {
+    let mut key = Vec::with_capacity(len + 16);
+    let mut prev: Vec<u8> = Vec::new();
+    while key.len() < len {
+        let mut hasher = Md5::new();
+        hasher.update(&prev);
+        hasher.update(password);
+        prev = hasher.finalize().to_vec();
+        key.extend_from_slice(&prev);
+    }
+    key.truncate(len);
+    key
+}
+
+struct OutputLen(usize);
+impl hkdf::KeyType for OutputLen {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+/// 由主密钥和盐派生会话子密钥
+fn session_subkey(method: Method, key: &[u8], salt: &[u8]) -> io::Result<Vec<u8>> {
+    let mut subkey = vec![0u8; method.key_len()];
+    if method.is_2022() {
+        let mut hasher = blake3::Hasher::new_derive_key("shadowsocks 2022 session subkey");
+        hasher.update(key);
+        hasher.update(salt);
+        hasher.finalize_xof().fill(&mut subkey);
+    } else {
+        hkdf::Salt::new(hkdf::HKDF_SHA1_FOR_LEGACY_USE_ONLY, salt)
+            .extract(key)
+            .expand(&[b"ss-subkey"], OutputLen(subkey.len()))
+            .and_then(|okm| okm.fill(&mut subkey))
+            .map_err(|_| io::Error::other("Shadowsocks 子密钥派生失败"))?;
+    }
+    Ok(subkey)
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+enum AeadCipher {
+    Aes128(Box<Aes128Gcm>),
+    Aes256(Box<Aes256Gcm>),
+}
+
+/// 单方向的 AEAD 状态: 每次加解密后 nonce 按小端递增
+struct Cipher {
+    aead: AeadCipher,
+    nonce: [u8; 12],
+}
+
+impl Cipher {
+    fn new(method: Method, key: &[u8], salt: &[u8]) -> io::Result<Self> {
+        let subkey = session_subkey(method, key, salt)?;
+        let aead = match method.key_len() {
+            16 => AeadCipher::Aes128(Box::new(Aes128Gcm::new_from_slice(&subkey).map_err(io::Error::other)?)),
+            _ => AeadCipher::Aes256(Box::new(Aes256Gcm::new_from_slice(&subkey).map_err(io::Error::other)?)),
+        };
+        Ok(Self { aead, nonce: [0; 12] })
+    }
+
+    fn advance_nonce(&mut self) -> [u8; 12] {
+        let current = self.nonce;
+        for byte in self.nonce.iter_mut() {
+            *byte = byte.wrapping_add(1);
+            if *byte != 0 {
+                break;
+            }
+        }
+        current
+    }
+
+    /// 加密一块，密文 (含标签) 追加到 `out`
+    fn seal(&mut self, plaintext: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+        let nonce = self.advance_nonce();
+        let nonce = Nonce::from_slice(&nonce);
+        let sealed = match &self.aead {
+            AeadCipher::Aes128(aead) => aead.encrypt(nonce, plaintext),
+            AeadCipher::Aes256(aead) => aead.encrypt(nonce, plaintext),
+        }
+        .map_err(|_| io::Error::other("Shadowsocks 加密失败"))?;
+        out.extend_from_slice(&sealed);
+        Ok(())
+    }
+
+    /// 解密一块 (含标签)
+    fn open(&mut self, ciphertext: &[u8]) -> io::Result<Vec<u8>> {
+        let nonce = self.advance_nonce();
+        let nonce = Nonce::from_slice(&nonce);
+        match &self.aead {
+            AeadCipher::Aes128(aead) => aead.decrypt(nonce, ciphertext),
+            AeadCipher::Aes256(aead) => aead.decrypt(nonce, ciphertext),
+        }
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Shadowsocks 数据块解密失败"))
+    }
+}
+
+/// 请求中的随机部分 (测试中可以固定)
+struct RequestSeed {
+    salt: Vec<u8>,
+    timestamp: u64,
+    /// 2022 系列没有首包数据时使用的填充长度
+    padding: usize,
+}
+
+impl RequestSeed {
+    fn random(method: Method) -> Self {
+        let mut rng = rand::thread_rng();
+        let mut salt = vec![0u8; method.key_len()];
+        rng.fill(&mut salt[..]);
+        Self {
+            salt,
+            timestamp: unix_now(),
+            padding: rng.gen_range(1..=MAX_PADDING),
+        }
+    }
+}
+
+/// 上行方向: 明文编码为 [加密长度][加密负载] 数据块
+pub(crate) struct Encoder {
+    method: Method,
+    cipher: Cipher,
+}
+
+impl Encoder {
+    pub(crate) fn new(method: Method, key: &[u8], salt: &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            method,
+            cipher: Cipher::new(method, key, salt)?,
+        })
+    }
+
+    pub(crate) fn encode(&mut self, data: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+        for chunk in data.chunks(self.method.max_payload()) {
+            self.cipher.seal(&(chunk.len() as u16).to_be_bytes(), out)?;
+            self.cipher.seal(chunk, out)?;
+        }
+        Ok(())
+    }
+
+    /// 编码请求: 盐 + 目标地址头 + 首包数据
+    fn encode_request(&mut self, target: &Address, payload: &[u8], seed: &RequestSeed, out: &mut Vec<u8>) -> io::Result<()> {
+        let mut addr = BytesMut::new();
+        target.encode_socks(&mut addr);
+        out.extend_from_slice(&seed.salt);
+
+        if !self.method.is_2022() {
+            let mut first = addr.to_vec();
+            first.extend_from_slice(payload);
+            return self.encode(&first, out);
+        }
+
+        // 可变长度头放不下的首包数据按普通数据块发送
+        let padding = if payload.is_empty() { seed.padding } else { 0 };
+        let room = MAX_PAYLOAD_2022 - addr.len() - 2 - padding;
+        let (head, rest) = payload.split_at(payload.len().min(room));
+
+        let mut variable = addr.to_vec();
+        variable.extend_from_slice(&(padding as u16).to_be_bytes());
+        variable.resize(variable.len() + padding, 0);
+        variable.extend_from_slice(head);
+
+        let mut fixed = Vec::with_capacity(11);
+        fixed.push(HEADER_TYPE_CLIENT);
+        fixed.extend_from_slice(&seed.timestamp.to_be_bytes());
+        fixed.extend_from_slice(&(variable.len() as u16).to_be_bytes());
+
+        self.cipher.seal(&fixed, out)?;
+        self.cipher.seal(&variable, out)?;
+        self.encode(rest, out)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReadState {
+    Salt,
+    /// 2022 响应的固定长度头
+    Header,
+    Length,
+    Payload(usize),
+}
+
+/// 下行方向: 从密文中逐块解出明文
+pub(crate) struct Decoder {
+    method: Method,
+    key: Vec<u8>,
+    /// 本方向请求使用的盐 (2022 响应头中会回显)
+    request_salt: Vec<u8>,
+    cipher: Option<Cipher>,
+    state: ReadState,
+}
+
+impl Decoder {
+    pub(crate) fn new(method: Method, key: &[u8], request_salt: &[u8]) -> Self {
+        Self {
+            method,
+            key: key.to_vec(),
+            request_salt: request_salt.to_vec(),
+            cipher: None,
+            state: ReadState::Salt,
+        }
+    }
+
+    /// 解出 `input` 中所有完整的数据块，明文追加到 `out`
+    pub(crate) fn decode(&mut self, input: &mut BytesMut, out: &mut BytesMut, now: u64) -> io::Result<()> {
+        let salt_len = self.method.key_len();
+        loop {
+            let need = match self.state {
+                ReadState::Salt => salt_len,
+                ReadState::Header => 1 + 8 + salt_len + 2 + TAG_LEN,
+                ReadState::Length => 2 + TAG_LEN,
+                ReadState::Payload(len) => len + TAG_LEN,
+            };
+            if input.len() < need {
+                return Ok(());
+            }
+            let block = input.split_to(need);
+
+            self.state = match (self.state, self.cipher.as_mut()) {
+                (ReadState::Salt, _) => {
+                    self.cipher = Some(Cipher::new(self.method, &self.key, &block)?);
+                    if self.method.is_2022() {
+                        ReadState::Header
+                    } else {
+                        ReadState::Length
+                    }
+                }
+                (ReadState::Header, Some(cipher)) => {
+                    let header = cipher.open(&block)?;
+                    if header[0] != HEADER_TYPE_SERVER {
+                        return Err(invalid_data(format!("Shadowsocks 响应头类型错误: {}", header[0])));
+                    }
+                    let timestamp = u64::from_be_bytes(header[1..9].try_into().unwrap());
+                    if timestamp.abs_diff(now) > MAX_TIME_DIFF {
+                        return Err(invalid_data(format!(
+                            "Shadowsocks 响应时间戳偏差过大: {} (本地 {})",
+                            timestamp, now
+                        )));
+                    }
+                    if header[9..9 + salt_len] != self.request_salt[..] {
+                        return Err(invalid_data("Shadowsocks 响应中的请求盐不匹配".to_string()));
+                    }
+                    let len = u16::from_be_bytes([header[9 + salt_len], header[10 + salt_len]]);
+                    ReadState::Payload(len as usize)
+                }
+                (ReadState::Length, Some(cipher)) => {
+                    let len = cipher.open(&block)?;
+                    let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+                    if len > self.method.max_payload() {
+                        return Err(invalid_data(format!("Shadowsocks 数据块长度超限: {}", len)));
+                    }
+                    ReadState::Payload(len)
+                }
+                (ReadState::Payload(_), Some(cipher)) => {
+                    out.extend_from_slice(&cipher.open(&block)?);
+                    ReadState::Length
+                }
+                (_, None) => unreachable!("盐之后的状态总是已有密钥"),
+            };
+        }
+    }
+
+    /// 是否停在数据块边界 (此时遇到 EOF 属于正常关闭)
+    pub(crate) fn at_boundary(&self, input: &BytesMut) -> bool {
+        self.state == ReadState::Length && input.is_empty()
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Shadowsocks 出站
+#[derive(Debug, Clone)]
+pub struct ShadowsocksOutbound {
+    settings: ShadowsocksSettings,
+    method: Method,
+    key: Vec<u8>,
+}
+
+impl ShadowsocksOutbound {
+    /// 根据 settings 创建，配置错误在启动时报告
+    pub fn new(settings: ShadowsocksSettings) -> Result<Self> {
+        let method = Method::from_name(&settings.method)?;
+        let key = method.derive_key(&settings.password)?;
+        Ok(Self { settings, method, key })
+    }
+
+    /// 上游服务器地址
+    fn upstream(&self) -> String {
+        Address::from_host(&self.settings.address, self.settings.port).to_string()
+    }
+
+    /// 建立到目标的 TCP 隧道
+    pub async fn connect(&self, target: &Address, sockopt: &SockOpt) -> Result<ShadowsocksStream<TcpStream>> {
+        let upstream = self.upstream();
+        let budget = Duration::from_secs(self.settings.connect_timeout);
+        let stream = super::dial::connect_any(&upstream, None, budget)
+            .await
+            .map_err(|e| anyhow!("无法连接 Shadowsocks 上游 {}: {}", upstream, e))?;
+        super::dial::apply_outbound_sockopt(&stream, sockopt);
+
+        debug!("Shadowsocks {} via {}", target.to_string(), upstream);
+        Ok(ShadowsocksStream::new(stream, self.method, &self.key, target.clone())?)
+    }
+}
+
+/// Shadowsocks 客户端流
+///
+/// 请求头在首次写入时与数据合并发出 (若先读取则单独发出)。
+pub struct ShadowsocksStream<S> {
+    inner: S,
+    encoder: Encoder,
+    /// 请求头尚未编码时保存目标地址与随机参数
+    request: Option<(Address, RequestSeed)>,
+    /// 待写出的密文
+    pending: Vec<u8>,
+    pending_pos: usize,
+    decoder: Decoder,
+    /// 已收到尚未解密的密文
+    incoming: BytesMut,
+    /// 已解密尚未交给调用方的明文
+    plaintext: BytesMut,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> ShadowsocksStream<S> {
+    pub fn new(inner: S, method: Method, key: &[u8], target: Address) -> io::Result<Self> {
+        Self::with_seed(inner, method, key, target, RequestSeed::random(method))
+    }
+
+    fn with_seed(inner: S, method: Method, key: &[u8], target: Address, seed: RequestSeed) -> io::Result<Self> {
+        Ok(Self {
+            inner,
+            encoder: Encoder::new(method, key, &seed.salt)?,
+            decoder: Decoder::new(method, key, &seed.salt),
+            request: Some((target, seed)),
+            pending: Vec::new(),
+            pending_pos: 0,
+            incoming: BytesMut::new(),
+            plaintext: BytesMut::new(),
+        })
+    }
+
+    /// 还没写过数据时单独编码请求头
+    fn queue_request(&mut self) -> io::Result<()> {
+        if let Some((target, seed)) = self.request.take() {
+            self.encoder.encode_request(&target, &[], &seed, &mut self.pending)?;
+        }
+        Ok(())
+    }
+
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.pending_pos < self.pending.len() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.pending[self.pending_pos..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.pending_pos += n;
+        }
+        self.pending.clear();
+        self.pending_pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for ShadowsocksStream<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        // 上游收到请求头后才会响应
+        this.queue_request()?;
+        ready!(this.poll_drain(cx))?;
+
+        loop {
+            if !this.plaintext.is_empty() {
+                let n = this.plaintext.len().min(buf.remaining());
+                buf.put_slice(&this.plaintext[..n]);
+                this.plaintext.advance(n);
+                return Poll::Ready(Ok(()));
+            }
+
+            this.decoder.decode(&mut this.incoming, &mut this.plaintext, unix_now())?;
+            if !this.plaintext.is_empty() {
+                continue;
+            }
+
+            let mut raw = [0u8; 16 * 1024];
+            let mut raw_buf = ReadBuf::new(&mut raw);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut raw_buf))?;
+            let n = raw_buf.filled().len();
+            if n == 0 {
+                if this.decoder.at_boundary(&this.incoming) {
+                    return Poll::Ready(Ok(()));
+                }
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Shadowsocks 上游在数据块中途关闭了连接",
+                )));
+            }
+            this.incoming.extend_from_slice(raw_buf.filled());
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for ShadowsocksStream<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        ready!(this.poll_drain(cx))?;
+
+        let n = buf.len().min(this.encoder.method.max_payload());
+        match this.request.take() {
+            Some((target, seed)) => this.encoder.encode_request(&target, &buf[..n], &seed, &mut this.pending)?,
+            None => this.encoder.encode(&buf[..n], &mut this.pending)?,
+        }
+        // 密文已缓存，剩余部分留给下次写入或 flush 发出
+        let _ = this.poll_drain(cx)?;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        this.queue_request()?;
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        this.queue_request()?;
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // 以下向量由独立的参考实现 (Python cryptography + BLAKE3) 生成
+
+    /// aes-128-gcm，密码 "password"，盐全为 0x01，目标 example.com:443
+    const AEAD_REQUEST: &str = "010101010101010101010101010101012616e0b83aa771f5381edea95ca427976e031a37bc082e851529cc9fa7d8e2b42e0ab9a452b6850b92cf5e72783cc784af0b0c25582fb004f45006bcd65446e0a7a627";
+    /// aes-128-gcm 响应，盐全为 0x02，分两块: "HTTP/1.1 " 与 "200 OK\r\n\r\n"
+    const AEAD_RESPONSE: &str = "020202020202020202020202020202028b3e0754c2b40262357f6dd20ebb21e54324da8cef2f7f78759aeb480524cfe238ab218753c2c85bf2d2ebbe3527600a7f2be0e00052f2b251be38c0ab19d7b18ed74386d1c5cceac85b283a7b7980cd4737309adadaed";
+    /// 2022-blake3-aes-128-gcm，密钥 00..0f，盐全为 0x03，时间戳 1700000000，
+    /// 目标 127.0.0.1:80，首包 "hello"，随后一块 " again"
+    const SS2022_REQUEST: &str = "0303030303030303030303030303030331415c36f27f543e3f040d302d083efdece5fdc2d4d0cae336fad731f6bd141690463034a1b8fbc1ea61894d6e85015cd2962b7f8c7c0b8952fef79f6b999941fdda4a0b625f4a13ed8226563a683c168efc8d8c3b4e24d31f8eb36ab42c3413bc";
+    /// 对应响应，盐全为 0x04，时间戳 1700000005，首块 "world"，随后一块 "!"
+    const SS2022_RESPONSE: &str = "04040404040404040404040404040404614a0a2ff04546cd4da12c7b75ad702d69220ae589db0e3aa7fd335930c7d7e49ab83026af2f0a47fb19a13985f8b2c072e495580794f52e3af7476020f1d51ee1fc65ae09794e8f59581bbd9d84d6d0cf1b3a2cebf1d091a78c7db4f0ecde47d0f74d";
+    const SS2022_PSK: &str = "AAECAwQFBgcICQoLDA0ODw==";
+
+    fn seed(byte: u8, timestamp: u64) -> RequestSeed {
+        RequestSeed {
+            salt: vec![byte; 16],
+            timestamp,
+            padding: 17,
+        }
+    }
+
+    fn decode_all(decoder: &mut Decoder, wire: &[u8], now: u64) -> io::Result<Vec<u8>> {
+        let mut input = BytesMut::from(wire);
+        let mut out = BytesMut::new();
+        decoder.decode(&mut input, &mut out, now)?;
+        assert!(decoder.at_boundary(&input));
+        Ok(out.to_vec())
+    }
+
+    #[test]
+    fn test_key_derivation() {
+        let key = Method::Aes128Gcm.derive_key("password").unwrap();
+        assert_eq!(hex::encode(&key), "5f4dcc3b5aa765d61d8327deb882cf99");
+        let key = Method::Aes256Gcm.derive_key("password").unwrap();
+        assert_eq!(
+            hex::encode(&key),
+            "5f4dcc3b5aa765d61d8327deb882cf992b95990a9151374abd8ff8c5a7a0fe08"
+        );
+
+        let psk = Method::Blake3Aes128Gcm.derive_key(SS2022_PSK).unwrap();
+        let subkey = session_subkey(Method::Blake3Aes128Gcm, &psk, &[3; 16]).unwrap();
+        assert_eq!(hex::encode(subkey), "ce1563e7242233d98db86a347b7c8c9e");
+    }
+
+    #[test]
+    fn test_invalid_settings() {
+        assert!(Method::from_name("chacha20-poly1305").is_err());
+        assert!(Method::Aes128Gcm.derive_key("").is_err());
+        // 2022 系列要求精确长度的 base64 密钥
+        assert!(Method::Blake3Aes256Gcm.derive_key(SS2022_PSK).is_err());
+        assert!(Method::Blake3Aes128Gcm.derive_key("password").is_err());
+    }
+
+    #[test]
+    fn test_aead_request_vector() {
+        let key = Method::Aes128Gcm.derive_key("password").unwrap();
+        let seed = seed(1, 0);
+        let mut encoder = Encoder::new(Method::Aes128Gcm, &key, &seed.salt).unwrap();
+        let mut out = Vec::new();
+        let target = Address::Domain("example.com".to_string(), 443);
+        encoder
+            .encode_request(&target, b"GET / HTTP/1.1\r\n\r\n", &seed, &mut out)
+            .unwrap();
+        assert_eq!(hex::encode(out), AEAD_REQUEST);
+    }
+
+    #[test]
+    fn test_aead_response_vector() {
+        let key = Method::Aes128Gcm.derive_key("password").unwrap();
+        let wire = hex::decode(AEAD_RESPONSE).unwrap();
+
+        let mut decoder = Decoder::new(Method::Aes128Gcm, &key, &[1; 16]);
+        assert_eq!(decode_all(&mut decoder, &wire, 0).unwrap(), b"HTTP/1.1 200 OK\r\n\r\n");
+
+        // 逐字节到达时结果相同
+        let mut decoder = Decoder::new(Method::Aes128Gcm, &key, &[1; 16]);
+        let mut input = BytesMut::new();
+        let mut out = BytesMut::new();
+        for byte in &wire {
+            input.extend_from_slice(&[*byte]);
+            decoder.decode(&mut input, &mut out, 0).unwrap();
+        }
+        assert_eq!(&out[..], b"HTTP/1.1 200 OK\r\n\r\n");
+
+        // 篡改的密文被拒绝
+        let mut tampered = wire.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        let mut decoder = Decoder::new(Method::Aes128Gcm, &key, &[1; 16]);
+        assert!(decode_all(&mut decoder, &tampered, 0).is_err());
+    }
+
+    #[test]
+    fn test_2022_request_vector() {
+        let psk = Method::Blake3Aes128Gcm.derive_key(SS2022_PSK).unwrap();
+        let seed = seed(3, 1_700_000_000);
+        let mut encoder = Encoder::new(Method::Blake3Aes128Gcm, &psk, &seed.salt).unwrap();
+        let mut out = Vec::new();
+        let target = Address::Ipv4(Ipv4Addr::LOCALHOST, 80);
+        encoder.encode_request(&target, b"hello", &seed, &mut out).unwrap();
+        encoder.encode(b" again", &mut out).unwrap();
+        assert_eq!(hex::encode(out), SS2022_REQUEST);
+    }
+
+    #[test]
+    fn test_2022_padding_without_payload() {
+        let psk = Method::Blake3Aes128Gcm.derive_key(SS2022_PSK).unwrap();
+        let seed = seed(3, 1_700_000_000);
+        let mut encoder = Encoder::new(Method::Blake3Aes128Gcm, &psk, &seed.salt).unwrap();
+        let mut out = Vec::new();
+        let target = Address::Ipv4(Ipv4Addr::LOCALHOST, 80);
+        encoder.encode_request(&target, b"", &seed, &mut out).unwrap();
+        // 盐 + 固定头 (11) + 可变头 (地址 7 + 填充长度 2 + 填充 17)
+        assert_eq!(out.len(), 16 + (11 + TAG_LEN) + (7 + 2 + 17 + TAG_LEN));
+    }
+
+    #[test]
+    fn test_2022_response_vector() {
+        let psk = Method::Blake3Aes128Gcm.derive_key(SS2022_PSK).unwrap();
+        let wire = hex::decode(SS2022_RESPONSE).unwrap();
+
+        let mut decoder = Decoder::new(Method::Blake3Aes128Gcm, &psk, &[3; 16]);
+        assert_eq!(decode_all(&mut decoder, &wire, 1_700_000_010).unwrap(), b"world!");
+
+        let mut decoder = Decoder::new(Method::Blake3Aes128Gcm, &psk, &[3; 16]);
+        let err = decode_all(&mut decoder, &wire, 1_700_000_100).unwrap_err();
+        assert!(err.to_string().contains("时间戳"), "{}", err);
+
+        let mut decoder = Decoder::new(Method::Blake3Aes128Gcm, &psk, &[9; 16]);
+        let err = decode_all(&mut decoder, &wire, 1_700_000_010).unwrap_err();
+        assert!(err.to_string().contains("请求盐"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_stream_roundtrip() {
+        let key = Method::Aes128Gcm.derive_key("password").unwrap();
+        let (client, mut upstream) = tokio::io::duplex(4096);
+        let target = Address::Domain("example.com".to_string(), 443);
+        let mut stream = ShadowsocksStream::with_seed(client, Method::Aes128Gcm, &key, target, seed(1, 0)).unwrap();
+
+        stream.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+        stream.flush().await.unwrap();
+        let mut request = vec![0u8; AEAD_REQUEST.len() / 2];
+        upstream.read_exact(&mut request).await.unwrap();
+        assert_eq!(hex::encode(request), AEAD_REQUEST);
+
+        upstream.write_all(&hex::decode(AEAD_RESPONSE).unwrap()).await.unwrap();
+        drop(upstream);
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        assert_eq!(response, b"HTTP/1.1 200 OK\r\n\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_stream_truncated_response() {
+        let key = Method::Aes128Gcm.derive_key("password").unwrap();
+        let (client, mut upstream) = tokio::io::duplex(4096);
+        let target = Address::Domain("example.com".to_string(), 443);
+        let mut stream = ShadowsocksStream::with_seed(client, Method::Aes128Gcm, &key, target, seed(1, 0)).unwrap();
+
+        let wire = hex::decode(AEAD_RESPONSE).unwrap();
+        upstream.write_all(&wire[..wire.len() - 5]).await.unwrap();
+        // 只关闭写方向，请求头仍能写入
+        upstream.shutdown().await.unwrap();
+        let mut response = Vec::new();
+        let err = stream.read_to_end(&mut response).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
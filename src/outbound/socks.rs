@@ -0,0 +1,263 @@
+//! SOCKS5 出站 (RFC 1928 / RFC 1929)
+//!
+//! TCP 使用 CONNECT，UDP 使用 UDP ASSOCIATE 并按 SOCKS UDP 头封装数据报。
+
+use anyhow::{anyhow, Result};
+use bytes::{BufMut, BytesMut};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tracing::debug;
+
+use super::dial;
+use crate::protocol::vless::Address;
+
+const SOCKS_VERSION: u8 = 0x05;
+const METHOD_NO_AUTH: u8 = 0x00;
+const METHOD_USER_PASS: u8 = 0x02;
+const METHOD_NONE_ACCEPTABLE: u8 = 0xFF;
+const CMD_CONNECT: u8 = 0x01;
+const CMD_UDP_ASSOCIATE: u8 = 0x03;
+
+/// SOCKS5 出站配置 (`Outbound.settings`)
+#[derive(Debug, Clone, Deserialize)]
+pub struct SocksSettings {
+    /// 上游 SOCKS5 服务器地址
+    pub address: String,
+    /// 上游 SOCKS5 服务器端口
+    pub port: u16,
+    /// 用户名 (可选)
+    #[serde(default, alias = "username")]
+    pub user: Option<String>,
+    /// 密码 (可选)
+    #[serde(default, alias = "password")]
+    pub pass: Option<String>,
+    /// 连接上游服务器的超时 (秒)，服务器地址解析出多个地址时为全部尝试的总时长
+    #[serde(rename = "connectTimeout", default = "super::default_connect_timeout")]
+    pub connect_timeout: u64,
+}
+
+/// SOCKS5 出站
+#[derive(Debug, Clone)]
+pub struct SocksOutbound {
+    settings: SocksSettings,
+}
+
+impl SocksOutbound {
+    pub fn new(settings: SocksSettings) -> Self {
+        Self { settings }
+    }
+
+    /// 上游服务器地址
+    fn upstream(&self) -> String {
+        Address::from_host(&self.settings.address, self.settings.port).to_string()
+    }
+
+    /// 连接上游并完成协商与认证
+    async fn handshake(&self) -> Result<TcpStream> {
+        let upstream = self.upstream();
+        let budget = Duration::from_secs(self.settings.connect_timeout);
+        let mut stream = dial::connect_any(&upstream, None, budget)
+            .await
+            .map_err(|e| anyhow!("无法连接 SOCKS5 上游 {}: {}", upstream, e))?;
+
+        let credentials = match (&self.settings.user, &self.settings.pass) {
+            (Some(user), pass) => Some((user.as_str(), pass.as_deref().unwrap_or(""))),
+            (None, _) => None,
+        };
+
+        // 方法协商
+        let greeting: &[u8] = if credentials.is_some() {
+            &[SOCKS_VERSION, 2, METHOD_NO_AUTH, METHOD_USER_PASS]
+        } else {
+            &[SOCKS_VERSION, 1, METHOD_NO_AUTH]
+        };
+        stream.write_all(greeting).await?;
+
+        let mut reply = [0u8; 2];
+        stream
+            .read_exact(&mut reply)
+            .await
+            .map_err(|e| anyhow!("SOCKS5 上游 {} 协商失败: {}", upstream, e))?;
+        if reply[0] != SOCKS_VERSION {
+            return Err(anyhow!("SOCKS5 上游 {} 返回了错误的版本: {}", upstream, reply[0]));
+        }
+
+        match reply[1] {
+            METHOD_NO_AUTH => {}
+            METHOD_USER_PASS => {
+                let (user, pass) = credentials
+                    .ok_or_else(|| anyhow!("SOCKS5 上游 {} 要求认证，但未配置用户名", upstream))?;
+                if user.len() > 255 || pass.len() > 255 {
+                    return Err(anyhow!("SOCKS5 用户名或密码过长"));
+                }
+                let mut auth = BytesMut::with_capacity(3 + user.len() + pass.len());
+                auth.put_u8(0x01);
+                auth.put_u8(user.len() as u8);
+                auth.put_slice(user.as_bytes());
+                auth.put_u8(pass.len() as u8);
+                auth.put_slice(pass.as_bytes());
+                stream.write_all(&auth).await?;
+
+                let mut status = [0u8; 2];
+                stream.read_exact(&mut status).await?;
+                if status[1] != 0x00 {
+                    return Err(anyhow!("SOCKS5 上游 {} 认证失败", upstream));
+                }
+            }
+            METHOD_NONE_ACCEPTABLE => {
+                return Err(anyhow!("SOCKS5 上游 {} 拒绝了所有认证方式", upstream));
+            }
+            other => {
+                return Err(anyhow!("SOCKS5 上游 {} 选择了不支持的认证方式: {}", upstream, other));
+            }
+        }
+
+        Ok(stream)
+    }
+
+    /// 发送命令并读取应答中的绑定地址
+    async fn command(&self, stream: &mut TcpStream, cmd: u8, target: &Address) -> Result<Address> {
+        let upstream = self.upstream();
+        let mut request = BytesMut::with_capacity(3 + 1 + 255 + 2);
+        request.put_slice(&[SOCKS_VERSION, cmd, 0x00]);
+        target.encode_socks(&mut request);
+        stream.write_all(&request).await?;
+
+        let mut head = [0u8; 3];
+        stream
+            .read_exact(&mut head)
+            .await
+            .map_err(|e| anyhow!("SOCKS5 上游 {} 未返回应答: {}", upstream, e))?;
+        if head[1] != 0x00 {
+            return Err(anyhow!(
+                "SOCKS5 上游 {} 拒绝请求 {}: {}",
+                upstream,
+                target.to_string(),
+                reply_message(head[1])
+            ));
+        }
+        read_address(stream).await
+    }
+
+    /// 通过 CONNECT 建立到目标的 TCP 隧道
+    pub async fn connect(&self, target: &Address) -> Result<TcpStream> {
+        let mut stream = self.handshake().await?;
+        self.command(&mut stream, CMD_CONNECT, target).await?;
+        debug!("SOCKS5 CONNECT {} via {} 成功", target.to_string(), self.upstream());
+        Ok(stream)
+    }
+
+    /// 通过 UDP ASSOCIATE 建立 UDP 中继
+    pub async fn associate(&self) -> Result<SocksUdpSession> {
+        let mut control = self.handshake().await?;
+        let unspecified = Address::Ipv4(std::net::Ipv4Addr::UNSPECIFIED, 0);
+        let bound = self.command(&mut control, CMD_UDP_ASSOCIATE, &unspecified).await?;
+
+        // 服务器返回 0.0.0.0 时，中继地址即上游服务器自身
+        let upstream_addr = control.peer_addr()?;
+        let relay = match bound {
+            Address::Ipv4(ip, port) if ip.is_unspecified() => SocketAddr::new(upstream_addr.ip(), port),
+            Address::Ipv6(ip, port) if ip.is_unspecified() => SocketAddr::new(upstream_addr.ip(), port),
+            Address::Ipv4(ip, port) => SocketAddr::new(ip.into(), port),
+            Address::Ipv6(ip, port) => SocketAddr::new(ip.into(), port),
+            Address::Domain(host, port) => tokio::net::lookup_host((host.as_str(), port))
+                .await?
+                .next()
+                .ok_or_else(|| anyhow!("无法解析 SOCKS5 UDP 中继地址: {}", host))?,
+        };
+
+        let bind_addr = if relay.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let socket = UdpSocket::bind(bind_addr).await?;
+        socket.connect(relay).await?;
+        debug!("SOCKS5 UDP ASSOCIATE via {}，中继 {}", self.upstream(), relay);
+
+        Ok(SocksUdpSession {
+            socket,
+            _control: control,
+        })
+    }
+}
+
+/// 读取应答中的 ATYP + 地址 + 端口
+async fn read_address<R: AsyncRead + Unpin>(stream: &mut R) -> Result<Address> {
+    let atyp = stream.read_u8().await?;
+    let mut buf = BytesMut::new();
+    buf.put_u8(atyp);
+    let len = match atyp {
+        0x01 => 4 + 2,
+        0x04 => 16 + 2,
+        0x03 => {
+            let n = stream.read_u8().await?;
+            buf.put_u8(n);
+            n as usize + 2
+        }
+        _ => return Err(anyhow!("未知的 SOCKS 地址类型: {}", atyp)),
+    };
+    let mut rest = vec![0u8; len];
+    stream.read_exact(&mut rest).await?;
+    buf.put_slice(&rest);
+    Address::decode_socks(&mut buf)
+}
+
+fn reply_message(code: u8) -> &'static str {
+    match code {
+        0x01 => "general failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+/// SOCKS5 UDP 中继会话
+///
+/// 控制连接必须在会话期间保持打开，关闭后上游会释放中继。
+#[derive(Debug)]
+pub struct SocksUdpSession {
+    socket: UdpSocket,
+    _control: TcpStream,
+}
+
+impl SocksUdpSession {
+    /// 发送数据报: RSV(2) + FRAG(1) + ATYP/ADDR/PORT + DATA
+    pub async fn send_to(&self, payload: &[u8], target: &Address) -> Result<()> {
+        let mut packet = BytesMut::with_capacity(3 + 1 + 255 + 2 + payload.len());
+        packet.put_slice(&[0x00, 0x00, 0x00]);
+        target.encode_socks(&mut packet);
+        packet.put_slice(payload);
+        self.socket.send(&packet).await?;
+        Ok(())
+    }
+
+    /// 接收数据报，去掉 SOCKS UDP 头后把负载写入 `buf` 开头
+    pub async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, Address)> {
+        loop {
+            let n = self.socket.recv(buf).await?;
+            if n < 4 {
+                continue;
+            }
+            // 不支持分片，直接丢弃
+            if buf[2] != 0x00 {
+                debug!("丢弃分片的 SOCKS5 UDP 数据报");
+                continue;
+            }
+            let mut header = BytesMut::from(&buf[3..n]);
+            let before = header.len();
+            let source = match Address::decode_socks(&mut header) {
+                Ok(addr) => addr,
+                Err(_) => continue,
+            };
+            let header_len = 3 + before - header.len();
+            let payload_len = n - header_len;
+            buf.copy_within(header_len..n, 0);
+            return Ok((payload_len, source));
+        }
+    }
+}
@@ -0,0 +1,350 @@
+//! Trojan 出站
+//!
+//! 与上游建立 TLS 连接后发送请求头 `hex(SHA224(password)) CRLF 命令 地址 端口 CRLF`，
+//! 请求头在首次写入时与数据合并发出。Trojan 没有响应头，上游数据直接转发。
+
+use anyhow::{anyhow, Result};
+use bytes::BytesMut;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, WebPkiSupportedAlgorithms};
+use rustls_pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use serde::Deserialize;
+use sha2::{Digest, Sha224};
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+use tracing::debug;
+
+use crate::config::SockOpt;
+use crate::protocol::vless::Address;
+
+/// Trojan CONNECT 命令
+const CMD_CONNECT: u8 = 0x01;
+
+/// Trojan 出站配置 (`Outbound.settings`)
+#[derive(Debug, Clone, Deserialize)]
+pub struct TrojanSettings {
+    /// 上游服务器地址
+    pub address: String,
+    /// 上游服务器端口
+    pub port: u16,
+    /// 密码
+    pub password: String,
+    /// TLS SNI 与证书校验使用的域名，默认与 address 相同
+    #[serde(rename = "serverName", default)]
+    pub server_name: Option<String>,
+    /// 跳过证书链与域名校验 (自签名证书)
+    #[serde(rename = "allowInsecure", default)]
+    pub allow_insecure: bool,
+    /// 连接上游服务器的超时 (秒)，服务器地址解析出多个地址时为全部尝试的总时长
+    #[serde(rename = "connectTimeout", default = "super::default_connect_timeout")]
+    pub connect_timeout: u64,
+}
+
+/// Trojan 出站
+#[derive(Debug, Clone)]
+pub struct TrojanOutbound {
+    settings: TrojanSettings,
+    /// 密码的 SHA224 十六进制
+    password_hash: String,
+    server_name: ServerName<'static>,
+    tls: Arc<ClientConfig>,
+}
+
+impl TrojanOutbound {
+    /// 根据 settings 创建，配置错误在启动时报告
+    pub fn new(settings: TrojanSettings) -> Result<Self> {
+        if settings.password.is_empty() {
+            return Err(anyhow!("Trojan 密码不能为空"));
+        }
+        let name = settings.server_name.clone().unwrap_or_else(|| settings.address.clone());
+        let server_name = ServerName::try_from(name.clone())
+            .map_err(|e| anyhow!("Trojan serverName 无效 {}: {}", name, e))?;
+
+        Ok(Self {
+            password_hash: hex::encode(Sha224::digest(settings.password.as_bytes())),
+            server_name,
+            tls: client_config(settings.allow_insecure),
+            settings,
+        })
+    }
+
+    /// 上游服务器地址
+    fn upstream(&self) -> String {
+        Address::from_host(&self.settings.address, self.settings.port).to_string()
+    }
+
+    /// 建立到目标的 TCP 隧道
+    pub async fn connect(&self, target: &Address, sockopt: &SockOpt) -> Result<TrojanStream<TlsStream<TcpStream>>> {
+        let upstream = self.upstream();
+        let budget = Duration::from_secs(self.settings.connect_timeout);
+        let stream = super::dial::connect_any(&upstream, None, budget)
+            .await
+            .map_err(|e| anyhow!("无法连接 Trojan 上游 {}: {}", upstream, e))?;
+        super::dial::apply_outbound_sockopt(&stream, sockopt);
+
+        let tls = TlsConnector::from(self.tls.clone())
+            .connect(self.server_name.clone(), stream)
+            .await
+            .map_err(|e| anyhow!("Trojan 上游 {} TLS 握手失败: {}", upstream, e))?;
+
+        debug!("Trojan {} via {}", target.to_string(), upstream);
+        Ok(TrojanStream::new(tls, encode_request(&self.password_hash, target)))
+    }
+}
+
+/// 编码 TCP 请求头
+fn encode_request(password_hash: &str, target: &Address) -> Vec<u8> {
+    let mut buf = BytesMut::with_capacity(password_hash.len() + 4 + 1 + 259);
+    buf.extend_from_slice(password_hash.as_bytes());
+    buf.extend_from_slice(b"\r\n");
+    buf.extend_from_slice(&[CMD_CONNECT]);
+    target.encode_socks(&mut buf);
+    buf.extend_from_slice(b"\r\n");
+    buf.to_vec()
+}
+
+pub(crate) fn client_config(allow_insecure: bool) -> Arc<ClientConfig> {
+    let config = if allow_insecure {
+        let algorithms = rustls::crypto::ring::default_provider().signature_verification_algorithms;
+        ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(InsecureVerifier(algorithms)))
+            .with_no_client_auth()
+    } else {
+        let mut roots = RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth()
+    };
+    Arc::new(config)
+}
+
+/// allowInsecure: 不校验证书链与域名，握手签名仍按证书公钥校验
+#[derive(Debug)]
+struct InsecureVerifier(WebPkiSupportedAlgorithms);
+
+impl ServerCertVerifier for InsecureVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.0)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.0)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.supported_schemes()
+    }
+}
+
+/// Trojan 客户端流
+///
+/// 请求头在首次写入时与数据合并发出 (若先读取则单独发出)。
+pub struct TrojanStream<S> {
+    inner: S,
+    /// 待写出的请求头 (及合并进来的首包数据)
+    pending: Vec<u8>,
+    pending_pos: usize,
+    /// 请求头是否已交给 `pending`
+    request_queued: bool,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> TrojanStream<S> {
+    pub fn new(inner: S, request: Vec<u8>) -> Self {
+        Self {
+            inner,
+            pending: request,
+            pending_pos: 0,
+            request_queued: false,
+        }
+    }
+
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.pending_pos < self.pending.len() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.pending[self.pending_pos..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.pending_pos += n;
+        }
+        if !self.pending.is_empty() {
+            self.pending = Vec::new();
+            self.pending_pos = 0;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for TrojanStream<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        // 还没写过数据时也要先把请求头发出去，否则上游不会响应
+        this.request_queued = true;
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for TrojanStream<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if !this.request_queued {
+            // 请求头与首包数据合并发送
+            this.request_queued = true;
+            this.pending.extend_from_slice(buf);
+            let _ = this.poll_drain(cx)?;
+            return Poll::Ready(Ok(buf.len()));
+        }
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        this.request_queued = true;
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        this.request_queued = true;
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls_pki_types::PrivateKeyDer;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio_rustls::TlsAcceptor;
+
+    /// SHA224("password") + CRLF + CONNECT + example.com:443 + CRLF
+    const REQUEST: &str = "64363364633931396532303164376263346338323536333064326366323566646339336434623266306434363730366432393033386430310d0a01030b6578616d706c652e636f6d01bb0d0a";
+
+    fn settings(port: u16, allow_insecure: bool) -> TrojanSettings {
+        TrojanSettings {
+            address: "127.0.0.1".to_string(),
+            port,
+            password: "password".to_string(),
+            server_name: Some("localhost".to_string()),
+            allow_insecure,
+            connect_timeout: 8,
+        }
+    }
+
+    /// localhost 自签名证书
+    fn self_signed_acceptor() -> TlsAcceptor {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let certs = vec![CertificateDer::from(cert.serialize_der().unwrap())];
+        let key = PrivateKeyDer::try_from(cert.serialize_private_key_der()).unwrap();
+        let config = rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .unwrap();
+        TlsAcceptor::from(Arc::new(config))
+    }
+
+    /// 最小 Trojan 回显服务: 读取并返回请求头，之后回显数据
+    async fn spawn_echo_server() -> (u16, tokio::task::JoinHandle<Vec<u8>>) {
+        let acceptor = self_signed_acceptor();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut tls = acceptor.accept(stream).await.unwrap();
+            let mut header = vec![0u8; REQUEST.len() / 2];
+            tls.read_exact(&mut header).await.unwrap();
+            let mut buf = [0u8; 1024];
+            loop {
+                let n = tls.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                tls.write_all(&buf[..n]).await.unwrap();
+            }
+            header
+        });
+        (port, server)
+    }
+
+    #[test]
+    fn test_request_header() {
+        let hash = hex::encode(Sha224::digest(b"password"));
+        assert_eq!(hash, "d63dc919e201d7bc4c825630d2cf25fdc93d4b2f0d46706d29038d01");
+        let target = Address::Domain("example.com".to_string(), 443);
+        assert_eq!(hex::encode(encode_request(&hash, &target)), REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_loopback_echo() {
+        let (port, server) = spawn_echo_server().await;
+        let outbound = TrojanOutbound::new(settings(port, true)).unwrap();
+        let target = Address::Domain("example.com".to_string(), 443);
+
+        let mut stream = outbound.connect(&target, &SockOpt::default()).await.unwrap();
+        stream.write_all(b"hello trojan").await.unwrap();
+        let mut echoed = [0u8; 12];
+        stream.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"hello trojan");
+        stream.shutdown().await.unwrap();
+
+        let header = server.await.unwrap();
+        assert_eq!(hex::encode(header), REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_self_signed_rejected_without_allow_insecure() {
+        let (port, _server) = spawn_echo_server().await;
+        let outbound = TrojanOutbound::new(settings(port, false)).unwrap();
+        let target = Address::Domain("example.com".to_string(), 443);
+
+        let err = outbound.connect(&target, &SockOpt::default()).await.err().unwrap().to_string();
+        assert!(err.contains("TLS 握手失败"), "{}", err);
+    }
+
+    #[test]
+    fn test_invalid_settings() {
+        let mut empty = settings(443, false);
+        empty.password.clear();
+        assert!(TrojanOutbound::new(empty).is_err());
+
+        let mut bad_name = settings(443, false);
+        bad_name.server_name = Some("bad name".to_string());
+        assert!(TrojanOutbound::new(bad_name).is_err());
+    }
+}
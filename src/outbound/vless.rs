@@ -0,0 +1,318 @@
+//! VLESS 出站 (链式转发到上游 VLESS / VLESS+Reality 节点)
+//!
+//! 请求头与首包数据合并发送，上游返回的 2 字节 VLESS 响应头在首次读取时剥离。
+
+use anyhow::{anyhow, Result};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine as _,
+};
+use serde::Deserialize;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::config::{OutboundStreamSettings, Security, SockOpt};
+use crate::protocol::vless::{Addons, Address, Command, VlessRequest};
+use crate::server::AsyncStream;
+use crate::transport::reality::{RealityClientConfig, RealityClientStream};
+
+/// VLESS 出站配置 (`Outbound.settings`)
+#[derive(Debug, Clone, Deserialize)]
+pub struct VlessSettings {
+    /// 上游 VLESS 服务器地址
+    pub address: String,
+    /// 上游 VLESS 服务器端口
+    pub port: u16,
+    /// 用户 UUID
+    pub id: String,
+    /// 连接上游服务器的超时 (秒)，服务器地址解析出多个地址时为全部尝试的总时长
+    #[serde(rename = "connectTimeout", default = "super::default_connect_timeout")]
+    pub connect_timeout: u64,
+}
+
+/// VLESS 出站
+#[derive(Debug, Clone)]
+pub struct VlessOutbound {
+    settings: VlessSettings,
+    uuid: Uuid,
+    reality: Option<RealityClientConfig>,
+}
+
+impl VlessOutbound {
+    /// 根据 settings 与 streamSettings 创建，配置错误在启动时报告
+    pub fn new(settings: VlessSettings, stream_settings: Option<&OutboundStreamSettings>) -> Result<Self> {
+        let uuid = Uuid::parse_str(&settings.id)
+            .map_err(|e| anyhow!("VLESS 出站 id 不是有效的 UUID: {}", e))?;
+
+        let reality = match stream_settings.map(|s| &s.security) {
+            None | Some(Security::None) => None,
+            Some(Security::Reality) => {
+                let reality = stream_settings
+                    .and_then(|s| s.reality_settings.as_ref())
+                    .ok_or_else(|| anyhow!("VLESS 出站 security 为 reality 时必须设置 realitySettings"))?;
+
+                let key = URL_SAFE_NO_PAD
+                    .decode(&reality.public_key)
+                    .or_else(|_| STANDARD.decode(&reality.public_key))
+                    .map_err(|e| anyhow!("Reality publicKey 解码失败: {}", e))?;
+                let public_key: [u8; 32] = key
+                    .try_into()
+                    .map_err(|k: Vec<u8>| anyhow!("Reality publicKey 必须是 32 字节 (实际 {})", k.len()))?;
+
+                let short_id = hex::decode(&reality.short_id)
+                    .map_err(|e| anyhow!("Reality shortId 不是有效的十六进制: {}", e))?;
+                if short_id.len() > 8 {
+                    return Err(anyhow!("Reality shortId 最多 8 字节"));
+                }
+
+                Some(RealityClientConfig {
+                    server_name: reality.server_name.clone(),
+                    public_key,
+                    short_id,
+                })
+            }
+            Some(Security::Tls) => return Err(anyhow!("VLESS 出站暂不支持 security: tls")),
+        };
+
+        Ok(Self {
+            settings,
+            uuid,
+            reality,
+        })
+    }
+
+    /// 上游服务器地址
+    fn upstream(&self) -> String {
+        Address::from_host(&self.settings.address, self.settings.port).to_string()
+    }
+
+    /// 连接上游服务器 (按配置完成 Reality 握手)
+    async fn dial(&self, sockopt: &SockOpt) -> Result<Box<dyn AsyncStream>> {
+        let upstream = self.upstream();
+        let budget = Duration::from_secs(self.settings.connect_timeout);
+        let stream = super::dial::connect_any(&upstream, None, budget)
+            .await
+            .map_err(|e| anyhow!("无法连接 VLESS 上游 {}: {}", upstream, e))?;
+        super::dial::apply_outbound_sockopt(&stream, sockopt);
+
+        match &self.reality {
+            Some(reality) => {
+                let tls = RealityClientStream::connect(stream, reality)
+                    .await
+                    .map_err(|e| anyhow!("VLESS 上游 {} Reality 握手失败: {}", upstream, e))?;
+                Ok(Box::new(tls))
+            }
+            None => Ok(Box::new(stream)),
+        }
+    }
+
+    /// 建立到目标的 TCP 隧道
+    pub async fn connect(&self, target: &Address, sockopt: &SockOpt) -> Result<VlessStream<Box<dyn AsyncStream>>> {
+        self.open(Command::Tcp, target, sockopt).await
+    }
+
+    /// 建立到目标的 UDP 隧道 (数据报以 2 字节长度前缀分帧)
+    pub async fn connect_udp(&self, target: &Address, sockopt: &SockOpt) -> Result<VlessStream<Box<dyn AsyncStream>>> {
+        let sockopt = SockOpt {
+            remote_tcp_no_delay: Some(false),
+            ..sockopt.clone()
+        };
+        self.open(Command::Udp, target, &sockopt).await
+    }
+
+    async fn open(&self, command: Command, target: &Address, sockopt: &SockOpt) -> Result<VlessStream<Box<dyn AsyncStream>>> {
+        let stream = self.dial(sockopt).await?;
+        let request = VlessRequest {
+            version: 0,
+            uuid: self.uuid,
+            command,
+            address: target.clone(),
+            addon_length: 0,
+            addons: Addons::default(),
+        };
+        debug!("VLESS {:?} {} via {}", command, target.to_string(), self.upstream());
+        Ok(VlessStream::new(stream, request.encode()?.to_vec()))
+    }
+}
+
+/// VLESS 客户端流
+///
+/// 请求头在首次写入时与数据合并发出 (若先读取则单独发出)，
+/// 读取时先剥离上游的响应头 (版本 + 附加数据)。
+pub struct VlessStream<S> {
+    inner: S,
+    /// 待写出的请求头 (及合并进来的首包数据)
+    pending: Vec<u8>,
+    pending_pos: usize,
+    /// 请求头是否已交给 `pending`
+    request_queued: bool,
+    /// 响应头剩余待剥离的字节: 先是 version + addon_len，再是附加数据
+    response_header: [u8; 2],
+    response_read: usize,
+    addons_left: usize,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> VlessStream<S> {
+    pub fn new(inner: S, request: Vec<u8>) -> Self {
+        Self {
+            inner,
+            pending: request,
+            pending_pos: 0,
+            request_queued: false,
+            response_header: [0; 2],
+            response_read: 0,
+            addons_left: 0,
+        }
+    }
+
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.pending_pos < self.pending.len() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.pending[self.pending_pos..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.pending_pos += n;
+        }
+        if !self.pending.is_empty() {
+            self.pending = Vec::new();
+            self.pending_pos = 0;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// 读取并校验响应头
+    fn poll_response_header(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.response_read < 2 {
+            let mut buf = ReadBuf::new(&mut self.response_header[self.response_read..]);
+            ready!(Pin::new(&mut self.inner).poll_read(cx, &mut buf))?;
+            let n = buf.filled().len();
+            if n == 0 {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "VLESS 上游在响应前关闭了连接",
+                )));
+            }
+            self.response_read += n;
+            if self.response_read == 2 {
+                if self.response_header[0] != 0 {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("VLESS 上游响应版本错误: {}", self.response_header[0]),
+                    )));
+                }
+                self.addons_left = self.response_header[1] as usize;
+            }
+        }
+        while self.addons_left > 0 {
+            let mut skip = [0u8; 255];
+            let mut buf = ReadBuf::new(&mut skip[..self.addons_left]);
+            ready!(Pin::new(&mut self.inner).poll_read(cx, &mut buf))?;
+            let n = buf.filled().len();
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+            }
+            self.addons_left -= n;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for VlessStream<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        // 还没写过数据时也要先把请求头发出去，否则上游不会响应
+        this.request_queued = true;
+        ready!(this.poll_drain(cx))?;
+        ready!(this.poll_response_header(cx))?;
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for VlessStream<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if !this.request_queued {
+            // 请求头与首包数据合并发送
+            this.request_queued = true;
+            this.pending.extend_from_slice(buf);
+            let _ = this.poll_drain(cx)?;
+            return Poll::Ready(Ok(buf.len()));
+        }
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        this.request_queued = true;
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        this.request_queued = true;
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_header_merged_with_first_write() {
+        let (client, mut upstream) = tokio::io::duplex(4096);
+        let mut stream = VlessStream::new(client, b"HDR".to_vec());
+
+        stream.write_all(b"data").await.unwrap();
+        stream.flush().await.unwrap();
+        let mut buf = [0u8; 7];
+        upstream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"HDRdata");
+
+        // 响应头 (含 3 字节附加数据) 被剥离
+        upstream.write_all(&[0, 3, 9, 9, 9]).await.unwrap();
+        upstream.write_all(b"reply").await.unwrap();
+        let mut reply = [0u8; 5];
+        stream.read_exact(&mut reply).await.unwrap();
+        assert_eq!(&reply, b"reply");
+    }
+
+    #[tokio::test]
+    async fn test_read_first_sends_header() {
+        let (client, mut upstream) = tokio::io::duplex(4096);
+        let mut stream = VlessStream::new(client, b"HDR".to_vec());
+
+        let server = tokio::spawn(async move {
+            let mut buf = [0u8; 3];
+            upstream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"HDR");
+            upstream.write_all(&[0, 0]).await.unwrap();
+            upstream.write_all(b"banner").await.unwrap();
+        });
+
+        let mut banner = [0u8; 6];
+        stream.read_exact(&mut banner).await.unwrap();
+        assert_eq!(&banner, b"banner");
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_bad_response_version() {
+        let (client, mut upstream) = tokio::io::duplex(4096);
+        let mut stream = VlessStream::new(client, b"HDR".to_vec());
+        upstream.write_all(&[7, 0]).await.unwrap();
+
+        let mut buf = [0u8; 1];
+        let err = stream.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,6 +1,14 @@
+pub mod mux;
 pub mod proxy_protocol;
+pub mod quic;
+pub mod shadowsocks;
 pub mod sniffer;
+pub mod socks;
 pub mod vless;
+pub mod vmess;
 
-pub use proxy_protocol::{is_proxy_protocol, parse_proxy_protocol, ProxyHeader};
+pub use proxy_protocol::{header_length, is_proxy_protocol, parse_proxy_protocol, HeaderLength, ProxyHeader};
+pub use shadowsocks::{ShadowsocksCodec, ShadowsocksServerStream};
+pub use socks::{SocksCodec, SocksCommand, SocksRequest};
 pub use vless::{VlessCodec, VlessRequest, VlessResponse};
+pub use vmess::{VmessCodec, VmessStream};
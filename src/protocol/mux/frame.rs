@@ -0,0 +1,320 @@
+use anyhow::{anyhow, Result};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::protocol::vless::Address;
+
+/// 元数据长度上限，超出视为无效帧 (正常元数据不超过 4 + 1 + 2 + 1 + 255 + 8 字节)
+pub const MAX_METADATA_LEN: usize = 512;
+
+/// 帧选项位
+pub mod option {
+    /// 帧带有数据
+    pub const DATA: u8 = 0x01;
+    /// 子连接因错误结束
+    pub const ERROR: u8 = 0x02;
+}
+
+/// 子连接状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionStatus {
+    /// 新建子连接，元数据带目标地址
+    New = 0x01,
+    /// 已有子连接的后续数据
+    Keep = 0x02,
+    /// 结束子连接
+    End = 0x03,
+    /// 保活，忽略
+    KeepAlive = 0x04,
+}
+
+impl SessionStatus {
+    pub fn from_u8(value: u8) -> Result<Self> {
+        match value {
+            0x01 => Ok(SessionStatus::New),
+            0x02 => Ok(SessionStatus::Keep),
+            0x03 => Ok(SessionStatus::End),
+            0x04 => Ok(SessionStatus::KeepAlive),
+            _ => Err(anyhow!("未知的 Mux 会话状态: {}", value)),
+        }
+    }
+}
+
+/// 子连接的网络类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetNetwork {
+    Tcp = 0x01,
+    Udp = 0x02,
+}
+
+impl TargetNetwork {
+    pub fn from_u8(value: u8) -> Result<Self> {
+        match value {
+            0x01 => Ok(TargetNetwork::Tcp),
+            0x02 => Ok(TargetNetwork::Udp),
+            _ => Err(anyhow!("未知的 Mux 网络类型: {}", value)),
+        }
+    }
+}
+
+/// 帧元数据: 会话 ID (2) + 状态 (1) + 选项 (1) + [网络类型 (1) + 端口 + 地址 + [XUDP 全局 ID (8)]]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameMetadata {
+    pub session_id: u16,
+    pub status: SessionStatus,
+    pub option: u8,
+    /// New 帧 (以及 UDP 的 Keep 帧) 携带的目标
+    pub target: Option<(TargetNetwork, Address)>,
+    /// XUDP 全局 ID，只出现在 UDP 的 New 帧
+    pub global_id: Option<[u8; 8]>,
+}
+
+impl FrameMetadata {
+    pub fn new(session_id: u16, status: SessionStatus) -> Self {
+        Self {
+            session_id,
+            status,
+            option: 0,
+            target: None,
+            global_id: None,
+        }
+    }
+
+    /// 帧是否带有数据
+    pub fn has_data(&self) -> bool {
+        self.option & option::DATA != 0
+    }
+
+    /// 子连接是否因错误结束
+    pub fn has_error(&self) -> bool {
+        self.option & option::ERROR != 0
+    }
+
+    /// 解析元数据 (不含长度前缀)
+    pub fn decode(mut buf: BytesMut) -> Result<Self> {
+        if buf.remaining() < 4 {
+            return Err(anyhow!("Mux 元数据过短: {} 字节", buf.remaining()));
+        }
+        let session_id = buf.get_u16();
+        let status = SessionStatus::from_u8(buf.get_u8())?;
+        let option = buf.get_u8();
+
+        // UDP 的 Keep 帧可以携带目标，以网络类型字节区分
+        let has_target = match status {
+            SessionStatus::New => true,
+            SessionStatus::Keep => buf.first() == Some(&(TargetNetwork::Udp as u8)),
+            SessionStatus::End | SessionStatus::KeepAlive => false,
+        };
+        let mut target = None;
+        let mut global_id = None;
+        if has_target {
+            if !buf.has_remaining() {
+                return Err(anyhow!("Mux New 帧缺少目标"));
+            }
+            let network = TargetNetwork::from_u8(buf.get_u8())?;
+            let address = Address::decode(&mut buf)?;
+            if status == SessionStatus::New && network == TargetNetwork::Udp && buf.remaining() >= 8 {
+                let mut id = [0u8; 8];
+                buf.copy_to_slice(&mut id);
+                global_id = Some(id);
+            }
+            target = Some((network, address));
+        }
+
+        Ok(Self {
+            session_id,
+            status,
+            option,
+            target,
+            global_id,
+        })
+    }
+
+    /// 编码元数据 (不含长度前缀)
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u16(self.session_id);
+        buf.put_u8(self.status as u8);
+        buf.put_u8(self.option);
+        if let Some((network, address)) = &self.target {
+            buf.put_u8(*network as u8);
+            address.encode(buf);
+            if let Some(id) = &self.global_id {
+                buf.put_slice(id);
+            }
+        }
+    }
+}
+
+/// Mux.cool 帧: 元数据长度 (2) + 元数据 + [数据长度 (2) + 数据]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub meta: FrameMetadata,
+    pub data: Option<Bytes>,
+}
+
+impl Frame {
+    /// 子连接的数据帧 (Keep)
+    pub fn keep(session_id: u16, data: Bytes) -> Self {
+        let mut meta = FrameMetadata::new(session_id, SessionStatus::Keep);
+        meta.option |= option::DATA;
+        Self { meta, data: Some(data) }
+    }
+
+    /// UDP 子连接的数据帧 (Keep)，携带数据报的目标或来源地址 (XUDP)
+    pub fn packet(session_id: u16, address: Address, data: Bytes) -> Self {
+        let mut frame = Self::keep(session_id, data);
+        frame.meta.target = Some((TargetNetwork::Udp, address));
+        frame
+    }
+
+    /// 结束子连接 (End)，`error` 表示因错误结束
+    pub fn end(session_id: u16, error: bool) -> Self {
+        let mut meta = FrameMetadata::new(session_id, SessionStatus::End);
+        if error {
+            meta.option |= option::ERROR;
+        }
+        Self { meta, data: None }
+    }
+
+    /// 编码整个帧，数据超过 65535 字节时返回错误
+    pub fn encode(&self, buf: &mut BytesMut) -> Result<()> {
+        let mut meta = BytesMut::with_capacity(32);
+        self.meta.encode(&mut meta);
+        buf.put_u16(meta.len() as u16);
+        buf.put_slice(&meta);
+        if let Some(data) = &self.data {
+            let len = u16::try_from(data.len()).map_err(|_| anyhow!("Mux 帧数据过长: {} 字节", data.len()))?;
+            buf.put_u16(len);
+            buf.put_slice(data);
+        }
+        Ok(())
+    }
+}
+
+/// 按帧切分 Mux 数据流
+#[derive(Debug, Default)]
+pub struct MuxCodec;
+
+impl Decoder for MuxCodec {
+    type Item = Frame;
+    type Error = anyhow::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame>> {
+        if src.len() < 2 {
+            return Ok(None);
+        }
+        let meta_len = u16::from_be_bytes([src[0], src[1]]) as usize;
+        if !(4..=MAX_METADATA_LEN).contains(&meta_len) {
+            return Err(anyhow!("无效的 Mux 元数据长度: {}", meta_len));
+        }
+        if src.len() < 2 + meta_len {
+            src.reserve(2 + meta_len - src.len());
+            return Ok(None);
+        }
+
+        // 选项位于元数据第 4 字节
+        let has_data = src[2 + 3] & option::DATA != 0;
+        let mut frame_len = 2 + meta_len;
+        if has_data {
+            if src.len() < frame_len + 2 {
+                src.reserve(frame_len + 2 - src.len());
+                return Ok(None);
+            }
+            let data_len = u16::from_be_bytes([src[frame_len], src[frame_len + 1]]) as usize;
+            frame_len += 2 + data_len;
+        }
+        if src.len() < frame_len {
+            src.reserve(frame_len - src.len());
+            return Ok(None);
+        }
+
+        let mut frame = src.split_to(frame_len);
+        frame.advance(2);
+        let meta = FrameMetadata::decode(frame.split_to(meta_len))?;
+        let data = has_data.then(|| {
+            frame.advance(2);
+            frame.freeze()
+        });
+        Ok(Some(Frame { meta, data }))
+    }
+}
+
+impl Encoder<Frame> for MuxCodec {
+    type Error = anyhow::Error;
+
+    fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> Result<()> {
+        frame.encode(dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_new_frame_round_trip() {
+        let mut meta = FrameMetadata::new(7, SessionStatus::New);
+        meta.option = option::DATA;
+        meta.target = Some((TargetNetwork::Tcp, Address::Domain("example.com".to_string(), 443)));
+        let frame = Frame {
+            meta,
+            data: Some(Bytes::from_static(b"hello")),
+        };
+
+        let mut buf = BytesMut::new();
+        MuxCodec.encode(frame.clone(), &mut buf).unwrap();
+        // 元数据: id(2) + 状态(1) + 选项(1) + 网络(1) + 端口(2) + 类型(1) + 长度(1) + 域名(11)
+        assert_eq!(&buf[..2], &[0, 20]);
+
+        // 不完整时等待更多数据
+        let mut partial = BytesMut::from(&buf[..buf.len() - 1]);
+        assert_eq!(MuxCodec.decode(&mut partial).unwrap(), None);
+
+        buf.extend_from_slice(&{
+            let mut end = BytesMut::new();
+            Frame::end(7, true).encode(&mut end).unwrap();
+            end
+        });
+        assert_eq!(MuxCodec.decode(&mut buf).unwrap(), Some(frame));
+        let end = MuxCodec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(end.meta.status, SessionStatus::End);
+        assert!(end.meta.has_error());
+        assert_eq!(end.data, None);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_udp_frames_carry_target() {
+        // UDP New 帧带 XUDP 全局 ID
+        let mut meta = FrameMetadata::new(1, SessionStatus::New);
+        meta.target = Some((TargetNetwork::Udp, Address::Ipv4(Ipv4Addr::new(8, 8, 8, 8), 53)));
+        meta.global_id = Some([9; 8]);
+        let mut buf = BytesMut::new();
+        meta.encode(&mut buf);
+        assert_eq!(FrameMetadata::decode(buf).unwrap(), meta);
+
+        // UDP Keep 帧可带目标，TCP Keep 帧只有基本字段
+        let mut keep = FrameMetadata::new(1, SessionStatus::Keep);
+        keep.option = option::DATA;
+        keep.target = Some((TargetNetwork::Udp, Address::Ipv4(Ipv4Addr::new(1, 1, 1, 1), 53)));
+        let mut buf = BytesMut::new();
+        keep.encode(&mut buf);
+        assert_eq!(FrameMetadata::decode(buf).unwrap(), keep);
+
+        let frame = Frame::keep(2, Bytes::from_static(b"x"));
+        let mut buf = BytesMut::new();
+        frame.meta.encode(&mut buf);
+        assert_eq!(buf.len(), 4);
+        assert_eq!(FrameMetadata::decode(buf).unwrap().target, None);
+    }
+
+    #[test]
+    fn test_invalid_frames() {
+        assert!(FrameMetadata::decode(BytesMut::from(&[0u8, 1, 9, 0][..])).is_err());
+        // New 帧缺少目标
+        assert!(FrameMetadata::decode(BytesMut::from(&[0u8, 1, 1, 0][..])).is_err());
+        // 元数据长度不合法
+        assert!(MuxCodec.decode(&mut BytesMut::from(&[0u8, 2, 0, 0][..])).is_err());
+    }
+}
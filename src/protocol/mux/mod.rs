@@ -0,0 +1,9 @@
+//! Mux.cool 多路复用
+//!
+//! VLESS 请求命令为 Mux 时，连接上承载的是 Mux.cool 帧流: 每个帧属于一个以会话 ID
+//! 区分的子连接，New 帧给出子连接的目标，Keep 帧传递数据，End 帧结束子连接。
+//! 本模块只负责帧的编解码，子连接的转发由处理器完成。
+
+mod frame;
+
+pub use frame::{option, Frame, FrameMetadata, MuxCodec, SessionStatus, TargetNetwork, MAX_METADATA_LEN};
@@ -12,6 +12,98 @@ pub struct ProxyHeader {
     pub source_addr: SocketAddr,
     /// 目标地址
     pub dest_addr: SocketAddr,
+    /// v2 头部携带的 TLV (类型, 值)，按出现顺序
+    pub tlvs: Vec<(u8, Vec<u8>)>,
+}
+
+/// PP2_TYPE_ALPN: 负载均衡器协商出的 ALPN
+pub const PP2_TYPE_ALPN: u8 = 0x01;
+/// PP2_TYPE_AUTHORITY: 客户端请求的主机名 (通常是负载均衡器看到的 SNI)
+pub const PP2_TYPE_AUTHORITY: u8 = 0x02;
+/// PP2_TYPE_UNIQUE_ID: 负载均衡器为连接分配的唯一 ID
+pub const PP2_TYPE_UNIQUE_ID: u8 = 0x05;
+
+impl ProxyHeader {
+    /// 只包含地址的头部
+    pub fn new(source_addr: SocketAddr, dest_addr: SocketAddr) -> Self {
+        Self {
+            source_addr,
+            dest_addr,
+            tlvs: Vec::new(),
+        }
+    }
+
+    /// 第一个指定类型的 TLV 的值
+    pub fn tlv(&self, tlv_type: u8) -> Option<&[u8]> {
+        self.tlvs.iter().find(|(t, _)| *t == tlv_type).map(|(_, value)| value.as_slice())
+    }
+
+    /// 负载均衡器协商出的 ALPN
+    pub fn alpn(&self) -> Option<&[u8]> {
+        self.tlv(PP2_TYPE_ALPN)
+    }
+
+    /// 负载均衡器看到的主机名 (SNI)，不是合法 UTF-8 时返回 `None`
+    pub fn authority(&self) -> Option<&str> {
+        std::str::from_utf8(self.tlv(PP2_TYPE_AUTHORITY)?).ok()
+    }
+
+    /// 负载均衡器为连接分配的唯一 ID
+    pub fn unique_id(&self) -> Option<&[u8]> {
+        self.tlv(PP2_TYPE_UNIQUE_ID)
+    }
+
+    /// 编码为指定版本 (1 或 2) 的 PROXY 头，地址族不一致时都按 IPv6 编码，TLV 只在 v2 中发送
+    pub fn encode(&self, version: u8) -> Result<Vec<u8>> {
+        let (src, dst) = match (self.source_addr.ip(), self.dest_addr.ip()) {
+            (IpAddr::V4(src), IpAddr::V4(dst)) => (IpAddr::V4(src), IpAddr::V4(dst)),
+            (src, dst) => (IpAddr::V6(to_ipv6(src)), IpAddr::V6(to_ipv6(dst))),
+        };
+        let (src_port, dst_port) = (self.source_addr.port(), self.dest_addr.port());
+        match version {
+            1 => {
+                let family = if src.is_ipv4() { "TCP4" } else { "TCP6" };
+                Ok(format!("PROXY {} {} {} {} {}\r\n", family, src, dst, src_port, dst_port).into_bytes())
+            }
+            2 => {
+                let mut buf = PROXY_V2_SIGNATURE.to_vec();
+                // 版本 2 + PROXY 命令
+                buf.push(0x21);
+                let addresses = match (src, dst) {
+                    (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                        // AF_INET + STREAM
+                        buf.push(0x11);
+                        [src.octets().as_slice(), dst.octets().as_slice()].concat()
+                    }
+                    (src, dst) => {
+                        // AF_INET6 + STREAM
+                        buf.push(0x21);
+                        [to_ipv6(src).octets().as_slice(), to_ipv6(dst).octets().as_slice()].concat()
+                    }
+                };
+                let tlv_len: usize = self.tlvs.iter().map(|(_, value)| 3 + value.len()).sum();
+                let len = u16::try_from(addresses.len() + 4 + tlv_len).map_err(|_| anyhow!("Proxy Protocol v2 TLV 过长"))?;
+                buf.extend_from_slice(&len.to_be_bytes());
+                buf.extend_from_slice(&addresses);
+                buf.extend_from_slice(&src_port.to_be_bytes());
+                buf.extend_from_slice(&dst_port.to_be_bytes());
+                for (tlv_type, value) in &self.tlvs {
+                    buf.push(*tlv_type);
+                    buf.extend_from_slice(&(value.len() as u16).to_be_bytes());
+                    buf.extend_from_slice(value);
+                }
+                Ok(buf)
+            }
+            other => Err(anyhow!("不支持的 Proxy Protocol 版本: {}", other)),
+        }
+    }
+}
+
+fn to_ipv6(ip: IpAddr) -> Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
 }
 
 /// Proxy Protocol v1 签名
@@ -93,10 +185,7 @@ fn parse_v1(data: &[u8]) -> Result<(ProxyHeader, usize)> {
     };
 
     Ok((
-        ProxyHeader {
-            source_addr: src_addr,
-            dest_addr: dst_addr,
-        },
+        ProxyHeader::new(src_addr, dst_addr),
         end + 2, // 消费的字节数 (包括 \r\n)
     ))
 }
@@ -124,6 +213,13 @@ fn parse_v2(data: &[u8]) -> Result<(ProxyHeader, usize)> {
         return Err(anyhow!("数据不完整"));
     }
 
+    // 地址块的长度，之后到 addr_len 为止都是 TLV
+    let addr_block = match family {
+        0x1 => 12,
+        0x2 => 36,
+        0x3 => 216,
+        _ => 0,
+    };
     let (src_addr, dst_addr) = match family {
         0x1 => {
             // IPv4
@@ -164,16 +260,175 @@ fn parse_v2(data: &[u8]) -> Result<(ProxyHeader, usize)> {
         }
     };
 
+    let tlvs = match data.get(16 + addr_block..16 + addr_len) {
+        Some(tlv_data) => parse_tlvs(tlv_data),
+        None => Vec::new(),
+    };
+
     Ok((
         ProxyHeader {
             source_addr: src_addr,
             dest_addr: dst_addr,
+            tlvs,
         },
         16 + addr_len,
     ))
 }
 
+/// 解析 v2 的 TLV: 类型 (1) + 长度 (2) + 值；遇到不完整的 TLV 时停止，只保留之前的部分
+fn parse_tlvs(mut data: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    let mut tlvs = Vec::new();
+    while data.len() >= 3 {
+        let len = u16::from_be_bytes([data[1], data[2]]) as usize;
+        let Some(value) = data.get(3..3 + len) else {
+            break;
+        };
+        tlvs.push((data[0], value.to_vec()));
+        data = &data[3 + len..];
+    }
+    tlvs
+}
+
 /// 检查数据是否以 Proxy Protocol 头部开始
 pub fn is_proxy_protocol(data: &[u8]) -> bool {
     data.starts_with(PROXY_V1_SIGNATURE) || (data.len() >= 12 && data[..12] == *PROXY_V2_SIGNATURE)
 }
+
+/// Proxy Protocol v1 头部的最大长度 (含 CRLF)
+const PROXY_V1_MAX_LEN: usize = 107;
+
+/// 按已收到的数据判断 Proxy Protocol 头部的长度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderLength {
+    /// 不是 Proxy Protocol 头部
+    Absent,
+    /// 可能是头部，需要更多数据才能判断
+    Incomplete,
+    /// 头部完整，长度为给定字节数
+    Complete(usize),
+}
+
+/// 判断 `data` 开头的 Proxy Protocol 头部是否完整
+///
+/// 只检查签名与长度字段，头部内容由 [`parse_proxy_protocol`] 解析。
+pub fn header_length(data: &[u8]) -> HeaderLength {
+    let matches_prefix = |signature: &[u8]| {
+        let n = data.len().min(signature.len());
+        data[..n] == signature[..n]
+    };
+    if matches_prefix(PROXY_V1_SIGNATURE) {
+        return match data.windows(2).position(|w| w == b"\r\n") {
+            Some(end) if end + 2 <= PROXY_V1_MAX_LEN => HeaderLength::Complete(end + 2),
+            Some(_) => HeaderLength::Absent,
+            None if data.len() < PROXY_V1_MAX_LEN => HeaderLength::Incomplete,
+            None => HeaderLength::Absent,
+        };
+    }
+    if matches_prefix(PROXY_V2_SIGNATURE) {
+        if data.len() < 16 {
+            return HeaderLength::Incomplete;
+        }
+        return HeaderLength::Complete(16 + u16::from_be_bytes([data[14], data[15]]) as usize);
+    }
+    HeaderLength::Absent
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_round_trip() {
+        let header = ProxyHeader::new("10.20.0.5:40000".parse().unwrap(), "10.0.0.1:443".parse().unwrap());
+        let v1 = header.encode(1).unwrap();
+        assert_eq!(v1, b"PROXY TCP4 10.20.0.5 10.0.0.1 40000 443\r\n");
+        for version in [1, 2] {
+            let encoded = header.encode(version).unwrap();
+            let (parsed, consumed) = parse_proxy_protocol(&encoded).unwrap();
+            assert_eq!(consumed, encoded.len());
+            assert_eq!(parsed.source_addr, header.source_addr);
+            assert_eq!(parsed.dest_addr, header.dest_addr);
+        }
+
+        // 地址族不一致时按 IPv6 编码
+        let mixed = ProxyHeader::new("[2001:db8::1]:1234".parse().unwrap(), "127.0.0.1:443".parse().unwrap());
+        let (parsed, _) = parse_proxy_protocol(&mixed.encode(2).unwrap()).unwrap();
+        assert_eq!(parsed.source_addr, mixed.source_addr);
+        assert_eq!(parsed.dest_addr, "[::ffff:127.0.0.1]:443".parse().unwrap());
+        assert!(header.encode(3).is_err());
+    }
+
+    /// 构造一个 IPv4 的 v2 头部，地址之后附加 `tlv_data`
+    fn v2_with_tlvs(tlv_data: &[u8]) -> Vec<u8> {
+        let mut buf = PROXY_V2_SIGNATURE.to_vec();
+        buf.extend_from_slice(&[0x21, 0x11]);
+        buf.extend_from_slice(&(12 + tlv_data.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&[192, 168, 1, 1, 10, 0, 0, 1]);
+        buf.extend_from_slice(&56789u16.to_be_bytes());
+        buf.extend_from_slice(&443u16.to_be_bytes());
+        buf.extend_from_slice(tlv_data);
+        buf
+    }
+
+    #[test]
+    fn test_parse_v2_tlvs() {
+        let mut tlvs = vec![PP2_TYPE_ALPN, 0x00, 0x02];
+        tlvs.extend_from_slice(b"h2");
+        tlvs.extend_from_slice(&[PP2_TYPE_AUTHORITY, 0x00, 0x0b]);
+        tlvs.extend_from_slice(b"example.com");
+        // 未知类型 (PP2_TYPE_NOOP) 同样保留
+        tlvs.extend_from_slice(&[0x04, 0x00, 0x00]);
+        tlvs.extend_from_slice(&[PP2_TYPE_UNIQUE_ID, 0x00, 0x04, 0xde, 0xad, 0xbe, 0xef]);
+        let mut data = v2_with_tlvs(&tlvs);
+        data.extend_from_slice(b"payload");
+
+        let (header, consumed) = parse_proxy_protocol(&data).unwrap();
+        assert_eq!(&data[consumed..], b"payload");
+        assert_eq!(header.source_addr, "192.168.1.1:56789".parse().unwrap());
+        assert_eq!(header.alpn(), Some(&b"h2"[..]));
+        assert_eq!(header.authority(), Some("example.com"));
+        assert_eq!(header.unique_id(), Some(&[0xde, 0xad, 0xbe, 0xef][..]));
+        assert_eq!(header.tlvs.len(), 4);
+
+        // TLV 也能编码回去
+        let (round_trip, _) = parse_proxy_protocol(&header.encode(2).unwrap()).unwrap();
+        assert_eq!(round_trip.tlvs, header.tlvs);
+    }
+
+    #[test]
+    fn test_header_length() {
+        let v1 = b"PROXY TCP4 10.20.0.5 10.0.0.1 40000 443\r\n";
+        assert_eq!(header_length(b""), HeaderLength::Incomplete);
+        assert_eq!(header_length(b"PRO"), HeaderLength::Incomplete);
+        assert_eq!(header_length(&v1[..20]), HeaderLength::Incomplete);
+        assert_eq!(header_length(&[&v1[..], b"payload"].concat()), HeaderLength::Complete(v1.len()));
+        assert_eq!(header_length(&[b"PROXY ", &[b'x'; 120][..]].concat()), HeaderLength::Absent);
+
+        let v2 = v2_with_tlvs(&[PP2_TYPE_ALPN, 0x00, 0x02, b'h', b'2']);
+        assert_eq!(header_length(&v2[..6]), HeaderLength::Incomplete);
+        assert_eq!(header_length(&v2[..15]), HeaderLength::Incomplete);
+        assert_eq!(header_length(&v2[..16]), HeaderLength::Complete(v2.len()));
+
+        assert_eq!(header_length(b"\x16\x03\x01"), HeaderLength::Absent);
+        assert_eq!(header_length(b"GET / HTTP/1.1"), HeaderLength::Absent);
+    }
+
+    #[test]
+    fn test_malformed_tlv_stops_parsing() {
+        // 第二个 TLV 声明的长度超出头部，只保留第一个，地址照常解析
+        let mut tlvs = vec![PP2_TYPE_AUTHORITY, 0x00, 0x03];
+        tlvs.extend_from_slice(b"a.b");
+        tlvs.extend_from_slice(&[PP2_TYPE_UNIQUE_ID, 0x00, 0x20, 0x01]);
+        let data = v2_with_tlvs(&tlvs);
+
+        let (header, consumed) = parse_proxy_protocol(&data).unwrap();
+        assert_eq!(consumed, data.len());
+        assert_eq!(header.dest_addr, "10.0.0.1:443".parse().unwrap());
+        assert_eq!(header.authority(), Some("a.b"));
+        assert_eq!(header.unique_id(), None);
+
+        // 截断的 TLV 头
+        let (header, _) = parse_proxy_protocol(&v2_with_tlvs(&[PP2_TYPE_ALPN, 0x00])).unwrap();
+        assert!(header.tlvs.is_empty());
+    }
+}
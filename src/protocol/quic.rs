@@ -0,0 +1,386 @@
+//! QUIC Initial 包解析 (RFC 9000 / RFC 9001)，用于嗅探 HTTP/3 流量的 SNI
+//!
+//! Initial 包的密钥只由目标连接 ID 派生，任何一方都可以解密。解除头部保护并解密负载后，
+//! 按偏移重组 CRYPTO 帧得到 TLS ClientHello，再复用 TLS 嗅探的解析逻辑。
+//! 目前只支持 QUIC v1，ClientHello 必须完整地包含在同一个数据报中 (可以是合并的多个包)。
+
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::aes::cipher::BlockEncrypt;
+use aes_gcm::aes::Aes128;
+use aes_gcm::{Aes128Gcm, KeyInit, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+use super::sniffer::{parse_client_hello, ClientHelloInfo};
+
+/// QUIC v1 版本号
+const QUIC_V1: u32 = 0x0000_0001;
+
+/// QUIC v1 Initial 密钥派生使用的盐 (RFC 9001 5.2)
+const INITIAL_SALT_V1: [u8; 20] = [
+    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad, 0xcc, 0xbb, 0x7f,
+    0x0a,
+];
+
+/// 连接 ID 的最大长度
+const MAX_CID_LEN: usize = 20;
+
+/// 头部保护的采样长度
+const SAMPLE_LEN: usize = 16;
+
+/// 最多重组的 CRYPTO 数据长度
+const MAX_CRYPTO_LEN: usize = 16 * 1024;
+
+/// Initial 包的类型
+const PACKET_TYPE_INITIAL: u8 = 0x00;
+
+/// 客户端 Initial 包的密钥
+struct InitialKeys {
+    key: [u8; 16],
+    iv: [u8; 12],
+    hp: [u8; 16],
+}
+
+impl InitialKeys {
+    /// 由客户端选择的目标连接 ID 派生
+    fn client(dcid: &[u8]) -> Self {
+        let (_, initial) = Hkdf::<Sha256>::extract(Some(&INITIAL_SALT_V1), dcid);
+        let mut secret = [0u8; 32];
+        expand_label(&initial, "client in", &mut secret);
+        let client = Hkdf::<Sha256>::from_prk(&secret).expect("SHA-256 输出可作为 PRK");
+
+        let mut keys = Self {
+            key: [0; 16],
+            iv: [0; 12],
+            hp: [0; 16],
+        };
+        expand_label(&client, "quic key", &mut keys.key);
+        expand_label(&client, "quic iv", &mut keys.iv);
+        expand_label(&client, "quic hp", &mut keys.hp);
+        keys
+    }
+
+    /// 头部保护掩码: AES-ECB(hp, sample)
+    fn header_mask(&self, sample: &[u8; SAMPLE_LEN]) -> [u8; SAMPLE_LEN] {
+        let mut block = (*sample).into();
+        Aes128::new(&self.hp.into()).encrypt_block(&mut block);
+        block.into()
+    }
+
+    /// 包号对应的 AEAD nonce
+    fn nonce(&self, packet_number: u64) -> [u8; 12] {
+        let mut nonce = self.iv;
+        for (n, b) in nonce[4..].iter_mut().zip(packet_number.to_be_bytes()) {
+            *n ^= b;
+        }
+        nonce
+    }
+}
+
+/// TLS 1.3 的 HKDF-Expand-Label，上下文为空
+fn expand_label(hkdf: &Hkdf<Sha256>, label: &str, out: &mut [u8]) {
+    let label = format!("tls13 {}", label);
+    let mut info = Vec::with_capacity(4 + label.len());
+    info.extend_from_slice(&(out.len() as u16).to_be_bytes());
+    info.push(label.len() as u8);
+    info.extend_from_slice(label.as_bytes());
+    info.push(0);
+    hkdf.expand(&info, out).expect("HKDF 输出长度有效");
+}
+
+/// 读取 QUIC 变长整数
+fn read_varint(data: &[u8], pos: &mut usize) -> Option<u64> {
+    let first = *data.get(*pos)?;
+    let len = 1usize << (first >> 6);
+    let bytes = data.get(*pos..*pos + len)?;
+    let value = bytes[1..].iter().fold((first & 0x3f) as u64, |v, b| (v << 8) | *b as u64);
+    *pos += len;
+    Some(value)
+}
+
+/// QUIC v1 长包头
+struct LongHeader<'a> {
+    packet_type: u8,
+    dcid: &'a [u8],
+    /// 包号在包中的偏移
+    pn_offset: usize,
+    /// 包的总长度
+    len: usize,
+}
+
+/// 解析长包头，不是 QUIC v1 长包头或数据不完整时返回 `None`
+fn parse_long_header(data: &[u8]) -> Option<LongHeader<'_>> {
+    let first = *data.first()?;
+    // 长包头 (0x80) + 固定位 (0x40)
+    if first & 0xc0 != 0xc0 {
+        return None;
+    }
+    let version = u32::from_be_bytes(data.get(1..5)?.try_into().ok()?);
+    if version != QUIC_V1 {
+        return None;
+    }
+    let mut pos = 5;
+    let dcid_len = *data.get(pos)? as usize;
+    if dcid_len > MAX_CID_LEN {
+        return None;
+    }
+    let dcid = data.get(pos + 1..pos + 1 + dcid_len)?;
+    pos += 1 + dcid_len;
+    let scid_len = *data.get(pos)? as usize;
+    if scid_len > MAX_CID_LEN {
+        return None;
+    }
+    pos += 1 + scid_len;
+
+    let packet_type = (first >> 4) & 0x03;
+    if packet_type == PACKET_TYPE_INITIAL {
+        let token_len = read_varint(data, &mut pos)? as usize;
+        pos = pos.checked_add(token_len)?;
+    }
+    let length = read_varint(data, &mut pos)? as usize;
+    let len = pos.checked_add(length)?;
+    if len > data.len() {
+        return None;
+    }
+    Some(LongHeader {
+        packet_type,
+        dcid,
+        pn_offset: pos,
+        len,
+    })
+}
+
+/// 解除头部保护并解密 Initial 包的负载
+fn decrypt_initial(packet: &[u8], header: &LongHeader) -> Option<Vec<u8>> {
+    let pn_offset = header.pn_offset;
+    // 采样从包号之后 4 字节开始，与包号的实际长度无关
+    let sample: [u8; SAMPLE_LEN] = packet.get(pn_offset + 4..pn_offset + 4 + SAMPLE_LEN)?.try_into().ok()?;
+    let keys = InitialKeys::client(header.dcid);
+    let mask = keys.header_mask(&sample);
+
+    let mut aad = packet[..pn_offset + 4].to_vec();
+    // 长包头只保护低 4 位
+    aad[0] ^= mask[0] & 0x0f;
+    let pn_len = (aad[0] & 0x03) as usize + 1;
+    aad.truncate(pn_offset + pn_len);
+    let mut packet_number = 0u64;
+    for (i, b) in aad[pn_offset..].iter_mut().enumerate() {
+        *b ^= mask[1 + i];
+        packet_number = (packet_number << 8) | *b as u64;
+    }
+
+    let cipher = Aes128Gcm::new(&keys.key.into());
+    let payload = Payload {
+        msg: packet.get(pn_offset + pn_len..header.len)?,
+        aad: &aad,
+    };
+    cipher.decrypt(Nonce::from_slice(&keys.nonce(packet_number)), payload).ok()
+}
+
+/// 按偏移重组的 CRYPTO 数据
+#[derive(Default)]
+struct CryptoStream {
+    data: Vec<u8>,
+    filled: Vec<bool>,
+}
+
+impl CryptoStream {
+    fn insert(&mut self, offset: usize, bytes: &[u8]) -> Option<()> {
+        let end = offset.checked_add(bytes.len())?;
+        if end > MAX_CRYPTO_LEN {
+            return None;
+        }
+        if self.data.len() < end {
+            self.data.resize(end, 0);
+            self.filled.resize(end, false);
+        }
+        self.data[offset..end].copy_from_slice(bytes);
+        self.filled[offset..end].fill(true);
+        Some(())
+    }
+
+    /// 从偏移 0 开始连续收到的数据中完整的 ClientHello 握手消息
+    fn client_hello(&self) -> Option<&[u8]> {
+        let contiguous = self.filled.iter().position(|filled| !filled).unwrap_or(self.filled.len());
+        let data = &self.data[..contiguous];
+        if data.len() < 4 || data[0] != 0x01 {
+            return None;
+        }
+        let len = ((data[1] as usize) << 16) | ((data[2] as usize) << 8) | data[3] as usize;
+        data.get(..4 + len)
+    }
+}
+
+/// 解析 Initial 包负载中的帧，收集 CRYPTO 数据
+fn read_frames(payload: &[u8], crypto: &mut CryptoStream) -> Option<()> {
+    let mut pos = 0;
+    while pos < payload.len() {
+        match read_varint(payload, &mut pos)? {
+            // PADDING、PING
+            0x00 | 0x01 => {}
+            // ACK: 最大确认号、延迟、区间数、第一个区间，之后每个区间两个值，带 ECN 时再加三个计数
+            frame_type @ (0x02 | 0x03) => {
+                read_varint(payload, &mut pos)?;
+                read_varint(payload, &mut pos)?;
+                let ranges = read_varint(payload, &mut pos)?;
+                read_varint(payload, &mut pos)?;
+                for _ in 0..ranges {
+                    read_varint(payload, &mut pos)?;
+                    read_varint(payload, &mut pos)?;
+                }
+                if frame_type == 0x03 {
+                    for _ in 0..3 {
+                        read_varint(payload, &mut pos)?;
+                    }
+                }
+            }
+            // CRYPTO
+            0x06 => {
+                let offset = read_varint(payload, &mut pos)? as usize;
+                let len = read_varint(payload, &mut pos)? as usize;
+                let bytes = payload.get(pos..pos.checked_add(len)?)?;
+                crypto.insert(offset, bytes)?;
+                pos += len;
+            }
+            // CONNECTION_CLOSE
+            0x1c => break,
+            _ => return None,
+        }
+    }
+    Some(())
+}
+
+/// 从 UDP 数据报中的 QUIC Initial 包解析 ClientHello
+pub fn sniff_quic_client_hello(datagram: &[u8]) -> Option<ClientHelloInfo> {
+    let mut crypto = CryptoStream::default();
+    let mut rest = datagram;
+    // 合并的包依次排列，短包头 (或数据报末尾的填充) 之后不会再有长包头
+    while rest.first().is_some_and(|b| b & 0x80 != 0) {
+        let header = parse_long_header(rest)?;
+        if header.packet_type == PACKET_TYPE_INITIAL {
+            let payload = decrypt_initial(rest, &header)?;
+            read_frames(&payload, &mut crypto)?;
+        }
+        rest = &rest[header.len..];
+    }
+    parse_client_hello(crypto.client_hello()?)
+}
+
+/// 嗅探 QUIC Initial 包中的 SNI
+pub fn sniff_quic_sni(datagram: &[u8]) -> Option<String> {
+    sniff_quic_client_hello(datagram)?.sni
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::sniffer::tests::client_hello_with;
+
+    fn crypto_frame(offset: usize, data: &[u8]) -> Vec<u8> {
+        // 偏移与长度都用 2 字节变长整数编码
+        let mut frame = vec![0x06];
+        frame.extend_from_slice(&(0x4000 | offset as u16).to_be_bytes());
+        frame.extend_from_slice(&(0x4000 | data.len() as u16).to_be_bytes());
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    /// 按客户端的方式构造并保护一个 Initial 包，负载填充到 `padded_len`
+    fn protect_initial(dcid: &[u8], packet_number: u32, frames: &[u8], padded_len: usize) -> Vec<u8> {
+        let keys = InitialKeys::client(dcid);
+        let mut payload = frames.to_vec();
+        payload.resize(padded_len.max(payload.len()), 0);
+
+        // 4 字节包号
+        let mut packet = vec![0xc3];
+        packet.extend_from_slice(&QUIC_V1.to_be_bytes());
+        packet.push(dcid.len() as u8);
+        packet.extend_from_slice(dcid);
+        packet.push(0);
+        packet.push(0);
+        packet.extend_from_slice(&(0x4000 | (4 + payload.len() + 16) as u16).to_be_bytes());
+        let pn_offset = packet.len();
+        packet.extend_from_slice(&packet_number.to_be_bytes());
+
+        let cipher = Aes128Gcm::new(&keys.key.into());
+        let sealed = cipher
+            .encrypt(
+                Nonce::from_slice(&keys.nonce(packet_number as u64)),
+                Payload {
+                    msg: &payload,
+                    aad: &packet,
+                },
+            )
+            .unwrap();
+        packet.extend_from_slice(&sealed);
+
+        let sample: [u8; SAMPLE_LEN] = packet[pn_offset + 4..pn_offset + 4 + SAMPLE_LEN].try_into().unwrap();
+        let mask = keys.header_mask(&sample);
+        packet[0] ^= mask[0] & 0x0f;
+        for i in 0..4 {
+            packet[pn_offset + i] ^= mask[1 + i];
+        }
+        packet
+    }
+
+    #[test]
+    fn test_initial_keys_rfc9001() {
+        // RFC 9001 附录 A.1 / A.2
+        let keys = InitialKeys::client(&hex::decode("8394c8f03e515708").unwrap());
+        assert_eq!(hex::encode(keys.key), "1f369613dd76d5467730efcbe3b1a22d");
+        assert_eq!(hex::encode(keys.iv), "fa044b2f42a3fd3b46fb255c");
+        assert_eq!(hex::encode(keys.hp), "9f50449e04a0e810283a1e9933adedd2");
+        let sample = hex::decode("d1b1c98dd7689fb8ec11d242b123dc9b").unwrap();
+        let mask = keys.header_mask(&sample.try_into().unwrap());
+        assert_eq!(hex::encode(&mask[..5]), "437b9aec36");
+    }
+
+    #[test]
+    fn test_sniff_chrome_style_initial() {
+        // 与 Chrome 一样把 ClientHello 拆成多个乱序的 CRYPTO 帧，中间夹杂 PING 与 PADDING，
+        // 并把数据报填充到 1200 字节以上
+        let hello = client_hello_with(Some("www.google.com"), 300);
+        let (a, rest) = hello.split_at(100);
+        let (b, c) = rest.split_at(150);
+        let mut frames = crypto_frame(100, b);
+        frames.extend_from_slice(&[0x01, 0x00, 0x00]);
+        frames.extend_from_slice(&crypto_frame(250, c));
+        frames.push(0x01);
+        frames.extend_from_slice(&crypto_frame(0, a));
+        let mut datagram = protect_initial(&[0x5a; 8], 1, &frames, 1162);
+        datagram.resize(1250, 0);
+
+        assert_eq!(sniff_quic_sni(&datagram), Some("www.google.com".to_string()));
+
+        // 被篡改的包无法解密
+        let mut tampered = datagram.clone();
+        tampered[40] ^= 0xff;
+        assert_eq!(sniff_quic_sni(&tampered), None);
+    }
+
+    #[test]
+    fn test_sniff_coalesced_initials() {
+        // ClientHello 分在同一个数据报中合并的两个 Initial 包里
+        let hello = client_hello_with(Some("quic.example.com"), 64);
+        let (a, b) = hello.split_at(60);
+        let dcid = [0x11; 12];
+        let mut datagram = protect_initial(&dcid, 0, &crypto_frame(0, a), 64);
+        datagram.extend_from_slice(&protect_initial(&dcid, 1, &crypto_frame(60, b), 64));
+        assert_eq!(sniff_quic_sni(&datagram), Some("quic.example.com".to_string()));
+
+        // 只有第一个包时 ClientHello 不完整
+        let first = protect_initial(&dcid, 0, &crypto_frame(0, a), 64);
+        assert_eq!(sniff_quic_sni(&first), None);
+    }
+
+    #[test]
+    fn test_not_quic() {
+        assert_eq!(sniff_quic_sni(b""), None);
+        // DNS 查询
+        assert_eq!(sniff_quic_sni(&hex::decode("abcd01000001000000000000076578616d706c6503636f6d0000010001").unwrap()), None);
+        // 其他 QUIC 版本
+        let mut packet = protect_initial(&[0x22; 8], 0, &crypto_frame(0, b"x"), 64);
+        packet[1..5].copy_from_slice(&0x6b33_43cfu32.to_be_bytes());
+        assert_eq!(sniff_quic_sni(&packet), None);
+    }
+}
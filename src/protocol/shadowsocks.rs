@@ -0,0 +1,290 @@
+//! Shadowsocks AEAD 入站 (SIP004)
+//!
+//! 支持 aes-128-gcm / aes-256-gcm，目前只转发 TCP。请求的第一个数据块以 SOCKS 风格的
+//! 目标地址开头，其后为首包数据；响应使用服务端自己的盐，在首次写入时发出。
+//! 复用出站的数据块编解码，2022 系列暂不支持作为入站。
+
+use anyhow::{anyhow, Result};
+use bytes::{Buf, BytesMut};
+use rand::RngCore;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+
+use crate::outbound::shadowsocks::{Decoder, Encoder, Method};
+use crate::protocol::vless::Address;
+
+/// 首个数据块的最大长度 (盐 + 加密长度 + 最大负载)，超过仍未解出请求视为无效连接
+const MAX_FIRST_CHUNK: usize = 32 + 2 + 16 + 0x3FFF + 16;
+
+/// Shadowsocks 入站的请求解码器
+#[derive(Debug, Clone)]
+pub struct ShadowsocksCodec {
+    method: Method,
+    key: Vec<u8>,
+}
+
+impl ShadowsocksCodec {
+    /// 按入站配置的加密方式与密码创建，配置错误在启动时报告
+    pub fn new(method: &str, password: &str) -> Result<Self> {
+        let method = Method::from_name(method)?;
+        if method.is_2022() {
+            return Err(anyhow!("Shadowsocks 入站暂不支持 2022 系列加密方式"));
+        }
+        let key = method.derive_key(password)?;
+        Ok(Self { method, key })
+    }
+
+    /// 读取并解密第一个数据块，解析出目标地址
+    ///
+    /// 只借用连接，失败时调用方仍可以继续处理原始连接 (例如读完剩余数据再关闭)。
+    pub async fn read_request<S>(&self, stream: &mut S) -> Result<ShadowsocksRequest>
+    where
+        S: AsyncRead + Unpin + ?Sized,
+    {
+        let mut decoder = Decoder::new(self.method, &self.key, &[]);
+        let mut incoming = BytesMut::new();
+        let mut plaintext = BytesMut::new();
+        let mut consumed = 0;
+
+        while plaintext.is_empty() {
+            if consumed > MAX_FIRST_CHUNK {
+                return Err(anyhow!("Shadowsocks 首个数据块过长"));
+            }
+            let n = stream.read_buf(&mut incoming).await?;
+            if n == 0 {
+                return Err(anyhow!("客户端在发送 Shadowsocks 请求前关闭了连接"));
+            }
+            consumed += n;
+            decoder.decode(&mut incoming, &mut plaintext, 0)?;
+        }
+
+        let target = Address::decode_socks(&mut plaintext)?;
+        Ok(ShadowsocksRequest {
+            target,
+            codec: self.clone(),
+            decoder,
+            incoming,
+            plaintext,
+        })
+    }
+}
+
+/// 已解密的 Shadowsocks 请求，连同读取请求时多收到的数据
+pub struct ShadowsocksRequest {
+    pub target: Address,
+    codec: ShadowsocksCodec,
+    decoder: Decoder,
+    incoming: BytesMut,
+    plaintext: BytesMut,
+}
+
+impl ShadowsocksRequest {
+    /// 在原连接上继续收发数据
+    pub fn into_stream<S>(self, inner: S) -> ShadowsocksServerStream<S> {
+        ShadowsocksServerStream {
+            inner,
+            method: self.codec.method,
+            key: self.codec.key,
+            encoder: None,
+            salt: None,
+            pending: Vec::new(),
+            pending_pos: 0,
+            decoder: self.decoder,
+            incoming: self.incoming,
+            plaintext: self.plaintext,
+        }
+    }
+}
+
+/// 服务端 Shadowsocks 数据流
+///
+/// 读取时解密客户端数据块；首次写入时生成响应盐并与第一块密文一起发出。
+pub struct ShadowsocksServerStream<S> {
+    inner: S,
+    method: Method,
+    key: Vec<u8>,
+    encoder: Option<Encoder>,
+    /// 固定的响应盐 (测试用)，未设置时随机生成
+    salt: Option<Vec<u8>>,
+    /// 待写出的密文
+    pending: Vec<u8>,
+    pending_pos: usize,
+    decoder: Decoder,
+    /// 已收到尚未解密的密文
+    incoming: BytesMut,
+    /// 已解密尚未交给调用方的明文
+    plaintext: BytesMut,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> ShadowsocksServerStream<S> {
+    /// 第一次写入前创建响应方向的加密器，并把盐写入待发数据
+    fn ensure_encoder(&mut self) -> io::Result<()> {
+        if self.encoder.is_none() {
+            let salt = self.salt.take().unwrap_or_else(|| {
+                let mut salt = vec![0u8; self.method.key_len()];
+                rand::thread_rng().fill_bytes(&mut salt);
+                salt
+            });
+            self.encoder = Some(Encoder::new(self.method, &self.key, &salt)?);
+            self.pending.extend_from_slice(&salt);
+        }
+        Ok(())
+    }
+
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.pending_pos < self.pending.len() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.pending[self.pending_pos..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.pending_pos += n;
+        }
+        self.pending.clear();
+        self.pending_pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for ShadowsocksServerStream<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            if !this.plaintext.is_empty() {
+                let n = this.plaintext.len().min(buf.remaining());
+                buf.put_slice(&this.plaintext[..n]);
+                this.plaintext.advance(n);
+                return Poll::Ready(Ok(()));
+            }
+
+            this.decoder.decode(&mut this.incoming, &mut this.plaintext, 0)?;
+            if !this.plaintext.is_empty() {
+                continue;
+            }
+
+            let mut raw = [0u8; 16 * 1024];
+            let mut raw_buf = ReadBuf::new(&mut raw);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut raw_buf))?;
+            if raw_buf.filled().is_empty() {
+                if this.decoder.at_boundary(&this.incoming) {
+                    return Poll::Ready(Ok(()));
+                }
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Shadowsocks 客户端在数据块中途关闭了连接",
+                )));
+            }
+            this.incoming.extend_from_slice(raw_buf.filled());
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for ShadowsocksServerStream<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        ready!(this.poll_drain(cx))?;
+
+        let n = buf.len().min(this.method.max_payload());
+        this.ensure_encoder()?;
+        if let Some(encoder) = this.encoder.as_mut() {
+            encoder.encode(&buf[..n], &mut this.pending)?;
+        }
+
+        // 密文已缓存，剩余部分留给下次写入或 flush 发出
+        let _ = this.poll_drain(cx)?;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outbound::ShadowsocksStream;
+    use std::net::Ipv4Addr;
+    use tokio::io::AsyncWriteExt;
+
+    // 与出站测试相同的向量，由独立的参考实现 (Python cryptography) 生成
+
+    /// aes-128-gcm，密码 "password"，盐全为 0x01，目标 example.com:443，首包 "GET / HTTP/1.1\r\n\r\n"
+    const AEAD_REQUEST: &str = "010101010101010101010101010101012616e0b83aa771f5381edea95ca427976e031a37bc082e851529cc9fa7d8e2b42e0ab9a452b6850b92cf5e72783cc784af0b0c25582fb004f45006bcd65446e0a7a627";
+    /// aes-128-gcm 响应，盐全为 0x02，分两块: "HTTP/1.1 " 与 "200 OK\r\n\r\n"
+    const AEAD_RESPONSE: &str = "020202020202020202020202020202028b3e0754c2b40262357f6dd20ebb21e54324da8cef2f7f78759aeb480524cfe238ab218753c2c85bf2d2ebbe3527600a7f2be0e00052f2b251be38c0ab19d7b18ed74386d1c5cceac85b283a7b7980cd4737309adadaed";
+
+    #[tokio::test]
+    async fn test_known_answer_vector() {
+        let codec = ShadowsocksCodec::new("aes-128-gcm", "password").unwrap();
+        let (mut client, mut peer) = tokio::io::duplex(4096);
+        peer.write_all(&hex::decode(AEAD_REQUEST).unwrap()).await.unwrap();
+
+        let request = codec.read_request(&mut client).await.unwrap();
+        assert_eq!(request.target, Address::Domain("example.com".to_string(), 443));
+        let mut stream = request.into_stream(client);
+        let mut first = vec![0u8; 18];
+        stream.read_exact(&mut first).await.unwrap();
+        assert_eq!(first, b"GET / HTTP/1.1\r\n\r\n");
+
+        stream.salt = Some(vec![2; 16]);
+        stream.write_all(b"HTTP/1.1 ").await.unwrap();
+        stream.write_all(b"200 OK\r\n\r\n").await.unwrap();
+        stream.shutdown().await.unwrap();
+        let mut response = Vec::new();
+        peer.read_to_end(&mut response).await.unwrap();
+        assert_eq!(hex::encode(response), AEAD_RESPONSE);
+    }
+
+    #[tokio::test]
+    async fn test_roundtrip_with_outbound() {
+        let codec = ShadowsocksCodec::new("aes-256-gcm", "secret").unwrap();
+        let key = Method::Aes256Gcm.derive_key("secret").unwrap();
+        let (client, mut server) = tokio::io::duplex(1 << 16);
+        let target = Address::Ipv4(Ipv4Addr::new(10, 0, 0, 1), 8080);
+
+        let client_task = tokio::spawn(async move {
+            let mut stream = ShadowsocksStream::new(client, Method::Aes256Gcm, &key, target).unwrap();
+            // 超过单块上限的数据会被拆成多块
+            stream.write_all(&vec![7u8; 0x3FFF + 100]).await.unwrap();
+            stream.flush().await.unwrap();
+            let mut reply = [0u8; 4];
+            stream.read_exact(&mut reply).await.unwrap();
+            reply
+        });
+
+        let request = codec.read_request(&mut server).await.unwrap();
+        assert_eq!(request.target, Address::Ipv4(Ipv4Addr::new(10, 0, 0, 1), 8080));
+        let mut stream = request.into_stream(server);
+        let mut data = vec![0u8; 0x3FFF + 100];
+        stream.read_exact(&mut data).await.unwrap();
+        assert!(data.iter().all(|b| *b == 7));
+        stream.write_all(b"pong").await.unwrap();
+        stream.flush().await.unwrap();
+        assert_eq!(&client_task.await.unwrap(), b"pong");
+    }
+
+    #[tokio::test]
+    async fn test_wrong_password_is_rejected() {
+        let codec = ShadowsocksCodec::new("aes-128-gcm", "not-the-password").unwrap();
+        let (mut client, mut peer) = tokio::io::duplex(4096);
+        peer.write_all(&hex::decode(AEAD_REQUEST).unwrap()).await.unwrap();
+        assert!(codec.read_request(&mut client).await.is_err());
+    }
+
+    #[test]
+    fn test_invalid_settings() {
+        assert!(ShadowsocksCodec::new("chacha20-poly1305", "password").is_err());
+        assert!(ShadowsocksCodec::new("aes-128-gcm", "").is_err());
+        assert!(ShadowsocksCodec::new("2022-blake3-aes-128-gcm", "AAECAwQFBgcICQoLDA0ODw==").is_err());
+    }
+}
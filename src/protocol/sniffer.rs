@@ -1,22 +1,103 @@
+/// TLS 记录头长度
+const RECORD_HEADER_LEN: usize = 5;
+
+/// 嗅探时最多重组的握手数据长度 (足以容纳带后量子密钥交换的 ClientHello)
+const MAX_CLIENT_HELLO_LEN: usize = 16 * 1024;
+
+/// 把连续的 Handshake 记录拼接成完整的握手消息
+///
+/// 返回 (握手数据, 是否已经包含完整的 ClientHello)。
+/// 较大的 ClientHello (例如携带 X25519MLKEM768 密钥份额) 经常被拆分到多个记录中。
+fn reassemble_handshake(data: &[u8]) -> Option<(Vec<u8>, bool)> {
+    let mut handshake = Vec::new();
+    let mut pos = 0;
+
+    while pos + RECORD_HEADER_LEN <= data.len() {
+        // ContentType(1) must be Handshake (0x16)
+        if data[pos] != 0x16 {
+            break;
+        }
+        let record_len = ((data[pos + 3] as usize) << 8) | (data[pos + 4] as usize);
+        let start = pos + RECORD_HEADER_LEN;
+        let end = (start + record_len).min(data.len());
+        handshake.extend_from_slice(&data[start..end]);
+        pos = start + record_len;
+
+        // Handshake Type(1) + Length(3)
+        if handshake.len() >= 4 {
+            let msg_len = ((handshake[1] as usize) << 16)
+                | ((handshake[2] as usize) << 8)
+                | (handshake[3] as usize);
+            if 4 + msg_len > MAX_CLIENT_HELLO_LEN {
+                return None;
+            }
+            if handshake.len() >= 4 + msg_len {
+                handshake.truncate(4 + msg_len);
+                return Some((handshake, true));
+            }
+        }
+    }
+
+    if handshake.is_empty() {
+        None
+    } else {
+        Some((handshake, false))
+    }
+}
+
+/// 判断数据是否是尚未接收完整的 TLS ClientHello
+///
+/// 用于嗅探时决定是否继续等待后续记录。
+pub fn is_partial_client_hello(data: &[u8]) -> bool {
+    if data.first() != Some(&0x16) {
+        return false;
+    }
+    match reassemble_handshake(data) {
+        Some((handshake, complete)) => !complete && handshake.first().is_none_or(|t| *t == 0x01),
+        // 只收到了部分记录头
+        None => data.len() < RECORD_HEADER_LEN,
+    }
+}
+
+/// 判断数据是否包含完整的 TLS ClientHello (不论是否携带 SNI)
+pub fn is_client_hello(data: &[u8]) -> bool {
+    matches!(reassemble_handshake(data), Some((handshake, true)) if handshake.first() == Some(&0x01))
+}
+
+/// encrypted_client_hello 扩展类型 (draft-ietf-tls-esni)
+const EXT_ENCRYPTED_CLIENT_HELLO: usize = 0xfe0d;
+
+/// 从 ClientHello 中提取的信息
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientHelloInfo {
+    /// SNI；使用 ECH 时为外层 (公开) SNI
+    pub sni: Option<String>,
+    /// 是否携带 ECH 扩展
+    pub has_ech: bool,
+}
+
 /// 尝试从数据包中嗅探 TLS SNI (Server Name Indication)
 /// 这是一个纯 Rust 实现，不通过 bytes crate，以避免依赖问题
+///
+/// ClientHello 被拆分到多个 TLS 记录时会先重组再解析。
 pub fn sniff_tls_sni(data: &[u8]) -> Option<String> {
-    let mut pos = 0;
-    if data.len() < 40 {
-        return None;
-    }
+    sniff_client_hello(data)?.sni
+}
 
-    // 1. Record Layer
-    // ContentType(1) must be Handshake (0x16)
-    if data[pos] != 0x16 {
+/// 解析 ClientHello，返回 SNI 与 ECH 等信息
+pub fn sniff_client_hello(data: &[u8]) -> Option<ClientHelloInfo> {
+    if data.len() < 40 {
         return None;
     }
-    pos += 1;
+    let (handshake, _) = reassemble_handshake(data)?;
+    parse_client_hello(&handshake)
+}
 
-    // Version (2) + Length (2)
-    pos += 4;
+/// 从握手消息 (不含记录头) 中解析 ClientHello
+pub(crate) fn parse_client_hello(data: &[u8]) -> Option<ClientHelloInfo> {
+    let mut pos = 0;
 
-    // 2. Handshake Layer
+    // Handshake Layer
     if pos >= data.len() {
         return None;
     }
@@ -71,6 +152,8 @@ pub fn sniff_tls_sni(data: &[u8]) -> Option<String> {
         return None;
     }
 
+    let mut info = ClientHelloInfo::default();
+
     while pos + 4 <= end_ext {
         let ext_type = ((data[pos] as usize) << 8) | (data[pos + 1] as usize);
         let len = ((data[pos + 2] as usize) << 8) | (data[pos + 3] as usize);
@@ -80,14 +163,18 @@ pub fn sniff_tls_sni(data: &[u8]) -> Option<String> {
             break;
         }
 
-        if ext_type == 0x0000 {
+        if ext_type == EXT_ENCRYPTED_CLIENT_HELLO {
+            info.has_ech = true;
+        }
+
+        if ext_type == 0x0000 && info.sni.is_none() {
             // ServerName Extension
             // ServerNameList Length (2)
             if len < 2 {
                 return None;
             }
             let list_len = ((data[pos] as usize) << 8) | (data[pos + 1] as usize);
-            let end_list = pos + 2 + list_len;
+            let end_list = (pos + 2 + list_len).min(pos + len);
             let mut p2 = pos + 2;
 
             while p2 + 3 <= end_list {
@@ -101,7 +188,8 @@ pub fn sniff_tls_sni(data: &[u8]) -> Option<String> {
 
                 if name_type == 0x00 {
                     // HostName
-                    return String::from_utf8(data[p2..p2 + name_len].to_vec()).ok();
+                    info.sni = String::from_utf8(data[p2..p2 + name_len].to_vec()).ok();
+                    break;
                 }
                 p2 += name_len;
             }
@@ -110,5 +198,238 @@ pub fn sniff_tls_sni(data: &[u8]) -> Option<String> {
         pos += len;
     }
 
-    None
+    Some(info)
+}
+
+/// 识别为 HTTP 请求的方法
+const HTTP_METHODS: &[&str] = &["GET", "POST", "HEAD", "PUT", "DELETE", "OPTIONS", "PATCH", "CONNECT", "TRACE"];
+
+/// 嗅探时最多等待的 HTTP 请求头长度
+const MAX_HTTP_HEADER_LEN: usize = 8 * 1024;
+
+/// 数据是否以 HTTP 请求行开头 (`方法 + 空格`)
+fn is_http_request(data: &[u8]) -> bool {
+    HTTP_METHODS.iter().any(|method| {
+        data.len() > method.len() && data.starts_with(method.as_bytes()) && data[method.len()] == b' '
+    })
+}
+
+/// 判断数据是否是请求头尚未接收完整的 HTTP 请求
+///
+/// 用于嗅探时决定是否继续等待后续数据。
+pub fn is_partial_http_request(data: &[u8]) -> bool {
+    is_http_request(data)
+        && data.len() < MAX_HTTP_HEADER_LEN
+        && !data.windows(4).any(|w| w == b"\r\n\r\n")
+}
+
+/// 从明文 HTTP 请求中嗅探 Host
+///
+/// 请求头名称不区分大小写，支持折叠的请求头 (以空白开头的续行)；
+/// 去掉端口后缀，返回小写的主机名。IPv6 字面量与明显无效的主机名返回 `None`。
+pub fn sniff_http_host(data: &[u8]) -> Option<String> {
+    if !is_http_request(data) {
+        return None;
+    }
+    let head = &data[..data.len().min(MAX_HTTP_HEADER_LEN)];
+    let head = match head.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(end) => &head[..end + 2],
+        None => head,
+    };
+    let head = String::from_utf8_lossy(head);
+    let mut lines = head.split('\n').collect::<Vec<_>>();
+    // 最后一段没有换行，可能还没有接收完整
+    lines.pop();
+
+    let mut host: Option<String> = None;
+    let mut in_host = false;
+    for line in lines.into_iter().skip(1) {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.starts_with([' ', '\t']) {
+            // 折叠的续行属于上一个请求头
+            if in_host {
+                host.get_or_insert_with(String::new).push_str(line.trim());
+            }
+            continue;
+        }
+        in_host = false;
+        if host.is_some() {
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("host") {
+                host = Some(value.trim().to_string());
+                in_host = true;
+            }
+        }
+    }
+    normalize_host(&host?)
+}
+
+/// 去掉端口并校验主机名
+fn normalize_host(host: &str) -> Option<String> {
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if port.parse::<u16>().is_ok() => name,
+        _ => host,
+    };
+    let host = host.strip_suffix('.').unwrap_or(host);
+    let valid = !host.is_empty()
+        && host.len() <= 253
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        });
+    valid.then(|| host.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// 构造一个 ClientHello 握手消息 (不含记录头)，可选 SNI，并带大体积填充扩展
+    pub(crate) fn client_hello_with(sni: Option<&str>, padding: usize) -> Vec<u8> {
+        client_hello_ext(sni, padding, false)
+    }
+
+    /// 同上，可选附带 encrypted_client_hello 扩展
+    pub(crate) fn client_hello_ext(sni: Option<&str>, padding: usize, ech: bool) -> Vec<u8> {
+        let mut extensions = Vec::new();
+        // 模拟大体积 key_share，放在 SNI 之前以确保 SNI 落在第二个记录中
+        extensions.extend_from_slice(&0x0033u16.to_be_bytes());
+        extensions.extend_from_slice(&(padding as u16).to_be_bytes());
+        extensions.resize(extensions.len() + padding, 0xAB);
+
+        if let Some(sni) = sni {
+            let mut sni_ext = Vec::new();
+            let name = sni.as_bytes();
+            sni_ext.extend_from_slice(&((name.len() + 3) as u16).to_be_bytes());
+            sni_ext.push(0x00);
+            sni_ext.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            sni_ext.extend_from_slice(name);
+
+            extensions.extend_from_slice(&0x0000u16.to_be_bytes());
+            extensions.extend_from_slice(&(sni_ext.len() as u16).to_be_bytes());
+            extensions.extend_from_slice(&sni_ext);
+        }
+
+        if ech {
+            // outer ClientHello: type(1) + cipher suite(4) + config_id(1) + enc + payload
+            let mut ech_ext = vec![0x00, 0x00, 0x01, 0x00, 0x01, 0x2a];
+            ech_ext.extend_from_slice(&32u16.to_be_bytes());
+            ech_ext.extend_from_slice(&[0x33; 32]);
+            ech_ext.extend_from_slice(&64u16.to_be_bytes());
+            ech_ext.extend_from_slice(&[0x44; 64]);
+            extensions.extend_from_slice(&0xfe0du16.to_be_bytes());
+            extensions.extend_from_slice(&(ech_ext.len() as u16).to_be_bytes());
+            extensions.extend_from_slice(&ech_ext);
+        }
+
+        let mut body = Vec::new();
+        body.extend_from_slice(&[0x03, 0x03]);
+        body.extend_from_slice(&[0x11; 32]);
+        body.push(32);
+        body.extend_from_slice(&[0x22; 32]);
+        body.extend_from_slice(&[0x00, 0x02, 0x13, 0x01]);
+        body.extend_from_slice(&[0x01, 0x00]);
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+
+        let mut msg = vec![0x01];
+        msg.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        msg.extend_from_slice(&body);
+        msg
+    }
+
+    fn client_hello(sni: &str, padding: usize) -> Vec<u8> {
+        client_hello_with(Some(sni), padding)
+    }
+
+    pub(crate) fn record(payload: &[u8]) -> Vec<u8> {
+        let mut out = vec![0x16, 0x03, 0x01];
+        out.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        out.extend_from_slice(payload);
+        out
+    }
+
+    #[test]
+    fn test_single_record() {
+        let data = record(&client_hello("www.example.com", 64));
+        assert_eq!(sniff_tls_sni(&data).as_deref(), Some("www.example.com"));
+        assert!(!is_partial_client_hello(&data));
+    }
+
+    #[test]
+    fn test_fragmented_client_hello() {
+        let hello = client_hello("pq.example.com", 1200);
+        let (first, second) = hello.split_at(600);
+        let mut data = record(first);
+
+        // 只收到第一个记录时 SNI 不可见，应继续等待
+        assert_eq!(sniff_tls_sni(&data), None);
+        assert!(is_partial_client_hello(&data));
+
+        data.extend_from_slice(&record(second));
+        assert_eq!(sniff_tls_sni(&data).as_deref(), Some("pq.example.com"));
+        assert!(!is_partial_client_hello(&data));
+    }
+
+    #[test]
+    fn test_client_hello_without_sni() {
+        let data = record(&client_hello_with(None, 64));
+        assert_eq!(sniff_tls_sni(&data), None);
+        assert!(is_client_hello(&data));
+    }
+
+    #[test]
+    fn test_detect_ech() {
+        let data = record(&client_hello_ext(Some("public.example.com"), 64, true));
+        let info = sniff_client_hello(&data).unwrap();
+        assert!(info.has_ech);
+        assert_eq!(info.sni.as_deref(), Some("public.example.com"));
+
+        let plain = record(&client_hello("www.example.com", 64));
+        assert!(!sniff_client_hello(&plain).unwrap().has_ech);
+    }
+
+    #[test]
+    fn test_not_tls() {
+        let data = b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n".to_vec();
+        assert_eq!(sniff_tls_sni(&data), None);
+        assert!(!is_partial_client_hello(&data));
+        assert!(!is_client_hello(&data));
+    }
+
+    #[test]
+    fn test_sniff_http_host() {
+        let host = |data: &[u8]| sniff_http_host(data);
+        assert_eq!(host(b"GET / HTTP/1.1\r\nHost: Example.COM:8080\r\n\r\n"), Some("example.com".to_string()));
+        assert_eq!(host(b"POST /x HTTP/1.1\r\nhOsT:example.com\r\nContent-Length: 0\r\n\r\n"), Some("example.com".to_string()));
+        // 折叠的请求头
+        assert_eq!(host(b"GET / HTTP/1.1\r\nHost:\r\n  www.example.com\r\nAccept: */*\r\n\r\n"), Some("www.example.com".to_string()));
+        assert_eq!(host(b"GET / HTTP/1.1\r\nX-Note: a\r\n Host: fake.com\r\nHost: real.com\r\n\r\n"), Some("real.com".to_string()));
+
+        // 缺少 Host、无效主机名、非 HTTP 请求
+        assert_eq!(host(b"GET / HTTP/1.0\r\nAccept: */*\r\n\r\n"), None);
+        assert_eq!(host(b"GET / HTTP/1.1\r\nHost: bad host\r\n\r\n"), None);
+        assert_eq!(host(b"GET / HTTP/1.1\r\nHost: -bad.com\r\n\r\n"), None);
+        assert_eq!(host(b"GET / HTTP/1.1\r\nHost: [::1]:80\r\n\r\n"), None);
+        assert_eq!(host(b"GETX / HTTP/1.1\r\nHost: example.com\r\n\r\n"), None);
+        assert_eq!(host(b"\x16\x03\x01\x00\x05Host: example.com\r\n\r\n"), None);
+    }
+
+    #[test]
+    fn test_partial_http_request() {
+        let request = b"GET /index.html HTTP/1.1\r\nUser-Agent: curl/8.0\r\nHost: example.com\r\n\r\n";
+        // 第一次读取只收到部分请求头，Host 所在的行还不完整
+        let first = &request[..request.len() - 10];
+        assert!(is_partial_http_request(first));
+        assert_eq!(sniff_http_host(first), None);
+
+        assert!(!is_partial_http_request(request));
+        assert_eq!(sniff_http_host(request), Some("example.com".to_string()));
+        assert!(!is_partial_http_request(b"\x16\x03\x01"));
+    }
 }
@@ -0,0 +1,304 @@
+//! SOCKS5 入站 (RFC 1928 / RFC 1929)
+//!
+//! 支持无认证与用户名密码认证、CONNECT 与 UDP ASSOCIATE。握手只在借用的连接上
+//! 进行，之后由处理器接管: CONNECT 进入通用的 TCP 转发，UDP ASSOCIATE 在独立的
+//! UDP 中继端口上按 SOCKS UDP 头收发数据报。
+
+use anyhow::{anyhow, Result};
+use bytes::{BufMut, BytesMut};
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::config::SocksAccount;
+use crate::protocol::vless::Address;
+
+const SOCKS_VERSION: u8 = 0x05;
+const AUTH_VERSION: u8 = 0x01;
+const METHOD_NO_AUTH: u8 = 0x00;
+const METHOD_USER_PASS: u8 = 0x02;
+const METHOD_NONE_ACCEPTABLE: u8 = 0xFF;
+const CMD_CONNECT: u8 = 0x01;
+const CMD_UDP_ASSOCIATE: u8 = 0x03;
+
+/// 应答码
+pub mod reply {
+    pub const SUCCEEDED: u8 = 0x00;
+    pub const GENERAL_FAILURE: u8 = 0x01;
+    pub const COMMAND_NOT_SUPPORTED: u8 = 0x07;
+}
+
+/// SOCKS5 命令
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocksCommand {
+    Connect,
+    UdpAssociate,
+}
+
+/// 已完成握手的 SOCKS5 请求
+#[derive(Debug, Clone, PartialEq)]
+pub struct SocksRequest {
+    pub command: SocksCommand,
+    /// CONNECT 的目标；UDP ASSOCIATE 时为客户端声明的 UDP 发送地址 (可能全为 0)
+    pub target: Address,
+    /// 用户名密码认证时的用户名
+    pub user: Option<String>,
+}
+
+/// SOCKS5 入站的握手处理
+#[derive(Debug, Clone)]
+pub struct SocksCodec {
+    /// 用户名 -> 密码，为空表示不需要认证
+    accounts: Arc<HashMap<String, String>>,
+    /// 是否允许 UDP ASSOCIATE
+    udp: bool,
+}
+
+impl SocksCodec {
+    pub fn new(accounts: &[SocksAccount], udp: bool) -> Self {
+        let accounts = accounts
+            .iter()
+            .map(|account| (account.user.clone(), account.pass.clone()))
+            .collect();
+        Self {
+            accounts: Arc::new(accounts),
+            udp,
+        }
+    }
+
+    /// 是否允许 UDP ASSOCIATE
+    pub fn udp_enabled(&self) -> bool {
+        self.udp
+    }
+
+    /// 完成方法协商、认证并读取请求
+    ///
+    /// 协商或认证失败时按协议回复后返回错误；不支持的命令回复 0x07。
+    pub async fn read_request<S>(&self, stream: &mut S) -> Result<SocksRequest>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + ?Sized,
+    {
+        let mut head = [0u8; 2];
+        stream.read_exact(&mut head).await?;
+        if head[0] != SOCKS_VERSION {
+            return Err(anyhow!("不是 SOCKS5 请求 (版本 {})", head[0]));
+        }
+        let mut methods = vec![0u8; head[1] as usize];
+        stream.read_exact(&mut methods).await?;
+
+        let method = if self.accounts.is_empty() {
+            METHOD_NO_AUTH
+        } else {
+            METHOD_USER_PASS
+        };
+        if !methods.contains(&method) {
+            stream.write_all(&[SOCKS_VERSION, METHOD_NONE_ACCEPTABLE]).await?;
+            return Err(anyhow!("客户端不支持所需的 SOCKS5 认证方式 ({:#04x})", method));
+        }
+        stream.write_all(&[SOCKS_VERSION, method]).await?;
+
+        let user = if method == METHOD_USER_PASS {
+            Some(self.authenticate(stream).await?)
+        } else {
+            None
+        };
+
+        let mut request = [0u8; 3];
+        stream.read_exact(&mut request).await?;
+        if request[0] != SOCKS_VERSION {
+            return Err(anyhow!("SOCKS5 请求版本错误: {}", request[0]));
+        }
+        let target = read_address(stream).await?;
+        let command = match request[1] {
+            CMD_CONNECT => SocksCommand::Connect,
+            CMD_UDP_ASSOCIATE if self.udp => SocksCommand::UdpAssociate,
+            other => {
+                write_reply(stream, reply::COMMAND_NOT_SUPPORTED, &unspecified()).await?;
+                return Err(anyhow!("不支持的 SOCKS5 命令: {}", other));
+            }
+        };
+
+        Ok(SocksRequest { command, target, user })
+    }
+
+    /// RFC 1929 用户名密码认证，成功时返回用户名
+    async fn authenticate<S>(&self, stream: &mut S) -> Result<String>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + ?Sized,
+    {
+        let version = stream.read_u8().await?;
+        if version != AUTH_VERSION {
+            return Err(anyhow!("SOCKS5 认证版本错误: {}", version));
+        }
+        let mut user = vec![0u8; stream.read_u8().await? as usize];
+        stream.read_exact(&mut user).await?;
+        let mut pass = vec![0u8; stream.read_u8().await? as usize];
+        stream.read_exact(&mut pass).await?;
+
+        let user = String::from_utf8_lossy(&user).into_owned();
+        if self.accounts.get(&user).map(String::as_bytes) != Some(&pass[..]) {
+            stream.write_all(&[AUTH_VERSION, 0x01]).await?;
+            return Err(anyhow!("SOCKS5 认证失败 (用户: {})", user));
+        }
+        stream.write_all(&[AUTH_VERSION, 0x00]).await?;
+        Ok(user)
+    }
+}
+
+/// 发送应答: VER + REP + RSV + BND.ADDR + BND.PORT
+pub async fn write_reply<S>(stream: &mut S, code: u8, bound: &Address) -> Result<()>
+where
+    S: AsyncWrite + Unpin + ?Sized,
+{
+    let mut buf = BytesMut::with_capacity(3 + 1 + 255 + 2);
+    buf.put_slice(&[SOCKS_VERSION, code, 0x00]);
+    bound.encode_socks(&mut buf);
+    stream.write_all(&buf).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// 0.0.0.0:0，用于失败应答以及 CONNECT 的绑定地址
+pub fn unspecified() -> Address {
+    Address::Ipv4(Ipv4Addr::UNSPECIFIED, 0)
+}
+
+/// 解析 SOCKS UDP 数据报: RSV(2) + FRAG(1) + ATYP/ADDR/PORT + DATA，返回目标与负载
+///
+/// 不支持分片，FRAG 非 0 的数据报视为无效。
+pub fn decode_udp_packet(packet: &[u8]) -> Result<(Address, &[u8])> {
+    if packet.len() < 4 {
+        return Err(anyhow!("SOCKS UDP 数据报过短"));
+    }
+    if packet[2] != 0x00 {
+        return Err(anyhow!("不支持分片的 SOCKS UDP 数据报"));
+    }
+    let mut header = BytesMut::from(&packet[3..]);
+    let before = header.len();
+    let target = Address::decode_socks(&mut header)?;
+    let header_len = 3 + before - header.len();
+    Ok((target, &packet[header_len..]))
+}
+
+/// 按 SOCKS UDP 头封装发往客户端的数据报，`source` 为回包的来源
+pub fn encode_udp_packet(source: &Address, payload: &[u8]) -> Vec<u8> {
+    let mut packet = BytesMut::with_capacity(3 + 1 + 255 + 2 + payload.len());
+    packet.put_slice(&[0x00, 0x00, 0x00]);
+    source.encode_socks(&mut packet);
+    packet.put_slice(payload);
+    packet.to_vec()
+}
+
+/// 读取 ATYP + 地址 + 端口
+async fn read_address<R: AsyncRead + Unpin + ?Sized>(stream: &mut R) -> Result<Address> {
+    let atyp = stream.read_u8().await?;
+    let mut buf = BytesMut::new();
+    buf.put_u8(atyp);
+    let len = match atyp {
+        0x01 => 4 + 2,
+        0x04 => 16 + 2,
+        0x03 => {
+            let n = stream.read_u8().await?;
+            buf.put_u8(n);
+            n as usize + 2
+        }
+        _ => return Err(anyhow!("未知的 SOCKS 地址类型: {}", atyp)),
+    };
+    let mut rest = vec![0u8; len];
+    stream.read_exact(&mut rest).await?;
+    buf.put_slice(&rest);
+    Address::decode_socks(&mut buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(user: &str, pass: &str) -> SocksAccount {
+        SocksAccount {
+            user: user.to_string(),
+            pass: pass.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_auth_connect() {
+        let codec = SocksCodec::new(&[], false);
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        client
+            .write_all(b"\x05\x01\x00\x05\x01\x00\x03\x0bexample.com\x01\xbb")
+            .await
+            .unwrap();
+
+        let request = codec.read_request(&mut server).await.unwrap();
+        assert_eq!(request.command, SocksCommand::Connect);
+        assert_eq!(request.target, Address::Domain("example.com".to_string(), 443));
+        assert_eq!(request.user, None);
+
+        let mut choice = [0u8; 2];
+        client.read_exact(&mut choice).await.unwrap();
+        assert_eq!(choice, [0x05, METHOD_NO_AUTH]);
+    }
+
+    #[tokio::test]
+    async fn test_password_auth() {
+        let codec = SocksCodec::new(&[account("alice", "secret")], true);
+
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        client.write_all(b"\x05\x02\x00\x02\x01\x05alice\x06secret").await.unwrap();
+        client.write_all(b"\x05\x03\x00\x01\x00\x00\x00\x00\x00\x00").await.unwrap();
+        let request = codec.read_request(&mut server).await.unwrap();
+        assert_eq!(request.command, SocksCommand::UdpAssociate);
+        assert_eq!(request.user.as_deref(), Some("alice"));
+        let mut replies = [0u8; 4];
+        client.read_exact(&mut replies).await.unwrap();
+        assert_eq!(replies, [0x05, METHOD_USER_PASS, AUTH_VERSION, 0x00]);
+
+        // 密码错误
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        client.write_all(b"\x05\x01\x02\x01\x05alice\x05wrong").await.unwrap();
+        assert!(codec.read_request(&mut server).await.is_err());
+        client.read_exact(&mut replies).await.unwrap();
+        assert_eq!(replies, [0x05, METHOD_USER_PASS, AUTH_VERSION, 0x01]);
+
+        // 客户端只支持无认证
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        client.write_all(b"\x05\x01\x00").await.unwrap();
+        assert!(codec.read_request(&mut server).await.is_err());
+        let mut choice = [0u8; 2];
+        client.read_exact(&mut choice).await.unwrap();
+        assert_eq!(choice, [0x05, METHOD_NONE_ACCEPTABLE]);
+    }
+
+    #[tokio::test]
+    async fn test_udp_disabled_is_rejected() {
+        let codec = SocksCodec::new(&[], false);
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        client
+            .write_all(b"\x05\x01\x00\x05\x03\x00\x01\x00\x00\x00\x00\x00\x00")
+            .await
+            .unwrap();
+        assert!(codec.read_request(&mut server).await.is_err());
+
+        let mut reply = [0u8; 2 + 10];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply[2..4], [0x05, reply::COMMAND_NOT_SUPPORTED]);
+    }
+
+    #[test]
+    fn test_udp_packet_round_trip() {
+        let target = Address::Ipv4(Ipv4Addr::new(8, 8, 8, 8), 53);
+        let packet = encode_udp_packet(&target, b"query");
+        assert_eq!(&packet[..4], &[0, 0, 0, 0x01]);
+
+        let (decoded, payload) = decode_udp_packet(&packet).unwrap();
+        assert_eq!(decoded, target);
+        assert_eq!(payload, b"query");
+
+        let mut fragmented = packet.clone();
+        fragmented[2] = 1;
+        assert!(decode_udp_packet(&fragmented).is_err());
+        assert!(decode_udp_packet(&packet[..3]).is_err());
+    }
+}
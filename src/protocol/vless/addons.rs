@@ -0,0 +1,156 @@
+use anyhow::{anyhow, Result};
+
+/// 流控 (flow) 的 protobuf 字段号，与 Xray 一致
+const FIELD_FLOW: u64 = 1;
+
+/// 填充种子的 protobuf 字段号，与 Xray 一致
+const FIELD_SEED: u64 = 2;
+
+/// 会话延续令牌的 protobuf 字段号 (xray-lite 扩展，避开 Xray 已使用的字段)
+const FIELD_CONTINUITY: u64 = 100;
+
+/// 会话延续令牌最大长度
+pub const MAX_CONTINUITY_LEN: usize = 64;
+
+/// VLESS 请求附加数据 (protobuf 编码)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Addons {
+    /// 客户端请求的流控，如 `xtls-rprx-vision`
+    pub flow: Option<String>,
+    /// Xray 的填充种子，原样保留
+    pub seed: Option<Vec<u8>>,
+    /// 会话延续令牌: 客户端重连时携带相同的值，日志据此归到同一会话
+    pub continuity: Option<String>,
+}
+
+impl Addons {
+    /// 是否没有任何字段
+    pub fn is_empty(&self) -> bool {
+        self.flow.is_none() && self.seed.is_none() && self.continuity.is_none()
+    }
+
+    /// 解码附加数据，未知字段直接跳过
+    pub fn decode(mut buf: &[u8]) -> Result<Self> {
+        let mut addons = Addons::default();
+        while !buf.is_empty() {
+            let key = read_varint(&mut buf)?;
+            let (field, wire_type) = (key >> 3, key & 0x07);
+            match wire_type {
+                // varint
+                0 => {
+                    read_varint(&mut buf)?;
+                }
+                // 64 位定长
+                1 => skip(&mut buf, 8)?,
+                // 长度前缀
+                2 => {
+                    let len = read_varint(&mut buf)? as usize;
+                    let value = buf.get(..len).ok_or_else(|| anyhow!("附加数据字段 {} 越界", field))?;
+                    if field == FIELD_FLOW {
+                        let flow = std::str::from_utf8(value).map_err(|_| anyhow!("flow 不是有效的 UTF-8"))?;
+                        addons.flow = (!flow.is_empty()).then(|| flow.to_string());
+                    } else if field == FIELD_SEED {
+                        addons.seed = (!value.is_empty()).then(|| value.to_vec());
+                    } else if field == FIELD_CONTINUITY {
+                        if value.len() > MAX_CONTINUITY_LEN {
+                            return Err(anyhow!("会话延续令牌超过 {} 字节", MAX_CONTINUITY_LEN));
+                        }
+                        let token = std::str::from_utf8(value)
+                            .map_err(|_| anyhow!("会话延续令牌不是有效的 UTF-8"))?;
+                        addons.continuity = Some(token.to_string());
+                    }
+                    buf = &buf[len..];
+                }
+                // 32 位定长
+                5 => skip(&mut buf, 4)?,
+                other => return Err(anyhow!("附加数据包含不支持的 wire type: {}", other)),
+            }
+        }
+        Ok(addons)
+    }
+
+    /// 编码为 protobuf
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        if let Some(flow) = &self.flow {
+            write_varint(&mut out, (FIELD_FLOW << 3) | 2);
+            write_varint(&mut out, flow.len() as u64);
+            out.extend_from_slice(flow.as_bytes());
+        }
+        if let Some(seed) = &self.seed {
+            write_varint(&mut out, (FIELD_SEED << 3) | 2);
+            write_varint(&mut out, seed.len() as u64);
+            out.extend_from_slice(seed);
+        }
+        if let Some(token) = &self.continuity {
+            write_varint(&mut out, (FIELD_CONTINUITY << 3) | 2);
+            write_varint(&mut out, token.len() as u64);
+            out.extend_from_slice(token.as_bytes());
+        }
+        out
+    }
+}
+
+fn read_varint(buf: &mut &[u8]) -> Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = buf.split_first().ok_or_else(|| anyhow!("附加数据 varint 不完整"))?;
+        *buf = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(anyhow!("附加数据 varint 过长"))
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn skip(buf: &mut &[u8], n: usize) -> Result<()> {
+    if buf.len() < n {
+        return Err(anyhow!("附加数据字段越界"));
+    }
+    *buf = &buf[n..];
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_continuity_roundtrip() {
+        let addons = Addons {
+            flow: Some("xtls-rprx-vision".to_string()),
+            seed: Some(vec![1, 2, 3]),
+            continuity: Some("laptop-1".to_string()),
+        };
+        assert_eq!(Addons::decode(&addons.encode()).unwrap(), addons);
+        assert!(Addons::decode(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_flow_and_unknown_fields() {
+        // 字段 1 (flow) = "xtls-rprx-vision"，未知字段 3 = varint 150
+        let mut buf = vec![0x0a, 16];
+        buf.extend_from_slice(b"xtls-rprx-vision");
+        buf.extend_from_slice(&[0x18, 0x96, 0x01]);
+        buf.extend_from_slice(&Addons { continuity: Some("abc".into()), ..Default::default() }.encode());
+
+        let addons = Addons::decode(&buf).unwrap();
+        assert_eq!(addons.flow.as_deref(), Some("xtls-rprx-vision"));
+        assert_eq!(addons.continuity.as_deref(), Some("abc"));
+    }
+
+    #[test]
+    fn test_malformed_addons() {
+        assert!(Addons::decode(&[0x0a, 5, b'a']).is_err());
+        assert!(Addons::decode(&[0x0a]).is_err());
+    }
+}
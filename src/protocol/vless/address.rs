@@ -1,9 +1,9 @@
 use anyhow::{anyhow, Result};
 use bytes::{Buf, BufMut, BytesMut};
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 
 /// VLESS 地址类型
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Address {
     /// IPv4 地址
     Ipv4(Ipv4Addr, u16),
@@ -98,6 +98,77 @@ impl Address {
         }
     }
 
+    /// 编码为 SOCKS5 风格 (AddressThenPort)，Trojan / Shadowsocks 也使用该格式
+    pub fn encode_socks(&self, buf: &mut BytesMut) {
+        match self {
+            Address::Ipv4(ip, port) => {
+                buf.put_u8(0x01);
+                buf.put_slice(&ip.octets());
+                buf.put_u16(*port);
+            }
+            Address::Domain(domain, port) => {
+                buf.put_u8(0x03);
+                buf.put_u8(domain.len() as u8);
+                buf.put_slice(domain.as_bytes());
+                buf.put_u16(*port);
+            }
+            Address::Ipv6(ip, port) => {
+                buf.put_u8(0x04);
+                buf.put_slice(&ip.octets());
+                buf.put_u16(*port);
+            }
+        }
+    }
+
+    /// 解析 SOCKS5 风格 (AddressThenPort) 的地址
+    pub fn decode_socks(buf: &mut BytesMut) -> Result<Self> {
+        if buf.remaining() < 1 {
+            return Err(anyhow!("缓冲区太小，无法读取地址类型"));
+        }
+        let addr_type = buf.get_u8();
+
+        let address = match addr_type {
+            0x01 => {
+                if buf.remaining() < 4 + 2 {
+                    return Err(anyhow!("缓冲区太小，无法读取 IPv4 地址"));
+                }
+                let mut octets = [0u8; 4];
+                buf.copy_to_slice(&mut octets);
+                Address::Ipv4(Ipv4Addr::from(octets), buf.get_u16())
+            }
+            0x03 => {
+                if buf.remaining() < 1 {
+                    return Err(anyhow!("缓冲区太小，无法读取域名长度"));
+                }
+                let len = buf.get_u8() as usize;
+                if buf.remaining() < len + 2 {
+                    return Err(anyhow!("缓冲区太小，无法读取域名"));
+                }
+                let domain_bytes = buf.copy_to_bytes(len);
+                let domain = String::from_utf8(domain_bytes.to_vec())?;
+                Address::Domain(domain, buf.get_u16())
+            }
+            0x04 => {
+                if buf.remaining() < 16 + 2 {
+                    return Err(anyhow!("缓冲区太小，无法读取 IPv6 地址"));
+                }
+                let mut octets = [0u8; 16];
+                buf.copy_to_slice(&mut octets);
+                Address::Ipv6(Ipv6Addr::from(octets), buf.get_u16())
+            }
+            _ => return Err(anyhow!("未知的 SOCKS 地址类型: {}", addr_type)),
+        };
+        Ok(address)
+    }
+
+    /// 由配置中的主机名或 IP 字面量与端口构造地址
+    pub fn from_host(host: &str, port: u16) -> Self {
+        match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+            Ok(ip) => SocketAddr::new(ip, port).into(),
+            Err(_) => Address::Domain(host.to_string(), port),
+        }
+    }
+
     /// 获取端口
     pub fn port(&self) -> u16 {
         match self {
@@ -115,10 +186,27 @@ impl Address {
     }
 }
 
+impl From<SocketAddr> for Address {
+    fn from(addr: SocketAddr) -> Self {
+        match addr.ip() {
+            IpAddr::V4(ip) => Address::Ipv4(ip, addr.port()),
+            IpAddr::V6(ip) => Address::Ipv6(ip, addr.port()),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_from_host() {
+        assert_eq!(Address::from_host("10.0.0.1", 80), Address::Ipv4(Ipv4Addr::new(10, 0, 0, 1), 80));
+        assert_eq!(Address::from_host("[::1]", 80), Address::Ipv6(Ipv6Addr::LOCALHOST, 80));
+        assert_eq!(Address::from_host("::1", 80), Address::Ipv6(Ipv6Addr::LOCALHOST, 80));
+        assert_eq!(Address::from_host("example.com", 80), Address::Domain("example.com".to_string(), 80));
+    }
+
     #[test]
     fn test_ipv4_encode_decode() {
         let addr = Address::Ipv4(Ipv4Addr::new(192, 168, 1, 1), 443);
@@ -148,4 +236,24 @@ mod tests {
         let decoded = Address::decode(&mut buf).unwrap();
         assert_eq!(addr, decoded);
     }
+
+    #[test]
+    fn test_socks_encode_decode() {
+        let domain = Address::Domain("example.com".to_string(), 443);
+        let mut buf = BytesMut::new();
+        domain.encode_socks(&mut buf);
+        assert_eq!(&buf[..2], &[0x03, 11]);
+        assert_eq!(&buf[buf.len() - 2..], &443u16.to_be_bytes());
+        assert_eq!(Address::decode_socks(&mut buf).unwrap(), domain);
+
+        for addr in [
+            Address::Ipv4(Ipv4Addr::new(10, 0, 0, 1), 53),
+            Address::Ipv6(Ipv6Addr::LOCALHOST, 8080),
+        ] {
+            let mut buf = BytesMut::new();
+            addr.encode_socks(&mut buf);
+            assert_eq!(Address::decode_socks(&mut buf).unwrap(), addr);
+            assert!(buf.is_empty());
+        }
+    }
 }
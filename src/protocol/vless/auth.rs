@@ -0,0 +1,113 @@
+//! VLESS 用户认证后端
+//!
+//! 握手时由 [`Authenticator`] 按 UUID 决定是否放行并给出用户信息。默认使用配置中的
+//! 静态客户端列表 ([`StaticAuthenticator`])；需要对接数据库或 HTTP 接口时实现该 trait，
+//! 通过 [`VlessCodec::with_authenticator`](super::VlessCodec::with_authenticator) 接入，
+//! 每个新连接都会重新查询，撤销即时生效。
+
+use futures::future::BoxFuture;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// 已授权用户的信息
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientInfo {
+    /// 用户邮箱 (用于路由与日志)
+    pub email: Option<String>,
+    /// 用户必须使用的流控 (如 `xtls-rprx-vision`)，`None` 表示不使用流控
+    pub flow: Option<String>,
+}
+
+/// 按 UUID 授权客户端
+pub trait Authenticator: Send + Sync {
+    /// 允许连接时返回用户信息，拒绝时返回 `None`
+    fn authorize<'a>(&'a self, uuid: &'a Uuid) -> BoxFuture<'a, Option<ClientInfo>>;
+}
+
+/// 基于配置中客户端列表的认证
+#[derive(Debug, Clone, Default)]
+pub struct StaticAuthenticator {
+    /// 允许的客户端 UUID 列表
+    allowed_uuids: Vec<Uuid>,
+    /// UUID 对应的用户邮箱
+    emails: HashMap<Uuid, String>,
+    /// UUID 对应的流控
+    flows: HashMap<Uuid, String>,
+}
+
+impl StaticAuthenticator {
+    pub fn new(allowed_uuids: Vec<Uuid>) -> Self {
+        Self {
+            allowed_uuids,
+            emails: HashMap::new(),
+            flows: HashMap::new(),
+        }
+    }
+
+    /// 允许的 UUID 列表
+    pub fn uuids(&self) -> &[Uuid] {
+        &self.allowed_uuids
+    }
+
+    /// 验证 UUID 是否在允许列表中
+    pub fn contains(&self, uuid: &Uuid) -> bool {
+        self.allowed_uuids.contains(uuid)
+    }
+
+    /// 设置 UUID 对应的用户邮箱，空字符串表示无邮箱
+    pub fn set_email(&mut self, uuid: Uuid, email: &str) {
+        if email.is_empty() {
+            self.emails.remove(&uuid);
+        } else {
+            self.emails.insert(uuid, email.to_string());
+        }
+    }
+
+    /// 查询 UUID 对应的用户邮箱
+    pub fn email(&self, uuid: &Uuid) -> Option<&str> {
+        self.emails.get(uuid).map(String::as_str)
+    }
+
+    /// 设置 UUID 对应的流控，空字符串表示不使用流控
+    pub fn set_flow(&mut self, uuid: Uuid, flow: &str) {
+        if flow.is_empty() {
+            self.flows.remove(&uuid);
+        } else {
+            self.flows.insert(uuid, flow.to_string());
+        }
+    }
+
+    /// 查询 UUID 对应的流控
+    pub fn flow(&self, uuid: &Uuid) -> Option<&str> {
+        self.flows.get(uuid).map(String::as_str)
+    }
+
+    /// 添加允许的 UUID
+    pub fn add(&mut self, uuid: Uuid) {
+        if !self.allowed_uuids.contains(&uuid) {
+            self.allowed_uuids.push(uuid);
+        }
+    }
+
+    /// 移除允许的 UUID 及其邮箱、流控
+    pub fn remove(&mut self, uuid: &Uuid) -> bool {
+        if let Some(pos) = self.allowed_uuids.iter().position(|u| u == uuid) {
+            self.allowed_uuids.remove(pos);
+            self.emails.remove(uuid);
+            self.flows.remove(uuid);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Authenticator for StaticAuthenticator {
+    fn authorize<'a>(&'a self, uuid: &'a Uuid) -> BoxFuture<'a, Option<ClientInfo>> {
+        let info = self.contains(uuid).then(|| ClientInfo {
+            email: self.email(uuid).map(str::to_string),
+            flow: self.flow(uuid).map(str::to_string),
+        });
+        Box::pin(async move { info })
+    }
+}
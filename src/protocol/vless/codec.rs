@@ -1,25 +1,60 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use bytes::BytesMut;
+use std::sync::Arc;
 use uuid::Uuid;
 
-use super::{VlessRequest, VlessResponse};
+use super::{Authenticator, ClientInfo, StaticAuthenticator, VlessRequest, VlessResponse};
 
 /// VLESS 协议编解码器
 #[derive(Clone)]
 pub struct VlessCodec {
-    /// 允许的客户端 UUID 列表
-    allowed_uuids: Vec<Uuid>,
+    /// 配置中的客户端列表，未设置外部认证时使用
+    clients: StaticAuthenticator,
+    /// 外部认证后端，设置后取代静态列表
+    authenticator: Option<Arc<dyn Authenticator>>,
 }
 
 impl VlessCodec {
     /// 创建新的编解码器
     pub fn new(allowed_uuids: Vec<Uuid>) -> Self {
-        Self { allowed_uuids }
+        Self {
+            clients: StaticAuthenticator::new(allowed_uuids),
+            authenticator: None,
+        }
+    }
+
+    /// 使用外部认证后端授权客户端 (取代静态列表)
+    pub fn with_authenticator(mut self, authenticator: Arc<dyn Authenticator>) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
+    /// 设置 UUID 对应的用户邮箱，空字符串表示无邮箱
+    pub fn set_email(&mut self, uuid: Uuid, email: &str) {
+        self.clients.set_email(uuid, email);
+    }
+
+    /// 设置 UUID 对应的流控，空字符串表示不使用流控
+    pub fn set_flow(&mut self, uuid: Uuid, flow: &str) {
+        self.clients.set_flow(uuid, flow);
+    }
+
+    /// 查询 UUID 对应的用户邮箱 (静态列表)
+    pub fn email(&self, uuid: &Uuid) -> Option<&str> {
+        self.clients.email(uuid)
     }
 
-    /// 解码 VLESS 请求
-    pub fn decode_request(&self, buf: &mut BytesMut) -> Result<VlessRequest> {
-        VlessRequest::decode(buf, &self.allowed_uuids)
+    /// 解码 VLESS 请求并授权客户端
+    pub async fn decode_request(&self, buf: &mut BytesMut) -> Result<(VlessRequest, ClientInfo)> {
+        let request = VlessRequest::decode_unverified(buf)?;
+        let authenticator: &dyn Authenticator = match &self.authenticator {
+            Some(authenticator) => authenticator.as_ref(),
+            None => &self.clients,
+        };
+        match authenticator.authorize(&request.uuid).await {
+            Some(client) => Ok((request, client)),
+            None => Err(anyhow!("未授权的 UUID: {}", request.uuid)),
+        }
     }
 
     /// 编码 VLESS 响应
@@ -29,24 +64,17 @@ impl VlessCodec {
 
     /// 验证 UUID 是否在允许列表中
     pub fn validate_uuid(&self, uuid: &Uuid) -> bool {
-        self.allowed_uuids.contains(uuid)
+        self.clients.contains(uuid)
     }
 
     /// 添加允许的 UUID
     pub fn add_uuid(&mut self, uuid: Uuid) {
-        if !self.allowed_uuids.contains(&uuid) {
-            self.allowed_uuids.push(uuid);
-        }
+        self.clients.add(uuid);
     }
 
     /// 移除允许的 UUID
     pub fn remove_uuid(&mut self, uuid: &Uuid) -> bool {
-        if let Some(pos) = self.allowed_uuids.iter().position(|u| u == uuid) {
-            self.allowed_uuids.remove(pos);
-            true
-        } else {
-            false
-        }
+        self.clients.remove(uuid)
     }
 }
 
@@ -80,4 +108,72 @@ mod tests {
         assert!(codec.remove_uuid(&uuid2));
         assert!(!codec.validate_uuid(&uuid2));
     }
+
+    #[test]
+    fn test_email_lookup() {
+        let uuid1 = Uuid::parse_str("b831381d-6324-4d53-ad4f-8cda48b30811").unwrap();
+        let uuid2 = Uuid::parse_str("a831381d-6324-4d53-ad4f-8cda48b30812").unwrap();
+
+        let mut codec = VlessCodec::new(vec![uuid1, uuid2]);
+        codec.set_email(uuid1, "alice@example.com");
+        codec.set_email(uuid2, "");
+
+        assert_eq!(codec.email(&uuid1), Some("alice@example.com"));
+        assert_eq!(codec.email(&uuid2), None);
+
+        codec.remove_uuid(&uuid1);
+        assert_eq!(codec.email(&uuid1), None);
+    }
+
+    /// 只放行指定 UUID 的外部认证
+    struct MockAuthenticator {
+        allowed: Uuid,
+    }
+
+    impl Authenticator for MockAuthenticator {
+        fn authorize<'a>(&'a self, uuid: &'a Uuid) -> futures::future::BoxFuture<'a, Option<ClientInfo>> {
+            let info = (*uuid == self.allowed).then(|| ClientInfo {
+                email: Some("remote@example.com".to_string()),
+                ..Default::default()
+            });
+            Box::pin(async move { info })
+        }
+    }
+
+    fn encoded_request(uuid: Uuid) -> BytesMut {
+        use crate::protocol::vless::{Addons, Address, Command};
+
+        VlessRequest {
+            version: 0,
+            uuid,
+            command: Command::Tcp,
+            address: Address::Domain("example.com".to_string(), 443),
+            addon_length: 0,
+            addons: Addons::default(),
+        }
+        .encode()
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_custom_authenticator() {
+        let listed = Uuid::parse_str("b831381d-6324-4d53-ad4f-8cda48b30811").unwrap();
+        let remote = Uuid::parse_str("a831381d-6324-4d53-ad4f-8cda48b30812").unwrap();
+
+        // 默认使用静态列表
+        let mut codec = VlessCodec::new(vec![listed]);
+        codec.set_email(listed, "alice@example.com");
+        let (request, client) = codec.decode_request(&mut encoded_request(listed)).await.unwrap();
+        assert_eq!(request.uuid, listed);
+        assert_eq!(client.email.as_deref(), Some("alice@example.com"));
+        assert!(codec.decode_request(&mut encoded_request(remote)).await.is_err());
+
+        // 外部认证取代静态列表
+        let codec = codec.with_authenticator(Arc::new(MockAuthenticator { allowed: remote }));
+        let (request, client) = codec.decode_request(&mut encoded_request(remote)).await.unwrap();
+        assert_eq!(request.uuid, remote);
+        assert_eq!(client.email.as_deref(), Some("remote@example.com"));
+        let denied = codec.decode_request(&mut encoded_request(listed)).await.unwrap_err();
+        assert!(denied.to_string().contains("未授权"), "{}", denied);
+    }
 }
@@ -1,9 +1,16 @@
+mod addons;
 mod address;
+mod auth;
 mod codec;
+pub mod packet_addr;
 mod request;
 mod response;
+mod vision;
 
+pub use addons::Addons;
 pub use address::Address;
+pub use auth::{Authenticator, ClientInfo, StaticAuthenticator};
 pub use codec::VlessCodec;
-pub use request::{Command, VlessRequest};
+pub use request::{Command, VlessRequest, MUX_COOL_DOMAIN};
 pub use response::VlessResponse;
+pub use vision::{check_flow, VisionStream, FLOW_VISION};
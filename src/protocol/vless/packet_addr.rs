@@ -0,0 +1,86 @@
+//! VLESS UDP 的 packet-addr 编码 (v2fly / Xray 的 `packetEncoding: packetaddr`)
+//!
+//! 请求头的目标为 [`PACKET_ADDR_DOMAIN`] 时，每个 UDP 帧的负载以目标地址开头:
+//! 地址类型 (1，0x01 为 IPv4、0x02 为 IPv6) + 地址 + 端口 (2) + 数据。
+//! 下行帧以同样的格式携带回包的来源地址，客户端据此区分多个目标。
+
+use anyhow::{anyhow, Result};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use super::Address;
+
+/// packet-addr 会话的请求头目标域名
+pub const PACKET_ADDR_DOMAIN: &str = "v1.packet-addr.v2fly.arpa";
+
+const ADDR_TYPE_IPV4: u8 = 0x01;
+const ADDR_TYPE_IPV6: u8 = 0x02;
+
+/// 请求头目标是否表示 packet-addr 会话
+pub fn is_packet_addr(address: &Address) -> bool {
+    matches!(address, Address::Domain(domain, _) if domain == PACKET_ADDR_DOMAIN)
+}
+
+/// 解析帧负载开头的目标地址，返回目标与数据
+pub fn decode(payload: &[u8]) -> Result<(Address, &[u8])> {
+    let (&addr_type, rest) = payload.split_first().ok_or_else(|| anyhow!("packet-addr 数据报为空"))?;
+    let addr_len = match addr_type {
+        ADDR_TYPE_IPV4 => 4,
+        ADDR_TYPE_IPV6 => 16,
+        other => return Err(anyhow!("packet-addr 不支持的地址类型: {}", other)),
+    };
+    if rest.len() < addr_len + 2 {
+        return Err(anyhow!("packet-addr 数据报过短: {} 字节", payload.len()));
+    }
+    let (addr, rest) = rest.split_at(addr_len);
+    let port = u16::from_be_bytes([rest[0], rest[1]]);
+    let address = if addr_len == 4 {
+        Address::Ipv4(Ipv4Addr::from(<[u8; 4]>::try_from(addr)?), port)
+    } else {
+        Address::Ipv6(Ipv6Addr::from(<[u8; 16]>::try_from(addr)?), port)
+    };
+    Ok((address, &rest[2..]))
+}
+
+/// 在 `out` 后追加带来源地址的负载，域名地址无法表示时返回错误
+pub fn encode(source: &Address, data: &[u8], out: &mut Vec<u8>) -> Result<()> {
+    let port = match source {
+        Address::Ipv4(ip, port) => {
+            out.push(ADDR_TYPE_IPV4);
+            out.extend_from_slice(&ip.octets());
+            port
+        }
+        Address::Ipv6(ip, port) => {
+            out.push(ADDR_TYPE_IPV6);
+            out.extend_from_slice(&ip.octets());
+            port
+        }
+        Address::Domain(..) => return Err(anyhow!("packet-addr 无法表示域名来源: {}", source.to_string())),
+    };
+    out.extend_from_slice(&port.to_be_bytes());
+    out.extend_from_slice(data);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_addr_round_trip() {
+        let source = Address::Ipv4(Ipv4Addr::new(8, 8, 8, 8), 53);
+        let mut buf = Vec::new();
+        encode(&source, b"query", &mut buf).unwrap();
+        assert_eq!(&buf[..7], &[0x01, 8, 8, 8, 8, 0x00, 0x35]);
+        assert_eq!(decode(&buf).unwrap(), (source, &b"query"[..]));
+
+        let v6 = Address::Ipv6(Ipv6Addr::LOCALHOST, 5353);
+        let mut buf = Vec::new();
+        encode(&v6, b"", &mut buf).unwrap();
+        assert_eq!(decode(&buf).unwrap(), (v6, &b""[..]));
+
+        assert!(encode(&Address::Domain("example.com".into(), 53), b"x", &mut Vec::new()).is_err());
+        assert!(decode(&[0x03, 1, 2]).is_err());
+        assert!(decode(&[0x01, 1, 2, 3, 4, 0]).is_err());
+        assert!(is_packet_addr(&Address::Domain(PACKET_ADDR_DOMAIN.to_string(), 0)));
+    }
+}
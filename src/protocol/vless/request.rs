@@ -2,11 +2,14 @@ use anyhow::{anyhow, Result};
 use bytes::{Buf, BufMut, BytesMut};
 use uuid::Uuid;
 
-use super::Address;
+use super::{Addons, Address};
 
 /// VLESS 协议版本
 pub const VLESS_VERSION: u8 = 0;
 
+/// Mux 请求不携带目标地址，与 Xray 一致记为该域名
+pub const MUX_COOL_DOMAIN: &str = "v1.mux.cool";
+
 /// VLESS 命令类型
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Command {
@@ -42,13 +45,24 @@ pub struct VlessRequest {
     pub address: Address,
     /// 附加数据长度
     pub addon_length: u8,
+    /// 附加数据
+    pub addons: Addons,
 }
 
 impl VlessRequest {
-    /// 从字节流解码请求
+    /// 从字节流解码请求，UUID 必须在 `allowed_uuids` 中
     pub fn decode(buf: &mut BytesMut, allowed_uuids: &[Uuid]) -> Result<Self> {
-        // 检查最小长度: version(1) + uuid(16) + addon_length(1) + command(1) + port(2) + addr_type(1)
-        if buf.remaining() < 22 {
+        let request = Self::decode_unverified(buf)?;
+        if !allowed_uuids.contains(&request.uuid) {
+            return Err(anyhow!("未授权的 UUID: {}", request.uuid));
+        }
+        Ok(request)
+    }
+
+    /// 从字节流解码请求但不校验 UUID，由调用方另行授权
+    pub fn decode_unverified(buf: &mut BytesMut) -> Result<Self> {
+        // 检查最小长度: version(1) + uuid(16) + addon_length(1) + command(1)，地址由 Address::decode 检查
+        if buf.remaining() < 19 {
             return Err(anyhow!("缓冲区太小，无法解码 VLESS 请求"));
         }
 
@@ -63,18 +77,14 @@ impl VlessRequest {
         buf.copy_to_slice(&mut uuid_bytes);
         let uuid = Uuid::from_bytes(uuid_bytes);
 
-        // 验证 UUID
-        if !allowed_uuids.contains(&uuid) {
-            return Err(anyhow!("未授权的 UUID: {}", uuid));
-        }
-
         // 读取附加数据长度
         let addon_length = buf.get_u8();
 
-        // 跳过附加数据
+        // 解析附加数据
         if buf.remaining() < addon_length as usize {
-            return Err(anyhow!("缓冲区太小，无法跳过附加数据"));
+            return Err(anyhow!("缓冲区太小，无法读取附加数据"));
         }
+        let addons = Addons::decode(&buf[..addon_length as usize])?;
         buf.advance(addon_length as usize);
 
         // 读取命令
@@ -83,8 +93,11 @@ impl VlessRequest {
         }
         let command = Command::from_u8(buf.get_u8())?;
 
-        // 读取目标地址
-        let address = Address::decode(buf)?;
+        // 读取目标地址 (Mux 请求没有地址)
+        let address = match command {
+            Command::Mux => Address::Domain(MUX_COOL_DOMAIN.to_string(), 0),
+            Command::Tcp | Command::Udp => Address::decode(buf)?,
+        };
 
         Ok(VlessRequest {
             version,
@@ -92,9 +105,15 @@ impl VlessRequest {
             command,
             address,
             addon_length,
+            addons,
         })
     }
 
+    /// 客户端请求的流控，未设置时为 `None`
+    pub fn flow(&self) -> Option<&str> {
+        self.addons.flow.as_deref()
+    }
+
     /// 将请求编码为字节流
     pub fn encode(&self) -> Result<BytesMut> {
         let mut buf = BytesMut::new();
@@ -105,14 +124,19 @@ impl VlessRequest {
         // 写入 UUID
         buf.put_slice(self.uuid.as_bytes());
 
-        // 写入附加数据长度 (暂时为 0)
-        buf.put_u8(0);
+        // 写入附加数据
+        let addons = self.addons.encode();
+        let addon_length = u8::try_from(addons.len()).map_err(|_| anyhow!("附加数据过长"))?;
+        buf.put_u8(addon_length);
+        buf.put_slice(&addons);
 
         // 写入命令
         buf.put_u8(self.command as u8);
 
-        // 写入地址
-        self.address.encode(&mut buf);
+        // 写入地址 (Mux 请求没有地址)
+        if self.command != Command::Mux {
+            self.address.encode(&mut buf);
+        }
 
         Ok(buf)
     }
@@ -132,6 +156,7 @@ mod tests {
             command: Command::Tcp,
             address: Address::Ipv4(Ipv4Addr::new(1, 1, 1, 1), 443),
             addon_length: 0,
+            addons: Addons::default(),
         };
 
         let mut buf = request.encode().unwrap();
@@ -143,6 +168,28 @@ mod tests {
         assert_eq!(request.address, decoded.address);
     }
 
+    #[test]
+    fn test_mux_request_has_no_address() {
+        let uuid = Uuid::parse_str("b831381d-6324-4d53-ad4f-8cda48b30811").unwrap();
+        let request = VlessRequest {
+            version: VLESS_VERSION,
+            uuid,
+            command: Command::Mux,
+            address: Address::Domain(MUX_COOL_DOMAIN.to_string(), 0),
+            addon_length: 0,
+            addons: Addons::default(),
+        };
+
+        let mut buf = request.encode().unwrap();
+        assert_eq!(buf.len(), 19);
+        buf.extend_from_slice(b"frames");
+        let decoded = VlessRequest::decode(&mut buf, &[uuid]).unwrap();
+        assert_eq!(decoded.command, Command::Mux);
+        assert_eq!(decoded.address, request.address);
+        // 请求头之后的数据原样保留
+        assert_eq!(&buf[..], b"frames");
+    }
+
     #[test]
     fn test_unauthorized_uuid() {
         let uuid1 = Uuid::parse_str("b831381d-6324-4d53-ad4f-8cda48b30811").unwrap();
@@ -154,6 +201,7 @@ mod tests {
             command: Command::Tcp,
             address: Address::Ipv4(Ipv4Addr::new(1, 1, 1, 1), 443),
             addon_length: 0,
+            addons: Addons::default(),
         };
 
         let mut buf = request.encode().unwrap();
@@ -162,4 +210,31 @@ mod tests {
         let result = VlessRequest::decode(&mut buf, &[uuid2]);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_decode_xray_addons() {
+        // Xray-core 以 xtls-rprx-vision 流控发出的请求头: 附加数据为 protobuf 字段 1 (Flow)
+        let mut buf = BytesMut::from(
+            &hex::decode(concat!(
+                "00b831381d63244d53ad4f8cda48b30811",
+                "12",
+                "0a1078746c732d727072782d766973696f6e",
+                "01",
+                "01bb020b6578616d706c652e636f6d",
+            ))
+            .unwrap()[..],
+        );
+        let uuid = Uuid::parse_str("b831381d-6324-4d53-ad4f-8cda48b30811").unwrap();
+        let request = VlessRequest::decode(&mut buf, &[uuid]).unwrap();
+        assert_eq!(request.addon_length, 18);
+        assert_eq!(request.flow(), Some("xtls-rprx-vision"));
+        assert_eq!(request.addons.seed, None);
+        assert_eq!(request.address, Address::Domain("example.com".to_string(), 443));
+        assert!(buf.is_empty());
+
+        // 编码时写出附加数据，与 Xray 的字节一致
+        let encoded = request.encode().unwrap();
+        assert_eq!(encoded[17], 18);
+        assert_eq!(&encoded[18..36], &hex::decode("0a1078746c732d727072782d766973696f6e").unwrap()[..]);
+    }
 }
@@ -0,0 +1,595 @@
+//! XTLS Vision 流控 (xtls-rprx-vision)
+//!
+//! 与 Xray 的实现兼容: 连接开始时两个方向的数据都被封装成填充帧，
+//! 以隐藏内层 TLS 握手的长度特征。每个方向的第一帧以用户 UUID 开头，
+//! 之后每帧为 命令 (1) + 内容长度 (2) + 填充长度 (2) + 内容 + 填充。
+//!
+//! 双方同时检查内层流量: 客户端的 ClientHello 表明内层是 TLS，服务器的
+//! ServerHello 给出版本与密码套件。内层为 TLS 1.3 时，发送方在第一条内层
+//! 应用数据记录处发出 Direct 命令，之后绕过外层 TLS 直接在 TCP 上传输
+//! (见 [`DirectCopy`])；其他情况以 End 命令结束填充，之后数据原样传输。
+
+use anyhow::{anyhow, Result};
+use bytes::{Buf, BufMut, BytesMut};
+use rand::Rng;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tracing::debug;
+use uuid::Uuid;
+
+use super::Command;
+use crate::network::DirectCopy;
+
+/// vision 流控名称
+pub const FLOW_VISION: &str = "xtls-rprx-vision";
+
+/// 填充帧命令
+pub mod command {
+    /// 后面还有填充帧
+    pub const CONTINUE: u8 = 0x00;
+    /// 填充结束，之后的数据原样传输
+    pub const END: u8 = 0x01;
+    /// 填充结束，之后绕过外层 TLS 直接传输
+    pub const DIRECT: u8 = 0x02;
+}
+
+/// 最多检查的数据包数，超过后仍未识别出内层 TLS 就不再检查
+const PACKETS_TO_FILTER: i32 = 8;
+/// 填充帧头长度: 命令 (1) + 内容长度 (2) + 填充长度 (2)
+const FRAME_HEADER_LEN: i32 = 5;
+/// 单帧大小上限 (与 Xray 的缓冲区大小一致)
+const BUFFER_SIZE: usize = 8192;
+/// 单帧内容上限，为 UUID 与帧头留出空间
+const MAX_CONTENT_LEN: usize = BUFFER_SIZE - 21;
+/// 短内容补齐到该长度附近，隐藏内层握手的长度
+const LONG_PADDING_TARGET: usize = 900;
+/// 一次从底层读取的数据量上限
+const READ_CHUNK: usize = 16 * 1024;
+
+const TLS_CLIENT_HANDSHAKE_START: [u8; 2] = [0x16, 0x03];
+const TLS_SERVER_HANDSHAKE_START: [u8; 3] = [0x16, 0x03, 0x03];
+const TLS_APPLICATION_DATA_START: [u8; 3] = [0x17, 0x03, 0x03];
+/// supported_versions 扩展只含 TLS 1.3
+const TLS13_SUPPORTED_VERSIONS: [u8; 6] = [0x00, 0x2b, 0x00, 0x02, 0x03, 0x04];
+const HANDSHAKE_CLIENT_HELLO: u8 = 0x01;
+const HANDSHAKE_SERVER_HELLO: u8 = 0x02;
+
+/// 两个方向共用的内层 TLS 识别状态
+#[derive(Debug)]
+struct TlsFilter {
+    packets_to_filter: i32,
+    is_tls: bool,
+    is_tls12_or_above: bool,
+    /// 内层为 TLS 1.3 且密码套件可以直接复制
+    enable_xtls: bool,
+    cipher: u16,
+    remaining_server_hello: i32,
+}
+
+impl TlsFilter {
+    fn new() -> Self {
+        Self {
+            packets_to_filter: PACKETS_TO_FILTER,
+            is_tls: false,
+            is_tls12_or_above: false,
+            enable_xtls: false,
+            cipher: 0,
+            remaining_server_hello: -1,
+        }
+    }
+
+    fn active(&self) -> bool {
+        self.packets_to_filter > 0
+    }
+
+    /// 检查一个数据包
+    fn inspect(&mut self, data: &[u8]) {
+        self.packets_to_filter -= 1;
+        if data.len() >= 6 {
+            if data[..3] == TLS_SERVER_HANDSHAKE_START && data[5] == HANDSHAKE_SERVER_HELLO {
+                self.remaining_server_hello = u16::from_be_bytes([data[3], data[4]]) as i32 + 5;
+                self.is_tls12_or_above = true;
+                self.is_tls = true;
+                if data.len() >= 79 && self.remaining_server_hello >= 79 {
+                    // 记录头 (5) + 握手头 (4) + 版本 (2) + 随机数 (32) 之后是 session id
+                    let session_id_len = data[43] as usize;
+                    if let Some(suite) = data.get(44 + session_id_len..46 + session_id_len) {
+                        self.cipher = u16::from_be_bytes([suite[0], suite[1]]);
+                    }
+                }
+            } else if data[..2] == TLS_CLIENT_HANDSHAKE_START && data[5] == HANDSHAKE_CLIENT_HELLO {
+                self.is_tls = true;
+                debug!("vision: 内层流量为 TLS (ClientHello)");
+            }
+        }
+        if self.remaining_server_hello > 0 {
+            let end = (self.remaining_server_hello as usize).min(data.len());
+            self.remaining_server_hello -= data.len() as i32;
+            if data[..end].windows(TLS13_SUPPORTED_VERSIONS.len()).any(|w| w == TLS13_SUPPORTED_VERSIONS) {
+                // TLS_AES_128_CCM_8_SHA256 (0x1305) 与未知套件不直接复制
+                self.enable_xtls = matches!(self.cipher, 0x1301..=0x1304);
+                self.packets_to_filter = 0;
+                debug!("vision: 内层为 TLS 1.3 (套件 {:#06x})", self.cipher);
+            } else if self.remaining_server_hello <= 0 {
+                self.packets_to_filter = 0;
+                debug!("vision: 内层为 TLS 1.2");
+            }
+        }
+    }
+}
+
+/// 解析对端发来的填充帧
+#[derive(Debug)]
+struct Unpadder {
+    uuid: [u8; 16],
+    remaining_command: i32,
+    remaining_content: i32,
+    remaining_padding: i32,
+    current_command: u8,
+}
+
+impl Unpadder {
+    fn new(uuid: Uuid) -> Self {
+        Self {
+            uuid: *uuid.as_bytes(),
+            remaining_command: -1,
+            remaining_content: -1,
+            remaining_padding: -1,
+            current_command: command::CONTINUE,
+        }
+    }
+
+    fn is_initial(&self) -> bool {
+        self.remaining_command == -1 && self.remaining_content == -1 && self.remaining_padding == -1
+    }
+
+    /// 去掉 `data` 中的填充，内容追加到 `out`；不是填充帧的数据原样追加
+    fn unpad(&mut self, mut data: &[u8], out: &mut BytesMut) {
+        if self.is_initial() {
+            if data.len() >= 21 && data[..16] == self.uuid {
+                data = &data[16..];
+                self.remaining_command = FRAME_HEADER_LEN;
+            } else {
+                out.put_slice(data);
+                return;
+            }
+        }
+        while !data.is_empty() {
+            if self.remaining_command > 0 {
+                let byte = data[0] as i32;
+                data = &data[1..];
+                match self.remaining_command {
+                    5 => self.current_command = byte as u8,
+                    4 => self.remaining_content = byte << 8,
+                    3 => self.remaining_content |= byte,
+                    2 => self.remaining_padding = byte << 8,
+                    _ => self.remaining_padding |= byte,
+                }
+                self.remaining_command -= 1;
+            } else if self.remaining_content > 0 {
+                let n = (self.remaining_content as usize).min(data.len());
+                out.put_slice(&data[..n]);
+                data = &data[n..];
+                self.remaining_content -= n as i32;
+            } else {
+                let n = (self.remaining_padding.max(0) as usize).min(data.len());
+                data = &data[n..];
+                self.remaining_padding -= n as i32;
+            }
+            if self.remaining_command <= 0 && self.remaining_content <= 0 && self.remaining_padding <= 0 {
+                if self.current_command == command::CONTINUE {
+                    self.remaining_command = FRAME_HEADER_LEN;
+                } else {
+                    // 填充结束，同一块中剩余的数据原样交出
+                    self.remaining_command = -1;
+                    self.remaining_content = -1;
+                    self.remaining_padding = -1;
+                    out.put_slice(data);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// 随机填充长度，`long` 时把短内容补齐到约 900 字节
+fn padding_len(content_len: usize, long: bool) -> usize {
+    let mut rng = rand::thread_rng();
+    let len = if content_len < LONG_PADDING_TARGET && long {
+        rng.gen_range(0..500) + LONG_PADDING_TARGET - content_len
+    } else {
+        rng.gen_range(0..256)
+    };
+    len.min(BUFFER_SIZE - 21 - content_len)
+}
+
+/// 服务端的 vision 连接: 读取时去掉客户端的填充，写入时按 vision 规则填充
+///
+/// 填充结束后两个方向都退化为直接转发；收到或发出 Direct 命令时打开
+/// [`DirectCopy`] 对应方向的开关，由传输层切换到底层连接。
+pub struct VisionStream<S> {
+    inner: S,
+    filter: TlsFilter,
+    direct: Option<Arc<DirectCopy>>,
+
+    // 读方向
+    unpadder: Unpadder,
+    within_padding: bool,
+    /// 与 VLESS 请求一起读到、尚未处理的数据
+    leftover: BytesMut,
+    /// 已去掉填充、尚未交给调用方的数据
+    plain: BytesMut,
+
+    // 写方向
+    /// 第一帧前缀的 UUID，写出后清空
+    write_uuid: Option<[u8; 16]>,
+    is_padding: bool,
+    /// 已编码、尚未写出的帧
+    pending: BytesMut,
+    /// Direct 帧写出后需要切换到直接写入
+    switch_write: bool,
+}
+
+impl<S> VisionStream<S> {
+    /// `initial_data` 为与 VLESS 请求一起读到的数据，`direct` 为传输层的直接复制开关
+    pub fn new(inner: S, uuid: Uuid, initial_data: &[u8], direct: Option<Arc<DirectCopy>>) -> Self {
+        Self {
+            inner,
+            filter: TlsFilter::new(),
+            direct,
+            unpadder: Unpadder::new(uuid),
+            within_padding: true,
+            leftover: BytesMut::from(initial_data),
+            plain: BytesMut::new(),
+            write_uuid: Some(*uuid.as_bytes()),
+            is_padding: true,
+            pending: BytesMut::new(),
+            switch_write: false,
+        }
+    }
+
+    /// 读方向是否还需要处理 (去填充或检查内层 TLS)
+    fn reading_padded(&self) -> bool {
+        self.within_padding || self.filter.active()
+    }
+
+    /// 处理从客户端读到的一块数据
+    fn process_read(&mut self, data: &[u8]) {
+        let mut out = BytesMut::with_capacity(data.len());
+        if self.within_padding || self.filter.active() {
+            self.unpadder.unpad(data, &mut out);
+            let unpadder = &self.unpadder;
+            if unpadder.remaining_content > 0
+                || unpadder.remaining_padding > 0
+                || unpadder.current_command == command::CONTINUE
+            {
+                self.within_padding = true;
+            } else if unpadder.current_command == command::END {
+                self.within_padding = false;
+            } else if unpadder.current_command == command::DIRECT {
+                self.within_padding = false;
+                if let Some(direct) = &self.direct {
+                    direct.enable_read();
+                }
+                debug!("vision: 客户端切换为直接复制");
+            } else {
+                debug!("vision: 未知的填充命令 {}", unpadder.current_command);
+            }
+        } else {
+            out.put_slice(data);
+        }
+        if self.filter.active() && !out.is_empty() {
+            self.filter.inspect(&out);
+        }
+        self.plain.unsplit(out);
+    }
+
+    /// 编码一帧，返回写入的内容长度
+    fn encode_frame(&mut self, data: &[u8]) -> usize {
+        let content = &data[..data.len().min(MAX_CONTENT_LEN)];
+        if self.filter.active() {
+            self.filter.inspect(content);
+        }
+
+        let filter = &self.filter;
+        let mut long_padding = filter.is_tls;
+        let command = if filter.is_tls && content.len() >= 6 && content[..3] == TLS_APPLICATION_DATA_START {
+            // 第一条内层应用数据: 结束填充
+            self.is_padding = false;
+            long_padding = false;
+            // 没有直接复制开关时对端切换到原始 TCP 会失去同步，只结束填充
+            if filter.enable_xtls && self.direct.is_some() {
+                command::DIRECT
+            } else {
+                command::END
+            }
+        } else if !filter.is_tls12_or_above && filter.packets_to_filter <= 1 {
+            // 不是 TLS 1.2+，提前一个包结束填充 (兼容旧版接收方)
+            self.is_padding = false;
+            command::END
+        } else {
+            command::CONTINUE
+        };
+
+        let padding = padding_len(content.len(), long_padding);
+        if let Some(uuid) = self.write_uuid.take() {
+            self.pending.put_slice(&uuid);
+        }
+        self.pending.put_u8(command);
+        self.pending.put_u16(content.len() as u16);
+        self.pending.put_u16(padding as u16);
+        self.pending.put_slice(content);
+        self.pending.put_bytes(0, padding);
+        if command == command::DIRECT {
+            self.switch_write = true;
+        }
+        content.len()
+    }
+}
+
+impl<S: AsyncWrite + Unpin> VisionStream<S> {
+    /// 写出已编码的帧；Direct 帧写出并刷新后切换到直接写入
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.pending.is_empty() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.pending))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.pending.advance(n);
+        }
+        if self.switch_write {
+            ready!(Pin::new(&mut self.inner).poll_flush(cx))?;
+            self.switch_write = false;
+            if let Some(direct) = &self.direct {
+                direct.enable_write();
+            }
+            debug!("vision: 服务器切换为直接复制");
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for VisionStream<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            if !this.plain.is_empty() {
+                let n = buf.remaining().min(this.plain.len());
+                buf.put_slice(&this.plain.split_to(n));
+                return Poll::Ready(Ok(()));
+            }
+            if !this.reading_padded() && this.leftover.is_empty() {
+                return Pin::new(&mut this.inner).poll_read(cx, buf);
+            }
+
+            let data = if this.leftover.is_empty() {
+                let mut chunk = vec![0u8; READ_CHUNK];
+                let mut chunk_buf = ReadBuf::new(&mut chunk);
+                ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk_buf))?;
+                let n = chunk_buf.filled().len();
+                if n == 0 {
+                    return Poll::Ready(Ok(()));
+                }
+                chunk.truncate(n);
+                BytesMut::from(&chunk[..])
+            } else {
+                this.leftover.split()
+            };
+            this.process_read(&data);
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for VisionStream<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        ready!(this.poll_drain(cx))?;
+        if !this.is_padding || buf.is_empty() {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        }
+
+        // 帧已编码即视为写入成功，剩余部分在下次写入或 flush 时写出
+        let n = this.encode_frame(buf);
+        let _ = this.poll_drain(cx)?;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+/// 按用户配置检查请求的流控，返回是否启用 vision
+///
+/// 与 Xray 一致: 配置了 vision 的用户不能以无流控的方式代理 TCP (内层 TLS 特征
+/// 会暴露)，未配置流控的用户不能请求 vision；vision 不承载 UDP，UDP 需经 XUDP (Mux)。
+pub fn check_flow(requested: Option<&str>, account: Option<&str>, command: Command) -> Result<bool> {
+    match requested {
+        None if account == Some(FLOW_VISION) && command == Command::Tcp => {
+            Err(anyhow!("该用户必须使用 {} 流控", FLOW_VISION))
+        }
+        None => Ok(false),
+        Some(FLOW_VISION) if account != Some(FLOW_VISION) => Err(anyhow!("该用户未启用 {} 流控", FLOW_VISION)),
+        Some(FLOW_VISION) if command == Command::Udp => Err(anyhow!("{} 不支持 UDP，请使用 XUDP", FLOW_VISION)),
+        Some(FLOW_VISION) => Ok(true),
+        Some(other) => Err(anyhow!("不支持的流控: {}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    /// 编码一个不带填充的帧
+    fn frame(uuid: Option<Uuid>, command: u8, content: &[u8]) -> Vec<u8> {
+        let mut buf = uuid.map(|u| u.as_bytes().to_vec()).unwrap_or_default();
+        buf.push(command);
+        buf.extend_from_slice(&(content.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&[0, 0]);
+        buf.extend_from_slice(content);
+        buf
+    }
+
+    /// 读取一个填充帧，返回命令与内容
+    async fn read_frame(stream: &mut DuplexStream) -> (u8, Vec<u8>) {
+        let mut header = [0u8; 5];
+        stream.read_exact(&mut header).await.unwrap();
+        let mut content = vec![0u8; u16::from_be_bytes([header[1], header[2]]) as usize];
+        stream.read_exact(&mut content).await.unwrap();
+        let mut padding = vec![0u8; u16::from_be_bytes([header[3], header[4]]) as usize];
+        stream.read_exact(&mut padding).await.unwrap();
+        (header[0], content)
+    }
+
+    /// 带 TLS 1.3 supported_versions 扩展的 ServerHello
+    fn server_hello(cipher: u16) -> Vec<u8> {
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0; 32]);
+        body.push(32);
+        body.extend_from_slice(&[0; 32]);
+        body.extend_from_slice(&cipher.to_be_bytes());
+        body.push(0);
+        body.extend_from_slice(&(TLS13_SUPPORTED_VERSIONS.len() as u16).to_be_bytes());
+        body.extend_from_slice(&TLS13_SUPPORTED_VERSIONS);
+        let mut record = vec![0x16, 0x03, 0x03];
+        record.extend_from_slice(&(body.len() as u16 + 4).to_be_bytes());
+        record.push(HANDSHAKE_SERVER_HELLO);
+        record.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        record.extend_from_slice(&body);
+        record
+    }
+
+    const CLIENT_HELLO: [u8; 9] = [0x16, 0x03, 0x01, 0x00, 0x04, HANDSHAKE_CLIENT_HELLO, 0x00, 0x00, 0x00];
+    const APPLICATION_DATA: [u8; 8] = [0x17, 0x03, 0x03, 0x00, 0x03, 0xaa, 0xbb, 0xcc];
+
+    #[test]
+    fn test_filter_detects_tls13_cipher() {
+        let mut filter = TlsFilter::new();
+        filter.inspect(&CLIENT_HELLO);
+        assert!(filter.is_tls);
+        filter.inspect(&server_hello(0x1302));
+        assert!(filter.enable_xtls);
+        assert!(!filter.active());
+
+        // CCM_8 套件不直接复制
+        let mut filter = TlsFilter::new();
+        filter.inspect(&server_hello(0x1305));
+        assert!(filter.is_tls12_or_above);
+        assert!(!filter.enable_xtls);
+    }
+
+    #[tokio::test]
+    async fn test_padding_round_trip() {
+        let uuid = Uuid::new_v4();
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let mut client = VisionStream::new(client, uuid, &[], None);
+        let mut server = VisionStream::new(server, uuid, &[], None);
+
+        // 非 TLS 流量: 几个包之后以 End 结束填充，之后原样传输
+        let mut expected = Vec::new();
+        for i in 0..12u8 {
+            let message = vec![b'a' + i; 100 + i as usize];
+            client.write_all(&message).await.unwrap();
+            client.flush().await.unwrap();
+            expected.extend_from_slice(&message);
+        }
+        let mut received = vec![0u8; expected.len()];
+        server.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, expected);
+        assert!(!client.is_padding);
+        assert!(!server.within_padding);
+    }
+
+    #[tokio::test]
+    async fn test_switch_to_direct_after_tls13() {
+        let uuid = Uuid::new_v4();
+        let direct = DirectCopy::new();
+        let (mut client, server) = tokio::io::duplex(64 * 1024);
+        let mut server = VisionStream::new(server, uuid, &frame(Some(uuid), command::CONTINUE, &CLIENT_HELLO), Some(direct.clone()));
+
+        let mut buf = [0u8; CLIENT_HELLO.len()];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, CLIENT_HELLO);
+
+        // ServerHello 以 UUID 开头的填充帧发出，内层为 TLS 时填充较长
+        let hello = server_hello(0x1301);
+        server.write_all(&hello).await.unwrap();
+        server.flush().await.unwrap();
+        let mut prefix = [0u8; 16];
+        client.read_exact(&mut prefix).await.unwrap();
+        assert_eq!(&prefix, uuid.as_bytes());
+        let mut header = [0u8; 5];
+        client.read_exact(&mut header).await.unwrap();
+        assert_eq!(header[0], command::CONTINUE);
+        assert!(u16::from_be_bytes([header[3], header[4]]) as usize + hello.len() >= LONG_PADDING_TARGET);
+        let mut rest = vec![0u8; hello.len() + u16::from_be_bytes([header[3], header[4]]) as usize];
+        client.read_exact(&mut rest).await.unwrap();
+        assert_eq!(&rest[..hello.len()], &hello[..]);
+
+        // 客户端的 Direct 帧之后的数据原样交出，并打开读方向开关
+        let mut data = frame(None, command::DIRECT, &APPLICATION_DATA);
+        data.extend_from_slice(b"raw");
+        client.write_all(&data).await.unwrap();
+        let mut buf = [0u8; APPLICATION_DATA.len() + 3];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf[..APPLICATION_DATA.len()], &APPLICATION_DATA);
+        assert_eq!(&buf[APPLICATION_DATA.len()..], b"raw");
+        assert!(direct.read_enabled());
+
+        // 服务器的第一条应用数据以 Direct 帧发出，之后不再填充
+        assert!(!direct.write_enabled());
+        server.write_all(&APPLICATION_DATA).await.unwrap();
+        server.flush().await.unwrap();
+        assert!(direct.write_enabled());
+        assert_eq!(read_frame(&mut client).await, (command::DIRECT, APPLICATION_DATA.to_vec()));
+        server.write_all(b"plain").await.unwrap();
+        let mut buf = [0u8; 5];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"plain");
+    }
+
+    #[tokio::test]
+    async fn test_no_direct_without_switch() {
+        let uuid = Uuid::new_v4();
+        let (mut client, server) = tokio::io::duplex(64 * 1024);
+        let mut server = VisionStream::new(server, uuid, &frame(Some(uuid), command::CONTINUE, &CLIENT_HELLO), None);
+
+        let mut buf = [0u8; CLIENT_HELLO.len()];
+        server.read_exact(&mut buf).await.unwrap();
+        server.write_all(&server_hello(0x1301)).await.unwrap();
+        server.flush().await.unwrap();
+        let mut prefix = [0u8; 16];
+        client.read_exact(&mut prefix).await.unwrap();
+        assert_eq!(read_frame(&mut client).await.0, command::CONTINUE);
+
+        // 传输层没有直接复制开关时以 End 结束填充，数据继续经过外层连接
+        server.write_all(&APPLICATION_DATA).await.unwrap();
+        server.flush().await.unwrap();
+        assert_eq!(read_frame(&mut client).await, (command::END, APPLICATION_DATA.to_vec()));
+    }
+
+    #[test]
+    fn test_check_flow() {
+        let vision = Some(FLOW_VISION);
+        assert!(check_flow(vision, vision, Command::Tcp).unwrap());
+        assert!(!check_flow(None, None, Command::Tcp).unwrap());
+        // 配置了 vision 的用户可以不带流控发 UDP，但不能代理 TCP
+        assert!(!check_flow(None, vision, Command::Udp).unwrap());
+        assert!(check_flow(None, vision, Command::Tcp).is_err());
+        assert!(check_flow(vision, None, Command::Tcp).is_err());
+        assert!(check_flow(vision, vision, Command::Udp).is_err());
+        assert!(check_flow(Some("xtls-rprx-direct"), vision, Command::Tcp).is_err());
+    }
+}
@@ -0,0 +1,510 @@
+//! VMess 数据块编解码
+//!
+//! 每块为 长度(2) + 密文 + 填充。开启 CHUNK_MASKING 时长度与以 IV 为种子的
+//! SHAKE128 输出异或；开启 GLOBAL_PADDING 时每块的填充长度同样取自该输出流，
+//! 且在长度掩码之前取出。AEAD 加密时空负载的数据块表示 EOF。
+
+use aes_gcm::aead::Aead;
+use aes_gcm::{Aes128Gcm, KeyInit, Nonce};
+use bytes::{Buf, BufMut, BytesMut};
+use md5::{Digest, Md5};
+use rand::RngCore;
+use ring::aead::{self, LessSafeKey, UnboundKey, CHACHA20_POLY1305};
+use sha3::digest::{ExtendableOutput, Update, XofReader};
+use sha3::{Shake128, Shake128Reader};
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use super::codec::{encode_response_header, response_keys};
+use super::{option, Security, VmessRequest};
+
+/// AEAD 认证标签长度
+const TAG_LEN: usize = 16;
+/// 最大填充长度 (不含)
+const MAX_PADDING: usize = 64;
+/// 单块明文上限，保证加上标签与填充后不超过 8 KiB 缓冲
+pub const MAX_CHUNK_PAYLOAD: usize = 8192 - 2 - TAG_LEN - MAX_PADDING;
+
+enum Cipher {
+    Aes128Gcm(Box<Aes128Gcm>),
+    ChaCha20Poly1305(Box<LessSafeKey>),
+    None,
+}
+
+impl Cipher {
+    fn new(security: Security, key: &[u8; 16]) -> Self {
+        match security {
+            Security::Aes128Gcm => Cipher::Aes128Gcm(Box::new(Aes128Gcm::new(key.into()))),
+            Security::ChaCha20Poly1305 => {
+                // 32 字节密钥: MD5(key) || MD5(MD5(key))
+                let first = Md5::digest(key);
+                let second = Md5::digest(first);
+                let mut full = [0u8; 32];
+                full[..16].copy_from_slice(&first);
+                full[16..].copy_from_slice(&second);
+                let key = UnboundKey::new(&CHACHA20_POLY1305, &full).expect("ChaCha20 密钥长度固定为 32 字节");
+                Cipher::ChaCha20Poly1305(Box::new(LessSafeKey::new(key)))
+            }
+            Security::None => Cipher::None,
+        }
+    }
+
+    fn is_aead(&self) -> bool {
+        !matches!(self, Cipher::None)
+    }
+
+    fn seal(&self, nonce: [u8; 12], plaintext: &[u8]) -> Vec<u8> {
+        match self {
+            Cipher::Aes128Gcm(cipher) => cipher
+                .encrypt(Nonce::from_slice(&nonce), plaintext)
+                .expect("AES-GCM 加密不会失败"),
+            Cipher::ChaCha20Poly1305(key) => {
+                let mut out = plaintext.to_vec();
+                key.seal_in_place_append_tag(aead::Nonce::assume_unique_for_key(nonce), aead::Aad::empty(), &mut out)
+                    .expect("ChaCha20-Poly1305 加密不会失败");
+                out
+            }
+            Cipher::None => plaintext.to_vec(),
+        }
+    }
+
+    fn open(&self, nonce: [u8; 12], ciphertext: &[u8]) -> io::Result<Vec<u8>> {
+        let failed = || invalid_data("VMess 数据块解密失败".to_string());
+        match self {
+            Cipher::Aes128Gcm(cipher) => cipher
+                .decrypt(Nonce::from_slice(&nonce), ciphertext)
+                .map_err(|_| failed()),
+            Cipher::ChaCha20Poly1305(key) => {
+                let mut buf = ciphertext.to_vec();
+                let len = key
+                    .open_in_place(aead::Nonce::assume_unique_for_key(nonce), aead::Aad::empty(), &mut buf)
+                    .map_err(|_| failed())?
+                    .len();
+                buf.truncate(len);
+                Ok(buf)
+            }
+            Cipher::None => Ok(ciphertext.to_vec()),
+        }
+    }
+}
+
+/// 单个方向的数据块状态 (加密器与解密器共用)
+struct ChunkState {
+    cipher: Cipher,
+    iv: [u8; 16],
+    count: u16,
+    /// 开启 CHUNK_MASKING 时的长度掩码流
+    shake: Option<Shake128Reader>,
+    padding: bool,
+    /// 不分块时数据原样传输 (仅无加密时可能)
+    chunked: bool,
+}
+
+impl ChunkState {
+    fn new(request: &VmessRequest, key: &[u8; 16], iv: &[u8; 16]) -> Self {
+        let cipher = Cipher::new(request.security, key);
+        let masking = request.option & option::CHUNK_MASKING != 0;
+        Self {
+            chunked: cipher.is_aead() || request.option & option::CHUNK_STREAM != 0,
+            // 流式无加密时 (xray 的 ChunkStreamReader) 不使用填充
+            padding: cipher.is_aead() && masking && request.option & option::GLOBAL_PADDING != 0,
+            shake: masking.then(|| shake128(iv)),
+            cipher,
+            iv: *iv,
+            count: 0,
+        }
+    }
+
+    fn next_nonce(&mut self) -> [u8; 12] {
+        let mut nonce = [0u8; 12];
+        nonce[..2].copy_from_slice(&self.count.to_be_bytes());
+        nonce[2..].copy_from_slice(&self.iv[2..12]);
+        self.count = self.count.wrapping_add(1);
+        nonce
+    }
+
+    fn next_padding(&mut self) -> usize {
+        match (&mut self.shake, self.padding) {
+            (Some(shake), true) => next_u16(shake) as usize % MAX_PADDING,
+            _ => 0,
+        }
+    }
+
+    fn next_mask(&mut self) -> u16 {
+        self.shake.as_mut().map_or(0, next_u16)
+    }
+}
+
+/// 以 `seed` 为输入的 SHAKE128 输出流
+fn shake128(seed: &[u8]) -> Shake128Reader {
+    let mut shake = Shake128::default();
+    Update::update(&mut shake, seed);
+    shake.finalize_xof()
+}
+
+/// 从输出流取出下一个大端 u16
+fn next_u16(shake: &mut Shake128Reader) -> u16 {
+    let mut buf = [0u8; 2];
+    shake.read(&mut buf);
+    u16::from_be_bytes(buf)
+}
+
+/// 数据块加密器
+pub struct ChunkEncoder {
+    state: ChunkState,
+}
+
+impl ChunkEncoder {
+    pub fn new(request: &VmessRequest, key: &[u8; 16], iv: &[u8; 16]) -> Self {
+        Self {
+            state: ChunkState::new(request, key, iv),
+        }
+    }
+
+    /// 加密一块数据 (不超过 [`MAX_CHUNK_PAYLOAD`])，空数据表示 EOF
+    pub fn encode(&mut self, plaintext: &[u8], out: &mut Vec<u8>) {
+        debug_assert!(plaintext.len() <= MAX_CHUNK_PAYLOAD);
+        let state = &mut self.state;
+        if !state.chunked {
+            out.extend_from_slice(plaintext);
+            return;
+        }
+
+        let padding = state.next_padding();
+        let sealed = if state.cipher.is_aead() {
+            let nonce = state.next_nonce();
+            state.cipher.seal(nonce, plaintext)
+        } else {
+            plaintext.to_vec()
+        };
+        let size = (sealed.len() + padding) as u16 ^ state.next_mask();
+        out.extend_from_slice(&size.to_be_bytes());
+        out.extend_from_slice(&sealed);
+
+        let start = out.len();
+        out.resize(start + padding, 0);
+        rand::thread_rng().fill_bytes(&mut out[start..]);
+    }
+
+    /// 写入 EOF 标记 (不分块时没有标记，由关闭连接表示)
+    pub fn encode_eof(&mut self, out: &mut Vec<u8>) {
+        if self.state.chunked {
+            self.encode(&[], out);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReadState {
+    Length,
+    Payload { size: usize, padding: usize },
+    Eof,
+}
+
+/// 数据块解密器
+pub struct ChunkDecoder {
+    state: ChunkState,
+    read_state: ReadState,
+}
+
+impl ChunkDecoder {
+    pub fn new(request: &VmessRequest, key: &[u8; 16], iv: &[u8; 16]) -> Self {
+        Self {
+            state: ChunkState::new(request, key, iv),
+            read_state: ReadState::Length,
+        }
+    }
+
+    /// 从 `input` 中解出尽可能多的完整数据块，明文追加到 `output`
+    pub fn decode(&mut self, input: &mut BytesMut, output: &mut BytesMut) -> io::Result<()> {
+        let state = &mut self.state;
+        if !state.chunked {
+            output.put(input.split());
+            return Ok(());
+        }
+
+        loop {
+            match self.read_state {
+                ReadState::Eof => {
+                    // EOF 之后的数据直接丢弃
+                    input.clear();
+                    return Ok(());
+                }
+                ReadState::Length => {
+                    if input.len() < 2 {
+                        return Ok(());
+                    }
+                    let padding = state.next_padding();
+                    let size = (input.get_u16() ^ state.next_mask()) as usize;
+                    let min = if state.cipher.is_aead() { TAG_LEN } else { 0 } + padding;
+                    if size < min {
+                        return Err(invalid_data(format!("VMess 数据块长度无效: {}", size)));
+                    }
+                    self.read_state = ReadState::Payload { size, padding };
+                }
+                ReadState::Payload { size, padding } => {
+                    if input.len() < size {
+                        return Ok(());
+                    }
+                    let chunk = input.split_to(size);
+                    let sealed = &chunk[..size - padding];
+                    let plaintext = if state.cipher.is_aead() {
+                        let nonce = state.next_nonce();
+                        state.cipher.open(nonce, sealed)?
+                    } else {
+                        sealed.to_vec()
+                    };
+                    if plaintext.is_empty() {
+                        self.read_state = ReadState::Eof;
+                    } else {
+                        output.extend_from_slice(&plaintext);
+                        self.read_state = ReadState::Length;
+                    }
+                }
+            }
+        }
+    }
+
+    /// 是否可以正常结束 (已收到 EOF 块，或不分块时随时可以)
+    pub fn at_boundary(&self, input: &BytesMut) -> bool {
+        !self.state.chunked || (self.read_state == ReadState::Eof) || (self.read_state == ReadState::Length && input.is_empty())
+    }
+
+    /// 是否已收到 EOF 块
+    pub fn is_eof(&self) -> bool {
+        self.read_state == ReadState::Eof
+    }
+}
+
+/// 服务端 VMess 数据流
+///
+/// 读取时解密请求方向的数据块；写入时加密响应数据，响应头随首次写入一起发出，
+/// 关闭写方向时发送 EOF 块。
+pub struct VmessStream<S> {
+    inner: S,
+    encoder: ChunkEncoder,
+    /// 尚未发出的响应头
+    response_header: Option<Vec<u8>>,
+    eof_sent: bool,
+    /// 待写出的密文
+    pending: Vec<u8>,
+    pending_pos: usize,
+    decoder: ChunkDecoder,
+    /// 已收到尚未解密的密文
+    incoming: BytesMut,
+    /// 已解密尚未交给调用方的明文
+    plaintext: BytesMut,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> VmessStream<S> {
+    pub fn new(inner: S, request: &VmessRequest) -> Self {
+        let (response_key, response_iv) = response_keys(request);
+        Self {
+            inner,
+            encoder: ChunkEncoder::new(request, &response_key, &response_iv),
+            response_header: Some(encode_response_header(request)),
+            eof_sent: false,
+            pending: Vec::new(),
+            pending_pos: 0,
+            decoder: ChunkDecoder::new(request, &request.body_key, &request.body_iv),
+            incoming: BytesMut::new(),
+            plaintext: BytesMut::new(),
+        }
+    }
+
+    fn queue_response_header(&mut self) {
+        if let Some(header) = self.response_header.take() {
+            self.pending.extend_from_slice(&header);
+        }
+    }
+
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.pending_pos < self.pending.len() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.pending[self.pending_pos..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.pending_pos += n;
+        }
+        self.pending.clear();
+        self.pending_pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for VmessStream<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            if !this.plaintext.is_empty() {
+                let n = this.plaintext.len().min(buf.remaining());
+                buf.put_slice(&this.plaintext[..n]);
+                this.plaintext.advance(n);
+                return Poll::Ready(Ok(()));
+            }
+
+            this.decoder.decode(&mut this.incoming, &mut this.plaintext)?;
+            if !this.plaintext.is_empty() {
+                continue;
+            }
+            if this.decoder.is_eof() {
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut raw = [0u8; 16 * 1024];
+            let mut raw_buf = ReadBuf::new(&mut raw);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut raw_buf))?;
+            let n = raw_buf.filled().len();
+            if n == 0 {
+                if this.decoder.at_boundary(&this.incoming) {
+                    return Poll::Ready(Ok(()));
+                }
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "VMess 客户端在数据块中途关闭了连接",
+                )));
+            }
+            this.incoming.extend_from_slice(raw_buf.filled());
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for VmessStream<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        ready!(this.poll_drain(cx))?;
+        if buf.is_empty() {
+            // 空数据块表示 EOF，不能编码
+            return Poll::Ready(Ok(0));
+        }
+
+        let n = buf.len().min(MAX_CHUNK_PAYLOAD);
+        this.queue_response_header();
+        this.encoder.encode(&buf[..n], &mut this.pending);
+        // 密文已缓存，剩余部分留给下次写入或 flush 发出
+        let _ = this.poll_drain(cx)?;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        this.queue_response_header();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        this.queue_response_header();
+        if !this.eof_sent {
+            this.encoder.encode_eof(&mut this.pending);
+            this.eof_sent = true;
+        }
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::*;
+    use super::super::VmessCodec;
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use uuid::Uuid;
+
+    /// 去掉数据块后的随机填充，便于与参考向量比较
+    fn strip_padding(wire: &[u8], chunks: &[(&str, usize)]) -> String {
+        let mut pos = 0;
+        let mut out = String::new();
+        for (chunk, padding) in chunks {
+            let len = chunk.len() / 2;
+            out.push_str(&hex::encode(&wire[pos..pos + len]));
+            pos += len + padding;
+        }
+        assert_eq!(pos, wire.len());
+        out
+    }
+
+    async fn roundtrip(request_hex: &str, response_chunks: &[(&str, usize)]) {
+        let wire = hex::decode(request_hex).unwrap();
+        let codec = VmessCodec::new(vec![Uuid::parse_str(USER).unwrap()]);
+        let mut reader = &wire[..];
+        let (_, request) = codec.read_request_at(&mut reader, TIMESTAMP).await.unwrap();
+
+        let (client, mut server) = tokio::io::duplex(64 * 1024);
+        let mut stream = VmessStream::new(client, &request);
+        server.write_all(reader).await.unwrap();
+
+        let mut body = Vec::new();
+        stream.read_to_end(&mut body).await.unwrap();
+        assert_eq!(body, b"GET / HTTP/1.1\r\n\r\n");
+
+        stream.write_all(b"HTTP/1.1 200 OK\r\n\r\n").await.unwrap();
+        stream.shutdown().await.unwrap();
+        let mut response = Vec::new();
+        server.read_to_end(&mut response).await.unwrap();
+
+        let (header, chunks) = response.split_at(AES_RESPONSE_HEADER.len() / 2);
+        assert_eq!(hex::encode(header), AES_RESPONSE_HEADER);
+        let expected: String = response_chunks.iter().map(|(chunk, _)| *chunk).collect();
+        assert_eq!(strip_padding(chunks, response_chunks), expected);
+    }
+
+    #[tokio::test]
+    async fn test_aes_128_gcm_stream() {
+        roundtrip(AES_REQUEST, AES_RESPONSE_CHUNKS).await;
+    }
+
+    #[tokio::test]
+    async fn test_chacha20_poly1305_stream() {
+        roundtrip(CHACHA_REQUEST, CHACHA_RESPONSE_CHUNKS).await;
+    }
+
+    #[tokio::test]
+    async fn test_none_stream() {
+        roundtrip(NONE_REQUEST, NONE_RESPONSE_CHUNKS).await;
+    }
+
+    #[test]
+    fn test_decode_byte_by_byte() {
+        let request = VmessRequest {
+            body_iv: [7; 16],
+            body_key: [9; 16],
+            response_header: 0,
+            option: option::CHUNK_STREAM | option::CHUNK_MASKING | option::GLOBAL_PADDING,
+            security: Security::ChaCha20Poly1305,
+            command: super::super::Command::Tcp,
+            address: None,
+        };
+        let mut encoder = ChunkEncoder::new(&request, &request.body_key, &request.body_iv);
+        let mut wire = Vec::new();
+        let payload = vec![0xab; MAX_CHUNK_PAYLOAD + 10];
+        for chunk in payload.chunks(MAX_CHUNK_PAYLOAD) {
+            encoder.encode(chunk, &mut wire);
+        }
+        encoder.encode_eof(&mut wire);
+
+        let mut decoder = ChunkDecoder::new(&request, &request.body_key, &request.body_iv);
+        let mut input = BytesMut::new();
+        let mut output = BytesMut::new();
+        for byte in &wire {
+            input.put_u8(*byte);
+            decoder.decode(&mut input, &mut output).unwrap();
+        }
+        assert!(decoder.is_eof());
+        assert_eq!(output.to_vec(), payload);
+
+        // 篡改任意密文字节都会解密失败
+        let mut decoder = ChunkDecoder::new(&request, &request.body_key, &request.body_iv);
+        let mut tampered = BytesMut::from(&wire[..]);
+        tampered[10] ^= 1;
+        assert!(decoder.decode(&mut tampered, &mut BytesMut::new()).is_err());
+    }
+}
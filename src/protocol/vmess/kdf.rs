@@ -0,0 +1,112 @@
+//! VMess 密钥派生与校验和
+//!
+//! AEAD 头部使用嵌套 HMAC 构成的 KDF: 最内层为以 "VMess AEAD KDF" 为密钥的 HMAC-SHA256，
+//! 每个路径元素再以上一层 HMAC 作为哈希函数套一层 HMAC。
+
+use md5::{Digest, Md5};
+use sha2::Sha256;
+use uuid::Uuid;
+
+const KDF_SALT: &[u8] = b"VMess AEAD KDF";
+/// HMAC-SHA256 及其嵌套形式的分组长度
+const BLOCK_LEN: usize = 64;
+
+pub const SALT_AUTH_ID_ENCRYPTION_KEY: &[u8] = b"AES Auth ID Encryption";
+pub const SALT_RESP_HEADER_LEN_KEY: &[u8] = b"AEAD Resp Header Len Key";
+pub const SALT_RESP_HEADER_LEN_IV: &[u8] = b"AEAD Resp Header Len IV";
+pub const SALT_RESP_HEADER_PAYLOAD_KEY: &[u8] = b"AEAD Resp Header Key";
+pub const SALT_RESP_HEADER_PAYLOAD_IV: &[u8] = b"AEAD Resp Header IV";
+pub const SALT_HEADER_PAYLOAD_KEY: &[u8] = b"VMess Header AEAD Key";
+pub const SALT_HEADER_PAYLOAD_IV: &[u8] = b"VMess Header AEAD Nonce";
+pub const SALT_HEADER_LENGTH_KEY: &[u8] = b"VMess Header AEAD Key_Length";
+pub const SALT_HEADER_LENGTH_IV: &[u8] = b"VMess Header AEAD Nonce_Length";
+
+/// 用户的指令密钥: MD5(UUID || 固定盐)
+pub fn cmd_key(uuid: &Uuid) -> [u8; 16] {
+    let mut hasher = Md5::new();
+    hasher.update(uuid.as_bytes());
+    hasher.update(b"c48619fe-8f02-49e0-b9e9-edf763e17e21");
+    hasher.finalize().into()
+}
+
+/// 按路径派生 32 字节密钥
+pub fn kdf(key: &[u8], path: &[&[u8]]) -> [u8; 32] {
+    let mut chain = Vec::with_capacity(path.len() + 1);
+    chain.push(KDF_SALT);
+    chain.extend_from_slice(path);
+    nested_hmac(&chain, key)
+}
+
+/// 按路径派生 16 字节密钥
+pub fn kdf16(key: &[u8], path: &[&[u8]]) -> [u8; 16] {
+    kdf(key, path)[..16].try_into().unwrap()
+}
+
+/// 按路径派生 12 字节 AEAD nonce
+pub fn kdf12(key: &[u8], path: &[&[u8]]) -> [u8; 12] {
+    kdf(key, path)[..12].try_into().unwrap()
+}
+
+/// 以 `chain[..n-1]` 构成的 HMAC 为哈希函数、`chain[n-1]` 为密钥计算 HMAC
+fn nested_hmac(chain: &[&[u8]], data: &[u8]) -> [u8; 32] {
+    let (key, parent) = chain.split_last().expect("KDF 路径不能为空");
+    let hash = |input: &[u8]| -> [u8; 32] {
+        if parent.is_empty() {
+            Sha256::digest(input).into()
+        } else {
+            nested_hmac(parent, input)
+        }
+    };
+
+    let mut block = [0u8; BLOCK_LEN];
+    if key.len() > BLOCK_LEN {
+        block[..32].copy_from_slice(&hash(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Vec::with_capacity(BLOCK_LEN + data.len());
+    inner.extend(block.iter().map(|b| b ^ 0x36));
+    inner.extend_from_slice(data);
+    let inner_hash = hash(&inner);
+
+    let mut outer = Vec::with_capacity(BLOCK_LEN + inner_hash.len());
+    outer.extend(block.iter().map(|b| b ^ 0x5c));
+    outer.extend_from_slice(&inner_hash);
+    hash(&outer)
+}
+
+/// FNV-1a 32 位哈希 (请求头校验)
+pub fn fnv1a32(data: &[u8]) -> u32 {
+    data.iter()
+        .fold(0x811c_9dc5, |hash: u32, byte| (hash ^ *byte as u32).wrapping_mul(0x0100_0193))
+}
+
+/// CRC-32 (IEEE) 校验和 (认证 ID 校验)
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32fast::hash(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cmd_key_and_kdf() {
+        // 由独立的 Python 参考实现 (hmac + hashlib) 计算
+        let uuid = Uuid::parse_str("b831381d-6324-4d53-ad4f-8cda48b30811").unwrap();
+        let key = cmd_key(&uuid);
+        assert_eq!(hex::encode(key), "b50d916ac0cec067981af8e5f38a758f");
+        assert_eq!(
+            hex::encode(kdf16(&key, &[SALT_AUTH_ID_ENCRYPTION_KEY])),
+            "1415ba74ca8b3d041a8f583fb4116315"
+        );
+    }
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(fnv1a32(b""), 0x811c_9dc5);
+        assert_eq!(fnv1a32(b"a"), 0xe40c_292c);
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::{anyhow, Result};
+use bytes::{Buf, BufMut, BytesMut};
+
+use super::kdf::fnv1a32;
+use crate::protocol::vless::Address;
+
+/// 请求头版本
+const VERSION: u8 = 1;
+
+/// 请求选项
+pub mod option {
+    /// 数据按块分帧
+    pub const CHUNK_STREAM: u8 = 0x01;
+    /// 块长度用 SHAKE128 输出流掩码
+    pub const CHUNK_MASKING: u8 = 0x04;
+    /// 每块附加随机填充
+    pub const GLOBAL_PADDING: u8 = 0x08;
+    /// 块长度单独 AEAD 加密
+    pub const AUTHENTICATED_LENGTH: u8 = 0x10;
+}
+
+/// 数据加密方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Security {
+    Aes128Gcm,
+    ChaCha20Poly1305,
+    None,
+}
+
+impl Security {
+    fn from_byte(byte: u8) -> Result<Self> {
+        match byte {
+            0x03 => Ok(Security::Aes128Gcm),
+            0x04 => Ok(Security::ChaCha20Poly1305),
+            0x05 => Ok(Security::None),
+            other => Err(anyhow!("不支持的 VMess 加密方式: {}", other)),
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Security::Aes128Gcm => 0x03,
+            Security::ChaCha20Poly1305 => 0x04,
+            Security::None => 0x05,
+        }
+    }
+}
+
+/// VMess 指令
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Tcp,
+    Udp,
+    Mux,
+}
+
+/// 解密后的 VMess 请求头
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmessRequest {
+    pub body_iv: [u8; 16],
+    pub body_key: [u8; 16],
+    /// 响应头中回显的校验字节
+    pub response_header: u8,
+    pub option: u8,
+    pub security: Security,
+    pub command: Command,
+    /// Mux 指令不携带目标地址
+    pub address: Option<Address>,
+}
+
+impl VmessRequest {
+    /// 解析请求头明文 (末尾 4 字节为 FNV-1a 校验)
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() < 42 {
+            return Err(anyhow!("VMess 请求头过短: {} 字节", data.len()));
+        }
+        let (body, checksum) = data.split_at(data.len() - 4);
+        if fnv1a32(body).to_be_bytes() != checksum {
+            return Err(anyhow!("VMess 请求头校验失败"));
+        }
+
+        let mut buf = BytesMut::from(body);
+        let version = buf.get_u8();
+        if version != VERSION {
+            return Err(anyhow!("不支持的 VMess 版本: {}", version));
+        }
+        let mut body_iv = [0u8; 16];
+        buf.copy_to_slice(&mut body_iv);
+        let mut body_key = [0u8; 16];
+        buf.copy_to_slice(&mut body_key);
+        let response_header = buf.get_u8();
+        let option = buf.get_u8();
+        let padding_security = buf.get_u8();
+        let padding_len = (padding_security >> 4) as usize;
+        let security = Security::from_byte(padding_security & 0x0f)?;
+        let _reserved = buf.get_u8();
+        let command = match buf.get_u8() {
+            0x01 => Command::Tcp,
+            0x02 => Command::Udp,
+            0x03 => Command::Mux,
+            other => return Err(anyhow!("未知的 VMess 指令: {}", other)),
+        };
+        let address = match command {
+            Command::Mux => None,
+            _ => Some(Address::decode(&mut buf)?),
+        };
+        if buf.remaining() != padding_len {
+            return Err(anyhow!("VMess 请求头长度不一致: 剩余 {} 字节，填充 {} 字节", buf.remaining(), padding_len));
+        }
+
+        Ok(Self {
+            body_iv,
+            body_key,
+            response_header,
+            option,
+            security,
+            command,
+            address,
+        })
+    }
+
+    /// 编码请求头明文 (附带 `padding` 与 FNV-1a 校验)
+    pub fn encode(&self, padding: &[u8]) -> Result<BytesMut> {
+        if padding.len() > 15 {
+            return Err(anyhow!("VMess 请求头填充最多 15 字节"));
+        }
+        let mut buf = BytesMut::with_capacity(64 + padding.len());
+        buf.put_u8(VERSION);
+        buf.put_slice(&self.body_iv);
+        buf.put_slice(&self.body_key);
+        buf.put_u8(self.response_header);
+        buf.put_u8(self.option);
+        buf.put_u8(((padding.len() as u8) << 4) | self.security.to_byte());
+        buf.put_u8(0);
+        buf.put_u8(match self.command {
+            Command::Tcp => 0x01,
+            Command::Udp => 0x02,
+            Command::Mux => 0x03,
+        });
+        if let Some(address) = &self.address {
+            address.encode(&mut buf);
+        }
+        buf.put_slice(padding);
+        let checksum = fnv1a32(&buf);
+        buf.put_u32(checksum);
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_request_roundtrip() {
+        let request = VmessRequest {
+            body_iv: [0x22; 16],
+            body_key: [0x33; 16],
+            response_header: 0x5a,
+            option: option::CHUNK_STREAM | option::CHUNK_MASKING,
+            security: Security::Aes128Gcm,
+            command: Command::Tcp,
+            address: Some(Address::Ipv4(Ipv4Addr::new(127, 0, 0, 1), 8080)),
+        };
+        let encoded = request.encode(&[0x44; 5]).unwrap();
+        assert_eq!(VmessRequest::decode(&encoded).unwrap(), request);
+
+        // 任意一位被篡改都会导致校验失败
+        let mut tampered = encoded.to_vec();
+        tampered[20] ^= 1;
+        assert!(VmessRequest::decode(&tampered).is_err());
+    }
+}
@@ -1,14 +1,26 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::net::SocketAddr;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tracing::{error, info, warn, debug};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, debug, warn};
 use uuid::Uuid;
 
-use crate::config::{Config, Inbound, Security};
-use crate::network::ConnectionManager;
-use crate::protocol::vless::{Command, VlessCodec};
-use crate::transport::{RealityServer, XhttpServer};
-use crate::handler::serve_vless;
+use crate::config::{Config, Inbound, InboundSettings, OverloadConfig, Protocol as InboundProtocol, Security, SockOpt, TrustedProxies};
+use crate::network::metrics::HandshakeGuard;
+use crate::network::{AttemptOutcome, ConnectionAttempt, ConnectionManager, CountingStream, Metrics, RelayOptions, Router, ThroughputSampling};
+use crate::protocol::vless::{Address, Authenticator, Command, VlessCodec};
+use crate::protocol::HeaderLength;
+use crate::transport::reality::server_rustls::PrefixedStream;
+use crate::transport::xhttp::Sessions;
+use crate::transport::{GrpcServer, HttpTransport, RealityServer, XhttpServer};
+use crate::handler::{reject_overloaded, serve_dokodemo_udp, InboundCodec};
+use crate::protocol::shadowsocks::ShadowsocksCodec;
+use crate::protocol::socks::SocksCodec;
+use crate::protocol::vmess::VmessCodec;
+use crate::outbound::dial::apply_sockopt;
 
 /// 定义通用的 AsyncStream trait 以支持 TCP 和 TLS 流
 pub trait AsyncStream: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send {}
@@ -18,27 +30,101 @@ impl<T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send> AsyncStream
 pub struct Server {
     config: Config,
     connection_manager: ConnectionManager,
+    router: std::sync::Arc<Router>,
+    /// VLESS 入站的外部认证后端
+    authenticator: Option<std::sync::Arc<dyn Authenticator>>,
 }
 
 impl Server {
     /// 创建新的服务器
     pub fn new(config: Config) -> Result<Self> {
+        let connection_manager = ConnectionManager::new()
+            .with_throughput_sampling(ThroughputSampling::from_config(&config.log))
+            .with_relay_options(RelayOptions::from_config(&config.relay));
+        let router = Router::new(&config.routing, &config.outbounds)?
+            .with_connection_manager(connection_manager.clone());
         Ok(Self {
             config,
-            connection_manager: ConnectionManager::new(),
+            connection_manager,
+            router: std::sync::Arc::new(router),
+            authenticator: None,
         })
     }
 
+    /// 按入站统计的并发指标，入站开始监听前登记
+    pub fn metrics(&self) -> std::sync::Arc<Metrics> {
+        self.connection_manager.metrics().clone()
+    }
+
+    /// 使用外部认证后端授权 VLESS 客户端，取代配置中的 `clients` 列表
+    pub fn with_authenticator(mut self, authenticator: std::sync::Arc<dyn Authenticator>) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
     /// 运行服务器
     pub async fn run(self) -> Result<()> {
+        self.run_until(std::future::pending()).await
+    }
+
+    /// 运行服务器，`signal` 完成后优雅关闭
+    ///
+    /// 各入站停止接受新连接，XHTTP 连接收到 GOAWAY；进行中的连接在
+    /// `runtime.shutdownTimeout` 内结束，超时后终止剩余的 XHTTP 会话。
+    pub async fn run_until<F>(self, signal: F) -> Result<()>
+    where
+        F: std::future::Future<Output = ()>,
+    {
         let mut handles = vec![];
+        let shutdown = CancellationToken::new();
+        let shutdown_timeout = Duration::from_secs(self.config.runtime.shutdown_timeout);
+
+        // 定期按入站输出并发指标
+        if self.config.log.metrics_interval > 0 {
+            let metrics = self.metrics();
+            let interval = Duration::from_secs(self.config.log.metrics_interval);
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(interval);
+                ticker.tick().await;
+                loop {
+                    tokio::select! {
+                        _ = ticker.tick() => metrics.log(),
+                        _ = shutdown.cancelled() => break,
+                    }
+                }
+            });
+        }
+
+        // 同一 sessionScope 的 XHTTP 入站共用会话表，上行与下行可以到达不同的入站
+        let mut xhttp_scopes: HashMap<String, Sessions> = HashMap::new();
 
         // 为每个入站配置启动监听器
         for inbound in self.config.inbounds.clone() {
+            let xhttp_sessions = inbound
+                .stream_settings
+                .xhttp_settings
+                .as_ref()
+                .and_then(|xhttp| xhttp.session_scope.clone())
+                .map(|scope| xhttp_scopes.entry(scope).or_default().clone());
             let connection_manager = self.connection_manager.clone();
+            let router = self.router.clone();
+            let overload = self.config.overload.clone();
+            let authenticator = self.authenticator.clone();
+            let shutdown = shutdown.clone();
             
             let handle = tokio::spawn(async move {
-                if let Err(e) = Self::run_inbound(inbound, connection_manager).await {
+                let run = Self::run_inbound(
+                    inbound,
+                    xhttp_sessions,
+                    connection_manager,
+                    router,
+                    overload,
+                    authenticator,
+                    shutdown,
+                    shutdown_timeout,
+                );
+                if let Err(e) = run.await {
                     error!("入站处理失败: {}", e);
                 }
             });
@@ -46,18 +132,67 @@ impl Server {
             handles.push(handle);
         }
 
-        // 等待所有任务完成
+        signal.await;
+        info!("🛑 收到退出信号，停止接受新连接");
+        shutdown.cancel();
+
+        // 等待所有入站排空
         for handle in handles {
             handle.await?;
         }
+        info!("👋 服务器已关闭");
 
         Ok(())
     }
 
     /// 运行单个入站配置
-    async fn run_inbound(inbound: Inbound, connection_manager: ConnectionManager) -> Result<()> {
+    #[allow(clippy::too_many_arguments)]
+    async fn run_inbound(
+        inbound: Inbound,
+        xhttp_sessions: Option<Sessions>,
+        connection_manager: ConnectionManager,
+        router: std::sync::Arc<Router>,
+        overload: OverloadConfig,
+        authenticator: Option<std::sync::Arc<dyn Authenticator>>,
+        shutdown: CancellationToken,
+        shutdown_timeout: Duration,
+    ) -> Result<()> {
         let addr = format!("{}:{}", inbound.listen, inbound.port);
         let sockopt = &inbound.stream_settings.sockopt;
+        let metrics = connection_manager.metrics().register(inbound.name(), inbound.max_handshakes);
+
+        // dokodemo 的 UDP 转发在同一端口上监听 UDP
+        let is_dokodemo = matches!(inbound.protocol, InboundProtocol::Dokodemo);
+        let udp_task = if is_dokodemo && inbound.settings.network.has_udp() {
+            let socket = tokio::net::UdpSocket::bind(&addr).await?;
+            info!("🎯 监听 {} (UDP, 协议: {:?})", addr, inbound.protocol);
+            let udp = serve_dokodemo_udp(
+                socket,
+                dokodemo_target(&inbound.settings),
+                router.clone(),
+                inbound.settings.max_udp_datagram_size,
+                sockopt.clone(),
+            );
+            let shutdown = shutdown.clone();
+            Some(tokio::spawn(async move {
+                tokio::select! {
+                    result = udp => {
+                        if let Err(e) = result {
+                            error!("UDP 入站处理失败: {}", e);
+                        }
+                    }
+                    _ = shutdown.cancelled() => {}
+                }
+            }))
+        } else {
+            None
+        };
+        if is_dokodemo && !inbound.settings.network.has_tcp() {
+            if let Some(task) = udp_task {
+                task.await?;
+            }
+            return Ok(());
+        }
         
         // 使用 socket2 创建监听器以支持 TCP Fast Open
         let listener = if sockopt.tcp_fast_open {
@@ -106,7 +241,7 @@ impl Server {
 
         info!("🎯 监听 {} (协议: {:?})", addr, inbound.protocol);
 
-        // 创建 VLESS 编解码器
+        // 创建请求解码器 (VMess / Shadowsocks / SOCKS 入站使用各自的协议，其余按 VLESS 处理)
         let uuids: Vec<Uuid> = inbound
             .settings
             .clients
@@ -114,7 +249,56 @@ impl Server {
             .filter_map(|c| Uuid::parse_str(&c.id).ok())
             .collect();
 
-        let codec = VlessCodec::new(uuids);
+        let codec = match inbound.protocol {
+            InboundProtocol::Shadowsocks => {
+                let settings = &inbound.settings;
+                InboundCodec::Shadowsocks(ShadowsocksCodec::new(
+                    settings.method.as_deref().unwrap_or_default(),
+                    settings.password.as_deref().unwrap_or_default(),
+                )?)
+            }
+            InboundProtocol::Dokodemo => InboundCodec::Dokodemo(dokodemo_target(&inbound.settings)),
+            InboundProtocol::Socks => {
+                let settings = &inbound.settings;
+                InboundCodec::Socks(SocksCodec::new(&settings.accounts, settings.udp))
+            }
+            InboundProtocol::Vmess => {
+                let mut codec = VmessCodec::new(uuids);
+                for client in &inbound.settings.clients {
+                    if let Ok(uuid) = Uuid::parse_str(&client.id) {
+                        codec.set_email(uuid, &client.email);
+                    }
+                }
+                InboundCodec::Vmess(codec)
+            }
+            _ => {
+                let mut codec = VlessCodec::new(uuids);
+                for client in &inbound.settings.clients {
+                    if let Ok(uuid) = Uuid::parse_str(&client.id) {
+                        codec.set_email(uuid, &client.email);
+                        codec.set_flow(uuid, &client.flow);
+                    }
+                }
+                if let Some(authenticator) = authenticator {
+                    codec = codec.with_authenticator(authenticator);
+                }
+                InboundCodec::Vless(codec)
+            }
+        };
+
+        // Proxy Protocol 可信名单只在启动时解析一次；等待头部沿用入站的握手超时，
+        // 避免只发半个头部的对端一直占着连接槽与握手名额
+        let proxy_protocol = match sockopt.trusted_proxies()? {
+            Some(trusted) => {
+                let secs = match (&inbound.stream_settings.security, &inbound.stream_settings.reality_settings) {
+                    (Security::Reality, Some(reality)) => reality.handshake_timeout,
+                    _ => crate::transport::reality::DEFAULT_HANDSHAKE_TIMEOUT,
+                };
+                let timeout = (secs > 0).then(|| Duration::from_secs(secs));
+                Some(std::sync::Arc::new(ProxyProtocol { trusted, timeout }))
+            }
+            None => None,
+        };
 
         // 创建 Reality 服务器 (如果启用)
         let reality_server = if matches!(inbound.stream_settings.security, Security::Reality) {
@@ -122,12 +306,29 @@ impl Server {
                 let reality_config = crate::transport::reality::RealityConfig {
                     dest: reality_settings.dest.clone(),
                     server_names: reality_settings.server_names.clone(),
-                    private_key: reality_settings.private_key.clone(),
+                    private_key: reality_settings.load_private_key()?,
                     public_key: reality_settings.public_key.clone(),
                     short_ids: reality_settings.short_ids.clone(),
                     fingerprint: reality_settings.fingerprint.clone(),
+                    xver: reality_settings.xver,
+                    max_time_diff: reality_settings.max_time_diff,
+                    cert_refresh_interval: reality_settings.cert_refresh_interval,
+                    dest_map: reality_settings.dest_map.clone(),
+                    min_client_ver: reality_settings.min_client_ver.clone(),
+                    max_client_ver: reality_settings.max_client_ver.clone(),
+                    backend: reality_settings.backend,
+                    max_fallbacks: reality_settings.max_fallbacks,
+                    fallback_rate: reality_settings.fallback_rate,
+                    fallback_burst: reality_settings.fallback_burst,
+                    fallback_pool_size: reality_settings.fallback_pool_size,
+                    fallback_pool_idle_timeout: reality_settings.fallback_pool_idle_timeout,
+                    handshake_timeout: reality_settings.handshake_timeout,
+                    certificate_file: reality_settings.certificate_file.clone(),
+                    key_file: reality_settings.key_file.clone(),
                 };
-                Some(RealityServer::new(reality_config)?)
+                let reality_server = RealityServer::new(reality_config)?.with_metrics(metrics.clone());
+                reality_server.spawn_cert_refresh();
+                Some(reality_server)
             } else {
                 None
             }
@@ -136,8 +337,12 @@ impl Server {
         };
 
 
-        // 创建 XHTTP 服务器 (如果启用)
-        let _xhttp_server = if let Some(xhttp_settings) = &inbound.stream_settings.xhttp_settings {
+        // 创建 HTTP 传输 (gRPC 或 XHTTP，如果启用)
+        let http_transport = if matches!(inbound.stream_settings.network, crate::config::Network::Grpc) {
+            let grpc_settings = inbound.stream_settings.grpc_settings.clone().unwrap_or_default();
+            let grpc_config = crate::transport::grpc::GrpcConfig { service_name: grpc_settings.service_name };
+            Some(HttpTransport::Grpc(GrpcServer::new(grpc_config)?))
+        } else if let Some(xhttp_settings) = &inbound.stream_settings.xhttp_settings {
             let xhttp_config = crate::transport::xhttp::XhttpConfig {
                 mode: match xhttp_settings.mode {
                     crate::config::XhttpMode::Auto => {
@@ -155,60 +360,136 @@ impl Server {
                 },
                 path: xhttp_settings.path.clone(),
                 host: xhttp_settings.host.clone(),
+                path_secret: xhttp_settings.path_secret.clone(),
+                sc_max_each_post_bytes: xhttp_settings.sc_max_each_post_bytes,
+                sc_max_buffered_posts: xhttp_settings.sc_max_buffered_posts,
+                x_padding_bytes: xhttp_settings.x_padding_bytes,
+                session_idle_timeout: xhttp_settings.session_idle_timeout,
+                no_grpc_header: xhttp_settings.no_grpc_header,
+                h2_settings: xhttp_settings.h2_settings,
+                keepalive_period: xhttp_settings.keepalive_period,
+                keepalive_timeout: xhttp_settings.keepalive_timeout,
+                response_headers: xhttp_settings.response_headers.clone(),
+                cors_origins: xhttp_settings.cors_origins.clone(),
+                max_sessions_per_ip: xhttp_settings.max_sessions_per_ip,
+                max_streams_per_ip: xhttp_settings.max_streams_per_ip,
             };
-            Some(XhttpServer::new(xhttp_config)?)
+            let xhttp_server = XhttpServer::new(xhttp_config)?;
+            Some(HttpTransport::Xhttp(Box::new(match xhttp_sessions {
+                Some(sessions) => xhttp_server.with_sessions(sessions),
+                None => xhttp_server,
+            })))
         } else {
             None
         };
 
+        let inbound_settings = std::sync::Arc::new(inbound.settings.clone());
+
         // 连接数限制 (防止 OOM)
-        const MAX_CONNECTIONS: usize = 4096;
-        let connection_semaphore = std::sync::Arc::new(tokio::sync::Semaphore::new(MAX_CONNECTIONS));
-        
-        info!("🔒 最大并发连接数: {}", MAX_CONNECTIONS);
+        let max_connections = overload.max_connections;
+        let connection_semaphore = std::sync::Arc::new(tokio::sync::Semaphore::new(max_connections));
+        // 明文入站可以看到 HTTP 请求，过载时回复 503；TLS/Reality 入站直接关闭
+        let sniff_http = matches!(inbound.stream_settings.security, Security::None);
+        let overload_replies = std::sync::Arc::new(tokio::sync::Semaphore::new(MAX_OVERLOAD_REPLIES));
+
+        info!("🔒 最大并发连接数: {}", max_connections);
+        if let Some(max_handshakes) = metrics.max_handshakes() {
+            info!("🔒 最大并发握手数: {}", max_handshakes);
+        }
+
+        // XHTTP 的 HTTP/3 监听，与 TCP 连接共用会话表与连接数限制
+        #[cfg(feature = "http3")]
+        let h3_task = match (&http_transport, inbound.stream_settings.xhttp_settings.as_ref().and_then(|xhttp| xhttp.http3.as_ref())) {
+            (Some(HttpTransport::Xhttp(xhttp)), Some(http3)) => {
+                let reality = inbound.stream_settings.reality_settings.as_ref();
+                // 没有单独配置证书时沿用 Reality 的证书文件
+                let (cert_file, key_file) = match (&http3.certificate_file, &http3.key_file) {
+                    (Some(cert), Some(key)) => (Some(cert.as_str()), Some(key.as_str())),
+                    _ => (
+                        reality.and_then(|reality| reality.certificate_file.as_deref()),
+                        reality.and_then(|reality| reality.key_file.as_deref()),
+                    ),
+                };
+                let host = xhttp.host().trim_start_matches("*.");
+                let server_name = match reality.and_then(|reality| reality.server_names.first()) {
+                    _ if !host.is_empty() => host,
+                    Some(name) => name.trim_start_matches("*."),
+                    None => "localhost",
+                };
+                let udp_addr = SocketAddr::new(listener.local_addr()?.ip(), http3.port.unwrap_or(inbound.port));
+                let endpoint = xhttp.bind_h3(udp_addr, cert_file, key_file, server_name)?;
+                info!("🎯 监听 {} (XHTTP/3, UDP)", udp_addr);
+                Some(tokio::spawn(Self::serve_http3(
+                    endpoint,
+                    (**xhttp).clone(),
+                    codec.clone(),
+                    connection_manager.clone(),
+                    router.clone(),
+                    inbound_settings.clone(),
+                    sockopt.clone(),
+                    connection_semaphore.clone(),
+                    shutdown.clone(),
+                )))
+            }
+            _ => None,
+        };
 
         // 接受连接循环
         loop {
-            // 获取连接许可
-            let permit = match connection_semaphore.clone().acquire_owned().await {
-                Ok(p) => p,
-                Err(_) => {
-                    error!("连接限制信号量已关闭");
-                    return Ok(());
-                }
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = shutdown.cancelled() => break,
             };
-            
-            match listener.accept().await {
+            match accepted {
                 Ok((stream, addr)) => {
-                    // 获取 sockopt 配置
-                    let sockopt = &inbound.stream_settings.sockopt;
-                    
-                    // 应用 TCP No Delay 配置
-                    if sockopt.tcp_no_delay {
-                        if let Err(e) = stream.set_nodelay(true) {
-                            error!("设置 TCP_NODELAY 失败: {}", e);
+                    // 获取连接许可，已满时立即拒绝而不是让连接在队列中等待
+                    let permit = match connection_semaphore.clone().try_acquire_owned() {
+                        Ok(p) => p,
+                        Err(tokio::sync::TryAcquireError::NoPermits) => {
+                            crate::log_limited!(warn, "overload_reject", "⚠️ 连接数已达上限 {}，拒绝来自 {} 的连接", max_connections, addr);
+                            ConnectionAttempt::new(addr, stream.local_addr().ok()).record(AttemptOutcome::Overloaded, None);
+                            spawn_reject(stream, &overload_replies, sniff_http, overload.retry_after);
+                            continue;
                         }
-                    }
+                        Err(tokio::sync::TryAcquireError::Closed) => {
+                            error!("连接限制信号量已关闭");
+                            return Ok(());
+                        }
+                    };
+
+                    // 本入站进行中的握手已满时同样立即拒绝，不影响其他入站
+                    let Some(handshake) = metrics.try_begin_handshake() else {
+                        crate::log_limited!(warn, "handshake_reject", "⚠️ 入站 {} 进行中的握手数已达上限，拒绝来自 {} 的连接", metrics.name(), addr);
+                        ConnectionAttempt::new(addr, stream.local_addr().ok()).record(AttemptOutcome::Overloaded, None);
+                        spawn_reject(stream, &overload_replies, sniff_http, overload.retry_after);
+                        continue;
+                    };
+                    let connection = metrics.track_connection();
+
+                    // 应用 sockopt 配置 (TCP_NODELAY / SO_LINGER)
+                    let sockopt = inbound.stream_settings.sockopt.clone();
+                    apply_sockopt(&stream, &sockopt);
                     
                     info!("📥 新连接来自: {}", addr);
 
                     let codec = codec.clone();
                     let reality_server = reality_server.clone();
                     let connection_manager = connection_manager.clone();
-                    let _xhttp_server = _xhttp_server.clone();
-                    let sniffing_enabled = inbound.settings.sniffing.enabled;
-                    let tcp_no_delay = inbound.stream_settings.sockopt.tcp_no_delay;
-                    let accept_proxy_protocol = inbound.stream_settings.sockopt.accept_proxy_protocol;
+                    let router = router.clone();
+                    let http_transport = http_transport.clone();
+                    let inbound_settings = inbound_settings.clone();
+                    let proxy_protocol = proxy_protocol.clone();
 
                     tokio::spawn(async move {
                         // 持有 permit 直到连接结束，自动释放
                         let _permit = permit;
+                        let _connection = connection;
                         
                         if let Err(e) =
-                            Self::handle_client(stream, codec, reality_server, _xhttp_server, connection_manager, sniffing_enabled, tcp_no_delay, accept_proxy_protocol)
+                            Self::handle_client(stream, handshake, codec, reality_server, http_transport, connection_manager, router, inbound_settings, sockopt, proxy_protocol)
                                 .await
                         {
-                            error!("客户端处理失败: {}", e);
+                            crate::log_limited!(error, "client_failed", "客户端处理失败: {}", e);
                         }
                         // permit 在这里自动 drop，释放连接槽
                     });
@@ -218,80 +499,361 @@ impl Server {
                 }
             }
         }
+
+        drop(listener);
+        if let Some(transport) = &http_transport {
+            transport.shutdown();
+        }
+
+        // 所有许可归还即所有连接已结束
+        let all_permits = u32::try_from(max_connections).unwrap_or(u32::MAX);
+        let drained = tokio::time::timeout(shutdown_timeout, connection_semaphore.acquire_many(all_permits)).await;
+        if drained.is_err() {
+            let active = max_connections - connection_semaphore.available_permits();
+            warn!("⚠️ 入站 {} 排空超时，终止剩余的 {} 个连接", addr, active);
+            if let Some(transport) = &http_transport {
+                transport.terminate();
+                // 终止后连接很快结束，稍等以便客户端收到 GOAWAY
+                let _ = tokio::time::timeout(Duration::from_secs(1), connection_semaphore.acquire_many(all_permits)).await;
+            }
+        }
+        if let Some(task) = udp_task {
+            task.await?;
+        }
+        #[cfg(feature = "http3")]
+        if let Some(task) = h3_task {
+            task.await?;
+        }
+        info!("入站 {} 已关闭", addr);
+        Ok(())
     }
 
     /// 处理客户端连接
     async fn handle_client(
         mut stream: TcpStream,
-        codec: VlessCodec,
+        handshake: HandshakeGuard,
+        codec: InboundCodec,
         reality_server: Option<RealityServer>,
-        xhttp_server: Option<XhttpServer>,
+        http_transport: Option<HttpTransport>,
         connection_manager: ConnectionManager,
-        sniffing_enabled: bool,
-        tcp_no_delay: bool,
-        accept_proxy_protocol: bool,
+        router: std::sync::Arc<Router>,
+        inbound_settings: std::sync::Arc<InboundSettings>,
+        sockopt: SockOpt,
+        proxy_protocol: Option<std::sync::Arc<ProxyProtocol>>,
     ) -> Result<()> {
-        // 如果启用 Proxy Protocol，先解析获取真实客户端 IP
-        let _real_client_addr = if accept_proxy_protocol {
-            use tokio::io::AsyncReadExt;
-            let mut pp_buf = [0u8; 512];
-            
-            // Peek 数据来检查是否有 Proxy Protocol 头
-            match stream.peek(&mut pp_buf).await {
-                Ok(n) if n > 0 => {
-                    if crate::protocol::is_proxy_protocol(&pp_buf[..n]) {
-                        // 读取实际数据
-                        let mut read_buf = vec![0u8; n];
-                        stream.read_exact(&mut read_buf).await?;
-                        
-                        match crate::protocol::parse_proxy_protocol(&read_buf) {
-                            Ok((header, _consumed)) => {
-                                info!("📡 Proxy Protocol: 真实客户端 IP = {}", header.source_addr);
-                                Some(header.source_addr)
-                            }
-                            Err(e) => {
-                                warn!("Proxy Protocol 解析失败: {}", e);
-                                None
-                            }
-                        }
-                    } else {
-                        None
-                    }
-                }
-                _ => None,
+        // 如果启用 Proxy Protocol 且对端可信，先解析获取真实客户端 IP
+        let peer_addr = stream.peer_addr()?;
+        let (client_addr, prefix) = match proxy_protocol.filter(|p| p.trusted.contains(peer_addr.ip())) {
+            Some(proxy_protocol) => {
+                let (source, rest) = proxy_protocol.read(&mut stream).await?;
+                (source.unwrap_or(peer_addr), rest)
             }
-        } else {
-            None
+            None => (peer_addr, Vec::new()),
         };
 
-        // 如果配置了 Reality，执行握手
-        let stream: Box<dyn AsyncStream> = if let Some(reality) = reality_server {
-            let tls_stream = reality.accept(stream).await?;
-            Box::new(tls_stream)
-        } else {
-            Box::new(stream)
-        };
+        // 认证前的尝试记录，统计认证前收到的字节数
+        let attempt = ConnectionAttempt::new(client_addr, stream.local_addr().ok());
+        attempt.hold_handshake(handshake);
+        // 为读取头部而多读的数据放回流的开头
+        let stream = CountingStream::new(PrefixedStream::new(prefix, stream), attempt.clone());
+        let handler_attempt = attempt.clone();
 
-        // 定义 VLESS 处理回调
-        let codec_clone = codec.clone();
-        let connection_manager_clone = connection_manager.clone(); 
-        
-        let vless_handler = move |stream: Box<dyn AsyncStream>| {
-            let codec = codec_clone.clone();
-            let connection_manager = connection_manager_clone.clone();
-            async move {
-                serve_vless(stream, codec, connection_manager, sniffing_enabled, tcp_no_delay).await
+        let result = attempt.clone().scope(async move {
+            // 如果配置了 Reality，执行握手
+            let (stream, direct): (Box<dyn AsyncStream>, _) = if let Some(reality) = reality_server {
+                reality.accept(stream).await?
+            } else {
+                (Box::new(stream), None)
+            };
+
+            // 定义协议处理回调
+            let codec_clone = codec.clone();
+            let connection_manager_clone = connection_manager.clone();
+
+            let vless_handler = move |stream: Box<dyn AsyncStream>| {
+                let codec = codec_clone.clone();
+                let connection_manager = connection_manager_clone.clone();
+                let router = router.clone();
+                let inbound_settings = inbound_settings.clone();
+                let sockopt = sockopt.clone();
+                // XHTTP 与 gRPC 在独立任务中处理每个流，需要重新进入尝试记录的作用域
+                handler_attempt.clone().scope(async move {
+                    codec.serve(stream, connection_manager, router, client_addr, inbound_settings, sockopt).await
+                })
+            };
+
+            // 如果配置了 gRPC 或 XHTTP，由其拆出内层流
+            if let Some(transport) = http_transport {
+                transport.accept(stream, client_addr.ip(), vless_handler).await
+            } else if let Some(direct) = direct {
+                // Reality 上的原始 TCP 支持 vision 直接复制
+                direct.scope(vless_handler(stream)).await
+            } else {
+                // 标准 TCP 模式，直接处理 VLESS
+                vless_handler(stream).await
             }
-        };
+        })
+        .await;
 
-        // 如果配置了 XHTTP，使用 XHTTP 处理
-        if let Some(xhttp) = xhttp_server {
-            xhttp.accept(stream, vless_handler).await?;
-        } else {
-            // 标准 TCP 模式，直接处理 VLESS
-            vless_handler(stream).await?;
+        if result.is_err() {
+            attempt.record(AttemptOutcome::Rejected, None);
         }
+        result
+    }
 
-        Ok(())
+    /// 接受 XHTTP/3 的 QUIC 连接，直到入站关闭
+    ///
+    /// 每个 QUIC 连接占用一个连接许可，排空时与 TCP 连接一起等待。
+    #[cfg(feature = "http3")]
+    #[allow(clippy::too_many_arguments)]
+    async fn serve_http3(
+        endpoint: quinn::Endpoint,
+        xhttp: XhttpServer,
+        codec: InboundCodec,
+        connection_manager: ConnectionManager,
+        router: std::sync::Arc<Router>,
+        inbound_settings: std::sync::Arc<InboundSettings>,
+        sockopt: SockOpt,
+        connection_semaphore: std::sync::Arc<tokio::sync::Semaphore>,
+        shutdown: CancellationToken,
+    ) {
+        let local = endpoint.local_addr().ok();
+        loop {
+            let incoming = tokio::select! {
+                incoming = endpoint.accept() => incoming,
+                _ = shutdown.cancelled() => break,
+            };
+            let Some(incoming) = incoming else { break };
+            let Ok(permit) = connection_semaphore.clone().try_acquire_owned() else {
+                crate::log_limited!(warn, "overload_reject", "⚠️ 连接数已达上限，拒绝来自 {} 的 QUIC 连接", incoming.remote_address());
+                incoming.refuse();
+                continue;
+            };
+
+            let xhttp = xhttp.clone();
+            let codec = codec.clone();
+            let connection_manager = connection_manager.clone();
+            let router = router.clone();
+            let inbound_settings = inbound_settings.clone();
+            let sockopt = sockopt.clone();
+            tokio::spawn(async move {
+                let _permit = permit;
+                let connection = match incoming.await {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        debug!("QUIC 握手失败: {}", e);
+                        return;
+                    }
+                };
+                let client_addr = connection.remote_address();
+                info!("📥 新的 QUIC 连接来自: {}", client_addr);
+                let attempt = ConnectionAttempt::new(client_addr, local);
+
+                let vless_handler = move |stream: Box<dyn AsyncStream>| {
+                    let codec = codec.clone();
+                    let connection_manager = connection_manager.clone();
+                    let router = router.clone();
+                    let inbound_settings = inbound_settings.clone();
+                    let sockopt = sockopt.clone();
+                    attempt.clone().scope(async move {
+                        codec.serve(stream, connection_manager, router, client_addr, inbound_settings, sockopt).await
+                    })
+                };
+                if let Err(e) = xhttp.accept_h3(connection, vless_handler).await {
+                    debug!("XHTTP/3 连接结束: {}", e);
+                }
+            });
+        }
+        // 不再接受新的 QUIC 连接，已有连接继续到排空结束
+        endpoint.set_server_config(None);
+    }
+}
+
+/// 每个入站同时回复 503 的过载连接数上限
+///
+/// 每个回复最多占用一个任务与文件描述符约 2 秒，超过上限的连接直接关闭。
+const MAX_OVERLOAD_REPLIES: usize = 32;
+
+/// 拒绝过载时的连接: 在回复名额内交给 [`reject_overloaded`]，名额用尽或无需回复时立即关闭
+fn spawn_reject(stream: TcpStream, replies: &std::sync::Arc<tokio::sync::Semaphore>, sniff_http: bool, retry_after: u64) {
+    if !sniff_http {
+        return;
+    }
+    let Ok(permit) = replies.clone().try_acquire_owned() else {
+        return;
+    };
+    tokio::spawn(async move {
+        let _permit = permit;
+        reject_overloaded(stream, sniff_http, retry_after).await;
+    });
+}
+
+/// dokodemo 入站的转发目标 (由配置校验保证已设置)
+fn dokodemo_target(settings: &InboundSettings) -> Address {
+    Address::from_host(
+        settings.target_address.as_deref().unwrap_or_default(),
+        settings.target_port.unwrap_or_default(),
+    )
+}
+
+/// 入站的 Proxy Protocol 设置
+struct ProxyProtocol {
+    trusted: TrustedProxies,
+    /// 等待头部的时限，None 表示一直等待
+    timeout: Option<Duration>,
+}
+
+impl ProxyProtocol {
+    /// 在时限内读取 Proxy Protocol 头部，超时返回错误，由调用方关闭连接
+    async fn read(&self, stream: &mut TcpStream) -> Result<(Option<SocketAddr>, Vec<u8>)> {
+        match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, read_proxy_protocol(stream))
+                .await
+                .map_err(|_| anyhow!("Proxy Protocol 头部超时: {:?} 内未收到完整头部", timeout))?,
+            None => read_proxy_protocol(stream).await,
+        }
+    }
+}
+
+/// 读取并剥离 Proxy Protocol 头部，返回其中携带的真实客户端地址与头部之后已读取的数据
+///
+/// 不是 Proxy Protocol 头部时，已读取的数据原样返回，由调用方放回流中。
+async fn read_proxy_protocol(stream: &mut TcpStream) -> Result<(Option<SocketAddr>, Vec<u8>)> {
+    let mut buf = Vec::with_capacity(256);
+    loop {
+        match crate::protocol::header_length(&buf) {
+            HeaderLength::Absent => return Ok((None, buf)),
+            HeaderLength::Complete(len) if buf.len() >= len => {
+                let rest = buf.split_off(len);
+                return match crate::protocol::parse_proxy_protocol(&buf) {
+                    Ok((header, _)) => {
+                        info!(
+                            "📡 Proxy Protocol: 真实客户端 IP = {} (authority: {})",
+                            header.source_addr,
+                            header.authority().unwrap_or("-")
+                        );
+                        Ok((Some(header.source_addr), rest))
+                    }
+                    Err(e) => {
+                        crate::log_limited!(warn, "proxy_protocol", "Proxy Protocol 解析失败: {}", e);
+                        Ok((None, rest))
+                    }
+                };
+            }
+            _ => {}
+        }
+        if stream.read_buf(&mut buf).await? == 0 {
+            return Ok((None, buf));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Outbound, RoutingConfig, RoutingRule};
+    use crate::network::RouteContext;
+
+    async fn tcp_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        (client, server)
+    }
+
+    #[tokio::test]
+    async fn test_overload_replies_bounded() {
+        let replies = std::sync::Arc::new(tokio::sync::Semaphore::new(1));
+        let (mut first, server) = tcp_pair().await;
+        spawn_reject(server, &replies, true, 5);
+        let (mut second, server) = tcp_pair().await;
+        spawn_reject(server, &replies, true, 5);
+
+        // 名额用尽后的连接立即关闭，不再等待嗅探首包
+        let mut buf = [0u8; 64];
+        let n = tokio::time::timeout(Duration::from_millis(500), second.read(&mut buf)).await.unwrap();
+        assert!(matches!(n, Ok(0) | Err(_)));
+
+        // 占用名额的连接仍然得到 503
+        first.write_all(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n").await.unwrap();
+        let mut response = Vec::new();
+        first.read_to_end(&mut response).await.unwrap();
+        assert!(response.starts_with(b"HTTP/1.1 503"));
+        // 回复结束后归还名额
+        drop(first);
+        tokio::time::timeout(Duration::from_secs(5), replies.acquire()).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_proxy_protocol_source_is_routed() {
+        let (mut client, mut server) = tcp_pair().await;
+        client
+            .write_all(b"PROXY TCP4 10.20.0.5 10.0.0.1 40000 443\r\npayload")
+            .await
+            .unwrap();
+
+        let (source, rest) = read_proxy_protocol(&mut server).await.unwrap();
+        let source = source.unwrap();
+        assert_eq!(source, "10.20.0.5:40000".parse().unwrap());
+
+        // 与头部一起读到的数据必须保留，放回流中后完整可读
+        let peer = server.peer_addr().unwrap();
+        let mut stream = PrefixedStream::new(rest, server);
+        let mut payload = [0u8; 7];
+        stream.read_exact(&mut payload).await.unwrap();
+        assert_eq!(&payload, b"payload");
+
+        let outbounds = vec![
+            Outbound { protocol: "freedom".to_string(), tag: "direct".to_string(), settings: None, stream_settings: None },
+            Outbound { protocol: "freedom".to_string(), tag: "office".to_string(), settings: None, stream_settings: None },
+        ];
+        let routing = RoutingConfig {
+            rules: vec![RoutingRule {
+                rule_type: "field".to_string(),
+                domain: None,
+                ip: None,
+                port: None,
+                source: Some(vec!["10.20.0.0/16".to_string()]),
+                user: None,
+                outbound_tag: "office".to_string(),
+                balancer_tag: None,
+                tee: None,
+            }],
+            balancers: vec![],
+        };
+        let router = Router::new(&routing, &outbounds).unwrap();
+        let target = Address::Domain("example.com".to_string(), 443);
+
+        assert_eq!(router.route(&RouteContext::new(&target).with_source(source)), "office");
+        assert_eq!(router.route(&RouteContext::new(&target).with_source(peer)), "direct");
+    }
+
+    #[tokio::test]
+    async fn test_no_proxy_protocol_header() {
+        let (mut client, mut server) = tcp_pair().await;
+        client.write_all(b"\x16\x03\x01").await.unwrap();
+
+        let (source, rest) = read_proxy_protocol(&mut server).await.unwrap();
+        assert!(source.is_none());
+        assert_eq!(rest, b"\x16\x03\x01");
+    }
+
+    #[tokio::test]
+    async fn test_partial_proxy_protocol_header_times_out() {
+        let (mut client, mut server) = tcp_pair().await;
+        client.write_all(b"PROXY TCP4 10.20").await.unwrap();
+
+        let sockopt = SockOpt {
+            accept_proxy_protocol: true,
+            proxy_protocol_trusted_addresses: vec!["127.0.0.1".to_string()],
+            ..Default::default()
+        };
+        let proxy_protocol = ProxyProtocol {
+            trusted: sockopt.trusted_proxies().unwrap().unwrap(),
+            timeout: Some(Duration::from_millis(200)),
+        };
+        // 只发半个头部后停住的对端不能一直占着连接
+        let result = tokio::time::timeout(Duration::from_secs(5), proxy_protocol.read(&mut server)).await.unwrap();
+        assert!(result.is_err());
     }
 }
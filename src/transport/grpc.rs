@@ -0,0 +1,468 @@
+use anyhow::{anyhow, Result};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use h2::server::{self, SendResponse};
+use h2::{RecvStream, SendStream};
+use hyper::http::{HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+use std::time::Duration;
+
+use super::xhttp::{GrpcStatus, GrpcTrailer, H2Settings};
+
+/// 单条 gRPC 消息的长度上限，与 gRPC 默认的接收上限一致
+pub const MAX_MESSAGE_SIZE: usize = 4 << 20;
+
+/// 每条下行消息携带的最大数据量
+const MAX_HUNK_SIZE: usize = 32 * 1024;
+
+/// protobuf `Hunk.data` (字段 1，length-delimited) 的 tag
+const HUNK_DATA_TAG: u8 = 0x0a;
+
+/// gRPC 传输配置
+#[derive(Debug, Clone)]
+pub struct GrpcConfig {
+    /// 服务名，客户端请求 `/{serviceName}/Tun`
+    pub service_name: String,
+}
+
+/// gRPC 传输 (network: grpc)，与 Xray 的 `GunService.Tun` 双向流互通
+///
+/// 每个 `Tun` 调用承载一条 VLESS 连接，上下行数据都装在 `Hunk { bytes data = 1; }` 消息里。
+/// 克隆共享同一组关闭信号，与 `XhttpServer` 一致。
+#[derive(Clone)]
+pub struct GrpcServer {
+    /// 完整的调用路径 `/{serviceName}/Tun`
+    path: String,
+    draining: CancellationToken,
+    terminating: CancellationToken,
+}
+
+impl GrpcServer {
+    /// 创建新的 gRPC 服务器
+    pub fn new(config: GrpcConfig) -> Result<Self> {
+        if config.service_name.is_empty() {
+            return Err(anyhow!("gRPC serviceName 不能为空"));
+        }
+        let path = format!("/{}/Tun", config.service_name);
+        info!("gRPC 服务器初始化成功");
+        debug!("路径: {}", path);
+        Ok(Self { path, draining: CancellationToken::new(), terminating: CancellationToken::new() })
+    }
+
+    /// 开始优雅关闭: 向所有连接发送 GOAWAY，进行中的调用继续到结束
+    pub fn shutdown(&self) {
+        self.draining.cancel();
+    }
+
+    /// 终止所有仍在进行的调用 (排空超时后调用)
+    pub fn terminate(&self) {
+        self.draining.cancel();
+        self.terminating.cancel();
+    }
+
+    /// 获取调用路径
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// 处理传入的 HTTP/2 连接
+    pub async fn accept<T, F, Fut>(&self, stream: T, handler: F) -> Result<()>
+    where
+        T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        F: Fn(Box<dyn crate::server::AsyncStream>) -> Fut + Clone + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        debug!("接收到新的 gRPC 连接");
+        let settings = H2Settings::default();
+        let mut connection = server::Builder::new()
+            .initial_window_size(settings.initial_stream_window)
+            .initial_connection_window_size(settings.initial_conn_window)
+            .max_concurrent_streams(settings.max_concurrent_streams)
+            .max_frame_size(settings.max_frame_size)
+            .handshake(stream)
+            .await?;
+        let mut draining = false;
+
+        loop {
+            tokio::select! {
+                result = connection.accept() => match result {
+                    Some(Ok((request, respond))) => {
+                        let path = self.path.clone();
+                        let handler = handler.clone();
+                        let terminating = self.terminating.clone();
+                        tokio::spawn(async move {
+                            tokio::select! {
+                                result = Self::handle_request(&path, request, respond, handler) => {
+                                    if let Err(e) = result {
+                                        debug!("gRPC 调用结束: {}", e);
+                                    }
+                                }
+                                _ = terminating.cancelled() => debug!("gRPC: 关闭时终止进行中的调用"),
+                            }
+                        });
+                    }
+                    Some(Err(e)) => {
+                        debug!("gRPC 连接中断: {}", e);
+                        break;
+                    }
+                    None => break,
+                },
+                _ = self.draining.cancelled(), if !draining => {
+                    draining = true;
+                    debug!("gRPC: 发送 GOAWAY，等待进行中的调用结束");
+                    connection.graceful_shutdown();
+                }
+                _ = self.terminating.cancelled() => {
+                    connection.abrupt_shutdown(h2::Reason::NO_ERROR);
+                    let _ = tokio::time::timeout(Duration::from_secs(1), async {
+                        while connection.accept().await.is_some() {}
+                    })
+                    .await;
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_request<F, Fut>(
+        path: &str,
+        request: Request<RecvStream>,
+        mut respond: SendResponse<Bytes>,
+        handler: F,
+    ) -> Result<()>
+    where
+        F: Fn(Box<dyn crate::server::AsyncStream>) -> Fut + Send + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        let is_grpc = request
+            .headers()
+            .get("content-type")
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("application/grpc"));
+        if request.method() != Method::POST || !is_grpc {
+            // 不是 gRPC 请求，按普通 HTTP 服务回应
+            let status = if request.method() != Method::POST {
+                StatusCode::METHOD_NOT_ALLOWED
+            } else {
+                StatusCode::UNSUPPORTED_MEDIA_TYPE
+            };
+            respond.send_response(Response::builder().status(status).body(()).unwrap(), true)?;
+            return Ok(());
+        }
+        if request.uri().path() != path {
+            // Trailers-Only 响应: 状态直接放在响应头里
+            debug!("gRPC: 未知的方法 {}", request.uri().path());
+            let trailer = GrpcTrailer::error(GrpcStatus::Unimplemented, "unknown method".to_string());
+            let mut response = Response::builder().status(StatusCode::OK).body(()).unwrap();
+            response.headers_mut().insert("content-type", HeaderValue::from_static("application/grpc"));
+            response.headers_mut().extend(trailer_headers(&trailer));
+            respond.send_response(response, true)?;
+            return Ok(());
+        }
+
+        let response = Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "application/grpc")
+            .body(())
+            .unwrap();
+        let mut send_stream = respond.send_response(response, false)?;
+
+        let (client_io, server_io) = tokio::io::duplex(65536);
+        tokio::spawn(handler(Box::new(server_io)));
+        let (mut client_read, client_write) = tokio::io::split(client_io);
+
+        let mut upstream = tokio::spawn(Self::upstream(request.into_body(), client_write));
+        let result = tokio::select! {
+            result = Self::downstream(&mut client_read, &mut send_stream) => result.map_err(|e| {
+                GrpcTrailer::error(GrpcStatus::Internal, e.to_string())
+            }),
+            // 上行正常结束 (客户端 CloseSend) 时继续转发下行
+            Ok(Err(trailer)) = &mut upstream => Err(trailer),
+        };
+        upstream.abort();
+
+        let trailer = match result {
+            Ok(()) => GrpcTrailer::ok(),
+            Err(trailer) => {
+                debug!("gRPC: 调用出错: {}", trailer.message.as_deref().unwrap_or_default());
+                trailer
+            }
+        };
+        send_stream.send_trailers(trailer_headers(&trailer))?;
+        Ok(())
+    }
+
+    /// 拆出请求体中的 gRPC 消息，把其中的数据写给 VLESS 一侧
+    ///
+    /// 数据写入后才归还流量控制窗口，VLESS 一侧读取变慢时客户端随之停止发送。
+    async fn upstream<W: AsyncWrite + Unpin>(mut body: RecvStream, mut writer: W) -> Result<(), GrpcTrailer> {
+        let mut buf = BytesMut::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk.map_err(|e| GrpcTrailer::error(GrpcStatus::Cancelled, e.to_string()))?;
+            let len = chunk.len();
+            buf.extend_from_slice(&chunk);
+            while let Some(message) = next_message(&mut buf)? {
+                let data = decode_hunk(message)?;
+                writer
+                    .write_all(&data)
+                    .await
+                    .map_err(|e| GrpcTrailer::error(GrpcStatus::Unavailable, e.to_string()))?;
+            }
+            let _ = body.flow_control().release_capacity(len);
+        }
+        if !buf.is_empty() {
+            return Err(GrpcTrailer::error(GrpcStatus::Internal, "truncated message".to_string()));
+        }
+        // 客户端已结束发送，通知 VLESS 一侧
+        let _ = writer.shutdown().await;
+        Ok(())
+    }
+
+    /// 把 VLESS 一侧的数据装入 gRPC 消息发给客户端，按对端的流量控制窗口发送
+    async fn downstream<R: AsyncRead + Unpin>(reader: &mut R, send_stream: &mut SendStream<Bytes>) -> Result<()> {
+        let mut buf = vec![0u8; MAX_HUNK_SIZE];
+        loop {
+            let n = reader.read(&mut buf).await?;
+            if n == 0 {
+                return Ok(());
+            }
+            send_all(send_stream, encode_message(&buf[..n])).await?;
+        }
+    }
+}
+
+/// 等待对端窗口后发送数据，避免客户端读取缓慢时数据堆积在内存中
+async fn send_all(send_stream: &mut SendStream<Bytes>, mut data: Bytes) -> Result<()> {
+    while !data.is_empty() {
+        send_stream.reserve_capacity(data.len());
+        let mut available = send_stream.capacity();
+        if available == 0 {
+            available = match std::future::poll_fn(|cx| send_stream.poll_capacity(cx)).await {
+                Some(capacity) => capacity?,
+                None => return Err(anyhow!("gRPC 流已关闭")),
+            };
+        }
+        let chunk = data.split_to(available.min(data.len()));
+        send_stream.send_data(chunk, false)?;
+    }
+    Ok(())
+}
+
+/// 把 trailer 转为 HTTP/2 头部
+fn trailer_headers(trailer: &GrpcTrailer) -> HeaderMap {
+    trailer
+        .build()
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.parse().ok()?, HeaderValue::from_str(&value).ok()?)))
+        .collect()
+}
+
+/// 从缓冲区开头取出一条完整的 Length-Prefixed-Message，数据不足时返回 None
+fn next_message(buf: &mut BytesMut) -> Result<Option<Bytes>, GrpcTrailer> {
+    if buf.len() < 5 {
+        return Ok(None);
+    }
+    if buf[0] != 0 {
+        return Err(GrpcTrailer::error(GrpcStatus::Unimplemented, "compression is not supported".to_string()));
+    }
+    let len = u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]) as usize;
+    if len > MAX_MESSAGE_SIZE {
+        return Err(GrpcTrailer::error(GrpcStatus::ResourceExhausted, format!("message larger than {}", MAX_MESSAGE_SIZE)));
+    }
+    if buf.len() < 5 + len {
+        return Ok(None);
+    }
+    buf.advance(5);
+    Ok(Some(buf.split_to(len).freeze()))
+}
+
+/// 把数据编码为 `Hunk` 消息并加上 gRPC 消息头
+pub fn encode_message(data: &[u8]) -> Bytes {
+    let mut len = [0u8; 10];
+    let len_size = encode_varint(data.len() as u64, &mut len);
+    let hunk_len = 1 + len_size + data.len();
+
+    let mut buf = BytesMut::with_capacity(5 + hunk_len);
+    buf.put_u8(0);
+    buf.put_u32(hunk_len as u32);
+    buf.put_u8(HUNK_DATA_TAG);
+    buf.put_slice(&len[..len_size]);
+    buf.put_slice(data);
+    buf.freeze()
+}
+
+/// 解码 `Hunk` 消息，取出 `data` 字段，跳过未知字段
+pub fn decode_hunk(mut message: Bytes) -> Result<Bytes, GrpcTrailer> {
+    let invalid = || GrpcTrailer::error(GrpcStatus::Internal, "invalid Hunk message".to_string());
+    let mut data = Bytes::new();
+    while message.has_remaining() {
+        let key = decode_varint(&mut message).ok_or_else(invalid)?;
+        let skip = match key & 0x7 {
+            0 => {
+                decode_varint(&mut message).ok_or_else(invalid)?;
+                0
+            }
+            1 => 8,
+            2 => {
+                let len = decode_varint(&mut message).ok_or_else(invalid)? as usize;
+                if len > message.len() {
+                    return Err(invalid());
+                }
+                if key >> 3 == 1 {
+                    data = message.split_to(len);
+                    0
+                } else {
+                    len
+                }
+            }
+            5 => 4,
+            _ => return Err(invalid()),
+        };
+        if skip > message.len() {
+            return Err(invalid());
+        }
+        message.advance(skip);
+    }
+    Ok(data)
+}
+
+/// 写入 protobuf varint，返回占用的字节数
+fn encode_varint(mut value: u64, out: &mut [u8; 10]) -> usize {
+    let mut i = 0;
+    while value >= 0x80 {
+        out[i] = (value as u8) | 0x80;
+        value >>= 7;
+        i += 1;
+    }
+    out[i] = value as u8;
+    i + 1
+}
+
+/// 读取 protobuf varint，数据不完整或超过 64 位时返回 None
+fn decode_varint(buf: &mut Bytes) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        if !buf.has_remaining() {
+            return None;
+        }
+        let byte = buf.get_u8();
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 在内存管道上启动 gRPC 服务器，内层会话回显收到的数据
+    async fn start(server: &GrpcServer) -> h2::client::SendRequest<Bytes> {
+        let echo = |mut stream: Box<dyn crate::server::AsyncStream>| async move {
+            let mut buf = [0u8; 1024];
+            loop {
+                let n = stream.read(&mut buf).await?;
+                if n == 0 {
+                    return Ok(());
+                }
+                stream.write_all(&buf[..n]).await?;
+            }
+        };
+        let (client_io, server_io) = tokio::io::duplex(1 << 16);
+        let server = server.clone();
+        tokio::spawn(async move { server.accept(server_io, echo).await });
+        let (client, connection) = h2::client::handshake(client_io).await.unwrap();
+        tokio::spawn(connection);
+        client
+    }
+
+    fn server() -> GrpcServer {
+        GrpcServer::new(GrpcConfig { service_name: "GunService".to_string() }).unwrap()
+    }
+
+    fn tun_request(path: &str) -> Request<()> {
+        Request::post(format!("http://example.com{}", path))
+            .header("content-type", "application/grpc")
+            .header("te", "trailers")
+            .body(())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_hunk_round_trip() {
+        for len in [0, 1, 127, 128, 300, 70000] {
+            let data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let mut buf = BytesMut::from(&encode_message(&data)[..]);
+            let message = next_message(&mut buf).unwrap().unwrap();
+            assert!(buf.is_empty());
+            assert_eq!(decode_hunk(message).unwrap(), data);
+        }
+
+        // 未知字段被跳过
+        let message = Bytes::from_static(&[0x10, 0x96, 0x01, 0x0a, 0x02, b'h', b'i', 0x1a, 0x01, 0x00]);
+        assert_eq!(decode_hunk(message).unwrap(), Bytes::from_static(b"hi"));
+        assert!(decode_hunk(Bytes::from_static(&[0x0a, 0x05, b'h'])).is_err());
+
+        // 压缩的消息与超长的消息被拒绝
+        assert!(next_message(&mut BytesMut::from(&[1u8, 0, 0, 0, 0][..])).is_err());
+        assert!(next_message(&mut BytesMut::from(&[0u8, 0xff, 0xff, 0xff, 0xff][..])).is_err());
+        assert!(next_message(&mut BytesMut::from(&[0u8, 0, 0, 0, 2, 0x0a][..])).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_tun_round_trip() {
+        let mut client = start(&server()).await;
+
+        let (response, mut upload) = client.send_request(tun_request("/GunService/Tun"), false).unwrap();
+        // 一条消息拆成两个 DATA 帧发送
+        let message = encode_message(b"hello");
+        upload.send_data(message.slice(..3), false).unwrap();
+        upload.send_data(message.slice(3..), false).unwrap();
+
+        let response = response.await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "application/grpc");
+        let mut body = response.into_body();
+        let mut received = BytesMut::new();
+        while received.len() < message.len() {
+            received.extend_from_slice(&body.data().await.unwrap().unwrap());
+        }
+        let reply = next_message(&mut received).unwrap().unwrap();
+        assert_eq!(decode_hunk(reply).unwrap(), Bytes::from_static(b"hello"));
+
+        // 客户端结束发送后会话结束，以 grpc-status 0 收尾
+        upload.send_data(Bytes::new(), true).unwrap();
+        while let Some(chunk) = body.data().await {
+            assert!(chunk.unwrap().is_empty());
+        }
+        let trailers = body.trailers().await.unwrap().unwrap();
+        assert_eq!(trailers["grpc-status"], "0");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_unknown_method_and_bad_messages() {
+        let mut client = start(&server()).await;
+
+        let (response, _) = client.send_request(tun_request("/Other/Tun"), true).unwrap();
+        let response = response.await.unwrap();
+        assert_eq!(response.headers()["grpc-status"], "12");
+
+        let request = Request::get("http://example.com/GunService/Tun").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        assert_eq!(response.await.unwrap().status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        // 压缩的消息以 grpc-status 12 结束调用
+        let (response, mut upload) = client.send_request(tun_request("/GunService/Tun"), false).unwrap();
+        upload.send_data(Bytes::from_static(&[1, 0, 0, 0, 1, 0]), false).unwrap();
+        let mut body = response.await.unwrap().into_body();
+        while let Some(chunk) = body.data().await {
+            chunk.unwrap();
+        }
+        let trailers = body.trailers().await.unwrap().unwrap();
+        assert_eq!(trailers["grpc-status"], "12");
+    }
+}
@@ -1,5 +1,48 @@
+pub mod grpc;
 pub mod reality;
 pub mod xhttp;
 
+use anyhow::Result;
+use std::net::IpAddr;
+
+pub use grpc::GrpcServer;
 pub use reality::RealityServer;
 pub use xhttp::XhttpServer;
+
+/// 建立在 HTTP 之上的传输层，Reality 握手之后 (如果启用) 由它处理连接
+#[derive(Clone)]
+pub enum HttpTransport {
+    Xhttp(Box<XhttpServer>),
+    Grpc(GrpcServer),
+}
+
+impl HttpTransport {
+    /// 处理来自 `peer` 的连接，每个内层流交给 `handler`
+    pub async fn accept<T, F, Fut>(&self, stream: T, peer: IpAddr, handler: F) -> Result<()>
+    where
+        T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+        F: Fn(Box<dyn crate::server::AsyncStream>) -> Fut + Clone + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        match self {
+            Self::Xhttp(server) => server.accept(stream, peer, handler).await,
+            Self::Grpc(server) => server.accept(stream, handler).await,
+        }
+    }
+
+    /// 开始优雅关闭: 向所有连接发送 GOAWAY
+    pub fn shutdown(&self) {
+        match self {
+            Self::Xhttp(server) => server.shutdown(),
+            Self::Grpc(server) => server.shutdown(),
+        }
+    }
+
+    /// 终止所有仍在进行的请求
+    pub fn terminate(&self) {
+        match self {
+            Self::Xhttp(server) => server.terminate(),
+            Self::Grpc(server) => server.terminate(),
+        }
+    }
+}
@@ -0,0 +1,331 @@
+//! 模仿 dest 真实证书的 Reality 临时证书
+//!
+//! 从 dest 的叶子证书中提取主题、SAN 与有效期作为模板，临时证书复制这些字段，
+//! 并由一次性 CA 签发 (CA 的主题复制 dest 证书的签发者)。
+//! 临时证书的密钥仍为 Ed25519: Reality 客户端按 HMAC-SHA512(AuthKey, Ed25519 公钥)
+//! 校验签名字段，无法沿用 dest 的密钥类型。
+
+use anyhow::{anyhow, bail, Result};
+use rcgen::{Certificate, CertificateParams, DistinguishedName, DnType, DnValue, IsCa, KeyPair, SanType, PKCS_ED25519};
+use rustls_pki_types::CertificateDer;
+use std::net::IpAddr;
+use std::time::Duration;
+
+const TAG_BOOLEAN: u8 = 0x01;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_OID: u8 = 0x06;
+const TAG_UTF8_STRING: u8 = 0x0c;
+const TAG_PRINTABLE_STRING: u8 = 0x13;
+const TAG_TELETEX_STRING: u8 = 0x14;
+const TAG_IA5_STRING: u8 = 0x16;
+const TAG_UTC_TIME: u8 = 0x17;
+const TAG_GENERALIZED_TIME: u8 = 0x18;
+const TAG_UNIVERSAL_STRING: u8 = 0x1c;
+const TAG_BMP_STRING: u8 = 0x1e;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_SET: u8 = 0x31;
+const TAG_VERSION: u8 = 0xa0;
+const TAG_EXTENSIONS: u8 = 0xa3;
+
+/// subjectAltName 扩展 (2.5.29.17)
+const OID_SUBJECT_ALT_NAME: &[u64] = &[2, 5, 29, 17];
+
+/// 从 dest 证书中复制的字段
+#[derive(Debug, Clone, PartialEq)]
+pub struct CertTemplate {
+    pub subject: Vec<(Vec<u64>, DnValue)>,
+    pub issuer: Vec<(Vec<u64>, DnValue)>,
+    pub subject_alt_names: Vec<SanType>,
+    /// 有效期起止 (Unix 秒)
+    pub not_before: u64,
+    pub not_after: u64,
+}
+
+impl CertTemplate {
+    /// 解析 DER 编码的 X.509 证书
+    pub fn from_der(der: &[u8]) -> Result<Self> {
+        let (certificate, _) = expect(der, TAG_SEQUENCE)?;
+        let (mut tbs, _) = expect(certificate, TAG_SEQUENCE)?;
+
+        if tbs.first() == Some(&TAG_VERSION) {
+            tbs = read_tlv(tbs)?.2;
+        }
+        let (_, _serial, tbs) = read_tlv(tbs)?;
+        let (_signature, tbs) = expect(tbs, TAG_SEQUENCE)?;
+        let (issuer, tbs) = expect(tbs, TAG_SEQUENCE)?;
+        let (validity, tbs) = expect(tbs, TAG_SEQUENCE)?;
+        let (subject, tbs) = expect(tbs, TAG_SEQUENCE)?;
+        let (_public_key, mut tbs) = expect(tbs, TAG_SEQUENCE)?;
+
+        let (not_before, validity) = parse_time(validity)?;
+        let (not_after, _) = parse_time(validity)?;
+
+        let mut subject_alt_names = Vec::new();
+        while !tbs.is_empty() {
+            let (tag, value, rest) = read_tlv(tbs)?;
+            if tag == TAG_EXTENSIONS {
+                subject_alt_names = parse_subject_alt_names(value)?;
+            }
+            tbs = rest;
+        }
+
+        Ok(Self {
+            subject: parse_name(subject)?,
+            issuer: parse_name(issuer)?,
+            subject_alt_names,
+            not_before,
+            not_after,
+        })
+    }
+
+    /// 复制了模板字段的叶子证书参数 (未设置密钥)
+    pub fn leaf_params(&self) -> CertificateParams {
+        let mut params = CertificateParams::default();
+        params.distinguished_name = distinguished_name(&self.subject);
+        params.subject_alt_names = self.subject_alt_names.clone();
+        let epoch = rcgen::date_time_ymd(1970, 1, 1);
+        params.not_before = epoch + Duration::from_secs(self.not_before);
+        params.not_after = epoch + Duration::from_secs(self.not_after);
+        params
+    }
+
+    /// 签发临时证书的一次性 CA，主题复制 dest 证书的签发者
+    pub fn issuer(&self) -> Result<MimicIssuer> {
+        let mut params = CertificateParams::default();
+        params.distinguished_name = distinguished_name(&self.issuer);
+        params.is_ca = IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        params.alg = &PKCS_ED25519;
+        params.key_pair = Some(KeyPair::generate(&PKCS_ED25519).map_err(|e| anyhow!("CA 密钥生成失败: {}", e))?);
+        let ca = Certificate::from_params(params).map_err(|e| anyhow!("CA 证书生成失败: {}", e))?;
+        let ca_der = CertificateDer::from(ca.serialize_der().map_err(|e| anyhow!("CA 证书序列化失败: {}", e))?);
+        Ok(MimicIssuer { template: self.clone(), ca, ca_der })
+    }
+}
+
+/// 按模板签发临时证书的一次性 CA
+pub struct MimicIssuer {
+    template: CertTemplate,
+    ca: Certificate,
+    ca_der: CertificateDer<'static>,
+}
+
+impl MimicIssuer {
+    pub fn template(&self) -> &CertTemplate {
+        &self.template
+    }
+
+    /// 用 CA 签发 `leaf`，返回叶子证书的 DER
+    pub fn sign(&self, leaf: &Certificate) -> Result<Vec<u8>> {
+        leaf.serialize_der_with_signer(&self.ca)
+            .map_err(|e| anyhow!("临时证书签发失败: {}", e))
+    }
+
+    /// 证书链中跟在叶子证书之后的 CA 证书
+    pub fn ca_der(&self) -> &CertificateDer<'static> {
+        &self.ca_der
+    }
+}
+
+fn distinguished_name(entries: &[(Vec<u64>, DnValue)]) -> DistinguishedName {
+    let mut name = DistinguishedName::new();
+    for (oid, value) in entries {
+        name.push(DnType::from_oid(oid), value.clone());
+    }
+    name
+}
+
+/// 读取一个 DER 元素，返回 (tag, 内容, 剩余数据)
+fn read_tlv(data: &[u8]) -> Result<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = data.split_first().ok_or_else(|| anyhow!("DER 数据为空"))?;
+    let (&first, rest) = rest.split_first().ok_or_else(|| anyhow!("DER 缺少长度"))?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let n = (first & 0x7f) as usize;
+        if n == 0 || n > 3 || rest.len() < n {
+            bail!("DER 长度无效");
+        }
+        let len = rest[..n].iter().fold(0usize, |len, &b| (len << 8) | b as usize);
+        (len, &rest[n..])
+    };
+    if rest.len() < len {
+        bail!("DER 数据不完整");
+    }
+    Ok((tag, &rest[..len], &rest[len..]))
+}
+
+/// 读取指定 tag 的元素，返回 (内容, 剩余数据)
+fn expect(data: &[u8], expected: u8) -> Result<(&[u8], &[u8])> {
+    let (tag, value, rest) = read_tlv(data)?;
+    if tag != expected {
+        bail!("DER tag 不符: 期望 {:#04x}，实际 {:#04x}", expected, tag);
+    }
+    Ok((value, rest))
+}
+
+fn parse_oid(data: &[u8]) -> Result<Vec<u64>> {
+    let (&first, rest) = data.split_first().ok_or_else(|| anyhow!("OID 为空"))?;
+    let mut oid = vec![(first / 40) as u64, (first % 40) as u64];
+    let mut arc = 0u64;
+    for &b in rest {
+        arc = (arc << 7) | (b & 0x7f) as u64;
+        if b & 0x80 == 0 {
+            oid.push(arc);
+            arc = 0;
+        }
+    }
+    Ok(oid)
+}
+
+/// Name: SEQUENCE OF SET OF SEQUENCE { OID, 值 }
+fn parse_name(mut data: &[u8]) -> Result<Vec<(Vec<u64>, DnValue)>> {
+    let mut entries = Vec::new();
+    while !data.is_empty() {
+        let (mut set, rest) = expect(data, TAG_SET)?;
+        while !set.is_empty() {
+            let (attribute, set_rest) = expect(set, TAG_SEQUENCE)?;
+            let (oid, attribute) = expect(attribute, TAG_OID)?;
+            let (tag, value, _) = read_tlv(attribute)?;
+            let text = || String::from_utf8_lossy(value).into_owned();
+            let value = match tag {
+                TAG_UTF8_STRING => DnValue::Utf8String(text()),
+                TAG_PRINTABLE_STRING => DnValue::PrintableString(text()),
+                TAG_IA5_STRING => DnValue::Ia5String(text()),
+                TAG_TELETEX_STRING => DnValue::TeletexString(value.to_vec()),
+                TAG_BMP_STRING => DnValue::BmpString(value.to_vec()),
+                TAG_UNIVERSAL_STRING => DnValue::UniversalString(value.to_vec()),
+                other => bail!("不支持的名称编码: {:#04x}", other),
+            };
+            entries.push((parse_oid(oid)?, value));
+            set = set_rest;
+        }
+        data = rest;
+    }
+    Ok(entries)
+}
+
+/// 读取 UTCTime / GeneralizedTime，返回 (Unix 秒, 剩余数据)
+fn parse_time(data: &[u8]) -> Result<(u64, &[u8])> {
+    let (tag, value, rest) = read_tlv(data)?;
+    let text = std::str::from_utf8(value)?;
+    let digits = text.strip_suffix('Z').ok_or_else(|| anyhow!("时间不是 UTC: {}", text))?;
+    let (year, digits) = match tag {
+        TAG_UTC_TIME if digits.len() == 12 => {
+            let yy: i64 = digits[..2].parse()?;
+            (if yy >= 50 { 1900 + yy } else { 2000 + yy }, &digits[2..])
+        }
+        TAG_GENERALIZED_TIME if digits.len() == 14 => (digits[..4].parse()?, &digits[4..]),
+        _ => bail!("无效的证书时间: {}", text),
+    };
+    let field = |i: usize| digits[i * 2..i * 2 + 2].parse::<i64>();
+    let (month, day, hour, minute, second) = (field(0)?, field(1)?, field(2)?, field(3)?, field(4)?);
+    let secs = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+    Ok((u64::try_from(secs).map_err(|_| anyhow!("证书时间早于 1970 年: {}", text))?, rest))
+}
+
+/// 公历日期距 1970-01-01 的天数
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// 扩展列表中的 subjectAltName
+fn parse_subject_alt_names(data: &[u8]) -> Result<Vec<SanType>> {
+    let (mut extensions, _) = expect(data, TAG_SEQUENCE)?;
+    while !extensions.is_empty() {
+        let (extension, rest) = expect(extensions, TAG_SEQUENCE)?;
+        extensions = rest;
+        let (oid, mut extension) = expect(extension, TAG_OID)?;
+        if parse_oid(oid)? != OID_SUBJECT_ALT_NAME {
+            continue;
+        }
+        if extension.first() == Some(&TAG_BOOLEAN) {
+            extension = read_tlv(extension)?.2;
+        }
+        let (value, _) = expect(extension, TAG_OCTET_STRING)?;
+        let (mut names, _) = expect(value, TAG_SEQUENCE)?;
+        let mut sans = Vec::new();
+        while !names.is_empty() {
+            let (tag, value, rest) = read_tlv(names)?;
+            let text = || String::from_utf8_lossy(value).into_owned();
+            match tag {
+                0x81 => sans.push(SanType::Rfc822Name(text())),
+                0x82 => sans.push(SanType::DnsName(text())),
+                0x86 => sans.push(SanType::URI(text())),
+                0x87 => match value.len() {
+                    4 => sans.push(SanType::IpAddress(IpAddr::from(<[u8; 4]>::try_from(value)?))),
+                    16 => sans.push(SanType::IpAddress(IpAddr::from(<[u8; 16]>::try_from(value)?))),
+                    _ => {}
+                },
+                _ => {}
+            }
+            names = rest;
+        }
+        return Ok(sans);
+    }
+    Ok(Vec::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 模拟 dest 的证书
+    fn dest_certificate() -> Vec<u8> {
+        let mut params = CertificateParams::new(vec!["www.example.com".to_string(), "example.com".to_string()]);
+        params.subject_alt_names.push(SanType::IpAddress("93.184.216.34".parse().unwrap()));
+        params.distinguished_name = DistinguishedName::new();
+        params.distinguished_name.push(DnType::CountryName, DnValue::PrintableString("US".to_string()));
+        params.distinguished_name.push(DnType::OrganizationName, "Example Inc.");
+        params.distinguished_name.push(DnType::CommonName, "www.example.com");
+        params.not_before = rcgen::date_time_ymd(2024, 1, 30);
+        params.not_after = rcgen::date_time_ymd(2025, 3, 1) + Duration::from_secs(12 * 3600 + 59 * 60 + 59);
+        Certificate::from_params(params).unwrap().serialize_der().unwrap()
+    }
+
+    #[test]
+    fn test_parse_template() {
+        let template = CertTemplate::from_der(&dest_certificate()).unwrap();
+        assert_eq!(
+            template.subject,
+            vec![
+                (vec![2, 5, 4, 6], DnValue::PrintableString("US".to_string())),
+                (vec![2, 5, 4, 10], DnValue::Utf8String("Example Inc.".to_string())),
+                (vec![2, 5, 4, 3], DnValue::Utf8String("www.example.com".to_string())),
+            ]
+        );
+        assert_eq!(
+            template.subject_alt_names,
+            vec![
+                SanType::DnsName("www.example.com".to_string()),
+                SanType::DnsName("example.com".to_string()),
+                SanType::IpAddress("93.184.216.34".parse().unwrap()),
+            ]
+        );
+        assert_eq!(template.not_before, 1706572800);
+        assert_eq!(template.not_after, 1740833999);
+        assert!(CertTemplate::from_der(b"not a certificate").is_err());
+    }
+
+    #[test]
+    fn test_mimic_certificate() {
+        let template = CertTemplate::from_der(&dest_certificate()).unwrap();
+        let issuer = template.issuer().unwrap();
+
+        let mut params = template.leaf_params();
+        params.alg = &PKCS_ED25519;
+        params.key_pair = Some(KeyPair::generate(&PKCS_ED25519).unwrap());
+        let leaf = issuer.sign(&Certificate::from_params(params).unwrap()).unwrap();
+
+        // 生成的证书复制了 dest 证书的字段，签发者为 CA 的主题
+        let mimic = CertTemplate::from_der(&leaf).unwrap();
+        assert_eq!(mimic.subject, template.subject);
+        assert_eq!(mimic.subject_alt_names, template.subject_alt_names);
+        assert_eq!((mimic.not_before, mimic.not_after), (template.not_before, template.not_after));
+        assert_eq!(mimic.issuer, CertTemplate::from_der(issuer.ca_der()).unwrap().subject);
+    }
+}
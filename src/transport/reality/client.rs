@@ -0,0 +1,530 @@
+//! Reality 客户端 (出站使用)
+//!
+//! 实现最小的 TLS 1.3 客户端 (X25519 + TLS_AES_128_GCM_SHA256)：
+//! 在 ClientHello 的 SessionID 中写入加密的 shortId，
+//! 通过证书签名 HMAC-SHA512(AuthKey, 公钥) 与 CertificateVerify 认证服务端。
+
+use aes_gcm::{AeadInPlace, Aes256Gcm, KeyInit, Nonce};
+use anyhow::{anyhow, bail, Result};
+use hkdf::Hkdf;
+use ring::{digest, hmac, signature};
+use sha2::Sha256;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use super::crypto::{CipherSuite, TlsKeys};
+
+const CONTENT_CHANGE_CIPHER_SPEC: u8 = 0x14;
+const CONTENT_ALERT: u8 = 0x15;
+const CONTENT_HANDSHAKE: u8 = 0x16;
+const CONTENT_APPLICATION_DATA: u8 = 0x17;
+
+const HANDSHAKE_SERVER_HELLO: u8 = 0x02;
+const HANDSHAKE_CERTIFICATE: u8 = 0x0b;
+const HANDSHAKE_CERTIFICATE_VERIFY: u8 = 0x0f;
+const HANDSHAKE_FINISHED: u8 = 0x14;
+
+/// 客户端只提供 TLS_AES_128_GCM_SHA256
+const SUITE: CipherSuite = CipherSuite::Aes128GcmSha256;
+
+/// ClientHello 握手消息中 SessionID 的偏移: type(1) + len(3) + version(2) + random(32) + sid_len(1)
+pub(super) const SESSION_ID_OFFSET: usize = 39;
+
+/// TLS 记录明文上限
+const MAX_FRAGMENT: usize = 16384;
+
+/// Ed25519 SubjectPublicKeyInfo 前缀 (其后紧跟 32 字节公钥)
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+/// Reality 客户端参数
+#[derive(Debug, Clone)]
+pub struct RealityClientConfig {
+    /// SNI
+    pub server_name: String,
+    /// 服务端 X25519 公钥
+    pub public_key: [u8; 32],
+    /// shortId (最多 8 字节)
+    pub short_id: Vec<u8>,
+}
+
+/// 完成 Reality 握手后的加密流
+pub struct RealityClientStream<S> {
+    inner: S,
+    keys: TlsKeys,
+    read_seq: u64,
+    write_seq: u64,
+    /// 尚未组成完整记录的密文
+    incoming: Vec<u8>,
+    /// 已解密、尚未交给调用方的明文
+    plaintext: Vec<u8>,
+    plaintext_pos: usize,
+    /// 已加密、尚未写出的记录
+    outgoing: Vec<u8>,
+    outgoing_pos: usize,
+    read_closed: bool,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> RealityClientStream<S> {
+    /// 在已建立的连接上执行 Reality 握手
+    pub async fn connect(mut inner: S, config: &RealityClientConfig) -> Result<Self> {
+        if config.short_id.len() > 8 {
+            bail!("Reality shortId 最多 8 字节");
+        }
+
+        let secret = StaticSecret::random_from_rng(rand::thread_rng());
+        let public = PublicKey::from(&secret);
+        let mut random = [0u8; 32];
+        rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut random);
+
+        // 1. ClientHello，SessionID 中写入 Reality 认证信息
+        let mut hello = build_client_hello(&random, public.as_bytes(), &config.server_name);
+        let shared = secret.diffie_hellman(&PublicKey::from(config.public_key));
+        let auth_key = derive_auth_key(shared.as_bytes(), &random);
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs() as u32;
+        let session_id = seal_session_id(&auth_key, &random, &hello, now, &config.short_id)?;
+        hello[SESSION_ID_OFFSET..SESSION_ID_OFFSET + 32].copy_from_slice(&session_id);
+
+        let mut record = vec![CONTENT_HANDSHAKE, 0x03, 0x01];
+        record.extend_from_slice(&(hello.len() as u16).to_be_bytes());
+        record.extend_from_slice(&hello);
+        inner.write_all(&record).await?;
+
+        let mut transcript = digest::Context::new(&digest::SHA256);
+        transcript.update(&hello);
+
+        // 2. ServerHello
+        let (content_type, _, server_hello) = read_record(&mut inner).await?;
+        if content_type != CONTENT_HANDSHAKE || server_hello.first() != Some(&HANDSHAKE_SERVER_HELLO) {
+            bail!("Reality 服务端未返回 ServerHello (content type {})", content_type);
+        }
+        let server_share = parse_server_hello(&server_hello)?;
+        transcript.update(&server_hello);
+
+        let ecdhe = secret.diffie_hellman(&PublicKey::from(server_share));
+        let hello_hash = transcript.clone().finish();
+        let (handshake_keys, handshake_secret) =
+            TlsKeys::derive_handshake_keys(SUITE, ecdhe.as_bytes(), hello_hash.as_ref())?;
+
+        // 3. EncryptedExtensions / Certificate / CertificateVerify / Finished
+        let mut seq = 0;
+        let mut pending = Vec::new();
+        let mut cert_key: Option<[u8; 32]> = None;
+        loop {
+            let (content_type, header, mut body) = read_record(&mut inner).await?;
+            match content_type {
+                CONTENT_CHANGE_CIPHER_SPEC => continue,
+                CONTENT_APPLICATION_DATA => {}
+                CONTENT_ALERT => bail!("Reality 握手被服务端中止"),
+                other => bail!("Reality 握手期间收到意外的 content type {}", other),
+            }
+            let (inner_type, len) = handshake_keys.decrypt_server_record(seq, &header, &mut body)?;
+            seq += 1;
+            if inner_type != CONTENT_HANDSHAKE {
+                bail!("Reality 握手期间收到意外的内层 content type {}", inner_type);
+            }
+            pending.extend_from_slice(&body[..len]);
+
+            let mut finished = false;
+            while pending.len() >= 4 {
+                let msg_len = u32::from_be_bytes([0, pending[1], pending[2], pending[3]]) as usize;
+                if pending.len() < 4 + msg_len {
+                    break;
+                }
+                let message: Vec<u8> = pending.drain(..4 + msg_len).collect();
+                match message[0] {
+                    HANDSHAKE_CERTIFICATE => {
+                        cert_key = Some(verify_certificate(&message, &auth_key)?);
+                    }
+                    HANDSHAKE_CERTIFICATE_VERIFY => {
+                        let key = cert_key.ok_or_else(|| anyhow!("Reality 服务端未发送证书"))?;
+                        verify_certificate_signature(&message, &key, transcript.clone().finish().as_ref())?;
+                    }
+                    HANDSHAKE_FINISHED => {
+                        if cert_key.is_none() {
+                            bail!("Reality 服务端未发送证书");
+                        }
+                        let expected = TlsKeys::calculate_verify_data(
+                            SUITE,
+                            &handshake_keys.server_traffic_secret,
+                            transcript.clone().finish().as_ref(),
+                        )?;
+                        if message[4..] != expected[..] {
+                            bail!("Reality 服务端 Finished 校验失败");
+                        }
+                        finished = true;
+                    }
+                    _ => {}
+                }
+                transcript.update(&message);
+                if finished {
+                    break;
+                }
+            }
+            if finished {
+                break;
+            }
+        }
+
+        // 4. 客户端 Finished，切换到应用数据密钥
+        let handshake_hash = transcript.finish();
+        let verify_data = TlsKeys::calculate_verify_data(
+            SUITE,
+            &handshake_keys.client_traffic_secret,
+            handshake_hash.as_ref(),
+        )?;
+        let mut finished = vec![HANDSHAKE_FINISHED, 0, 0, verify_data.len() as u8];
+        finished.extend_from_slice(&verify_data);
+        inner
+            .write_all(&handshake_keys.encrypt_client_record(0, &finished, CONTENT_HANDSHAKE)?)
+            .await?;
+        inner.flush().await?;
+
+        let keys = TlsKeys::derive_application_keys(SUITE, &handshake_secret, handshake_hash.as_ref())?;
+
+        Ok(Self {
+            inner,
+            keys,
+            read_seq: 0,
+            write_seq: 0,
+            incoming: Vec::new(),
+            plaintext: Vec::new(),
+            plaintext_pos: 0,
+            outgoing: Vec::new(),
+            outgoing_pos: 0,
+            read_closed: false,
+        })
+    }
+
+    /// 底层连接，vision 切换为直接复制后绕过 TLS 直接写入
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// 拆出底层连接与已读取、尚未解密的数据
+    pub fn into_inner(self) -> (S, Vec<u8>) {
+        (self.inner, self.incoming)
+    }
+
+    /// 尽量写出已加密的记录
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.outgoing_pos < self.outgoing.len() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.outgoing[self.outgoing_pos..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.outgoing_pos += n;
+        }
+        self.outgoing.clear();
+        self.outgoing_pos = 0;
+        Poll::Ready(Ok(()))
+    }
+
+    /// 从缓冲区中解出一个完整记录，返回是否消费了记录 (不一定产生明文)
+    fn decode_record(&mut self) -> io::Result<bool> {
+        if self.incoming.len() < 5 {
+            return Ok(false);
+        }
+        let len = u16::from_be_bytes([self.incoming[3], self.incoming[4]]) as usize;
+        if self.incoming.len() < 5 + len {
+            return Ok(false);
+        }
+
+        let mut header = [0u8; 5];
+        header.copy_from_slice(&self.incoming[..5]);
+        let mut body: Vec<u8> = self.incoming.drain(..5 + len).skip(5).collect();
+
+        match header[0] {
+            CONTENT_APPLICATION_DATA => {}
+            CONTENT_CHANGE_CIPHER_SPEC => return Ok(true),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "意外的 TLS 记录类型")),
+        }
+
+        let (inner_type, len) = self
+            .keys
+            .decrypt_server_record(self.read_seq, &header, &mut body)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        self.read_seq += 1;
+
+        match inner_type {
+            CONTENT_APPLICATION_DATA => {
+                body.truncate(len);
+                self.plaintext = body;
+                self.plaintext_pos = 0;
+                Ok(true)
+            }
+            // NewSessionTicket 等握手后消息直接忽略
+            CONTENT_HANDSHAKE => Ok(true),
+            CONTENT_ALERT => {
+                self.read_closed = true;
+                Ok(true)
+            }
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "意外的 TLS 内层记录类型")),
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for RealityClientStream<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            if this.plaintext_pos < this.plaintext.len() {
+                let n = buf.remaining().min(this.plaintext.len() - this.plaintext_pos);
+                buf.put_slice(&this.plaintext[this.plaintext_pos..this.plaintext_pos + n]);
+                this.plaintext_pos += n;
+                return Poll::Ready(Ok(()));
+            }
+            if this.read_closed {
+                return Poll::Ready(Ok(()));
+            }
+            if this.decode_record()? {
+                continue;
+            }
+
+            let mut chunk = [0u8; 8192];
+            let mut chunk_buf = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk_buf))?;
+            if chunk_buf.filled().is_empty() {
+                this.read_closed = true;
+                continue;
+            }
+            this.incoming.extend_from_slice(chunk_buf.filled());
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for RealityClientStream<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        ready!(this.poll_drain(cx))?;
+
+        let n = buf.len().min(MAX_FRAGMENT);
+        let record = this
+            .keys
+            .encrypt_client_record(this.write_seq, &buf[..n], CONTENT_APPLICATION_DATA)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        this.write_seq += 1;
+        this.outgoing = record;
+
+        // 记录已加密即视为写入成功，剩余部分在下次写入或 flush 时写出
+        let _ = this.poll_drain(cx)?;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+fn push_extension(out: &mut Vec<u8>, ext_type: u16, data: &[u8]) {
+    out.extend_from_slice(&ext_type.to_be_bytes());
+    out.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    out.extend_from_slice(data);
+}
+
+/// 构造 ClientHello 握手消息 (SessionID 先填 0，整个消息作为 AEAD 附加数据)
+pub(super) fn build_client_hello(random: &[u8; 32], public_key: &[u8; 32], server_name: &str) -> Vec<u8> {
+    build_client_hello_with(random, server_name, &[0x001d], &[(0x001d, public_key)])
+}
+
+/// 同 [`build_client_hello`]，supported_groups 与密钥份额由调用方指定
+pub(super) fn build_client_hello_with(
+    random: &[u8; 32],
+    server_name: &str,
+    supported_groups: &[u16],
+    key_shares: &[(u16, &[u8])],
+) -> Vec<u8> {
+    let mut extensions = Vec::new();
+
+    let name = server_name.as_bytes();
+    let mut sni = Vec::new();
+    sni.extend_from_slice(&(name.len() as u16 + 3).to_be_bytes());
+    sni.push(0x00);
+    sni.extend_from_slice(&(name.len() as u16).to_be_bytes());
+    sni.extend_from_slice(name);
+    push_extension(&mut extensions, 0x0000, &sni);
+
+    let mut groups = (supported_groups.len() as u16 * 2).to_be_bytes().to_vec();
+    for group in supported_groups {
+        groups.extend_from_slice(&group.to_be_bytes());
+    }
+    push_extension(&mut extensions, 0x000a, &groups);
+    // signature_algorithms: ed25519, ecdsa_secp256r1_sha256, rsa_pss_rsae_sha256
+    push_extension(&mut extensions, 0x000d, &[0x00, 0x06, 0x08, 0x07, 0x04, 0x03, 0x08, 0x04]);
+    // supported_versions: TLS 1.3
+    push_extension(&mut extensions, 0x002b, &[0x02, 0x03, 0x04]);
+    // psk_key_exchange_modes: psk_dhe_ke
+    push_extension(&mut extensions, 0x002d, &[0x01, 0x01]);
+
+    let mut shares = Vec::new();
+    for (group, key) in key_shares {
+        shares.extend_from_slice(&group.to_be_bytes());
+        shares.extend_from_slice(&(key.len() as u16).to_be_bytes());
+        shares.extend_from_slice(key);
+    }
+    let mut key_share = (shares.len() as u16).to_be_bytes().to_vec();
+    key_share.extend_from_slice(&shares);
+    push_extension(&mut extensions, 0x0033, &key_share);
+
+    let mut body = Vec::new();
+    body.extend_from_slice(&[0x03, 0x03]);
+    body.extend_from_slice(random);
+    body.push(32);
+    body.extend_from_slice(&[0u8; 32]);
+    // cipher_suites: TLS_AES_128_GCM_SHA256
+    body.extend_from_slice(&[0x00, 0x02, 0x13, 0x01]);
+    body.extend_from_slice(&[0x01, 0x00]);
+    body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+    body.extend_from_slice(&extensions);
+
+    let mut message = vec![0x01];
+    message.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    message.extend_from_slice(&body);
+    message
+}
+
+/// AuthKey = HKDF-SHA256(salt = ClientHello.random[..20], ikm = ECDH, info = "REALITY")
+pub(super) fn derive_auth_key(shared: &[u8; 32], random: &[u8; 32]) -> [u8; 32] {
+    let hk = Hkdf::<Sha256>::new(Some(&random[..20]), shared);
+    let mut key = [0u8; 32];
+    hk.expand(b"REALITY", &mut key).expect("32 字节输出合法");
+    key
+}
+
+/// SessionID = AES-256-GCM(AuthKey, nonce = random[20..], aad = ClientHello, 版本 + 时间戳 + shortId)
+pub(super) fn seal_session_id(
+    auth_key: &[u8; 32],
+    random: &[u8; 32],
+    hello: &[u8],
+    timestamp: u32,
+    short_id: &[u8],
+) -> Result<[u8; 32]> {
+    let mut plaintext = Vec::with_capacity(32);
+    plaintext.extend_from_slice(&[1, 8, 0, 0]);
+    plaintext.extend_from_slice(&timestamp.to_be_bytes());
+    let mut sid = [0u8; 8];
+    sid[..short_id.len()].copy_from_slice(short_id);
+    plaintext.extend_from_slice(&sid);
+
+    let cipher = Aes256Gcm::new(aes_gcm::Key::<Aes256Gcm>::from_slice(auth_key));
+    cipher
+        .encrypt_in_place(Nonce::from_slice(&random[20..]), hello, &mut plaintext)
+        .map_err(|e| anyhow!("SessionID 加密失败: {}", e))?;
+
+    let mut session_id = [0u8; 32];
+    session_id.copy_from_slice(&plaintext);
+    Ok(session_id)
+}
+
+/// 解析 ServerHello，返回服务端 X25519 key share
+fn parse_server_hello(message: &[u8]) -> Result<[u8; 32]> {
+    let body = message.get(4..).ok_or_else(|| anyhow!("ServerHello 过短"))?;
+    let sid_len = *body.get(34).ok_or_else(|| anyhow!("ServerHello 过短"))? as usize;
+    let mut pos = 35 + sid_len;
+    let field = |pos: usize| -> Result<u16> {
+        Ok(u16::from_be_bytes([
+            *body.get(pos).ok_or_else(|| anyhow!("ServerHello 过短"))?,
+            *body.get(pos + 1).ok_or_else(|| anyhow!("ServerHello 过短"))?,
+        ]))
+    };
+
+    let cipher_suite = field(pos)?;
+    if cipher_suite != SUITE.id() {
+        bail!("Reality 服务端选择了不支持的密码套件 {:#06x}", cipher_suite);
+    }
+    pos += 3;
+    let ext_end = pos + 2 + field(pos)? as usize;
+    pos += 2;
+
+    while pos + 4 <= ext_end {
+        let ext_type = field(pos)?;
+        let ext_len = field(pos + 2)? as usize;
+        let data = body
+            .get(pos + 4..pos + 4 + ext_len)
+            .ok_or_else(|| anyhow!("ServerHello 扩展越界"))?;
+        if ext_type == 0x0033 && data.len() == 36 && data[..4] == [0x00, 0x1d, 0x00, 0x20] {
+            let mut key = [0u8; 32];
+            key.copy_from_slice(&data[4..]);
+            return Ok(key);
+        }
+        pos += 4 + ext_len;
+    }
+    bail!("ServerHello 缺少 X25519 key share")
+}
+
+/// 校验 Reality 临时证书: 签名字段为 HMAC-SHA512(AuthKey, Ed25519 公钥)，返回证书公钥
+pub(super) fn verify_certificate(message: &[u8], auth_key: &[u8; 32]) -> Result<[u8; 32]> {
+    // context_len(1) + context + list_len(3) + cert_len(3) + cert
+    let body = &message[4..];
+    let ctx_len = *body.first().ok_or_else(|| anyhow!("Certificate 消息过短"))? as usize;
+    let pos = 1 + ctx_len + 3;
+    let len_bytes = body.get(pos..pos + 3).ok_or_else(|| anyhow!("Certificate 消息过短"))?;
+    let cert_len = u32::from_be_bytes([0, len_bytes[0], len_bytes[1], len_bytes[2]]) as usize;
+    let cert = body
+        .get(pos + 3..pos + 3 + cert_len)
+        .ok_or_else(|| anyhow!("Certificate 消息过短"))?;
+
+    let key_pos = cert
+        .windows(ED25519_SPKI_PREFIX.len())
+        .position(|w| w == ED25519_SPKI_PREFIX)
+        .ok_or_else(|| anyhow!("Reality 证书不是 Ed25519 证书"))?
+        + ED25519_SPKI_PREFIX.len();
+    let mut public_key = [0u8; 32];
+    public_key.copy_from_slice(
+        cert.get(key_pos..key_pos + 32)
+            .ok_or_else(|| anyhow!("Reality 证书公钥不完整"))?,
+    );
+
+    if cert.len() < 64 {
+        bail!("Reality 证书过短");
+    }
+    let key = hmac::Key::new(hmac::HMAC_SHA512, auth_key);
+    if hmac::verify(&key, &public_key, &cert[cert.len() - 64..]).is_err() {
+        bail!("Reality 证书签名校验失败 (publicKey 或 shortId 不匹配?)");
+    }
+    Ok(public_key)
+}
+
+/// 校验 CertificateVerify (Ed25519 对握手摘要的签名)
+pub(super) fn verify_certificate_signature(message: &[u8], public_key: &[u8; 32], transcript_hash: &[u8]) -> Result<()> {
+    let body = &message[4..];
+    if body.len() < 4 || body[..2] != [0x08, 0x07] {
+        bail!("Reality 服务端使用了非 Ed25519 的 CertificateVerify");
+    }
+    let sig_len = u16::from_be_bytes([body[2], body[3]]) as usize;
+    let sig = body
+        .get(4..4 + sig_len)
+        .ok_or_else(|| anyhow!("CertificateVerify 消息过短"))?;
+
+    let mut content = vec![0x20u8; 64];
+    content.extend_from_slice(b"TLS 1.3, server CertificateVerify");
+    content.push(0x00);
+    content.extend_from_slice(transcript_hash);
+
+    signature::UnparsedPublicKey::new(&signature::ED25519, public_key)
+        .verify(&content, sig)
+        .map_err(|_| anyhow!("Reality CertificateVerify 校验失败"))
+}
+
+async fn read_record<S: AsyncRead + Unpin>(stream: &mut S) -> Result<(u8, [u8; 5], Vec<u8>)> {
+    let mut header = [0u8; 5];
+    stream.read_exact(&mut header).await?;
+    let len = u16::from_be_bytes([header[3], header[4]]) as usize;
+    let mut body = vec![0u8; len];
+    stream.read_exact(&mut body).await?;
+    Ok((header[0], header, body))
+}
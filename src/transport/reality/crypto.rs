@@ -3,13 +3,82 @@ use rand::rngs::OsRng;
 use ring::{aead, digest, hkdf, hmac};
 use x25519_dalek::{PublicKey, StaticSecret};
 
-/// 计算 Transcript Hash (SHA256)
-pub fn hash_transcript(messages: &[&[u8]]) -> Vec<u8> {
-    let mut ctx = digest::Context::new(&digest::SHA256);
-    for msg in messages {
-        ctx.update(msg);
+/// 支持的 TLS 1.3 密码套件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CipherSuite {
+    /// TLS_AES_128_GCM_SHA256
+    Aes128GcmSha256,
+    /// TLS_AES_256_GCM_SHA384
+    Aes256GcmSha384,
+    /// TLS_CHACHA20_POLY1305_SHA256
+    Chacha20Poly1305Sha256,
+}
+
+impl CipherSuite {
+    pub fn from_id(id: u16) -> Option<Self> {
+        match id {
+            0x1301 => Some(CipherSuite::Aes128GcmSha256),
+            0x1302 => Some(CipherSuite::Aes256GcmSha384),
+            0x1303 => Some(CipherSuite::Chacha20Poly1305Sha256),
+            _ => None,
+        }
+    }
+
+    pub fn id(self) -> u16 {
+        match self {
+            CipherSuite::Aes128GcmSha256 => 0x1301,
+            CipherSuite::Aes256GcmSha384 => 0x1302,
+            CipherSuite::Chacha20Poly1305Sha256 => 0x1303,
+        }
+    }
+
+    /// 按客户端的偏好顺序选择第一个支持的套件
+    pub fn select(offered: &[u16]) -> Option<Self> {
+        offered.iter().find_map(|&id| Self::from_id(id))
+    }
+
+    fn aead(self) -> &'static aead::Algorithm {
+        match self {
+            CipherSuite::Aes128GcmSha256 => &aead::AES_128_GCM,
+            CipherSuite::Aes256GcmSha384 => &aead::AES_256_GCM,
+            CipherSuite::Chacha20Poly1305Sha256 => &aead::CHACHA20_POLY1305,
+        }
+    }
+
+    fn hkdf(self) -> hkdf::Algorithm {
+        match self {
+            CipherSuite::Aes256GcmSha384 => hkdf::HKDF_SHA384,
+            _ => hkdf::HKDF_SHA256,
+        }
+    }
+
+    fn hmac(self) -> hmac::Algorithm {
+        match self {
+            CipherSuite::Aes256GcmSha384 => hmac::HMAC_SHA384,
+            _ => hmac::HMAC_SHA256,
+        }
+    }
+
+    fn digest(self) -> &'static digest::Algorithm {
+        match self {
+            CipherSuite::Aes256GcmSha384 => &digest::SHA384,
+            _ => &digest::SHA256,
+        }
+    }
+
+    /// 握手摘要与各级 secret 的长度
+    pub fn hash_len(self) -> usize {
+        self.digest().output_len()
+    }
+
+    /// 计算 Transcript Hash
+    pub fn hash_transcript(self, messages: &[&[u8]]) -> Vec<u8> {
+        let mut ctx = digest::Context::new(self.digest());
+        for msg in messages {
+            ctx.update(msg);
+        }
+        ctx.finish().as_ref().to_vec()
     }
-    ctx.finish().as_ref().to_vec()
 }
 
 /// Reality 加密助手
@@ -42,6 +111,7 @@ impl RealityCrypto {
 }
 
 pub struct TlsKeys {
+    pub suite: CipherSuite,
     pub client_write_key: aead::LessSafeKey,
     pub server_write_key: aead::LessSafeKey,
     pub client_iv: [u8; 12],
@@ -52,29 +122,34 @@ pub struct TlsKeys {
 
 impl TlsKeys {
     pub fn derive_handshake_keys(
+        suite: CipherSuite,
         shared_secret: &[u8],
         hello_hash: &[u8],
     ) -> Result<(Self, hkdf::Prk)> {
+        let hash_len = suite.hash_len();
+        let zeros = vec![0u8; hash_len];
+
         // RFC 8446 Section 7.1: Early Secret = HKDF-Extract(0, 0)
-        // Without PSK, salt is 32 zero bytes for SHA256
-        let salt = hkdf::Salt::new(hkdf::HKDF_SHA256, &[0u8; 32]);
-        let early_secret = salt.extract(&[0u8; 32]);
+        // Without PSK, salt and IKM are Hash.length zero bytes
+        let salt = hkdf::Salt::new(suite.hkdf(), &zeros);
+        let early_secret = salt.extract(&zeros);
 
-        // derived = HKDF-Expand-Label(Early Secret, "derived", "", 32)
-        let derived_secret = expand_label(&early_secret, b"derived", &hash_empty(), 32)?;
+        // derived = HKDF-Expand-Label(Early Secret, "derived", "", Hash.length)
+        let derived_secret = expand_label(&early_secret, b"derived", &suite.hash_transcript(&[]), hash_len)?;
 
         // Handshake Secret = HKDF-Extract(derived_secret, shared_secret)
         let handshake_secret =
-            hkdf::Salt::new(hkdf::HKDF_SHA256, &derived_secret).extract(shared_secret);
+            hkdf::Salt::new(suite.hkdf(), &derived_secret).extract(shared_secret);
 
-        let client_hs_secret = expand_label(&handshake_secret, b"c hs traffic", hello_hash, 32)?;
-        let server_hs_secret = expand_label(&handshake_secret, b"s hs traffic", hello_hash, 32)?;
+        let client_hs_secret = expand_label(&handshake_secret, b"c hs traffic", hello_hash, hash_len)?;
+        let server_hs_secret = expand_label(&handshake_secret, b"s hs traffic", hello_hash, hash_len)?;
 
-        let client_keys = derive_key_iv(&client_hs_secret)?;
-        let server_keys = derive_key_iv(&server_hs_secret)?;
+        let client_keys = derive_key_iv(suite, &client_hs_secret)?;
+        let server_keys = derive_key_iv(suite, &server_hs_secret)?;
 
         Ok((
             TlsKeys {
+                suite,
                 client_write_key: client_keys.0,
                 server_write_key: server_keys.0,
                 client_iv: client_keys.1,
@@ -87,19 +162,22 @@ impl TlsKeys {
     }
 
     pub fn derive_application_keys(
+        suite: CipherSuite,
         handshake_secret: &hkdf::Prk,
         handshake_hash: &[u8],
     ) -> Result<Self> {
-        let derived_secret = expand_label(handshake_secret, b"derived", &hash_empty(), 32)?;
-        let master_secret = hkdf::Salt::new(hkdf::HKDF_SHA256, &derived_secret).extract(&[0u8; 32]);
+        let hash_len = suite.hash_len();
+        let derived_secret = expand_label(handshake_secret, b"derived", &suite.hash_transcript(&[]), hash_len)?;
+        let master_secret = hkdf::Salt::new(suite.hkdf(), &derived_secret).extract(&vec![0u8; hash_len]);
 
-        let client_app_secret = expand_label(&master_secret, b"c ap traffic", handshake_hash, 32)?;
-        let server_app_secret = expand_label(&master_secret, b"s ap traffic", handshake_hash, 32)?;
+        let client_app_secret = expand_label(&master_secret, b"c ap traffic", handshake_hash, hash_len)?;
+        let server_app_secret = expand_label(&master_secret, b"s ap traffic", handshake_hash, hash_len)?;
 
-        let client_keys = derive_key_iv(&client_app_secret)?;
-        let server_keys = derive_key_iv(&server_app_secret)?;
+        let client_keys = derive_key_iv(suite, &client_app_secret)?;
+        let server_keys = derive_key_iv(suite, &server_app_secret)?;
 
         Ok(TlsKeys {
+            suite,
             client_write_key: client_keys.0,
             server_write_key: server_keys.0,
             client_iv: client_keys.1,
@@ -109,51 +187,53 @@ impl TlsKeys {
         })
     }
 
+    /// RFC 8446 §7.2: application_traffic_secret_N+1 = HKDF-Expand-Label(secret_N, "traffic upd", "", Hash.length)
+    pub fn next_application_secret(suite: CipherSuite, traffic_secret: &[u8]) -> Result<Vec<u8>> {
+        expand_label_raw(suite, traffic_secret, b"traffic upd", &[], suite.hash_len())
+    }
+
+    /// 收到对端 KeyUpdate 后切换到下一代客户端方向密钥
+    pub fn update_client_keys(&mut self) -> Result<()> {
+        let secret = Self::next_application_secret(self.suite, &self.client_traffic_secret)?;
+        (self.client_write_key, self.client_iv) = derive_key_iv(self.suite, &secret)?;
+        self.client_traffic_secret = secret;
+        Ok(())
+    }
+
+    /// 发出 KeyUpdate 后切换到下一代服务端方向密钥
+    pub fn update_server_keys(&mut self) -> Result<()> {
+        let secret = Self::next_application_secret(self.suite, &self.server_traffic_secret)?;
+        (self.server_write_key, self.server_iv) = derive_key_iv(self.suite, &secret)?;
+        self.server_traffic_secret = secret;
+        Ok(())
+    }
+
     pub fn encrypt_server_record(
         &self,
         seq: u64,
         plaintext: &[u8],
         content_type: u8,
     ) -> Result<Vec<u8>> {
-        let mut nonce_bytes = [0u8; 12];
-        let mut padded_seq = [0u8; 12];
-        padded_seq[4..].copy_from_slice(&seq.to_be_bytes());
-        for i in 0..12 {
-            nonce_bytes[i] = self.server_iv[i] ^ padded_seq[i];
-        }
-        let nonce = aead::Nonce::try_assume_unique_for_key(&nonce_bytes)
-            .map_err(|_| anyhow!("Nonce err"))?;
-
-        let mut buffer = plaintext.to_vec();
-        buffer.push(content_type);
-
-        let tag_len = self.server_write_key.algorithm().tag_len();
-        let encrypted_len = buffer.len() + tag_len;
-
-        let mut header = [23u8, 0x03, 0x03, 0, 0];
-        let len_bytes = (encrypted_len as u16).to_be_bytes();
-        header[3] = len_bytes[0];
-        header[4] = len_bytes[1];
-
-        let aad = aead::Aad::from(header);
-
-        self.server_write_key
-            .seal_in_place_append_tag(nonce, aad, &mut buffer)
-            .map_err(|_| anyhow!("Encrypt fail"))?;
-
-        let mut record = Vec::with_capacity(5 + buffer.len());
-        record.extend_from_slice(&header);
-        record.extend_from_slice(&buffer);
+        seal_record(&self.server_write_key, &self.server_iv, seq, plaintext, content_type)
+    }
 
-        Ok(record)
+    /// 客户端方向加密 (出站 Reality 客户端使用)
+    pub fn encrypt_client_record(
+        &self,
+        seq: u64,
+        plaintext: &[u8],
+        content_type: u8,
+    ) -> Result<Vec<u8>> {
+        seal_record(&self.client_write_key, &self.client_iv, seq, plaintext, content_type)
     }
 
     pub fn calculate_verify_data(
+        suite: CipherSuite,
         traffic_secret_bytes: &[u8],
         handshake_hash: &[u8],
     ) -> Result<Vec<u8>> {
-        let finished_key = expand_label_raw(traffic_secret_bytes, b"finished", &[], 32)?;
-        let key = hmac::Key::new(hmac::HMAC_SHA256, &finished_key);
+        let finished_key = expand_label_raw(suite, traffic_secret_bytes, b"finished", &[], suite.hash_len())?;
+        let key = hmac::Key::new(suite.hmac(), &finished_key);
         let tag = hmac::sign(&key, handshake_hash);
         Ok(tag.as_ref().to_vec())
     }
@@ -164,37 +244,91 @@ impl TlsKeys {
         header: &[u8; 5],
         ciphertext: &mut [u8],
     ) -> Result<(u8, usize)> {
-        let mut nonce_bytes = [0u8; 12];
-        let mut padded_seq = [0u8; 12];
-        padded_seq[4..].copy_from_slice(&seq.to_be_bytes());
-        for i in 0..12 {
-            nonce_bytes[i] = self.client_iv[i] ^ padded_seq[i];
-        }
-        let nonce = aead::Nonce::try_assume_unique_for_key(&nonce_bytes)
-            .map_err(|_| anyhow!("Nonce err"))?;
+        open_record(&self.client_write_key, &self.client_iv, seq, header, ciphertext)
+    }
+
+    /// 服务端方向解密 (出站 Reality 客户端使用)
+    pub fn decrypt_server_record(
+        &self,
+        seq: u64,
+        header: &[u8; 5],
+        ciphertext: &mut [u8],
+    ) -> Result<(u8, usize)> {
+        open_record(&self.server_write_key, &self.server_iv, seq, header, ciphertext)
+    }
+}
 
-        let aad = aead::Aad::from(&header[..]);
+fn record_nonce(iv: &[u8; 12], seq: u64) -> Result<aead::Nonce> {
+    let mut nonce_bytes = [0u8; 12];
+    let mut padded_seq = [0u8; 12];
+    padded_seq[4..].copy_from_slice(&seq.to_be_bytes());
+    for i in 0..12 {
+        nonce_bytes[i] = iv[i] ^ padded_seq[i];
+    }
+    aead::Nonce::try_assume_unique_for_key(&nonce_bytes).map_err(|_| anyhow!("Nonce err"))
+}
 
-        let plaintext_len = self
-            .client_write_key
-            .open_in_place(nonce, aad, ciphertext)
-            .map_err(|_| anyhow!("Decryption failed"))?
-            .len();
+fn seal_record(
+    key: &aead::LessSafeKey,
+    iv: &[u8; 12],
+    seq: u64,
+    plaintext: &[u8],
+    content_type: u8,
+) -> Result<Vec<u8>> {
+    let nonce = record_nonce(iv, seq)?;
 
-        if plaintext_len == 0 {
-            return Err(anyhow!("Empty plaintext"));
-        }
+    let mut buffer = plaintext.to_vec();
+    buffer.push(content_type);
 
-        let mut scan_idx = plaintext_len - 1;
-        while scan_idx > 0 && ciphertext[scan_idx] == 0 {
-            scan_idx -= 1;
-        }
+    let tag_len = key.algorithm().tag_len();
+    let encrypted_len = buffer.len() + tag_len;
 
-        let content_type = ciphertext[scan_idx];
-        let real_content_len = scan_idx;
+    let mut header = [23u8, 0x03, 0x03, 0, 0];
+    let len_bytes = (encrypted_len as u16).to_be_bytes();
+    header[3] = len_bytes[0];
+    header[4] = len_bytes[1];
 
-        Ok((content_type, real_content_len))
+    let aad = aead::Aad::from(header);
+
+    key.seal_in_place_append_tag(nonce, aad, &mut buffer)
+        .map_err(|_| anyhow!("Encrypt fail"))?;
+
+    let mut record = Vec::with_capacity(5 + buffer.len());
+    record.extend_from_slice(&header);
+    record.extend_from_slice(&buffer);
+
+    Ok(record)
+}
+
+/// 解密一个 TLS 1.3 记录，返回 (内层 content type, 明文长度)
+fn open_record(
+    key: &aead::LessSafeKey,
+    iv: &[u8; 12],
+    seq: u64,
+    header: &[u8; 5],
+    ciphertext: &mut [u8],
+) -> Result<(u8, usize)> {
+    let nonce = record_nonce(iv, seq)?;
+    let aad = aead::Aad::from(&header[..]);
+
+    let plaintext_len = key
+        .open_in_place(nonce, aad, ciphertext)
+        .map_err(|_| anyhow!("Decryption failed"))?
+        .len();
+
+    if plaintext_len == 0 {
+        return Err(anyhow!("Empty plaintext"));
     }
+
+    let mut scan_idx = plaintext_len - 1;
+    while scan_idx > 0 && ciphertext[scan_idx] == 0 {
+        scan_idx -= 1;
+    }
+
+    let content_type = ciphertext[scan_idx];
+    let real_content_len = scan_idx;
+
+    Ok((content_type, real_content_len))
 }
 
 // Helpers
@@ -227,10 +361,10 @@ fn expand_label(prk: &hkdf::Prk, label: &[u8], context: &[u8], len: usize) -> Re
     Ok(out)
 }
 
-fn manual_hkdf_expand(secret: &[u8], info: &[u8], len: usize) -> Result<Vec<u8>> {
-    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
-    if len > 32 {
-        return Err(anyhow!("Manual HKDF-Expand limit: 32 bytes"));
+fn manual_hkdf_expand(suite: CipherSuite, secret: &[u8], info: &[u8], len: usize) -> Result<Vec<u8>> {
+    let key = hmac::Key::new(suite.hmac(), secret);
+    if len > suite.hash_len() {
+        return Err(anyhow!("Manual HKDF-Expand limit: {} bytes", suite.hash_len()));
     }
     let mut msg = Vec::with_capacity(info.len() + 1);
     msg.extend_from_slice(info);
@@ -239,7 +373,7 @@ fn manual_hkdf_expand(secret: &[u8], info: &[u8], len: usize) -> Result<Vec<u8>>
     Ok(tag.as_ref()[..len].to_vec())
 }
 
-fn expand_label_raw(secret: &[u8], label: &[u8], context: &[u8], len: usize) -> Result<Vec<u8>> {
+fn expand_label_raw(suite: CipherSuite, secret: &[u8], label: &[u8], context: &[u8], len: usize) -> Result<Vec<u8>> {
     let mut info = Vec::new();
     info.extend_from_slice(&(len as u16).to_be_bytes());
     let full_label = [b"tls13 ", label].concat();
@@ -247,25 +381,95 @@ fn expand_label_raw(secret: &[u8], label: &[u8], context: &[u8], len: usize) ->
     info.extend_from_slice(&full_label);
     info.push(context.len() as u8);
     info.extend_from_slice(context);
-    manual_hkdf_expand(secret, &info, len)
+    manual_hkdf_expand(suite, secret, &info, len)
 }
 
-fn derive_key_iv(secret: &[u8]) -> Result<(aead::LessSafeKey, [u8; 12])> {
-    let key_bytes = expand_label_raw(secret, b"key", &[], 16)?;
-    let unbound_key = aead::UnboundKey::new(&aead::AES_128_GCM, &key_bytes)
+fn derive_key_iv(suite: CipherSuite, secret: &[u8]) -> Result<(aead::LessSafeKey, [u8; 12])> {
+    let key_bytes = expand_label_raw(suite, secret, b"key", &[], suite.aead().key_len())?;
+    let unbound_key = aead::UnboundKey::new(suite.aead(), &key_bytes)
         .map_err(|_| anyhow!("Failed to create unbound key"))?;
     let key = aead::LessSafeKey::new(unbound_key);
 
-    let iv_bytes = expand_label_raw(secret, b"iv", &[], 12)?;
+    let iv_bytes = expand_label_raw(suite, secret, b"iv", &[], 12)?;
     let mut iv = [0u8; 12];
     iv.copy_from_slice(&iv_bytes);
     Ok((key, iv))
 }
 
-fn hash_empty() -> Vec<u8> {
-    vec![
-        0xe3, 0xb0, 0xc4, 0x42, 0x98, 0xfc, 0x1c, 0x14, 0x9a, 0xfb, 0xf4, 0xc8, 0x99, 0x6f, 0xb9,
-        0x24, 0x27, 0xae, 0x41, 0xe4, 0x64, 0x9b, 0x93, 0x4c, 0xa4, 0x95, 0x99, 0x1b, 0x78, 0x52,
-        0xb8, 0x55,
-    ]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 同一组输入推导出的两份密钥，分别代表连接的两端
+    fn key_pair(suite: CipherSuite) -> (TlsKeys, TlsKeys) {
+        let derive = || {
+            let hash = vec![0x22; suite.hash_len()];
+            let (_, handshake_secret) = TlsKeys::derive_handshake_keys(suite, &[0x11; 32], &hash).unwrap();
+            TlsKeys::derive_application_keys(suite, &handshake_secret, &hash).unwrap()
+        };
+        (derive(), derive())
+    }
+
+    fn open(keys: &TlsKeys, seq: u64, mut record: Vec<u8>) -> Result<Vec<u8>> {
+        let header: [u8; 5] = record[..5].try_into().unwrap();
+        let (content_type, len) = keys.decrypt_client_record(seq, &header, &mut record[5..])?;
+        assert_eq!(content_type, 23);
+        Ok(record[5..5 + len].to_vec())
+    }
+
+    #[test]
+    fn test_key_update() {
+        let (mut client, mut server) = key_pair(CipherSuite::Aes128GcmSha256);
+        assert_eq!(open(&server, 0, client.encrypt_client_record(0, b"before", 23).unwrap()).unwrap(), b"before");
+
+        // 双方各自推导下一代密钥，序列号从 0 重新开始
+        let old_secret = client.client_traffic_secret.clone();
+        client.update_client_keys().unwrap();
+        assert_eq!(client.client_traffic_secret, TlsKeys::next_application_secret(client.suite, &old_secret).unwrap());
+        let record = client.encrypt_client_record(0, b"after", 23).unwrap();
+        assert!(open(&server, 0, record.clone()).is_err());
+        server.update_client_keys().unwrap();
+        assert_eq!(open(&server, 0, record).unwrap(), b"after");
+
+        // 服务端方向独立更新
+        let before = server.server_traffic_secret.clone();
+        server.update_server_keys().unwrap();
+        assert_ne!(server.server_traffic_secret, before);
+        assert_eq!(server.client_traffic_secret, client.client_traffic_secret);
+    }
+
+    #[test]
+    fn test_cipher_suite_round_trip() {
+        for (id, key_len, hash_len) in [(0x1301, 16, 32), (0x1302, 32, 48), (0x1303, 32, 32)] {
+            let suite = CipherSuite::from_id(id).unwrap();
+            assert_eq!(suite.id(), id);
+            assert_eq!(suite.aead().key_len(), key_len);
+            let (server, client) = key_pair(suite);
+            assert_eq!(server.server_traffic_secret.len(), hash_len);
+            assert_eq!(TlsKeys::calculate_verify_data(suite, &server.server_traffic_secret, &[0x44; 48]).unwrap().len(), hash_len);
+
+            let mut record = server.encrypt_server_record(3, b"hello", 23).unwrap();
+            let header: [u8; 5] = record[..5].try_into().unwrap();
+            let (content_type, len) = client.decrypt_server_record(3, &header, &mut record[5..]).unwrap();
+            assert_eq!((content_type, &record[5..5 + len]), (23, &b"hello"[..]));
+            assert_eq!(open(&server, 0, client.encrypt_client_record(0, b"world", 23).unwrap()).unwrap(), b"world");
+
+            // 其他套件推导出的密钥无法解密
+            for other in [0x1301, 0x1302, 0x1303].into_iter().filter(|&other| other != id) {
+                let (other, _) = key_pair(CipherSuite::from_id(other).unwrap());
+                let mut record = server.encrypt_server_record(0, b"hello", 23).unwrap();
+                let header: [u8; 5] = record[..5].try_into().unwrap();
+                assert!(other.decrypt_server_record(0, &header, &mut record[5..]).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_select_cipher_suite() {
+        assert_eq!(CipherSuite::select(&[0x1303, 0x1301]), Some(CipherSuite::Chacha20Poly1305Sha256));
+        assert_eq!(CipherSuite::select(&[0x0a0a, 0x1302, 0x1303]), Some(CipherSuite::Aes256GcmSha384));
+        assert_eq!(CipherSuite::select(&[0x1304, 0xc02f]), None);
+        // 空摘要与 RFC 8446 中 SHA256("") 一致
+        assert_eq!(&hex::encode(CipherSuite::Aes128GcmSha256.hash_transcript(&[]))[..16], "e3b0c44298fc1c14");
+    }
 }
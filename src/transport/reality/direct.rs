@@ -0,0 +1,233 @@
+//! 支持 XTLS 直接复制的 Reality 连接
+//!
+//! vision 切换到直接复制后，客户端不再经过外层 TLS，而是把内层 TLS 记录直接写到
+//! TCP 上。为了在切换点不丢失数据，rustls 下层的 [`RecordBoundary`] 每次最多只交给
+//! rustls 一条完整的 TLS 记录；rustls 在还有未读明文时不会继续读取，因此切换时
+//! [`RecordBoundary`] 中缓存的剩余数据与底层连接中尚未读取的数据都是切换之后的原始数据。
+
+use std::io::{self, Read};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_rustls::server::TlsStream;
+
+use super::server_rustls::PrefixedStream;
+use crate::network::DirectCopy;
+
+/// TLS 记录头长度: 类型 (1) + 版本 (2) + 长度 (2)
+const RECORD_HEADER_LEN: usize = 5;
+
+/// 单次从底层连接读取的缓冲区大小，足够容纳一条最大的 TLS 记录
+const READ_BUF_LEN: usize = 16 * 1024 + 256 + RECORD_HEADER_LEN;
+
+/// 按 TLS 记录边界读取，单次读取不会跨越当前记录的末尾
+///
+/// 底层连接按整块读入缓冲区，再按记录边界分批交出，不会因为记录边界增加读系统调用。
+pub struct RecordBoundary<S> {
+    inner: S,
+    /// 已从底层连接读取、尚未交出的数据为 `buf[start..end]`
+    buf: Box<[u8]>,
+    start: usize,
+    end: usize,
+    header: [u8; RECORD_HEADER_LEN],
+    header_len: usize,
+    /// 当前记录尚未读取的负载字节数
+    remaining: usize,
+}
+
+impl<S> RecordBoundary<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            buf: vec![0; READ_BUF_LEN].into_boxed_slice(),
+            start: 0,
+            end: 0,
+            header: [0; RECORD_HEADER_LEN],
+            header_len: 0,
+            remaining: 0,
+        }
+    }
+
+    /// 底层连接，绕过记录边界直接写入
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// 本次最多可以读取的字节数
+    fn limit(&self) -> usize {
+        if self.remaining > 0 {
+            self.remaining
+        } else {
+            RECORD_HEADER_LEN - self.header_len
+        }
+    }
+
+    /// 记录缓冲区开头交出的 `n` 个字节
+    fn consume(&mut self, n: usize) {
+        let data = &self.buf[self.start..self.start + n];
+        self.start += n;
+        if self.remaining > 0 {
+            self.remaining -= n;
+            return;
+        }
+        self.header[self.header_len..self.header_len + n].copy_from_slice(data);
+        self.header_len += n;
+        if self.header_len == RECORD_HEADER_LEN {
+            self.header_len = 0;
+            self.remaining = u16::from_be_bytes([self.header[3], self.header[4]]) as usize;
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> RecordBoundary<S> {
+    /// 缓冲区为空时从底层连接读取一块，底层连接结束时缓冲区仍为空
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.start < self.end {
+            return Poll::Ready(Ok(()));
+        }
+        let mut read = ReadBuf::new(&mut self.buf);
+        ready!(Pin::new(&mut self.inner).poll_read(cx, &mut read))?;
+        self.start = 0;
+        self.end = read.filled().len();
+        Poll::Ready(Ok(()))
+    }
+
+    /// 不按记录边界读取: 先交出缓冲区中剩余的数据，之后直接读取底层连接
+    pub fn poll_read_raw(&mut self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        if self.start == self.end {
+            return Pin::new(&mut self.inner).poll_read(cx, buf);
+        }
+        let n = (self.end - self.start).min(buf.remaining());
+        buf.put_slice(&self.buf[self.start..self.start + n]);
+        self.start += n;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for RecordBoundary<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        ready!(this.poll_fill(cx))?;
+        let n = this.limit().min(buf.remaining()).min(this.end - this.start);
+        buf.put_slice(&this.buf[this.start..this.start + n]);
+        this.consume(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for RecordBoundary<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Reality 握手完成后的连接，按 [`DirectCopy`] 开关在 TLS 与底层连接之间切换
+pub struct DirectStream<S> {
+    tls: TlsStream<RecordBoundary<PrefixedStream<S>>>,
+    direct: Arc<DirectCopy>,
+    /// 切换为直接写入前，外层 TLS 中缓存的记录是否已经写出
+    tls_flushed: bool,
+}
+
+impl<S> DirectStream<S> {
+    pub fn new(tls: TlsStream<RecordBoundary<PrefixedStream<S>>>) -> Self {
+        Self {
+            tls,
+            direct: DirectCopy::new(),
+            tls_flushed: false,
+        }
+    }
+
+    /// 本连接的直接复制开关
+    pub fn direct_copy(&self) -> Arc<DirectCopy> {
+        self.direct.clone()
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for DirectStream<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        if !self.direct.read_enabled() {
+            return Pin::new(&mut self.tls).poll_read(cx, buf);
+        }
+        let (io, session) = self.tls.get_mut();
+        // 先交出 TLS 中已解密但未读取的明文，之后直接读取底层连接
+        if let Ok(n) = session.reader().read(buf.initialize_unfilled()) {
+            if n > 0 {
+                buf.advance(n);
+                return Poll::Ready(Ok(()));
+            }
+        }
+        io.poll_read_raw(cx, buf)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for DirectStream<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        if !self.direct.write_enabled() {
+            return Pin::new(&mut self.tls).poll_write(cx, buf);
+        }
+        if !self.tls_flushed {
+            ready!(Pin::new(&mut self.tls).poll_flush(cx))?;
+            self.tls_flushed = true;
+        }
+        Pin::new(self.tls.get_mut().0.get_mut()).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.direct.write_enabled() && self.tls_flushed {
+            return Pin::new(self.tls.get_mut().0.get_mut()).poll_flush(cx);
+        }
+        Pin::new(&mut self.tls).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.direct.write_enabled() && self.tls_flushed {
+            return Pin::new(self.tls.get_mut().0.get_mut()).poll_shutdown(cx);
+        }
+        Pin::new(&mut self.tls).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_reads_stop_at_record_boundary() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        // 两条记录与随后的原始数据一次写入
+        client.write_all(&[0x16, 0x03, 0x01, 0x00, 0x02, 0xaa, 0xbb]).await.unwrap();
+        client.write_all(&[0x17, 0x03, 0x03, 0x00, 0x01, 0xcc, 0xdd, 0xee]).await.unwrap();
+
+        let mut reader = RecordBoundary::new(server);
+        let mut buf = [0u8; 64];
+        let mut sizes = Vec::new();
+        let mut total = 0;
+        while total < 13 {
+            let n = reader.read(&mut buf).await.unwrap();
+            sizes.push(n);
+            total += n;
+        }
+        // 记录头与负载分别交出，不会交出第二条记录之后的数据
+        assert_eq!(sizes, vec![5, 2, 5, 1]);
+        // 一并读入缓冲区的后续数据在切换后原样交出
+        let mut raw = ReadBuf::new(&mut buf);
+        std::future::poll_fn(|cx| reader.poll_read_raw(cx, &mut raw)).await.unwrap();
+        assert_eq!(raw.filled(), &[0xdd, 0xee]);
+
+        client.write_all(b"raw").await.unwrap();
+        let mut raw = ReadBuf::new(&mut buf);
+        std::future::poll_fn(|cx| reader.poll_read_raw(cx, &mut raw)).await.unwrap();
+        assert_eq!(raw.filled(), b"raw");
+    }
+}
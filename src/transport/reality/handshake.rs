@@ -1,231 +1,587 @@
-use anyhow::{anyhow, Result};
-use bytes::{BytesMut, Buf, BufMut};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
-use tracing::{debug, info, warn, error};
+use anyhow::{anyhow, bail, Result};
+use bytes::{BufMut, BytesMut};
+use ring::signature::Ed25519KeyPair;
+use rustls_pki_types::PrivateKeyDer;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::{debug, info, error};
 
-use super::tls::{ClientHello, TlsRecord};
-use super::RealityConfig;
-use super::crypto::{RealityCrypto, TlsKeys};
+use super::crypto::{CipherSuite, RealityCrypto, TlsKeys};
+use super::hello_parser::{self, ClientHelloInfo};
+use super::server_rustls::RealityServerRustls;
 
+/// 兼容中间设备的 ChangeCipherSpec 记录
+const CHANGE_CIPHER_SPEC: [u8; 6] = [0x14, 0x03, 0x03, 0x00, 0x01, 0x01];
+const ALERT_HANDSHAKE_FAILURE: u8 = 40;
+const ALERT_ILLEGAL_PARAMETER: u8 = 47;
+
+/// 等待客户端 Finished 时最多丢弃的 0-RTT 早期数据 (字节)
+const MAX_EARLY_DATA: usize = 64 * 1024;
+
+/// 握手完成后发送的 NewSessionTicket 数量
+const SESSION_TICKETS: u64 = 2;
+/// 票据声明的有效期 (秒)
+const TICKET_LIFETIME: u32 = 7200;
+/// 随机票据的长度
+const TICKET_LEN: usize = 192;
+
+/// 手写的 TLS 1.3 服务端握手 (native 后端)
+///
+/// 认证、回落与临时证书与 rustls 后端共用 [`RealityServerRustls`]，只有 TLS 握手本身不同。
+/// 支持 TLS_AES_128_GCM_SHA256、TLS_AES_256_GCM_SHA384 与 TLS_CHACHA20_POLY1305_SHA256，
+/// 按客户端的偏好顺序选择；密钥交换仅支持 X25519，不支持 vision 直接复制。
 #[derive(Clone)]
 pub struct RealityHandshake {
-    config: RealityConfig,
+    verifier: RealityServerRustls,
 }
 
 impl RealityHandshake {
-    pub fn new(config: RealityConfig) -> Self {
-        Self { config }
+    pub fn new(verifier: RealityServerRustls) -> Self {
+        Self { verifier }
     }
 
     /// Reality 握手with认证验证和回落
-    pub async fn perform(&self, mut client_stream: TcpStream) -> Result<super::stream::TlsStream<TcpStream>> {
+    pub async fn perform<S>(&self, mut client_stream: S) -> Result<super::stream::TlsStream<S>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
         // 1. 读取 ClientHello
-        let (client_hello, client_hello_raw) = self.read_client_hello(&mut client_stream).await?;
-        info!("ClientHello received, SNI: {:?}", client_hello.get_sni());
-        
-        // 2. 验证 Reality 认证
-        debug!("Client SessionID: {}", hex::encode(&client_hello.session_id));
-        debug!("Client Random: {}", hex::encode(&client_hello.random));
-        
-        // TODO: 暂时禁用客户端认证，测试握手流程
-        let is_reality_client = true; // 临时：接受所有客户端
-        
-        debug!("Reality authentication result: {}", is_reality_client);
-        
-        if !is_reality_client {
-            warn!("Reality authentication failed - falling back to dest");
-            return self.fallback_to_dest(client_stream, &client_hello_raw).await;
-        }
-        
-        info!("✅ Reality authentication successful!");
-        
-        // 3. 执行 Reality 握手（使用我们自己的密钥）
-        let client_key_share = match client_hello.get_key_share() {
-            Some(key) => key,
-            None => return Err(anyhow!("No X25519 key share")),
+        let buffer = self.verifier.read_client_hello(&mut client_stream).await?;
+        let hello = self.verifier.parse_client_hello(&buffer);
+
+        // 2. 验证 Reality 认证，失败时回落
+        let verified = hello
+            .as_ref()
+            .and_then(|info| Some((info, self.verifier.verify_client_reality(info)?.1)));
+        let Some((info, auth_key)) = verified else {
+            self.verifier.reject(client_stream, &buffer, hello.as_ref()).await?;
+            bail!("Fallback total");
+        };
+        info!("Reality: Verified client (native backend), SNI: {:?}", info.server_name);
+
+        // 承载 ClientHello 的记录之后已读到的数据留给后续记录
+        let mut leftover = buffer[info.records_len..].to_vec();
+
+        let Some(suite) = CipherSuite::select(&info.cipher_suites) else {
+            client_stream.write_all(&alert(ALERT_HANDSHAKE_FAILURE)).await?;
+            bail!("客户端没有提供支持的密码套件 ({:04x?})", info.cipher_suites);
         };
+        debug!("Reality: 选择密码套件 {:?}", suite);
 
+        // 只通过 X25519MLKEM768 认证的客户端没有可用的 X25519 密钥份额，用 HelloRetryRequest 要求 X25519
+        let retry = if info.key_share_groups.contains(&hello_parser::GROUP_X25519) {
+            None
+        } else {
+            Some(hello_retry(&mut client_stream, info, suite, &mut leftover).await?)
+        };
+        let (info, mut transcript) = match &retry {
+            Some((hello, prefix)) => (hello, prefix.iter().map(Vec::as_slice).collect()),
+            None => (info, Vec::new()),
+        };
+        let client_key_share = info.public_key.as_deref().ok_or_else(|| anyhow!("No X25519 key share"))?;
+        let client_hello_raw = &info.message[..];
+
+        // 3. 执行 Reality 握手（使用我们自己的密钥）
         let crypto = RealityCrypto::new();
         let my_public_key = crypto.get_public_key();
-        let shared_secret = crypto.derive_shared_secret(&client_key_share)?;
+        let shared_secret = crypto.derive_shared_secret(client_key_share)?;
 
-        // 4. 构造 ServerHello（带 Reality 认证）
+        // 4. 构造 ServerHello
         use rand::RngCore;
         let mut server_random = [0u8; 32];
         rand::rngs::OsRng.fill_bytes(&mut server_random);
 
-        let mut server_hello = super::tls::ServerHello::new_reality(
-            &client_hello.session_id,
+        let server_hello = super::tls::ServerHello::new_reality(
+            &info.session_id,
             server_random,
+            suite.id(),
             &my_public_key
         )?;
-        
-        server_hello.modify_for_reality(&self.config.private_key, &client_hello.random)?;
 
-        // 5. 发送 ServerHello 和 CCS
+        // 5. 发送 ServerHello 和 CCS (HelloRetryRequest 之后已发送过 CCS)
         client_stream.write_all(&server_hello.encode()).await?;
-        client_stream.write_all(&[0x14, 0x03, 0x03, 0x00, 0x01, 0x01]).await?;
+        if retry.is_none() {
+            client_stream.write_all(&CHANGE_CIPHER_SPEC).await?;
+        }
         debug!("ServerHello & CCS sent");
 
         // 6. 推导握手密钥
-        let transcript0 = vec![client_hello_raw.as_slice(), server_hello.handshake_payload()];
-        let (mut hs_keys, handshake_secret) = TlsKeys::derive_handshake_keys(
-            &shared_secret, 
-            &super::crypto::hash_transcript(&transcript0)
+        transcript.extend([client_hello_raw, server_hello.handshake_payload()]);
+        let (hs_keys, handshake_secret) = TlsKeys::derive_handshake_keys(
+            suite,
+            &shared_secret,
+            &suite.hash_transcript(&transcript)
         )?;
-        
-        // 7. 发送加密握手消息（标准 TLS 1.3：EE + Cert + Fin）
+
+        // 7. 发送加密握手消息（标准 TLS 1.3：EE + Cert + CertVerify + Fin）
         let ee_msg = vec![8, 0, 0, 2, 0, 0];
-        debug!("EncryptedExtensions plaintext: {}", hex::encode(&ee_msg));
-        
-        // Certificate 消息（空证书列表）
-        // 格式：Type(1) + Length(3) + CertReqCtx(1) + CertList(3)
-        let cert_msg = vec![
-            11,       // Type: Certificate
-            0, 0, 4,  // Length: 4 bytes
-            0,        // Certificate Request Context Length: 0
-            0, 0, 0   // Certificate List Length: 0
-        ];
-        debug!("Certificate plaintext: {}", hex::encode(&cert_msg));
-        
-        let transcript1 = vec![
-            client_hello_raw.as_slice(),
-            server_hello.handshake_payload(),
-            &ee_msg,
-            &cert_msg
-        ];
-        let hash1 = super::crypto::hash_transcript(&transcript1);
-        debug!("Transcript hash (for Finished): {}", hex::encode(&hash1));
-        
-        let verify_data = TlsKeys::calculate_verify_data(&hs_keys.server_traffic_secret, &hash1)?;
-        debug!("Verify data: {}", hex::encode(&verify_data));
-        
+
+        // Certificate 消息：带 Reality 签名的临时证书 (与 rustls 后端相同)
+        let (chain, key) = self.verifier.generate_reality_cert(&auth_key, self.verifier.cert_host())?;
+        let cert_msg = certificate_message(&chain);
+
+        // CertificateVerify：用临时证书的 Ed25519 私钥签名握手摘要
+        // Reality 客户端通过 Ed25519 公钥校验证书，即使客户端没有声明 ed25519 也只能使用它
+        if !info.signature_algorithms.is_empty() && !info.signature_algorithms.contains(&hello_parser::SIGNATURE_ED25519) {
+            debug!("Reality: 客户端未声明 ed25519 ({:04x?})，仍使用 Ed25519 证书", info.signature_algorithms);
+        }
+        transcript.extend([&ee_msg[..], &cert_msg]);
+        let verify_msg = certificate_verify_message(&key, &suite.hash_transcript(&transcript))?;
+
+        transcript.push(&verify_msg);
+        let hash2 = suite.hash_transcript(&transcript);
+        let verify_data = TlsKeys::calculate_verify_data(suite, &hs_keys.server_traffic_secret, &hash2)?;
+
         let mut fin_msg = BytesMut::new();
         fin_msg.put_u8(20);
         let fin_len = verify_data.len() as u32;
         fin_msg.put_slice(&fin_len.to_be_bytes()[1..4]);
         fin_msg.put_slice(&verify_data);
-        debug!("Finished plaintext: {}", hex::encode(&fin_msg));
-        
+
         // 打包所有消息到一个 TLS Record
         let mut bundle = BytesMut::new();
         bundle.put_slice(&ee_msg);
         bundle.put_slice(&cert_msg);
+        bundle.put_slice(&verify_msg);
         bundle.put_slice(&fin_msg);
-        
-        debug!("Bundled handshake messages (plaintext): {}", hex::encode(&bundle));
-        
+
         let bundled_record = hs_keys.encrypt_server_record(0, &bundle, 22)?;
-        debug!("Bundled handshake messages (encrypted): {}", hex::encode(&bundled_record));
         client_stream.write_all(&bundled_record).await?;
-        
-        info!("Server handshake complete, waiting for client Finished...");
+        client_stream.flush().await?;
+
+        debug!("Server handshake complete, waiting for client Finished...");
+        transcript.push(&fin_msg);
+        let hash_app = suite.hash_transcript(&transcript);
 
         // 8. 读取客户端 Finished
-        let mut buf = BytesMut::with_capacity(4096);
-        
-        loop {
-            if buf.len() < 5 {
-                let n = client_stream.read_buf(&mut buf).await?;
-                if n == 0 { return Err(anyhow!("Connection closed")); }
-                if buf.len() < 5 { continue; }
-            }
-            
-            let ctype = buf[0];
-            let rlen = u16::from_be_bytes([buf[3], buf[4]]) as usize;
-            
-            if buf.len() < 5 + rlen {
-                let n = client_stream.read_buf(&mut buf).await?;
-                if n == 0 { return Err(anyhow!("EOF")); }
-                continue;
-            }
-            
-            let mut record_data = buf.split_to(5 + rlen);
-            
-            if ctype == 20 { continue; }
-            
-            if ctype == 23 {
-                let mut header = [0u8; 5];
-                header.copy_from_slice(&record_data[..5]);
-                let (inner_type, plen) = hs_keys.decrypt_client_record(0, &header, &mut record_data[5..])?;
-                
-                if inner_type == 21 {
-                    let level = if plen > 0 { record_data[5] } else { 0 };
-                    let desc = if plen > 1 { record_data[6] } else { 0 };
-                    error!("Client Alert: {}/{}", level, desc);
-                    return Err(anyhow!("Client sent Alert {}/{}", level, desc));
+        let mut buf = BytesMut::from(&leftover[..]);
+        let expected = TlsKeys::calculate_verify_data(suite, &hs_keys.client_traffic_secret, &hash_app)?;
+        read_client_finished(&mut client_stream, &mut buf, &hs_keys, &expected, info.early_data).await?;
+
+        // 9. 推导应用层密钥
+        let app_keys = TlsKeys::derive_application_keys(suite, &handshake_secret, &hash_app)?;
+
+        // 10. 与常见的服务端一样发送两张 NewSessionTicket；票据只是随机数据，恢复请求按完整握手处理
+        let mut write_seq = 0;
+        for _ in 0..SESSION_TICKETS {
+            client_stream.write_all(&app_keys.encrypt_server_record(write_seq, &new_session_ticket(), 22)?).await?;
+            write_seq += 1;
+        }
+
+        info!("Reality handshake successful (native backend)");
+        Ok(super::stream::TlsStream::new_with_buffer(client_stream, app_keys, buf).with_write_seq(write_seq))
+    }
+}
+
+/// 读取并校验客户端 Finished，`buf` 中为已读到的数据，返回时留下 Finished 之后的数据
+///
+/// 服务端从不接受 0-RTT: 客户端提供了 early_data 时，无法用握手密钥解密的记录按早期数据丢弃
+/// (RFC 8446 4.2.10)，最多丢弃 [`MAX_EARLY_DATA`] 字节。
+async fn read_client_finished<S>(stream: &mut S, buf: &mut BytesMut, hs_keys: &TlsKeys, expected: &[u8], early_data: bool) -> Result<()>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut skipped = 0;
+    loop {
+        if buf.len() < 5 {
+            let n = stream.read_buf(buf).await?;
+            if n == 0 { return Err(anyhow!("Connection closed")); }
+            if buf.len() < 5 { continue; }
+        }
+
+        let ctype = buf[0];
+        let rlen = u16::from_be_bytes([buf[3], buf[4]]) as usize;
+
+        if buf.len() < 5 + rlen {
+            let n = stream.read_buf(buf).await?;
+            if n == 0 { return Err(anyhow!("EOF")); }
+            continue;
+        }
+
+        let mut record_data = buf.split_to(5 + rlen);
+
+        if ctype == 20 { continue; }
+
+        if ctype == 23 {
+            let mut header = [0u8; 5];
+            header.copy_from_slice(&record_data[..5]);
+            let (inner_type, plen) = match hs_keys.decrypt_client_record(0, &header, &mut record_data[5..]) {
+                Ok(opened) => opened,
+                Err(_) if early_data && skipped + rlen <= MAX_EARLY_DATA => {
+                    skipped += rlen;
+                    debug!("丢弃 {} 字节 0-RTT 早期数据", rlen);
+                    continue;
                 }
-                
-                if inner_type == 22 && plen > 0 && record_data[5] == 20 {
-                    info!("✅ Client Finished received!");
-                    break;
+                Err(e) => return Err(e),
+            };
+
+            if inner_type == 21 {
+                let level = if plen > 0 { record_data[5] } else { 0 };
+                let desc = if plen > 1 { record_data[6] } else { 0 };
+                error!("Client Alert: {}/{}", level, desc);
+                return Err(anyhow!("Client sent Alert {}/{}", level, desc));
+            }
+
+            if inner_type == 22 && plen > 0 && record_data[5] == 20 {
+                if record_data[9..5 + plen] != expected[..] {
+                    bail!("Client Finished 校验失败");
                 }
+                debug!("Client Finished received");
+                return Ok(());
             }
         }
-        
-        // 9. 推导应用层密钥
-        let transcript_app = vec![
-            client_hello_raw.as_slice(),
-            server_hello.handshake_payload(),
-            &ee_msg,
-            &cert_msg,
-            &fin_msg
-        ];
-        let app_keys = TlsKeys::derive_application_keys(&handshake_secret, &super::crypto::hash_transcript(&transcript_app))?;
-        
-        info!("🎉 Reality handshake successful! Tunnel established.");
-        Ok(super::stream::TlsStream::new_with_buffer(client_stream, app_keys, buf))
-    }
-    
-    /// 回落到真实的 dest 服务器（透明代理）
-    async fn fallback_to_dest(&self, mut client: TcpStream, client_hello: &[u8]) -> Result<super::stream::TlsStream<TcpStream>> {
-        info!("Falling back to dest: {}", self.config.dest);
-        
-        // 连接到 dest
-        let mut dest = TcpStream::connect(&self.config.dest).await
-            .map_err(|e| anyhow!("Failed to connect to dest: {}", e))?;
-        
-        // 构造完整的 ClientHello TLS Record
-        let mut ch_record = BytesMut::new();
-        ch_record.put_u8(0x16);
-        ch_record.put_u16(0x0303);
-        ch_record.put_u16(client_hello.len() as u16);
-        ch_record.put_slice(client_hello);
-        
-        // 转发 ClientHello
-        dest.write_all(&ch_record).await?;
-        
-        // 启动双向透明转发
-        tokio::spawn(async move {
-            let (mut client_read, mut client_write) = client.split();
-            let (mut dest_read, mut dest_write) = dest.split();
-            
-            let c2d = tokio::io::copy(&mut client_read, &mut dest_write);
-            let d2c = tokio::io::copy(&mut dest_read, &mut client_write);
-            
-            tokio::select! {
-                _ = c2d => {},
-                _ = d2c => {},
+    }
+}
+
+/// 发送 HelloRetryRequest 要求 X25519 并确定密码套件 `suite`，读取第二个 ClientHello
+///
+/// 返回第二个 ClientHello 与握手摘要的前缀 (第一个 ClientHello 的 message_hash + HelloRetryRequest)，
+/// `leftover` 更新为第二个 ClientHello 之后已读到的数据。客户端不支持 X25519 时发送
+/// handshake_failure alert 并返回错误。
+async fn hello_retry<S>(stream: &mut S, first: &ClientHelloInfo, suite: CipherSuite, leftover: &mut Vec<u8>) -> Result<(ClientHelloInfo, Vec<Vec<u8>>)>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    if !first.supported_groups.contains(&hello_parser::GROUP_X25519) {
+        stream.write_all(&alert(ALERT_HANDSHAKE_FAILURE)).await?;
+        bail!("客户端不支持 X25519 (supported_groups {:04x?})", first.supported_groups);
+    }
+    debug!("Reality: ClientHello 没有 X25519 密钥份额 ({:04x?})，发送 HelloRetryRequest", first.key_share_groups);
+    let retry = super::tls::ServerHello::new_hello_retry(&first.session_id, suite.id(), hello_parser::GROUP_X25519);
+    stream.write_all(&retry.encode()).await?;
+    stream.write_all(&CHANGE_CIPHER_SPEC).await?;
+    stream.flush().await?;
+
+    // 跳过客户端的 CCS 与被拒绝的 0-RTT 早期数据，读到完整的第二个 ClientHello
+    let skippable = |content_type: u8| content_type == 0x14 || (first.early_data && content_type == 0x17);
+    let (message, records_len) = loop {
+        while leftover.len() >= 5 && skippable(leftover[0]) {
+            let record_len = 5 + u16::from_be_bytes([leftover[3], leftover[4]]) as usize;
+            if leftover.len() < record_len {
+                break;
             }
-        });
-        
-        // 返回错误，因为连接已经被转发
-        Err(anyhow!("Connection fell back to dest"))
-    }
-
-    async fn read_client_hello(&self, stream: &mut TcpStream) -> Result<(ClientHello, Vec<u8>)> {
-        let mut buf = BytesMut::with_capacity(4096);
-        loop {
-            let n = stream.read_buf(&mut buf).await?;
-            if n == 0 { return Err(anyhow!("EOF reading CH")); }
-            let mut parse_buf = buf.clone();
-            if let Some(record) = TlsRecord::parse(&mut parse_buf)? {
-                if record.content_type == super::tls::ContentType::Handshake {
-                     let ch = ClientHello::parse(&record.payload)?;
-                     return Ok((ch, record.payload));
-                }
+            leftover.drain(..record_len);
+        }
+        if !leftover.first().is_some_and(|&content_type| skippable(content_type)) {
+            if let Some(hello) = hello_parser::reassemble_handshake(leftover)? {
+                break hello;
             }
         }
+        let mut chunk = [0u8; 4096];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 { bail!("等待第二个 ClientHello 时连接已关闭"); }
+        leftover.extend_from_slice(&chunk[..n]);
+    };
+    let second = hello_parser::parse_client_hello(&leftover[..records_len])?
+        .ok_or_else(|| anyhow!("HelloRetryRequest 之后收到的不是 ClientHello: {:02x?}", &message[..message.len().min(4)]))?;
+    if second.session_id != first.session_id
+        || !second.key_share_groups.contains(&hello_parser::GROUP_X25519)
+        || !second.cipher_suites.contains(&suite.id())
+    {
+        stream.write_all(&alert(ALERT_ILLEGAL_PARAMETER)).await?;
+        bail!("第二个 ClientHello 与 HelloRetryRequest 不符");
+    }
+    leftover.drain(..records_len);
+
+    // 第一个 ClientHello 在握手摘要中替换为 message_hash (RFC 8446 4.4.1)
+    let mut message_hash = vec![254, 0, 0, suite.hash_len() as u8];
+    message_hash.extend_from_slice(&suite.hash_transcript(&[&first.message]));
+    Ok((second, vec![message_hash, retry.handshake_payload().to_vec()]))
+}
+
+/// 明文的 fatal alert 记录
+fn alert(description: u8) -> [u8; 7] {
+    [21, 0x03, 0x03, 0x00, 0x02, 2, description]
+}
+
+/// Certificate 消息: Type(1) + Length(3) + CertReqCtx(1) + CertList(3) + [CertLen(3) + Cert + ExtLen(2)]...
+fn certificate_message(chain: &[rustls_pki_types::CertificateDer<'_>]) -> Vec<u8> {
+    let mut list = Vec::new();
+    for cert in chain {
+        list.extend_from_slice(&(cert.len() as u32).to_be_bytes()[1..]);
+        list.extend_from_slice(cert);
+        list.extend_from_slice(&[0, 0]);
+    }
+    let mut body = vec![0];
+    body.extend_from_slice(&(list.len() as u32).to_be_bytes()[1..]);
+    body.extend_from_slice(&list);
+    handshake_message(11, &body)
+}
+
+/// CertificateVerify 消息 (ed25519): 对 64 个空格 + 上下文字符串 + 0 + 握手摘要签名
+fn certificate_verify_message(key: &PrivateKeyDer<'_>, transcript_hash: &[u8]) -> Result<Vec<u8>> {
+    let PrivateKeyDer::Pkcs8(pkcs8) = key else { bail!("临时证书私钥不是 PKCS#8 格式") };
+    let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(pkcs8.secret_pkcs8_der())
+        .map_err(|e| anyhow!("临时证书私钥无效: {}", e))?;
+
+    let mut content = vec![0x20u8; 64];
+    content.extend_from_slice(b"TLS 1.3, server CertificateVerify");
+    content.push(0x00);
+    content.extend_from_slice(transcript_hash);
+    let signature = key_pair.sign(&content);
+
+    let mut body = hello_parser::SIGNATURE_ED25519.to_be_bytes().to_vec();
+    body.extend_from_slice(&(signature.as_ref().len() as u16).to_be_bytes());
+    body.extend_from_slice(signature.as_ref());
+    Ok(handshake_message(15, &body))
+}
+
+/// NewSessionTicket 消息: lifetime(4) + age_add(4) + nonce(1+n) + ticket(2+n) + extensions(2)
+fn new_session_ticket() -> Vec<u8> {
+    use rand::RngCore;
+    let mut rng = rand::rngs::OsRng;
+    let mut ticket = [0u8; TICKET_LEN];
+    rng.fill_bytes(&mut ticket);
+
+    let mut body = Vec::with_capacity(TICKET_LEN + 20);
+    body.extend_from_slice(&TICKET_LIFETIME.to_be_bytes());
+    body.extend_from_slice(&rng.next_u32().to_be_bytes());
+    body.push(8);
+    body.extend_from_slice(&rng.next_u64().to_be_bytes());
+    body.extend_from_slice(&(TICKET_LEN as u16).to_be_bytes());
+    body.extend_from_slice(&ticket);
+    body.extend_from_slice(&[0, 0]);
+    handshake_message(4, &body)
+}
+
+fn handshake_message(msg_type: u8, body: &[u8]) -> Vec<u8> {
+    let mut message = vec![msg_type];
+    message.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    message.extend_from_slice(body);
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::reality::client;
+    use crate::transport::reality::stats::VerifyFailure;
+    use crate::transport::reality::tls::HELLO_RETRY_REQUEST_RANDOM;
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    const SERVER_KEY: [u8; 32] = [0x24; 32];
+    const SHORT_ID: [u8; 8] = [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef];
+    const GROUP_SECP256R1: u16 = 0x0017;
+
+    fn handshake(dest: &str) -> RealityHandshake {
+        let verifier = RealityServerRustls::new(
+            SERVER_KEY.to_vec(),
+            Some(dest.to_string()),
+            vec![hex::encode(SHORT_ID)],
+            vec!["www.example.com".to_string()],
+        )
+        .unwrap();
+        RealityHandshake::new(verifier)
+    }
+
+    /// 带 Reality 认证信息的 ClientHello 记录，认证使用 `secret` 对应的 X25519 公钥
+    fn hello_record(random: &[u8; 32], secret: &StaticSecret, supported_groups: &[u16], key_shares: &[(u16, &[u8])]) -> Vec<u8> {
+        hello_record_with_suites(random, secret, &[0x1301], supported_groups, key_shares)
+    }
+
+    fn hello_record_with_suites(random: &[u8; 32], secret: &StaticSecret, cipher_suites: &[u16], supported_groups: &[u16], key_shares: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut hello = client::build_client_hello_with(random, "www.example.com", supported_groups, key_shares);
+        // 替换客户端默认的密码套件列表
+        let mut suites = ((cipher_suites.len() * 2) as u16).to_be_bytes().to_vec();
+        suites.extend(cipher_suites.iter().flat_map(|id| id.to_be_bytes()));
+        let pos = client::SESSION_ID_OFFSET + 32;
+        hello.splice(pos..pos + 4, suites);
+        let body_len = (hello.len() - 4) as u32;
+        hello[1..4].copy_from_slice(&body_len.to_be_bytes()[1..]);
+
+        let server_public = PublicKey::from(&StaticSecret::from(SERVER_KEY));
+        let auth_key = client::derive_auth_key(secret.diffie_hellman(&server_public).as_bytes(), random);
+        let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as u32;
+        let session_id = client::seal_session_id(&auth_key, random, &hello, timestamp, &SHORT_ID).unwrap();
+        hello[client::SESSION_ID_OFFSET..client::SESSION_ID_OFFSET + 32].copy_from_slice(&session_id);
+
+        let mut record = vec![0x16, 0x03, 0x01];
+        record.extend_from_slice(&(hello.len() as u16).to_be_bytes());
+        record.extend_from_slice(&hello);
+        record
+    }
+
+    /// X25519MLKEM768 密钥份额: 随机的 ML-KEM 封装密钥 + X25519 公钥
+    fn hybrid_share(public: &PublicKey) -> Vec<u8> {
+        let mut share = vec![0x5a; 1184];
+        share.extend_from_slice(public.as_bytes());
+        share
+    }
+
+    async fn read_record<S: AsyncRead + Unpin>(stream: &mut S) -> Vec<u8> {
+        let mut header = [0u8; 5];
+        stream.read_exact(&mut header).await.unwrap();
+        let mut record = header.to_vec();
+        record.resize(5 + u16::from_be_bytes([header[3], header[4]]) as usize, 0);
+        stream.read_exact(&mut record[5..]).await.unwrap();
+        record
+    }
+
+    #[tokio::test]
+    async fn test_hello_retry_for_hybrid_only_client() {
+        let secret = StaticSecret::random_from_rng(rand::thread_rng());
+        let public = PublicKey::from(&secret);
+        let random = [0x33; 32];
+        let hybrid = hybrid_share(&public);
+        let first = hello_record(&random, &secret, &[hello_parser::GROUP_X25519_MLKEM768, hello_parser::GROUP_X25519], &[(hello_parser::GROUP_X25519_MLKEM768, &hybrid)]);
+        let info = hello_parser::parse_client_hello(&first).unwrap().unwrap();
+        assert_eq!(info.key_share_groups, [hello_parser::GROUP_X25519_MLKEM768]);
+        assert_eq!(info.supported_groups, [hello_parser::GROUP_X25519_MLKEM768, hello_parser::GROUP_X25519]);
+        assert_eq!(info.public_key.as_deref(), Some(&public.as_bytes()[..]));
+
+        let (mut client, server_side) = tokio::io::duplex(65536);
+        let server = tokio::spawn(async move { handshake("127.0.0.1:1").perform(server_side).await.map(|_| ()) });
+        client.write_all(&first).await.unwrap();
+
+        // HelloRetryRequest 要求 X25519，并回显 SessionID
+        let retry = read_record(&mut client).await;
+        assert_eq!(retry[5], 2);
+        assert_eq!(retry[11..43], HELLO_RETRY_REQUEST_RANDOM);
+        assert_eq!(retry[44..76], info.session_id[..]);
+        assert!(retry.ends_with(&[0x00, 0x33, 0x00, 0x02, 0x00, 0x1d]));
+        assert_eq!(read_record(&mut client).await, CHANGE_CIPHER_SPEC);
+
+        // 第二个 ClientHello 带 X25519 密钥份额，服务端回复真正的 ServerHello
+        let mut second = client::build_client_hello(&random, public.as_bytes(), "www.example.com");
+        second[client::SESSION_ID_OFFSET..client::SESSION_ID_OFFSET + 32].copy_from_slice(&info.session_id);
+        client.write_all(&CHANGE_CIPHER_SPEC).await.unwrap();
+        client.write_all(&[0x16, 0x03, 0x03]).await.unwrap();
+        client.write_all(&(second.len() as u16).to_be_bytes()).await.unwrap();
+        client.write_all(&second).await.unwrap();
+        let server_hello = read_record(&mut client).await;
+        assert_eq!(server_hello[5], 2);
+        assert_ne!(server_hello[11..43], HELLO_RETRY_REQUEST_RANDOM);
+        // 之后是加密的握手消息，而不是第二个 CCS
+        assert_eq!(read_record(&mut client).await[0], 23);
+        drop(client);
+        assert!(server.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_handshake_failure_without_x25519_support() {
+        let secret = StaticSecret::random_from_rng(rand::thread_rng());
+        let hybrid = hybrid_share(&PublicKey::from(&secret));
+        let record = hello_record(&[0x44; 32], &secret, &[hello_parser::GROUP_X25519_MLKEM768], &[(hello_parser::GROUP_X25519_MLKEM768, &hybrid)]);
+
+        let (mut client, server_side) = tokio::io::duplex(65536);
+        let server = tokio::spawn(async move { handshake("127.0.0.1:1").perform(server_side).await.map(|_| ()) });
+        client.write_all(&record).await.unwrap();
+        assert_eq!(read_record(&mut client).await, alert(ALERT_HANDSHAKE_FAILURE));
+        assert!(server.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_secp256r1_only_hello_falls_back() {
+        let dest = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let handshake = handshake(&dest.local_addr().unwrap().to_string());
+        let secret = StaticSecret::random_from_rng(rand::thread_rng());
+        let record = hello_record(&[0x55; 32], &secret, &[GROUP_SECP256R1], &[(GROUP_SECP256R1, &[0x04; 65])]);
+        let info = hello_parser::parse_client_hello(&record).unwrap().unwrap();
+        assert!(info.public_key.is_none());
+        assert_eq!(info.key_share_groups, [GROUP_SECP256R1]);
+
+        // 无法认证，原样转发给 dest，由 dest 自己处理 (例如发送 HelloRetryRequest)
+        let (mut client, server_side) = tokio::io::duplex(65536);
+        let server = tokio::spawn({
+            let handshake = handshake.clone();
+            async move { handshake.perform(server_side).await.map(|_| ()) }
+        });
+        client.write_all(&record).await.unwrap();
+        let (mut fallback, _) = dest.accept().await.unwrap();
+        let mut received = vec![0u8; record.len()];
+        fallback.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, record);
+        drop(fallback);
+        drop(client);
+        assert!(server.await.unwrap().is_err());
+        assert_eq!(handshake.verifier.stats().failures(VerifyFailure::NoKeyShare), 1);
+    }
+
+    #[tokio::test]
+    async fn test_early_data_skipped_before_finished() {
+        let suite = CipherSuite::Aes128GcmSha256;
+        let (hs_keys, _) = TlsKeys::derive_handshake_keys(suite, &[0x07; 32], &[0x00; 32]).unwrap();
+        let expected = TlsKeys::calculate_verify_data(suite, &hs_keys.client_traffic_secret, &[0x11; 32]).unwrap();
+        let finished = hs_keys.encrypt_client_record(0, &handshake_message(20, &expected), 22).unwrap();
+        // 用客户端早期数据密钥加密的 0-RTT 记录，服务端的握手密钥无法解密
+        let mut early = vec![0x17, 0x03, 0x03, 0x00, 0x30];
+        early.extend_from_slice(&[0xee; 0x30]);
+
+        let mut flight = early.clone();
+        flight.extend_from_slice(&CHANGE_CIPHER_SPEC);
+        flight.extend_from_slice(&early);
+        flight.extend_from_slice(&finished);
+        flight.extend_from_slice(b"after");
+
+        let (mut client, mut server) = tokio::io::duplex(65536);
+        client.write_all(&flight).await.unwrap();
+        let mut buf = BytesMut::new();
+        read_client_finished(&mut server, &mut buf, &hs_keys, &expected, true).await.unwrap();
+        assert_eq!(&buf[..], b"after");
+
+        // 没有提供 early_data 时同样的记录是错误
+        client.write_all(&flight).await.unwrap();
+        let mut buf = BytesMut::new();
+        assert!(read_client_finished(&mut server, &mut buf, &hs_keys, &expected, false).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cipher_suite_follows_client_preference() {
+        let secret = StaticSecret::random_from_rng(rand::thread_rng());
+        let public = PublicKey::from(&secret);
+        let record = hello_record_with_suites(&[0x66; 32], &secret, &[0x0a0a, 0x1303, 0x1302, 0x1301], &[hello_parser::GROUP_X25519], &[(hello_parser::GROUP_X25519, public.as_bytes())]);
+        let info = hello_parser::parse_client_hello(&record).unwrap().unwrap();
+        assert_eq!(info.cipher_suites, [0x0a0a, 0x1303, 0x1302, 0x1301]);
+
+        let (mut client, server_side) = tokio::io::duplex(65536);
+        let server = tokio::spawn(async move { handshake("127.0.0.1:1").perform(server_side).await.map(|_| ()) });
+        client.write_all(&record).await.unwrap();
+
+        // ServerHello 选择客户端最偏好的 TLS_CHACHA20_POLY1305_SHA256
+        let server_hello = read_record(&mut client).await;
+        assert_eq!(server_hello[76..78], [0x13, 0x03]);
+        assert_eq!(read_record(&mut client).await, CHANGE_CIPHER_SPEC);
+
+        // 客户端用同一套件推导握手密钥，能解密 EncryptedExtensions
+        let suite = CipherSuite::Chacha20Poly1305Sha256;
+        let server_public: [u8; 32] = server_hello[server_hello.len() - 32..].try_into().unwrap();
+        let shared = secret.diffie_hellman(&PublicKey::from(server_public));
+        let hello_hash = suite.hash_transcript(&[&info.message, &server_hello[5..]]);
+        let (hs_keys, _) = TlsKeys::derive_handshake_keys(suite, shared.as_bytes(), &hello_hash).unwrap();
+        let mut encrypted = read_record(&mut client).await;
+        let header: [u8; 5] = encrypted[..5].try_into().unwrap();
+        let (content_type, _) = hs_keys.decrypt_server_record(0, &header, &mut encrypted[5..]).unwrap();
+        assert_eq!((content_type, encrypted[5]), (22, 8));
+        drop(client);
+        assert!(server.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_handshake_failure_without_supported_cipher_suite() {
+        let secret = StaticSecret::random_from_rng(rand::thread_rng());
+        let public = PublicKey::from(&secret);
+        let record = hello_record_with_suites(&[0x77; 32], &secret, &[0x1304], &[hello_parser::GROUP_X25519], &[(hello_parser::GROUP_X25519, public.as_bytes())]);
+
+        let (mut client, server_side) = tokio::io::duplex(65536);
+        let server = tokio::spawn(async move { handshake("127.0.0.1:1").perform(server_side).await.map(|_| ()) });
+        client.write_all(&record).await.unwrap();
+        assert_eq!(read_record(&mut client).await, alert(ALERT_HANDSHAKE_FAILURE));
+        assert!(server.await.unwrap().is_err());
+    }
+
+    #[test]
+    fn test_certificate_verify_matches_certificate_key() {
+        let secret = StaticSecret::random_from_rng(rand::thread_rng());
+        let record = hello_record(&[0x88; 32], &secret, &[hello_parser::GROUP_X25519], &[(hello_parser::GROUP_X25519, PublicKey::from(&secret).as_bytes())]);
+        let info = hello_parser::parse_client_hello(&record).unwrap().unwrap();
+        assert_eq!(info.signature_algorithms, [hello_parser::SIGNATURE_ED25519, 0x0403, 0x0804]);
+
+        // 证书带 Reality 签名，CertificateVerify 能用证书中的 Ed25519 公钥验证
+        let auth_key = [0x5c; 32];
+        let (chain, key) = handshake("127.0.0.1:1").verifier.generate_reality_cert(&auth_key, "www.example.com").unwrap();
+        let transcript_hash = CipherSuite::Aes128GcmSha256.hash_transcript(&[&info.message]);
+        let verify = certificate_verify_message(&key, &transcript_hash).unwrap();
+        let public_key = client::verify_certificate(&certificate_message(&chain), &auth_key).unwrap();
+        client::verify_certificate_signature(&verify, &public_key, &transcript_hash).unwrap();
+        assert!(client::verify_certificate_signature(&verify, &public_key, &[0u8; 32]).is_err());
     }
 }
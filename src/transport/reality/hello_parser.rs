@@ -4,25 +4,88 @@ use bytes::{Buf, Bytes};
 pub struct ClientHelloInfo {
     pub session_id: Vec<u8>,
     pub client_random: [u8; 32],
+    /// 客户端提供的密码套件，按客户端的偏好顺序
+    pub cipher_suites: Vec<u16>,
+    /// 用于 Reality 认证的 X25519 公钥: 优先取 X25519 密钥份额，其次取 X25519MLKEM768 中的 X25519 部分
     pub public_key: Option<Vec<u8>>,
+    /// supported_groups 扩展中的组
+    pub supported_groups: Vec<u16>,
+    /// key_share 扩展中提供了密钥份额的组，按客户端的顺序
+    pub key_share_groups: Vec<u16>,
+    /// signature_algorithms 扩展中的签名算法，按客户端的偏好顺序
+    pub signature_algorithms: Vec<u16>,
     pub server_name: Option<String>,
+    /// 是否携带 pre_shared_key 扩展 (会话恢复尝试)
+    pub pre_shared_key: bool,
+    /// 是否携带 early_data 扩展 (之后可能跟着 0-RTT 数据，服务端从不接受)
+    pub early_data: bool,
+    /// 重组后的 ClientHello 握手消息 (含 4 字节握手头)，用作认证的 AAD 与握手摘要
+    pub message: Vec<u8>,
+    /// 承载 ClientHello 的记录占用的原始字节数，之后的数据属于后续记录
+    pub records_len: usize,
+}
+
+/// X25519 组
+pub const GROUP_X25519: u16 = 0x001d;
+/// X25519MLKEM768 组: 密钥份额为 ML-KEM-768 封装密钥 (1184 字节) + X25519 公钥 (32 字节)
+pub const GROUP_X25519_MLKEM768: u16 = 0x11ec;
+const MLKEM768_ENCAPSULATION_KEY_LEN: usize = 1184;
+
+/// ed25519 签名算法
+pub const SIGNATURE_ED25519: u16 = 0x0807;
+
+/// ClientHello 握手消息的长度上限
+pub const MAX_CLIENT_HELLO_LEN: usize = 16 * 1024;
+
+/// 把开头的连续 Handshake 记录重组成第一条完整的握手消息
+///
+/// 较大的 ClientHello (如携带后量子密钥份额) 可以被拆分到多个记录中。
+/// 返回 (握手消息, 承载它的记录占用的字节数)；数据还不够时返回 `Ok(None)`，
+/// 不是 Handshake 记录或消息超过 [`MAX_CLIENT_HELLO_LEN`] 时返回错误。
+pub fn reassemble_handshake(buf: &[u8]) -> Result<Option<(Vec<u8>, usize)>> {
+    let mut message = Vec::new();
+    let mut pos = 0;
+    while pos < buf.len() {
+        // TLS Record Header: Type(1) + Ver(2) + Len(2)
+        if buf[pos] != 0x16 {
+            return Err(anyhow!("不是 TLS Handshake 记录"));
+        }
+        if buf.len() < pos + 5 {
+            break;
+        }
+        let record_len = u16::from_be_bytes([buf[pos + 3], buf[pos + 4]]) as usize;
+        if buf.len() < pos + 5 + record_len {
+            break;
+        }
+        message.extend_from_slice(&buf[pos + 5..pos + 5 + record_len]);
+        pos += 5 + record_len;
+
+        // Handshake Header: Type(1) + Len(3)
+        if message.len() >= 4 {
+            let message_len = 4 + u32::from_be_bytes([0, message[1], message[2], message[3]]) as usize;
+            if message_len > MAX_CLIENT_HELLO_LEN {
+                return Err(anyhow!("ClientHello 过长: {} 字节", message_len));
+            }
+            if message.len() >= message_len {
+                message.truncate(message_len);
+                return Ok(Some((message, pos)));
+            }
+        }
+    }
+    Ok(None)
 }
 
 /// 解析 ClientHello 消息，提取 SessionID, Random, X25519 Public Key 和 SNI
 /// 注意：这是一个最小化实现，仅用于 Reality 预检
+///
+/// `buf` 为客户端发送的原始记录，ClientHello 被拆分到多个记录时先重组再解析。
 pub fn parse_client_hello(buf: &[u8]) -> Result<Option<ClientHelloInfo>> {
-    // 检查是否是 TLS Handshake (0x16)
-    if buf.len() < 5 || buf[0] != 0x16 {
-        return Ok(None); // 不是 TLS 握手
-    }
-
-    // TLS Record Header: Type(1) + Ver(2) + Len(2)
-    let record_len = u16::from_be_bytes([buf[3], buf[4]]) as usize;
-    if buf.len() < 5 + record_len {
-        return Ok(None); // 数据包不完整
-    }
+    // 不是 TLS 握手或数据不完整
+    let Ok(Some((message, records_len))) = reassemble_handshake(buf) else {
+        return Ok(None);
+    };
 
-    let mut cursor = &buf[5..]; // 跳过 Record Header
+    let mut cursor = &message[..];
 
     // Handshake Header: Type(1) + Len(3)
     if cursor.remaining() < 4 {
@@ -70,6 +133,7 @@ pub fn parse_client_hello(buf: &[u8]) -> Result<Option<ClientHelloInfo>> {
     if cursor.remaining() < cipher_suites_len {
         return Err(anyhow!("Short buffer for Cipher Suites"));
     }
+    let cipher_suites = cursor[..cipher_suites_len].chunks_exact(2).map(|id| u16::from_be_bytes([id[0], id[1]])).collect();
     cursor.advance(cipher_suites_len);
 
     // Compression Methods
@@ -88,8 +152,16 @@ pub fn parse_client_hello(buf: &[u8]) -> Result<Option<ClientHelloInfo>> {
         return Ok(Some(ClientHelloInfo {
             session_id,
             client_random,
+            cipher_suites,
             public_key: None,
+            supported_groups: Vec::new(),
+            key_share_groups: Vec::new(),
+            signature_algorithms: Vec::new(),
             server_name: None,
+            pre_shared_key: false,
+            early_data: false,
+            message,
+            records_len,
         }));
     }
 
@@ -100,7 +172,13 @@ pub fn parse_client_hello(buf: &[u8]) -> Result<Option<ClientHelloInfo>> {
     let mut extensions = &cursor[..extensions_len];
 
     let mut public_key = None;
+    let mut hybrid_public_key = None;
+    let mut supported_groups = Vec::new();
+    let mut key_share_groups = Vec::new();
+    let mut signature_algorithms = Vec::new();
     let mut server_name = None;
+    let mut pre_shared_key = false;
+    let mut early_data = false;
 
     while extensions.has_remaining() {
         if extensions.remaining() < 4 {
@@ -172,27 +250,62 @@ pub fn parse_client_hello(buf: &[u8]) -> Result<Option<ClientHelloInfo>> {
                     break;
                 }
 
-                // Group X25519 is 0x001d
-                if group == 0x001d && key_len == 32 {
-                    let mut key = vec![0u8; 32];
-                    shares.copy_to_slice(&mut key);
-                    public_key = Some(key);
-                    break; // Found it
-                } else {
-                    shares.advance(key_len);
+                key_share_groups.push(group);
+                let key = &shares[..key_len];
+                if group == GROUP_X25519 && key_len == 32 && public_key.is_none() {
+                    public_key = Some(key.to_vec());
+                } else if group == GROUP_X25519_MLKEM768 && key_len == MLKEM768_ENCAPSULATION_KEY_LEN + 32 {
+                    hybrid_public_key = Some(key[MLKEM768_ENCAPSULATION_KEY_LEN..].to_vec());
                 }
+                shares.advance(key_len);
             }
         }
 
-        if public_key.is_some() && server_name.is_some() {
-            break;
+        // supported_groups (0x000a)
+        if ext_type == 0x000a && ext_data.remaining() >= 2 {
+            let groups_len = ext_data.get_u16() as usize;
+            if ext_data.remaining() >= groups_len {
+                let mut groups = &ext_data[..groups_len];
+                while groups.remaining() >= 2 {
+                    supported_groups.push(groups.get_u16());
+                }
+            }
+        }
+
+        // signature_algorithms (0x000d)
+        if ext_type == 0x000d && ext_data.remaining() >= 2 {
+            let algorithms_len = ext_data.get_u16() as usize;
+            if ext_data.remaining() >= algorithms_len {
+                let mut algorithms = &ext_data[..algorithms_len];
+                while algorithms.remaining() >= 2 {
+                    signature_algorithms.push(algorithms.get_u16());
+                }
+            }
+        }
+
+        // pre_shared_key (0x0029) 只记录是否存在，身份与 binder 不解析
+        if ext_type == 0x0029 {
+            pre_shared_key = true;
+        }
+
+        // early_data (0x002a)
+        if ext_type == 0x002a {
+            early_data = true;
         }
     }
 
     Ok(Some(ClientHelloInfo {
         session_id,
         client_random,
-        public_key,
+        cipher_suites,
+        public_key: public_key.or(hybrid_public_key),
+        supported_groups,
+        key_share_groups,
+        signature_algorithms,
         server_name,
+        pre_shared_key,
+        early_data,
+        message,
+        records_len,
     }))
 }
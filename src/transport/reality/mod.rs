@@ -1,19 +1,27 @@
 mod auth;
 mod cert_fetch;
 mod cert_gen;
+pub mod cert_template;
+pub mod direct;
+pub mod client;
 pub mod crypto;
 mod handshake;
 mod server;
+pub mod stats;
 pub mod stream;
 mod tls;
 
 pub use auth::{RealityAuth, ServerHelloModifier};
 pub use cert_fetch::fetch_certificate;
+pub use client::{RealityClientConfig, RealityClientStream};
+pub use direct::DirectStream;
 pub use handshake::RealityHandshake;
 pub use server::RealityServer;
+pub use stats::{RealityStats, VerifyFailure};
 pub use tls::{ClientHello, ServerHello, TlsRecord};
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 /// Reality 配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,6 +38,98 @@ pub struct RealityConfig {
     pub short_ids: Vec<String>,
     /// TLS 指纹类型 (chrome, firefox, safari, etc.)
     pub fingerprint: String,
+    /// 回落到 dest 时发送的 PROXY protocol 版本，0 表示不发送
+    #[serde(default)]
+    pub xver: u8,
+    /// 客户端时间戳允许的最大偏差 (毫秒)，0 表示不检查
+    #[serde(default = "default_max_time_diff")]
+    pub max_time_diff: u64,
+    /// 重新获取 dest 证书模板的间隔 (秒)，0 表示不模仿 dest 的证书
+    #[serde(default = "default_cert_refresh_interval")]
+    pub cert_refresh_interval: u64,
+    /// 按 SNI 选择的回落目标，`*` 为默认条目
+    #[serde(default)]
+    pub dest_map: BTreeMap<String, String>,
+    /// 允许的最低客户端版本 (如 `1.8.0`)，为空表示不限制
+    #[serde(default)]
+    pub min_client_ver: Option<String>,
+    /// 允许的最高客户端版本，为空表示不限制
+    #[serde(default)]
+    pub max_client_ver: Option<String>,
+    /// TLS 握手实现
+    #[serde(default)]
+    pub backend: crate::config::RealityBackend,
+    /// 同时回落到 dest 的连接上限，0 表示不限制
+    #[serde(default = "default_max_fallbacks")]
+    pub max_fallbacks: usize,
+    /// 每个来源 IP 每秒允许的回落次数，0 表示不限制
+    #[serde(default)]
+    pub fallback_rate: u32,
+    /// 每个来源 IP 允许突发的回落次数
+    #[serde(default)]
+    pub fallback_burst: u32,
+    /// 到 dest 的预连接数，0 表示不预连接
+    #[serde(default)]
+    pub fallback_pool_size: usize,
+    /// 预连接的空闲时间上限 (秒)
+    #[serde(default = "default_fallback_pool_idle_timeout")]
+    pub fallback_pool_idle_timeout: u64,
+    /// 等待完整 ClientHello 的时间 (秒)，0 表示不限制
+    #[serde(default = "default_handshake_timeout")]
+    pub handshake_timeout: u64,
+    /// PEM 格式的 Ed25519 证书链，为空时生成临时证书
+    #[serde(default)]
+    pub certificate_file: Option<String>,
+    /// 证书对应的私钥 (PEM)
+    #[serde(default)]
+    pub key_file: Option<String>,
+}
+
+/// 解析 `x.y.z` 形式的客户端版本，每段为 0-255
+pub fn parse_client_version(version: &str) -> anyhow::Result<[u8; 3]> {
+    let parts = version
+        .split('.')
+        .map(str::parse::<u8>)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow::anyhow!("客户端版本 {:?} 无效: {}", version, e))?;
+    parts
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("客户端版本 {:?} 应为 x.y.z 形式", version))
+}
+
+/// 默认每 6 小时重新获取一次 dest 的证书
+pub const DEFAULT_CERT_REFRESH_INTERVAL: u64 = 6 * 3600;
+
+fn default_cert_refresh_interval() -> u64 {
+    DEFAULT_CERT_REFRESH_INTERVAL
+}
+
+/// 默认最多同时回落 256 个连接
+pub const DEFAULT_MAX_FALLBACKS: usize = 256;
+
+fn default_max_fallbacks() -> usize {
+    DEFAULT_MAX_FALLBACKS
+}
+
+/// 预连接默认最多空闲 30 秒，低于常见服务器的空闲超时
+pub const DEFAULT_FALLBACK_POOL_IDLE_TIMEOUT: u64 = 30;
+
+fn default_fallback_pool_idle_timeout() -> u64 {
+    DEFAULT_FALLBACK_POOL_IDLE_TIMEOUT
+}
+
+/// 默认最多等待 10 秒客户端发送完整的 ClientHello
+pub const DEFAULT_HANDSHAKE_TIMEOUT: u64 = 10;
+
+fn default_handshake_timeout() -> u64 {
+    DEFAULT_HANDSHAKE_TIMEOUT
+}
+
+/// 客户端时间戳默认允许的最大偏差 (毫秒)
+pub const DEFAULT_MAX_TIME_DIFF: u64 = 120_000;
+
+fn default_max_time_diff() -> u64 {
+    DEFAULT_MAX_TIME_DIFF
 }
 pub mod server_rustls;
 pub mod hello_parser;
@@ -1,15 +1,21 @@
 use anyhow::{anyhow, Result};
-use tokio::net::TcpStream;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tracing::{debug, info};
 use base64::{Engine as _, engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD}};
 
-use super::RealityConfig;
+use super::{RealityConfig, RealityHandshake, RealityStats};
 use super::server_rustls::RealityServerRustls;
+use crate::config::RealityBackend;
+use crate::network::{DirectCopy, InboundMetrics};
+use crate::server::AsyncStream;
 
 /// Reality 服务器 (Wrapper around RealityServerRustls)
 #[derive(Clone)]
 pub struct RealityServer {
     inner: RealityServerRustls,
+    backend: RealityBackend,
 }
 
 impl RealityServer {
@@ -33,24 +39,67 @@ impl RealityServer {
             return Err(anyhow!("Reality privateKey must be 32 bytes (got {})", private_key_bytes.len()));
         }
 
-        info!("Reality 服务器初始化成功 (Rustls backend)");
+        info!("Reality 服务器初始化成功 ({:?} backend)", config.backend);
         debug!("目标: {}", config.dest);
         debug!("指纹: {}", config.fingerprint);
 
-        let inner = RealityServerRustls::new(
+        let mut inner = RealityServerRustls::new(
             private_key_bytes, 
             Some(config.dest.clone()), 
             config.short_ids.clone(),
             config.server_names.clone()
-        )?;
+        )?
+        .with_xver(config.xver)
+        .with_max_time_diff(Duration::from_millis(config.max_time_diff))
+        .with_cert_refresh_interval(Duration::from_secs(config.cert_refresh_interval))
+        .with_dest_map(config.dest_map.clone())
+        .with_client_ver(
+            config.min_client_ver.as_deref().map(super::parse_client_version).transpose()?,
+            config.max_client_ver.as_deref().map(super::parse_client_version).transpose()?,
+        )
+        .with_fallback_limits(config.max_fallbacks, config.fallback_rate, config.fallback_burst)
+        .with_fallback_pool(config.fallback_pool_size, Duration::from_secs(config.fallback_pool_idle_timeout))
+        .with_handshake_timeout(Duration::from_secs(config.handshake_timeout));
+        if let (Some(cert), Some(key)) = (&config.certificate_file, &config.key_file) {
+            inner = inner.with_certificate_files(cert, key)?;
+        }
+
+        Ok(Self { inner, backend: config.backend })
+    }
+
+    /// 回落计数记录到该入站的统计中
+    pub fn with_metrics(mut self, metrics: Arc<InboundMetrics>) -> Self {
+        self.inner = self.inner.with_metrics(metrics);
+        self
+    }
+
+    /// 按原因累计的 Reality 验证失败次数
+    pub fn stats(&self) -> &RealityStats {
+        self.inner.stats()
+    }
 
-        Ok(Self { inner })
+    /// 在后台获取并定期刷新 dest 的证书模板 (需要在 tokio 运行时中调用)
+    pub fn spawn_cert_refresh(&self) {
+        self.inner.spawn_cert_refresh();
     }
 
-    /// 处理传入的 TLS 连接
-    pub async fn accept(&self, stream: TcpStream) -> Result<tokio_rustls::server::TlsStream<super::server_rustls::PrefixedStream<TcpStream>>> {
-        // 使用 Sniff-and-Dispatch 逻辑
-        self.inner.accept(stream).await
+    /// 处理传入的 TLS 连接，返回解密后的流与 vision 直接复制开关 (后端不支持时为 `None`)
+    pub async fn accept<S>(&self, stream: S) -> Result<(Box<dyn AsyncStream>, Option<Arc<DirectCopy>>)>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        match self.backend {
+            // 使用 Sniff-and-Dispatch 逻辑
+            RealityBackend::Rustls => {
+                let tls_stream = self.inner.accept(stream).await?;
+                let direct = tls_stream.direct_copy();
+                Ok((Box::new(tls_stream), Some(direct)))
+            }
+            RealityBackend::Native => {
+                let tls_stream = RealityHandshake::new(self.inner.clone()).perform(stream).await?;
+                Ok((Box::new(tls_stream), None))
+            }
+        }
     }
 }
 
@@ -67,6 +116,21 @@ mod tests {
             public_key: None,
             short_ids: vec!["0123456789abcdef".to_string()],
             fingerprint: "chrome".to_string(),
+            xver: 0,
+            max_time_diff: 120_000,
+            cert_refresh_interval: 0,
+            dest_map: Default::default(),
+            min_client_ver: None,
+            max_client_ver: None,
+            backend: RealityBackend::Rustls,
+            max_fallbacks: 0,
+            fallback_rate: 0,
+            fallback_burst: 0,
+            fallback_pool_size: 0,
+            fallback_pool_idle_timeout: 30,
+            handshake_timeout: 10,
+            certificate_file: None,
+            key_file: None,
         }
     }
 
@@ -1,26 +1,146 @@
-use std::sync::Arc;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, RwLock};
 use std::pin::Pin;
 use std::task::{Context, Poll};
-use tokio::net::TcpStream;
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
 use tokio_rustls::TlsAcceptor;
 use rustls::ServerConfig;
 use rustls::reality::RealityConfig;
 use anyhow::{Result, anyhow, bail};
-use tracing::{info, warn, error, debug};
+use tracing::{info, debug, warn};
 use rustls_pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
 use x25519_dalek::{PublicKey as X25519PublicKey, StaticSecret};
 use hkdf::Hkdf;
-use sha2::Sha256;
+use sha2::{Digest, Sha256};
 use aes_gcm::{Aes256Gcm, KeyInit, AeadInPlace, Nonce};
 use bytes::Buf;
 use ring::hmac;
 
+use super::cert_fetch::fetch_certificate;
+use super::cert_template::{CertTemplate, MimicIssuer};
+use super::direct::{DirectStream, RecordBoundary};
 use super::hello_parser::{self, ClientHelloInfo};
+use super::stats::{RealityStats, VerifyFailure};
+use crate::network::attempt::{self, AttemptOutcome};
+use crate::network::fallback::{self, DestPool, FallbackRateLimiter};
+use crate::network::InboundMetrics;
+use crate::utils::replay::ReplayFilter;
 
 pub struct RealityServerRustls {
     reality_config: Arc<RealityConfig>,
     server_names: Vec<String>,
+    /// 回落时发送的 PROXY protocol 版本，0 表示不发送
+    xver: u8,
+    /// 客户端时间戳允许的最大偏差，0 表示不检查
+    max_time_diff: Duration,
+    /// 已通过认证的 ClientHello，所有连接共享
+    replay: Arc<Mutex<ReplayFilter<[u8; 32]>>>,
+    /// 按 dest 证书模板签发临时证书的 CA，尚未获取到模板时为空
+    mimic: Arc<RwLock<Option<Arc<MimicIssuer>>>>,
+    /// 重新获取 dest 证书的间隔，0 表示不模仿
+    cert_refresh_interval: Duration,
+    /// 按 SNI 选择的回落目标
+    dest_map: Arc<BTreeMap<String, String>>,
+    /// 允许的客户端版本范围 (含两端)，为空表示不限制
+    min_client_ver: Option<[u8; 3]>,
+    max_client_ver: Option<[u8; 3]>,
+    /// 同时回落的连接上限，0 表示不限制
+    max_fallbacks: usize,
+    /// 按来源 IP 的回落速率限制，为空表示不限制
+    fallback_rate: Option<Arc<FallbackRateLimiter>>,
+    /// 到 dest 的预连接池，为空表示每次回落直接连接
+    dest_pool: Option<Arc<DestPool>>,
+    /// 回落计数所在的入站统计
+    metrics: Arc<InboundMetrics>,
+    /// 按原因累计的验证失败次数，所有连接共享
+    stats: Arc<RealityStats>,
+    /// 等待完整 ClientHello 的时间，0 表示不限制
+    handshake_timeout: Duration,
+    /// 最近生成的临时证书，所有连接共享
+    cert_cache: Arc<Mutex<Option<Arc<RealityCert>>>>,
+    /// 从文件加载的证书，设置后不再生成临时证书
+    certificate: Option<Arc<RealityCert>>,
+}
+
+/// 重放过滤最多记录的 ClientHello 数
+const MAX_REPLAY_ENTRIES: usize = 65536;
+
+/// Ed25519 SubjectPublicKeyInfo 前缀 (其后紧跟 32 字节公钥)
+const ED25519_SPKI_PREFIX: [u8; 12] = [0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+
+/// 在连接间复用的 Reality 证书，签名字段在每个连接中替换
+struct RealityCert {
+    /// 生成时使用的 SNI 与 dest 证书模板，从文件加载时为空
+    host: String,
+    issuer: Option<Arc<MimicIssuer>>,
+    cert_der: Vec<u8>,
+    /// 证书中的 Ed25519 公钥 (32 字节)
+    public_key: Vec<u8>,
+    key: PrivateKeyDer<'static>,
+    /// 叶证书之后的证书链
+    intermediates: Vec<CertificateDer<'static>>,
+}
+
+impl RealityCert {
+    /// 生成 Ed25519 证书: 有模板时复制其字段并由模板的 CA 签发，否则为 `host` 的自签名证书
+    fn generate(host: &str, issuer: Option<Arc<MimicIssuer>>) -> Result<Self> {
+        use rcgen::{CertificateParams, KeyPair, PKCS_ED25519};
+
+        let key_pair = KeyPair::generate(&PKCS_ED25519).map_err(|e| anyhow!("Key generation fail: {}", e))?;
+        let public_key = key_pair.public_key_raw().to_vec();
+        let mut params = match &issuer {
+            Some(issuer) => issuer.template().leaf_params(),
+            None => CertificateParams::new(vec![host.to_string()]),
+        };
+        params.alg = &PKCS_ED25519;
+        params.key_pair = Some(key_pair);
+
+        let cert = rcgen::Certificate::from_params(params).map_err(|e| anyhow!("Cert generation fail: {}", e))?;
+        let cert_der = match &issuer {
+            Some(issuer) => issuer.sign(&cert)?,
+            None => cert.serialize_der().map_err(|e| anyhow!("Cert serialization fail: {}", e))?,
+        };
+        let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(cert.serialize_private_key_der()));
+        let intermediates = issuer.iter().map(|issuer| issuer.ca_der().clone()).collect();
+        Ok(Self { host: host.to_string(), issuer, cert_der, public_key, key, intermediates })
+    }
+
+    /// 读取 PEM 格式的证书链与 Ed25519 私钥 (PKCS#8)
+    ///
+    /// Reality 客户端只校验叶证书的 Ed25519 公钥，叶证书原有的签名会被 Reality 签名覆盖。
+    fn from_pem_files(cert_path: &str, key_path: &str) -> Result<Self> {
+        let read = |path: &str| std::fs::read(path).map_err(|e| anyhow!("读取 Reality 证书文件 {} 失败: {}", path, e));
+        let mut chain = rustls_pemfile::certs(&mut &read(cert_path)?[..])
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("解析证书文件 {} 失败: {}", cert_path, e))?;
+        let key = rustls_pemfile::private_key(&mut &read(key_path)?[..])
+            .map_err(|e| anyhow!("解析私钥文件 {} 失败: {}", key_path, e))?
+            .ok_or_else(|| anyhow!("私钥文件 {} 中没有私钥", key_path))?;
+        if chain.is_empty() {
+            bail!("证书文件 {} 中没有证书", cert_path);
+        }
+
+        let PrivateKeyDer::Pkcs8(pkcs8) = &key else { bail!("Reality 证书私钥必须是 PKCS#8 格式的 Ed25519 私钥") };
+        let key_pair = ring::signature::Ed25519KeyPair::from_pkcs8_maybe_unchecked(pkcs8.secret_pkcs8_der())
+            .map_err(|e| anyhow!("Reality 证书私钥不是 Ed25519 私钥: {}", e))?;
+        let public_key = ring::signature::KeyPair::public_key(&key_pair).as_ref().to_vec();
+        let cert_der = chain.remove(0).to_vec();
+        let spki = [&ED25519_SPKI_PREFIX[..], &public_key].concat();
+        if !cert_der.windows(spki.len()).any(|window| window == spki) {
+            bail!("证书 {} 与私钥 {} 不匹配 (Reality 需要 Ed25519 证书)", cert_path, key_path);
+        }
+        Ok(Self { host: String::new(), issuer: None, cert_der, public_key, key, intermediates: chain })
+    }
+
+    fn matches(&self, host: &str, issuer: Option<&Arc<MimicIssuer>>) -> bool {
+        let same_issuer = match (&self.issuer, issuer) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        self.host == host && same_issuer
+    }
 }
 
 impl Clone for RealityServerRustls {
@@ -28,6 +148,22 @@ impl Clone for RealityServerRustls {
         Self {
             reality_config: Arc::clone(&self.reality_config),
             server_names: self.server_names.clone(),
+            xver: self.xver,
+            max_time_diff: self.max_time_diff,
+            replay: Arc::clone(&self.replay),
+            mimic: Arc::clone(&self.mimic),
+            cert_refresh_interval: self.cert_refresh_interval,
+            dest_map: Arc::clone(&self.dest_map),
+            min_client_ver: self.min_client_ver,
+            max_client_ver: self.max_client_ver,
+            max_fallbacks: self.max_fallbacks,
+            fallback_rate: self.fallback_rate.clone(),
+            dest_pool: self.dest_pool.clone(),
+            metrics: Arc::clone(&self.metrics),
+            stats: Arc::clone(&self.stats),
+            handshake_timeout: self.handshake_timeout,
+            cert_cache: Arc::clone(&self.cert_cache),
+            certificate: self.certificate.clone(),
         }
     }
 }
@@ -36,7 +172,11 @@ impl RealityServerRustls {
     pub fn new(private_key: Vec<u8>, dest: Option<String>, short_ids: Vec<String>, server_names: Vec<String>) -> Result<Self> {
         let mut short_ids_bytes = Vec::new();
         for id in short_ids {
+            // 空字符串表示客户端不带 shortId，不足 8 字节的 shortId 由客户端补零
             let b = hex::decode(&id).map_err(|e| anyhow!("Invalid shortId hex: {}", e))?;
+            if b.len() > 8 {
+                return Err(anyhow!("shortId 最长 8 字节 (16 个十六进制字符): {}", id));
+            }
             short_ids_bytes.push(b);
         }
 
@@ -50,46 +190,143 @@ impl RealityServerRustls {
         Ok(Self { 
             reality_config: Arc::new(reality_config),
             server_names,
+            xver: 0,
+            max_time_diff: Duration::from_millis(super::DEFAULT_MAX_TIME_DIFF),
+            replay: Arc::new(Mutex::new(ReplayFilter::new(MAX_REPLAY_ENTRIES))),
+            mimic: Arc::new(RwLock::new(None)),
+            cert_refresh_interval: Duration::ZERO,
+            dest_map: Arc::new(BTreeMap::new()),
+            min_client_ver: None,
+            max_client_ver: None,
+            max_fallbacks: 0,
+            fallback_rate: None,
+            dest_pool: None,
+            metrics: InboundMetrics::new("reality", None),
+            stats: Arc::new(RealityStats::default()),
+            handshake_timeout: Duration::from_secs(super::DEFAULT_HANDSHAKE_TIMEOUT),
+            cert_cache: Arc::new(Mutex::new(None)),
+            certificate: None,
         })
     }
 
-    pub async fn accept(&self, mut stream: TcpStream) -> Result<tokio_rustls::server::TlsStream<PrefixedStream<TcpStream>>> {
-        let mut buffer = Vec::with_capacity(2048);
-        while buffer.len() < 5 {
-            let mut chunk = [0u8; 1024];
-            let n = stream.read(&mut chunk).await?;
-            if n == 0 { bail!("Connection closed early"); }
-            buffer.extend_from_slice(&chunk[..n]);
-        }
-
-        let needed = if buffer[0] == 0x16 { 5 + u16::from_be_bytes([buffer[3], buffer[4]]) as usize } else { buffer.len() };
-        while buffer.len() < needed && buffer.len() < 16384 {
-             let mut chunk = [0u8; 1024];
-             let n = stream.read(&mut chunk).await?;
-             if n == 0 { break; }
-             buffer.extend_from_slice(&chunk[..n]);
-        }
-
-        if let Ok(Some(info)) = hello_parser::parse_client_hello(&buffer) {
-            // SNI 验证逻辑
-            let sni_valid = if self.server_names.is_empty() {
-                true // 如果没配置 server_names，则允许所有（或者应该默认不允许？为了安全推荐配置）
-            } else if let Some(sni) = &info.server_name {
-                self.server_names.iter().any(|s| s == sni)
-            } else {
-                false // 必须携带 SNI
-            };
+    /// 回落到 dest 时先发送指定版本的 PROXY protocol 头 (1 或 2)
+    pub fn with_xver(mut self, xver: u8) -> Self {
+        self.xver = xver;
+        self
+    }
+
+    /// 客户端时间戳与服务器时间允许的最大偏差，超出时按非 Reality 客户端回落，0 表示不检查
+    pub fn with_max_time_diff(mut self, max_time_diff: Duration) -> Self {
+        self.max_time_diff = max_time_diff;
+        self
+    }
+
+    /// 只接受版本在 `[min, max]` 内的客户端，其余按非 Reality 客户端回落，`None` 表示不限制
+    pub fn with_client_ver(mut self, min: Option<[u8; 3]>, max: Option<[u8; 3]>) -> Self {
+        self.min_client_ver = min;
+        self.max_client_ver = max;
+        self
+    }
 
-            if !sni_valid {
-                warn!("Reality SNI mismatch: {:?} (Allowed: {:?})", info.server_name, self.server_names);
-                // Fallthrough to fallback (don't verify reality)
-            } else if let Some((offset, auth_key)) = self.verify_client_reality(&info, &buffer) {
-                let dest_str = self.reality_config.dest.as_deref().unwrap_or("www.microsoft.com");
-                let dest_host = dest_str.split(':').next().unwrap_or("www.microsoft.com");
+    /// 按 SNI 选择回落目标，见 [`fallback_dest`](Self::fallback_dest)
+    pub fn with_dest_map(mut self, dest_map: BTreeMap<String, String>) -> Self {
+        self.dest_map = Arc::new(dest_map);
+        self
+    }
+
+    /// 限制回落: 最多同时回落 `max` 个连接，每个来源 IP 每秒最多 `rate` 次 (可突发 `burst` 次)，0 表示不限制
+    pub fn with_fallback_limits(mut self, max: usize, rate: u32, burst: u32) -> Self {
+        self.max_fallbacks = max;
+        self.fallback_rate = (rate > 0).then(|| Arc::new(FallbackRateLimiter::new(rate, burst)));
+        self
+    }
+
+    /// 为 dest 保持最多 `size` 条预先建立的连接，空闲超过 `idle_ttl` 的连接被丢弃；0 表示不使用
+    ///
+    /// 只用于 dest 本身 (destMap 中的其他目标与 unix socket 仍直接连接)。
+    pub fn with_fallback_pool(mut self, size: usize, idle_ttl: Duration) -> Self {
+        let dest = self.dest().to_string();
+        self.dest_pool = (size > 0 && !dest.starts_with(fallback::UNIX_SCHEME)).then(|| DestPool::new(dest, size, idle_ttl));
+        self
+    }
+
+    /// 等待客户端发送完整 ClientHello 的时间，见 [`read_client_hello`](Self::read_client_hello)
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
 
+    /// 使用 PEM 文件中的 Ed25519 证书与私钥，代替生成的临时证书，见 [`generate_reality_cert`](Self::generate_reality_cert)
+    pub fn with_certificate_files(mut self, cert_path: &str, key_path: &str) -> Result<Self> {
+        self.certificate = Some(Arc::new(RealityCert::from_pem_files(cert_path, key_path)?));
+        Ok(self)
+    }
+
+    /// 回落计数记录到指定入站的统计中
+    pub fn with_metrics(mut self, metrics: Arc<InboundMetrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// 重新获取 dest 证书模板的间隔，见 [`spawn_cert_refresh`](Self::spawn_cert_refresh)
+    pub fn with_cert_refresh_interval(mut self, interval: Duration) -> Self {
+        self.cert_refresh_interval = interval;
+        self
+    }
+
+    /// 在后台获取 dest 的证书作为临时证书的模板，之后按间隔刷新，间隔为 0 时不启动
+    ///
+    /// 获取失败时只记录警告: 已有模板时继续使用，没有模板时使用普通的自签名证书。
+    /// 所有克隆都释放后任务在下一次刷新时退出。
+    pub fn spawn_cert_refresh(&self) {
+        if self.cert_refresh_interval.is_zero() {
+            return;
+        }
+        let dest = self.dest().to_string();
+        if dest.starts_with(fallback::UNIX_SCHEME) {
+            info!("Reality: dest {} 是 unix socket，不获取证书模板", dest);
+            return;
+        }
+        // 按客户端使用的 SNI 获取证书，通配符条目无法作为 SNI
+        let server_name = self
+            .server_names
+            .iter()
+            .find(|name| !name.starts_with("*."))
+            .cloned()
+            .unwrap_or_else(|| dest.split(':').next().unwrap_or_default().to_string());
+        let slot = Arc::downgrade(&self.mimic);
+        let interval = self.cert_refresh_interval;
+        tokio::spawn(async move {
+            loop {
+                let result = async { CertTemplate::from_der(&fetch_certificate(&dest, &server_name).await?)?.issuer() }.await;
+                let Some(slot) = slot.upgrade() else { break };
+                match result {
+                    Ok(issuer) => {
+                        info!("Reality: 已获取 {} 的证书模板 (SNI: {})", dest, server_name);
+                        *slot.write().unwrap() = Some(Arc::new(issuer));
+                    }
+                    Err(e) if slot.read().unwrap().is_some() => {
+                        warn!("Reality: 刷新 {} 的证书失败，继续使用之前的模板: {}", dest, e);
+                    }
+                    Err(e) => warn!("Reality: 获取 {} 的证书失败，使用自签名证书: {}", dest, e),
+                }
+                drop(slot);
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+
+    pub async fn accept<S>(&self, mut stream: S) -> Result<DirectStream<S>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let buffer = self.read_client_hello(&mut stream).await?;
+        let hello = self.parse_client_hello(&buffer);
+        if let Some(info) = &hello {
+            if let Some((offset, auth_key)) = self.verify_client_reality(info) {
                 info!("Reality: Verified client (Offset {}), generating dynamic signature-certificate", offset);
                 
-                let (cert, key) = self.generate_reality_cert(&auth_key, dest_host)?;
+                let (chain, key) = self.generate_reality_cert(&auth_key, self.cert_host())?;
 
                 let mut conn_reality_config = (*self.reality_config).clone();
                 conn_reality_config.private_key = auth_key.to_vec();
@@ -99,38 +336,152 @@ impl RealityServerRustls {
                 let mut config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
                     .with_safe_default_protocol_versions()?
                     .with_no_client_auth()
-                    .with_single_cert(vec![cert], key)
+                    .with_single_cert(chain, key)
                     .map_err(|e| anyhow!("Config build fail: {}", e))?;
                 config.reality_config = Some(Arc::new(conn_reality_config));
 
                 let acceptor = TlsAcceptor::from(Arc::new(config));
-                let prefixed = PrefixedStream::new(buffer, stream);
+                // 按记录边界交给 rustls，以便 vision 切换到直接复制
+                let prefixed = RecordBoundary::new(PrefixedStream::new(buffer, stream));
                 
                 match acceptor.accept(prefixed).await {
                     Ok(tls) => {
                         info!("Reality handshake successful");
-                        return Ok(tls);
+                        return Ok(DirectStream::new(tls));
                     }
                     Err(e) => {
-                        error!("Reality TLS handshake failed: {}", e);
+                        crate::log_limited!(error, "reality_handshake", "Reality TLS handshake failed: {}", e);
                         bail!("Handshake failure");
                     }
                 }
             }
         }
 
-        let dest = self.reality_config.dest.as_deref().unwrap_or("www.microsoft.com:443");
-        debug!("Non-Reality client or SNI mismatch, falling back to {}", dest);
-        self.fallback(stream, &buffer, dest).await?;
+        self.reject(stream, &buffer, hello.as_ref()).await?;
         bail!("Fallback total");
     }
 
-    fn verify_client_reality(&self, info: &ClientHelloInfo, full_hello: &[u8]) -> Option<(usize, [u8; 32])> {
-        if info.session_id.len() != 32 || info.public_key.is_none() { return None; }
+    /// 读取客户端的首包，直到 ClientHello 完整或确定不是 TLS
+    ///
+    /// 超过握手超时仍不完整时，已收到的数据交给回落处理，一个字节都没收到时返回错误 (关闭连接)。
+    pub(super) async fn read_client_hello<S: AsyncRead + Unpin>(&self, stream: &mut S) -> Result<Vec<u8>> {
+        let mut buffer = Vec::with_capacity(2048);
+        let read = fill_client_hello(stream, &mut buffer);
+        if self.handshake_timeout.is_zero() {
+            read.await?;
+        } else if let Ok(read) = tokio::time::timeout(self.handshake_timeout, read).await {
+            read?;
+        } else if buffer.is_empty() {
+            bail!("握手超时: {:?} 内没有收到任何数据", self.handshake_timeout);
+        } else {
+            debug!("握手超时: {:?} 内只收到 {} 字节，按非 Reality 客户端处理", self.handshake_timeout, buffer.len());
+        }
+        Ok(buffer)
+    }
+
+    /// 按原因累计的验证失败次数
+    pub fn stats(&self) -> &RealityStats {
+        &self.stats
+    }
+
+    /// 解析首包中的 ClientHello，不是 TLS ClientHello 时返回 `None` 并计入失败原因
+    pub(super) fn parse_client_hello(&self, buffer: &[u8]) -> Option<ClientHelloInfo> {
+        let hello = hello_parser::parse_client_hello(buffer).ok().flatten();
+        if hello.is_none() {
+            self.stats.record(VerifyFailure::NotTls);
+        }
+        hello
+    }
+
+    /// 未通过认证的连接: 按 SNI 选择回落目标，把已读取的数据连同后续流量一起转发
+    pub(super) async fn reject<S>(&self, stream: S, buffer: &[u8], hello: Option<&ClientHelloInfo>) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let dest = self.fallback_dest(hello.and_then(|info| info.server_name.as_deref()));
+        // 超出限制时直接关闭客户端连接，不连接 dest
+        let source = attempt::source_addr();
+        if let (Some(limiter), Some(source)) = (&self.fallback_rate, source) {
+            if !limiter.allow(source.ip()) {
+                self.metrics.reject_fallback();
+                attempt::record(AttemptOutcome::Overloaded, None);
+                crate::log_limited!(warn, "reality_fallback_rate", "⚠️ 来自 {} 的回落超出速率限制，关闭连接", source.ip());
+                return Ok(());
+            }
+        }
+        let Some(_guard) = self.metrics.try_begin_fallback(self.max_fallbacks) else {
+            attempt::record(AttemptOutcome::Overloaded, None);
+            crate::log_limited!(warn, "reality_fallback_cap", "⚠️ 入站 {} 同时回落的连接数已达上限 {}，关闭连接", self.metrics.name(), self.max_fallbacks);
+            return Ok(());
+        };
+        debug!("Non-Reality client or SNI mismatch, falling back to {}", dest);
+        attempt::record(AttemptOutcome::Fallback, None);
+        self.fallback(stream, buffer, dest).await
+    }
+
+    /// 伪装目标 dest (`host:port`)
+    fn dest(&self) -> &str {
+        self.reality_config.dest.as_deref().unwrap_or("www.microsoft.com:443")
+    }
+
+    /// 自签名临时证书使用的主机名: dest 的主机名，dest 为 unix socket 时为第一个非通配的 serverNames 条目
+    pub(super) fn cert_host(&self) -> &str {
+        let dest = self.dest();
+        if dest.starts_with(fallback::UNIX_SCHEME) {
+            return self.server_names.iter().find(|name| !name.starts_with("*.")).map_or("localhost", String::as_str);
+        }
+        dest.split(':').next().unwrap_or(dest)
+    }
+
+    /// SNI 是否在 serverNames 中，未携带 SNI 时不通过
+    fn server_name_allowed(&self, sni: Option<&str>) -> bool {
+        sni.is_some_and(|sni| self.server_names.iter().any(|name| server_name_matches(name, sni)))
+    }
+
+    /// 回落目标: destMap 中与 SNI 匹配的条目 (精确匹配优先于通配符)，其次为 `*` 条目，最后为 dest
+    fn fallback_dest(&self, sni: Option<&str>) -> &str {
+        let by_sni = sni.and_then(|sni| {
+            let mut matches = self
+                .dest_map
+                .iter()
+                .filter(|(name, _)| name.as_str() != "*" && server_name_matches(name, sni));
+            let first = matches.clone().find(|(name, _)| !name.starts_with("*."));
+            first.or_else(|| matches.next())
+        });
+        by_sni
+            .map(|(_, dest)| dest.as_str())
+            .or_else(|| self.dest_map.get("*").map(String::as_str))
+            .or(self.reality_config.dest.as_deref())
+            .unwrap_or("www.microsoft.com:443")
+    }
+
+    pub(super) fn verify_client_reality(&self, info: &ClientHelloInfo) -> Option<(usize, [u8; 32])> {
+        if info.pre_shared_key {
+            debug!("Reality: 客户端尝试 PSK 会话恢复，服务端不支持恢复，按完整握手处理");
+        }
+        if !self.server_name_allowed(info.server_name.as_deref()) {
+            self.stats.record(VerifyFailure::SniMismatch);
+            crate::log_limited!(warn, "reality_sni_mismatch", "Reality SNI mismatch: {:?} (Allowed: {:?})", info.server_name, self.server_names);
+            return None;
+        }
+        let Some(client_pub) = info.public_key.as_deref().and_then(|key| <[u8; 32]>::try_from(key).ok()) else {
+            self.stats.record(VerifyFailure::NoKeyShare);
+            crate::log_limited!(
+                debug,
+                "reality_no_key_share",
+                "Reality: ClientHello 没有可用的 X25519 密钥份额，密钥份额组 {:04x?}，supported_groups {:04x?}",
+                info.key_share_groups,
+                info.supported_groups
+            );
+            return None;
+        };
+        if info.session_id.len() != 32 {
+            self.stats.record(VerifyFailure::InvalidSessionId);
+            return None;
+        }
         
         let mut server_priv = [0u8; 32];
         server_priv.copy_from_slice(&self.reality_config.private_key);
-        let client_pub: [u8; 32] = info.public_key.as_ref()?.as_slice().try_into().ok()?;
         
         let shared = StaticSecret::from(server_priv).diffie_hellman(&X25519PublicKey::from(client_pub));
         
@@ -142,60 +493,168 @@ impl RealityServerRustls {
         let cipher = Aes256Gcm::new(aes_gcm::Key::<Aes256Gcm>::from_slice(&auth_key));
         let nonce = Nonce::from_slice(&info.client_random[20..32]);
 
-        let handshake_msg = if full_hello[0] == 0x16 { &full_hello[5..] } else { full_hello };
-        let mut aad = handshake_msg.to_vec();
+        // AAD 为重组后的 ClientHello 消息，其中 SessionID 置零
+        let mut aad = info.message.clone();
         if let Some(pos) = hex::encode(&aad).find(&hex::encode(&info.session_id)).map(|p| p/2) {
             for i in 0..32 { if pos + i < aad.len() { aad[pos + i] = 0; } }
         }
 
         let mut buf = info.session_id.clone();
-        if cipher.decrypt_in_place(nonce, &aad, &mut buf).is_err() { return None; }
-        if buf.len() < 16 { return None; }
+        if cipher.decrypt_in_place(nonce, &aad, &mut buf).is_err() || buf.len() < 16 {
+            self.stats.record(VerifyFailure::DecryptFailed);
+            return None;
+        }
+
+        let Some(offset) = self.reality_config.short_ids.iter().find_map(|sid| {
+            if short_id_matches(sid, &buf[4..12]) {
+                Some(4)
+            } else if short_id_matches(sid, &buf[8..16]) {
+                Some(8)
+            } else {
+                None
+            }
+        }) else {
+            self.stats.record(VerifyFailure::ShortIdMismatch);
+            crate::log_limited!(warn, "reality_short_id", "Reality shortId 不匹配: {}", hex::encode(&buf[8..16]));
+            return None;
+        };
+
+        // 载荷前 3 字节为客户端版本
+        let version = [buf[0], buf[1], buf[2]];
+        debug!("Reality 客户端版本: {}.{}.{}", version[0], version[1], version[2]);
+        if self.min_client_ver.is_some_and(|min| version < min) || self.max_client_ver.is_some_and(|max| version > max) {
+            self.stats.record(VerifyFailure::ClientVersion);
+            crate::log_limited!(
+                warn,
+                "reality_client_ver",
+                "Reality 客户端版本 {}.{}.{} 不在允许范围内",
+                version[0],
+                version[1],
+                version[2]
+            );
+            return None;
+        }
 
-        for sid in &self.reality_config.short_ids {
-            if sid == &buf[4..12] { return Some((4, auth_key)); }
-            if sid == &buf[8..16] { return Some((8, auth_key)); }
+        // 时间戳紧挨在 shortId 之前
+        let timestamp = u32::from_be_bytes(buf[offset - 4..offset].try_into().ok()?);
+        if let Some(skew) = self.clock_skew(timestamp) {
+            self.stats.record(VerifyFailure::StaleTimestamp);
+            crate::log_limited!(
+                warn,
+                "reality_time_diff",
+                "Reality 客户端时间偏差过大: {}s (允许 {}ms)，请检查客户端与服务器的 NTP 同步",
+                skew,
+                self.max_time_diff.as_millis()
+            );
+            return None;
         }
-        None
+
+        if !self.check_replay(info, timestamp) {
+            self.stats.record(VerifyFailure::Replay);
+            crate::log_limited!(warn, "reality_replay", "Reality ClientHello 重放，按非 Reality 客户端回落");
+            return None;
+        }
+        Some((offset, auth_key))
     }
 
-    fn generate_reality_cert(&self, auth_key: &[u8; 32], host: &str) -> Result<(CertificateDer<'static>, PrivateKeyDer<'static>)> {
-        use rcgen::{CertificateParams, KeyPair, PKCS_ED25519};
+    /// 记录已通过认证的 ClientHello，同一个 ClientHello 在有效期内再次出现时返回 false
+    fn check_replay(&self, info: &ClientHelloInfo, timestamp: u32) -> bool {
+        let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) else { return true };
+        let now = now.as_secs();
+        // 时间戳检查之外的重放仍需拦截，此时按默认偏差保留记录
+        let window = if self.max_time_diff.is_zero() {
+            Duration::from_millis(super::DEFAULT_MAX_TIME_DIFF)
+        } else {
+            self.max_time_diff
+        };
+        let expires = (timestamp as u64).max(now) + window.as_secs_f64().ceil() as u64;
 
-        let key_pair = KeyPair::generate(&PKCS_ED25519).map_err(|e| anyhow!("Key generation fail: {}", e))?;
-        let pub_key_raw = key_pair.public_key_raw().to_vec();
-        let mut params = CertificateParams::new(vec![host.to_string()]);
-        params.alg = &PKCS_ED25519;
-        params.key_pair = Some(key_pair);
-        
-        let cert = rcgen::Certificate::from_params(params).map_err(|e| anyhow!("Cert generation fail: {}", e))?;
-        let mut cert_der = cert.serialize_der().map_err(|e| anyhow!("Cert serialization fail: {}", e))?;
-        let priv_key_der = cert.serialize_private_key_der();
-        
-        // Reality Signature: HMAC-SHA512(AuthKey, RawPublicKey)
-        let total_len = cert_der.len();
-        if total_len < 64 {
-            bail!("CERT DER too short");
+        let mut hasher = Sha256::new();
+        hasher.update(info.client_random);
+        hasher.update(&info.session_id);
+        let digest: [u8; 32] = hasher.finalize().into();
+        self.replay.lock().unwrap().insert(digest, expires, now)
+    }
+
+    /// 客户端时间戳超出允许偏差时返回偏差秒数 (正数表示客户端时间超前)
+    fn clock_skew(&self, timestamp: u32) -> Option<i64> {
+        if self.max_time_diff.is_zero() {
+            return None;
         }
-        let sig_pos = total_len - 64;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs() as i64;
+        let skew = timestamp as i64 - now;
+        (Duration::from_secs(skew.unsigned_abs()) > self.max_time_diff).then_some(skew)
+    }
+
+    /// 生成临时证书链: 有 dest 证书模板时复制其字段并附上签发的 CA，否则为 `host` 的自签名证书
+    ///
+    /// 证书与密钥只在首次使用、`host` 或模板变化时生成 (配置了证书文件时使用文件中的证书)，
+    /// 每个连接只把签名字段替换为 Reality 签名 HMAC-SHA512(AuthKey, 证书公钥)。
+    pub(super) fn generate_reality_cert(&self, auth_key: &[u8; 32], host: &str) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+        let cert = match &self.certificate {
+            Some(cert) => Arc::clone(cert),
+            None => self.cached_cert(host)?,
+        };
+
+        // Reality Signature: HMAC-SHA512(AuthKey, RawPublicKey)，覆盖 DER 末尾的签名
+        let mut cert_der = cert.cert_der.clone();
+        let sig_pos = cert_der.len() - 64;
         let ring_key = hmac::Key::new(hmac::HMAC_SHA512, auth_key);
-        let signature = hmac::sign(&ring_key, &pub_key_raw);
-        let sig_bytes = signature.as_ref(); 
+        cert_der[sig_pos..].copy_from_slice(hmac::sign(&ring_key, &cert.public_key).as_ref());
 
-        // Overwrite the signature at the end of DER
-        cert_der[sig_pos..].copy_from_slice(sig_bytes);
-        
-        std::eprintln!("REALITY_STDERR: Generated dynamic cert successfully.");
+        let mut chain = vec![CertificateDer::from(cert_der)];
+        chain.extend(cert.intermediates.iter().cloned());
+        Ok((chain, cert.key.clone_key()))
+    }
+
+    /// 当前 `host` 与模板对应的缓存证书，没有时生成
+    fn cached_cert(&self, host: &str) -> Result<Arc<RealityCert>> {
+        let mimic = self.mimic.read().unwrap().clone();
+        let mut cache = self.cert_cache.lock().unwrap();
+        if let Some(cert) = cache.as_ref().filter(|cert| cert.matches(host, mimic.as_ref())) {
+            return Ok(Arc::clone(cert));
+        }
+        let cert = Arc::new(RealityCert::generate(host, mimic)?);
+        debug!("Reality: 已生成 {} 的临时证书", host);
+        *cache = Some(Arc::clone(&cert));
+        Ok(cert)
+    }
 
-        Ok((CertificateDer::from(cert_der), PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(priv_key_der))))
+    async fn fallback<S: AsyncRead + AsyncWrite + Unpin>(&self, stream: S, prefix: &[u8], dest: &str) -> Result<()> {
+        // 真实客户端地址来自连接尝试记录 (已按入站的 PROXY protocol 解析)
+        let (client_addr, local_addr) = (attempt::source_addr(), attempt::local_addr());
+        match &self.dest_pool {
+            Some(pool) if pool.dest() == dest => {
+                let target = pool.connect().await?;
+                fallback::relay_over(stream, target, dest, self.xver, prefix, client_addr, local_addr).await
+            }
+            _ => fallback::relay_to(stream, dest, self.xver, prefix, client_addr, local_addr).await,
+        }
     }
+}
 
-    async fn fallback(&self, mut stream: TcpStream, prefix: &[u8], dest: &str) -> Result<()> {
-        let mut dest_stream = TcpStream::connect(dest).await?;
-        dest_stream.write_all(prefix).await?;
-        tokio::io::copy_bidirectional(&mut stream, &mut dest_stream).await?;
-        Ok(())
+/// 读取第一条 TLS 记录 (ClientHello)，不是 TLS 握手时返回已读到的数据
+/// 读到 `buffer` 中的 ClientHello 完整为止
+///
+/// ClientHello 可能被拆分到多个记录中；首字节不是 TLS Handshake 时立即返回，交给回落处理。
+async fn fill_client_hello<S: AsyncRead + Unpin>(stream: &mut S, buffer: &mut Vec<u8>) -> Result<()> {
+    while matches!(hello_parser::reassemble_handshake(buffer), Ok(None))
+        && buffer.len() < hello_parser::MAX_CLIENT_HELLO_LEN + 5
+    {
+        let mut chunk = [0u8; 1024];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            if buffer.len() < 5 { bail!("Connection closed early"); }
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..n]);
     }
+    Ok(())
+}
+
+/// 客户端把 shortId 补零到 8 字节发送: 前缀与配置一致且其余字节为零才算匹配，空 shortId 对应全零
+fn short_id_matches(short_id: &[u8], field: &[u8]) -> bool {
+    field.starts_with(short_id) && field[short_id.len()..].iter().all(|&b| b == 0)
 }
 
 pub struct PrefixedStream<S> { prefix: std::io::Cursor<Vec<u8>>, inner: S }
@@ -217,3 +676,610 @@ impl<S: AsyncWrite + Unpin> AsyncWrite for PrefixedStream<S> {
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> { Pin::new(&mut self.inner).poll_flush(cx) }
     fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> { Pin::new(&mut self.inner).poll_shutdown(cx) }
 }
+
+/// SNI 是否与配置的名称匹配: 不区分大小写，`*.example.com` 匹配其任意子域名
+fn server_name_matches(pattern: &str, sni: &str) -> bool {
+    let sni = sni.trim_end_matches('.').to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => sni
+            .strip_suffix(&suffix.to_ascii_lowercase())
+            .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+        None => pattern.eq_ignore_ascii_case(&sni),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::reality::client;
+
+    const SERVER_KEY: [u8; 32] = [0x24; 32];
+    const SHORT_ID: [u8; 8] = [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef];
+
+    fn server(server_names: &[&str]) -> RealityServerRustls {
+        server_with_dest(server_names, "127.0.0.1:443")
+    }
+
+    fn server_with_dest(server_names: &[&str], dest: &str) -> RealityServerRustls {
+        RealityServerRustls::new(
+            SERVER_KEY.to_vec(),
+            Some(dest.to_string()),
+            vec![hex::encode(SHORT_ID)],
+            server_names.iter().map(|s| s.to_string()).collect(),
+        )
+        .unwrap()
+    }
+
+    fn now() -> u32 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as u32
+    }
+
+    /// 按客户端的方式构造带 Reality 认证信息的 ClientHello 记录
+    fn reality_hello(server_name: &str) -> Vec<u8> {
+        reality_hello_at(server_name, now())
+    }
+
+    /// 同 [`reality_hello`]，时间戳由调用方指定
+    fn reality_hello_at(server_name: &str, timestamp: u32) -> Vec<u8> {
+        reality_hello_with(server_name, timestamp, &SHORT_ID)
+    }
+
+    /// 同 [`reality_hello`]，时间戳与 shortId 由调用方指定
+    fn reality_hello_with(server_name: &str, timestamp: u32, short_id: &[u8]) -> Vec<u8> {
+        reality_hello_edited(server_name, timestamp, short_id, |_| {})
+    }
+
+    /// 同 [`reality_hello_with`]，在写入 SessionID 之前由 `edit` 修改 ClientHello 消息
+    fn reality_hello_edited(server_name: &str, timestamp: u32, short_id: &[u8], edit: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+        let secret = StaticSecret::random_from_rng(rand::thread_rng());
+        let public = X25519PublicKey::from(&secret);
+        let mut random = [0u8; 32];
+        rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut random);
+
+        let mut hello = client::build_client_hello(&random, public.as_bytes(), server_name);
+        edit(&mut hello);
+        let server_public = X25519PublicKey::from(&StaticSecret::from(SERVER_KEY));
+        let auth_key = client::derive_auth_key(secret.diffie_hellman(&server_public).as_bytes(), &random);
+        let session_id = client::seal_session_id(&auth_key, &random, &hello, timestamp, short_id).unwrap();
+        hello[client::SESSION_ID_OFFSET..client::SESSION_ID_OFFSET + 32].copy_from_slice(&session_id);
+
+        let mut record = vec![0x16, 0x03, 0x01];
+        record.extend_from_slice(&(hello.len() as u16).to_be_bytes());
+        record.extend_from_slice(&hello);
+        record
+    }
+
+    fn verifies(server: &RealityServerRustls, server_name: &str) -> bool {
+        verifies_record(server, &reality_hello(server_name))
+    }
+
+    fn verifies_record(server: &RealityServerRustls, record: &[u8]) -> bool {
+        let info = hello_parser::parse_client_hello(record).unwrap().unwrap();
+        server.verify_client_reality(&info).is_some()
+    }
+
+    #[test]
+    fn test_server_name_check() {
+        let server = server(&["www.example.com", "*.apple.com"]);
+
+        assert!(verifies(&server, "www.example.com"));
+        assert!(verifies(&server, "WWW.Example.COM"));
+        assert!(verifies(&server, "www.apple.com"));
+        assert!(verifies(&server, "a.b.apple.com"));
+
+        // 认证信息同样有效，只是 SNI 不在 serverNames 中
+        assert!(!verifies(&server, "example.com"));
+        assert!(!verifies(&server, "www.example.org"));
+        assert!(!verifies(&server, "apple.com"));
+        assert!(!verifies(&server, "badapple.com"));
+        assert!(!server.server_name_allowed(None));
+    }
+
+    #[test]
+    fn test_timestamp_window() {
+        let server = server(&["www.example.com"]);
+        let hello_at = |offset: i64| reality_hello_at("www.example.com", (now() as i64 + offset) as u32);
+
+        assert!(verifies_record(&server, &hello_at(0)));
+        assert!(verifies_record(&server, &hello_at(-100)));
+        assert!(verifies_record(&server, &hello_at(100)));
+        // 默认允许 ±120 秒
+        assert!(!verifies_record(&server, &hello_at(-300)));
+        assert!(!verifies_record(&server, &hello_at(300)));
+
+        // 偏差上限可配置，0 表示不检查
+        let strict = server.clone().with_max_time_diff(Duration::from_secs(10));
+        assert!(!verifies_record(&strict, &hello_at(-30)));
+        let unchecked = server.with_max_time_diff(Duration::ZERO);
+        assert!(verifies_record(&unchecked, &hello_at(-86400)));
+    }
+
+    /// 在 ClientHello 的扩展末尾追加一个扩展
+    fn append_extension(hello: &mut Vec<u8>, ext_type: u16, data: &[u8]) {
+        hello.extend_from_slice(&ext_type.to_be_bytes());
+        hello.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        hello.extend_from_slice(data);
+
+        let extensions_len = hello.len() - client::SESSION_ID_OFFSET - 32 - 8;
+        let at = client::SESSION_ID_OFFSET + 32 + 6;
+        hello[at..at + 2].copy_from_slice(&(extensions_len as u16).to_be_bytes());
+        let body_len = (hello.len() - 4) as u32;
+        hello[1..4].copy_from_slice(&body_len.to_be_bytes()[1..]);
+    }
+
+    #[test]
+    fn test_pre_shared_key_hello() {
+        let append_psk = |hello: &mut Vec<u8>, psk: &[u8]| append_extension(hello, 0x0029, psk);
+        let server = server(&["www.example.com"]);
+
+        // 一个身份 + 一个 binder
+        let mut psk = vec![0x00, 0x26, 0x00, 0x20];
+        psk.extend_from_slice(&[0x77; 32]);
+        psk.extend_from_slice(&[0x00, 0x00, 0x1c, 0x20, 0x00, 0x21, 0x20]);
+        psk.extend_from_slice(&[0x88; 32]);
+        let record = reality_hello_edited("www.example.com", now(), &SHORT_ID, |hello| append_psk(hello, &psk));
+        let info = hello_parser::parse_client_hello(&record).unwrap().unwrap();
+        assert!(info.pre_shared_key);
+        assert_eq!(info.server_name.as_deref(), Some("www.example.com"));
+        assert!(info.public_key.is_some());
+        assert!(verifies_record(&server, &record));
+
+        // 内容畸形的 PSK 扩展同样按完整握手处理
+        let record = reality_hello_edited("www.example.com", now(), &SHORT_ID, |hello| append_psk(hello, &[0xff; 5]));
+        assert!(verifies_record(&server, &record));
+    }
+
+    #[test]
+    fn test_early_data_hello() {
+        let server = server(&["www.example.com"]);
+        let record = reality_hello_edited("www.example.com", now(), &SHORT_ID, |hello| append_extension(hello, 0x002a, &[]));
+        let info = hello_parser::parse_client_hello(&record).unwrap().unwrap();
+        assert!(info.early_data);
+        assert!(verifies_record(&server, &record));
+
+        let info = hello_parser::parse_client_hello(&reality_hello("www.example.com")).unwrap().unwrap();
+        assert!(!info.early_data);
+    }
+
+    #[test]
+    fn test_client_version_range() {
+        // 测试客户端发送的版本为 1.8.0
+        let server = server(&["www.example.com"]);
+        assert!(verifies(&server.clone().with_client_ver(Some([1, 8, 0]), Some([1, 8, 0])), "www.example.com"));
+        assert!(verifies(&server.clone().with_client_ver(Some([1, 7, 9]), None), "www.example.com"));
+        assert!(verifies(&server.clone().with_client_ver(None, Some([25, 1, 0])), "www.example.com"));
+        assert!(!verifies(&server.clone().with_client_ver(Some([1, 8, 1]), None), "www.example.com"));
+        assert!(!verifies(&server.with_client_ver(None, Some([1, 7, 255])), "www.example.com"));
+    }
+
+    #[test]
+    fn test_short_id_lengths() {
+        let server = |short_ids: &[&str]| {
+            let short_ids = short_ids.iter().map(|s| s.to_string()).collect();
+            RealityServerRustls::new(SERVER_KEY.to_vec(), None, short_ids, vec!["www.example.com".to_string()]).unwrap()
+        };
+        let verifies_id = |server: &RealityServerRustls, short_id: &[u8]| {
+            verifies_record(server, &reality_hello_with("www.example.com", now(), short_id))
+        };
+
+        // 空 shortId: 客户端不带 shortId (全零)
+        let empty = server(&[""]);
+        assert!(verifies_id(&empty, &[]));
+        assert!(!verifies_id(&empty, &[0x01]));
+
+        // 4 字节 shortId 只比较配置的前缀，其余字节须为零
+        let short = server(&["01234567"]);
+        assert!(verifies_id(&short, &SHORT_ID[..4]));
+        assert!(!verifies_id(&short, &SHORT_ID));
+        assert!(!verifies_id(&short, &[]));
+
+        let full = server(&["0123456789abcdef", ""]);
+        assert!(verifies_id(&full, &SHORT_ID));
+        assert!(verifies_id(&full, &[]));
+        assert!(!verifies_id(&full, &SHORT_ID[..4]));
+
+        assert!(RealityServerRustls::new(SERVER_KEY.to_vec(), None, vec!["0123456789abcdef00".to_string()], vec![]).is_err());
+    }
+
+    #[test]
+    fn test_failure_stats() {
+        let server = server(&["www.example.com"]).with_client_ver(None, Some([25, 1, 0]));
+        // 执行 `fail` 后只有 `reason` 的计数加一
+        let expect = |reason: VerifyFailure, fail: &dyn Fn()| {
+            let before = server.stats().snapshot();
+            fail();
+            for ((r, was), (_, now)) in before.into_iter().zip(server.stats().snapshot()) {
+                assert_eq!(now, was + (r == reason) as u64, "{}", r.as_str());
+            }
+        };
+        let verify_edited = |edit: &dyn Fn(&mut ClientHelloInfo)| {
+            let record = reality_hello("www.example.com");
+            let mut info = hello_parser::parse_client_hello(&record).unwrap().unwrap();
+            edit(&mut info);
+            assert!(server.verify_client_reality(&info).is_none());
+        };
+
+        expect(VerifyFailure::NotTls, &|| assert!(server.parse_client_hello(b"GET / HTTP/1.1\r\n\r\n").is_none()));
+        expect(VerifyFailure::SniMismatch, &|| assert!(!verifies(&server, "www.example.org")));
+        expect(VerifyFailure::NoKeyShare, &|| verify_edited(&|info| info.public_key = None));
+        expect(VerifyFailure::InvalidSessionId, &|| verify_edited(&|info| info.session_id.truncate(16)));
+        expect(VerifyFailure::DecryptFailed, &|| verify_edited(&|info| info.client_random[0] ^= 1));
+        expect(VerifyFailure::ShortIdMismatch, &|| {
+            assert!(!verifies_record(&server, &reality_hello_with("www.example.com", now(), &[0xaa; 8])));
+        });
+        expect(VerifyFailure::ClientVersion, &|| {
+            assert!(!verifies(&server.clone().with_client_ver(Some([1, 8, 1]), None), "www.example.com"));
+        });
+        expect(VerifyFailure::StaleTimestamp, &|| {
+            assert!(!verifies_record(&server, &reality_hello_at("www.example.com", now() - 3600)));
+        });
+        let record = reality_hello("www.example.com");
+        assert!(verifies_record(&server, &record));
+        expect(VerifyFailure::Replay, &|| assert!(!verifies_record(&server, &record)));
+    }
+
+    /// 把单个记录中的握手消息在 `splits` 处拆分成多个记录
+    fn fragment(record: &[u8], splits: &[usize]) -> Vec<Vec<u8>> {
+        let message = &record[5..];
+        let mut bounds = vec![0];
+        bounds.extend_from_slice(splits);
+        bounds.push(message.len());
+        bounds
+            .windows(2)
+            .map(|w| {
+                let mut fragment = vec![0x16, 0x03, 0x01];
+                fragment.extend_from_slice(&((w[1] - w[0]) as u16).to_be_bytes());
+                fragment.extend_from_slice(&message[w[0]..w[1]]);
+                fragment
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fragmented_client_hello() {
+        let server = server(&["www.example.com"]);
+        let record = reality_hello("www.example.com");
+        let whole = hello_parser::parse_client_hello(&record).unwrap().unwrap();
+
+        // 在握手头与 SessionID 中间拆分，后面还跟着一个 CCS 记录
+        let mut records = fragment(&record, &[3, client::SESSION_ID_OFFSET + 5]).concat();
+        let records_len = records.len();
+        records.extend_from_slice(&[0x14, 0x03, 0x03, 0x00, 0x01, 0x01]);
+
+        let info = hello_parser::parse_client_hello(&records).unwrap().unwrap();
+        assert_eq!(info.message, &record[5..]);
+        assert_eq!(info.records_len, records_len);
+        assert_eq!(info.session_id, whole.session_id);
+        assert_eq!(info.public_key, whole.public_key);
+        assert_eq!(info.server_name.as_deref(), Some("www.example.com"));
+        assert!(server.verify_client_reality(&info).is_some());
+
+        // 缺少最后一个分片时还不完整
+        assert!(hello_parser::reassemble_handshake(&records[..records_len - 1]).unwrap().is_none());
+        assert!(hello_parser::reassemble_handshake(b"GET / HTTP/1.1").is_err());
+        assert!(hello_parser::reassemble_handshake(&[0x16, 0x03, 0x01, 0x00, 0x04, 0x01, 0x01, 0x00, 0x00]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_accept_fragmented_client_hello() {
+        use tokio::io::AsyncWriteExt;
+
+        let server = server(&["www.example.com"]);
+        let record = reality_hello("www.example.com");
+        let (mut client, server_side) = tokio::io::duplex(65536);
+        let accept = tokio::spawn(async move { server.accept(server_side).await.map(|_| ()) });
+
+        // 分片逐个到达，服务端读完整个 ClientHello 后才回复 ServerHello
+        for fragment in fragment(&record, &[100, 101]) {
+            client.write_all(&fragment).await.unwrap();
+            tokio::task::yield_now().await;
+        }
+        let mut header = [0u8; 6];
+        client.read_exact(&mut header).await.unwrap();
+        assert_eq!(header[0], 0x16);
+        assert_eq!(header[5], 0x02);
+        drop(client);
+        let _ = accept.await;
+    }
+
+    #[tokio::test]
+    async fn test_handshake_timeout() {
+        use tokio::io::AsyncWriteExt;
+
+        let dest = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = server_with_dest(&["www.example.com"], &dest.local_addr().unwrap().to_string())
+            .with_handshake_timeout(Duration::from_millis(200));
+
+        // 连接后什么都不发送的客户端在超时后被关闭
+        let (mut client, server_side) = tokio::io::duplex(65536);
+        let accept = tokio::spawn({
+            let server = server.clone();
+            async move { server.accept(server_side).await.map(|_| ()) }
+        });
+        let mut buf = [0u8; 1];
+        let read = tokio::time::timeout(Duration::from_secs(2), client.read(&mut buf)).await.unwrap();
+        assert!(matches!(read, Ok(0)), "{:?}", read);
+        assert!(accept.await.unwrap().is_err());
+
+        // 只发送了部分 ClientHello 的客户端在超时后回落，dest 收到已发送的数据
+        let (mut client, server_side) = tokio::io::duplex(65536);
+        tokio::spawn(async move { server.accept(server_side).await.map(|_| ()) });
+        let partial = &reality_hello("www.example.com")[..100];
+        client.write_all(partial).await.unwrap();
+        let (mut fallback, _) = tokio::time::timeout(Duration::from_secs(2), dest.accept()).await.unwrap().unwrap();
+        let mut received = vec![0u8; partial.len()];
+        fallback.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, partial);
+    }
+
+    #[tokio::test]
+    async fn test_non_tls_falls_back_immediately() {
+        use tokio::io::AsyncWriteExt;
+
+        let dest = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = server_with_dest(&["www.example.com"], &dest.local_addr().unwrap().to_string());
+
+        // 不足 5 字节，也不必等到握手超时
+        let (mut client, server_side) = tokio::io::duplex(65536);
+        tokio::spawn(async move { server.accept(server_side).await.map(|_| ()) });
+        client.write_all(b"GET ").await.unwrap();
+        let (mut fallback, _) = tokio::time::timeout(Duration::from_secs(1), dest.accept()).await.unwrap().unwrap();
+        let mut received = [0u8; 4];
+        fallback.read_exact(&mut received).await.unwrap();
+        assert_eq!(&received, b"GET ");
+    }
+
+    #[tokio::test]
+    async fn test_fallback_through_pool() {
+        use tokio::io::AsyncWriteExt;
+
+        let dest = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = server_with_dest(&["www.example.com"], &dest.local_addr().unwrap().to_string())
+            .with_fallback_pool(1, Duration::from_secs(60));
+
+        // 第二次回落使用第一次回落后补充的预连接，首包照常先到达
+        let mut clients = Vec::new();
+        for payload in [&b"GET /1"[..], b"GET /2"] {
+            let (mut client, server_side) = tokio::io::duplex(65536);
+            tokio::spawn({
+                let server = server.clone();
+                async move { server.accept(server_side).await.map(|_| ()) }
+            });
+            client.write_all(payload).await.unwrap();
+            clients.push(client);
+        }
+        let mut received = Vec::new();
+        while received.len() < 2 {
+            let (mut stream, _) = tokio::time::timeout(Duration::from_secs(1), dest.accept()).await.unwrap().unwrap();
+            let mut buf = [0u8; 6];
+            if tokio::time::timeout(Duration::from_millis(200), stream.read_exact(&mut buf)).await.is_ok() {
+                received.push(buf);
+            }
+        }
+        received.sort();
+        assert_eq!(received, [*b"GET /1", *b"GET /2"]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket_fallback() {
+        use tokio::io::AsyncWriteExt;
+
+        let path = std::env::temp_dir().join(format!("xray-lite-dest-{}.sock", uuid::Uuid::new_v4()));
+        let dest = tokio::net::UnixListener::bind(&path).unwrap();
+        let server = server_with_dest(&["*.example.com", "www.example.com"], &format!("unix:{}", path.display()));
+        assert_eq!(server.cert_host(), "www.example.com");
+
+        // 未通过认证的 ClientHello 原样转发到 unix socket，之后双向转发
+        let record = reality_hello("www.other.com");
+        let (mut client, server_side) = tokio::io::duplex(65536);
+        tokio::spawn(async move { server.accept(server_side).await.map(|_| ()) });
+        client.write_all(&record).await.unwrap();
+        let (mut fallback, _) = tokio::time::timeout(Duration::from_secs(1), dest.accept()).await.unwrap().unwrap();
+        let mut received = vec![0u8; record.len()];
+        fallback.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, record);
+        fallback.write_all(b"pong").await.unwrap();
+        let mut reply = [0u8; 4];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(&reply, b"pong");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replayed_client_hello_falls_back() {
+        use tokio::io::AsyncWriteExt;
+
+        let dest = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = server_with_dest(&["www.example.com"], &dest.local_addr().unwrap().to_string());
+        let record = reality_hello("www.example.com");
+
+        // 第一次通过认证，服务端回复 ServerHello
+        let (mut client, server_side) = tokio::io::duplex(65536);
+        let first = tokio::spawn({
+            let server = server.clone();
+            async move { server.accept(server_side).await.map(|_| ()) }
+        });
+        client.write_all(&record).await.unwrap();
+        let mut header = [0u8; 5];
+        client.read_exact(&mut header).await.unwrap();
+        assert_eq!(header[0], 0x16);
+        drop(client);
+        let _ = first.await;
+
+        // 相同的 ClientHello 再次出现时原样转发给 dest
+        let (mut client, server_side) = tokio::io::duplex(65536);
+        let second = tokio::spawn(async move { server.accept(server_side).await.map(|_| ()) });
+        client.write_all(&record).await.unwrap();
+        let (mut fallback, _) = dest.accept().await.unwrap();
+        let mut received = vec![0u8; record.len()];
+        fallback.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, record);
+        drop(fallback);
+        drop(client);
+        assert!(second.await.unwrap().is_err());
+    }
+
+    /// 模拟 dest 的证书 (DER) 与私钥
+    fn dest_certificate() -> (CertificateDer<'static>, PrivateKeyDer<'static>) {
+        let mut params = rcgen::CertificateParams::new(vec!["www.example.com".to_string()]);
+        params.distinguished_name = rcgen::DistinguishedName::new();
+        params.distinguished_name.push(rcgen::DnType::OrganizationName, "Example Inc.");
+        params.distinguished_name.push(rcgen::DnType::CommonName, "www.example.com");
+        let cert = rcgen::Certificate::from_params(params).unwrap();
+        let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(cert.serialize_private_key_der()));
+        (CertificateDer::from(cert.serialize_der().unwrap()), key)
+    }
+
+    /// 一把随机的 ECDSA 私钥 (PEM)
+    fn ecdsa_key_pem() -> String {
+        rcgen::Certificate::from_params(rcgen::CertificateParams::new(vec!["www.example.com".to_string()]))
+            .unwrap()
+            .serialize_private_key_pem()
+    }
+
+    #[tokio::test]
+    async fn test_mimic_certificate_handshake() {
+        let server = server(&["www.example.com"]);
+        let template = CertTemplate::from_der(&dest_certificate().0).unwrap();
+        *server.mimic.write().unwrap() = Some(Arc::new(template.issuer().unwrap()));
+
+        // 临时证书复制了 dest 证书的字段，后面附上签发它的 CA
+        let (chain, _) = server.generate_reality_cert(&[7; 32], "www.example.com").unwrap();
+        assert_eq!(chain.len(), 2);
+        let leaf = CertTemplate::from_der(&chain[0]).unwrap();
+        assert_eq!(leaf.subject, template.subject);
+        assert_eq!(leaf.subject_alt_names, template.subject_alt_names);
+        assert_eq!(leaf.issuer, CertTemplate::from_der(&chain[1]).unwrap().subject);
+
+        // Reality 客户端照常认证这条证书链
+        let (client_side, server_side) = tokio::io::duplex(65536);
+        let accept = tokio::spawn(async move { server.accept(server_side).await.map(|_| ()) });
+        let config = client::RealityClientConfig {
+            server_name: "www.example.com".to_string(),
+            public_key: *X25519PublicKey::from(&StaticSecret::from(SERVER_KEY)).as_bytes(),
+            short_id: SHORT_ID.to_vec(),
+        };
+        let _client = client::RealityClientStream::connect(client_side, &config).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), accept).await.unwrap().unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cert_refresh_fetches_dest() {
+        let (cert, key) = dest_certificate();
+        let config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.clone()], key)
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let dest = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let _ = acceptor.accept(stream).await;
+            }
+        });
+
+        let server = server_with_dest(&["*.example.com", "www.example.com"], &dest)
+            .with_cert_refresh_interval(Duration::from_secs(3600));
+        server.spawn_cert_refresh();
+        let mut installed = None;
+        for _ in 0..100 {
+            installed = server.mimic.read().unwrap().clone();
+            if installed.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(*installed.expect("未获取到 dest 证书").template(), CertTemplate::from_der(&cert).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_cert_refresh_failure_keeps_self_signed() {
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let dest = closed.local_addr().unwrap().to_string();
+        drop(closed);
+
+        let server = server_with_dest(&["www.example.com"], &dest).with_cert_refresh_interval(Duration::from_secs(3600));
+        server.spawn_cert_refresh();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(server.mimic.read().unwrap().is_none());
+        let (chain, _) = server.generate_reality_cert(&[7; 32], "www.example.com").unwrap();
+        assert_eq!(chain.len(), 1);
+    }
+
+    #[test]
+    fn test_certificate_reused_across_handshakes() {
+        let server = server(&["www.example.com"]);
+        let (first, first_key) = server.generate_reality_cert(&[7; 32], "www.example.com").unwrap();
+        let (second, second_key) = server.clone().generate_reality_cert(&[7; 32], "www.example.com").unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first_key.secret_der(), second_key.secret_der());
+
+        // 不同客户端只有 Reality 签名不同
+        let (other, _) = server.generate_reality_cert(&[8; 32], "www.example.com").unwrap();
+        let sig_pos = first[0].len() - 64;
+        assert_eq!(first[0][..sig_pos], other[0][..sig_pos]);
+        assert_ne!(first[0][sig_pos..], other[0][sig_pos..]);
+
+        // 获取到 dest 证书模板后重新生成
+        let template = CertTemplate::from_der(&dest_certificate().0).unwrap();
+        *server.mimic.write().unwrap() = Some(Arc::new(template.issuer().unwrap()));
+        let (mimicked, _) = server.generate_reality_cert(&[7; 32], "www.example.com").unwrap();
+        assert_eq!(mimicked.len(), 2);
+        assert_ne!(mimicked[0], first[0]);
+        assert_eq!(server.generate_reality_cert(&[7; 32], "www.example.com").unwrap().0, mimicked);
+    }
+
+    #[tokio::test]
+    async fn test_certificate_files() {
+        let dir = std::env::temp_dir().join(format!("xray-lite-reality-cert-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let mut params = rcgen::CertificateParams::new(vec!["www.example.com".to_string()]);
+        params.alg = &rcgen::PKCS_ED25519;
+        let cert = rcgen::Certificate::from_params(params).unwrap();
+        std::fs::write(path("cert.pem"), cert.serialize_pem().unwrap()).unwrap();
+        std::fs::write(path("key.pem"), cert.serialize_private_key_pem()).unwrap();
+        // 与证书不匹配的 ECDSA 私钥
+        std::fs::write(path("ecdsa.pem"), ecdsa_key_pem()).unwrap();
+
+        assert!(server(&["www.example.com"]).with_certificate_files(&path("cert.pem"), &path("ecdsa.pem")).is_err());
+        assert!(server(&["www.example.com"]).with_certificate_files(&path("missing.pem"), &path("key.pem")).is_err());
+        let server = server(&["www.example.com"]).with_certificate_files(&path("cert.pem"), &path("key.pem")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // 文件中的证书只替换签名字段
+        let (chain, _) = server.generate_reality_cert(&[7; 32], "www.example.com").unwrap();
+        let der = cert.serialize_der().unwrap();
+        assert_eq!(chain[0][..der.len() - 64], der[..der.len() - 64]);
+
+        let (client_side, server_side) = tokio::io::duplex(65536);
+        let accept = tokio::spawn(async move { server.accept(server_side).await.map(|_| ()) });
+        let config = client::RealityClientConfig {
+            server_name: "www.example.com".to_string(),
+            public_key: *X25519PublicKey::from(&StaticSecret::from(SERVER_KEY)).as_bytes(),
+            short_id: SHORT_ID.to_vec(),
+        };
+        let _client = client::RealityClientStream::connect(client_side, &config).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), accept).await.unwrap().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_fallback_dest() {
+        let plain = server(&["www.example.com"]);
+        assert_eq!(plain.fallback_dest(Some("www.example.com")), "127.0.0.1:443");
+
+        let mapped = plain.with_dest_map(BTreeMap::from([
+            ("*.a.com".to_string(), "wildcard:443".to_string()),
+            ("www.a.com".to_string(), "exact:443".to_string()),
+        ]));
+        // 精确匹配优先于通配符，没有 `*` 条目时使用 dest
+        assert_eq!(mapped.fallback_dest(Some("www.a.com")), "exact:443");
+        assert_eq!(mapped.fallback_dest(Some("img.a.com")), "wildcard:443");
+        assert_eq!(mapped.fallback_dest(Some("www.b.com")), "127.0.0.1:443");
+        assert_eq!(mapped.fallback_dest(None), "127.0.0.1:443");
+    }
+}
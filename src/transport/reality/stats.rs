@@ -0,0 +1,87 @@
+//! Reality 验证失败的分类计数
+//!
+//! 客户端被意外回落时，按失败原因累计的计数比 debug 日志里的十六进制转储更容易定位问题。
+//! 计数在同一个入站的所有连接间共享，读取只需几次原子加载。
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::debug;
+
+/// 每种原因每累计这么多次输出一条 debug 日志
+const LOG_SAMPLE: u64 = 100;
+
+/// 未通过 Reality 验证的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyFailure {
+    /// 首包不是 TLS ClientHello
+    NotTls,
+    /// SNI 不在 serverNames 中
+    SniMismatch,
+    /// 没有可用于认证的 X25519 密钥份额 (与认证失败区分，如客户端只提供 secp256r1)
+    NoKeyShare,
+    /// SessionID 不是 32 字节，无法携带认证信息
+    InvalidSessionId,
+    /// SessionID 解密失败 (公钥不匹配或不是 Reality 客户端)
+    DecryptFailed,
+    /// shortId 不在 shortIds 中
+    ShortIdMismatch,
+    /// 客户端版本不在允许范围内
+    ClientVersion,
+    /// 客户端时间戳超出允许偏差
+    StaleTimestamp,
+    /// ClientHello 重放
+    Replay,
+}
+
+impl VerifyFailure {
+    pub const ALL: [VerifyFailure; 9] = [
+        VerifyFailure::NotTls,
+        VerifyFailure::SniMismatch,
+        VerifyFailure::NoKeyShare,
+        VerifyFailure::InvalidSessionId,
+        VerifyFailure::DecryptFailed,
+        VerifyFailure::ShortIdMismatch,
+        VerifyFailure::ClientVersion,
+        VerifyFailure::StaleTimestamp,
+        VerifyFailure::Replay,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            VerifyFailure::NotTls => "not_tls",
+            VerifyFailure::SniMismatch => "sni_mismatch",
+            VerifyFailure::NoKeyShare => "no_key_share",
+            VerifyFailure::InvalidSessionId => "invalid_session_id",
+            VerifyFailure::DecryptFailed => "decrypt_failed",
+            VerifyFailure::ShortIdMismatch => "short_id_mismatch",
+            VerifyFailure::ClientVersion => "client_version",
+            VerifyFailure::StaleTimestamp => "stale_timestamp",
+            VerifyFailure::Replay => "replay",
+        }
+    }
+}
+
+/// 按原因累计的验证失败次数
+#[derive(Debug, Default)]
+pub struct RealityStats {
+    failures: [AtomicU64; VerifyFailure::ALL.len()],
+}
+
+impl RealityStats {
+    /// 记录一次验证失败，每种原因的第 1 次及之后每 [`LOG_SAMPLE`] 次输出一条 debug 日志
+    pub fn record(&self, reason: VerifyFailure) {
+        let count = self.failures[reason as usize].fetch_add(1, Ordering::Relaxed) + 1;
+        if count % LOG_SAMPLE == 1 {
+            debug!("Reality 验证失败: {} (累计 {} 次)", reason.as_str(), count);
+        }
+    }
+
+    /// 某种原因的累计次数
+    pub fn failures(&self, reason: VerifyFailure) -> u64 {
+        self.failures[reason as usize].load(Ordering::Relaxed)
+    }
+
+    /// 所有原因的累计次数，按 [`VerifyFailure::ALL`] 的顺序
+    pub fn snapshot(&self) -> Vec<(VerifyFailure, u64)> {
+        VerifyFailure::ALL.iter().map(|&reason| (reason, self.failures(reason))).collect()
+    }
+}
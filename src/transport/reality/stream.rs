@@ -1,12 +1,22 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use bytes::{Buf, BufMut, BytesMut};
 use std::io;
 use std::pin::Pin;
-use std::task::{Context, Poll};
+use std::task::{ready, Context, Poll};
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 
 use super::crypto::TlsKeys;
 
+/// 单条记录的明文上限 (留出余量，低于 TLS 的 16KB 限制)
+const MAX_RECORD_PLAINTEXT: usize = 14 * 1024;
+/// KeyUpdate 握手消息类型
+const HANDSHAKE_KEY_UPDATE: u8 = 24;
+/// 回应对端的 KeyUpdate (update_not_requested)
+const KEY_UPDATE_NOT_REQUESTED: [u8; 5] = [HANDSHAKE_KEY_UPDATE, 0, 0, 1, 0];
+
+/// close_notify 告警描述
+const ALERT_CLOSE_NOTIFY: u8 = 0;
+
 /// 封装了 TLS 1.3 加解密的流
 pub struct TlsStream<S> {
     stream: S,
@@ -19,10 +29,17 @@ pub struct TlsStream<S> {
 
     // Write buffer (plaintext accumulation)
     write_buffer: BytesMut,
+    /// 已加密、尚未写入底层连接的记录
+    encrypted_out: BytesMut,
 
     // 序列号
     read_seq: u64,
     write_seq: u64,
+
+    /// 已收到对端的 close_notify，之后的读取返回 EOF
+    read_closed: bool,
+    /// 已发出 close_notify
+    close_sent: bool,
 }
 
 impl<S: AsyncRead + AsyncWrite + Unpin> TlsStream<S> {
@@ -33,8 +50,11 @@ impl<S: AsyncRead + AsyncWrite + Unpin> TlsStream<S> {
             input_buffer: BytesMut::with_capacity(24 * 1024),
             decrypted_buffer: BytesMut::with_capacity(24 * 1024),
             write_buffer: BytesMut::with_capacity(16 * 1024 + 1024),
+            encrypted_out: BytesMut::with_capacity(16 * 1024 + 1024),
             read_seq: 0,
             write_seq: 0,
+            read_closed: false,
+            close_sent: false,
         }
     }
 
@@ -45,11 +65,20 @@ impl<S: AsyncRead + AsyncWrite + Unpin> TlsStream<S> {
             input_buffer: initial_data, // Use provided buffer
             decrypted_buffer: BytesMut::with_capacity(24 * 1024),
             write_buffer: BytesMut::with_capacity(16 * 1024 + 1024),
+            encrypted_out: BytesMut::with_capacity(16 * 1024 + 1024),
             read_seq: 0,
             write_seq: 0,
+            read_closed: false,
+            close_sent: false,
         }
     }
 
+    /// 握手阶段已用应用层密钥发送了记录 (如 NewSessionTicket) 时，从其后的序列号继续
+    pub fn with_write_seq(mut self, write_seq: u64) -> Self {
+        self.write_seq = write_seq;
+        self
+    }
+
     /// 尝试从 input_buffer 解析并解密一条 TLS 记录
     fn process_record(&mut self) -> Result<bool> {
         if self.input_buffer.len() < 5 {
@@ -80,75 +109,85 @@ impl<S: AsyncRead + AsyncWrite + Unpin> TlsStream<S> {
         if content_type == 23 {
             // Application Data
             self.decrypted_buffer.extend_from_slice(&ciphertext[..len]);
-        } else if content_type == 21 { // Alert
-             // Close notify (100) ?
+        } else if content_type == 21 {
+            // Alert: close_notify 表示对端正常关闭，其余告警均为致命错误
+            match &ciphertext[..len] {
+                [_, ALERT_CLOSE_NOTIFY] => self.read_closed = true,
+                [level, description] => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::ConnectionAborted,
+                        format!("对端发送了 TLS alert (level {}, description {})", level, description),
+                    )
+                    .into())
+                }
+                _ => return Err(anyhow!("TLS alert 格式无效")),
+            }
+        } else if content_type == 22 {
+            // 握手后消息，只处理 KeyUpdate
+            self.handle_post_handshake(&ciphertext[..len])?;
         }
 
         Ok(true)
     }
 
-    /// 将 write_buffer 中的明文数据打包加密并发送
-    fn flush_write_buffer(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        if self.write_buffer.is_empty() {
-            return Poll::Ready(Ok(()));
+    /// 处理握手后消息: 收到 KeyUpdate 时切换读取密钥，对端要求时回应 KeyUpdate 并切换写入密钥
+    fn handle_post_handshake(&mut self, mut messages: &[u8]) -> Result<()> {
+        while messages.len() >= 4 {
+            let len = u32::from_be_bytes([0, messages[1], messages[2], messages[3]]) as usize;
+            let body = messages.get(4..4 + len).ok_or_else(|| anyhow!("握手后消息不完整"))?;
+            if messages[0] == HANDSHAKE_KEY_UPDATE {
+                let update_requested = match body {
+                    [0] => false,
+                    [1] => true,
+                    _ => return Err(anyhow!("KeyUpdate 消息无效")),
+                };
+                self.keys.update_client_keys()?;
+                self.read_seq = 0;
+                if update_requested {
+                    // 已加密的记录按顺序排在前面，尚未加密的明文之后使用新密钥
+                    let record = self.keys.encrypt_server_record(self.write_seq, &KEY_UPDATE_NOT_REQUESTED, 22)?;
+                    self.encrypted_out.extend_from_slice(&record);
+                    self.keys.update_server_keys()?;
+                    self.write_seq = 0;
+                }
+            }
+            messages = &messages[4 + len..];
         }
+        Ok(())
+    }
 
-        // 1. Encrypt accumulated plaintext
-        let encrypted_record =
-            match self
-                .keys
-                .encrypt_server_record(self.write_seq, &self.write_buffer, 23)
-            {
-                Ok(data) => data,
-                Err(e) => return Poll::Ready(Err(io::Error::new(io::ErrorKind::Other, e))),
-            };
-
-        let total_len = encrypted_record.len();
-        let mut written_len = 0;
-
-        // 2. Write ALL encrypted bytes to underlying stream
-        // Note: For strict correctness, we should handle partial writes properly by keeping `encrypted_record` in a separate buffer.
-        // However, standard tokio AsyncWrite mostly handles the buffering internally or we assume the socket can take it.
-        // But to be safe and simple, we do a loop here - BEWARE: this blocks the task if socket full.
-        // A better approach is another encryption_buffer state. But for now, let's try direct write loop.
-
-        // Actually, we can't loop-wait in Poll.
-        // We really need an `encrypted_output_buffer`.
-
-        // Let's rely on the assumption that for typical TLS record sizes (~16KB), the kernel socket buffer is likely sufficient.
-        // IF it returns Pending, we are in trouble because we lose the encrypted record.
-
-        // REVISION: We MUST just loop write here for simplicity in this minimal implementation, using `poll_write` repeatedly?
-        // No, that panics context.
-
-        // Let's implement a simplified "write all at once" for now.
-        // If this performance optimization is critical, we assume the underlying stream handles buffering (e.g. TCP).
-
-        // Or better: Let's assume poll_write handles the whole buffer or nothing/error.
-        // Most async IO implementations won't do partial writes on small buffers unless socket buffer is nearly full.
-
-        // Correct implementation for production:
-        // shift `write_buffer` -> `encrypted_buffer`.
-        // drain `encrypted_buffer` to `stream`.
+    /// 把 write_buffer 中的明文加密为一条记录追加到 encrypted_out
+    fn seal_write_buffer(&mut self) -> io::Result<()> {
+        if self.write_buffer.is_empty() {
+            return Ok(());
+        }
+        let record = self
+            .keys
+            .encrypt_server_record(self.write_seq, &self.write_buffer, 23)
+            .map_err(io::Error::other)?;
+        self.encrypted_out.extend_from_slice(&record);
+        self.write_seq += 1;
+        self.write_buffer.clear();
+        Ok(())
+    }
 
-        // For this step, I'll do a direct write attempt.
-        match Pin::new(&mut self.stream).poll_write(cx, &encrypted_record) {
-            Poll::Ready(Ok(n)) => {
-                if n < encrypted_record.len() {
-                    // CRITICAL: Partial write of encrypted frame corrupts the stream.
-                    // We must return error.
-                    return Poll::Ready(Err(io::Error::new(
-                        io::ErrorKind::Other,
-                        "Partial TLS record write",
-                    )));
-                }
-                self.write_seq += 1;
-                self.write_buffer.clear();
-                Poll::Ready(Ok(()))
+    /// 把 encrypted_out 写入底层连接，可跨多次唤醒逐步写完；底层不可写时返回 Pending
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.encrypted_out.is_empty() {
+            match Pin::new(&mut self.stream).poll_write(cx, &self.encrypted_out) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Poll::Ready(Ok(n)) => self.encrypted_out.advance(n),
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
             }
-            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
-            Poll::Pending => Poll::Pending,
         }
+        Poll::Ready(Ok(()))
+    }
+
+    /// 加密 write_buffer 中的明文并全部写出
+    fn flush_write_buffer(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.seal_write_buffer()?;
+        self.poll_drain(cx)
     }
 }
 
@@ -170,6 +209,10 @@ impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for TlsStream<S> {
 
         // 2. Loop to read and process records
         loop {
+            if this.read_closed {
+                return Poll::Ready(Ok(()));
+            }
+
             // Process any pending data
             match this.process_record() {
                 Ok(true) => {
@@ -182,7 +225,10 @@ impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for TlsStream<S> {
                     continue;
                 }
                 Ok(false) => { /* Need more data */ }
-                Err(e) => return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, e))),
+                Err(e) => {
+                    let e = e.downcast::<io::Error>().unwrap_or_else(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+                    return Poll::Ready(Err(e));
+                }
             }
 
             // Read from underlying stream
@@ -234,44 +280,181 @@ impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for TlsStream<S> {
     ) -> Poll<io::Result<usize>> {
         let this = self.get_mut();
 
-        // 缓冲策略: 只有 buffer 超过 14KB 时才 flush，否则只是积攒
-        if this.write_buffer.len() + buf.len() > 14336 {
-            // ~14KB threshold
-            // Try to flush first
-            if let Poll::Ready(Err(e)) = this.flush_write_buffer(cx) {
-                return Poll::Ready(Err(e));
-            }
-            // If flush returned Pending, we can't accept more data yet?
-            // Actually, if flush is Pending, strictly we should return Pending.
-            // But our `flush_write_buffer` is simple and atomic-like.
+        // 已加密的数据尚未写完时等待底层可写 (底层的 poll_write 已登记唤醒)
+        ready!(this.poll_drain(cx))?;
 
-            if !this.write_buffer.is_empty() {
-                return Poll::Pending;
-            }
+        // 缓冲策略: 明文积攒到一条记录的上限后才加密
+        if this.write_buffer.len() >= MAX_RECORD_PLAINTEXT {
+            this.seal_write_buffer()?;
+            ready!(this.poll_drain(cx))?;
         }
 
-        // Add to buffer
-        this.write_buffer.extend_from_slice(buf);
-        Poll::Ready(Ok(buf.len()))
+        let n = buf.len().min(MAX_RECORD_PLAINTEXT - this.write_buffer.len());
+        this.write_buffer.extend_from_slice(&buf[..n]);
+        Poll::Ready(Ok(n))
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         let this = self.get_mut();
-
-        match this.flush_write_buffer(cx) {
-            Poll::Ready(Ok(())) => Pin::new(&mut this.stream).poll_flush(cx),
-            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
-            Poll::Pending => Poll::Pending,
-        }
+        ready!(this.flush_write_buffer(cx))?;
+        Pin::new(&mut this.stream).poll_flush(cx)
     }
 
     fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         let this = self.get_mut();
-        // Try to flush any remaining data
-        match this.flush_write_buffer(cx) {
-            Poll::Ready(Ok(())) => Pin::new(&mut this.stream).poll_shutdown(cx),
-            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
-            Poll::Pending => Poll::Pending, // Cannot shutdown if can't flush
+        // 先写完剩余数据，再发送 close_notify
+        this.seal_write_buffer()?;
+        if !this.close_sent {
+            let record = this
+                .keys
+                .encrypt_server_record(this.write_seq, &[1, ALERT_CLOSE_NOTIFY], 21)
+                .map_err(io::Error::other)?;
+            this.encrypted_out.extend_from_slice(&record);
+            this.write_seq += 1;
+            this.close_sent = true;
         }
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.stream).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::reality::crypto::CipherSuite;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn keys() -> TlsKeys {
+        let suite = CipherSuite::Aes128GcmSha256;
+        let (_, handshake_secret) = TlsKeys::derive_handshake_keys(suite, &[0x11; 32], &[0x22; 32]).unwrap();
+        TlsKeys::derive_application_keys(suite, &handshake_secret, &[0x33; 32]).unwrap()
+    }
+
+    /// 以客户端身份读取一条记录，返回 (内层 content type, 明文)
+    async fn read_server_record<S: AsyncRead + Unpin>(stream: &mut S, keys: &TlsKeys, seq: u64) -> (u8, Vec<u8>) {
+        let mut header = [0u8; 5];
+        stream.read_exact(&mut header).await.unwrap();
+        let mut body = vec![0u8; u16::from_be_bytes([header[3], header[4]]) as usize];
+        stream.read_exact(&mut body).await.unwrap();
+        let (content_type, len) = keys.decrypt_server_record(seq, &header, &mut body).unwrap();
+        body.truncate(len);
+        (content_type, body)
+    }
+
+    #[tokio::test]
+    async fn test_key_update_from_client() {
+        let (server_io, mut client_io) = tokio::io::duplex(64 * 1024);
+        let mut server = TlsStream::new(server_io, keys());
+        let mut client = keys();
+
+        // 旧密钥下的数据、要求对端更新的 KeyUpdate、新密钥下的数据
+        client_io.write_all(&client.encrypt_client_record(0, b"one", 23).unwrap()).await.unwrap();
+        client_io.write_all(&client.encrypt_client_record(1, &[HANDSHAKE_KEY_UPDATE, 0, 0, 1, 1], 22).unwrap()).await.unwrap();
+        client.update_client_keys().unwrap();
+        client_io.write_all(&client.encrypt_client_record(0, b"two", 23).unwrap()).await.unwrap();
+
+        let mut buf = [0u8; 6];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"onetwo");
+
+        // 服务端先用旧密钥回应 KeyUpdate，之后的数据使用新密钥
+        server.write_all(b"reply").await.unwrap();
+        server.flush().await.unwrap();
+        assert_eq!(read_server_record(&mut client_io, &client, 0).await, (22, KEY_UPDATE_NOT_REQUESTED.to_vec()));
+        client.update_server_keys().unwrap();
+        assert_eq!(read_server_record(&mut client_io, &client, 0).await, (23, b"reply".to_vec()));
+    }
+
+    /// 每次 poll 交替返回 Pending 与只接受 1 字节的底层连接
+    #[derive(Default)]
+    struct Trickle {
+        written: Vec<u8>,
+        ready: bool,
+    }
+
+    impl AsyncRead for Trickle {
+        fn poll_read(self: Pin<&mut Self>, _: &mut Context<'_>, _: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+            Poll::Pending
+        }
+    }
+
+    impl AsyncWrite for Trickle {
+        fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            self.ready = !self.ready;
+            if !self.ready {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            self.written.push(buf[0]);
+            Poll::Ready(Ok(1))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_partial_writes() {
+        let mut server = TlsStream::new(Trickle::default(), keys());
+        let data: Vec<u8> = (0..40_000u32).map(|i| i as u8).collect();
+        server.write_all(&data).await.unwrap();
+        server.flush().await.unwrap();
+
+        // 逐字节写出的密文仍是完整、有序的记录
+        let client = keys();
+        let mut written = server.stream.written.as_slice();
+        let mut received = Vec::new();
+        let mut seq = 0;
+        while !written.is_empty() {
+            let (content_type, plaintext) = read_server_record(&mut written, &client, seq).await;
+            assert_eq!(content_type, 23);
+            assert!(plaintext.len() <= MAX_RECORD_PLAINTEXT);
+            received.extend_from_slice(&plaintext);
+            seq += 1;
+        }
+        assert_eq!(received, data);
+    }
+
+    /// 收发方向互换的密钥，用于在测试中扮演客户端
+    fn client_keys() -> TlsKeys {
+        let mut keys = keys();
+        std::mem::swap(&mut keys.client_write_key, &mut keys.server_write_key);
+        std::mem::swap(&mut keys.client_iv, &mut keys.server_iv);
+        std::mem::swap(&mut keys.client_traffic_secret, &mut keys.server_traffic_secret);
+        keys
+    }
+
+    #[tokio::test]
+    async fn test_close_notify() {
+        let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+        let mut server = TlsStream::new(server_io, keys());
+        let mut client = TlsStream::new(client_io, client_keys());
+
+        client.write_all(b"request").await.unwrap();
+        client.flush().await.unwrap();
+        let mut request = [0u8; 7];
+        server.read_exact(&mut request).await.unwrap();
+        assert_eq!(&request, b"request");
+
+        // shutdown 先写完剩余数据再发送 close_notify，对端读到正常的 EOF
+        server.write_all(b"response").await.unwrap();
+        server.shutdown().await.unwrap();
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        assert_eq!(response, b"response");
+        assert!(client.read_closed);
+
+        // 其它告警作为错误交给读取方
+        let (server_io, mut client_io) = tokio::io::duplex(64 * 1024);
+        let mut server = TlsStream::new(server_io, keys());
+        client_io.write_all(&keys().encrypt_client_record(0, &[2, 40], 21).unwrap()).await.unwrap();
+        let err = server.read(&mut [0u8; 16]).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionAborted);
+        assert!(err.to_string().contains("description 40"), "{}", err);
     }
 }
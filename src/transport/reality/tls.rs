@@ -312,6 +312,12 @@ pub struct ServerHello {
     pub raw_data: Vec<u8>,
 }
 
+/// HelloRetryRequest 的 random: SHA-256("HelloRetryRequest") (RFC 8446 4.1.3)
+pub const HELLO_RETRY_REQUEST_RANDOM: [u8; 32] = [
+    0xcf, 0x21, 0xad, 0x74, 0xe5, 0x9a, 0x61, 0x11, 0xbe, 0x1d, 0x8c, 0x02, 0x1e, 0x65, 0xb8, 0x91,
+    0xc2, 0xa2, 0x11, 0x16, 0x7a, 0xbb, 0x8c, 0x5e, 0x07, 0x9e, 0x09, 0xe2, 0xc8, 0xa8, 0x33, 0x9c,
+];
+
 impl ServerHello {
     /// 从原始数据创建
     pub fn from_raw(data: Vec<u8>) -> Self {
@@ -339,6 +345,7 @@ impl ServerHello {
     pub fn new_reality(
         client_session_id: &[u8],
         random: [u8; 32],
+        cipher_suite: u16,
         key_share_data: &[u8],
     ) -> Result<Self> {
         use bytes::BufMut; // Added for BufMut trait
@@ -361,8 +368,8 @@ impl ServerHello {
         payload.put_u8(client_session_id.len() as u8);
         payload.put_slice(client_session_id);
 
-        // 5. Cipher Suite (TLS_AES_128_GCM_SHA256)
-        payload.put_u16(0x1301);
+        // 5. Cipher Suite
+        payload.put_u16(cipher_suite);
 
         // 6. Compression Method (0)
         payload.put_u8(0);
@@ -401,6 +408,39 @@ impl ServerHello {
         })
     }
 
+    /// 构造要求客户端改用 `group` 重新发送 ClientHello 的 HelloRetryRequest
+    ///
+    /// HelloRetryRequest 是 random 为固定值的 ServerHello，key_share 扩展只包含选定的组，
+    /// `cipher_suite` 必须与之后的 ServerHello 一致。
+    pub fn new_hello_retry(client_session_id: &[u8], cipher_suite: u16, group: u16) -> Self {
+        use bytes::BufMut;
+
+        let mut extensions = BytesMut::new();
+        // Supported Versions (TLS 1.3)
+        extensions.put_u16(0x002b);
+        extensions.put_u16(2);
+        extensions.put_u16(0x0304);
+        // Key Share: selected_group
+        extensions.put_u16(0x0033);
+        extensions.put_u16(2);
+        extensions.put_u16(group);
+
+        let mut body = BytesMut::new();
+        body.put_u16(0x0303);
+        body.put_slice(&HELLO_RETRY_REQUEST_RANDOM);
+        body.put_u8(client_session_id.len() as u8);
+        body.put_slice(client_session_id);
+        body.put_u16(cipher_suite);
+        body.put_u8(0);
+        body.put_u16(extensions.len() as u16);
+        body.put_slice(&extensions);
+
+        let mut payload = vec![HandshakeType::ServerHello as u8];
+        payload.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        payload.extend_from_slice(&body);
+        ServerHello { raw_data: payload }
+    }
+
     pub fn encode(&self) -> Vec<u8> {
         use bytes::BufMut; // Added for BufMut trait
 
@@ -0,0 +1,65 @@
+use hyper::http::header::{HeaderMap, HeaderValue};
+
+use super::XhttpConfig;
+
+/// 预检结果的缓存时间 (秒)
+const PREFLIGHT_MAX_AGE: &str = "86400";
+
+/// 浏览器 (browser dialer) 请求的来源检查结果
+pub(super) enum Origin {
+    /// 没有 Origin 头，或没有配置 corsOrigins
+    None,
+    /// 在 corsOrigins 之中，响应需要带上该来源
+    Allowed(HeaderValue),
+    /// 不在 corsOrigins 之中，按未知请求拒绝
+    Denied,
+}
+
+/// 按 corsOrigins 检查请求的 Origin 头，`*` 允许任意来源
+pub(super) fn check(config: &XhttpConfig, headers: &HeaderMap) -> Origin {
+    if config.cors_origins.is_empty() {
+        return Origin::None;
+    }
+    let Some(origin) = headers.get("origin") else {
+        return Origin::None;
+    };
+    let allowed = origin.to_str().is_ok_and(|origin| {
+        config.cors_origins.iter().any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(origin))
+    });
+    if allowed {
+        Origin::Allowed(origin.clone())
+    } else {
+        Origin::Denied
+    }
+}
+
+/// 允许的来源，没有或被拒绝时为 None
+pub(super) fn allowed(config: &XhttpConfig, headers: &HeaderMap) -> Option<HeaderValue> {
+    match check(config, headers) {
+        Origin::Allowed(origin) => Some(origin),
+        Origin::None | Origin::Denied => None,
+    }
+}
+
+/// 普通响应上的 CORS 头
+pub(super) fn response_headers(origin: &HeaderValue) -> [(&'static str, HeaderValue); 2] {
+    [
+        ("access-control-allow-origin", origin.clone()),
+        ("vary", HeaderValue::from_static("Origin")),
+    ]
+}
+
+/// 预检 (OPTIONS) 响应的 CORS 头，允许浏览器请求的全部请求头
+pub(super) fn preflight_headers(origin: &HeaderValue, request: &HeaderMap) -> Vec<(&'static str, HeaderValue)> {
+    let allow_headers = request
+        .get("access-control-request-headers")
+        .cloned()
+        .unwrap_or_else(|| HeaderValue::from_static("Content-Type"));
+    let mut headers = response_headers(origin).to_vec();
+    headers.extend([
+        ("access-control-allow-methods", HeaderValue::from_static("GET, POST, OPTIONS")),
+        ("access-control-allow-headers", allow_headers),
+        ("access-control-max-age", HeaderValue::from_static(PREFLIGHT_MAX_AGE)),
+    ]);
+    headers
+}
@@ -175,6 +175,7 @@ impl GrpcStatus {
 }
 
 /// gRPC Trailer (结束标记)
+#[derive(Debug)]
 pub struct GrpcTrailer {
     pub status: GrpcStatus,
     pub message: Option<String>,
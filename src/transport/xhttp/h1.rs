@@ -0,0 +1,523 @@
+//! XHTTP over HTTP/1.1，供只向源站转发 HTTP/1.1 的 CDN 使用
+//!
+//! 请求体支持 Content-Length 与 chunked 编码；下行响应使用 chunked 编码，
+//! HTTP/1.0 客户端则以关闭连接标记结束。会话配对与 HTTP/2 路径共用。
+
+use anyhow::{bail, Result};
+use bytes::{Buf, Bytes, BytesMut};
+use hyper::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
+use std::net::IpAddr;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+use super::session::{self, Activity, PushError, Route, Sessions};
+use super::{cors, PaddingRange, XhttpConfig};
+
+/// 请求头最多解析的字段数
+const MAX_HEADERS: usize = 64;
+/// 请求行与请求头的总长度上限
+const MAX_HEAD_SIZE: usize = 16 * 1024;
+
+/// 已解析的请求头
+struct Head {
+    method: Method,
+    path: String,
+    query: Option<String>,
+    headers: HeaderMap,
+    body: BodyState,
+    /// HTTP/1.1 (chunked 响应) 或 HTTP/1.0 (以关闭连接结束响应)
+    http11: bool,
+    keep_alive: bool,
+}
+
+/// 请求体的解码状态
+enum BodyState {
+    /// 剩余的 Content-Length 字节数
+    Length(u64),
+    /// chunked 编码: 等待块长度行
+    ChunkSize,
+    /// chunked 编码: 当前块的剩余字节数
+    ChunkData(u64),
+    /// chunked 编码: 块数据后的 CRLF
+    ChunkEnd,
+    /// chunked 编码: 结尾的 trailer
+    Trailers,
+    Done,
+}
+
+/// 连接的读取端，缓存多读的数据供下一个请求使用
+struct Reader<T> {
+    io: ReadHalf<T>,
+    buf: BytesMut,
+}
+
+impl<T: AsyncRead + AsyncWrite> Reader<T> {
+    /// 读取更多数据，连接关闭时返回 false
+    async fn fill(&mut self) -> Result<bool> {
+        if self.buf.capacity() - self.buf.len() < 4096 {
+            self.buf.reserve(16 * 1024);
+        }
+        Ok(self.io.read_buf(&mut self.buf).await? > 0)
+    }
+
+    /// 读取下一个请求头，连接在请求之间正常关闭时返回 None
+    async fn read_head(&mut self) -> Result<Option<Head>> {
+        loop {
+            if !self.buf.is_empty() {
+                let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+                let mut request = httparse::Request::new(&mut headers);
+                if let httparse::Status::Complete(len) = request.parse(&self.buf)? {
+                    let head = Self::build_head(&request)?;
+                    self.buf.advance(len);
+                    return Ok(Some(head));
+                }
+                if self.buf.len() > MAX_HEAD_SIZE {
+                    bail!("HTTP/1.1 请求头过长");
+                }
+            }
+            if !self.fill().await? {
+                if self.buf.is_empty() {
+                    return Ok(None);
+                }
+                bail!("HTTP/1.1 请求头不完整");
+            }
+        }
+    }
+
+    fn build_head(request: &httparse::Request) -> Result<Head> {
+        let mut headers = HeaderMap::new();
+        for header in request.headers.iter() {
+            headers.append(
+                HeaderName::from_bytes(header.name.as_bytes())?,
+                HeaderValue::from_bytes(header.value)?,
+            );
+        }
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).unwrap_or("");
+        let http11 = request.version == Some(1);
+        let connection = header("connection").to_ascii_lowercase();
+        let keep_alive = if http11 { !connection.contains("close") } else { connection.contains("keep-alive") };
+
+        let body = if header("transfer-encoding").to_ascii_lowercase().contains("chunked") {
+            BodyState::ChunkSize
+        } else {
+            match header("content-length") {
+                "" => BodyState::Length(0),
+                len => BodyState::Length(len.trim().parse()?),
+            }
+        };
+
+        let target = request.path.unwrap_or("/");
+        // 绝对形式的请求目标 (经由代理) 只保留路径部分
+        let path = match target.find("://") {
+            Some(scheme) => target[scheme + 3..].find('/').map_or("/", |i| &target[scheme + 3 + i..]),
+            None => target,
+        };
+        let (path, query) = match path.split_once('?') {
+            Some((path, query)) => (path.to_string(), Some(query.to_string())),
+            None => (path.to_string(), None),
+        };
+
+        Ok(Head {
+            method: Method::from_bytes(request.method.unwrap_or("GET").as_bytes())?,
+            path,
+            query,
+            headers,
+            body,
+            http11,
+            keep_alive,
+        })
+    }
+
+    /// 读取一段请求体，请求体结束时返回 None
+    async fn read_body(&mut self, state: &mut BodyState) -> Result<Option<Bytes>> {
+        loop {
+            match *state {
+                BodyState::Done | BodyState::Length(0) => {
+                    *state = BodyState::Done;
+                    return Ok(None);
+                }
+                BodyState::Length(remaining) | BodyState::ChunkData(remaining) if !self.buf.is_empty() => {
+                    let n = remaining.min(self.buf.len() as u64);
+                    let data = self.buf.split_to(n as usize).freeze();
+                    *state = match *state {
+                        BodyState::ChunkData(_) if n == remaining => BodyState::ChunkEnd,
+                        BodyState::ChunkData(_) => BodyState::ChunkData(remaining - n),
+                        _ => BodyState::Length(remaining - n),
+                    };
+                    return Ok(Some(data));
+                }
+                BodyState::ChunkSize | BodyState::Trailers => {
+                    if let Some(end) = self.buf.windows(2).position(|w| w == b"\r\n") {
+                        let line = self.buf.split_to(end + 2);
+                        let line = std::str::from_utf8(&line[..end])?;
+                        *state = match *state {
+                            BodyState::Trailers if line.is_empty() => BodyState::Done,
+                            BodyState::Trailers => BodyState::Trailers,
+                            _ => {
+                                let size = line.split(';').next().unwrap_or("").trim();
+                                match u64::from_str_radix(size, 16)? {
+                                    0 => BodyState::Trailers,
+                                    size => BodyState::ChunkData(size),
+                                }
+                            }
+                        };
+                        continue;
+                    }
+                    if self.buf.len() > MAX_HEAD_SIZE {
+                        bail!("HTTP/1.1 chunk 长度行过长");
+                    }
+                }
+                BodyState::ChunkEnd if self.buf.len() >= 2 => {
+                    if &self.buf[..2] != b"\r\n" {
+                        bail!("HTTP/1.1 chunk 结尾缺少 CRLF");
+                    }
+                    self.buf.advance(2);
+                    *state = BodyState::ChunkSize;
+                    continue;
+                }
+                _ => {}
+            }
+            if !self.fill().await? {
+                bail!("HTTP/1.1 请求体不完整");
+            }
+        }
+    }
+}
+
+/// HTTP/1.1 XHTTP 处理器
+///
+/// 与 `H2Handler` 相同，克隆共享同一组关闭信号: `shutdown` 后连接处理完当前请求即关闭，
+/// `terminate` 立即结束仍在进行的请求。
+#[derive(Clone)]
+pub struct H1Handler {
+    config: XhttpConfig,
+    sessions: Sessions,
+    draining: CancellationToken,
+    terminating: CancellationToken,
+}
+
+impl H1Handler {
+    pub fn new(config: XhttpConfig) -> Self {
+        Self {
+            config,
+            sessions: Sessions::default(),
+            draining: CancellationToken::new(),
+            terminating: CancellationToken::new(),
+        }
+    }
+
+    /// 使用指定的会话表，同一 `XhttpServer` 的 h2 与 HTTP/1.1 连接共用一张表以便互相配对
+    pub(super) fn with_sessions(mut self, sessions: Sessions) -> Self {
+        self.sessions = sessions;
+        self
+    }
+
+    /// 开始优雅关闭: 不再读取新的请求，进行中的会话继续转发
+    pub fn shutdown(&self) {
+        self.draining.cancel();
+    }
+
+    /// 终止所有进行中的请求与会话 (排空超时后调用)
+    pub fn terminate(&self) {
+        self.draining.cancel();
+        self.terminating.cancel();
+    }
+
+    /// 处理来自 `peer` 的 HTTP/1.1 连接
+    pub async fn handle<T, F, Fut>(&self, stream: T, peer: IpAddr, handler: F) -> Result<()>
+    where
+        T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        F: Fn(Box<dyn crate::server::AsyncStream>) -> Fut + Clone + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        tokio::select! {
+            result = serve(self.config.clone(), self.sessions.clone(), peer, stream, handler, self.draining.clone()) => result,
+            _ = self.terminating.cancelled() => Ok(()),
+        }
+    }
+}
+
+/// 在一条 HTTP/1.1 连接上依次处理请求，`draining` 触发后不再读取新的请求
+async fn serve<T, F, Fut>(
+    config: XhttpConfig,
+    sessions: Sessions,
+    peer: IpAddr,
+    stream: T,
+    handler: F,
+    draining: CancellationToken,
+) -> Result<()>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    F: Fn(Box<dyn crate::server::AsyncStream>) -> Fut + Clone + Send + Sync + 'static,
+    Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+{
+    debug!("XHTTP: 使用 HTTP/1.1");
+    let (io, mut writer) = tokio::io::split(stream);
+    let mut reader = Reader { io, buf: BytesMut::with_capacity(16 * 1024) };
+
+    loop {
+        let head = tokio::select! {
+            head = reader.read_head() => head?,
+            _ = draining.cancelled() => None,
+        };
+        let Some(mut head) = head else {
+            return Ok(());
+        };
+
+        // 请求处理结束 (进入下一轮循环或连接关闭) 时释放
+        let Some(_stream_permit) = sessions.begin_stream(peer, &config) else {
+            write_empty_response(&mut writer, &extra_headers(config.x_padding_bytes, &[]), StatusCode::TOO_MANY_REQUESTS, false).await?;
+            return Ok(());
+        };
+        let host = head.headers.get("host").and_then(|v| v.to_str().ok());
+        let route = session::route(&config, &sessions, peer, &head.method, host, &head.path, head.query.as_deref(), &head.headers)
+            .await;
+        let origin = cors::allowed(&config, &head.headers);
+        let cors_headers: Vec<_> = origin.iter().flat_map(cors::response_headers).collect();
+        match route {
+            Route::Reject(status) => {
+                // 请求体没有读取，回复后关闭连接
+                write_empty_response(&mut writer, &extra_headers(config.x_padding_bytes, &[]), status, false).await?;
+                return Ok(());
+            }
+            Route::Preflight => {
+                while reader.read_body(&mut head.body).await?.is_some() {}
+                let headers = match &origin {
+                    Some(origin) => cors::preflight_headers(origin, &head.headers),
+                    None => Vec::new(),
+                };
+                let headers = extra_headers(config.x_padding_bytes, &headers);
+                write_empty_response(&mut writer, &headers, StatusCode::NO_CONTENT, head.keep_alive).await?;
+                if !head.keep_alive {
+                    return Ok(());
+                }
+            }
+            Route::Upload(uplink, seq) => {
+                let max_bytes = config.sc_max_each_post_bytes;
+                if matches!(head.body, BodyState::Length(len) if len > max_bytes as u64) {
+                    write_empty_response(&mut writer, &extra_headers(config.x_padding_bytes, &cors_headers), StatusCode::PAYLOAD_TOO_LARGE, false).await?;
+                    return Ok(());
+                }
+                let mut received = 0;
+                let mut packet = BytesMut::new();
+                while let Some(chunk) = reader.read_body(&mut head.body).await? {
+                    received += chunk.len();
+                    if received > max_bytes {
+                        debug!("XHTTP: POST 超过 scMaxEachPostBytes ({} 字节)，关闭连接", max_bytes);
+                        write_empty_response(&mut writer, &extra_headers(config.x_padding_bytes, &cors_headers), StatusCode::PAYLOAD_TOO_LARGE, false).await?;
+                        return Ok(());
+                    }
+                    if seq.is_some() {
+                        // packet-up: 整段缓存，读完后按 seq 交付
+                        packet.extend_from_slice(&chunk);
+                    } else if uplink.send(chunk).await.is_err() {
+                        // 会话缓存满时停止读取，反压到客户端；会话已结束时不回复，直接关闭连接
+                        debug!("XHTTP: 会话已结束，关闭上行连接");
+                        return Ok(());
+                    }
+                }
+                if let Some(seq) = seq {
+                    match uplink.push(seq, packet.freeze()).await {
+                        Ok(()) => {}
+                        Err(PushError::OutOfWindow) => {
+                            write_empty_response(&mut writer, &extra_headers(config.x_padding_bytes, &cors_headers), StatusCode::BAD_REQUEST, false).await?;
+                            return Ok(());
+                        }
+                        Err(PushError::Closed) => {
+                            debug!("XHTTP: 会话已结束，关闭上行连接");
+                            return Ok(());
+                        }
+                    }
+                }
+                write_empty_response(&mut writer, &extra_headers(config.x_padding_bytes, &cors_headers), StatusCode::OK, head.keep_alive).await?;
+                if !head.keep_alive {
+                    return Ok(());
+                }
+            }
+            // 下行与 stream-one 的响应一直持续到会话结束，连接不再复用
+            Route::Download(id, permit) => {
+                let (mut to_vless_rx, closed, session) = sessions.open(id, permit, &config);
+                let (client_io, server_io) = tokio::io::duplex(65536);
+                tokio::spawn(handler(Box::new(server_io)));
+                let (client_read, mut client_write) = tokio::io::split(client_io);
+
+                let upstream = tokio::spawn(async move {
+                    while let Some(data) = to_vless_rx.recv().await {
+                        client_write.write_all(&data).await?;
+                    }
+                    Ok::<(), anyhow::Error>(())
+                });
+                let headers = extra_headers(config.x_padding_bytes, &cors_headers);
+                let result = tokio::select! {
+                    result = send_downlink(&mut writer, client_read, head.http11, &headers, Some(session.activity())) => result,
+                    _ = closed.cancelled() => Ok(()),
+                };
+                upstream.abort();
+                return result;
+            }
+            // HTTP/1.1 上没有 gRPC，stream-one 的数据不分帧
+            Route::Standalone { .. } => {
+                let (client_io, server_io) = tokio::io::duplex(65536);
+                tokio::spawn(handler(Box::new(server_io)));
+                let (client_read, mut client_write) = tokio::io::split(client_io);
+
+                tokio::spawn(async move {
+                    while let Some(chunk) = reader.read_body(&mut head.body).await? {
+                        client_write.write_all(&chunk).await?;
+                    }
+                    Ok::<(), anyhow::Error>(())
+                });
+                send_downlink(&mut writer, client_read, head.http11, &extra_headers(config.x_padding_bytes, &cors_headers), None).await?;
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// 附加的响应头部行: 给定的头 (如 CORS) 与按 xPaddingBytes 生成的 X-Padding 头，后者范围为 0 时省略
+fn extra_headers(padding: PaddingRange, headers: &[(&str, HeaderValue)]) -> String {
+    let mut lines = String::new();
+    for (name, value) in headers {
+        if let Ok(value) = value.to_str() {
+            // 与常见服务器一致，按单词首字母大写输出头部名称
+            let name: Vec<String> = name
+                .split('-')
+                .map(|word| {
+                    let mut chars = word.chars();
+                    chars.next().map(|first| first.to_ascii_uppercase().to_string() + chars.as_str()).unwrap_or_default()
+                })
+                .collect();
+            lines.push_str(&format!("{}: {}\r\n", name.join("-"), value));
+        }
+    }
+    if let Some(value) = padding.sample() {
+        lines.push_str(&format!("X-Padding: {}\r\n", value));
+    }
+    lines
+}
+
+/// 回复不带响应体的响应
+async fn write_empty_response<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    headers: &str,
+    status: StatusCode,
+    keep_alive: bool,
+) -> Result<()> {
+    // 204 响应不能带 Content-Length
+    let length = if status == StatusCode::NO_CONTENT { "" } else { "Content-Length: 0\r\n" };
+    let response = format!(
+        "HTTP/1.1 {} {}\r\n{}{}Connection: {}\r\n\r\n",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or(""),
+        length,
+        headers,
+        if keep_alive { "keep-alive" } else { "close" },
+    );
+    writer.write_all(response.as_bytes()).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// 把内层会话的输出作为响应体发送: HTTP/1.1 使用 chunked 编码，HTTP/1.0 直接写出并以关闭连接结束
+async fn send_downlink<T: AsyncRead + AsyncWrite>(
+    writer: &mut WriteHalf<T>,
+    mut client_read: impl AsyncRead + Unpin,
+    chunked: bool,
+    headers: &str,
+    activity: Option<Activity>,
+) -> Result<()> {
+    let framing = if chunked { "Transfer-Encoding: chunked" } else { "Connection: close" };
+    let head = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\n{}\r\n{}Cache-Control: no-store\r\n\r\n",
+        framing,
+        headers,
+    );
+    writer.write_all(head.as_bytes()).await?;
+    writer.flush().await?;
+
+    let mut buf = BytesMut::with_capacity(65536);
+    loop {
+        if buf.capacity() < 2048 {
+            buf.reserve(65536);
+        }
+        let n = client_read.read_buf(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        if let Some(activity) = &activity {
+            activity.touch();
+        }
+        let chunk = buf.split_to(n);
+        if chunked {
+            writer.write_all(format!("{:x}\r\n", n).as_bytes()).await?;
+            writer.write_all(&chunk).await?;
+            writer.write_all(b"\r\n").await?;
+        } else {
+            writer.write_all(&chunk).await?;
+        }
+        writer.flush().await?;
+    }
+    if chunked {
+        writer.write_all(b"0\r\n\r\n").await?;
+    }
+    writer.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 把原始请求写入内存管道，返回读取端
+    async fn open_reader(raw: &'static [u8]) -> Reader<tokio::io::DuplexStream> {
+        let (mut client, server) = tokio::io::duplex(4096);
+        client.write_all(raw).await.unwrap();
+        drop(client);
+        let (io, _) = tokio::io::split(server);
+        Reader { io, buf: BytesMut::new() }
+    }
+
+    async fn read_all(reader: &mut Reader<tokio::io::DuplexStream>, state: &mut BodyState) -> Vec<u8> {
+        let mut body = Vec::new();
+        while let Some(chunk) = reader.read_body(state).await.unwrap() {
+            body.extend_from_slice(&chunk);
+        }
+        body
+    }
+
+    #[tokio::test]
+    async fn test_chunked_requests_keep_alive() {
+        let mut reader = open_reader(
+            b"POST /xh/s/0 HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n\
+              5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nX-Trailer: 1\r\n\r\n\
+              POST http://a/xh/s/1?x=1 HTTP/1.1\r\nContent-Length: 3\r\n\r\nabc",
+        )
+        .await;
+
+        let mut head = reader.read_head().await.unwrap().unwrap();
+        assert_eq!(head.method, Method::POST);
+        assert_eq!(head.path, "/xh/s/0");
+        assert!(head.http11 && head.keep_alive);
+        assert_eq!(read_all(&mut reader, &mut head.body).await, b"hello world");
+
+        // 第二个请求使用绝对形式的目标与 Content-Length
+        let mut head = reader.read_head().await.unwrap().unwrap();
+        assert_eq!(head.path, "/xh/s/1");
+        assert_eq!(head.query.as_deref(), Some("x=1"));
+        assert_eq!(read_all(&mut reader, &mut head.body).await, b"abc");
+        assert!(reader.read_head().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_http10_closes_by_default() {
+        let mut reader = open_reader(b"GET /xh/s HTTP/1.0\r\n\r\n").await;
+        let head = reader.read_head().await.unwrap().unwrap();
+        assert!(!head.http11 && !head.keep_alive);
+
+        let mut reader = open_reader(b"POST /xh HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhel").await;
+        let mut head = reader.read_head().await.unwrap().unwrap();
+        assert_eq!(reader.read_body(&mut head.body).await.unwrap().unwrap(), Bytes::from_static(b"hel"));
+        assert!(reader.read_body(&mut head.body).await.is_err());
+    }
+}
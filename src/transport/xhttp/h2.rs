@@ -1,50 +1,77 @@
 use anyhow::{anyhow, Result};
 use bytes::{Buf, Bytes, BytesMut};
 use h2::server::{self, SendResponse};
-use hyper::http::{Request, Response, StatusCode};
+use hyper::http::{response, HeaderValue, Request, Response, StatusCode};
 use tokio::io::{AsyncRead, AsyncWrite};
-use tokio::sync::{mpsc, Notify};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, info, warn};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::net::IpAddr;
 use std::time::Duration;
-use once_cell::sync::Lazy;
-use rand::{distributions::Alphanumeric, Rng};
 
-use super::XhttpConfig;
-
-/// 全局会话管理器
-struct Session {
-    to_vless_tx: mpsc::UnboundedSender<Bytes>,
-    notify: Arc<Notify>,
-}
-
-static SESSIONS: Lazy<Arc<Mutex<HashMap<String, Session>>>> = Lazy::new(|| {
-    Arc::new(Mutex::new(HashMap::new()))
-});
+use super::limit::IpPermit;
+use super::session::{self, PushError, Route, Sessions, Uplink};
+use super::{cors, XhttpConfig};
 
 /// 终极 H2/XHTTP 处理器 (v0.2.74: 带全域静默 Padding)
+///
+/// 克隆共享同一组关闭信号: `shutdown` 向所有连接发送 GOAWAY 并停止接受新请求，
+/// `terminate` 立即结束仍在进行的请求。
 #[derive(Clone)]
 pub struct H2Handler {
     config: XhttpConfig,
+    sessions: Sessions,
+    draining: CancellationToken,
+    terminating: CancellationToken,
 }
 
 impl H2Handler {
     pub fn new(config: XhttpConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            sessions: Sessions::default(),
+            draining: CancellationToken::new(),
+            terminating: CancellationToken::new(),
+        }
+    }
+
+    /// 使用指定的会话表，同一 `XhttpServer` 的 h2 与 HTTP/1.1 连接共用一张表以便互相配对
+    pub(super) fn with_sessions(mut self, sessions: Sessions) -> Self {
+        self.sessions = sessions;
+        self
+    }
+
+    /// 开始优雅关闭: 发送 GOAWAY，已建立的会话继续转发直到自然结束
+    pub fn shutdown(&self) {
+        self.draining.cancel();
     }
 
-    /// 生成随机 Padding 字符串，用于模糊 HTTP 头部长度
-    fn gen_padding() -> String {
-        let mut rng = rand::thread_rng();
-        let len = rng.gen_range(64..512); // 随机 64 到 512 字节
-        rng.sample_iter(&Alphanumeric)
-            .take(len)
-            .map(char::from)
-            .collect()
+    /// 终止所有进行中的请求与会话 (排空超时后调用)
+    ///
+    /// 下行会话以 END_STREAM 正常结束，客户端收到已发出的全部数据；其余的流随连接关闭被重置。
+    pub fn terminate(&self) {
+        self.sessions.close_all();
+        self.draining.cancel();
+        self.terminating.cancel();
     }
 
-    pub async fn handle<T, F, Fut>(&self, stream: T, handler: F) -> Result<()>
+    /// 加上配置的伪装响应头，再按 xPaddingBytes 加上随机长度的 x-padding 头，用于模糊 HTTP 头部长度
+    ///
+    /// 允许的浏览器来源 (`origin`) 同时加上 CORS 头。
+    pub(super) fn with_padding(builder: response::Builder, config: &XhttpConfig, origin: Option<&HeaderValue>) -> response::Builder {
+        let mut builder = config.response_headers.apply(builder);
+        if let Some(origin) = origin {
+            for (name, value) in cors::response_headers(origin) {
+                builder = builder.header(name, value);
+            }
+        }
+        match config.x_padding_bytes.sample() {
+            Some(value) => builder.header("x-padding", value),
+            None => builder,
+        }
+    }
+
+    /// 处理来自 `peer` 的 h2 连接，每个请求在独立的任务中处理
+    pub async fn handle<T, F, Fut>(&self, stream: T, peer: IpAddr, handler: F) -> Result<()>
     where
         T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
         F: Fn(Box<dyn crate::server::AsyncStream>) -> Fut + Clone + Send + Sync + 'static,
@@ -52,27 +79,96 @@ impl H2Handler {
     {
         debug!("XHTTP: 启动 V74 全域静默填充引擎");
 
+        let settings = self.config.h2_settings;
         let mut builder = server::Builder::new();
         builder
-            .initial_window_size(524288) // 512KB (Xray default)
-            .max_concurrent_streams(500)
-            .max_frame_size(16384);
+            .initial_window_size(settings.initial_stream_window)
+            .initial_connection_window_size(settings.initial_conn_window)
+            .max_concurrent_streams(settings.max_concurrent_streams)
+            .max_frame_size(settings.max_frame_size);
 
         let mut connection = builder.handshake(stream).await?;
-        
-        while let Some(result) = connection.accept().await {
-            match result {
-                Ok((request, respond)) => {
-                    let config = self.config.clone();
-                    let handler = handler.clone();
-                    tokio::spawn(async move {
-                        if let Err(e) = Self::handle_request(config, request, respond, handler).await {
-                            debug!("连接处理闭合: {}", e);
-                        }
-                    });
+        let mut draining = false;
+
+        // 保活超时后关闭连接，并结束其上仍在进行的请求
+        let dead = CancellationToken::new();
+        let keepalive_stop = CancellationToken::new();
+        let _keepalive_guard = keepalive_stop.clone().drop_guard();
+        if self.config.keepalive_period > 0 {
+            if let Some(ping_pong) = connection.ping_pong() {
+                let period = Duration::from_secs(self.config.keepalive_period);
+                let timeout = Duration::from_secs(self.config.keepalive_timeout);
+                let dead = dead.clone();
+                tokio::spawn(async move {
+                    tokio::select! {
+                        _ = Self::keepalive(ping_pong, period, timeout, dead) => {}
+                        _ = keepalive_stop.cancelled() => {}
+                    }
+                });
+            }
+        }
+
+        loop {
+            tokio::select! {
+                result = connection.accept() => match result {
+                    Some(Ok((request, mut respond))) => {
+                        // 超出来源 IP 的请求数上限时直接拒绝，不为其启动任务
+                        let Some(stream_permit) = self.sessions.begin_stream(peer, &self.config) else {
+                            let _ = Self::send_error_response(&mut respond, StatusCode::TOO_MANY_REQUESTS, &self.config).await;
+                            continue;
+                        };
+                        let config = self.config.clone();
+                        let sessions = self.sessions.clone();
+                        let handler = handler.clone();
+                        let terminating = self.terminating.clone();
+                        let dead = dead.clone();
+                        tokio::spawn(async move {
+                            let _stream_permit = stream_permit;
+                            tokio::select! {
+                                // 先检查请求本身: 会话被关闭的下行在同一次轮询中正常结束
+                                biased;
+                                result = Self::handle_request(config, sessions, peer, request, respond, handler) => {
+                                    if let Err(e) = result {
+                                        debug!("连接处理闭合: {}", e);
+                                    }
+                                }
+                                _ = terminating.cancelled() => debug!("XHTTP: 关闭时终止进行中的请求"),
+                                _ = dead.cancelled() => debug!("XHTTP: 连接已失效，终止进行中的请求"),
+                            }
+                        });
+                    }
+                    Some(Err(e)) => {
+                        debug!("H2 连接中断: {}", e);
+                        break;
+                    }
+                    None => break,
+                },
+                _ = dead.cancelled() => {
+                    debug!("XHTTP: PING 超时，关闭连接");
+                    break;
                 }
-                Err(e) => {
-                    debug!("H2 连接中断: {}", e);
+                _ = self.draining.cancelled(), if !draining => {
+                    // 客户端收到 GOAWAY 后改用新连接，已有的流继续到结束
+                    draining = true;
+                    debug!("XHTTP: 发送 GOAWAY，等待进行中的请求结束");
+                    connection.graceful_shutdown();
+                }
+                _ = self.terminating.cancelled() => {
+                    if !draining {
+                        connection.graceful_shutdown();
+                    }
+                    // 继续驱动连接，发出已结束的下行剩余的数据；仍未结束的流在超时后被重置
+                    let closed = tokio::time::timeout(Duration::from_secs(1), async {
+                        while connection.accept().await.is_some() {}
+                    })
+                    .await;
+                    if closed.is_err() {
+                        connection.abrupt_shutdown(h2::Reason::NO_ERROR);
+                        let _ = tokio::time::timeout(Duration::from_secs(1), async {
+                            while connection.accept().await.is_some() {}
+                        })
+                        .await;
+                    }
                     break;
                 }
             }
@@ -80,8 +176,30 @@ impl H2Handler {
         Ok(())
     }
 
+    /// 每隔 `period` 发送一次 PING，`timeout` 内没有收到应答时标记连接失效
+    ///
+    /// 中间的 NAT 丢弃映射后，空闲的下行连接两端都不会察觉，直到下一次写入失败。
+    async fn keepalive(mut ping_pong: h2::PingPong, period: Duration, timeout: Duration, dead: CancellationToken) {
+        loop {
+            tokio::time::sleep(period).await;
+            match tokio::time::timeout(timeout, ping_pong.ping(h2::Ping::opaque())).await {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => {
+                    debug!("XHTTP: 发送 PING 失败: {}", e);
+                    return;
+                }
+                Err(_) => {
+                    dead.cancel();
+                    return;
+                }
+            }
+        }
+    }
+
     async fn handle_request<F, Fut>(
         config: XhttpConfig,
+        sessions: Sessions,
+        peer: IpAddr,
         request: Request<h2::RecvStream>,
         mut respond: SendResponse<Bytes>,
         handler: F,
@@ -90,46 +208,43 @@ impl H2Handler {
         F: Fn(Box<dyn crate::server::AsyncStream>) -> Fut + Clone + Send + 'static,
         Fut: std::future::Future<Output = Result<()>> + Send + 'static,
     {
-        let path = request.uri().path().to_string();
-        let method = request.method();
-        
-        if !path.starts_with(&config.path) {
-            Self::send_error_response(&mut respond, StatusCode::NOT_FOUND).await?;
-            return Ok(());
-        }
-
-        if method == "GET" {
-            Self::handle_xhttp_get(path, respond, handler).await?;
-        } else if method == "POST" {
-            let user_agent = request.headers().get("user-agent").and_then(|v| v.to_str().ok()).unwrap_or("");
-            let is_pc = user_agent.contains("Go-http-client");
-
-            // 等候配对逻辑
-            if !is_pc {
-                for _ in 0..10 {
-                    let found = {
-                        let sessions = SESSIONS.lock().unwrap();
-                        sessions.contains_key(&path)
-                    };
-                    if found { break; }
-                    tokio::time::sleep(Duration::from_millis(50)).await;
+        // h2 客户端通过 :authority 携带主机名，经 HTTP/1.1 转换过来的请求可能只有 Host 头
+        let authority = request
+            .uri()
+            .authority()
+            .map(|authority| authority.as_str())
+            .or_else(|| request.headers().get("host").and_then(|v| v.to_str().ok()));
+        let route = session::route(
+            &config,
+            &sessions,
+            peer,
+            request.method(),
+            authority,
+            request.uri().path(),
+            request.uri().query(),
+            request.headers(),
+        )
+        .await;
+        let origin = cors::allowed(&config, request.headers());
+        match route {
+            Route::Reject(status) => Self::send_error_response(&mut respond, status, &config).await?,
+            Route::Preflight => {
+                let mut response = Response::builder().status(StatusCode::NO_CONTENT);
+                if let Some(origin) = &origin {
+                    for (name, value) in cors::preflight_headers(origin, request.headers()) {
+                        response = response.header(name, value);
+                    }
                 }
+                let response = Self::with_padding(response, &config, None).body(()).unwrap();
+                respond.send_response(response, true)?;
             }
-
-            let session_tx = {
-                let sessions = SESSIONS.lock().unwrap();
-                sessions.get(&path).map(|s| s.to_vless_tx.clone())
-            };
-
-            if let Some(tx) = session_tx {
-                Self::handle_xhttp_post(request, respond, tx).await?;
-            } else {
-                let content_type = request.headers().get("content-type").and_then(|v| v.to_str().ok()).unwrap_or("");
-                let is_grpc = content_type.contains("grpc") && !is_pc;
-                Self::handle_standalone(request, respond, handler, is_grpc).await?;
+            Route::Download(id, permit) => {
+                Self::handle_xhttp_get(id, permit, &config, &sessions, origin, respond, handler).await?
+            }
+            Route::Upload(uplink, seq) => Self::handle_xhttp_post(request, respond, uplink, seq, &config, origin).await?,
+            Route::Standalone { grpc } => {
+                Self::handle_standalone(request, respond, handler, grpc, &config, origin).await?
             }
-        } else {
-            Self::send_error_response(&mut respond, StatusCode::METHOD_NOT_ALLOWED).await?;
         }
         Ok(())
     }
@@ -139,6 +254,8 @@ impl H2Handler {
         mut respond: SendResponse<Bytes>,
         handler: F,
         is_grpc: bool,
+        config: &XhttpConfig,
+        origin: Option<HeaderValue>,
     ) -> Result<()>
     where
         F: Fn(Box<dyn crate::server::AsyncStream>) -> Fut + Clone + Send + 'static,
@@ -146,10 +263,8 @@ impl H2Handler {
     {
         let response = Response::builder()
             .status(StatusCode::OK)
-            .header("content-type", if is_grpc { "application/grpc" } else { "application/octet-stream" })
-            .header("x-padding", Self::gen_padding()) // 注入动态填充
-            .body(())
-            .unwrap();
+            .header("content-type", if is_grpc { "application/grpc" } else { "application/octet-stream" });
+        let response = Self::with_padding(response, config, origin.as_ref()).body(()).unwrap();
 
         let mut send_stream = respond.send_response(response, false)?;
         let (client_io, server_io) = tokio::io::duplex(65536);
@@ -222,7 +337,11 @@ impl H2Handler {
     }
 
     async fn handle_xhttp_get<F, Fut>(
-        path: String,
+        id: String,
+        permit: IpPermit,
+        config: &XhttpConfig,
+        sessions: &Sessions,
+        origin: Option<HeaderValue>,
         mut respond: SendResponse<Bytes>,
         handler: F,
     ) -> Result<()>
@@ -230,12 +349,8 @@ impl H2Handler {
         F: Fn(Box<dyn crate::server::AsyncStream>) -> Fut + Clone + Send + 'static,
         Fut: std::future::Future<Output = Result<()>> + Send + 'static,
     {
-        let (to_vless_tx, mut to_vless_rx) = mpsc::unbounded_channel::<Bytes>();
-        let notify = Arc::new(Notify::new());
-        {
-            let mut sessions = SESSIONS.lock().unwrap();
-            sessions.insert(path.clone(), Session { to_vless_tx, notify: notify.clone() });
-        }
+        let (mut to_vless_rx, closed, session) = sessions.open(id, permit, config);
+        let activity = session.activity();
 
         let (client_io, server_io) = tokio::io::duplex(65536);
         tokio::spawn(handler(Box::new(server_io)));
@@ -243,13 +358,11 @@ impl H2Handler {
 
         let response = Response::builder()
             .status(StatusCode::OK)
-            .header("content-type", "application/octet-stream")
-            .header("x-padding", Self::gen_padding()) // 注入动态填充
-            .body(())
-            .unwrap();
+            .header("content-type", "application/octet-stream");
+        let response = Self::with_padding(response, config, origin.as_ref()).body(()).unwrap();
         let mut send_stream = respond.send_response(response, false)?;
 
-        let downstream = async move {
+        let downstream = async {
             let mut buf = BytesMut::with_capacity(65536);
             use tokio::io::AsyncReadExt;
             loop {
@@ -258,10 +371,10 @@ impl H2Handler {
                 }
                 let n = client_read.read_buf(&mut buf).await?;
                 if n == 0 { break; }
+                activity.touch();
                 let chunk = buf.split_to(n).freeze();
                 send_stream.send_data(chunk, false)?;
             }
-            send_stream.send_data(Bytes::new(), true)?;
             Ok::<(), anyhow::Error>(())
         };
 
@@ -273,33 +386,65 @@ impl H2Handler {
             Ok::<(), anyhow::Error>(())
         };
 
-        let _ = tokio::spawn(upstream);
-        let _ = downstream.await;
-        
-        {
-            let mut sessions = SESSIONS.lock().unwrap();
-            sessions.remove(&path);
+        // 会话结束时停止接收上行数据，仍在上传的 POST 随之被重置
+        let upstream = tokio::spawn(upstream);
+        tokio::select! {
+            _ = downstream => {}
+            _ = closed.cancelled() => {}
         }
-        notify.notify_waiters();
+        upstream.abort();
+        // 会话结束 (包括被替换、空闲超时与关闭时终止) 时以 END_STREAM 结束下行，而不是重置
+        send_stream.send_data(Bytes::new(), true)?;
         Ok(())
     }
 
     async fn handle_xhttp_post(
         request: Request<h2::RecvStream>,
         mut respond: SendResponse<Bytes>,
-        tx: mpsc::UnboundedSender<Bytes>,
+        uplink: Uplink,
+        seq: Option<u64>,
+        config: &XhttpConfig,
+        origin: Option<HeaderValue>,
     ) -> Result<()> {
+        let max_bytes = config.sc_max_each_post_bytes;
         let mut body = request.into_body();
+        let mut received = 0;
+        let mut packet = BytesMut::new();
         while let Some(chunk_res) = body.data().await {
             let chunk = chunk_res?;
-            let _ = body.flow_control().release_capacity(chunk.len());
-            let _ = tx.send(chunk);
+            let len = chunk.len();
+            received += len;
+            if received > max_bytes {
+                debug!("XHTTP: POST 超过 scMaxEachPostBytes ({} 字节)，重置请求", max_bytes);
+                respond.send_reset(h2::Reason::CANCEL);
+                return Ok(());
+            }
+            if seq.is_some() {
+                // packet-up: 整段缓存，读完后按 seq 交付
+                packet.extend_from_slice(&chunk);
+            } else if uplink.send(chunk).await.is_err() {
+                // 会话收下数据后才归还窗口，VLESS 一侧读取变慢时客户端随之停止发送
+                debug!("XHTTP: 会话已结束，重置上行请求");
+                respond.send_reset(h2::Reason::CANCEL);
+                return Ok(());
+            }
+            let _ = body.flow_control().release_capacity(len);
         }
-        let response = Response::builder()
-            .status(StatusCode::OK)
-            .header("x-padding", Self::gen_padding()) // 注入动态填充
-            .body(())
-            .unwrap();
+        if let Some(seq) = seq {
+            match uplink.push(seq, packet.freeze()).await {
+                Ok(()) => {}
+                Err(PushError::OutOfWindow) => {
+                    return Self::send_error_response(&mut respond, StatusCode::BAD_REQUEST, config).await;
+                }
+                Err(PushError::Closed) => {
+                    debug!("XHTTP: 会话已结束，重置上行请求");
+                    respond.send_reset(h2::Reason::CANCEL);
+                    return Ok(());
+                }
+            }
+        }
+        let response = Response::builder().status(StatusCode::OK);
+        let response = Self::with_padding(response, config, origin.as_ref()).body(()).unwrap();
         respond.send_response(response, true)?;
         Ok(())
     }
@@ -307,9 +452,736 @@ impl H2Handler {
     async fn send_error_response(
         respond: &mut SendResponse<Bytes>,
         status: StatusCode,
+        config: &XhttpConfig,
     ) -> Result<()> {
-        let response = Response::builder().status(status).body(()).unwrap();
+        let response = Response::builder().status(status);
+        let response = config.response_headers.apply(response).body(()).unwrap();
         respond.send_response(response, true)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::xhttp::{H2Settings, PaddingRange, XhttpMode};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// 测试连接的来源地址
+    const PEER: IpAddr = IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+
+    /// 在内存管道上启动 XHTTP 处理器，内层会话回显收到的数据，收到 `bye` 后结束
+    async fn start(
+        handler: &H2Handler,
+    ) -> (h2::client::SendRequest<Bytes>, tokio::task::JoinHandle<Result<()>>) {
+        start_from(handler, PEER).await
+    }
+
+    /// 与 [`start`] 相同，连接来自 `peer`
+    async fn start_from(
+        handler: &H2Handler,
+        peer: IpAddr,
+    ) -> (h2::client::SendRequest<Bytes>, tokio::task::JoinHandle<Result<()>>) {
+        let echo = |mut stream: Box<dyn crate::server::AsyncStream>| async move {
+            let mut buf = [0u8; 1024];
+            loop {
+                let n = stream.read(&mut buf).await?;
+                if n == 0 {
+                    return Ok(());
+                }
+                stream.write_all(&buf[..n]).await?;
+                if buf[..n].ends_with(b"bye") {
+                    return Ok(());
+                }
+            }
+        };
+        start_with(handler, peer, echo).await
+    }
+
+    async fn start_with<F, Fut>(
+        handler: &H2Handler,
+        peer: IpAddr,
+        inner: F,
+    ) -> (h2::client::SendRequest<Bytes>, tokio::task::JoinHandle<Result<()>>)
+    where
+        F: Fn(Box<dyn crate::server::AsyncStream>) -> Fut + Clone + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        let (client_io, server_io) = tokio::io::duplex(1 << 16);
+        let server = tokio::spawn({
+            let handler = handler.clone();
+            async move { handler.handle(server_io, peer, inner).await }
+        });
+        let (client, connection) = h2::client::handshake(client_io).await.unwrap();
+        tokio::spawn(connection);
+        (client, server)
+    }
+
+    fn handler() -> H2Handler {
+        handler_with_mode(XhttpMode::Auto)
+    }
+
+    fn handler_with_mode(mode: XhttpMode) -> H2Handler {
+        H2Handler::new(config(mode))
+    }
+
+    fn config(mode: XhttpMode) -> XhttpConfig {
+        XhttpConfig {
+            mode,
+            path: "/drain".to_string(),
+            host: String::new(),
+            path_secret: String::new(),
+            sc_max_each_post_bytes: crate::transport::xhttp::DEFAULT_SC_MAX_EACH_POST_BYTES,
+            sc_max_buffered_posts: crate::transport::xhttp::DEFAULT_SC_MAX_BUFFERED_POSTS,
+            x_padding_bytes: PaddingRange::default(),
+            session_idle_timeout: crate::transport::xhttp::DEFAULT_SESSION_IDLE_TIMEOUT,
+            no_grpc_header: false,
+            h2_settings: Default::default(),
+            keepalive_period: 0,
+            keepalive_timeout: crate::transport::xhttp::DEFAULT_KEEPALIVE_TIMEOUT,
+            response_headers: Default::default(),
+            cors_origins: Vec::new(),
+            max_sessions_per_ip: 0,
+            max_streams_per_ip: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drains_active_stream() {
+        let handler = handler();
+        let (mut client, server) = start(&handler).await;
+
+        let request = Request::post("https://example.com/drain/one").body(()).unwrap();
+        let (response, mut upload) = client.send_request(request, false).unwrap();
+        upload.send_data(Bytes::from_static(b"before"), false).unwrap();
+        let mut body = response.await.unwrap().into_body();
+        assert_eq!(body.data().await.unwrap().unwrap(), Bytes::from_static(b"before"));
+
+        // GOAWAY 之后已有的流继续转发
+        handler.shutdown();
+        upload.send_data(Bytes::from_static(b"after"), false).unwrap();
+        assert_eq!(body.data().await.unwrap().unwrap(), Bytes::from_static(b"after"));
+        assert!(!server.is_finished());
+
+        // 会话结束后流关闭，没有剩余的流时连接随之关闭
+        upload.send_data(Bytes::from_static(b"bye"), true).unwrap();
+        assert_eq!(body.data().await.unwrap().unwrap(), Bytes::from_static(b"bye"));
+        while let Some(chunk) = body.data().await {
+            assert!(chunk.unwrap().is_empty());
+        }
+        tokio::time::timeout(Duration::from_secs(5), server).await.unwrap().unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_terminate_clears_session() {
+        let handler = handler();
+        let (mut client, server) = start(&handler).await;
+
+        let request = Request::get("https://example.com/drain/session").body(()).unwrap();
+        let (response, _upload) = client.send_request(request, true).unwrap();
+        let mut body = response.await.unwrap().into_body();
+        assert!(handler.sessions.contains("session"));
+
+        // 长连接的 packet-up 会话不会自行结束，排空超时后终止
+        handler.shutdown();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!server.is_finished());
+        assert!(handler.sessions.contains("session"));
+
+        handler.terminate();
+        tokio::time::timeout(Duration::from_secs(5), server).await.unwrap().unwrap().unwrap();
+        let ended = tokio::time::timeout(Duration::from_secs(5), body.data()).await.unwrap();
+        assert!(!matches!(ended, Some(Ok(ref data)) if !data.is_empty()));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!handler.sessions.contains("session"));
+    }
+
+    #[tokio::test]
+    async fn test_terminate_ends_download_without_reset() {
+        let handler = handler();
+        let (mut client, server) = start(&handler).await;
+
+        let mut down = open_download(&mut client, "streaming").await;
+        upload(&mut client, "/drain/streaming/0", b"sent").await;
+        assert_eq!(down.data().await.unwrap().unwrap(), Bytes::from_static(b"sent"));
+
+        // 下行仍在进行时关闭: 已发出的数据之后是 END_STREAM，而不是 RST_STREAM
+        handler.shutdown();
+        handler.terminate();
+        assert!(!handler.sessions.contains("streaming"));
+        let ended = tokio::time::timeout(Duration::from_secs(5), async {
+            while let Some(chunk) = down.data().await {
+                assert!(chunk.unwrap().is_empty());
+            }
+        })
+        .await;
+        assert!(ended.is_ok());
+        tokio::time::timeout(Duration::from_secs(5), server).await.unwrap().unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_closes_unresponsive_connection() {
+        let handler = H2Handler::new(XhttpConfig { keepalive_period: 1, keepalive_timeout: 1, ..config(XhttpMode::Auto) });
+        let echo = |mut stream: Box<dyn crate::server::AsyncStream>| async move {
+            let mut buf = [0u8; 64];
+            while stream.read(&mut buf).await? > 0 {}
+            Ok(())
+        };
+
+        // 客户端与服务端之间经过一个可以暂停的中继，模拟 NAT 丢弃映射后不再有数据往来
+        let (client_io, mut relay_client) = tokio::io::duplex(1 << 16);
+        let (mut relay_server, server_io) = tokio::io::duplex(1 << 16);
+        let paused = CancellationToken::new();
+        tokio::spawn({
+            let paused = paused.clone();
+            async move {
+                tokio::select! {
+                    _ = tokio::io::copy_bidirectional(&mut relay_client, &mut relay_server) => {}
+                    _ = paused.cancelled() => std::future::pending::<()>().await,
+                }
+            }
+        });
+        let server = tokio::spawn({
+            let handler = handler.clone();
+            async move { handler.handle(server_io, PEER, echo).await }
+        });
+        let (mut client, connection) = h2::client::handshake(client_io).await.unwrap();
+        tokio::spawn(connection);
+
+        // 正常应答 PING 时空闲的下行会话保持打开
+        let _down = open_download(&mut client, "idle").await;
+        tokio::time::sleep(Duration::from_millis(2500)).await;
+        assert!(handler.sessions.contains("idle"));
+        assert!(!server.is_finished());
+
+        paused.cancel();
+        tokio::time::timeout(Duration::from_secs(5), server).await.unwrap().unwrap().unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!handler.sessions.contains("idle"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_one_single_post() {
+        let handler = handler_with_mode(XhttpMode::StreamOne);
+        let (mut client, _server) = start(&handler).await;
+
+        // 浏览器风格的 UA 在 auto 模式下会先等待配对，stream-one 不应等待
+        let request = Request::post("https://example.com/drain/one")
+            .header("user-agent", "Mozilla/5.0")
+            .header("content-type", "application/grpc")
+            .body(())
+            .unwrap();
+        let (response, mut upload) = client.send_request(request, false).unwrap();
+        upload.send_data(Bytes::from_static(b"hello"), false).unwrap();
+        let response = tokio::time::timeout(Duration::from_millis(300), response)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut body = response.into_body();
+        // 不做 gRPC 分帧，下行原样返回
+        assert_eq!(body.data().await.unwrap().unwrap(), Bytes::from_static(b"hello"));
+        assert!(!handler.sessions.contains("one"));
+
+        upload.send_data(Bytes::from_static(b"bye"), true).unwrap();
+        assert_eq!(body.data().await.unwrap().unwrap(), Bytes::from_static(b"bye"));
+    }
+
+    #[tokio::test]
+    async fn test_no_grpc_header_skips_framing() {
+        let handler = H2Handler::new(XhttpConfig { no_grpc_header: true, ..config(XhttpMode::Auto) });
+        let (mut client, _server) = start(&handler).await;
+
+        // 没有该选项时这个请求会按 gRPC 分帧
+        let request = Request::post("https://example.com/drain")
+            .header("user-agent", "Mozilla/5.0")
+            .header("content-type", "application/grpc")
+            .body(())
+            .unwrap();
+        let (response, mut upload) = client.send_request(request, false).unwrap();
+        upload.send_data(Bytes::from_static(b"hello"), false).unwrap();
+        let response = response.await.unwrap();
+        assert_eq!(response.headers()["content-type"], "application/octet-stream");
+        let mut body = response.into_body();
+        // 没有 5 字节的 gRPC 长度前缀
+        assert_eq!(body.data().await.unwrap().unwrap(), Bytes::from_static(b"hello"));
+
+        upload.send_data(Bytes::from_static(b"bye"), true).unwrap();
+        assert_eq!(body.data().await.unwrap().unwrap(), Bytes::from_static(b"bye"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_one_rejects_get() {
+        let handler = handler_with_mode(XhttpMode::StreamOne);
+        let (mut client, _server) = start(&handler).await;
+
+        let request = Request::get("https://example.com/drain/get").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        assert_eq!(response.await.unwrap().status(), StatusCode::NOT_FOUND);
+        assert!(!handler.sessions.contains("get"));
+    }
+
+    #[tokio::test]
+    async fn test_host_mismatch_gets_decoy() {
+        let handler = H2Handler::new(XhttpConfig { host: "cdn.example.com".to_string(), ..config(XhttpMode::Auto) });
+        let (mut client, _server) = start(&handler).await;
+
+        // 主机名不符 (例如直接访问 IP) 时与未知路径一样返回 404，不登记会话
+        for uri in ["https://1.2.3.4/drain/scan", "http://other.example.com:8080/drain/scan"] {
+            let request = Request::get(uri).body(()).unwrap();
+            let (response, _) = client.send_request(request, true).unwrap();
+            assert_eq!(response.await.unwrap().status(), StatusCode::NOT_FOUND, "{}", uri);
+        }
+        assert!(!handler.sessions.contains("scan"));
+
+        let request = Request::get("https://cdn.example.com:443/drain/scan").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        assert_eq!(response.await.unwrap().status(), StatusCode::OK);
+        assert!(handler.sessions.contains("scan"));
+    }
+
+    #[tokio::test]
+    async fn test_path_secret_required() {
+        let handler = H2Handler::new(XhttpConfig {
+            host: "cdn.example.com".to_string(),
+            path_secret: "k=9f3a51".to_string(),
+            ..config(XhttpMode::Auto)
+        });
+        let (mut client, _server) = start(&handler).await;
+
+        // 缺少或带错密钥时与未知路径一样返回 404，Host 正确也不行
+        for uri in [
+            "https://cdn.example.com/drain/probe",
+            "https://cdn.example.com/drain/probe?k=9f3a5",
+            "https://cdn.example.com/drain/probe?k=9f3a511",
+            "https://cdn.example.com/drain/probe?x=k=9f3a51",
+            // 密钥正确但 Host 不符
+            "https://1.2.3.4/drain/probe?k=9f3a51",
+        ] {
+            let request = Request::get(uri).body(()).unwrap();
+            let (response, _) = client.send_request(request, true).unwrap();
+            assert_eq!(response.await.unwrap().status(), StatusCode::NOT_FOUND, "{}", uri);
+        }
+        assert!(!handler.sessions.contains("probe"));
+        let request = Request::post("https://cdn.example.com/drain").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        assert_eq!(response.await.unwrap().status(), StatusCode::NOT_FOUND);
+
+        // 两项检查都通过时正常配对
+        let request = Request::get("https://cdn.example.com/drain/probe?k=9f3a51").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        let response = response.await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut down = response.into_body();
+        assert!(handler.sessions.contains("probe"));
+        let request = Request::post("https://cdn.example.com/drain/probe/0?x=1&k=9f3a51").body(()).unwrap();
+        let (response, mut body) = client.send_request(request, false).unwrap();
+        body.send_data(Bytes::from_static(b"secret"), true).unwrap();
+        assert_eq!(response.await.unwrap().status(), StatusCode::OK);
+        assert_eq!(down.data().await.unwrap().unwrap(), Bytes::from_static(b"secret"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_up_requires_session() {
+        let handler = handler_with_mode(XhttpMode::StreamUp);
+        let (mut client, _server) = start(&handler).await;
+
+        // Go 客户端的 UA 在 auto 模式下会走独立流，配置为 stream-up 时必须配对
+        let request = Request::post("https://example.com/drain/lonely")
+            .header("user-agent", "Go-http-client/2.0")
+            .body(())
+            .unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        assert_eq!(response.await.unwrap().status(), StatusCode::NOT_FOUND);
+    }
+
+    /// 打开下行 GET，等待会话登记后返回响应体
+    async fn open_download(client: &mut h2::client::SendRequest<Bytes>, id: &str) -> h2::RecvStream {
+        let request = Request::get(format!("https://example.com/drain/{id}")).body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        response.await.unwrap().into_body()
+    }
+
+    async fn upload(client: &mut h2::client::SendRequest<Bytes>, path: &str, data: &'static [u8]) {
+        let request = Request::post(format!("https://example.com{path}")).body(()).unwrap();
+        let (response, mut body) = client.send_request(request, false).unwrap();
+        body.send_data(Bytes::from_static(data), true).unwrap();
+        assert_eq!(response.await.unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_sessions_paired_by_id() {
+        let handler = handler();
+        let (mut client, _server) = start(&handler).await;
+
+        // 两个客户端共用同一配置路径，只有 session ID 不同
+        let id_a = "6f1e0c2a-0000-4000-8000-00000000000a";
+        let id_b = "6f1e0c2a-0000-4000-8000-00000000000b";
+        let mut down_a = open_download(&mut client, id_a).await;
+        let mut down_b = open_download(&mut client, id_b).await;
+
+        upload(&mut client, &format!("/drain/{id_b}/0"), b"from-b").await;
+        upload(&mut client, &format!("/drain/{id_a}/0"), b"from-a").await;
+        assert_eq!(down_a.data().await.unwrap().unwrap(), Bytes::from_static(b"from-a"));
+        assert_eq!(down_b.data().await.unwrap().unwrap(), Bytes::from_static(b"from-b"));
+
+        upload(&mut client, &format!("/drain/{id_a}/1"), b"again-a").await;
+        assert_eq!(down_a.data().await.unwrap().unwrap(), Bytes::from_static(b"again-a"));
+    }
+
+    #[tokio::test]
+    async fn test_packet_up_delivered_in_seq_order() {
+        let handler = handler();
+        let (mut client, _server) = start(&handler).await;
+        let mut down = open_download(&mut client, "order").await;
+
+        // seq 1 的请求体先于 seq 0 完整到达，仍然排在 seq 0 之后交付
+        let request = Request::post("https://example.com/drain/order/1").body(()).unwrap();
+        let (second, mut body) = client.send_request(request, false).unwrap();
+        body.send_data(Bytes::from_static(b"world"), true).unwrap();
+        let request = Request::post("https://example.com/drain/order/0").body(()).unwrap();
+        let (first, mut body) = client.send_request(request, false).unwrap();
+        body.send_data(Bytes::from_static(b"hel"), false).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        body.send_data(Bytes::from_static(b"lo "), true).unwrap();
+        assert_eq!(first.await.unwrap().status(), StatusCode::OK);
+        assert_eq!(second.await.unwrap().status(), StatusCode::OK);
+
+        let mut received = BytesMut::new();
+        while received.len() < 11 {
+            received.extend_from_slice(&down.data().await.unwrap().unwrap());
+        }
+        assert_eq!(&received[..], b"hello world");
+    }
+
+    #[tokio::test]
+    async fn test_packet_up_seq_outside_window() {
+        let handler = H2Handler::new(XhttpConfig { sc_max_buffered_posts: 4, ..config(XhttpMode::Auto) });
+        let (mut client, _server) = start(&handler).await;
+        let mut down = open_download(&mut client, "window").await;
+
+        let post = |client: &mut h2::client::SendRequest<Bytes>, path: &str| {
+            let request = Request::post(format!("https://example.com{path}")).body(()).unwrap();
+            let (response, mut body) = client.send_request(request, false).unwrap();
+            body.send_data(Bytes::from_static(b"x"), true).unwrap();
+            response
+        };
+        // 下一个期望 seq 0，seq 4 超出 4 个 POST 的重排窗口
+        let far = post(&mut client, "/drain/window/4").await.unwrap();
+        assert_eq!(far.status(), StatusCode::BAD_REQUEST);
+        let not_number = post(&mut client, "/drain/window/x").await.unwrap();
+        assert_eq!(not_number.status(), StatusCode::BAD_REQUEST);
+
+        upload(&mut client, "/drain/window/0", b"a").await;
+        assert_eq!(down.data().await.unwrap().unwrap(), Bytes::from_static(b"a"));
+        // 已交付的 seq 不能重放
+        let replay = post(&mut client, "/drain/window/0").await.unwrap();
+        assert_eq!(replay.status(), StatusCode::BAD_REQUEST);
+        upload(&mut client, "/drain/window/4", b"b").await;
+        upload(&mut client, "/drain/window/1", b"c").await;
+    }
+
+    #[tokio::test]
+    async fn test_session_id_collision_closes_older() {
+        let handler = handler();
+        let (mut client, _server) = start(&handler).await;
+
+        let mut older = open_download(&mut client, "collide").await;
+        let mut newer = open_download(&mut client, "collide").await;
+
+        let ended = tokio::time::timeout(Duration::from_secs(5), older.data()).await.unwrap();
+        assert!(!matches!(ended, Some(Ok(ref data)) if !data.is_empty()));
+
+        // 旧会话的清理不能移除新会话
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(handler.sessions.contains("collide"));
+        upload(&mut client, "/drain/collide/0", b"newer").await;
+        assert_eq!(newer.data().await.unwrap().unwrap(), Bytes::from_static(b"newer"));
+    }
+
+    #[tokio::test]
+    async fn test_post_over_limit_is_reset() {
+        let handler = H2Handler::new(XhttpConfig { sc_max_each_post_bytes: 1000, ..config(XhttpMode::Auto) });
+        let (mut client, _server) = start(&handler).await;
+        let mut down = open_download(&mut client, "limit").await;
+
+        upload(&mut client, "/drain/limit/0", &[b'a'; 1000]).await;
+        assert_eq!(down.data().await.unwrap().unwrap().len(), 1000);
+
+        let request = Request::post("https://example.com/drain/limit/1").body(()).unwrap();
+        let (response, mut body) = client.send_request(request, false).unwrap();
+        body.send_data(Bytes::from_static(&[b'b'; 1001]), true).unwrap();
+        let err = response.await.unwrap_err();
+        assert_eq!(err.reason(), Some(h2::Reason::CANCEL));
+    }
+
+    /// 只在服务端归还窗口后才发送，返回服务端一共收下的字节数 (最多 `wanted`)
+    async fn fill_upload(body: &mut h2::SendStream<Bytes>, wanted: usize) -> usize {
+        body.reserve_capacity(wanted);
+        let mut sent = 0;
+        while sent < wanted {
+            let granted = std::future::poll_fn(|cx| body.poll_capacity(cx));
+            match tokio::time::timeout(Duration::from_millis(300), granted).await {
+                Ok(Some(Ok(n))) if n > 0 => {
+                    body.send_data(Bytes::from(vec![0u8; n]), false).unwrap();
+                    sent += n;
+                }
+                Ok(Some(Ok(_))) => {}
+                _ => break,
+            }
+        }
+        sent
+    }
+
+    /// 内层会话不读取数据时，一个 stream-one 上行流能送出的字节数
+    async fn bytes_in_flight(settings: H2Settings) -> usize {
+        let paused = |stream: Box<dyn crate::server::AsyncStream>| async move {
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            drop(stream);
+            Ok(())
+        };
+        let handler = H2Handler::new(XhttpConfig { h2_settings: settings, ..config(XhttpMode::StreamOne) });
+        let (mut client, _server) = start_with(&handler, PEER, paused).await;
+        let request = Request::post("https://example.com/drain").body(()).unwrap();
+        let (_response, mut body) = client.send_request(request, false).unwrap();
+        fill_upload(&mut body, 32 << 20).await
+    }
+
+    #[tokio::test]
+    async fn test_sessions_limited_per_ip() {
+        let handler = H2Handler::new(XhttpConfig { max_sessions_per_ip: 2, ..config(XhttpMode::Auto) });
+        let (mut client, _server) = start(&handler).await;
+        let other = IpAddr::from([192, 0, 2, 7]);
+        let (mut other_client, _other_server) = start_from(&handler, other).await;
+
+        let mut first = open_download(&mut client, "first").await;
+        let _second = open_download(&mut client, "second").await;
+        let request = Request::get("https://example.com/drain/third").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        assert_eq!(response.await.unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(!handler.sessions.contains("third"));
+        assert_eq!(handler.sessions.per_ip(PEER).0, 2);
+
+        // 另一个来源 IP 不受影响
+        let _elsewhere = open_download(&mut other_client, "elsewhere").await;
+        assert!(handler.sessions.contains("elsewhere"));
+
+        // 会话结束后归还名额
+        upload(&mut client, "/drain/first/0", b"bye").await;
+        assert_eq!(first.data().await.unwrap().unwrap(), Bytes::from_static(b"bye"));
+        while first.data().await.is_some() {}
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(handler.sessions.per_ip(PEER).0, 1);
+        let _third = open_download(&mut client, "third").await;
+        assert!(handler.sessions.contains("third"));
+    }
+
+    #[tokio::test]
+    async fn test_streams_limited_per_ip() {
+        let handler = H2Handler::new(XhttpConfig { max_streams_per_ip: 1, ..config(XhttpMode::StreamOne) });
+        let (mut client, _server) = start(&handler).await;
+        let other = IpAddr::from([192, 0, 2, 7]);
+        let (mut other_client, _other_server) = start_from(&handler, other).await;
+
+        let request = Request::post("https://example.com/drain").body(()).unwrap();
+        let (response, mut upload) = client.send_request(request, false).unwrap();
+        let mut body = response.await.unwrap().into_body();
+
+        let request = Request::post("https://example.com/drain").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        assert_eq!(response.await.unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(handler.sessions.per_ip(PEER).1, 1);
+
+        let request = Request::post("https://example.com/drain").body(()).unwrap();
+        let (response, _) = other_client.send_request(request, true).unwrap();
+        assert_eq!(response.await.unwrap().status(), StatusCode::OK);
+
+        // 流结束后同一来源可以再次发起请求
+        upload.send_data(Bytes::from_static(b"bye"), true).unwrap();
+        assert_eq!(body.data().await.unwrap().unwrap(), Bytes::from_static(b"bye"));
+        while body.data().await.is_some() {}
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(handler.sessions.per_ip(PEER).1, 0);
+        let request = Request::post("https://example.com/drain").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        assert_eq!(response.await.unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_h2_window_settings() {
+        let default = bytes_in_flight(H2Settings::default()).await;
+        let large = bytes_in_flight(H2Settings {
+            initial_stream_window: 8 << 20,
+            initial_conn_window: 8 << 20,
+            ..H2Settings::default()
+        })
+        .await;
+        // 默认的 64 KB 连接窗口限制了在途数据，放大窗口后单个流可以送出 8 MB 以上
+        assert!(default < 1 << 20, "默认窗口下送出 {} 字节", default);
+        assert!(large >= 8 << 20, "放大窗口后只送出 {} 字节", large);
+    }
+
+    #[tokio::test]
+    async fn test_upload_backpressure_when_consumer_paused() {
+        // 内层会话不读取数据，模拟读取缓慢的 VLESS 目标
+        let paused = |stream: Box<dyn crate::server::AsyncStream>| async move {
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            drop(stream);
+            Ok(())
+        };
+        let handler = handler();
+        let (mut client, _server) = start_with(&handler, PEER, paused).await;
+        let _down = open_download(&mut client, "paused").await;
+
+        // 只在服务端归还窗口后才发送，统计服务端一共收下多少数据
+        let request = Request::post("https://example.com/drain/paused").body(()).unwrap();
+        let (_response, mut body) = client.send_request(request, false).unwrap();
+        let sent = fill_upload(&mut body, 8 << 20).await;
+        // 连接窗口 + 内存管道 + 上行通道中的几段数据，远小于客户端想发送的 8 MB
+        assert!(sent < 1 << 20, "服务端收下了 {} 字节", sent);
+    }
+
+    #[tokio::test]
+    async fn test_upload_reset_when_session_ends() {
+        let handler = handler();
+        let (mut client, _server) = start(&handler).await;
+        let mut down = open_download(&mut client, "ending").await;
+
+        let request = Request::post("https://example.com/drain/ending").body(()).unwrap();
+        let (response, mut body) = client.send_request(request, false).unwrap();
+        body.send_data(Bytes::from_static(b"bye"), false).unwrap();
+        assert_eq!(down.data().await.unwrap().unwrap(), Bytes::from_static(b"bye"));
+        while down.data().await.is_some() {}
+
+        // 会话已结束，继续上传的数据无处可去，请求被重置而不是静默丢弃
+        body.send_data(Bytes::from_static(b"more"), false).unwrap();
+        let reset = tokio::time::timeout(Duration::from_secs(5), response).await.unwrap().unwrap_err();
+        assert_eq!(reset.reason(), Some(h2::Reason::CANCEL));
+    }
+
+    #[tokio::test]
+    async fn test_response_headers_on_every_response() {
+        let response_headers = serde_json::from_value(serde_json::json!({
+            "server": "nginx/1.24.0",
+            "date": "",
+            "cache-control": "no-store",
+        }))
+        .unwrap();
+        let handler = H2Handler::new(XhttpConfig { response_headers, ..config(XhttpMode::Auto) });
+        let (mut client, _server) = start(&handler).await;
+
+        let check = |response: &hyper::http::Response<h2::RecvStream>| {
+            let headers = response.headers();
+            assert_eq!(headers["server"], "nginx/1.24.0");
+            assert_eq!(headers["cache-control"], "no-store");
+            assert!(httpdate::parse_http_date(headers["date"].to_str().unwrap()).is_ok());
+        };
+
+        // 下行保持打开，之后的 POST 才能配对
+        let request = Request::get("https://example.com/drain/hdr").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        let download = response.await.unwrap();
+        assert_eq!(download.status(), StatusCode::OK);
+        check(&download);
+
+        let request = Request::post("https://example.com/drain/hdr/0").body(()).unwrap();
+        let (response, mut body) = client.send_request(request, false).unwrap();
+        body.send_data(Bytes::from_static(b"x"), true).unwrap();
+        let response = response.await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        check(&response);
+
+        let request = Request::get("https://example.com/unknown").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        let response = response.await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        check(&response);
+    }
+
+    #[tokio::test]
+    async fn test_cors_for_browser_dialer() {
+        let cors_origins = vec!["https://app.example.com".to_string()];
+        let handler = H2Handler::new(XhttpConfig { cors_origins, ..config(XhttpMode::Auto) });
+        let (mut client, _server) = start(&handler).await;
+
+        // 预检
+        let request = Request::options("https://example.com/drain/cors")
+            .header("origin", "https://app.example.com")
+            .header("access-control-request-method", "POST")
+            .header("access-control-request-headers", "content-type, x-padding")
+            .body(())
+            .unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        let response = response.await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let headers = response.headers();
+        assert_eq!(headers["access-control-allow-origin"], "https://app.example.com");
+        assert_eq!(headers["access-control-allow-methods"], "GET, POST, OPTIONS");
+        assert_eq!(headers["access-control-allow-headers"], "content-type, x-padding");
+
+        // 允许的来源: 下行与上行响应都带上 Access-Control-Allow-Origin
+        let request = Request::get("https://example.com/drain/cors")
+            .header("origin", "https://app.example.com")
+            .body(())
+            .unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        let download = response.await.unwrap();
+        assert_eq!(download.status(), StatusCode::OK);
+        assert_eq!(download.headers()["access-control-allow-origin"], "https://app.example.com");
+        let request = Request::post("https://example.com/drain/cors/0")
+            .header("origin", "https://app.example.com")
+            .body(())
+            .unwrap();
+        let (response, mut body) = client.send_request(request, false).unwrap();
+        body.send_data(Bytes::from_static(b"x"), true).unwrap();
+        let response = response.await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["access-control-allow-origin"], "https://app.example.com");
+
+        // 其他来源与未知请求一样得到 404，预检也不例外
+        for method in [hyper::http::Method::OPTIONS, hyper::http::Method::GET] {
+            let request = Request::builder()
+                .method(method)
+                .uri("https://example.com/drain/evil")
+                .header("origin", "https://evil.example.net")
+                .body(())
+                .unwrap();
+            let (response, _) = client.send_request(request, true).unwrap();
+            let response = response.await.unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+            assert!(!response.headers().contains_key("access-control-allow-origin"));
+        }
+        assert!(!handler.sessions.contains("evil"));
+
+        // 没有配置 corsOrigins 时 OPTIONS 仍然是 405
+        let (mut client, _server) = start(&handler_with_mode(XhttpMode::Auto)).await;
+        let request = Request::options("https://example.com/drain/cors")
+            .header("origin", "https://app.example.com")
+            .body(())
+            .unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        assert_eq!(response.await.unwrap().status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[tokio::test]
+    async fn test_padding_follows_config() {
+        let padded = H2Handler::new(XhttpConfig { x_padding_bytes: "10-20".parse().unwrap(), ..config(XhttpMode::Auto) });
+        let (mut client, _server) = start(&padded).await;
+        let request = Request::get("https://example.com/drain/padded").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        let response = response.await.unwrap();
+        let padding = response.headers()["x-padding"].len();
+        assert!((10..=20).contains(&padding), "长度 {}", padding);
+
+        // 0 表示完全不发送 x-padding 头
+        let plain = H2Handler::new(XhttpConfig { x_padding_bytes: "0".parse().unwrap(), ..config(XhttpMode::Auto) });
+        let (mut client, _server) = start(&plain).await;
+        let request = Request::get("https://example.com/drain/plain").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        assert!(!response.await.unwrap().headers().contains_key("x-padding"));
+        let request = Request::post("https://example.com/drain/plain/0").body(()).unwrap();
+        let (response, mut body) = client.send_request(request, false).unwrap();
+        body.send_data(Bytes::from_static(b"x"), true).unwrap();
+        assert!(!response.await.unwrap().headers().contains_key("x-padding"));
+    }
+}
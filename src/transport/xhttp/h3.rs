@@ -0,0 +1,413 @@
+use anyhow::{anyhow, Result};
+use bytes::{Buf, Bytes, BytesMut};
+use h3::server::RequestStream;
+use hyper::http::{HeaderMap, HeaderValue, Request, Response, StatusCode};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+use super::limit::IpPermit;
+use super::session::{self, PushError, Route, Sessions, Uplink};
+use super::{cors, H2Handler, XhttpConfig};
+
+/// 没有配置 keepalivePeriod 时 HTTP/3 连接的 PING 间隔，低于 QUIC 默认的 30 秒空闲超时
+const DEFAULT_QUIC_KEEPALIVE: Duration = Duration::from_secs(10);
+
+/// HTTP/3 应用层的 H3_NO_ERROR
+const H3_NO_ERROR: u32 = 0x100;
+
+type H3Stream = RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>;
+
+/// 生成 QUIC 监听使用的服务端配置 (TLS 1.3，ALPN `h3`)
+///
+/// 证书来自 PEM 文件；没有配置文件时为 `server_name` 生成自签名证书，客户端需要关闭证书校验或固定该证书。
+pub fn server_config(
+    config: &XhttpConfig,
+    cert_file: Option<&str>,
+    key_file: Option<&str>,
+    server_name: &str,
+) -> Result<quinn::ServerConfig> {
+    use quinn::rustls;
+
+    let (certs, key) = match (cert_file, key_file) {
+        (Some(cert_file), Some(key_file)) => {
+            let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(std::fs::File::open(cert_file)?))
+                .collect::<Result<Vec<_>, _>>()?;
+            let key = rustls_pemfile::private_key(&mut std::io::BufReader::new(std::fs::File::open(key_file)?))?
+                .ok_or_else(|| anyhow!("{} 中没有私钥", key_file))?;
+            (certs, key)
+        }
+        _ => {
+            let cert = rcgen::generate_simple_self_signed(vec![server_name.to_string()])?;
+            let key = rustls::pki_types::PrivatePkcs8KeyDer::from(cert.serialize_private_key_der());
+            (vec![cert.serialize_der()?.into()], key.into())
+        }
+    };
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut tls = rustls::ServerConfig::builder_with_provider(provider)
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    tls.alpn_protocols = vec![b"h3".to_vec()];
+
+    let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(tls)?;
+    let mut server_config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+    let mut transport = quinn::TransportConfig::default();
+    let keepalive = match config.keepalive_period {
+        0 => DEFAULT_QUIC_KEEPALIVE,
+        period => Duration::from_secs(period),
+    };
+    transport
+        .keep_alive_interval(Some(keepalive))
+        .max_concurrent_bidi_streams(quinn::VarInt::from_u32(config.h2_settings.max_concurrent_streams));
+    server_config.transport_config(Arc::new(transport));
+    Ok(server_config)
+}
+
+/// HTTP/3 上的 XHTTP 处理器，路径、模式与会话配对规则与 [`H2Handler`] 相同
+///
+/// 克隆共享同一组关闭信号: `shutdown` 发送 GOAWAY，`terminate` 关闭连接。
+#[derive(Clone)]
+pub struct H3Handler {
+    config: XhttpConfig,
+    sessions: Sessions,
+    draining: CancellationToken,
+    terminating: CancellationToken,
+}
+
+impl H3Handler {
+    pub fn new(config: XhttpConfig) -> Self {
+        Self {
+            config,
+            sessions: Sessions::default(),
+            draining: CancellationToken::new(),
+            terminating: CancellationToken::new(),
+        }
+    }
+
+    /// 使用指定的会话表，与同一 `XhttpServer` 的 TCP 连接互相配对
+    pub(super) fn with_sessions(mut self, sessions: Sessions) -> Self {
+        self.sessions = sessions;
+        self
+    }
+
+    /// 开始优雅关闭: 发送 GOAWAY，进行中的请求继续到结束
+    pub fn shutdown(&self) {
+        self.draining.cancel();
+    }
+
+    /// 关闭所有连接 (排空超时后调用)
+    pub fn terminate(&self) {
+        self.draining.cancel();
+        self.terminating.cancel();
+    }
+
+    pub async fn handle<F, Fut>(&self, quic: quinn::Connection, handler: F) -> Result<()>
+    where
+        F: Fn(Box<dyn crate::server::AsyncStream>) -> Fut + Clone + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        let peer = quic.remote_address().ip();
+        let mut connection = h3::server::Connection::<_, Bytes>::new(h3_quinn::Connection::new(quic.clone())).await?;
+        let mut draining = false;
+
+        loop {
+            tokio::select! {
+                result = connection.accept() => match result {
+                    Ok(Some(resolver)) => {
+                        let Some(stream_permit) = self.sessions.begin_stream(peer, &self.config) else {
+                            tokio::spawn(Self::reject(resolver, self.config.clone()));
+                            continue;
+                        };
+                        let config = self.config.clone();
+                        let sessions = self.sessions.clone();
+                        let handler = handler.clone();
+                        let terminating = self.terminating.clone();
+                        tokio::spawn(async move {
+                            let _stream_permit = stream_permit;
+                            let request = async move {
+                                let (request, stream) = resolver.resolve_request().await?;
+                                Self::handle_request(config, sessions, peer, request, stream, handler).await
+                            };
+                            tokio::select! {
+                                result = request => {
+                                    if let Err(e) = result {
+                                        debug!("XHTTP/3 请求结束: {}", e);
+                                    }
+                                }
+                                _ = terminating.cancelled() => debug!("XHTTP/3: 关闭时终止进行中的请求"),
+                            }
+                        });
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        debug!("XHTTP/3 连接中断: {}", e);
+                        break;
+                    }
+                },
+                _ = self.draining.cancelled(), if !draining => {
+                    draining = true;
+                    debug!("XHTTP/3: 发送 GOAWAY，等待进行中的请求结束");
+                    connection.shutdown(0).await?;
+                }
+                _ = self.terminating.cancelled() => {
+                    quic.close(quinn::VarInt::from_u32(H3_NO_ERROR), b"");
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 以 429 拒绝超出来源 IP 请求数上限的请求
+    async fn reject(resolver: h3::server::RequestResolver<h3_quinn::Connection, Bytes>, config: XhttpConfig) {
+        let Ok((_, mut stream)) = resolver.resolve_request().await else {
+            return;
+        };
+        let response = config.response_headers.apply(Response::builder().status(StatusCode::TOO_MANY_REQUESTS)).body(()).unwrap();
+        if stream.send_response(response).await.is_ok() {
+            let _ = stream.finish().await;
+        }
+    }
+
+    async fn handle_request<F, Fut>(
+        config: XhttpConfig,
+        sessions: Sessions,
+        peer: IpAddr,
+        request: Request<()>,
+        mut stream: H3Stream,
+        handler: F,
+    ) -> Result<()>
+    where
+        F: Fn(Box<dyn crate::server::AsyncStream>) -> Fut + Clone + Send + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        let authority = request
+            .uri()
+            .authority()
+            .map(|authority| authority.as_str())
+            .or_else(|| request.headers().get("host").and_then(|v| v.to_str().ok()));
+        let route = session::route(
+            &config,
+            &sessions,
+            peer,
+            request.method(),
+            authority,
+            request.uri().path(),
+            request.uri().query(),
+            request.headers(),
+        )
+        .await;
+        let origin = cors::allowed(&config, request.headers());
+        match route {
+            Route::Reject(status) => {
+                let response = config.response_headers.apply(Response::builder().status(status)).body(()).unwrap();
+                stream.send_response(response).await?;
+                stream.finish().await?;
+            }
+            Route::Preflight => {
+                let mut response = Response::builder().status(StatusCode::NO_CONTENT);
+                if let Some(origin) = &origin {
+                    for (name, value) in cors::preflight_headers(origin, request.headers()) {
+                        response = response.header(name, value);
+                    }
+                }
+                let response = H2Handler::with_padding(response, &config, None).body(()).unwrap();
+                stream.send_response(response).await?;
+                stream.finish().await?;
+            }
+            Route::Download(id, permit) => Self::handle_get(id, permit, &config, &sessions, origin, stream, handler).await?,
+            Route::Upload(uplink, seq) => Self::handle_post(stream, uplink, seq, &config, origin).await?,
+            Route::Standalone { grpc } => Self::handle_standalone(stream, handler, grpc, &config, origin).await?,
+        }
+        Ok(())
+    }
+
+    /// 下行 GET: 登记会话，VLESS 一侧的数据作为响应体发出
+    async fn handle_get<F, Fut>(
+        id: String,
+        permit: IpPermit,
+        config: &XhttpConfig,
+        sessions: &Sessions,
+        origin: Option<HeaderValue>,
+        mut stream: H3Stream,
+        handler: F,
+    ) -> Result<()>
+    where
+        F: Fn(Box<dyn crate::server::AsyncStream>) -> Fut + Send + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        let (mut to_vless_rx, closed, session) = sessions.open(id, permit, config);
+        let activity = session.activity();
+
+        let (client_io, server_io) = tokio::io::duplex(65536);
+        tokio::spawn(handler(Box::new(server_io)));
+        let (mut client_read, mut client_write) = tokio::io::split(client_io);
+
+        let response = Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "application/octet-stream");
+        let response = H2Handler::with_padding(response, config, origin.as_ref()).body(()).unwrap();
+        stream.send_response(response).await?;
+
+        let upstream = tokio::spawn(async move {
+            while let Some(data) = to_vless_rx.recv().await {
+                client_write.write_all(&data).await?;
+            }
+            Ok::<(), anyhow::Error>(())
+        });
+
+        let downstream = async {
+            let mut buf = BytesMut::with_capacity(65536);
+            loop {
+                if buf.capacity() < 2048 {
+                    buf.reserve(65536);
+                }
+                let n = client_read.read_buf(&mut buf).await?;
+                if n == 0 {
+                    break;
+                }
+                activity.touch();
+                stream.send_data(buf.split_to(n).freeze()).await?;
+            }
+            stream.finish().await?;
+            Ok::<(), anyhow::Error>(())
+        };
+
+        let result = tokio::select! {
+            result = downstream => result,
+            _ = closed.cancelled() => Ok(()),
+        };
+        upstream.abort();
+        drop(session);
+        result
+    }
+
+    /// 上行 POST: 请求体交给已配对的会话，带 seq 的 packet-up 请求体整段按序交付
+    async fn handle_post(
+        mut stream: H3Stream,
+        uplink: Uplink,
+        seq: Option<u64>,
+        config: &XhttpConfig,
+        origin: Option<HeaderValue>,
+    ) -> Result<()> {
+        let max_bytes = config.sc_max_each_post_bytes;
+        let mut received = 0;
+        let mut packet = BytesMut::new();
+        while let Some(mut chunk) = stream.recv_data().await? {
+            let data = chunk.copy_to_bytes(chunk.remaining());
+            received += data.len();
+            if received > max_bytes {
+                debug!("XHTTP/3: POST 超过 scMaxEachPostBytes ({} 字节)，重置请求", max_bytes);
+                stream.stop_sending(h3::error::Code::H3_REQUEST_REJECTED);
+                stream.stop_stream(h3::error::Code::H3_REQUEST_REJECTED);
+                return Ok(());
+            }
+            if seq.is_some() {
+                packet.extend_from_slice(&data);
+            } else if uplink.send(data).await.is_err() {
+                // 会话收下数据后才继续读取，QUIC 的流量控制随之对客户端施加反压
+                debug!("XHTTP/3: 会话已结束，重置上行请求");
+                stream.stop_sending(h3::error::Code::H3_REQUEST_CANCELLED);
+                stream.stop_stream(h3::error::Code::H3_REQUEST_CANCELLED);
+                return Ok(());
+            }
+        }
+        let status = match seq {
+            Some(seq) => match uplink.push(seq, packet.freeze()).await {
+                Ok(()) => StatusCode::OK,
+                Err(PushError::OutOfWindow) => StatusCode::BAD_REQUEST,
+                Err(PushError::Closed) => {
+                    debug!("XHTTP/3: 会话已结束，重置上行请求");
+                    stream.stop_stream(h3::error::Code::H3_REQUEST_CANCELLED);
+                    return Ok(());
+                }
+            },
+            None => StatusCode::OK,
+        };
+        let response = Response::builder().status(status);
+        let response = H2Handler::with_padding(response, config, origin.as_ref()).body(()).unwrap();
+        stream.send_response(response).await?;
+        stream.finish().await?;
+        Ok(())
+    }
+
+    /// 单个请求同时承载上下行 (stream-one 或未配对的 POST)
+    async fn handle_standalone<F, Fut>(
+        mut stream: H3Stream,
+        handler: F,
+        is_grpc: bool,
+        config: &XhttpConfig,
+        origin: Option<HeaderValue>,
+    ) -> Result<()>
+    where
+        F: Fn(Box<dyn crate::server::AsyncStream>) -> Fut + Send + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        let response = Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", if is_grpc { "application/grpc" } else { "application/octet-stream" });
+        let response = H2Handler::with_padding(response, config, origin.as_ref()).body(()).unwrap();
+        stream.send_response(response).await?;
+        let (mut send, mut recv) = stream.split();
+
+        let (client_io, server_io) = tokio::io::duplex(65536);
+        tokio::spawn(handler(Box::new(server_io)));
+        let (mut client_read, mut client_write) = tokio::io::split(client_io);
+
+        let upstream = tokio::spawn(async move {
+            let mut leftover = BytesMut::new();
+            while let Some(mut chunk) = recv.recv_data().await? {
+                let data = chunk.copy_to_bytes(chunk.remaining());
+                if !is_grpc {
+                    client_write.write_all(&data).await?;
+                    continue;
+                }
+                leftover.extend_from_slice(&data);
+                while leftover.len() >= 5 {
+                    let len = u32::from_be_bytes([leftover[1], leftover[2], leftover[3], leftover[4]]) as usize;
+                    if leftover.len() < 5 + len {
+                        break;
+                    }
+                    leftover.advance(5);
+                    client_write.write_all(&leftover.split_to(len)).await?;
+                }
+            }
+            Ok::<(), anyhow::Error>(())
+        });
+
+        let mut buf = BytesMut::with_capacity(65536);
+        loop {
+            if buf.capacity() < 2048 {
+                buf.reserve(65536);
+            }
+            let n = client_read.read_buf(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            let data = buf.split_to(n);
+            if is_grpc {
+                let mut frame = BytesMut::with_capacity(5 + n);
+                frame.extend_from_slice(&[0u8]);
+                frame.extend_from_slice(&(n as u32).to_be_bytes());
+                frame.extend_from_slice(&data);
+                send.send_data(frame.freeze()).await?;
+            } else {
+                send.send_data(data.freeze()).await?;
+            }
+        }
+        if is_grpc {
+            let mut trailers = HeaderMap::new();
+            trailers.insert("grpc-status", HeaderValue::from_static("0"));
+            send.send_trailers(trailers).await?;
+        } else {
+            send.finish().await?;
+        }
+        upstream.abort();
+        Ok(())
+    }
+}
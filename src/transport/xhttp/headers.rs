@@ -0,0 +1,161 @@
+use hyper::http::header::{HeaderName, HeaderValue};
+use hyper::http::response;
+use rand::Rng;
+use serde::de::{self, MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::time::SystemTime;
+
+/// 附加在每个 XHTTP 响应上的伪装响应头 (responseHeaders)
+///
+/// 可以写成内置模板名 (`"nginx"`、`"cloudflare"`)，也可以写成按顺序排列的头部表。
+/// `date` 总是填入当前时间；`cf-ray` 填入随机 ID，配置的值作为机房后缀 (如 `"LAX"`)。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResponseHeaders {
+    /// 使用的内置模板，序列化时原样写回
+    profile: Option<String>,
+    entries: Vec<(HeaderName, HeaderValue)>,
+}
+
+/// 内置模板: 与常见反向代理默认发出的头部一致
+const PROFILES: &[(&str, &[(&str, &str)])] = &[
+    ("nginx", &[("server", "nginx"), ("date", ""), ("cache-control", "no-store")]),
+    (
+        "cloudflare",
+        &[
+            ("date", ""),
+            ("cache-control", "no-store"),
+            ("cf-cache-status", "DYNAMIC"),
+            ("server", "cloudflare"),
+            ("cf-ray", "LAX"),
+        ],
+    ),
+];
+
+impl ResponseHeaders {
+    /// 按名称取内置模板
+    pub fn profile(name: &str) -> Option<Self> {
+        let (name, headers) = PROFILES.iter().find(|(profile, _)| profile.eq_ignore_ascii_case(name))?;
+        let entries = headers
+            .iter()
+            .map(|(name, value)| (HeaderName::from_static(name), HeaderValue::from_static(value)))
+            .collect();
+        Some(Self { profile: Some(name.to_string()), entries })
+    }
+
+    /// 按配置的顺序加上响应头
+    pub fn apply(&self, mut builder: response::Builder) -> response::Builder {
+        for (name, value) in &self.entries {
+            builder = match name.as_str() {
+                "date" => builder.header(name, httpdate::fmt_http_date(SystemTime::now())),
+                "cf-ray" => builder.header(name, cf_ray(value)),
+                _ => builder.header(name, value),
+            };
+        }
+        builder
+    }
+}
+
+/// 16 位十六进制的随机 ID 加机房后缀，与 Cloudflare 的 CF-Ray 格式一致
+fn cf_ray(colo: &HeaderValue) -> String {
+    let id: u64 = rand::thread_rng().gen();
+    match colo.to_str() {
+        Ok(colo) if !colo.is_empty() => format!("{:016x}-{}", id, colo),
+        _ => format!("{:016x}", id),
+    }
+}
+
+impl Serialize for ResponseHeaders {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if let Some(profile) = &self.profile {
+            return serializer.serialize_str(profile);
+        }
+        let mut map = serializer.serialize_map(Some(self.entries.len()))?;
+        for (name, value) in &self.entries {
+            map.serialize_entry(name.as_str(), value.to_str().unwrap_or(""))?;
+        }
+        map.end()
+    }
+}
+
+struct ResponseHeadersVisitor;
+
+impl<'de> Visitor<'de> for ResponseHeadersVisitor {
+    type Value = ResponseHeaders;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("内置模板名或响应头表")
+    }
+
+    fn visit_str<E: de::Error>(self, name: &str) -> Result<Self::Value, E> {
+        ResponseHeaders::profile(name).ok_or_else(|| E::custom(format!("未知的 responseHeaders 模板: {}", name)))
+    }
+
+    // 逐项读取而不经过 Map，保留配置中的顺序
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+        let mut entries = Vec::new();
+        while let Some((name, value)) = access.next_entry::<String, String>()? {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| de::Error::custom(format!("无效的响应头名称: {}", name)))?;
+            let value = HeaderValue::from_str(&value)
+                .map_err(|_| de::Error::custom(format!("响应头 {} 的值无效", name)))?;
+            entries.push((name, value));
+        }
+        Ok(ResponseHeaders { profile: None, entries })
+    }
+}
+
+impl<'de> Deserialize<'de> for ResponseHeaders {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ResponseHeadersVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::http::Response;
+
+    fn build(headers: &ResponseHeaders) -> Response<()> {
+        headers.apply(Response::builder()).body(()).unwrap()
+    }
+
+    #[test]
+    fn test_custom_headers_keep_order() {
+        // 直接解析文本，json! 宏构造的对象不保留顺序
+        let json = r#"{"x-b":"2","server":"nginx/1.24.0","x-a":"1"}"#;
+        let headers: ResponseHeaders = serde_json::from_str(json).unwrap();
+        let response = build(&headers);
+        let names: Vec<_> = response.headers().keys().map(|name| name.as_str()).collect();
+        assert_eq!(names, ["x-b", "server", "x-a"]);
+        assert_eq!(response.headers()["server"], "nginx/1.24.0");
+
+        // 原样写回配置
+        assert_eq!(serde_json::to_string(&headers).unwrap(), json);
+
+        assert!(serde_json::from_value::<ResponseHeaders>(serde_json::json!({ "bad name": "1" })).is_err());
+        assert!(serde_json::from_value::<ResponseHeaders>(serde_json::json!({ "x-a": "line\nbreak" })).is_err());
+    }
+
+    #[test]
+    fn test_profiles() {
+        let nginx: ResponseHeaders = serde_json::from_value(serde_json::json!("nginx")).unwrap();
+        assert_eq!(serde_json::to_value(&nginx).unwrap(), serde_json::json!("nginx"));
+        let response = build(&nginx);
+        assert_eq!(response.headers()["server"], "nginx");
+        // 当前时间，IMF-fixdate 格式
+        let date = httpdate::parse_http_date(response.headers()["date"].to_str().unwrap()).unwrap();
+        assert!(SystemTime::now().duration_since(date).unwrap().as_secs() < 5);
+
+        let cloudflare = ResponseHeaders::profile("Cloudflare").unwrap();
+        let first = build(&cloudflare);
+        let second = build(&cloudflare);
+        let ray = first.headers()["cf-ray"].to_str().unwrap();
+        assert!(ray.len() == 20 && ray.ends_with("-LAX"), "{}", ray);
+        assert_ne!(first.headers()["cf-ray"], second.headers()["cf-ray"]);
+
+        assert!(serde_json::from_value::<ResponseHeaders>(serde_json::json!("apache")).is_err());
+        assert!(build(&ResponseHeaders::default()).headers().is_empty());
+    }
+}
@@ -0,0 +1,80 @@
+//! 按来源 IP 统计的会话数与并发请求数 (maxSessionsPerIp / maxStreamsPerIp)
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+/// 每个来源 IP 当前持有的许可数
+#[derive(Clone, Default)]
+pub(super) struct PerIp {
+    counts: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl PerIp {
+    /// 该 IP 持有的许可少于 `max` 时取得一个许可，`max` 为 0 表示不限制
+    ///
+    /// IPv4 映射的 IPv6 地址 (双栈监听) 与对应的 IPv4 地址计为同一来源。
+    pub(super) fn try_acquire(&self, ip: IpAddr, max: usize) -> Option<IpPermit> {
+        let ip = ip.to_canonical();
+        let mut counts = self.counts.lock().unwrap();
+        let count = counts.entry(ip).or_default();
+        if max > 0 && *count >= max {
+            return None;
+        }
+        *count += 1;
+        Some(IpPermit { counts: self.clone(), ip })
+    }
+
+    /// 该 IP 当前持有的许可数
+    #[cfg(test)]
+    pub(super) fn count(&self, ip: IpAddr) -> usize {
+        self.counts.lock().unwrap().get(&ip.to_canonical()).copied().unwrap_or(0)
+    }
+}
+
+/// 会话或请求结束 (许可被释放) 时归还计数
+pub(super) struct IpPermit {
+    counts: PerIp,
+    ip: IpAddr,
+}
+
+impl Drop for IpPermit {
+    fn drop(&mut self) {
+        let mut counts = self.counts.counts.lock().unwrap();
+        if let Some(count) = counts.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(&self.ip);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_per_ip_limit() {
+        let limit = PerIp::default();
+        let a: IpAddr = "10.0.0.1".parse().unwrap();
+        let b: IpAddr = "10.0.0.2".parse().unwrap();
+
+        let first = limit.try_acquire(a, 2).unwrap();
+        let _second = limit.try_acquire(a, 2).unwrap();
+        assert!(limit.try_acquire(a, 2).is_none());
+        // 双栈监听上的映射地址与 IPv4 地址共用计数
+        assert!(limit.try_acquire("::ffff:10.0.0.1".parse().unwrap(), 2).is_none());
+        let _other = limit.try_acquire(b, 2).unwrap();
+
+        drop(first);
+        assert_eq!(limit.count(a), 1);
+        let _third = limit.try_acquire(a, 2).unwrap();
+
+        // 0 表示不限制
+        let unlimited: Vec<_> = (0..100).map(|_| limit.try_acquire(b, 0).unwrap()).collect();
+        assert_eq!(limit.count(b), 101);
+        drop(unlimited);
+        assert_eq!(limit.count(b), 1);
+    }
+}
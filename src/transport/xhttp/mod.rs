@@ -1,11 +1,26 @@
+mod cors;
 mod grpc;
+mod h1;
 mod h2;
+#[cfg(feature = "http3")]
+mod h3;
+mod headers;
+mod limit;
+mod padding;
 mod server;
+mod session;
 
 pub use grpc::{GrpcHeaders, GrpcMessage, GrpcStatus, GrpcTrailer};
+pub use h1::H1Handler;
 pub use h2::H2Handler;
+#[cfg(feature = "http3")]
+pub use h3::H3Handler;
+pub use headers::ResponseHeaders;
+pub use padding::PaddingRange;
 pub use server::XhttpServer;
+pub use session::Sessions;
 
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 
 /// XHTTP 模式
@@ -39,6 +54,83 @@ impl std::fmt::Display for XhttpMode {
     }
 }
 
+/// 单个上行 POST 默认最多 1 MB，与 xray-core 一致
+pub const DEFAULT_SC_MAX_EACH_POST_BYTES: usize = 1_000_000;
+
+fn default_sc_max_each_post_bytes() -> usize {
+    DEFAULT_SC_MAX_EACH_POST_BYTES
+}
+
+/// 每个会话的重排缓冲默认最多容纳 30 个乱序到达的 packet-up POST，与 xray-core 一致
+pub const DEFAULT_SC_MAX_BUFFERED_POSTS: usize = 30;
+
+fn default_sc_max_buffered_posts() -> usize {
+    DEFAULT_SC_MAX_BUFFERED_POSTS
+}
+
+/// 默认清理空闲 5 分钟的会话
+pub const DEFAULT_SESSION_IDLE_TIMEOUT: u64 = 300;
+
+fn default_session_idle_timeout() -> u64 {
+    DEFAULT_SESSION_IDLE_TIMEOUT
+}
+
+/// 默认等待 PING 应答 15 秒
+pub const DEFAULT_KEEPALIVE_TIMEOUT: u64 = 15;
+
+fn default_keepalive_timeout() -> u64 {
+    DEFAULT_KEEPALIVE_TIMEOUT
+}
+
+/// HTTP/2 流量控制窗口的上限 (2^31 - 1)
+const MAX_WINDOW_SIZE: u32 = (1 << 31) - 1;
+
+/// HTTP/2 服务端参数 (h2Settings)，默认值与之前写死的参数一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct H2Settings {
+    /// 每个流的初始接收窗口，限制单个上行流的在途数据量
+    pub initial_stream_window: u32,
+    /// 整个连接的接收窗口
+    pub initial_conn_window: u32,
+    /// 每个连接允许的并发流数
+    pub max_concurrent_streams: u32,
+    /// 允许对端发送的最大帧长度
+    pub max_frame_size: u32,
+}
+
+/// 默认 512 KB 的流窗口、64 KB 的连接窗口 (HTTP/2 协议默认值)、500 个并发流、16 KB 的帧
+impl Default for H2Settings {
+    fn default() -> Self {
+        Self {
+            initial_stream_window: 524_288,
+            initial_conn_window: 65_535,
+            max_concurrent_streams: 500,
+            max_frame_size: 16_384,
+        }
+    }
+}
+
+impl H2Settings {
+    /// 检查参数是否在 HTTP/2 允许的范围内
+    pub fn validate(&self) -> Result<()> {
+        if !(1..=MAX_WINDOW_SIZE).contains(&self.initial_stream_window) {
+            return Err(anyhow!("initialStreamWindow 必须在 1 到 {} 之间", MAX_WINDOW_SIZE));
+        }
+        // 连接窗口只能通过 WINDOW_UPDATE 扩大，不能小于协议默认的 65535
+        if !(65_535..=MAX_WINDOW_SIZE).contains(&self.initial_conn_window) {
+            return Err(anyhow!("initialConnWindow 必须在 65535 到 {} 之间", MAX_WINDOW_SIZE));
+        }
+        if self.max_concurrent_streams == 0 {
+            return Err(anyhow!("maxConcurrentStreams 必须大于 0"));
+        }
+        if !(16_384..=16_777_215).contains(&self.max_frame_size) {
+            return Err(anyhow!("maxFrameSize 必须在 16384 到 16777215 之间"));
+        }
+        Ok(())
+    }
+}
+
 /// XHTTP 配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct XhttpConfig {
@@ -46,6 +138,47 @@ pub struct XhttpConfig {
     pub mode: XhttpMode,
     /// 路径
     pub path: String,
-    /// Host 头
+    /// 允许的 Host (可用 `*.example.com` 匹配子域名)，为空时不检查
     pub host: String,
+    /// 请求的查询串必须带有的密钥，为空时不检查
+    #[serde(default)]
+    pub path_secret: String,
+    /// 单个上行 POST 的字节数上限，超过时重置该请求
+    #[serde(default = "default_sc_max_each_post_bytes")]
+    pub sc_max_each_post_bytes: usize,
+    /// packet-up 重排缓冲最多容纳的 POST 数，与 xray-core 的 `scMaxBufferedPosts` 含义相同
+    ///
+    /// 缓冲中的数据最多约为该值乘以 `scMaxEachPostBytes`。
+    #[serde(default = "default_sc_max_buffered_posts")]
+    pub sc_max_buffered_posts: usize,
+    /// 响应头 `x-padding` 的长度范围
+    #[serde(default)]
+    pub x_padding_bytes: PaddingRange,
+    /// 会话空闲超过该秒数 (没有上下行数据) 后被清理，0 表示不清理
+    #[serde(default = "default_session_idle_timeout")]
+    pub session_idle_timeout: u64,
+    /// 独立流 (stream-one) 不使用 gRPC 分帧，无论请求头如何
+    #[serde(default)]
+    pub no_grpc_header: bool,
+    /// HTTP/2 服务端参数
+    #[serde(default)]
+    pub h2_settings: H2Settings,
+    /// HTTP/2 连接每隔该秒数发送一次 PING，0 表示不发送
+    #[serde(default)]
+    pub keepalive_period: u64,
+    /// 超过该秒数没有收到 PING 应答时关闭连接并清理其上的会话
+    #[serde(default = "default_keepalive_timeout")]
+    pub keepalive_timeout: u64,
+    /// 附加在每个响应上的伪装响应头
+    #[serde(default)]
+    pub response_headers: ResponseHeaders,
+    /// 允许跨域访问的来源 (browser dialer)，`*` 表示任意来源，为空时不处理 CORS
+    #[serde(default)]
+    pub cors_origins: Vec<String>,
+    /// 每个来源 IP 同时打开的会话数上限，超过时下行 GET 得到 429，0 表示不限制
+    #[serde(default)]
+    pub max_sessions_per_ip: usize,
+    /// 每个来源 IP 进行中的请求数上限，超过时新请求得到 429，0 表示不限制
+    #[serde(default)]
+    pub max_streams_per_ip: usize,
 }
@@ -0,0 +1,123 @@
+use anyhow::{anyhow, Result};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// 响应头 `x-padding` 的长度范围 (xPaddingBytes)
+///
+/// 支持 `"100-1000"` 与单个长度 `"256"`，`0` 表示不发送该头；在加载配置时一次性解析。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "PaddingRangeRaw", into = "String")]
+pub struct PaddingRange {
+    min: usize,
+    max: usize,
+}
+
+/// JSON 中既可以写成数字也可以写成字符串
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PaddingRangeRaw {
+    Number(usize),
+    Text(String),
+}
+
+impl TryFrom<PaddingRangeRaw> for PaddingRange {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: PaddingRangeRaw) -> Result<Self> {
+        match raw {
+            PaddingRangeRaw::Number(n) => Ok(Self { min: n, max: n }),
+            PaddingRangeRaw::Text(s) => s.parse(),
+        }
+    }
+}
+
+impl From<PaddingRange> for String {
+    fn from(range: PaddingRange) -> Self {
+        range.to_string()
+    }
+}
+
+/// 默认 64 到 511 字节
+impl Default for PaddingRange {
+    fn default() -> Self {
+        Self { min: 64, max: 511 }
+    }
+}
+
+impl PaddingRange {
+    /// 生成一个随机长度的填充字符串，范围为 0 时返回 None (不发送该头)
+    pub fn sample(&self) -> Option<String> {
+        if self.max == 0 {
+            return None;
+        }
+        let mut rng = rand::thread_rng();
+        let len = rng.gen_range(self.min..=self.max);
+        Some(rng.sample_iter(&Alphanumeric).take(len).map(char::from).collect())
+    }
+}
+
+fn parse_len(s: &str) -> Result<usize> {
+    s.trim().parse().map_err(|_| anyhow!("无效的 xPaddingBytes 长度: {:?}", s))
+}
+
+impl FromStr for PaddingRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (min, max) = match s.split_once('-') {
+            Some((min, max)) => (parse_len(min)?, parse_len(max)?),
+            None => {
+                let len = parse_len(s)?;
+                (len, len)
+            }
+        };
+        if min > max {
+            return Err(anyhow!("xPaddingBytes 范围起点大于终点: {}", s));
+        }
+        Ok(Self { min, max })
+    }
+}
+
+impl fmt::Display for PaddingRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.min == self.max {
+            write!(f, "{}", self.min)
+        } else {
+            write!(f, "{}-{}", self.min, self.max)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_forms() {
+        assert_eq!("100-1000".parse::<PaddingRange>().unwrap(), PaddingRange { min: 100, max: 1000 });
+        assert_eq!("256".parse::<PaddingRange>().unwrap(), PaddingRange { min: 256, max: 256 });
+        assert!("1000-100".parse::<PaddingRange>().is_err());
+        assert!("abc".parse::<PaddingRange>().is_err());
+
+        let number: PaddingRange = serde_json::from_value(serde_json::json!(0)).unwrap();
+        assert_eq!(number.sample(), None);
+        let text: PaddingRange = serde_json::from_value(serde_json::json!("10-20")).unwrap();
+        assert_eq!(serde_json::to_value(text).unwrap(), serde_json::json!("10-20"));
+    }
+
+    #[test]
+    fn test_sample_within_bounds() {
+        let range: PaddingRange = "100-110".parse().unwrap();
+        let mut seen = [false; 11];
+        for _ in 0..2000 {
+            let padding = range.sample().unwrap();
+            assert!((100..=110).contains(&padding.len()), "长度 {}", padding.len());
+            assert!(padding.bytes().all(|b| b.is_ascii_alphanumeric()));
+            seen[padding.len() - 100] = true;
+        }
+        // 两端都能取到
+        assert!(seen[0] && seen[10]);
+    }
+}
@@ -1,14 +1,57 @@
 use anyhow::{anyhow, Result};
+use tokio::io::{AsyncRead, AsyncReadExt};
 use tokio::net::TcpStream;
 use tracing::{debug, info};
 
-use super::{XhttpConfig, H2Handler, XhttpMode};
+use super::session::Sessions;
+use super::{XhttpConfig, H1Handler, H2Handler, XhttpMode};
+use std::net::IpAddr;
+use std::sync::{Arc, Once};
+use crate::transport::reality::server_rustls::PrefixedStream;
+
+/// HTTP/2 客户端连接前言
+const H2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// 连接使用的 HTTP 版本
+#[derive(Debug, PartialEq)]
+enum Protocol {
+    /// 以连接前言开头的 HTTP/2 (h2c prior knowledge 或 TLS 之上的 h2)
+    H2,
+    Http1,
+}
+
+/// 读取连接开头的数据判断协议，读到的数据放回流的开头，连接未发送任何数据就关闭时返回 None
+///
+/// 只读到能与 HTTP/2 连接前言区分为止，不会读过前言。
+async fn sniff<T: AsyncRead + Unpin>(mut stream: T) -> Result<Option<(Protocol, PrefixedStream<T>)>> {
+    let mut prefix = Vec::with_capacity(H2_PREFACE.len());
+    while prefix.len() < H2_PREFACE.len() && H2_PREFACE.starts_with(&prefix) {
+        let mut buf = [0u8; H2_PREFACE.len()];
+        let n = stream.read(&mut buf[..H2_PREFACE.len() - prefix.len()]).await?;
+        if n == 0 {
+            if prefix.is_empty() {
+                return Ok(None);
+            }
+            break;
+        }
+        prefix.extend_from_slice(&buf[..n]);
+    }
+    let protocol = if prefix == H2_PREFACE { Protocol::H2 } else { Protocol::Http1 };
+    Ok(Some((protocol, PrefixedStream::new(prefix, stream))))
+}
 
 /// XHTTP 服务器
 #[derive(Clone)]
 pub struct XhttpServer {
     config: XhttpConfig,
+    h1_handler: H1Handler,
     h2_handler: H2Handler,
+    #[cfg(feature = "http3")]
+    h3_handler: super::H3Handler,
+    /// 本入站的会话表，h2 与 HTTP/1.1 连接共用
+    sessions: Sessions,
+    /// 第一个连接到来时启动空闲会话清理任务
+    sweeper: Arc<Once>,
 }
 
 impl XhttpServer {
@@ -18,6 +61,7 @@ impl XhttpServer {
         if config.path.is_empty() {
             return Err(anyhow!("XHTTP path 不能为空"));
         }
+        config.h2_settings.validate().map_err(|e| anyhow!("XHTTP h2Settings 无效: {}", e))?;
 
         // if config.host.is_empty() {
         //     return Err(anyhow!("XHTTP host 不能为空"));
@@ -28,26 +72,103 @@ impl XhttpServer {
         debug!("路径: {}", config.path);
         debug!("Host: {}", config.host);
 
-        let h2_handler = H2Handler::new(config.clone());
+        let sessions = Sessions::default();
+        let h1_handler = H1Handler::new(config.clone()).with_sessions(sessions.clone());
+        let h2_handler = H2Handler::new(config.clone()).with_sessions(sessions.clone());
 
-        Ok(Self { config, h2_handler })
+        Ok(Self {
+            #[cfg(feature = "http3")]
+            h3_handler: super::H3Handler::new(config.clone()).with_sessions(sessions.clone()),
+            config,
+            h1_handler,
+            h2_handler,
+            sessions,
+            sweeper: Arc::new(Once::new()),
+        })
     }
 
-    /// 处理传入的连接
-    pub async fn accept<T, F, Fut>(&self, stream: T, handler: F) -> Result<()>
+    /// 与其他入站共用会话表 (sessionScope)，使上下行可以经过不同的入站或域名
+    pub fn with_sessions(mut self, sessions: Sessions) -> Self {
+        self.h1_handler = self.h1_handler.with_sessions(sessions.clone());
+        self.h2_handler = self.h2_handler.with_sessions(sessions.clone());
+        #[cfg(feature = "http3")]
+        {
+            self.h3_handler = self.h3_handler.with_sessions(sessions.clone());
+        }
+        self.sessions = sessions;
+        self
+    }
+
+    /// 处理来自 `peer` 的连接，`peer` 用于按来源 IP 限制会话数与请求数
+    pub async fn accept<T, F, Fut>(&self, stream: T, peer: IpAddr, handler: F) -> Result<()>
     where
         T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
         F: Fn(Box<dyn crate::server::AsyncStream>) -> Fut + Clone + Send + Sync + 'static,
         Fut: std::future::Future<Output = Result<()>> + Send + 'static,
     {
         debug!("接收到新的 XHTTP 连接");
+        self.sweeper.call_once(|| {
+            tokio::spawn(self.sessions.clone().sweep_periodically());
+        });
 
-        // 使用 H2Handler 处理 HTTP/2 连接
-        self.h2_handler.handle(stream, handler).await?;
+        // 没有 HTTP/2 连接前言的客户端 (h1 请求或只转发 HTTP/1.1 的 CDN) 交给 H1Handler
+        match sniff(stream).await? {
+            Some((Protocol::H2, stream)) => self.h2_handler.handle(stream, peer, handler).await?,
+            Some((Protocol::Http1, stream)) => self.h1_handler.handle(stream, peer, handler).await?,
+            None => debug!("XHTTP 连接未发送数据即关闭"),
+        }
 
         Ok(())
     }
 
+    /// 在 `addr` 上打开 HTTP/3 (QUIC) 监听，证书见 [`h3::server_config`](super::h3::server_config)
+    #[cfg(feature = "http3")]
+    pub fn bind_h3(
+        &self,
+        addr: std::net::SocketAddr,
+        cert_file: Option<&str>,
+        key_file: Option<&str>,
+        server_name: &str,
+    ) -> Result<quinn::Endpoint> {
+        let server_config = super::h3::server_config(&self.config, cert_file, key_file, server_name)?;
+        Ok(quinn::Endpoint::server(server_config, addr)?)
+    }
+
+    /// 处理 HTTP/3 监听上接受的 QUIC 连接，与 TCP 连接共用会话表
+    #[cfg(feature = "http3")]
+    pub async fn accept_h3<F, Fut>(&self, connection: quinn::Connection, handler: F) -> Result<()>
+    where
+        F: Fn(Box<dyn crate::server::AsyncStream>) -> Fut + Clone + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        debug!("接收到新的 XHTTP/3 连接");
+        self.sweeper.call_once(|| {
+            tokio::spawn(self.sessions.clone().sweep_periodically());
+        });
+        self.h3_handler.handle(connection, handler).await
+    }
+
+    /// 开始优雅关闭: 向所有连接发送 GOAWAY
+    pub fn shutdown(&self) {
+        self.h1_handler.shutdown();
+        self.h2_handler.shutdown();
+        #[cfg(feature = "http3")]
+        self.h3_handler.shutdown();
+    }
+
+    /// 终止所有仍在进行的 XHTTP 会话
+    pub fn terminate(&self) {
+        self.h1_handler.terminate();
+        self.h2_handler.terminate();
+        #[cfg(feature = "http3")]
+        self.h3_handler.terminate();
+    }
+
+    /// 当前的 XHTTP 会话数 (已打开下行、尚未结束的会话)
+    pub fn session_count(&self) -> usize {
+        self.sessions.count()
+    }
+
     /// 获取工作模式
     pub fn mode(&self) -> &XhttpMode {
         &self.config.mode
@@ -74,6 +195,19 @@ mod tests {
             mode: XhttpMode::StreamUp,
             path: "/".to_string(),
             host: "www.example.com".to_string(),
+            path_secret: String::new(),
+            sc_max_each_post_bytes: super::super::DEFAULT_SC_MAX_EACH_POST_BYTES,
+            sc_max_buffered_posts: super::super::DEFAULT_SC_MAX_BUFFERED_POSTS,
+            x_padding_bytes: Default::default(),
+            session_idle_timeout: super::super::DEFAULT_SESSION_IDLE_TIMEOUT,
+            no_grpc_header: false,
+            h2_settings: Default::default(),
+            keepalive_period: 0,
+            keepalive_timeout: crate::transport::xhttp::DEFAULT_KEEPALIVE_TIMEOUT,
+            response_headers: Default::default(),
+            cors_origins: Vec::new(),
+            max_sessions_per_ip: 0,
+            max_streams_per_ip: 0,
         };
 
         let server = XhttpServer::new(config);
@@ -90,8 +224,205 @@ mod tests {
             mode: XhttpMode::StreamUp,
             path: "".to_string(),
             host: "www.example.com".to_string(),
+            path_secret: String::new(),
+            sc_max_each_post_bytes: super::super::DEFAULT_SC_MAX_EACH_POST_BYTES,
+            sc_max_buffered_posts: super::super::DEFAULT_SC_MAX_BUFFERED_POSTS,
+            x_padding_bytes: Default::default(),
+            session_idle_timeout: super::super::DEFAULT_SESSION_IDLE_TIMEOUT,
+            no_grpc_header: false,
+            h2_settings: Default::default(),
+            keepalive_period: 0,
+            keepalive_timeout: crate::transport::xhttp::DEFAULT_KEEPALIVE_TIMEOUT,
+            response_headers: Default::default(),
+            cors_origins: Vec::new(),
+            max_sessions_per_ip: 0,
+            max_streams_per_ip: 0,
         };
         let server = XhttpServer::new(config);
         assert!(server.is_err());
     }
+
+    /// 逐字节写入数据，返回嗅探结果与放回后读到的全部数据
+    async fn sniff_bytes(data: &'static [u8]) -> (Protocol, Vec<u8>) {
+        let (mut client, server) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            use tokio::io::AsyncWriteExt;
+            for byte in data {
+                client.write_all(&[*byte]).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+        });
+        let (protocol, mut stream) = sniff(server).await.unwrap().unwrap();
+        let mut replay = Vec::new();
+        stream.read_to_end(&mut replay).await.unwrap();
+        (protocol, replay)
+    }
+
+    #[tokio::test]
+    async fn test_sniff_protocol() {
+        // 前言分多次到达也能识别，之后的 SETTINGS 帧不会被提前读走
+        let h2 = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n\x00\x00\x00\x04\x00\x00\x00\x00\x00";
+        assert_eq!(sniff_bytes(h2).await, (Protocol::H2, h2.to_vec()));
+
+        let h1 = b"POST /xh HTTP/1.1\r\n\r\n";
+        assert_eq!(sniff_bytes(h1).await, (Protocol::Http1, h1.to_vec()));
+
+        // 前言不完整的连接按 HTTP/1.1 处理
+        assert_eq!(sniff_bytes(b"PRI * HTTP").await, (Protocol::Http1, b"PRI * HTTP".to_vec()));
+
+        let (client, server) = tokio::io::duplex(64);
+        drop(client);
+        assert!(sniff(server).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_idle_session_swept() {
+        let server = XhttpServer::new(XhttpConfig {
+            mode: XhttpMode::Auto,
+            path: "/gc".to_string(),
+            host: String::new(),
+            path_secret: String::new(),
+            sc_max_each_post_bytes: super::super::DEFAULT_SC_MAX_EACH_POST_BYTES,
+            sc_max_buffered_posts: super::super::DEFAULT_SC_MAX_BUFFERED_POSTS,
+            x_padding_bytes: Default::default(),
+            session_idle_timeout: 1,
+            no_grpc_header: false,
+            h2_settings: Default::default(),
+            keepalive_period: 0,
+            keepalive_timeout: crate::transport::xhttp::DEFAULT_KEEPALIVE_TIMEOUT,
+            response_headers: Default::default(),
+            cors_origins: Vec::new(),
+            max_sessions_per_ip: 0,
+            max_streams_per_ip: 0,
+        })
+        .unwrap();
+
+        let (client_io, server_io) = tokio::io::duplex(1 << 16);
+        let inner = |stream: Box<dyn crate::server::AsyncStream>| async move {
+            // 内层会话一直等待数据，直到管道关闭
+            let mut stream = stream;
+            let mut buf = [0u8; 64];
+            while stream.read(&mut buf).await? > 0 {}
+            Ok(())
+        };
+        tokio::spawn({
+            let server = server.clone();
+            async move { server.accept(server_io, IpAddr::from([127, 0, 0, 1]), inner).await }
+        });
+        let (mut client, connection) = h2::client::handshake(client_io).await.unwrap();
+        tokio::spawn(connection);
+
+        // 只打开下行 GET，之后不再有任何上下行数据
+        let request = hyper::http::Request::get("https://example.com/gc/lonely").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        let mut body = response.await.unwrap().into_body();
+        assert!(server.sessions.contains("lonely"));
+
+        let ended = tokio::time::timeout(std::time::Duration::from_secs(5), body.data()).await.unwrap();
+        assert!(!matches!(ended, Some(Ok(ref data)) if !data.is_empty()));
+        assert!(!server.sessions.contains("lonely"));
+    }
+
+    /// 建立一条 h2c 连接，内层会话一直等待数据直到管道关闭
+    async fn connect_h2(server: &XhttpServer) -> h2::client::SendRequest<bytes::Bytes> {
+        let (client_io, server_io) = tokio::io::duplex(1 << 16);
+        let inner = |mut stream: Box<dyn crate::server::AsyncStream>| async move {
+            let mut buf = [0u8; 64];
+            while stream.read(&mut buf).await? > 0 {}
+            Ok(())
+        };
+        tokio::spawn({
+            let server = server.clone();
+            async move { server.accept(server_io, IpAddr::from([127, 0, 0, 1]), inner).await }
+        });
+        let (client, connection) = h2::client::handshake(client_io).await.unwrap();
+        tokio::spawn(connection);
+        client
+    }
+
+    #[tokio::test]
+    async fn test_servers_have_separate_sessions() {
+        let config = XhttpConfig {
+            mode: XhttpMode::StreamUp,
+            path: "/iso".to_string(),
+            host: String::new(),
+            path_secret: String::new(),
+            sc_max_each_post_bytes: super::super::DEFAULT_SC_MAX_EACH_POST_BYTES,
+            sc_max_buffered_posts: super::super::DEFAULT_SC_MAX_BUFFERED_POSTS,
+            x_padding_bytes: Default::default(),
+            session_idle_timeout: super::super::DEFAULT_SESSION_IDLE_TIMEOUT,
+            no_grpc_header: false,
+            h2_settings: Default::default(),
+            keepalive_period: 0,
+            keepalive_timeout: crate::transport::xhttp::DEFAULT_KEEPALIVE_TIMEOUT,
+            response_headers: Default::default(),
+            cors_origins: Vec::new(),
+            max_sessions_per_ip: 0,
+            max_streams_per_ip: 0,
+        };
+        let first = XhttpServer::new(config.clone()).unwrap();
+        let second = XhttpServer::new(config).unwrap();
+        let mut first_client = connect_h2(&first).await;
+        let mut second_client = connect_h2(&second).await;
+
+        let request = hyper::http::Request::get("https://example.com/iso/same").body(()).unwrap();
+        let (response, _) = first_client.send_request(request, true).unwrap();
+        let _first_body = response.await.unwrap().into_body();
+        assert_eq!((first.session_count(), second.session_count()), (1, 0));
+
+        // 另一个入站看不到该会话，同 ID 的上行无法配对
+        let request = hyper::http::Request::post("https://example.com/iso/same/0").body(()).unwrap();
+        let (response, _) = second_client.send_request(request, true).unwrap();
+        assert_eq!(response.await.unwrap().status(), hyper::http::StatusCode::NOT_FOUND);
+
+        // 同 ID 的下行只在各自的入站登记，不会关闭另一个入站的会话
+        let request = hyper::http::Request::get("https://example.com/iso/same").body(()).unwrap();
+        let (response, _) = second_client.send_request(request, true).unwrap();
+        let _second_body = response.await.unwrap().into_body();
+        assert!(first.sessions.contains("same") && second.sessions.contains("same"));
+        assert_eq!((first.session_count(), second.session_count()), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_shared_scope_pairs_across_inbounds() {
+        let config = |host: &str| -> XhttpConfig {
+            serde_json::from_value(serde_json::json!({ "mode": "auto", "path": "/split", "host": host })).unwrap()
+        };
+        let sessions = Sessions::default();
+        let download = XhttpServer::new(config("down.example.com")).unwrap().with_sessions(sessions.clone());
+        let upload = XhttpServer::new(config("up.example.com")).unwrap().with_sessions(sessions);
+
+        // 下行入站上的内层会话回显收到的上行数据
+        let (client_io, server_io) = tokio::io::duplex(1 << 16);
+        let echo = |mut stream: Box<dyn crate::server::AsyncStream>| async move {
+            use tokio::io::AsyncWriteExt;
+            let mut buf = [0u8; 64];
+            loop {
+                let n = stream.read(&mut buf).await?;
+                if n == 0 {
+                    return Ok(());
+                }
+                stream.write_all(&buf[..n]).await?;
+            }
+        };
+        tokio::spawn({
+            let download = download.clone();
+            async move { download.accept(server_io, IpAddr::from([127, 0, 0, 1]), echo).await }
+        });
+        let (mut down_client, connection) = h2::client::handshake(client_io).await.unwrap();
+        tokio::spawn(connection);
+        let mut up_client = connect_h2(&upload).await;
+
+        let request = hyper::http::Request::get("https://down.example.com/split/pair").body(()).unwrap();
+        let (response, _) = down_client.send_request(request, true).unwrap();
+        let mut body = response.await.unwrap().into_body();
+        assert_eq!(upload.session_count(), 1);
+
+        // 上行经另一条连接、另一个域名到达另一个入站，仍与下行配对
+        let request = hyper::http::Request::post("https://up.example.com/split/pair/0").body(()).unwrap();
+        let (response, mut data) = up_client.send_request(request, false).unwrap();
+        data.send_data(bytes::Bytes::from_static(b"across"), true).unwrap();
+        assert_eq!(response.await.unwrap().status(), hyper::http::StatusCode::OK);
+        assert_eq!(body.data().await.unwrap().unwrap(), bytes::Bytes::from_static(b"across"));
+    }
 }
@@ -0,0 +1,553 @@
+//! XHTTP 会话配对与请求分派，HTTP/2 与 HTTP/1.1 两条路径共用
+
+use bytes::Bytes;
+use hyper::http::{HeaderMap, Method, StatusCode};
+use once_cell::sync::Lazy;
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Notify};
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+use super::cors::{self, Origin};
+use super::limit::{IpPermit, PerIp};
+use super::{XhttpConfig, XhttpMode};
+
+/// 上行通道最多排队的数据段数，排满后上行请求等待 VLESS 一侧读取
+///
+/// 这是内部的反压缓冲，与 `scMaxBufferedPosts` (packet-up 的重排窗口) 无关。
+const UPLINK_QUEUE: usize = 4;
+
+/// 清理空闲会话的间隔
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 计算会话空闲时间的起点
+static CLOCK: Lazy<Instant> = Lazy::new(Instant::now);
+
+/// 会话最近一次收发数据的时间 (相对 [`CLOCK`] 的毫秒数)
+#[derive(Clone)]
+pub(super) struct Activity(Arc<AtomicU64>);
+
+impl Activity {
+    fn new() -> Self {
+        let activity = Self(Arc::new(AtomicU64::new(0)));
+        activity.touch();
+        activity
+    }
+
+    /// 记录一次上行或下行数据
+    pub(super) fn touch(&self) {
+        self.0.store(CLOCK.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn idle(&self) -> Duration {
+        let last = Duration::from_millis(self.0.load(Ordering::Relaxed));
+        CLOCK.elapsed().saturating_sub(last)
+    }
+}
+
+/// 一个 packet-up 会话: 下行 GET 与配对的上行 POST 共用
+struct Session {
+    to_vless_tx: mpsc::Sender<Bytes>,
+    reorder: Arc<tokio::sync::Mutex<Reorder>>,
+    /// 重排缓冲最多容纳的 POST 数 (`scMaxBufferedPosts`)
+    max_buffered_posts: usize,
+    notify: Arc<Notify>,
+    /// 同一 session ID 被新的 GET 占用或空闲超时时关闭会话
+    closed: CancellationToken,
+    activity: Activity,
+    /// 空闲超过该时间的会话被清理，0 表示不清理
+    idle_timeout: Duration,
+}
+
+impl Session {
+    fn uplink(&self) -> Uplink {
+        Uplink {
+            tx: self.to_vless_tx.clone(),
+            reorder: self.reorder.clone(),
+            max_buffered_posts: self.max_buffered_posts,
+            activity: self.activity.clone(),
+        }
+    }
+}
+
+/// packet-up 上行的重排缓冲
+///
+/// 客户端在不同的流 (或连接) 上并发发送 `{path}/{sessionId}/{seq}`，到达顺序不一定与 seq 一致。
+/// 每个 POST 的请求体整段缓存在这里，只有下一个期望的 seq 到齐后才依次交给 VLESS。
+#[derive(Default)]
+struct Reorder {
+    next: u64,
+    pending: BTreeMap<u64, Bytes>,
+}
+
+/// packet-up 上行无法交付的原因
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum PushError {
+    /// seq 已经交付过，或超出下一个期望 seq 之后 `scMaxBufferedPosts` 的范围
+    OutOfWindow,
+    /// 会话已结束
+    Closed,
+}
+
+/// 已配对会话的上行端，发送数据时刷新会话的活跃时间
+pub(super) struct Uplink {
+    tx: mpsc::Sender<Bytes>,
+    reorder: Arc<tokio::sync::Mutex<Reorder>>,
+    max_buffered_posts: usize,
+    activity: Activity,
+}
+
+impl Uplink {
+    /// stream-up: 单个长 POST 的数据按到达顺序直接交给 VLESS
+    pub(super) async fn send(&self, data: Bytes) -> Result<(), mpsc::error::SendError<Bytes>> {
+        self.activity.touch();
+        self.tx.send(data).await
+    }
+
+    /// packet-up: 交付序号为 `seq` 的完整请求体，它之前的 seq 到齐后才按顺序发出
+    ///
+    /// 交付期间持有重排缓冲的锁 (跨越 await，因此是 tokio 的 Mutex)，
+    /// 并发的 POST 不会把各自的数据交错写入会话。
+    pub(super) async fn push(&self, seq: u64, body: Bytes) -> Result<(), PushError> {
+        self.activity.touch();
+        let mut reorder = self.reorder.lock().await;
+        let ahead = seq.checked_sub(reorder.next).ok_or(PushError::OutOfWindow)?;
+        if ahead >= self.max_buffered_posts as u64 || reorder.pending.contains_key(&seq) {
+            debug!("XHTTP: seq {} 不在重排窗口内 (下一个期望 {})", seq, reorder.next);
+            return Err(PushError::OutOfWindow);
+        }
+        reorder.pending.insert(seq, body);
+        loop {
+            let next = reorder.next;
+            let Some(body) = reorder.pending.remove(&next) else {
+                return Ok(());
+            };
+            reorder.next += 1;
+            self.tx.send(body).await.map_err(|_| PushError::Closed)?;
+        }
+    }
+}
+
+/// 一个 XHTTP 入站的会话表，以客户端生成的 session ID 为键
+///
+/// 每个 `XhttpServer` 持有自己的会话表，只有配置了相同 sessionScope 的入站才共用。
+/// 会话表同时统计每个来源 IP 的会话数与进行中的请求数。
+///
+/// 这里有意使用 `std::sync::Mutex` 而不是 tokio 的 Mutex 或 dashmap: 锁只在本类型的
+/// 同步方法内部短暂持有，取出 `Uplink` 等句柄后即释放，之后的发送与等待都在锁外进行。
+/// 修改时不要在持有锁期间 `.await`，否则会阻塞运行时线程；确实需要跨越 await 时应改用
+/// `tokio::sync::Mutex`。
+#[derive(Clone, Default)]
+pub struct Sessions {
+    inner: Arc<Mutex<HashMap<String, Session>>>,
+    sessions_per_ip: PerIp,
+    streams_per_ip: PerIp,
+}
+
+/// 从会话表中移除 packet-up 会话，会话任务结束或被取消时都会执行
+pub(super) struct SessionGuard {
+    sessions: Sessions,
+    id: String,
+    notify: Arc<Notify>,
+    activity: Activity,
+    _permit: IpPermit,
+}
+
+impl SessionGuard {
+    /// 会话的活跃时间，下行发送数据时刷新
+    pub(super) fn activity(&self) -> Activity {
+        self.activity.clone()
+    }
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        let mut sessions = self.sessions.inner.lock().unwrap();
+        // 会话可能已被同 ID 的新会话替换，只移除自己
+        if sessions.get(&self.id).is_some_and(|s| Arc::ptr_eq(&s.notify, &self.notify)) {
+            sessions.remove(&self.id);
+        }
+        self.notify.notify_waiters();
+    }
+}
+
+impl Sessions {
+    /// 为下行 GET 登记会话，返回上行数据的接收端与会话被替换时的关闭信号
+    ///
+    /// 上行通道最多排队 [`UPLINK_QUEUE`] 段数据，排满后上行请求等待 VLESS 一侧读取；
+    /// packet-up 的 POST 另按 seq 在重排缓冲中等候，最多 `scMaxBufferedPosts` 个。
+    /// 来源 IP 的会话许可 (见 [`route`]) 随会话结束归还。
+    pub(super) fn open(
+        &self,
+        id: String,
+        permit: IpPermit,
+        config: &XhttpConfig,
+    ) -> (mpsc::Receiver<Bytes>, CancellationToken, SessionGuard) {
+        let (to_vless_tx, to_vless_rx) = mpsc::channel::<Bytes>(UPLINK_QUEUE);
+        let notify = Arc::new(Notify::new());
+        let closed = CancellationToken::new();
+        let activity = Activity::new();
+        let session = Session {
+            to_vless_tx,
+            reorder: Arc::default(),
+            max_buffered_posts: config.sc_max_buffered_posts,
+            notify: notify.clone(),
+            closed: closed.clone(),
+            activity: activity.clone(),
+            idle_timeout: Duration::from_secs(config.session_idle_timeout),
+        };
+        if let Some(old) = self.inner.lock().unwrap().insert(id.clone(), session) {
+            debug!("XHTTP: session {} 被新的 GET 占用，关闭旧会话", id);
+            old.closed.cancel();
+        }
+        (to_vless_rx, closed, SessionGuard { sessions: self.clone(), id, notify, activity, _permit: permit })
+    }
+
+    /// 为来源 IP 的一个新请求取得许可，进行中的请求数已达 `maxStreamsPerIp` 时返回 None
+    ///
+    /// 许可在请求处理结束时释放。
+    pub(super) fn begin_stream(&self, peer: IpAddr, config: &XhttpConfig) -> Option<IpPermit> {
+        let permit = self.streams_per_ip.try_acquire(peer, config.max_streams_per_ip);
+        if permit.is_none() {
+            debug!("XHTTP: {} 进行中的请求数已达 maxStreamsPerIp ({})", peer, config.max_streams_per_ip);
+        }
+        permit
+    }
+
+    /// 已登记会话的上行端
+    fn uplink(&self, id: &str) -> Option<Uplink> {
+        self.inner.lock().unwrap().get(id).map(Session::uplink)
+    }
+
+    /// 等待同一 session ID 的 GET 建立会话，最多约 500ms
+    async fn wait_uplink(&self, id: &str) -> Option<Uplink> {
+        for attempt in 0..10 {
+            if let Some(uplink) = self.uplink(id) {
+                return Some(uplink);
+            }
+            if attempt < 9 {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        }
+        None
+    }
+
+    /// 移除空闲超时的会话并关闭它们的下行，返回移除的数量
+    ///
+    /// 客户端只打开 GET 就断开 (或上行始终无法配对) 时，会话不会自行结束。
+    pub(super) fn sweep(&self) -> usize {
+        let mut sessions = self.inner.lock().unwrap();
+        let before = sessions.len();
+        sessions.retain(|id, session| {
+            let expired = !session.idle_timeout.is_zero() && session.activity.idle() >= session.idle_timeout;
+            if expired {
+                debug!("XHTTP: session {} 空闲超时，清理", id);
+                session.closed.cancel();
+            }
+            !expired
+        });
+        before - sessions.len()
+    }
+
+    /// 关闭所有会话的下行并清空会话表 (排空超时后调用)
+    pub(super) fn close_all(&self) {
+        for (id, session) in self.inner.lock().unwrap().drain() {
+            debug!("XHTTP: 关闭时结束 session {}", id);
+            session.closed.cancel();
+        }
+    }
+
+    /// 定期清理空闲会话，会话表被释放 (所属服务器已销毁) 后结束
+    pub(super) async fn sweep_periodically(self) {
+        let weak = Arc::downgrade(&self.inner);
+        drop(self);
+        let mut ticker = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            ticker.tick().await;
+            match weak.upgrade() {
+                Some(inner) => Sessions { inner, ..Default::default() }.sweep(),
+                None => return,
+            };
+        }
+    }
+
+    /// 当前的会话数
+    pub(super) fn count(&self) -> usize {
+        self.inner.lock().unwrap().len()
+    }
+
+    /// 是否存在该 session ID 的会话
+    #[cfg(test)]
+    pub(super) fn contains(&self, id: &str) -> bool {
+        self.inner.lock().unwrap().contains_key(id)
+    }
+
+    /// 该来源 IP 的会话数与进行中的请求数
+    #[cfg(test)]
+    pub(super) fn per_ip(&self, peer: IpAddr) -> (usize, usize) {
+        (self.sessions_per_ip.count(peer), self.streams_per_ip.count(peer))
+    }
+}
+
+/// 比较两段字节，耗时只取决于长度而不是第一个不同字节的位置
+///
+/// 用于路径与 pathSecret，避免探测者根据响应时间逐字节猜出它们。
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// 请求路径是否位于配置的路径之下 (按路径段匹配，`/api` 不匹配 `/apix`)
+///
+/// 配置的路径可能就是一段长随机串，以恒定时间比较。
+pub(super) fn matches_path(base: &str, path: &str) -> bool {
+    let base = base.trim_end_matches('/');
+    let matched = path.as_bytes().get(..base.len()).is_some_and(|prefix| constant_time_eq(prefix, base.as_bytes()));
+    match path.get(base.len()..) {
+        Some(rest) if matched => rest.is_empty() || rest.starts_with('/'),
+        _ => false,
+    }
+}
+
+/// 请求的查询串是否带有配置的 pathSecret
+///
+/// 客户端把密钥写在路径的查询串中 (`/xh?secret`)，xray 客户端的每个请求都会带上它；
+/// 以 `&` 分隔的任意一项与密钥相同即可。配置为空时不检查。
+pub(super) fn secret_matches(secret: &str, query: Option<&str>) -> bool {
+    if secret.is_empty() {
+        return true;
+    }
+    // 比较每一项，不在第一个匹配处停止
+    query
+        .unwrap_or("")
+        .split('&')
+        .fold(false, |found, item| constant_time_eq(item.as_bytes(), secret.as_bytes()) | found)
+}
+
+/// 取出 xray 客户端附加在配置路径之后的 session ID: `{path}/{sessionId}[/{seq}]`
+pub(super) fn session_id<'a>(base: &str, path: &'a str) -> Option<&'a str> {
+    let rest = path.strip_prefix(base.trim_end_matches('/'))?;
+    rest.trim_start_matches('/').split('/').next().filter(|id| !id.is_empty())
+}
+
+/// 取出 packet-up 上行路径中 session ID 之后的 seq 段，stream-up 的 POST 没有该段
+fn seq_segment<'a>(base: &str, path: &'a str) -> Option<&'a str> {
+    let rest = path.strip_prefix(base.trim_end_matches('/'))?;
+    rest.trim_start_matches('/').split('/').nth(1).filter(|seq| !seq.is_empty())
+}
+
+/// 请求的 `:authority` / Host 是否符合配置的 host
+///
+/// 配置为空时不检查；`*.example.com` 匹配任意子域名 (不含 `example.com` 本身)。比较时忽略端口与大小写。
+pub(super) fn host_matches(configured: &str, authority: Option<&str>) -> bool {
+    if configured.is_empty() {
+        return true;
+    }
+    let Some(authority) = authority else {
+        return false;
+    };
+    // 去掉端口，IPv6 地址保留方括号
+    let host = match authority.strip_prefix('[') {
+        Some(rest) => rest.split_once(']').map_or(authority, |(ip, _)| &authority[..ip.len() + 2]),
+        None => authority.split_once(':').map_or(authority, |(host, _)| host),
+    };
+    match configured.strip_prefix("*.") {
+        Some(domain) => host
+            .len()
+            .checked_sub(domain.len() + 1)
+            .filter(|&dot| host.as_bytes()[dot] == b'.' && dot > 0)
+            .is_some_and(|dot| host[dot + 1..].eq_ignore_ascii_case(domain)),
+        None => host.eq_ignore_ascii_case(configured),
+    }
+}
+
+/// 独立流是否使用 gRPC 分帧
+///
+/// 明确的 protobuf 类型 (`application/grpc+proto`、`application/x-protobuf`) 直接采用；
+/// 只有 `application/grpc` 时，xray 的 Go 客户端仅用它做伪装，不做分帧。
+fn wants_grpc(content_type: &str, is_pc: bool) -> bool {
+    let content_type = content_type.to_ascii_lowercase();
+    if content_type.contains("grpc+proto") || content_type.contains("x-protobuf") {
+        return true;
+    }
+    content_type.contains("grpc") && !is_pc
+}
+
+/// 一个请求的处理方式
+pub(super) enum Route {
+    /// 以该状态码拒绝
+    Reject(StatusCode),
+    /// 下行 GET: 以 session ID 登记会话，持有来源 IP 的会话许可
+    Download(String, IpPermit),
+    /// 上行 POST: 请求体转发给已配对的会话；带 seq 的 packet-up 请求体整段按序交付
+    Upload(Uplink, Option<u64>),
+    /// 单个请求同时承载上下行 (stream-one)
+    Standalone { grpc: bool },
+    /// 浏览器的 CORS 预检，回复 204
+    Preflight,
+}
+
+/// 按配置的模式决定请求的处理方式，auto 模式下根据 User-Agent 与 Content-Type 猜测
+///
+/// 来源 IP 的会话数已达 `maxSessionsPerIp` 时新的下行 GET 得到 429。
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn route(
+    config: &XhttpConfig,
+    sessions: &Sessions,
+    peer: IpAddr,
+    method: &Method,
+    authority: Option<&str>,
+    path: &str,
+    query: Option<&str>,
+    headers: &HeaderMap,
+) -> Route {
+    // Host 不符的请求 (如直接扫描 IP) 与未知路径一样返回 404，不进入配对逻辑
+    if !host_matches(&config.host, authority) {
+        debug!("XHTTP: Host {:?} 与配置的 {} 不符", authority, config.host);
+        return Route::Reject(StatusCode::NOT_FOUND);
+    }
+    if !matches_path(&config.path, path) {
+        return Route::Reject(StatusCode::NOT_FOUND);
+    }
+    // 缺少或带错密钥的请求同样只看到 404，在建立任何会话之前拒绝
+    if !secret_matches(&config.path_secret, query) {
+        debug!("XHTTP: 来自 {} 的请求没有正确的 pathSecret", peer);
+        return Route::Reject(StatusCode::NOT_FOUND);
+    }
+    match cors::check(config, headers) {
+        Origin::Denied => return Route::Reject(StatusCode::NOT_FOUND),
+        Origin::Allowed(_) if *method == Method::OPTIONS => return Route::Preflight,
+        Origin::Allowed(_) | Origin::None => {}
+    }
+    let session = session_id(&config.path, path);
+    let Ok(seq) = seq_segment(&config.path, path).map(str::parse::<u64>).transpose() else {
+        debug!("XHTTP: 上行路径 {} 的 seq 不是数字", path);
+        return Route::Reject(StatusCode::BAD_REQUEST);
+    };
+    let download = |id: &str| match sessions.sessions_per_ip.try_acquire(peer, config.max_sessions_per_ip) {
+        Some(permit) => Route::Download(id.to_string(), permit),
+        None => {
+            debug!("XHTTP: {} 的会话数已达 maxSessionsPerIp ({})", peer, config.max_sessions_per_ip);
+            Route::Reject(StatusCode::TOO_MANY_REQUESTS)
+        }
+    };
+
+    match config.mode {
+        // stream-one: 单个 POST 同时承载上下行，不做会话配对
+        XhttpMode::StreamOne => match *method {
+            Method::POST => Route::Standalone { grpc: false },
+            Method::GET => Route::Reject(StatusCode::NOT_FOUND),
+            _ => Route::Reject(StatusCode::METHOD_NOT_ALLOWED),
+        },
+        // stream-up / stream-down: GET 承载下行，POST 必须配对到已有会话
+        XhttpMode::StreamUp | XhttpMode::StreamDown => {
+            let Some(id) = session else {
+                return Route::Reject(StatusCode::NOT_FOUND);
+            };
+            match *method {
+                Method::GET => download(id),
+                Method::POST => match sessions.wait_uplink(id).await {
+                    Some(tx) => Route::Upload(tx, seq),
+                    None => Route::Reject(StatusCode::NOT_FOUND),
+                },
+                _ => Route::Reject(StatusCode::METHOD_NOT_ALLOWED),
+            }
+        }
+        XhttpMode::Auto => match *method {
+            // 没有 session ID 的 GET 无法与上行配对
+            Method::GET => match session {
+                Some(id) => download(id),
+                None => Route::Reject(StatusCode::NOT_FOUND),
+            },
+            Method::POST => {
+                let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).unwrap_or("");
+                let is_pc = header("user-agent").contains("Go-http-client");
+
+                // 等候配对逻辑
+                let session_tx = match session {
+                    None => None,
+                    Some(id) if is_pc => sessions.uplink(id),
+                    Some(id) => sessions.wait_uplink(id).await,
+                };
+
+                match session_tx {
+                    Some(tx) => Route::Upload(tx, seq),
+                    None => Route::Standalone { grpc: !config.no_grpc_header && wants_grpc(header("content-type"), is_pc) },
+                }
+            }
+            _ => Route::Reject(StatusCode::METHOD_NOT_ALLOWED),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_id() {
+        assert_eq!(session_id("/drain", "/drain/abc"), Some("abc"));
+        assert_eq!(session_id("/drain/", "/drain/abc/3"), Some("abc"));
+        assert_eq!(session_id("/", "/abc/0"), Some("abc"));
+        assert_eq!(session_id("/drain", "/drain"), None);
+        assert_eq!(session_id("/drain", "/drain/"), None);
+        assert!(matches_path("/drain", "/drain/abc"));
+        assert!(matches_path("/", "/abc"));
+        assert!(!matches_path("/drain", "/drainage/abc"));
+        assert!(!matches_path("/drain", "/other/abc"));
+    }
+
+    #[test]
+    fn test_wants_grpc() {
+        assert!(wants_grpc("application/grpc", false));
+        assert!(!wants_grpc("application/grpc", true));
+        assert!(wants_grpc("application/grpc+proto", true));
+        assert!(wants_grpc("application/x-protobuf", true));
+        assert!(!wants_grpc("", false));
+        assert!(!wants_grpc("application/octet-stream", false));
+    }
+
+    #[test]
+    fn test_host_matches() {
+        assert!(host_matches("", None));
+        assert!(host_matches("", Some("1.2.3.4")));
+        assert!(host_matches("cdn.example.com", Some("cdn.example.com")));
+        assert!(host_matches("cdn.example.com", Some("CDN.Example.com:443")));
+        assert!(!host_matches("cdn.example.com", Some("1.2.3.4:443")));
+        assert!(!host_matches("cdn.example.com", Some("cdn.example.com.evil.test")));
+        assert!(!host_matches("cdn.example.com", None));
+        assert!(!host_matches("cdn.example.com", Some("")));
+
+        assert!(host_matches("*.example.com", Some("a.example.com")));
+        assert!(host_matches("*.example.com", Some("a.b.example.com:8443")));
+        assert!(!host_matches("*.example.com", Some("example.com")));
+        assert!(!host_matches("*.example.com", Some(".example.com")));
+        assert!(!host_matches("*.example.com", Some("badexample.com")));
+
+        assert!(host_matches("[::1]", Some("[::1]:443")));
+        assert!(!host_matches("[::1]", Some("[::2]")));
+    }
+
+    #[test]
+    fn test_secret_matches() {
+        assert!(secret_matches("", None));
+        assert!(secret_matches("", Some("anything")));
+        assert!(secret_matches("k9Qz", Some("k9Qz")));
+        assert!(secret_matches("k9Qz", Some("ed=2048&k9Qz")));
+        assert!(secret_matches("key=k9Qz", Some("key=k9Qz&x=1")));
+        assert!(!secret_matches("k9Qz", None));
+        assert!(!secret_matches("k9Qz", Some("")));
+        assert!(!secret_matches("k9Qz", Some("k9Q")));
+        assert!(!secret_matches("k9Qz", Some("k9Qzz")));
+        assert!(!secret_matches("k9Qz", Some("K9QZ")));
+
+        assert!(constant_time_eq(b"", b""));
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"Secret"));
+        assert!(!constant_time_eq(b"secret", b"secrets"));
+    }
+}
@@ -0,0 +1,155 @@
+//! 运行时工作线程的 CPU 绑定
+//!
+//! 多路服务器上把 tokio 工作线程固定在同一 NUMA 节点或固定的核心上，可以减少
+//! 跨节点访存和缓存失效。绑定通过 `sched_setaffinity` 实现，仅支持 Linux，
+//! 其他平台只输出警告。
+
+use crate::config::{AffinityMode, RuntimeConfig};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::runtime::{Builder, Runtime};
+use tracing::warn;
+
+/// 支持的最大 CPU 编号 (不含)，与 glibc 的 `CPU_SETSIZE` 一致
+pub const MAX_CPUS: usize = 1024;
+
+/// 把当前线程绑定到 `cpus`
+#[cfg(target_os = "linux")]
+pub fn set_current_thread_affinity(cpus: &[usize]) -> io::Result<()> {
+    // SAFETY: cpu_set_t 是纯数据结构，全零即空集合
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for &cpu in cpus {
+        if cpu >= MAX_CPUS {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("CPU 编号超出范围: {}", cpu)));
+        }
+        // SAFETY: cpu 已检查小于集合容量
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+    }
+    // SAFETY: pid 0 表示调用线程，set 在调用期间有效
+    let ret = unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// 读取当前线程允许运行的 CPU 列表 (升序)
+#[cfg(target_os = "linux")]
+pub fn current_thread_affinity() -> io::Result<Vec<usize>> {
+    // SAFETY: 同上
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    let ret = unsafe { libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((0..MAX_CPUS).filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &set) }).collect())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_current_thread_affinity(_cpus: &[usize]) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "CPU 绑定仅支持 Linux"))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn current_thread_affinity() -> io::Result<Vec<usize>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "CPU 绑定仅支持 Linux"))
+}
+
+/// 按配置为每个新启动的运行时线程计算并应用绑定
+struct AffinityPlan {
+    cpus: Vec<usize>,
+    mode: AffinityMode,
+    next: AtomicUsize,
+}
+
+impl AffinityPlan {
+    /// 下一个线程应绑定的 CPU 集合
+    fn next_cpus(&self) -> Vec<usize> {
+        match self.mode {
+            AffinityMode::Shared => self.cpus.clone(),
+            AffinityMode::PerThread => {
+                let index = self.next.fetch_add(1, Ordering::Relaxed);
+                vec![self.cpus[index % self.cpus.len()]]
+            }
+        }
+    }
+
+    fn apply(&self) {
+        let cpus = self.next_cpus();
+        if let Err(e) = set_current_thread_affinity(&cpus) {
+            warn!("⚠️ 设置线程 CPU 绑定 {:?} 失败: {}", cpus, e);
+        }
+    }
+}
+
+/// 按配置构建多线程运行时
+pub fn build_runtime(config: &RuntimeConfig) -> io::Result<Runtime> {
+    let mut builder = Builder::new_multi_thread();
+    builder.enable_all();
+    if let Some(threads) = config.worker_threads {
+        builder.worker_threads(threads);
+    }
+    if !config.cpu_affinity.is_empty() {
+        let plan = Arc::new(AffinityPlan {
+            cpus: config.cpu_affinity.clone(),
+            mode: config.affinity_mode,
+            next: AtomicUsize::new(0),
+        });
+        builder.on_thread_start(move || plan.apply());
+    }
+    builder.build()
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_per_thread_plan_round_robin() {
+        let plan = AffinityPlan {
+            cpus: vec![2, 5],
+            mode: AffinityMode::PerThread,
+            next: AtomicUsize::new(0),
+        };
+        let picked: Vec<_> = (0..4).map(|_| plan.next_cpus()).collect();
+        assert_eq!(picked, vec![vec![2], vec![5], vec![2], vec![5]]);
+
+        let shared = AffinityPlan {
+            cpus: vec![2, 5],
+            mode: AffinityMode::Shared,
+            next: AtomicUsize::new(0),
+        };
+        assert_eq!(shared.next_cpus(), vec![2, 5]);
+    }
+
+    #[test]
+    fn test_worker_threads_are_pinned() {
+        // 只绑定到当前进程允许的某个 CPU，受限环境 (容器、cgroup) 下也能通过
+        let allowed = current_thread_affinity().unwrap();
+        let cpu = *allowed.last().unwrap();
+        if std::thread::spawn(move || set_current_thread_affinity(&[cpu])).join().unwrap().is_err() {
+            eprintln!("跳过: 当前环境不允许设置 CPU 绑定");
+            return;
+        }
+
+        let config = RuntimeConfig {
+            worker_threads: Some(2),
+            cpu_affinity: vec![cpu],
+            affinity_mode: AffinityMode::Shared,
+            ..RuntimeConfig::default()
+        };
+        let runtime = build_runtime(&config).unwrap();
+        let handles: Vec<_> = (0..4).map(|_| runtime.spawn(async { current_thread_affinity().unwrap() })).collect();
+        runtime.block_on(async {
+            for handle in handles {
+                assert_eq!(handle.await.unwrap(), vec![cpu]);
+            }
+        });
+        let blocking = runtime.block_on(runtime.spawn_blocking(|| current_thread_affinity().unwrap())).unwrap();
+        assert_eq!(blocking, vec![cpu]);
+
+        // 测试线程本身不受影响
+        assert_eq!(current_thread_affinity().unwrap(), allowed);
+    }
+}
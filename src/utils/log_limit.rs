@@ -0,0 +1,155 @@
+//! 高频日志限流
+//!
+//! 遭受扫描或攻击时，解码失败、握手失败等错误日志 (尤其是附带十六进制转储的) 本身
+//! 就可能占满磁盘和 CPU。这里按调用点的键分窗口计数: 每个窗口内最多输出 `burst` 条，
+//! 其余丢弃并计数，下一次允许输出时先报告被抑制的条数。
+
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 默认每个窗口内每个键最多输出的条数
+pub const DEFAULT_BURST: u32 = 10;
+/// 默认窗口长度
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(10);
+
+static LIMITER: Lazy<Mutex<LogLimiter>> = Lazy::new(|| Mutex::new(LogLimiter::new(DEFAULT_BURST, DEFAULT_WINDOW)));
+
+/// 单个键的计数状态
+struct Bucket {
+    window_start: Instant,
+    emitted: u32,
+    suppressed: u64,
+}
+
+/// 按键分窗口的日志限流器
+pub struct LogLimiter {
+    /// 每个窗口内最多输出的条数，0 表示不限流
+    burst: u32,
+    window: Duration,
+    buckets: HashMap<&'static str, Bucket>,
+}
+
+impl LogLimiter {
+    pub fn new(burst: u32, window: Duration) -> Self {
+        Self {
+            burst,
+            window,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// 判断 `key` 的日志此时能否输出
+    ///
+    /// 可以输出时返回此前被抑制的条数 (并清零)，应当丢弃时返回 `None`。
+    pub fn check(&mut self, key: &'static str, now: Instant) -> Option<u64> {
+        if self.burst == 0 {
+            return Some(0);
+        }
+
+        let bucket = self.buckets.entry(key).or_insert(Bucket {
+            window_start: now,
+            emitted: 0,
+            suppressed: 0,
+        });
+        if now.duration_since(bucket.window_start) >= self.window {
+            bucket.window_start = now;
+            bucket.emitted = 0;
+        }
+        if bucket.emitted < self.burst {
+            bucket.emitted += 1;
+            Some(std::mem::take(&mut bucket.suppressed))
+        } else {
+            bucket.suppressed += 1;
+            None
+        }
+    }
+}
+
+/// 设置全局限流参数 (启动时按配置调用)，已有的计数清空
+pub fn configure(burst: u32, window: Duration) {
+    *LIMITER.lock().unwrap() = LogLimiter::new(burst, window);
+}
+
+/// 查询全局限流器，供 [`log_limited!`](crate::log_limited) 使用
+pub fn allow(key: &'static str) -> Option<u64> {
+    LIMITER.lock().unwrap().check(key, Instant::now())
+}
+
+/// 按键限流输出日志: `log_limited!(error, "vless_decode", "...", args)`
+///
+/// 被丢弃时不会对参数求值，十六进制转储等开销较大的参数也随之省去。
+#[macro_export]
+macro_rules! log_limited {
+    ($level:ident, $key:expr, $($arg:tt)+) => {
+        if let Some(suppressed) = $crate::utils::log_limit::allow($key) {
+            if suppressed > 0 {
+                tracing::$level!("⏸ 已抑制 {} 条重复日志 ({})", suppressed, $key);
+            }
+            tracing::$level!($($arg)+);
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_window_and_suppressed_count() {
+        let mut limiter = LogLimiter::new(2, Duration::from_secs(10));
+        let start = Instant::now();
+
+        assert_eq!(limiter.check("a", start), Some(0));
+        assert_eq!(limiter.check("a", start), Some(0));
+        assert_eq!(limiter.check("a", start), None);
+        assert_eq!(limiter.check("a", start + Duration::from_secs(5)), None);
+        // 不同的键互不影响
+        assert_eq!(limiter.check("b", start), Some(0));
+
+        // 新窗口的第一条报告上一窗口被抑制的条数
+        assert_eq!(limiter.check("a", start + Duration::from_secs(10)), Some(2));
+        assert_eq!(limiter.check("a", start + Duration::from_secs(10)), Some(0));
+
+        let mut unlimited = LogLimiter::new(0, Duration::from_secs(10));
+        assert!((0..100).all(|_| unlimited.check("a", start).is_some()));
+    }
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_repeated_errors_are_limited_in_output() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            for i in 0..100 {
+                crate::log_limited!(error, "log_limit_test_flood", "解码失败 #{} Hex: {}", i, hex::encode([i as u8; 64]));
+            }
+        });
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(output.matches("解码失败").count(), DEFAULT_BURST as usize, "{}", output);
+        assert!(output.contains("解码失败 #9 "));
+        assert!(!output.contains("解码失败 #10 "));
+    }
+}
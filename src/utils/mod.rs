@@ -1,5 +1,8 @@
+pub mod affinity;
 pub mod crypto;
 pub mod error;
+pub mod log_limit;
+pub mod replay;
 
 pub use crypto::{generate_x25519_keypair, X25519KeyPair};
 pub use error::ProxyError;
@@ -0,0 +1,67 @@
+//! 有界重放过滤
+//!
+//! Reality 的 ClientHello 摘要和 VMess 的认证 ID 都只能使用一次。这里记录已出现的键及其
+//! 过期时间 (Unix 秒)，每 30 秒清理一次过期记录；超过容量时按记录顺序淘汰最早的键，
+//! 避免大量伪造但能通过认证的握手把内存撑满。
+
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+/// 已使用的键及其过期时间
+pub struct ReplayFilter<K> {
+    seen: HashMap<K, u64>,
+    order: VecDeque<K>,
+    capacity: usize,
+    next_purge: u64,
+}
+
+impl<K: Eq + Hash + Copy> ReplayFilter<K> {
+    /// 创建最多记录 `capacity` 个键的过滤器
+    pub fn new(capacity: usize) -> Self {
+        Self { seen: HashMap::new(), order: VecDeque::new(), capacity, next_purge: 0 }
+    }
+
+    /// 记录键，已存在且未过期时返回 false
+    pub fn insert(&mut self, key: K, expires: u64, now: u64) -> bool {
+        if now >= self.next_purge {
+            // 过期的键会被调用方的时间戳检查拒绝，不必再记录
+            self.seen.retain(|_, expiry| *expiry >= now);
+            let seen = &self.seen;
+            self.order.retain(|key| seen.contains_key(key));
+            self.next_purge = now + 30;
+        }
+        if matches!(self.seen.get(&key), Some(expiry) if *expiry >= now) {
+            return false;
+        }
+        if self.seen.insert(key, expires).is_none() {
+            self.order.push_back(key);
+        }
+        while self.seen.len() > self.capacity {
+            match self.order.pop_front() {
+                Some(oldest) => self.seen.remove(&oldest),
+                None => break,
+            };
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_filter_is_bounded() {
+        let mut filter = ReplayFilter::new(2);
+        assert!(filter.insert([1u8; 32], 200, 100));
+        assert!(!filter.insert([1; 32], 200, 150));
+        assert!(filter.insert([2; 32], 200, 150));
+        // 超过容量时淘汰最早的记录
+        assert!(filter.insert([3; 32], 200, 150));
+        assert_eq!(filter.seen.len(), 2);
+        assert!(filter.insert([1; 32], 200, 150));
+        // 过期的记录不再拦截
+        assert!(filter.insert([3; 32], 300, 250));
+        assert_eq!(filter.order.len(), filter.seen.len());
+    }
+}
@@ -0,0 +1,90 @@
+//! 集成测试共用的辅助代码
+#![allow(dead_code)]
+
+use anyhow::{bail, Result};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use uuid::Uuid;
+use xray_lite::protocol::vless::{Addons, Address, Command, VlessRequest};
+use std::sync::Arc;
+use xray_lite::network::Metrics;
+use xray_lite::{Config, Server};
+
+/// 取一个当前空闲的本地端口
+pub async fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// 启动 TCP 回显服务
+pub async fn spawn_tcp_echo() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut r, mut w) = stream.split();
+                let _ = tokio::io::copy(&mut r, &mut w).await;
+            });
+        }
+    });
+    addr
+}
+
+/// 按 JSON 配置启动完整服务器，等待所有入站端口开始监听，返回服务器的入站指标
+pub async fn start_server(config: serde_json::Value) -> Result<Arc<Metrics>> {
+    let config: Config = serde_json::from_value(config)?;
+    let ports: Vec<u16> = config.inbounds.iter().map(|i| i.port).collect();
+    let server = Server::new(config)?;
+    let metrics = server.metrics();
+    tokio::spawn(server.run());
+
+    for port in ports {
+        let mut ready = false;
+        for _ in 0..50 {
+            if TcpStream::connect(("127.0.0.1", port)).await.is_ok() {
+                ready = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        if !ready {
+            bail!("入站端口 {} 未开始监听", port);
+        }
+    }
+    Ok(metrics)
+}
+
+/// 发送 VLESS 请求头 (附带首包数据) 并读取响应头
+pub async fn open_vless<S>(stream: &mut S, uuid: Uuid, command: Command, target: Address, payload: &[u8]) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let request = VlessRequest {
+        version: 0,
+        uuid,
+        command,
+        address: target,
+        addon_length: 0,
+        addons: Addons::default(),
+    };
+    let mut buf = request.encode()?;
+    buf.extend_from_slice(payload);
+    stream.write_all(&buf).await?;
+    stream.flush().await?;
+
+    let mut header = [0u8; 2];
+    stream.read_exact(&mut header).await?;
+    if header[0] != 0 {
+        bail!("VLESS 响应版本错误: {}", header[0]);
+    }
+    let mut addons = vec![0u8; header[1] as usize];
+    stream.read_exact(&mut addons).await?;
+    Ok(())
+}
@@ -0,0 +1,73 @@
+//! dokodemo 入站集成测试: TCP 与 UDP 端口转发
+
+use anyhow::Result;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+
+mod common;
+
+fn dokodemo_inbound(port: u16, target: SocketAddr, network: &str) -> serde_json::Value {
+    serde_json::json!({
+        "protocol": "dokodemo-door",
+        "listen": "127.0.0.1",
+        "port": port,
+        "settings": {
+            "targetAddress": target.ip().to_string(),
+            "targetPort": target.port(),
+            "network": network
+        },
+        "streamSettings": { "network": "tcp", "security": "none", "sockopt": { "tcpFastOpen": false } }
+    })
+}
+
+#[tokio::test]
+async fn test_dokodemo_tcp_forward() -> Result<()> {
+    let echo = common::spawn_tcp_echo().await;
+    let port = common::free_port().await;
+    common::start_server(serde_json::json!({
+        "inbounds": [dokodemo_inbound(port, echo, "tcp")],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+    }))
+    .await?;
+
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    stream.write_all(b"forwarded").await?;
+    let mut echoed = [0u8; 9];
+    tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut echoed)).await??;
+    assert_eq!(&echoed, b"forwarded");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dokodemo_udp_forward() -> Result<()> {
+    // UDP 回显服务
+    let echo = UdpSocket::bind("127.0.0.1:0").await?;
+    let target = echo.local_addr()?;
+    tokio::spawn(async move {
+        let mut buf = [0u8; 2048];
+        while let Ok((n, from)) = echo.recv_from(&mut buf).await {
+            let _ = echo.send_to(&buf[..n], from).await;
+        }
+    });
+
+    let port = common::free_port().await;
+    // 同时监听 TCP 以便 start_server 判断入站已就绪
+    common::start_server(serde_json::json!({
+        "inbounds": [dokodemo_inbound(port, target, "tcp,udp")],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+    }))
+    .await?;
+
+    // 两个客户端各自收到自己的回包
+    for payload in [&b"from one"[..], &b"from two"[..]] {
+        let client = UdpSocket::bind("127.0.0.1:0").await?;
+        client.connect(("127.0.0.1", port)).await?;
+        client.send(payload).await?;
+        let mut buf = [0u8; 2048];
+        let n = tokio::time::timeout(Duration::from_secs(5), client.recv(&mut buf)).await??;
+        assert_eq!(&buf[..n], payload);
+    }
+    Ok(())
+}
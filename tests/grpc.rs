@@ -0,0 +1,162 @@
+//! gRPC 传输集成测试: 用 tonic 客户端调用 `/{serviceName}/Tun`，验证与标准 gRPC 实现互通
+
+use anyhow::Result;
+use bytes::{Buf, BufMut, Bytes};
+use futures::channel::mpsc;
+use futures::SinkExt;
+use hyper::http::uri::PathAndQuery;
+use std::time::Duration;
+use tonic::codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder};
+use tonic::transport::Endpoint;
+use tonic::{Code, Status, Streaming};
+use uuid::Uuid;
+use xray_lite::protocol::vless::{Address, Addons, Command, VlessRequest};
+
+mod common;
+
+/// `message Hunk { bytes data = 1; }` 的编解码，不依赖 prost 生成代码
+#[derive(Default)]
+struct HunkCodec;
+
+struct HunkEncoder;
+struct HunkDecoder;
+
+impl Codec for HunkCodec {
+    type Encode = Bytes;
+    type Decode = Bytes;
+    type Encoder = HunkEncoder;
+    type Decoder = HunkDecoder;
+
+    fn encoder(&mut self) -> HunkEncoder {
+        HunkEncoder
+    }
+
+    fn decoder(&mut self) -> HunkDecoder {
+        HunkDecoder
+    }
+}
+
+impl Encoder for HunkEncoder {
+    type Item = Bytes;
+    type Error = Status;
+
+    fn encode(&mut self, item: Bytes, dst: &mut EncodeBuf<'_>) -> Result<(), Status> {
+        dst.put_u8(0x0a);
+        let mut len = item.len();
+        while len >= 0x80 {
+            dst.put_u8(len as u8 | 0x80);
+            len >>= 7;
+        }
+        dst.put_u8(len as u8);
+        dst.put_slice(&item);
+        Ok(())
+    }
+}
+
+impl Decoder for HunkDecoder {
+    type Item = Bytes;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<Bytes>, Status> {
+        if src.remaining() == 0 {
+            return Ok(Some(Bytes::new()));
+        }
+        if src.get_u8() != 0x0a {
+            return Err(Status::internal("unexpected field"));
+        }
+        let mut len = 0usize;
+        for shift in (0..).step_by(7) {
+            let byte = src.get_u8();
+            len |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        Ok(Some(src.copy_to_bytes(len)))
+    }
+}
+
+/// 启动使用 gRPC 传输的 VLESS 入站，返回端口
+async fn start_grpc(user: Uuid) -> Result<u16> {
+    let port = common::free_port().await;
+    common::start_server(serde_json::json!({
+        "inbounds": [{
+            "protocol": "vless",
+            "listen": "127.0.0.1",
+            "port": port,
+            "settings": { "clients": [{ "id": user.to_string() }] },
+            "streamSettings": {
+                "network": "grpc",
+                "security": "none",
+                "grpcSettings": { "serviceName": "GunService" },
+                "sockopt": { "tcpFastOpen": false }
+            }
+        }],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+    }))
+    .await?;
+    Ok(port)
+}
+
+/// 调用 `path`，返回上行消息的发送端与下行消息流
+async fn call_tun(port: u16, path: &'static str) -> Result<(mpsc::Sender<Bytes>, Result<Streaming<Bytes>, Status>)> {
+    let channel = Endpoint::from_shared(format!("http://127.0.0.1:{}", port))?.connect().await?;
+    let mut client = tonic::client::Grpc::new(channel);
+    client.ready().await?;
+    let (tx, rx) = mpsc::channel(16);
+    let response = client
+        .streaming(tonic::Request::new(rx), PathAndQuery::from_static(path), HunkCodec)
+        .await
+        .map(|response| response.into_inner());
+    Ok((tx, response))
+}
+
+#[tokio::test]
+async fn test_tonic_client_round_trip() -> Result<()> {
+    let echo = common::spawn_tcp_echo().await;
+    let user = Uuid::new_v4();
+    let port = start_grpc(user).await?;
+
+    let (mut tx, response) = call_tun(port, "/GunService/Tun").await?;
+    let mut downlink = response?;
+
+    // 第一条消息携带 VLESS 请求头与首包数据
+    let request = VlessRequest {
+        version: 0,
+        uuid: user,
+        command: Command::Tcp,
+        address: Address::from(echo),
+        addon_length: 0,
+        addons: Addons::default(),
+    };
+    let mut first = request.encode()?;
+    first.extend_from_slice(b"hello");
+    tx.send(first.freeze()).await?;
+    tx.send(Bytes::from_static(b" grpc")).await?;
+
+    // VLESS 响应头 (版本 + 附加长度) 之后是回显的数据
+    let mut received = Vec::new();
+    while received.len() < 2 + b"hello grpc".len() {
+        let message = tokio::time::timeout(Duration::from_secs(5), downlink.message()).await??;
+        received.extend_from_slice(&message.expect("下行提前结束"));
+    }
+    assert_eq!(&received[..2], &[0, 0]);
+    assert_eq!(&received[2..], b"hello grpc");
+
+    // 客户端结束发送后调用以 OK 状态结束
+    drop(tx);
+    while let Some(message) = tokio::time::timeout(Duration::from_secs(5), downlink.message()).await?? {
+        assert!(message.is_empty(), "多余的下行数据: {:?}", message);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tonic_client_unknown_service() -> Result<()> {
+    let port = start_grpc(Uuid::new_v4()).await?;
+
+    let (_tx, response) = call_tun(port, "/OtherService/Tun").await?;
+    let status = response.expect_err("未知服务应被拒绝");
+    assert_eq!(status.code(), Code::Unimplemented);
+    Ok(())
+}
@@ -0,0 +1,166 @@
+//! 过载拒绝集成测试
+
+use anyhow::{bail, Result};
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use uuid::Uuid;
+use xray_lite::network::InboundMetrics;
+use xray_lite::protocol::vless::{Address, Command};
+
+mod common;
+
+/// 发送明文 HTTP 请求，读取服务器写回的全部内容
+async fn probe(port: u16) -> Result<String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: example.com\r\nUser-Agent: curl/8.0\r\n\r\n")
+        .await?;
+    let mut response = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response)).await??;
+    Ok(String::from_utf8(response)?)
+}
+
+/// 等待入站进行中的握手数变为 `expected`
+async fn wait_handshakes(metrics: &Arc<InboundMetrics>, expected: usize) -> Result<()> {
+    for _ in 0..100 {
+        if metrics.handshakes_in_flight() == expected {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    bail!("入站 {} 进行中的握手数为 {}，期望 {}", metrics.name(), metrics.handshakes_in_flight(), expected)
+}
+
+/// 建立一个占住连接槽的 VLESS 会话
+///
+/// 启动时的就绪探测连接可能还没释放槽位，被拒绝时重试。
+async fn occupy_slot(port: u16, user: Uuid, target: Address) -> Result<TcpStream> {
+    for _ in 0..50 {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+        let opened = tokio::time::timeout(
+            Duration::from_secs(2),
+            common::open_vless(&mut stream, user, Command::Tcp, target.clone(), b"hold"),
+        )
+        .await;
+        if matches!(opened, Ok(Ok(()))) {
+            let mut echoed = [0u8; 4];
+            stream.read_exact(&mut echoed).await?;
+            return Ok(stream);
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    bail!("无法占用连接槽")
+}
+
+#[tokio::test]
+async fn test_overload_rejects_with_retry_after() -> Result<()> {
+    let echo = common::spawn_tcp_echo().await;
+    let user = Uuid::new_v4();
+    let port = common::free_port().await;
+    common::start_server(serde_json::json!({
+        "inbounds": [{
+            "protocol": "vless",
+            "listen": "127.0.0.1",
+            "port": port,
+            "settings": { "clients": [{ "id": user.to_string() }] },
+            "streamSettings": { "network": "tcp", "security": "none", "sockopt": { "tcpFastOpen": false } }
+        }],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }],
+        "overload": { "maxConnections": 1, "retryAfter": 7 }
+    }))
+    .await?;
+
+    let target = Address::Ipv4(Ipv4Addr::LOCALHOST, echo.port());
+    let holder = occupy_slot(port, user, target.clone()).await?;
+
+    // 过载期间 HTTP 请求得到 503 + Retry-After
+    let rejected = probe(port).await?;
+    assert!(rejected.starts_with("HTTP/1.1 503 Service Unavailable\r\n"), "{}", rejected);
+    assert!(rejected.contains("\r\nRetry-After: 7\r\n"), "{}", rejected);
+
+    // 原始 VLESS 连接被直接关闭
+    let mut raw = TcpStream::connect(("127.0.0.1", port)).await?;
+    let opened = tokio::time::timeout(
+        Duration::from_secs(3),
+        common::open_vless(&mut raw, user, Command::Tcp, target, b"x"),
+    )
+    .await?;
+    assert!(opened.is_err());
+
+    // 槽位释放后恢复正常
+    drop(holder);
+    for _ in 0..50 {
+        if probe(port).await? == "HTTP/1.1 204 No Content\r\n\r\n" {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    bail!("槽位释放后仍被拒绝")
+}
+
+#[tokio::test]
+async fn test_per_inbound_handshake_cap() -> Result<()> {
+    let echo = common::spawn_tcp_echo().await;
+    let user = Uuid::new_v4();
+    let limited_port = common::free_port().await;
+    let open_port = common::free_port().await;
+    let inbound = |tag: &str, port: u16| {
+        serde_json::json!({
+            "tag": tag,
+            "protocol": "vless",
+            "listen": "127.0.0.1",
+            "port": port,
+            "settings": { "clients": [{ "id": user.to_string() }] },
+            "streamSettings": { "network": "tcp", "security": "none", "sockopt": { "tcpFastOpen": false } }
+        })
+    };
+    let mut limited_inbound = inbound("limited", limited_port);
+    limited_inbound["maxHandshakes"] = 1.into();
+    let metrics = common::start_server(serde_json::json!({
+        "inbounds": [limited_inbound, inbound("open", open_port)],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+    }))
+    .await?;
+    let limited = metrics.inbound("limited").unwrap();
+    let open = metrics.inbound("open").unwrap();
+    assert_eq!(limited.max_handshakes(), Some(1));
+
+    // 一个不发送任何数据的连接占住唯一的握手名额 (先等就绪探测连接释放)
+    wait_handshakes(&limited, 0).await?;
+    let stalled = TcpStream::connect(("127.0.0.1", limited_port)).await?;
+    wait_handshakes(&limited, 1).await?;
+
+    // 新连接被拒绝并计入指标
+    let target = Address::Ipv4(Ipv4Addr::LOCALHOST, echo.port());
+    let mut rejected = TcpStream::connect(("127.0.0.1", limited_port)).await?;
+    let opened = tokio::time::timeout(
+        Duration::from_secs(3),
+        common::open_vless(&mut rejected, user, Command::Tcp, target.clone(), b"x"),
+    )
+    .await?;
+    assert!(opened.is_err());
+    assert_eq!(limited.rejected_handshakes(), 1);
+    assert_eq!(limited.handshakes_in_flight(), 1);
+
+    // 其他入站不受影响；认证完成后握手结束，连接仍计为活跃
+    let mut session = TcpStream::connect(("127.0.0.1", open_port)).await?;
+    common::open_vless(&mut session, user, Command::Tcp, target.clone(), b"open").await?;
+    let mut echoed = [0u8; 4];
+    session.read_exact(&mut echoed).await?;
+    wait_handshakes(&open, 0).await?;
+    assert_eq!(open.active_connections(), 1);
+    assert_eq!(open.rejected_handshakes(), 0);
+
+    // 占位连接关闭后恢复接受握手
+    drop(stalled);
+    wait_handshakes(&limited, 0).await?;
+    let mut session = TcpStream::connect(("127.0.0.1", limited_port)).await?;
+    common::open_vless(&mut session, user, Command::Tcp, target, b"back").await?;
+    session.read_exact(&mut echoed).await?;
+    assert_eq!(&echoed, b"back");
+    assert_eq!(limited.active_connections(), 1);
+    Ok(())
+}
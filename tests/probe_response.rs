@@ -0,0 +1,83 @@
+//! 按入站配置的探测响应集成测试
+
+use anyhow::Result;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+mod common;
+
+/// 发送明文 HTTP 请求，读取服务器写回的全部内容
+async fn probe(port: u16) -> Result<String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: example.com\r\nUser-Agent: curl/8.0\r\n\r\n")
+        .await?;
+    let mut response = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response)).await??;
+    Ok(String::from_utf8(response)?)
+}
+
+fn inbound(port: u16, probe_response: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "protocol": "vless",
+        "listen": "127.0.0.1",
+        "port": port,
+        "settings": {
+            "clients": [{ "id": uuid::Uuid::new_v4().to_string() }],
+            "probeResponse": probe_response
+        },
+        "streamSettings": {
+            "network": "tcp",
+            "security": "none",
+            "sockopt": { "tcpFastOpen": false }
+        }
+    })
+}
+
+#[tokio::test]
+async fn test_probe_response_per_inbound() -> Result<()> {
+    // 分别扮演 80 (跳转到 https) 与 443 (普通网站) 端口
+    let http_port = common::free_port().await;
+    let https_port = common::free_port().await;
+    let default_port = common::free_port().await;
+
+    common::start_server(serde_json::json!({
+        "inbounds": [
+            inbound(http_port, serde_json::json!({
+                "status": 301,
+                "headers": { "Location": "https://example.com/" }
+            })),
+            inbound(https_port, serde_json::json!({
+                "status": 200,
+                "headers": { "Server": "nginx", "Content-Type": "text/html" },
+                "body": "<h1>It works!</h1>"
+            })),
+            {
+                "protocol": "vless",
+                "listen": "127.0.0.1",
+                "port": default_port,
+                "settings": { "clients": [{ "id": uuid::Uuid::new_v4().to_string() }] },
+                "streamSettings": { "network": "tcp", "security": "none", "sockopt": { "tcpFastOpen": false } }
+            }
+        ],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+    }))
+    .await?;
+
+    let redirect = probe(http_port).await?;
+    assert!(redirect.starts_with("HTTP/1.1 301 Moved Permanently\r\n"), "{}", redirect);
+    assert!(redirect.contains("\r\nLocation: https://example.com/\r\n"), "{}", redirect);
+    assert!(redirect.ends_with("Content-Length: 0\r\n\r\n"), "{}", redirect);
+
+    let page = probe(https_port).await?;
+    assert!(page.starts_with("HTTP/1.1 200 OK\r\n"), "{}", page);
+    assert!(page.contains("\r\nServer: nginx\r\n"), "{}", page);
+    assert!(page.contains("\r\nContent-Length: 18\r\n"), "{}", page);
+    assert!(page.ends_with("\r\n\r\n<h1>It works!</h1>"), "{}", page);
+
+    // 未配置时保持原来的 204
+    assert_eq!(probe(default_port).await?, "HTTP/1.1 204 No Content\r\n\r\n");
+
+    Ok(())
+}
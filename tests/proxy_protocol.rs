@@ -0,0 +1,183 @@
+//! Proxy Protocol 入站集成测试: 只解析可信对端发来的头部，头部之后的数据照常交给 VLESS
+
+use anyhow::Result;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+use xray_lite::protocol::vless::{Address, Command};
+use xray_lite::protocol::ProxyHeader;
+
+mod common;
+
+/// 只放行来源为 10.20.0.0/16 的流量，其余流量被 blackhole 丢弃
+fn config(port: u16, uuid: Uuid, trusted: &[&str], fallback: Option<u16>) -> serde_json::Value {
+    let mut settings = serde_json::json!({ "clients": [{ "id": uuid.to_string() }] });
+    if let Some(fallback) = fallback {
+        settings["fallbacks"] = serde_json::json!([{ "dest": fallback }]);
+    }
+    serde_json::json!({
+        "inbounds": [{
+            "protocol": "vless",
+            "listen": "127.0.0.1",
+            "port": port,
+            "settings": settings,
+            "streamSettings": {
+                "network": "tcp",
+                "security": "none",
+                "sockopt": {
+                    "tcpFastOpen": false,
+                    "acceptProxyProtocol": true,
+                    "proxyProtocolTrustedAddresses": trusted
+                }
+            }
+        }],
+        "outbounds": [
+            { "protocol": "blackhole", "tag": "block" },
+            { "protocol": "freedom", "tag": "direct" }
+        ],
+        "routing": {
+            "rules": [{ "type": "field", "source": ["10.20.0.0/16"], "outboundTag": "direct" }]
+        }
+    })
+}
+
+#[tokio::test]
+async fn test_trusted_proxy_header_with_trailing_data() -> Result<()> {
+    let echo = common::spawn_tcp_echo().await;
+    let uuid = Uuid::new_v4();
+    let port = common::free_port().await;
+    common::start_server(config(port, uuid, &["127.0.0.0/8"], None)).await?;
+
+    // PROXY 头部与 VLESS 请求在同一个包里发出，头部之后的数据不能丢
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    let header = ProxyHeader::new("10.20.0.5:40000".parse()?, format!("127.0.0.1:{}", port).parse()?);
+    let mut data = header.encode(2)?;
+    data.extend_from_slice(&xray_lite::protocol::vless::VlessRequest {
+        version: 0,
+        uuid,
+        command: Command::Tcp,
+        address: Address::Ipv4(std::net::Ipv4Addr::LOCALHOST, echo.port()),
+        addon_length: 0,
+        addons: Default::default(),
+    }
+    .encode()?);
+    data.extend_from_slice(b"hello");
+    stream.write_all(&data).await?;
+
+    // 来源地址取自头部，才能命中放行规则
+    let mut response = [0u8; 7];
+    tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut response)).await??;
+    assert_eq!(&response, b"\x00\x00hello");
+
+    stream.write_all(b" again").await?;
+    let mut echoed = [0u8; 6];
+    tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut echoed)).await??;
+    assert_eq!(&echoed, b" again");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_untrusted_peer_cannot_spoof_source() -> Result<()> {
+    // 回落目标记录收到的原始数据
+    let site = TcpListener::bind("127.0.0.1:0").await?;
+    let site_port = site.local_addr()?.port();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = site.accept().await {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let mut received = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !received.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => received.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let _ = tx.send(received);
+            });
+        }
+    });
+
+    let echo = common::spawn_tcp_echo().await;
+    let uuid = Uuid::new_v4();
+    let port = common::free_port().await;
+    common::start_server(config(port, uuid, &["10.0.0.0/8"], Some(site_port))).await?;
+
+    // 127.0.0.1 不在可信名单内，伪造的 PROXY 行按普通流量处理，认证失败后原样回落
+    let fake = b"PROXY TCP4 10.20.0.5 10.0.0.1 40000 443\r\nGET / HTTP/1.1\r\n\r\n";
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    stream.write_all(fake).await?;
+    let received = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await?.unwrap();
+    assert_eq!(received, fake);
+
+    // 合法的 VLESS 请求使用真实来源地址，不会命中放行规则
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    common::open_vless(&mut stream, uuid, Command::Tcp, Address::Ipv4(std::net::Ipv4Addr::LOCALHOST, echo.port()), b"hello").await?;
+    let mut buf = [0u8; 5];
+    let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf)).await?;
+    assert!(matches!(read, Ok(0) | Err(_)), "{:?}", read);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_partial_header_releases_handshake_slot() -> Result<()> {
+    use base64::Engine as _;
+
+    let uuid = Uuid::new_v4();
+    let port = common::free_port().await;
+    let mut config = config(port, uuid, &["127.0.0.0/8"], None);
+    let inbound = &mut config["inbounds"][0];
+    inbound["tag"] = "pp".into();
+    inbound["maxHandshakes"] = 1.into();
+    // 等待 PROXY 头部沿用 Reality 的握手超时
+    inbound["streamSettings"]["security"] = "reality".into();
+    inbound["streamSettings"]["realitySettings"] = serde_json::json!({
+        "dest": "127.0.0.1:9",
+        "serverNames": ["www.example.com"],
+        "privateKey": base64::engine::general_purpose::STANDARD.encode([7u8; 32]),
+        "shortIds": ["0123456789abcdef"],
+        "certRefreshInterval": 0,
+        "handshakeTimeout": 1
+    });
+    let metrics = common::start_server(config).await?;
+    let inbound = metrics.inbound("pp").unwrap();
+
+    // 只发半个头部后停住，占住唯一的握手名额
+    // (启动时的就绪探测连接可能还没归还名额，被拒绝时重试)
+    let mut stream = None;
+    for _ in 0..50 {
+        let rejected = inbound.rejected_handshakes();
+        let mut attempt = TcpStream::connect(("127.0.0.1", port)).await?;
+        attempt.write_all(b"PROXY TCP4 10.20").await?;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        if inbound.rejected_handshakes() == rejected {
+            stream = Some(attempt);
+            break;
+        }
+    }
+    let mut stream = stream.expect("握手名额一直被占用");
+    wait_handshakes(&inbound, 1).await?;
+    let started = std::time::Instant::now();
+
+    // 超时后服务端关闭连接并归还名额
+    let mut buf = [0u8; 16];
+    let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf)).await?;
+    assert!(matches!(read, Ok(0) | Err(_)), "{:?}", read);
+    assert!(started.elapsed() >= Duration::from_millis(500), "{:?}", started.elapsed());
+    wait_handshakes(&inbound, 0).await?;
+    Ok(())
+}
+
+/// 等待入站进行中的握手数变为 `expected`
+async fn wait_handshakes(metrics: &xray_lite::network::InboundMetrics, expected: usize) -> Result<()> {
+    for _ in 0..100 {
+        if metrics.handshakes_in_flight() == expected {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    anyhow::bail!("进行中的握手数为 {}，期望 {}", metrics.handshakes_in_flight(), expected)
+}
@@ -1,8 +1,14 @@
 use anyhow::Result;
 use tokio::net::{TcpStream, TcpListener};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use vless_reality_xhttp::transport::reality::server_rustls::RealityServerRustls;
+use xray_lite::transport::reality::server_rustls::RealityServerRustls;
 use std::time::Duration;
+use base64::Engine as _;
+use x25519_dalek::{PublicKey, StaticSecret};
+use xray_lite::protocol::vless::{Address, Command};
+use xray_lite::transport::reality::{RealityClientConfig, RealityClientStream};
+
+mod common;
 
 #[tokio::test]
 async fn test_reality_fallback() -> Result<()> {
@@ -23,7 +29,8 @@ async fn test_reality_fallback() -> Result<()> {
     let server = RealityServerRustls::new(
         private_key, 
         Some(dest_addr.to_string()),
-        vec!["0123456789abcdef".to_string()]
+        vec!["0123456789abcdef".to_string()],
+        vec![]
     )?;
     
     // Pick a random port
@@ -64,3 +71,90 @@ async fn test_reality_fallback() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_reality_vless_session() -> Result<()> {
+    run_vless_session("rustls").await
+}
+
+#[tokio::test]
+async fn test_reality_vless_session_native_backend() -> Result<()> {
+    run_vless_session("native").await
+}
+
+/// 完整服务器 + Reality 客户端: 使用指定的 TLS 后端完成握手并转发 VLESS 流量
+async fn run_vless_session(backend: &str) -> Result<()> {
+    // 1. 目标回显服务 (VLESS 请求的目的地)
+    let echo_addr = common::spawn_tcp_echo().await;
+
+    // 2. 回落目标 (认证成功时不应被访问)
+    let dest_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let dest_addr = dest_listener.local_addr()?;
+
+    // 3. 按正常配置启动完整服务器
+    let secret = StaticSecret::from([0x42; 32]);
+    let public = PublicKey::from(&secret);
+    let uuid = uuid::Uuid::parse_str("b831381d-6324-4d53-ad4f-8cda48b30811")?;
+
+    let port = common::free_port().await;
+    common::start_server(serde_json::json!({
+        "inbounds": [{
+            "protocol": "vless",
+            "listen": "127.0.0.1",
+            "port": port,
+            "settings": {
+                "clients": [{ "id": uuid.to_string(), "email": "e2e@test" }]
+            },
+            "streamSettings": {
+                "network": "tcp",
+                "security": "reality",
+                "realitySettings": {
+                    "dest": dest_addr.to_string(),
+                    "serverNames": ["www.example.com"],
+                    "privateKey": base64::engine::general_purpose::STANDARD.encode(secret.to_bytes()),
+                    "shortIds": ["0123456789abcdef"],
+                    // 不获取 dest 的证书，dest 只用于检查是否发生回落
+                    "certRefreshInterval": 0,
+                    "backend": backend
+                },
+                "sockopt": { "tcpFastOpen": false }
+            }
+        }],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+    }))
+    .await?;
+
+    // 4. Reality 握手 + VLESS 请求，首包随请求头一起发送
+    let client_config = RealityClientConfig {
+        server_name: "www.example.com".to_string(),
+        public_key: public.to_bytes(),
+        short_id: hex::decode("0123456789abcdef")?,
+    };
+    let tcp = TcpStream::connect(("127.0.0.1", port)).await?;
+    let mut client = tokio::time::timeout(
+        Duration::from_secs(5),
+        RealityClientStream::connect(tcp, &client_config),
+    )
+    .await??;
+
+    let target = Address::Ipv4(std::net::Ipv4Addr::LOCALHOST, echo_addr.port());
+    common::open_vless(&mut client, uuid, Command::Tcp, target, b"first payload").await?;
+    let mut first = [0u8; 13];
+    client.read_exact(&mut first).await?;
+    assert_eq!(&first, b"first payload");
+
+    // 5. 后续数据继续经由同一条隧道转发
+    let large: Vec<u8> = (0..40_000u32).map(|i| i as u8).collect();
+    client.write_all(&large).await?;
+    client.flush().await?;
+    let mut echoed = vec![0u8; large.len()];
+    tokio::time::timeout(Duration::from_secs(5), client.read_exact(&mut echoed)).await??;
+    assert_eq!(echoed, large);
+
+    // 回落目标从未被连接
+    assert!(tokio::time::timeout(Duration::from_millis(50), dest_listener.accept())
+        .await
+        .is_err());
+
+    Ok(())
+}
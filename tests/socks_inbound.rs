@@ -0,0 +1,94 @@
+//! SOCKS5 入站集成测试
+//!
+//! TCP 使用 tokio-socks 客户端经由入站访问回显服务 (含用户名密码认证)，
+//! UDP 使用本项目的 SOCKS5 出站作为客户端完成 UDP ASSOCIATE。
+
+use anyhow::Result;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UdpSocket;
+use tokio_socks::tcp::Socks5Stream;
+use xray_lite::outbound::{SocksOutbound, SocksSettings};
+use xray_lite::protocol::vless::Address;
+
+mod common;
+
+const USER: &str = "alice";
+const PASS: &str = "secret";
+
+async fn start_socks_inbound(settings: serde_json::Value) -> Result<u16> {
+    let port = common::free_port().await;
+    common::start_server(serde_json::json!({
+        "inbounds": [{
+            "protocol": "socks",
+            "listen": "127.0.0.1",
+            "port": port,
+            "settings": settings,
+            "streamSettings": { "network": "tcp", "security": "none", "sockopt": { "tcpFastOpen": false } }
+        }],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+    }))
+    .await?;
+    Ok(port)
+}
+
+/// 启动 UDP 回显服务
+async fn spawn_udp_echo() -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = [0u8; 2048];
+        while let Ok((n, from)) = socket.recv_from(&mut buf).await {
+            let _ = socket.send_to(&buf[..n], from).await;
+        }
+    });
+    addr
+}
+
+#[tokio::test]
+async fn test_socks_connect_with_password() -> Result<()> {
+    let echo = common::spawn_tcp_echo().await;
+    let port = start_socks_inbound(serde_json::json!({
+        "accounts": [{ "user": USER, "pass": PASS }]
+    }))
+    .await?;
+
+    let mut stream = Socks5Stream::connect_with_password(("127.0.0.1", port), echo, USER, PASS).await?;
+    stream.write_all(b"hello socks").await?;
+    let mut echoed = [0u8; 11];
+    tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut echoed)).await??;
+    assert_eq!(&echoed, b"hello socks");
+
+    // 密码错误与未认证的连接都被拒绝
+    assert!(Socks5Stream::connect_with_password(("127.0.0.1", port), echo, USER, "wrong").await.is_err());
+    assert!(Socks5Stream::connect(("127.0.0.1", port), echo).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_socks_udp_associate() -> Result<()> {
+    let first = spawn_udp_echo().await;
+    let second = spawn_udp_echo().await;
+    let port = start_socks_inbound(serde_json::json!({ "udp": true, "ip": "127.0.0.1" })).await?;
+
+    let client = SocksOutbound::new(SocksSettings {
+        address: "127.0.0.1".to_string(),
+        port,
+        user: None,
+        pass: None,
+        connect_timeout: 8,
+    });
+    let session = client.associate().await?;
+
+    // 同一个关联中发往不同目标的数据报各自得到回包，来源地址为对应目标
+    for (target, payload) in [(first, &b"ping one"[..]), (second, &b"ping two"[..])] {
+        let target = Address::Ipv4(Ipv4Addr::LOCALHOST, target.port());
+        session.send_to(payload, &target).await?;
+        let mut buf = [0u8; 2048];
+        let (n, source) = tokio::time::timeout(Duration::from_secs(5), session.recv_from(&mut buf)).await??;
+        assert_eq!(&buf[..n], payload);
+        assert_eq!(source, target);
+    }
+    Ok(())
+}
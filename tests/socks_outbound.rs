@@ -0,0 +1,236 @@
+//! SOCKS5 出站集成测试
+//!
+//! 在进程内启动一个最小的 SOCKS5 服务器 (支持用户名密码认证、CONNECT 与 UDP ASSOCIATE)，
+//! 验证出站能经由它访问 TCP / UDP 回显服务。
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+use bytes::BytesMut;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use xray_lite::config::{Outbound, SockOpt};
+use xray_lite::outbound::{Dialer, OutboundHandler};
+use xray_lite::protocol::vless::Address;
+
+const USER: &str = "alice";
+const PASS: &str = "secret";
+
+/// 记录 SOCKS5 服务器收到的请求目标
+type Seen = Arc<Mutex<Vec<(u8, Address)>>>;
+
+async fn read_socks_addr(stream: &mut TcpStream) -> Address {
+    let atyp = stream.read_u8().await.unwrap();
+    let mut buf = BytesMut::new();
+    buf.extend_from_slice(&[atyp]);
+    let len = match atyp {
+        0x01 => 6,
+        0x04 => 18,
+        0x03 => {
+            let n = stream.read_u8().await.unwrap();
+            buf.extend_from_slice(&[n]);
+            n as usize + 2
+        }
+        _ => panic!("bad atyp"),
+    };
+    let mut rest = vec![0u8; len];
+    stream.read_exact(&mut rest).await.unwrap();
+    buf.extend_from_slice(&rest);
+    Address::decode_socks(&mut buf).unwrap()
+}
+
+async fn resolve(addr: &Address) -> SocketAddr {
+    tokio::net::lookup_host(addr.to_string()).await.unwrap().next().unwrap()
+}
+
+/// 最小 SOCKS5 服务器，要求用户名密码认证
+async fn spawn_socks_server(seen: Seen) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        loop {
+            let (mut client, _) = listener.accept().await.unwrap();
+            let seen = seen.clone();
+            tokio::spawn(async move {
+                // 方法协商: 只接受用户名密码
+                let mut head = [0u8; 2];
+                client.read_exact(&mut head).await.unwrap();
+                let mut methods = vec![0u8; head[1] as usize];
+                client.read_exact(&mut methods).await.unwrap();
+                if !methods.contains(&0x02) {
+                    client.write_all(&[0x05, 0xFF]).await.unwrap();
+                    return;
+                }
+                client.write_all(&[0x05, 0x02]).await.unwrap();
+
+                // RFC 1929
+                let _ver = client.read_u8().await.unwrap();
+                let mut user = vec![0u8; client.read_u8().await.unwrap() as usize];
+                client.read_exact(&mut user).await.unwrap();
+                let mut pass = vec![0u8; client.read_u8().await.unwrap() as usize];
+                client.read_exact(&mut pass).await.unwrap();
+                if user != USER.as_bytes() || pass != PASS.as_bytes() {
+                    client.write_all(&[0x01, 0x01]).await.unwrap();
+                    return;
+                }
+                client.write_all(&[0x01, 0x00]).await.unwrap();
+
+                let mut req = [0u8; 3];
+                client.read_exact(&mut req).await.unwrap();
+                let target = read_socks_addr(&mut client).await;
+                seen.lock().unwrap().push((req[1], target.clone()));
+
+                match req[1] {
+                    0x01 => {
+                        let mut upstream = TcpStream::connect(resolve(&target).await).await.unwrap();
+                        client
+                            .write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+                            .await
+                            .unwrap();
+                        let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+                    }
+                    0x03 => {
+                        let relay = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+                        let port = relay.local_addr().unwrap().port();
+                        // 回复 0.0.0.0，客户端应改用服务器自身地址
+                        let mut reply = vec![0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0];
+                        reply.extend_from_slice(&port.to_be_bytes());
+                        client.write_all(&reply).await.unwrap();
+
+                        let mut buf = vec![0u8; 2048];
+                        let (n, client_udp) = relay.recv_from(&mut buf).await.unwrap();
+                        assert_eq!(&buf[..3], &[0, 0, 0]);
+                        let mut packet = BytesMut::from(&buf[3..n]);
+                        let dest = Address::decode_socks(&mut packet).unwrap();
+                        seen.lock().unwrap().push((0xFF, dest.clone()));
+
+                        let outbound = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+                        outbound.send_to(&packet, resolve(&dest).await).await.unwrap();
+                        let (m, from) = outbound.recv_from(&mut buf).await.unwrap();
+
+                        let mut response = BytesMut::new();
+                        response.extend_from_slice(&[0, 0, 0]);
+                        Address::from(from).encode_socks(&mut response);
+                        response.extend_from_slice(&buf[..m]);
+                        relay.send_to(&response, client_udp).await.unwrap();
+
+                        // 保持控制连接直到客户端关闭
+                        let mut sink = [0u8; 1];
+                        let _ = client.read(&mut sink).await;
+                    }
+                    _ => {}
+                }
+            });
+        }
+    });
+
+    addr
+}
+
+async fn spawn_tcp_echo() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let (mut r, mut w) = stream.split();
+                let _ = tokio::io::copy(&mut r, &mut w).await;
+            });
+        }
+    });
+    addr
+}
+
+async fn spawn_udp_echo() -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; 2048];
+        loop {
+            let (n, from) = socket.recv_from(&mut buf).await.unwrap();
+            socket.send_to(&buf[..n], from).await.unwrap();
+        }
+    });
+    addr
+}
+
+fn socks_outbound(server: SocketAddr, pass: &str) -> OutboundHandler {
+    let outbound = Outbound {
+        protocol: "socks".to_string(),
+        tag: "chain".to_string(),
+        settings: Some(serde_json::json!({
+            "address": server.ip().to_string(),
+            "port": server.port(),
+            "user": USER,
+            "pass": pass,
+        })),
+        stream_settings: None,
+    };
+    OutboundHandler::from_config(&outbound).unwrap()
+}
+
+#[tokio::test]
+async fn test_socks_tcp_connect() {
+    let seen = Seen::default();
+    let server = spawn_socks_server(seen.clone()).await;
+    let echo = spawn_tcp_echo().await;
+    let outbound = socks_outbound(server, PASS);
+
+    let target = Address::Domain("localhost".to_string(), echo.port());
+    let mut stream = outbound.connect_tcp(&target, &SockOpt::default()).await.unwrap();
+    stream.write_all(b"hello through socks").await.unwrap();
+    let mut buf = [0u8; 19];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"hello through socks");
+
+    // 目标地址以域名形式交给上游解析
+    assert_eq!(seen.lock().unwrap()[0], (0x01, target));
+}
+
+#[tokio::test]
+async fn test_socks_udp_associate() {
+    let seen = Seen::default();
+    let server = spawn_socks_server(seen.clone()).await;
+    let echo = spawn_udp_echo().await;
+    let outbound = socks_outbound(server, PASS);
+
+    let target = Address::Ipv4(Ipv4Addr::LOCALHOST, echo.port());
+    let session = outbound.connect_udp(&target, &SockOpt::default()).await.unwrap();
+    session.send(b"ping over udp").await.unwrap();
+
+    let mut buf = vec![0u8; 2048];
+    let n = tokio::time::timeout(std::time::Duration::from_secs(5), session.recv(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(&buf[..n], b"ping over udp");
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen[0].0, 0x03);
+    assert_eq!(seen[1], (0xFF, target));
+}
+
+#[tokio::test]
+async fn test_socks_auth_failure() {
+    let server = spawn_socks_server(Seen::default()).await;
+    let echo = spawn_tcp_echo().await;
+    let outbound = socks_outbound(server, "wrong");
+
+    let target = Address::Ipv4(Ipv4Addr::LOCALHOST, echo.port());
+    let err = outbound.connect_tcp(&target, &SockOpt::default()).await.err().unwrap().to_string();
+    assert!(err.contains("认证失败"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_socks_upstream_unreachable_reports_address() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let dead = listener.local_addr().unwrap();
+    drop(listener);
+
+    let outbound = socks_outbound(dead, PASS);
+    let target = Address::Domain("example.com".to_string(), 443);
+    let err = outbound.connect_tcp(&target, &SockOpt::default()).await.err().unwrap().to_string();
+    assert!(err.contains(&dead.to_string()), "{}", err);
+}
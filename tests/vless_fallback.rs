@@ -0,0 +1,361 @@
+//! 回落集成测试: 认证失败的连接按路径转发给本地网站，Reality 回落携带 PROXY protocol 头、按 SNI 选择目标并受并发与速率限制
+
+use anyhow::Result;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+use xray_lite::protocol::vless::{Address, Command, VlessRequest};
+use xray_lite::protocol::parse_proxy_protocol;
+use xray_lite::transport::reality::{RealityClientConfig, RealityClientStream};
+
+mod common;
+
+/// 模拟网站: 读到请求头结束后返回以 `name` 为内容的响应，并把收到的原始数据发给测试
+async fn spawn_site(name: &'static str) -> Result<(SocketAddr, mpsc::UnboundedReceiver<Vec<u8>>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let mut received = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !received.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => received.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", name.len(), name);
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = tx.send(received);
+            });
+        }
+    });
+    Ok((addr, rx))
+}
+
+/// 模拟源站: 收到任意数据后返回 `name` 并关闭连接
+async fn spawn_origin(name: &'static str) -> Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buf = [0u8; 4096];
+                if let Ok(n) = stream.read(&mut buf).await {
+                    if n > 0 {
+                        let _ = stream.write_all(name.as_bytes()).await;
+                    }
+                }
+            });
+        }
+    });
+    Ok(addr)
+}
+
+/// 不带 Reality 认证信息、只携带 SNI 的 ClientHello 记录
+fn client_hello(server_name: &str) -> Vec<u8> {
+    let name = server_name.as_bytes();
+    let mut sni = Vec::new();
+    sni.extend_from_slice(&(name.len() as u16 + 3).to_be_bytes());
+    sni.push(0x00);
+    sni.extend_from_slice(&(name.len() as u16).to_be_bytes());
+    sni.extend_from_slice(name);
+    let mut extensions = vec![0x00, 0x00];
+    extensions.extend_from_slice(&(sni.len() as u16).to_be_bytes());
+    extensions.extend_from_slice(&sni);
+
+    let mut body = vec![0x03, 0x03];
+    body.extend_from_slice(&[0x5a; 32]);
+    body.push(0);
+    body.extend_from_slice(&[0x00, 0x02, 0x13, 0x01, 0x01, 0x00]);
+    body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+    body.extend_from_slice(&extensions);
+
+    let mut record = vec![0x16, 0x03, 0x01];
+    record.extend_from_slice(&(body.len() as u16 + 4).to_be_bytes());
+    record.push(0x01);
+    record.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    record.extend_from_slice(&body);
+    record
+}
+
+/// 发送数据并读取服务器写回的全部内容
+async fn send(port: u16, data: &[u8]) -> Result<String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    stream.write_all(data).await?;
+    let mut response = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response)).await??;
+    Ok(String::from_utf8(response)?)
+}
+
+#[tokio::test]
+async fn test_fallback_by_path() -> Result<()> {
+    let (default_site, mut default_rx) = spawn_site("default").await?;
+    let (api_site, mut api_rx) = spawn_site("api").await?;
+    let port = common::free_port().await;
+    common::start_server(serde_json::json!({
+        "inbounds": [{
+            "protocol": "vless",
+            "listen": "127.0.0.1",
+            "port": port,
+            "settings": {
+                "clients": [{ "id": Uuid::new_v4().to_string() }],
+                "fallbacks": [
+                    { "dest": default_site.to_string() },
+                    { "path": "/api", "dest": api_site.port(), "xver": 1 }
+                ]
+            },
+            "streamSettings": { "network": "tcp", "security": "none", "sockopt": { "tcpFastOpen": false } }
+        }],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+    }))
+    .await?;
+
+    // 1. 普通 HTTP 请求原样转发给默认回落
+    let request = b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n";
+    let response = send(port, request).await?;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(response.ends_with("default"));
+    assert_eq!(default_rx.recv().await.unwrap(), request);
+
+    // 2. 匹配路径的请求转发给 /api 回落，并带 PROXY protocol 头
+    let request = b"GET /api?page=1 HTTP/1.1\r\nHost: example.com\r\n\r\n";
+    let response = send(port, request).await?;
+    assert!(response.ends_with("api"), "{}", response);
+    let received = api_rx.recv().await.unwrap();
+    let (header, consumed) = parse_proxy_protocol(&received)?;
+    assert_eq!(header.source_addr.ip(), "127.0.0.1".parse::<std::net::IpAddr>()?);
+    assert_eq!(header.dest_addr, SocketAddr::from(([127, 0, 0, 1], port)));
+    assert_eq!(&received[consumed..], request);
+
+    // 3. UUID 无效的 VLESS 请求同样回落，而不是直接断开
+    let request = VlessRequest {
+        version: 0,
+        uuid: Uuid::new_v4(),
+        command: Command::Tcp,
+        address: Address::Domain("example.com".to_string(), 80),
+        addon_length: 0,
+        addons: Default::default(),
+    };
+    let mut data = request.encode()?;
+    data.extend_from_slice(b"\r\n\r\n");
+    let response = send(port, &data).await?;
+    assert!(response.ends_with("default"), "{}", response);
+    assert_eq!(default_rx.recv().await.unwrap(), data);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reality_fallback_sends_proxy_header() -> Result<()> {
+    let (dest, mut dest_rx) = spawn_site("dest").await?;
+    let port = common::free_port().await;
+    common::start_server(serde_json::json!({
+        "inbounds": [{
+            "protocol": "vless",
+            "listen": "127.0.0.1",
+            "port": port,
+            "settings": { "clients": [{ "id": Uuid::new_v4().to_string() }] },
+            "streamSettings": {
+                "network": "tcp",
+                "security": "reality",
+                "realitySettings": {
+                    "dest": dest.to_string(),
+                    "serverNames": ["www.example.com"],
+                    "privateKey": "QUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUE=",
+                    "shortIds": ["0123456789abcdef"],
+                    "xver": 2
+                },
+                "sockopt": { "tcpFastOpen": false }
+            }
+        }],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+    }))
+    .await?;
+
+    // 非 Reality 客户端被转发给 dest，dest 先收到带真实客户端地址的 v2 头
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    let client_addr = stream.local_addr()?;
+    let request = b"GET / HTTP/1.1\r\nHost: www.example.com\r\n\r\n";
+    stream.write_all(request).await?;
+    let mut response = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response)).await??;
+    assert!(String::from_utf8(response)?.ends_with("dest"));
+
+    let received = dest_rx.recv().await.unwrap();
+    let (header, consumed) = parse_proxy_protocol(&received)?;
+    assert_eq!(&received[..12], b"\r\n\r\n\0\r\nQUIT\n");
+    assert_eq!(header.source_addr, client_addr);
+    assert_eq!(header.dest_addr, SocketAddr::from(([127, 0, 0, 1], port)));
+    assert_eq!(&received[consumed..], request);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reality_dest_map_by_sni() -> Result<()> {
+    let origin_a = spawn_origin("origin-a").await?;
+    let origin_b = spawn_origin("origin-b").await?;
+    let default = spawn_origin("default").await?;
+    let dest = spawn_origin("dest").await?;
+    let port = common::free_port().await;
+    common::start_server(serde_json::json!({
+        "inbounds": [{
+            "protocol": "vless",
+            "listen": "127.0.0.1",
+            "port": port,
+            "settings": { "clients": [{ "id": Uuid::new_v4().to_string() }] },
+            "streamSettings": {
+                "network": "tcp",
+                "security": "reality",
+                "realitySettings": {
+                    "dest": dest.to_string(),
+                    "destMap": {
+                        "www.a.com": origin_a.to_string(),
+                        "*.b.com": origin_b.to_string(),
+                        "*": default.to_string()
+                    },
+                    "serverNames": ["www.a.com", "www.b.com"],
+                    "privateKey": "QUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUE=",
+                    "shortIds": ["0123456789abcdef"],
+                    "certRefreshInterval": 0
+                },
+                "sockopt": { "tcpFastOpen": false }
+            }
+        }],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+    }))
+    .await?;
+
+    // 未通过认证的 TLS 客户端按 SNI 回落到对应源站
+    assert_eq!(send(port, &client_hello("www.a.com")).await?, "origin-a");
+    assert_eq!(send(port, &client_hello("WWW.A.COM")).await?, "origin-a");
+    assert_eq!(send(port, &client_hello("img.b.com")).await?, "origin-b");
+    // 没有匹配的 SNI 与非 TLS 数据使用 `*` 条目
+    assert_eq!(send(port, &client_hello("www.c.com")).await?, "default");
+    assert_eq!(send(port, b"GET / HTTP/1.1\r\n\r\n").await?, "default");
+    Ok(())
+}
+
+/// 模拟 dest: 接受连接后一直保持，每接受一个连接通知一次测试
+async fn spawn_holding_dest() -> Result<(SocketAddr, mpsc::UnboundedReceiver<TcpStream>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let _ = tx.send(stream);
+        }
+    });
+    Ok((addr, rx))
+}
+
+#[tokio::test]
+async fn test_reality_fallback_cap() -> Result<()> {
+    let (dest, mut dest_rx) = spawn_holding_dest().await?;
+    let echo = common::spawn_tcp_echo().await;
+    let uuid = Uuid::new_v4();
+    let port = common::free_port().await;
+    let metrics = common::start_server(serde_json::json!({
+        "inbounds": [{
+            "tag": "reality-in",
+            "protocol": "vless",
+            "listen": "127.0.0.1",
+            "port": port,
+            "settings": { "clients": [{ "id": uuid.to_string() }] },
+            "streamSettings": {
+                "network": "tcp",
+                "security": "reality",
+                "realitySettings": {
+                    "dest": dest.to_string(),
+                    "serverNames": ["www.example.com"],
+                    "privateKey": "QUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUE=",
+                    "shortIds": ["0123456789abcdef"],
+                    "certRefreshInterval": 0,
+                    "maxFallbacks": 2
+                },
+                "sockopt": { "tcpFastOpen": false }
+            }
+        }],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+    }))
+    .await?;
+
+    // 1. 前两个非 Reality 连接被转发给 dest 并一直保持
+    let mut held = Vec::new();
+    for _ in 0..2 {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+        stream.write_all(b"GET / HTTP/1.1\r\n\r\n").await?;
+        let upstream = tokio::time::timeout(Duration::from_secs(5), dest_rx.recv()).await?.unwrap();
+        held.push((stream, upstream));
+    }
+
+    // 2. 第三个连接被直接关闭，dest 收不到新连接
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    stream.write_all(b"GET / HTTP/1.1\r\n\r\n").await?;
+    let mut buf = [0u8; 16];
+    let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf)).await?;
+    assert!(matches!(read, Ok(0) | Err(_)), "{:?}", read);
+    assert!(tokio::time::timeout(Duration::from_millis(100), dest_rx.recv()).await.is_err());
+
+    // 3. 通过认证的客户端不受回落上限影响
+    let secret = x25519_dalek::StaticSecret::from([b'A'; 32]);
+    let client_config = RealityClientConfig {
+        server_name: "www.example.com".to_string(),
+        public_key: x25519_dalek::PublicKey::from(&secret).to_bytes(),
+        short_id: hex::decode("0123456789abcdef")?,
+    };
+    let tcp = TcpStream::connect(("127.0.0.1", port)).await?;
+    let mut client = tokio::time::timeout(Duration::from_secs(5), RealityClientStream::connect(tcp, &client_config)).await??;
+    let target = Address::Ipv4(std::net::Ipv4Addr::LOCALHOST, echo.port());
+    common::open_vless(&mut client, uuid, Command::Tcp, target, b"hello").await?;
+    let mut echoed = [0u8; 5];
+    tokio::time::timeout(Duration::from_secs(5), client.read_exact(&mut echoed)).await??;
+    assert_eq!(&echoed, b"hello");
+
+    let inbound = metrics.inbound("reality-in").unwrap();
+    assert_eq!(inbound.fallbacks_attempted(), 3);
+    assert_eq!(inbound.fallbacks_active(), 2);
+    assert_eq!(inbound.fallbacks_rejected(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reality_fallback_rate_limit() -> Result<()> {
+    let dest = spawn_origin("dest").await?;
+    let port = common::free_port().await;
+    common::start_server(serde_json::json!({
+        "inbounds": [{
+            "protocol": "vless",
+            "listen": "127.0.0.1",
+            "port": port,
+            "settings": { "clients": [{ "id": Uuid::new_v4().to_string() }] },
+            "streamSettings": {
+                "network": "tcp",
+                "security": "reality",
+                "realitySettings": {
+                    "dest": dest.to_string(),
+                    "serverNames": ["www.example.com"],
+                    "privateKey": "QUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUE=",
+                    "shortIds": ["0123456789abcdef"],
+                    "certRefreshInterval": 0,
+                    "fallbackRate": 1,
+                    "fallbackBurst": 2
+                },
+                "sockopt": { "tcpFastOpen": false }
+            }
+        }],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+    }))
+    .await?;
+
+    // 同一来源在突发额度内正常回落，超出后连接被直接关闭
+    assert_eq!(send(port, b"GET / HTTP/1.1\r\n\r\n").await?, "dest");
+    assert_eq!(send(port, b"GET / HTTP/1.1\r\n\r\n").await?, "dest");
+    assert_eq!(send(port, b"GET / HTTP/1.1\r\n\r\n").await?, "");
+    Ok(())
+}
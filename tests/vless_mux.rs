@@ -0,0 +1,214 @@
+//! VLESS Mux (Mux.cool) 集成测试: 手工构造帧序列
+//!
+//! 在一条连接上并发两个 TCP 子连接和一个 UDP 子连接，以及 XUDP 在同一子连接中
+//! 发往两个不同目标的数据报。
+
+use anyhow::{bail, Result};
+use bytes::{Bytes, BytesMut};
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio_util::codec::FramedRead;
+use uuid::Uuid;
+use xray_lite::protocol::mux::{option, Frame, FrameMetadata, MuxCodec, SessionStatus, TargetNetwork};
+use xray_lite::protocol::vless::{Address, Command, MUX_COOL_DOMAIN};
+
+mod common;
+
+fn new_frame(id: u16, network: TargetNetwork, target: SocketAddr, data: &'static [u8]) -> Frame {
+    let mut meta = FrameMetadata::new(id, SessionStatus::New);
+    meta.option = option::DATA;
+    meta.target = Some((network, Address::from(target)));
+    Frame {
+        meta,
+        data: Some(Bytes::from_static(data)),
+    }
+}
+
+fn encode(frames: &[Frame]) -> Result<BytesMut> {
+    let mut buf = BytesMut::new();
+    for frame in frames {
+        frame.encode(&mut buf)?;
+    }
+    Ok(buf)
+}
+
+/// 启动 UDP 回显服务
+async fn spawn_udp_echo() -> Result<SocketAddr> {
+    let socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let addr = socket.local_addr()?;
+    tokio::spawn(async move {
+        let mut buf = [0u8; 2048];
+        while let Ok((n, from)) = socket.recv_from(&mut buf).await {
+            let _ = socket.send_to(&buf[..n], from).await;
+        }
+    });
+    Ok(addr)
+}
+
+/// 启动只有一个 VLESS 入站的服务器，返回端口
+async fn start_vless(user: Uuid) -> Result<u16> {
+    let port = common::free_port().await;
+    common::start_server(serde_json::json!({
+        "inbounds": [{
+            "protocol": "vless",
+            "listen": "127.0.0.1",
+            "port": port,
+            "settings": { "clients": [{ "id": user.to_string() }] },
+            "streamSettings": { "network": "tcp", "security": "none", "sockopt": { "tcpFastOpen": false } }
+        }],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+    }))
+    .await?;
+    Ok(port)
+}
+
+#[tokio::test]
+async fn test_mux_concurrent_sub_connections() -> Result<()> {
+    let first = common::spawn_tcp_echo().await;
+    let second = common::spawn_tcp_echo().await;
+    let udp_target = spawn_udp_echo().await?;
+    let user = Uuid::new_v4();
+    let port = start_vless(user).await?;
+
+    // 第一个 New 帧随请求头一起发出，其余帧交错发送
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    let opening = encode(&[new_frame(1, TargetNetwork::Tcp, first, b"one-a")])?;
+    let mux = Address::Domain(MUX_COOL_DOMAIN.to_string(), 0);
+    common::open_vless(&mut stream, user, Command::Mux, mux, &opening).await?;
+    let (read_half, mut write_half) = stream.into_split();
+    write_half
+        .write_all(&encode(&[
+            new_frame(2, TargetNetwork::Tcp, second, b"two-a"),
+            Frame::keep(1, Bytes::from_static(b"one-b")),
+            new_frame(3, TargetNetwork::Udp, udp_target, b"datagram"),
+            Frame::keep(2, Bytes::from_static(b"two-b")),
+        ])?)
+        .await?;
+
+    let mut frames = FramedRead::new(read_half, MuxCodec);
+    let mut received: HashMap<u16, Vec<u8>> = HashMap::new();
+    let expected: HashMap<u16, Vec<u8>> = [
+        (1, b"one-aone-b".to_vec()),
+        (2, b"two-atwo-b".to_vec()),
+        (3, b"datagram".to_vec()),
+    ]
+    .into_iter()
+    .collect();
+    while received != expected {
+        let frame = match tokio::time::timeout(Duration::from_secs(5), frames.next()).await? {
+            Some(frame) => frame?,
+            None => bail!("连接提前关闭，已收到: {:?}", received),
+        };
+        assert_eq!(frame.meta.status, SessionStatus::Keep, "{:?}", frame);
+        received
+            .entry(frame.meta.session_id)
+            .or_default()
+            .extend_from_slice(&frame.data.unwrap_or_default());
+    }
+
+    // 客户端结束子连接 1 后，目标关闭，服务器回复 End；子连接 2 不受影响
+    write_half.write_all(&encode(&[Frame::end(1, false)])?).await?;
+    let end = tokio::time::timeout(Duration::from_secs(5), frames.next()).await?.unwrap()?;
+    assert_eq!(end.meta.session_id, 1);
+    assert_eq!(end.meta.status, SessionStatus::End);
+    assert!(!end.meta.has_error());
+
+    write_half.write_all(&encode(&[Frame::keep(2, Bytes::from_static(b"two-c"))])?).await?;
+    let frame = tokio::time::timeout(Duration::from_secs(5), frames.next()).await?.unwrap()?;
+    assert_eq!(frame.meta.session_id, 2);
+    assert_eq!(frame.data.as_deref(), Some(&b"two-c"[..]));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_xudp_packets_to_two_destinations() -> Result<()> {
+    let first = spawn_udp_echo().await?;
+    let second = spawn_udp_echo().await?;
+    let user = Uuid::new_v4();
+    let port = start_vless(user).await?;
+
+    // XUDP: 会话 ID 0 的 New 帧带全局 ID，之后的 Keep 帧逐包指定目标
+    let mut opening = new_frame(0, TargetNetwork::Udp, first, b"to first");
+    opening.meta.global_id = Some([7; 8]);
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    let mux = Address::Domain(MUX_COOL_DOMAIN.to_string(), 0);
+    common::open_vless(&mut stream, user, Command::Mux, mux, &encode(&[opening])?).await?;
+    let (read_half, mut write_half) = stream.into_split();
+    let mut frames = FramedRead::new(read_half, MuxCodec);
+
+    // 首包随 New 帧发出，后续数据报用 Keep 帧交替发往两个目标
+    let packets = [(first, &b"to first"[..]), (second, &b"to second"[..]), (first, &b"again"[..])];
+    for (index, (target, payload)) in packets.into_iter().enumerate() {
+        if index > 0 {
+            let frame = Frame::packet(0, Address::from(target), Bytes::copy_from_slice(payload));
+            write_half.write_all(&encode(&[frame])?).await?;
+        }
+        let reply = tokio::time::timeout(Duration::from_secs(5), frames.next()).await?.unwrap()?;
+        assert_eq!(reply.meta.session_id, 0);
+        assert_eq!(reply.meta.status, SessionStatus::Keep);
+        // 回包附带来源地址，客户端据此区分
+        assert_eq!(reply.meta.target, Some((TargetNetwork::Udp, Address::from(target))));
+        assert_eq!(reply.data.as_deref(), Some(payload));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stalled_sub_connection_does_not_block_others() -> Result<()> {
+    // 接受连接但从不读取的目标
+    let stalled = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let stalled_addr = stalled.local_addr()?;
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((stream, _)) = stalled.accept().await {
+            held.push(stream);
+        }
+    });
+    let echo = common::spawn_tcp_echo().await;
+    let user = Uuid::new_v4();
+    let port = start_vless(user).await?;
+
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    let opening = encode(&[new_frame(1, TargetNetwork::Tcp, stalled_addr, b"stall")])?;
+    let mux = Address::Domain(MUX_COOL_DOMAIN.to_string(), 0);
+    common::open_vless(&mut stream, user, Command::Mux, mux, &opening).await?;
+    let (read_half, mut write_half) = stream.into_split();
+    let mut frames = FramedRead::new(read_half, MuxCodec);
+
+    // 持续向不读取的目标发送，直到服务器重置该子连接
+    let writer = tokio::spawn(async move {
+        let chunk = Bytes::from(vec![0x42; 16 * 1024]);
+        for _ in 0..4096 {
+            if write_half.write_all(&encode(&[Frame::keep(1, chunk.clone())])?).await.is_err() {
+                break;
+            }
+        }
+        Ok::<_, anyhow::Error>(write_half)
+    });
+    loop {
+        let frame = match tokio::time::timeout(Duration::from_secs(10), frames.next()).await? {
+            Some(frame) => frame?,
+            None => bail!("连接提前关闭"),
+        };
+        if frame.meta.session_id == 1 && frame.meta.status == SessionStatus::End {
+            assert!(frame.meta.has_error());
+            break;
+        }
+    }
+    let mut write_half = writer.await??;
+
+    // 同一连接上的其他子连接不受影响
+    write_half.write_all(&encode(&[new_frame(2, TargetNetwork::Tcp, echo, b"still alive")])?).await?;
+    loop {
+        let frame = tokio::time::timeout(Duration::from_secs(5), frames.next()).await?.unwrap()?;
+        if frame.meta.session_id == 2 {
+            assert_eq!(frame.data.as_deref(), Some(&b"still alive"[..]));
+            break;
+        }
+    }
+    Ok(())
+}
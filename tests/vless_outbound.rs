@@ -0,0 +1,134 @@
+//! VLESS 出站集成测试
+//!
+//! 两个进程内服务器串联: 客户端 -> A (VLESS) -> B (VLESS+Reality) -> 回显服务。
+
+use anyhow::Result;
+use base64::Engine as _;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use uuid::Uuid;
+use x25519_dalek::{PublicKey, StaticSecret};
+use xray_lite::protocol::vless::{Address, Command};
+
+mod common;
+
+/// 启动 B (Reality) 与 A (以 B 为 vless 出站)，返回 A 的端口与 A 的用户
+async fn start_chain() -> Result<(u16, Uuid)> {
+    let secret = StaticSecret::from([0x24; 32]);
+    let public = PublicKey::from(&secret);
+    let upstream_user = Uuid::new_v4();
+    let local_user = Uuid::new_v4();
+
+    // B 的回落目标只需存在即可
+    let dest = TcpListener::bind("127.0.0.1:0").await?;
+    let dest_addr = dest.local_addr()?;
+    tokio::spawn(async move {
+        let _dest = dest;
+        std::future::pending::<()>().await;
+    });
+
+    let port_b = common::free_port().await;
+    common::start_server(serde_json::json!({
+        "inbounds": [{
+            "protocol": "vless",
+            "listen": "127.0.0.1",
+            "port": port_b,
+            "settings": { "clients": [{ "id": upstream_user.to_string() }] },
+            "streamSettings": {
+                "network": "tcp",
+                "security": "reality",
+                "realitySettings": {
+                    "dest": dest_addr.to_string(),
+                    "serverNames": ["www.example.com"],
+                    "privateKey": base64::engine::general_purpose::STANDARD.encode(secret.to_bytes()),
+                    "shortIds": ["a1b2c3d4e5f60718"]
+                },
+                "sockopt": { "tcpFastOpen": false }
+            }
+        }],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+    }))
+    .await?;
+
+    let port_a = common::free_port().await;
+    common::start_server(serde_json::json!({
+        "inbounds": [{
+            "protocol": "vless",
+            "listen": "127.0.0.1",
+            "port": port_a,
+            "settings": { "clients": [{ "id": local_user.to_string() }] },
+            "streamSettings": {
+                "network": "tcp",
+                "security": "none",
+                "sockopt": { "tcpFastOpen": false }
+            }
+        }],
+        "outbounds": [{
+            "protocol": "vless",
+            "tag": "chain",
+            "settings": {
+                "address": "127.0.0.1",
+                "port": port_b,
+                "id": upstream_user.to_string()
+            },
+            "streamSettings": {
+                "security": "reality",
+                "realitySettings": {
+                    "serverName": "www.example.com",
+                    "publicKey": base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(public.to_bytes()),
+                    "shortId": "a1b2c3d4e5f60718"
+                }
+            }
+        }]
+    }))
+    .await?;
+
+    Ok((port_a, local_user))
+}
+
+#[tokio::test]
+async fn test_vless_outbound_chain_tcp() -> Result<()> {
+    let echo = common::spawn_tcp_echo().await;
+    let (port_a, user) = start_chain().await?;
+
+    let mut client = TcpStream::connect(("127.0.0.1", port_a)).await?;
+    let target = Address::Ipv4(Ipv4Addr::LOCALHOST, echo.port());
+    common::open_vless(&mut client, user, Command::Tcp, target, b"through two hops").await?;
+
+    let mut first = [0u8; 16];
+    tokio::time::timeout(Duration::from_secs(5), client.read_exact(&mut first)).await??;
+    assert_eq!(&first, b"through two hops");
+
+    let large: Vec<u8> = (0..50_000u32).map(|i| (i % 251) as u8).collect();
+    client.write_all(&large).await?;
+    let mut echoed = vec![0u8; large.len()];
+    tokio::time::timeout(Duration::from_secs(5), client.read_exact(&mut echoed)).await??;
+    assert_eq!(echoed, large);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_vless_outbound_chain_udp() -> Result<()> {
+    let echo = UdpSocket::bind("127.0.0.1:0").await?;
+    let echo_addr = echo.local_addr()?;
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; 2048];
+        while let Ok((n, from)) = echo.recv_from(&mut buf).await {
+            let _ = echo.send_to(&buf[..n], from).await;
+        }
+    });
+    let (port_a, user) = start_chain().await?;
+
+    let mut client = TcpStream::connect(("127.0.0.1", port_a)).await?;
+    let target = Address::from(echo_addr);
+    common::open_vless(&mut client, user, Command::Udp, target, b"\x00\x04ping").await?;
+
+    let mut frame = [0u8; 6];
+    tokio::time::timeout(Duration::from_secs(5), client.read_exact(&mut frame)).await??;
+    assert_eq!(&frame, b"\x00\x04ping");
+
+    Ok(())
+}
@@ -0,0 +1,259 @@
+//! xtls-rprx-vision 集成测试: Reality 上的填充帧与直接复制
+//!
+//! 客户端手工构造 vision 帧，目标服务模拟内层 TLS 1.3 握手；切换到直接复制后，
+//! 双方都绕过 Reality 直接在 TCP 上收发内层记录。
+
+use anyhow::Result;
+use base64::Engine as _;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use uuid::Uuid;
+use x25519_dalek::{PublicKey, StaticSecret};
+use xray_lite::protocol::vless::{Addons, Address, Command, VlessRequest, FLOW_VISION};
+use xray_lite::transport::reality::{RealityClientConfig, RealityClientStream};
+
+mod common;
+
+const CONTINUE: u8 = 0x00;
+const DIRECT: u8 = 0x02;
+
+const CLIENT_HELLO: [u8; 9] = [0x16, 0x03, 0x01, 0x00, 0x04, 0x01, 0x00, 0x00, 0x00];
+/// 内层应用数据记录，`tag` 区分方向与顺序
+fn application_data(tag: u8) -> Vec<u8> {
+    vec![0x17, 0x03, 0x03, 0x00, 0x04, tag, tag, tag, tag]
+}
+
+/// TLS 1.3 ServerHello (TLS_AES_128_GCM_SHA256)
+fn server_hello() -> Vec<u8> {
+    let mut body = vec![0x03, 0x03];
+    body.extend_from_slice(&[0; 32]);
+    body.push(32);
+    body.extend_from_slice(&[0; 32]);
+    body.extend_from_slice(&[0x13, 0x01, 0x00]);
+    body.extend_from_slice(&[0x00, 0x06, 0x00, 0x2b, 0x00, 0x02, 0x03, 0x04]);
+    let mut record = vec![0x16, 0x03, 0x03];
+    record.extend_from_slice(&(body.len() as u16 + 4).to_be_bytes());
+    record.extend_from_slice(&[0x02, 0x00]);
+    record.extend_from_slice(&(body.len() as u16).to_be_bytes());
+    record.extend_from_slice(&body);
+    record
+}
+
+/// 不带填充的 vision 帧
+fn frame(uuid: Option<Uuid>, command: u8, content: &[u8]) -> Vec<u8> {
+    let mut buf = uuid.map(|u| u.as_bytes().to_vec()).unwrap_or_default();
+    buf.push(command);
+    buf.extend_from_slice(&(content.len() as u16).to_be_bytes());
+    buf.extend_from_slice(&[0, 0]);
+    buf.extend_from_slice(content);
+    buf
+}
+
+/// 读取一个 vision 帧，返回命令与内容
+async fn read_frame<S: AsyncRead + Unpin>(stream: &mut S) -> Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 5];
+    stream.read_exact(&mut header).await?;
+    let mut content = vec![0u8; u16::from_be_bytes([header[1], header[2]]) as usize];
+    stream.read_exact(&mut content).await?;
+    let mut padding = vec![0u8; u16::from_be_bytes([header[3], header[4]]) as usize];
+    stream.read_exact(&mut padding).await?;
+    Ok((header[0], content))
+}
+
+#[tokio::test]
+async fn test_vision_direct_copy_over_reality() -> Result<()> {
+    // 1. 目标服务: 模拟内层 TLS 1.3 服务器，按顺序收发记录
+    let target_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let target_addr = target_listener.local_addr()?;
+    let target = tokio::spawn(async move {
+        let (mut stream, _) = target_listener.accept().await?;
+        let mut hello = [0u8; CLIENT_HELLO.len()];
+        stream.read_exact(&mut hello).await?;
+        assert_eq!(hello, CLIENT_HELLO);
+        stream.write_all(&server_hello()).await?;
+
+        let mut records = vec![0u8; 18];
+        stream.read_exact(&mut records).await?;
+        assert_eq!(records, [application_data(1), application_data(2)].concat());
+        stream.write_all(&application_data(0xb1)).await?;
+
+        // 收到客户端直接写入的记录后才发送下一条，确保它不会与 Direct 帧一起发出
+        let mut record = vec![0u8; 9];
+        stream.read_exact(&mut record).await?;
+        assert_eq!(record, application_data(3));
+        stream.write_all(&application_data(0xb2)).await?;
+        anyhow::Ok(())
+    });
+
+    // 2. Reality 入站，用户启用 vision
+    let dest_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let secret = StaticSecret::from([0x42; 32]);
+    let public = PublicKey::from(&secret);
+    let uuid = Uuid::new_v4();
+    let port = common::free_port().await;
+    common::start_server(serde_json::json!({
+        "inbounds": [{
+            "protocol": "vless",
+            "listen": "127.0.0.1",
+            "port": port,
+            "settings": { "clients": [{ "id": uuid.to_string(), "flow": FLOW_VISION }] },
+            "streamSettings": {
+                "network": "tcp",
+                "security": "reality",
+                "realitySettings": {
+                    "dest": dest_listener.local_addr()?.to_string(),
+                    "serverNames": ["www.example.com"],
+                    "privateKey": base64::engine::general_purpose::STANDARD.encode(secret.to_bytes()),
+                    "shortIds": ["0123456789abcdef"]
+                },
+                "sockopt": { "tcpFastOpen": false }
+            }
+        }],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+    }))
+    .await?;
+
+    let client_config = RealityClientConfig {
+        server_name: "www.example.com".to_string(),
+        public_key: public.to_bytes(),
+        short_id: hex::decode("0123456789abcdef")?,
+    };
+    let tcp = TcpStream::connect(("127.0.0.1", port)).await?;
+    let mut client = tokio::time::timeout(Duration::from_secs(5), RealityClientStream::connect(tcp, &client_config)).await??;
+
+    // 3. 请求携带 vision 流控，ClientHello 作为第一帧随请求发出
+    let request = VlessRequest {
+        version: 0,
+        uuid,
+        command: Command::Tcp,
+        address: Address::from(target_addr),
+        addon_length: 0,
+        addons: Addons {
+            flow: Some(FLOW_VISION.to_string()),
+            ..Default::default()
+        },
+    };
+    let mut buf = request.encode()?;
+    buf.extend_from_slice(&frame(Some(uuid), CONTINUE, &CLIENT_HELLO));
+    client.write_all(&buf).await?;
+    client.flush().await?;
+
+    let mut header = [0u8; 2];
+    client.read_exact(&mut header).await?;
+    assert_eq!(header, [0, 0]);
+
+    // 4. ServerHello 以 UUID 开头的填充帧返回
+    let mut prefix = [0u8; 16];
+    client.read_exact(&mut prefix).await?;
+    assert_eq!(&prefix, uuid.as_bytes());
+    let hello = tokio::time::timeout(Duration::from_secs(5), read_frame(&mut client)).await??;
+    assert_eq!(hello, (CONTINUE, server_hello()));
+
+    // 5. 客户端发出 Direct 帧，之后的记录直接写到 TCP 上
+    client.write_all(&frame(None, DIRECT, &application_data(1))).await?;
+    client.flush().await?;
+    client.get_mut().write_all(&application_data(2)).await?;
+
+    // 6. 服务器的第一条应用数据以 Direct 帧返回，之后的数据不再经过 Reality
+    let reply = tokio::time::timeout(Duration::from_secs(5), read_frame(&mut client)).await??;
+    assert_eq!(reply, (DIRECT, application_data(0xb1)));
+    let (mut tcp, leftover) = client.into_inner();
+    assert!(leftover.is_empty());
+
+    tcp.write_all(&application_data(3)).await?;
+    let mut raw = vec![0u8; 9];
+    tokio::time::timeout(Duration::from_secs(5), tcp.read_exact(&mut raw)).await??;
+    assert_eq!(raw, application_data(0xb2));
+
+    target.await??;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_vision_user_requires_flow() -> Result<()> {
+    let echo = common::spawn_tcp_echo().await;
+    let uuid = Uuid::new_v4();
+    let port = common::free_port().await;
+    common::start_server(serde_json::json!({
+        "inbounds": [{
+            "protocol": "vless",
+            "listen": "127.0.0.1",
+            "port": port,
+            "settings": { "clients": [{ "id": uuid.to_string(), "flow": FLOW_VISION }] },
+            "streamSettings": { "network": "tcp", "security": "none", "sockopt": { "tcpFastOpen": false } }
+        }],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+    }))
+    .await?;
+
+    // 启用 vision 的用户不带流控代理 TCP，连接被直接关闭
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    let result = common::open_vless(&mut stream, uuid, Command::Tcp, Address::from(echo), b"hello").await;
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_vision_rejected_on_native_backend() -> Result<()> {
+    // native 后端不支持直接复制，vision 请求在握手时被拒绝，而不是切换后失去同步
+    let echo = common::spawn_tcp_echo().await;
+    let dest_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let secret = StaticSecret::from([0x42; 32]);
+    let public = PublicKey::from(&secret);
+    let uuid = Uuid::new_v4();
+    let port = common::free_port().await;
+    common::start_server(serde_json::json!({
+        "inbounds": [{
+            "protocol": "vless",
+            "listen": "127.0.0.1",
+            "port": port,
+            "settings": { "clients": [{ "id": uuid.to_string(), "flow": FLOW_VISION }] },
+            "streamSettings": {
+                "network": "tcp",
+                "security": "reality",
+                "realitySettings": {
+                    "dest": dest_listener.local_addr()?.to_string(),
+                    "serverNames": ["www.example.com"],
+                    "privateKey": base64::engine::general_purpose::STANDARD.encode(secret.to_bytes()),
+                    "shortIds": ["0123456789abcdef"],
+                    "certRefreshInterval": 0,
+                    "backend": "native"
+                },
+                "sockopt": { "tcpFastOpen": false }
+            }
+        }],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+    }))
+    .await?;
+
+    let client_config = RealityClientConfig {
+        server_name: "www.example.com".to_string(),
+        public_key: public.to_bytes(),
+        short_id: hex::decode("0123456789abcdef")?,
+    };
+    let tcp = TcpStream::connect(("127.0.0.1", port)).await?;
+    let mut client = tokio::time::timeout(Duration::from_secs(5), RealityClientStream::connect(tcp, &client_config)).await??;
+
+    let request = VlessRequest {
+        version: 0,
+        uuid,
+        command: Command::Tcp,
+        address: Address::from(echo),
+        addon_length: 0,
+        addons: Addons {
+            flow: Some(FLOW_VISION.to_string()),
+            ..Default::default()
+        },
+    };
+    let mut buf = request.encode()?;
+    buf.extend_from_slice(&frame(Some(uuid), CONTINUE, &CLIENT_HELLO));
+    client.write_all(&buf).await?;
+    client.flush().await?;
+
+    // 不返回 VLESS 响应头，连接直接关闭
+    let mut header = [0u8; 2];
+    let read = tokio::time::timeout(Duration::from_secs(5), client.read_exact(&mut header)).await?;
+    assert!(read.is_err(), "{:?}", header);
+    Ok(())
+}
@@ -0,0 +1,291 @@
+//! XHTTP 集成测试: 用 hyper 的 HTTP/1.1 客户端与 h2c 客户端访问同一个 XHTTP 入站
+
+use anyhow::{bail, Result};
+use bytes::{Bytes, BytesMut};
+use hyper::body::{Body, Frame, Incoming};
+use hyper::client::conn::http1;
+use hyper::{Request, StatusCode};
+use hyper_util::rt::TokioIo;
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+use xray_lite::protocol::vless::{Address, Addons, Command, VlessRequest};
+
+mod common;
+
+/// 由通道驱动的请求体，没有 Content-Length，hyper 以 chunked 编码发送
+struct ChannelBody(Option<mpsc::UnboundedReceiver<Bytes>>);
+
+impl ChannelBody {
+    /// GET 请求使用的空请求体
+    fn empty() -> Self {
+        Self(None)
+    }
+}
+
+impl Body for ChannelBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        match &mut self.0 {
+            Some(rx) => rx.poll_recv(cx).map(|data| data.map(|data| Ok(Frame::data(data)))),
+            None => Poll::Ready(None),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.0.is_none()
+    }
+}
+
+/// 启动使用 XHTTP 传输的 VLESS 入站，返回端口
+async fn start_xhttp(user: Uuid, mode: &str) -> Result<u16> {
+    start_xhttp_with(user, serde_json::json!({ "mode": mode, "path": "/xh" })).await
+}
+
+/// 以指定的 xhttpSettings 启动服务
+async fn start_xhttp_with(user: Uuid, xhttp_settings: serde_json::Value) -> Result<u16> {
+    let port = common::free_port().await;
+    common::start_server(serde_json::json!({
+        "inbounds": [{
+            "protocol": "vless",
+            "listen": "127.0.0.1",
+            "port": port,
+            "settings": { "clients": [{ "id": user.to_string() }] },
+            "streamSettings": {
+                "network": "http",
+                "security": "none",
+                "xhttpSettings": xhttp_settings,
+                "sockopt": { "tcpFastOpen": false }
+            }
+        }],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+    }))
+    .await?;
+    Ok(port)
+}
+
+/// 建立 HTTP/1.1 客户端连接
+async fn connect<B>(port: u16) -> Result<http1::SendRequest<B>>
+where
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    let (sender, connection) = http1::handshake(TokioIo::new(stream)).await?;
+    tokio::spawn(connection);
+    Ok(sender)
+}
+
+/// VLESS 请求头加首包数据
+fn vless_request(user: Uuid, target: std::net::SocketAddr, payload: &[u8]) -> Result<Bytes> {
+    let request = VlessRequest {
+        version: 0,
+        uuid: user,
+        command: Command::Tcp,
+        address: Address::from(target),
+        addon_length: 0,
+        addons: Addons::default(),
+    };
+    let mut buf = request.encode()?;
+    buf.extend_from_slice(payload);
+    Ok(buf.freeze())
+}
+
+/// 从响应体中读取至少 `len` 字节
+async fn read_at_least(body: &mut Incoming, received: &mut BytesMut, len: usize) -> Result<()> {
+    while received.len() < len {
+        let frame = tokio::time::timeout(
+            Duration::from_secs(5),
+            std::future::poll_fn(|cx| Pin::new(&mut *body).poll_frame(cx)),
+        )
+        .await?;
+        match frame {
+            Some(frame) => {
+                if let Ok(data) = frame?.into_data() {
+                    received.extend_from_slice(&data);
+                }
+            }
+            None => bail!("响应体提前结束"),
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stream_one_over_http1() -> Result<()> {
+    let echo = common::spawn_tcp_echo().await;
+    let user = Uuid::new_v4();
+    let port = start_xhttp(user, "stream-one").await?;
+
+    // 单个 chunked POST 同时承载上下行
+    let mut sender = connect(port).await?;
+    let (tx, rx) = mpsc::unbounded_channel();
+    tx.send(vless_request(user, echo, b"hello")?)?;
+    let request = Request::post("/xh").header("host", "example.com").body(ChannelBody(Some(rx)))?;
+    let response = sender.send_request(request).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["transfer-encoding"], "chunked");
+
+    let mut body = response.into_body();
+    let mut received = BytesMut::new();
+    read_at_least(&mut body, &mut received, 7).await?;
+    assert_eq!(&received[..], b"\x00\x00hello");
+
+    // 上行仍在进行时下行可以继续收到数据
+    tx.send(Bytes::from_static(b" world"))?;
+    read_at_least(&mut body, &mut received, 13).await?;
+    assert_eq!(&received[2..], b"hello world");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_packet_up_over_http1() -> Result<()> {
+    let echo = common::spawn_tcp_echo().await;
+    let user = Uuid::new_v4();
+    let port = start_xhttp(user, "auto").await?;
+    let session = Uuid::new_v4();
+
+    // GET 承载下行
+    let mut download = connect::<ChannelBody>(port).await?;
+    let request = Request::get(format!("/xh/{session}")).header("host", "example.com").body(ChannelBody::empty())?;
+    let response = download.send_request(request).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let mut body = response.into_body();
+
+    // 同一条 keep-alive 连接上依次发送两个上行 POST
+    let mut upload = connect::<ChannelBody>(port).await?;
+    let chunks = [vless_request(user, echo, b"first")?, Bytes::from_static(b"second")];
+    let mut received = BytesMut::new();
+    for (seq, chunk) in chunks.into_iter().enumerate() {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tx.send(chunk)?;
+        drop(tx);
+        let request = Request::post(format!("/xh/{session}/{seq}")).header("host", "example.com").body(ChannelBody(Some(rx)))?;
+        let response = upload.send_request(request).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        if seq == 0 {
+            read_at_least(&mut body, &mut received, 7).await?;
+        }
+    }
+    read_at_least(&mut body, &mut received, 13).await?;
+    assert_eq!(&received[..], b"\x00\x00firstsecond");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_http1_unknown_path() -> Result<()> {
+    let user = Uuid::new_v4();
+    let port = start_xhttp(user, "auto").await?;
+
+    let mut sender = connect::<ChannelBody>(port).await?;
+    let request = Request::get("/other/abc").header("host", "example.com").body(ChannelBody::empty())?;
+    let response = sender.send_request(request).await?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_http1_host_mismatch() -> Result<()> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let user = Uuid::new_v4();
+    let port = start_xhttp_with(user, serde_json::json!({ "path": "/xh", "host": "cdn.example.com" })).await?;
+
+    let mut sender = connect::<ChannelBody>(port).await?;
+    let request = Request::get("/xh/abc").header("host", "127.0.0.1").body(ChannelBody::empty())?;
+    assert_eq!(sender.send_request(request).await?.status(), StatusCode::NOT_FOUND);
+
+    // 没有 Host 头的 HTTP/1.0 请求同样被拒绝
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    stream.write_all(b"GET /xh/abc HTTP/1.0\r\n\r\n").await?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    assert!(response.starts_with(b"HTTP/1.1 404"), "{}", String::from_utf8_lossy(&response));
+
+    // 主机名匹配时进入正常的下行流程
+    let mut sender = connect::<ChannelBody>(port).await?;
+    let request = Request::get("/xh/abc").header("host", "cdn.example.com:80").body(ChannelBody::empty())?;
+    assert_eq!(sender.send_request(request).await?.status(), StatusCode::OK);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_http1_cors_preflight() -> Result<()> {
+    let user = Uuid::new_v4();
+    let port = start_xhttp_with(user, serde_json::json!({ "path": "/xh", "corsOrigins": ["*"] })).await?;
+
+    // 预检之后同一连接继续可用
+    let mut sender = connect::<ChannelBody>(port).await?;
+    let request = Request::options("/xh/abc")
+        .header("host", "example.com")
+        .header("origin", "https://app.example.com")
+        .header("access-control-request-method", "POST")
+        .body(ChannelBody::empty())?;
+    let response = sender.send_request(request).await?;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(response.headers()["access-control-allow-origin"], "https://app.example.com");
+    assert_eq!(response.headers()["access-control-allow-methods"], "GET, POST, OPTIONS");
+
+    let request = Request::get("/xh/abc")
+        .header("host", "example.com")
+        .header("origin", "https://app.example.com")
+        .body(ChannelBody::empty())?;
+    let response = sender.send_request(request).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["access-control-allow-origin"], "https://app.example.com");
+    Ok(())
+}
+
+/// 以 h2c (prior knowledge) 发起 stream-one 请求，返回下行收到的至少 `len` 字节
+async fn stream_one_h2c(port: u16, payload: Bytes, len: usize) -> Result<BytesMut> {
+    let stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    let (mut client, connection) = h2::client::handshake(stream).await?;
+    tokio::spawn(connection);
+
+    let request = Request::post("http://example.com/xh").body(())?;
+    let (response, mut upload) = client.send_request(request, false)?;
+    upload.send_data(payload, false)?;
+    let response = response.await?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let mut body = response.into_body();
+    let mut received = BytesMut::new();
+    while received.len() < len {
+        match tokio::time::timeout(Duration::from_secs(5), body.data()).await? {
+            Some(data) => received.extend_from_slice(&data?),
+            None => bail!("响应体提前结束"),
+        }
+    }
+    Ok(received)
+}
+
+#[tokio::test]
+async fn test_h2c_and_http1_on_same_listener() -> Result<()> {
+    let echo = common::spawn_tcp_echo().await;
+    let user = Uuid::new_v4();
+    let port = start_xhttp(user, "stream-one").await?;
+
+    // h2c 客户端
+    let received = stream_one_h2c(port, vless_request(user, echo, b"via-h2")?, 8).await?;
+    assert_eq!(&received[..], b"\x00\x00via-h2");
+
+    // 同一端口上的 HTTP/1.1 客户端
+    let mut sender = connect(port).await?;
+    let (tx, rx) = mpsc::unbounded_channel();
+    tx.send(vless_request(user, echo, b"via-h1")?)?;
+    let request = Request::post("/xh").header("host", "example.com").body(ChannelBody(Some(rx)))?;
+    let response = sender.send_request(request).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let mut body = response.into_body();
+    let mut received = BytesMut::new();
+    read_at_least(&mut body, &mut received, 8).await?;
+    assert_eq!(&received[..], b"\x00\x00via-h1");
+    Ok(())
+}
@@ -0,0 +1,129 @@
+//! XHTTP over HTTP/3 集成测试: 用 h3 + quinn 客户端经 QUIC 访问 XHTTP 入站
+#![cfg(feature = "http3")]
+
+use anyhow::Result;
+use bytes::{Buf, Bytes};
+use hyper::http::{Request, StatusCode};
+use quinn::rustls;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+use xray_lite::protocol::vless::{Address, Addons, Command, VlessRequest};
+
+mod common;
+
+/// 为 localhost 生成自签名证书，写入临时目录，返回证书文件、私钥文件与证书
+fn write_certificate() -> Result<(String, String, rustls::pki_types::CertificateDer<'static>)> {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
+    let dir = std::env::temp_dir().join(format!("xray-lite-h3-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir)?;
+    let cert_file = dir.join("cert.pem");
+    let key_file = dir.join("key.pem");
+    std::fs::write(&cert_file, cert.serialize_pem()?)?;
+    std::fs::write(&key_file, cert.serialize_private_key_pem())?;
+    Ok((
+        cert_file.to_string_lossy().into_owned(),
+        key_file.to_string_lossy().into_owned(),
+        cert.serialize_der()?.into(),
+    ))
+}
+
+/// 信任 `cert` 的 HTTP/3 客户端
+fn client_endpoint(cert: rustls::pki_types::CertificateDer<'static>) -> Result<quinn::Endpoint> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(cert)?;
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut tls = rustls::ClientConfig::builder_with_provider(provider)
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    tls.alpn_protocols = vec![b"h3".to_vec()];
+    let mut endpoint = quinn::Endpoint::client("127.0.0.1:0".parse()?)?;
+    let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(tls)?;
+    endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(crypto)));
+    Ok(endpoint)
+}
+
+/// VLESS 请求头加首包数据
+fn vless_request(user: Uuid, target: SocketAddr, payload: &[u8]) -> Result<Bytes> {
+    let request = VlessRequest {
+        version: 0,
+        uuid: user,
+        command: Command::Tcp,
+        address: Address::from(target),
+        addon_length: 0,
+        addons: Addons::default(),
+    };
+    let mut buf = request.encode()?;
+    buf.extend_from_slice(payload);
+    Ok(buf.freeze())
+}
+
+/// 从响应体中读取至少 `len` 字节
+async fn read_at_least(
+    stream: &mut h3::client::RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    received: &mut Vec<u8>,
+    len: usize,
+) -> Result<()> {
+    while received.len() < len {
+        let mut chunk = tokio::time::timeout(Duration::from_secs(5), stream.recv_data())
+            .await??
+            .ok_or_else(|| anyhow::anyhow!("响应体提前结束"))?;
+        received.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stream_up_over_http3() -> Result<()> {
+    let echo = common::spawn_tcp_echo().await;
+    let user = Uuid::new_v4();
+    let (cert_file, key_file, cert) = write_certificate()?;
+    let port = common::free_port().await;
+    common::start_server(serde_json::json!({
+        "inbounds": [{
+            "protocol": "vless",
+            "listen": "127.0.0.1",
+            "port": port,
+            "settings": { "clients": [{ "id": user.to_string() }] },
+            "streamSettings": {
+                "network": "http",
+                "security": "none",
+                "xhttpSettings": {
+                    "mode": "stream-up",
+                    "path": "/xh",
+                    "http3": { "certificateFile": cert_file, "keyFile": key_file }
+                },
+                "sockopt": { "tcpFastOpen": false }
+            }
+        }],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+    }))
+    .await?;
+
+    let endpoint = client_endpoint(cert)?;
+    let connection = endpoint.connect(SocketAddr::from(([127, 0, 0, 1], port)), "localhost")?.await?;
+    let (mut driver, mut send_request) = h3::client::new(h3_quinn::Connection::new(connection)).await?;
+    tokio::spawn(async move { std::future::poll_fn(|cx| driver.poll_close(cx)).await });
+
+    // GET 承载下行，随后的流式 POST 按 session ID 与之配对
+    let mut download = send_request.send_request(Request::get("https://localhost/xh/h3session").body(())?).await?;
+    download.finish().await?;
+    let mut upload = send_request.send_request(Request::post("https://localhost/xh/h3session").body(())?).await?;
+    upload.send_data(vless_request(user, echo, b"hello")?).await?;
+
+    let response = tokio::time::timeout(Duration::from_secs(5), download.recv_response()).await??;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // VLESS 响应头 (版本 + 附加长度) 之后是回显的数据
+    let mut received = Vec::new();
+    read_at_least(&mut download, &mut received, 2 + 5).await?;
+    assert_eq!(&received[..], b"\x00\x00hello");
+
+    // 同一个上行流继续发送
+    upload.send_data(Bytes::from_static(b" over h3")).await?;
+    read_at_least(&mut download, &mut received, 2 + 13).await?;
+    assert_eq!(&received[2..], b"hello over h3");
+    Ok(())
+}